@@ -0,0 +1,256 @@
+// Backend capabilities
+//
+// Not every language feature can be expressed in every target. Before code
+// generation the program is checked against the selected backend, so a
+// feature it cannot generate is reported as an E7001 diagnostic at the
+// statement using it, naming the feature, the backend and what to write
+// instead, rather than failing halfway through codegen or emitting code that
+// breaks at runtime.
+
+use wtlang_core::ast::{BinaryOp, Expr, ProgramItem, Span, Statement, UnaryOp};
+use wtlang_core::interp::statement_span;
+use wtlang_core::{DiagnosticBag, ErrorCode, Location, Program};
+
+/// Target the program is compiled for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Streamlit,
+}
+
+/// A language feature some backend cannot generate, with what to use instead
+struct Unsupported {
+    feature: String,
+    alternative: &'static str,
+}
+
+impl Backend {
+    /// Every backend
+    pub const ALL: &'static [Backend] = &[Backend::Streamlit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Streamlit => "streamlit",
+        }
+    }
+
+    /// Diagnostics for every use of a feature this backend does not support
+    pub fn check(&self, program: &Program) -> DiagnosticBag {
+        let mut diagnostics = DiagnosticBag::new();
+        for item in &program.items {
+            let (body, span) = match item {
+                ProgramItem::Page(page) => (&page.statements, page.span),
+                ProgramItem::FunctionDef(function) => (&function.body, function.span),
+                ProgramItem::Test(test) => (&test.body, test.span),
+                ProgramItem::Hook(hook) => (&hook.body, hook.span),
+                ProgramItem::TableDef(_) | ProgramItem::ExternalFunction(_)
+                | ProgramItem::Restriction(_) | ProgramItem::DerivedTable(_) | ProgramItem::SharedVariable(_)
+                | ProgramItem::Constant(_) | ProgramItem::Config(_) | ProgramItem::Import(_) => continue,
+            };
+            self.check_statements(body, span, &mut diagnostics);
+        }
+        diagnostics
+    }
+
+    fn check_statements(&self, statements: &[Statement], enclosing: Span, diagnostics: &mut DiagnosticBag) {
+        for stmt in statements {
+            // Statements without a span of their own report at the enclosing one
+            let span = statement_span(stmt).unwrap_or(enclosing);
+            let mut exprs: Vec<&Expr> = Vec::new();
+            match stmt {
+                // Streamlit pages are Python, so `python` blocks run as written
+                Statement::Title(_) | Statement::Subtitle(_) | Statement::Text(_) | Statement::Refresh { .. }
+                | Statement::Python { .. } => {}
+                Statement::Button { body, .. } | Statement::Section { body, .. } | Statement::Confirm { body, .. } => {
+                    self.check_statements(body, span, diagnostics);
+                }
+                Statement::Let { value, .. } => exprs.extend(value),
+                Statement::Assign { value, .. } | Statement::Mock { value, .. } => exprs.push(value),
+                Statement::If { condition, then_branch, else_branch, else_span, .. } => {
+                    exprs.push(condition);
+                    self.check_statements(then_branch, span, diagnostics);
+                    if let Some(else_branch) = else_branch {
+                        self.check_statements(else_branch, else_span.unwrap_or(span), diagnostics);
+                    }
+                }
+                Statement::Forall { iterable, body, .. } => {
+                    exprs.push(iterable);
+                    self.check_statements(body, span, diagnostics);
+                }
+                Statement::Match { value, arms, .. } => {
+                    exprs.push(value);
+                    for arm in arms {
+                        self.check_statements(&arm.body, arm.span, diagnostics);
+                    }
+                }
+                Statement::Return(value) | Statement::Assert { condition: value, .. } => exprs.push(value),
+                Statement::FunctionCall(call) => exprs.extend(&call.args),
+            }
+            for expr in exprs {
+                self.check_expr(expr, span, diagnostics);
+            }
+        }
+    }
+
+    fn check_expr(&self, expr: &Expr, span: Span, diagnostics: &mut DiagnosticBag) {
+        match expr {
+            Expr::Where { table, condition, .. } => {
+                self.check_expr(table, span, diagnostics);
+                if let Some(unsupported) = self.where_condition(condition) {
+                    diagnostics.add_error(
+                        ErrorCode::E7001,
+                        format!(
+                            "{} is not supported by the {} backend; {}",
+                            unsupported.feature, self.name(), unsupported.alternative
+                        ),
+                        Location::new(span.line, span.column),
+                    );
+                }
+            }
+            Expr::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.check_expr(arg, span, diagnostics);
+                }
+            }
+            Expr::BinaryOp { left, right, .. } | Expr::Chain { left, right, .. } => {
+                self.check_expr(left, span, diagnostics);
+                self.check_expr(right, span, diagnostics);
+            }
+            Expr::UnaryOp { operand, .. } => self.check_expr(operand, span, diagnostics),
+            Expr::Between { value, low, high, .. } => {
+                self.check_expr(value, span, diagnostics);
+                self.check_expr(low, span, diagnostics);
+                self.check_expr(high, span, diagnostics);
+            }
+            Expr::Lambda { body, .. } => self.check_expr(body, span, diagnostics),
+            Expr::FieldAccess { object, .. } => self.check_expr(object, span, diagnostics),
+            Expr::Index { object, index, .. } => {
+                self.check_expr(object, span, diagnostics);
+                self.check_expr(index, span, diagnostics);
+            }
+            Expr::TableLiteral(fields) => {
+                for (_, value) in fields {
+                    self.check_expr(value, span, diagnostics);
+                }
+            }
+            Expr::ArrayLiteral(items) => {
+                for item in items {
+                    self.check_expr(item, span, diagnostics);
+                }
+            }
+            Expr::SortBy { table, columns, .. } => {
+                self.check_expr(table, span, diagnostics);
+                for column in columns {
+                    self.check_expr(&column.key, span, diagnostics);
+                }
+            }
+            Expr::ColumnSelect { table, .. } | Expr::Rename { table, .. } | Expr::DropColumns { table, .. } => {
+                self.check_expr(table, span, diagnostics)
+            }
+            Expr::GroupBy { table, having, .. } => {
+                self.check_expr(table, span, diagnostics);
+                self.check_expr(having, span, diagnostics);
+            }
+            Expr::Match { value, arms, .. } => {
+                self.check_expr(value, span, diagnostics);
+                for arm in arms {
+                    self.check_expr(&arm.body, arm.span, diagnostics);
+                }
+            }
+            Expr::IntLiteral(_) | Expr::FloatLiteral(_) | Expr::StringLiteral(_) | Expr::BoolLiteral(_) | Expr::DateLiteral(_)
+            | Expr::CurrencyLiteral { .. }
+            | Expr::Identifier(_) | Expr::FilterLiteral(_) => {}
+        }
+    }
+
+    /// The first part of a `where` condition this backend cannot translate
+    ///
+    /// Streamlit conditions become pandas `query()` strings built from
+    /// comparisons and `and`/`or` of columns and literals, or masks using
+    /// `isin` once they test membership with `in`.
+    fn where_condition(&self, condition: &Expr) -> Option<Unsupported> {
+        match self {
+            Backend::Streamlit => match condition {
+                Expr::BinaryOp { op, left, right, .. } => match op {
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::LessThan | BinaryOp::LessThanEqual
+                    | BinaryOp::GreaterThan | BinaryOp::GreaterThanEqual | BinaryOp::And | BinaryOp::Or
+                    | BinaryOp::In => {
+                        self.where_condition(left).or_else(|| self.where_condition(right))
+                    }
+                    _ => Some(Unsupported {
+                        feature: format!("Arithmetic (`{}`) in a where condition", operator(op)),
+                        alternative: "compare the column against a value computed with `let` before the filter",
+                    }),
+                },
+                Expr::UnaryOp { op: UnaryOp::Not, .. } => Some(Unsupported {
+                    feature: "`not` in a where condition".to_string(),
+                    alternative: "invert the comparison instead (e.g. `<=` for `not >`, `!=` for `not ==`)",
+                }),
+                Expr::UnaryOp { op: UnaryOp::Negate, .. } => Some(Unsupported {
+                    feature: "Negation in a where condition".to_string(),
+                    alternative: "negate the other side of the comparison instead",
+                }),
+                Expr::FunctionCall(call) => Some(Unsupported {
+                    feature: format!("Calling `{}` in a where condition", call.name),
+                    alternative: "compute the value with `let` before the filter and compare against the variable",
+                }),
+                Expr::Between { value, low, high, .. } => [value, low, high].into_iter().find_map(|part| self.where_condition(part)),
+                Expr::UnaryOp { op: UnaryOp::IsNull | UnaryOp::IsNotNull, operand, .. } => self.where_condition(operand),
+                Expr::ArrayLiteral(items) => items.iter().find_map(|item| self.where_condition(item)),
+                Expr::FieldAccess { .. } | Expr::Identifier(_) | Expr::IntLiteral(_) | Expr::FloatLiteral(_)
+                | Expr::StringLiteral(_) | Expr::BoolLiteral(_) | Expr::DateLiteral(_) | Expr::CurrencyLiteral { .. } => None,
+                _ => Some(Unsupported {
+                    feature: "This expression in a where condition".to_string(),
+                    alternative: "use comparisons of columns with literals, combined with `&&` and `||`",
+                }),
+            },
+        }
+    }
+}
+
+fn operator(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add | BinaryOp::Union => "+",
+        BinaryOp::Subtract | BinaryOp::Minus => "-",
+        BinaryOp::Multiply => "*",
+        BinaryOp::Divide => "/",
+        BinaryOp::Modulo => "%",
+        BinaryOp::Intersect => "&",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{Lexer, Parser};
+
+    fn check(body: &str) -> Vec<(usize, String)> {
+        let source = format!(
+            "table Order {{\n  id: int [key]\n  amount: float\n}}\npage Home {{\n  let orders = load_csv(\"o.csv\", Order)\n{}\n}}\n",
+            body
+        );
+        let tokens = Lexer::new(&source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Backend::Streamlit.check(&program).diagnostics().iter()
+            .map(|d| {
+                assert_eq!(d.code, ErrorCode::E7001);
+                (d.location.line, d.message.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_supported_where_conditions() {
+        assert!(check("  show(orders where amount > 10 && id != 3)").is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_where_conditions() {
+        let diagnostics = check("  let big = orders where amount * 2 > 10\n  section \"S\" {\n    show(orders where not (amount > 1))\n  }");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].0, 7);
+        assert!(diagnostics[0].1.starts_with("Arithmetic (`*`) in a where condition is not supported by the streamlit backend;"));
+        assert_eq!(diagnostics[1].0, 9);
+        assert!(diagnostics[1].1.contains("`not` in a where condition"));
+    }
+}
@@ -1,196 +1,1303 @@
 // Code generator for WTLang -> Python/Streamlit
 use wtlang_core::ast::{self, *};
-use wtlang_core::ir::{self, IRModule, IRBuilder, IRNode, IRExpr, IRItem, FilterSpec, TextStyle, Literal, BinOp, UnOp, TableSchema, ExternalInfo};
-use std::collections::HashMap;
+use wtlang_core::{Atom, PluginRegistry};
+use wtlang_core::symbols::split_qualified;
+use wtlang_core::semantics::{is_python_reserved, PYTHON_KEYWORDS};
+use wtlang_core::ir::{self, IRModule, IRBuilder, IRNode, IRExpr, IRItem, FilterSpec, TextStyle, DisplayText, TextSegment, Literal, BinOp, UnOp, TableSchema, ExternalInfo, RuntimeFeature};
+use std::collections::{BTreeMap, BTreeSet};
+use clap::ValueEnum;
+use crate::backend::Backend;
+use crate::i18n::{self, Locales};
+use crate::project::{self, Formats};
+
+/// Runtime library emitted unchanged for every program
+const RUNTIME_SOURCE: &str = include_str!("runtime/wt_runtime.py");
+const RUNTIME_MODULE: &str = "wt_runtime";
+/// Program-specific module with external imports and table loaders
+const HELPERS_MODULE: &str = "helpers";
+/// pytest module generated from the program's `test` items
+const TESTS_MODULE: &str = "test_app";
+/// Streamlit's settings file, generated for the `theme` of the config block
+const STREAMLIT_CONFIG: &str = ".streamlit/config.toml";
+/// Script that refreshes the tables materialized daily, run by a scheduler
+const REFRESH_MODULE: &str = "refresh_tables";
+/// Seconds a daily materialized table is used for before it is computed again
+const DAY_SECONDS: u32 = 24 * 60 * 60;
+
+/// Python type annotations of the generated code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypeHints {
+    #[default]
+    None,
+    /// Builtin types, `Decimal`, `date` and `pd.DataFrame`
+    Plain,
+    /// Tables typed by a pandera model generated per table
+    Pandera,
+}
+
+/// Oldest Python version the generated code runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, ValueEnum)]
+pub enum PythonVersion {
+    /// `match` compiled to `if` chains, f-strings whose expressions contain
+    /// neither their quote nor backslashes, and annotations only evaluated
+    /// by type checkers
+    #[default]
+    #[value(name = "3.8")]
+    V3_8,
+    /// `match` statements
+    #[value(name = "3.10")]
+    V3_10,
+    /// f-strings whose expressions contain any code
+    #[value(name = "3.12")]
+    V3_12,
+}
 
 pub struct CodeGenerator {
     indent_level: usize,
-    table_schemas: HashMap<String, TableSchema>,
-    table_defs: HashMap<String, TableDef>, // Keep for AST compatibility
-    external_functions: HashMap<String, ExternalInfo>,
-    ext_functions_ast: HashMap<String, ExternalFunction>, // Keep for AST compatibility
+    table_schemas: BTreeMap<Atom, TableSchema>,
+    table_defs: BTreeMap<Atom, TableDef>, // Keep for AST compatibility
+    external_functions: BTreeMap<Atom, ExternalInfo>,
+    ext_functions_ast: BTreeMap<Atom, ExternalFunction>, // Keep for AST compatibility
     key_counter: usize,
+    with_tests: bool,
+    trace: bool,
+    profile: bool,
+    type_hints: TypeHints,
+    python: PythonVersion,
+    locales: Locales,
+    /// Translation keys of the text generated so far
+    translation_keys: BTreeSet<String>,
+    /// Whether the page being generated has translated text
+    page_translated: bool,
+    formats: Formats,
+    /// Whether the code generated so far calls a `format_*` builtin
+    uses_formats: bool,
+    /// Whether the code generated so far calls `round`, `floor`, `ceil` or
+    /// `percent`
+    uses_numbers: bool,
+    /// Whether the code generated so far aggregates with `"strict"`
+    uses_strict_aggregates: bool,
+    /// Sections of the page being generated, for `export_pdf`
+    page_sections: Vec<(DisplayText, Vec<IRNode>)>,
+    uses_email: bool,
+    uses_pdf_export: bool,
+    uses_sql: bool,
+    uses_audit: bool,
+    uses_confirm: bool,
+    uses_revert: bool,
+    /// Session state keys of the confirmations in the button being generated
+    confirm_keys: Vec<String>,
+    /// Keys of the editors showing each table variable of the page being
+    /// generated, for `revert`
+    editors: BTreeMap<Atom, Vec<String>>,
+    /// Key of the editor generated last, until it is bound to a variable
+    last_editor: Option<String>,
+    uses_current_user: bool,
+    /// Whether the code generated so far calls `first`, `last` or `single`
+    uses_rows: bool,
+    /// Whether the code generated so far filters rows with a lambda
+    uses_where_rows: bool,
+    /// Conditions of the `restrict` rules of each table, in source order
+    restrictions: BTreeMap<Atom, Vec<IRExpr>>,
+    /// Values of the derived tables and how each is kept, in source order
+    derived_tables: Vec<(Atom, IRExpr, ir::Materialize)>,
+    /// Initial values of the shared variables, in source order
+    shared_variables: Vec<(Atom, IRExpr)>,
+    /// Folded values of the `const` declarations, in source order
+    constants: Vec<(Atom, Literal)>,
+    /// Functions the program defines, which the generated code names by
+    /// `python_name` unlike builtins and external functions
+    functions: BTreeSet<Atom>,
+    /// Modules imported by name, each generated into a Python module, and
+    /// the one being generated
+    modules: BTreeSet<String>,
+    current_module: Option<String>,
+    /// Tables with an `on_load`, `on_edit` or `on_save` hook
+    hooks: BTreeSet<(ir::HookEvent, Atom)>,
+    uses_on_edit: bool,
+    uses_refresh: bool,
+    /// Tables loaded from or saved to a database
+    sql_tables: BTreeSet<Atom>,
+    /// Whether a `having` condition is being generated, whose aggregates
+    /// read the group `g`
+    in_having: bool,
+    /// Tables loaded with `load_csv(path, Table, "normalize")`, whose loader
+    /// can match the CSV headers loosely
+    normalized_tables: BTreeSet<Atom>,
+    /// Browser tab title of every page, from the `config` block
+    app_title: Option<String>,
+    /// Streamlit theme, "light" or "dark", from the `config` block
+    theme: Option<String>,
+    /// Directory relative CSV paths are read from and written to
+    data_dir: Option<String>,
+    /// Name of the compiled file, given in the `# region` markers
+    source_file: Option<String>,
+    /// Names of the files it imports and the numbers of their items
+    imported_files: Vec<(String, std::ops::Range<u32>)>,
+    /// Builtins added by plugins
+    plugins: PluginRegistry,
+    /// Imports of the plugins' builtins called so far, in order
+    plugin_imports: Vec<String>,
+    /// Runtime features of the program generated last
+    runtime_features: BTreeSet<RuntimeFeature>,
 }
 
 impl CodeGenerator {
     pub fn new() -> Self {
         CodeGenerator {
             indent_level: 0,
-            table_schemas: HashMap::new(),
-            table_defs: HashMap::new(),
-            external_functions: HashMap::new(),
-            ext_functions_ast: HashMap::new(),
+            table_schemas: BTreeMap::new(),
+            table_defs: BTreeMap::new(),
+            external_functions: BTreeMap::new(),
+            ext_functions_ast: BTreeMap::new(),
             key_counter: 0,
+            with_tests: false,
+            trace: false,
+            profile: false,
+            type_hints: TypeHints::None,
+            python: PythonVersion::default(),
+            locales: Locales::new(),
+            translation_keys: BTreeSet::new(),
+            page_translated: false,
+            formats: Formats::new(),
+            uses_formats: false,
+            uses_numbers: false,
+            uses_strict_aggregates: false,
+            page_sections: Vec::new(),
+            uses_email: false,
+            uses_pdf_export: false,
+            uses_sql: false,
+            uses_audit: false,
+            uses_confirm: false,
+            uses_revert: false,
+            confirm_keys: Vec::new(),
+            editors: BTreeMap::new(),
+            last_editor: None,
+            uses_current_user: false,
+            uses_rows: false,
+            uses_where_rows: false,
+            restrictions: BTreeMap::new(),
+            derived_tables: Vec::new(),
+            shared_variables: Vec::new(),
+            constants: Vec::new(),
+            functions: BTreeSet::new(),
+            modules: BTreeSet::new(),
+            current_module: None,
+            hooks: BTreeSet::new(),
+            uses_on_edit: false,
+            uses_refresh: false,
+            sql_tables: BTreeSet::new(),
+            in_having: false,
+            normalized_tables: BTreeSet::new(),
+            app_title: None,
+            theme: None,
+            data_dir: None,
+            source_file: None,
+            imported_files: Vec::new(),
+            plugins: PluginRegistry::new(),
+            plugin_imports: Vec::new(),
+            runtime_features: BTreeSet::new(),
         }
     }
+    
+    /// Also compile `test` items into a pytest module
+    pub fn with_tests(mut self, enabled: bool) -> Self {
+        self.with_tests = enabled;
+        self
+    }
+
+    /// Log the duration and row count of every pipeline stage
+    pub fn with_trace(mut self, enabled: bool) -> Self {
+        self.trace = enabled;
+        self
+    }
+
+    /// Time every pipeline stage and show the timings in a Performance
+    /// section at the end of each page
+    pub fn with_profile(mut self, enabled: bool) -> Self {
+        self.profile = enabled;
+        self
+    }
+
+    /// Annotate functions, variables and table helpers with their types
+    pub fn with_type_hints(mut self, type_hints: TypeHints) -> Self {
+        self.type_hints = type_hints;
+        self
+    }
+
+    /// Only use constructs `python` has
+    pub fn with_python(mut self, python: PythonVersion) -> Self {
+        self.python = python;
+        self
+    }
+
+    /// Translations of `t("key")` text, by language
+    pub fn with_locales(mut self, locales: Locales) -> Self {
+        self.locales = locales;
+        self
+    }
+
+    /// Number, date and currency formats from the project's `wt.toml`
+    pub fn with_formats(mut self, formats: Formats) -> Self {
+        self.formats = formats;
+        self
+    }
+
+    /// Name of the compiled file, for the `# region` markers around the
+    /// code of each page, function and statement
+    pub fn with_source_file(mut self, name: impl Into<String>) -> Self {
+        self.source_file = Some(name.into());
+        self
+    }
+
+    /// Names of the files the compiled file imports, with the numbers of
+    /// their items, for the markers of the code generated from them
+    pub fn with_imported_files(mut self, files: Vec<(String, std::ops::Range<u32>)>) -> Self {
+        self.imported_files = files;
+        self
+    }
+
+    /// Builtins added by plugins, called with their code for this backend
+    pub fn with_plugins(mut self, plugins: PluginRegistry) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// What the generated app needs besides Streamlit and pandas, such as
+    /// SQLAlchemy when it uses a database
+    pub fn runtime_features(&self) -> &BTreeSet<RuntimeFeature> {
+        &self.runtime_features
+    }
 
     /// Generate code from IR
-    pub fn generate_from_ir(&mut self, ir_module: &IRModule) -> Result<HashMap<String, String>, String> {
-        let mut output_files = HashMap::new();
+    pub fn generate_from_ir(&mut self, ir_module: &IRModule) -> Result<BTreeMap<String, String>, String> {
+        let mut output_files = BTreeMap::new();
+        self.runtime_features = ir_module.runtime_features();
         
         // First pass: collect table schemas and external functions
         for item in &ir_module.items {
             match item {
                 IRItem::TableDef { name, schema, .. } => {
-                    self.table_schemas.insert(name.clone(), schema.clone());
+                    self.table_schemas.insert(*name, schema.clone());
                 }
                 IRItem::FunctionDef { name, is_external, external_info, .. } if *is_external => {
                     if let Some(info) = external_info {
-                        self.external_functions.insert(name.clone(), info.clone());
+                        self.external_functions.insert(*name, info.clone());
                     }
                 }
+                IRItem::FunctionDef { name, .. } => {
+                    self.functions.insert(*name);
+                }
+                IRItem::Restriction { table, condition, .. } => {
+                    self.restrictions.entry(*table).or_default().push(condition.clone());
+                }
+                IRItem::DerivedTable { name, value, materialize, .. } => {
+                    self.derived_tables.push((*name, value.clone(), *materialize));
+                }
+                IRItem::SharedVariable { name, value, .. } => {
+                    self.shared_variables.push((*name, value.clone()));
+                }
+                IRItem::Constant { name, value, .. } => {
+                    self.constants.push((*name, value.clone()));
+                }
+                IRItem::Hook { event, table, .. } => {
+                    self.hooks.insert((*event, *table));
+                }
+                IRItem::Config { settings, .. } => self.apply_config(settings),
                 _ => {}
             }
+            if let IRItem::FunctionDef { name, .. } | IRItem::Constant { name, .. } = item {
+                if let Some((module, _)) = split_qualified(name) {
+                    self.modules.insert(module.to_string());
+                }
+            }
         }
         
-        // Second pass: generate pages
+        // Second pass: generate pages, each into a file of its own
         for item in &ir_module.items {
-            if let IRItem::PageDef { name, body, .. } = item {
-                let code = self.generate_page_from_ir(name, body)?;
-                output_files.insert(format!("{}.py", name), code);
+            if let IRItem::PageDef { name, body, source_loc } = item {
+                if [RUNTIME_MODULE, HELPERS_MODULE, TESTS_MODULE, REFRESH_MODULE].contains(&name.as_str()) {
+                    return Err(format!("Page '{}' would overwrite the generated {}.py; rename the page", name, name));
+                }
+                let code = self.generate_page_from_ir(name, body, source_loc)?;
+                if output_files.insert(format!("{}.py", name), code).is_some() {
+                    return Err(format!("Two pages are named '{}'; rename one of them", name));
+                }
+            }
+        }
+        
+        // A Python module per module of the program, before the helpers it
+        // imports
+        for module in self.modules.clone() {
+            if [RUNTIME_MODULE, HELPERS_MODULE, TESTS_MODULE, REFRESH_MODULE].contains(&module.as_str()) {
+                return Err(format!("Module '{}' would overwrite the generated {}.py; import it under another name", module, module));
+            }
+            if is_python_reserved(&module) {
+                return Err(format!("Module '{}' would hide a Python name the generated code uses; import it under another name", module));
+            }
+            let code = self.generate_module(&module, ir_module)?;
+            if output_files.insert(format!("{}.py", module), code).is_some() {
+                return Err(format!("Module '{}' and a page have the same name; rename one of them", module));
+            }
+        }
+        
+        if self.with_tests {
+            let tests = self.generate_tests(ir_module)?;
+            output_files.insert(format!("{}.py", TESTS_MODULE), tests);
+        }
+        if let Some(refresh) = self.generate_refresh_script() {
+            output_files.insert(format!("{}.py", REFRESH_MODULE), refresh);
+        }
+        if let Some(theme) = &self.theme {
+            output_files.insert(STREAMLIT_CONFIG.to_string(), format!("[theme]\nbase = \"{}\"\n", theme));
+        }
+        
+        // Modules shared by all pages; the helpers last, once the translated
+        // text of every page is known
+        output_files.insert(format!("{}.py", RUNTIME_MODULE), RUNTIME_SOURCE.replace("\r\n", "\n"));
+        let helpers = self.generate_helpers(ir_module)?;
+        output_files.insert(format!("{}.py", HELPERS_MODULE), helpers);
+        
+        // Before 3.9 annotations like `list[int]` fail when evaluated, so
+        // they are left to type checkers
+        if self.python < PythonVersion::V3_10 && self.type_hints != TypeHints::None {
+            let runtime = format!("{}.py", RUNTIME_MODULE);
+            for (name, code) in output_files.iter_mut() {
+                if name.ends_with(".py") && *name != runtime {
+                    code.insert_str(0, "from __future__ import annotations\n");
+                }
             }
         }
         
         Ok(output_files)
     }
 
+    /// Take the settings of the `config` block; formats set in wt.toml
+    /// take precedence over the block's
+    fn apply_config(&mut self, settings: &[(Atom, String)]) {
+        for (name, value) in settings {
+            match name.as_str() {
+                "title" => self.app_title = Some(value.clone()),
+                "theme" => self.theme = Some(value.clone()),
+                "data_dir" => self.data_dir = Some(value.clone()),
+                setting if project::FORMAT_SETTINGS.iter().any(|(format, _)| *format == setting) => {
+                    self.formats.entry(setting.to_string()).or_insert_with(|| value.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
     /// Legacy method: generate from AST (will delegate to IR-based generation)
-    pub fn generate(&mut self, program: &Program) -> Result<HashMap<String, String>, String> {
+    pub fn generate(&mut self, program: &Program) -> Result<BTreeMap<String, String>, String> {
         // Convert AST to IR first
-        let mut builder = IRBuilder::new();
+        let mut builder = IRBuilder::new().with_plugins(self.plugins.clone());
         let ir_module = builder.build(program)?;
         
         // Use IR-based generation
         self.generate_from_ir(&ir_module)
     }
 
-    fn generate_page_from_ir(&mut self, page_name: &str, body: &[IRNode]) -> Result<String, String> {
-        let mut code = String::new();
+    /// Generate the program-specific module imported by every page
+    fn generate_helpers(&mut self, ir_module: &IRModule) -> Result<String, String> {
+        // User-defined functions, in source order; generated first as they
+        // decide which runtime functions and table helpers are needed
+        let mut functions = String::new();
+        for item in &ir_module.items {
+            if let IRItem::FunctionDef { is_external: false, name, .. } = item {
+                if split_qualified(name).is_some() {
+                    continue;
+                }
+                functions.push_str("\n\n");
+                functions.push_str(&self.generate_function_from_ir(item)?);
+            }
+        }
         
-        // Standard imports
+        // A function per hook, called by the table's helpers and editors
+        for item in &ir_module.items {
+            if let IRItem::Hook { .. } = item {
+                functions.push_str("\n\n");
+                functions.push_str(&self.generate_hook(item)?);
+            }
+        }
+        
+        // A loader per derived table, likewise
+        let mut derived = String::new();
+        for (name, value, materialize) in self.derived_tables.clone() {
+            derived.push_str(&self.generate_derived_table(name, &value, materialize)?);
+        }
+        let shared = self.generate_shared_state()?;
+        
+        // The rows of each restricted table the user may see, also generated
+        // before the imports as they call current_user
+        let tables: Vec<TableSchema> = self.table_schemas.values().cloned().collect();
+        let mut visible = BTreeMap::new();
+        for schema in &tables {
+            if self.restrictions.contains_key(&schema.name) {
+                visible.insert(schema.name, self.generate_visible_rows(schema)?);
+            }
+        }
+        
+        let mut code = String::new();
+        code.push_str("# Shared helpers imported by every page\n");
+        code.push_str("#\n");
+        code.push_str("# Generated by wtc from the program's tables, functions and external\n");
+        code.push_str("# functions.\n");
+        code.push('\n');
         code.push_str("import streamlit as st\n");
         code.push_str("import pandas as pd\n");
-        code.push_str("from datetime import datetime\n");
+        code.push_str("from decimal import Decimal\n");
+        if self.type_hints != TypeHints::None {
+            code.push_str("from datetime import date\n");
+        }
+        if self.type_hints == TypeHints::Pandera {
+            code.push_str("import pandera as pa\n");
+            code.push_str("from pandera.typing import DataFrame, Series\n");
+        }
+        let mut runtime_imports = vec!["validate_schema", "read_csv", "write_csv", "configure_logging", "log_message", "traced", "profiled"];
+        if !self.translation_keys.is_empty() {
+            runtime_imports.extend(["configure_translations", "select_language", "t"]);
+        }
+        if !self.formats.is_empty() || self.uses_formats {
+            runtime_imports.extend(["configure_formats", "formatted", "format_number", "format_currency", "format_date"]);
+        }
+        if self.data_dir.is_some() {
+            runtime_imports.push("configure_data_dir");
+        }
+        if self.uses_email {
+            runtime_imports.push("send_email");
+        }
+        if self.uses_pdf_export {
+            runtime_imports.push("export_pdf");
+        }
+        if self.uses_sql {
+            runtime_imports.extend(["load_sql", "save_sql"]);
+        }
+        if self.uses_audit {
+            runtime_imports.extend(["audit", "audit_edits"]);
+        }
+        if self.uses_on_edit {
+            runtime_imports.push("on_edited");
+        }
+        if !self.normalized_tables.is_empty() {
+            runtime_imports.push("match_columns");
+        }
+        if self.uses_refresh {
+            runtime_imports.push("refresh_every");
+        }
+        if self.uses_confirm {
+            runtime_imports.push("confirmed");
+        }
+        if self.uses_revert {
+            runtime_imports.push("revert");
+        }
+        if self.uses_current_user {
+            runtime_imports.push("current_user");
+        }
+        if self.uses_rows {
+            runtime_imports.extend(["first_row", "last_row", "single_row"]);
+        }
+        if self.uses_where_rows {
+            runtime_imports.push("where_rows");
+        }
+        if self.uses_numbers {
+            runtime_imports.extend(["round_number", "floor_number", "ceil_number", "percent"]);
+        }
+        if self.uses_strict_aggregates {
+            runtime_imports.push("strict_aggregate");
+        }
+        if !self.derived_tables.is_empty() {
+            runtime_imports.push("cached_table");
+        }
+        if self.derived_tables.iter().any(|(_, _, materialize)| *materialize != ir::Materialize::Cached) {
+            runtime_imports.push("materialized_table");
+        }
+        code.push_str(&format!("from {} import {}\n", RUNTIME_MODULE, runtime_imports.join(", ")));
         
-        // External function imports
-        // Group by module to generate clean imports
-        let mut modules: HashMap<String, Vec<String>> = HashMap::new();
-        for (func_name, ext_fn) in &self.external_functions {
-            modules.entry(ext_fn.module.clone())
-                .or_insert_with(Vec::new)
-                .push(func_name.clone());
+        // External function imports, grouped by module in a stable order
+        code.push_str(&self.external_imports());
+        for import in &self.plugin_imports {
+            code.push_str(import);
+            code.push('\n');
         }
+        code.push_str(&self.generate_constants());
         
-        // Generate import statements
-        for (module, functions) in modules {
-            if functions.len() == 1 {
-                code.push_str(&format!("from {} import {}\n", module, functions[0]));
+        // `log` messages go to the `wtlang.app` logger and `--trace` timings
+        // to `wtlang.trace`
+        code.push('\n');
+        code.push_str("LOGGING_CONFIG = {\n");
+        code.push_str("    \"version\": 1,\n");
+        code.push_str("    \"disable_existing_loggers\": False,\n");
+        code.push_str("    \"formatters\": {\"default\": {\"format\": \"%(asctime)s %(levelname)s %(name)s: %(message)s\"}},\n");
+        code.push_str("    \"handlers\": {\"console\": {\"class\": \"logging.StreamHandler\", \"formatter\": \"default\"}},\n");
+        code.push_str("    \"loggers\": {\"wtlang\": {\"handlers\": [\"console\"], \"level\": \"INFO\", \"propagate\": False}},\n");
+        code.push_str("}\n");
+        code.push_str("configure_logging(LOGGING_CONFIG)\n");
+        
+        if !self.translation_keys.is_empty() {
+            code.push_str(&self.generate_translations());
+        }
+        if !self.formats.is_empty() {
+            code.push('\n');
+            code.push_str("# Number, date and currency formats, from wt.toml and the config block\n");
+            code.push_str("FORMATS = {\n");
+            for (setting, value) in &self.formats {
+                code.push_str(&format!("    \"{}\": \"{}\",\n", setting, self.escape_string(value)));
+            }
+            code.push_str("}\n");
+            code.push_str("configure_formats(FORMATS)\n");
+        }
+        if let Some(data_dir) = &self.data_dir {
+            code.push('\n');
+            code.push_str(&format!("configure_data_dir(\"{}\")\n", self.escape_string(data_dir)));
+        }
+        
+        // A schema, loader and saver per table, used by `load_csv(path, Table)`
+        // and by `save_csv` on tables of a known type, and for tables kept in
+        // a database by `load_sql` and `save_sql` too. Restricted tables only
+        // load the rows the user may see, and saving them keeps the others.
+        for schema in &tables {
+            let schema_name = table_schema_name(schema.name);
+            let columns: Vec<String> = schema.fields.iter()
+                .map(|field| {
+                    let ty = match field.ty {
+                        ir::FieldType::Ref { .. } => "ref".to_string(),
+                        ref ty => ty.to_string(),
+                    };
+                    format!("\"{}\": \"{}\"", self.escape_string(field.name.as_str()), ty)
+                })
+                .collect();
+            
+            code.push_str("\n\n");
+            code.push_str(&format!("{} = {{{}}}\n", schema_name, columns.join(", ")));
+            // Columns stored in the CSV file under another header
+            let headers: Vec<String> = schema.fields.iter()
+                .filter_map(|field| {
+                    let header = field.header.as_deref()?;
+                    Some(format!("\"{}\": \"{}\"", self.escape_string(field.name.as_str()), self.escape_string(header)))
+                })
+                .collect();
+            let (read_args, write_args) = if headers.is_empty() {
+                (String::new(), String::new())
+            } else {
+                let headers_name = table_headers_name(schema.name);
+                code.push_str(&format!("{} = {{{}}}\n", headers_name, headers.join(", ")));
+                (format!(", {}", headers_name), format!(", headers={}", headers_name))
+            };
+            code.push_str(&self.generate_table_constraints(schema));
+            code.push('\n');
+            code.push('\n');
+            if self.type_hints == TypeHints::Pandera {
+                code.push_str(&self.generate_table_model(schema));
+                code.push('\n');
+                code.push('\n');
+            }
+            if let Some(visible) = visible.get(&schema.name) {
+                code.push_str(visible);
+                code.push('\n');
+                code.push('\n');
+            }
+            if self.sql_tables.contains(&schema.name) {
+                code.push_str(&self.generate_sql_table_helpers(schema));
+                continue;
+            }
+            let (loaded, keep_hidden) = self.restricted_load_and_save(schema.name);
+            let returns = self.return_annotation(&ir::Type::Table(schema.clone()));
+            if self.normalized_tables.contains(&schema.name) {
+                code.push_str(&format!("def {}(path, match_headers=False){}:\n", table_loader_name(schema.name), returns));
+                code.push_str(&format!("    \"\"\"Load the {} table from a CSV file, matching its headers loosely if asked\"\"\"\n", schema.name));
+                code.push_str(&format!("    df = read_csv(path{})\n", read_args));
+                code.push_str("    if match_headers:\n");
+                code.push_str(&format!("        df = match_columns(df, {})\n", schema_name));
             } else {
-                code.push_str(&format!("from {} import {}\n", module, functions.join(", ")));
+                code.push_str(&format!("def {}(path){}:\n", table_loader_name(schema.name), returns));
+                code.push_str(&format!("    \"\"\"Load the {} table from a CSV file\"\"\"\n", schema.name));
+                code.push_str(&format!("    df = read_csv(path{})\n", read_args));
             }
+            code.push_str(&format!("    validate_schema(df, {}, \"{}\")\n", schema_name, schema.name));
+            code.push_str(&self.loaded_rows(schema.name, &loaded));
+            code.push('\n');
+            code.push('\n');
+            code.push_str(&format!("def {}(df, path):\n", table_saver_name(schema.name)));
+            code.push_str(&format!("    \"\"\"Save the {} table to a CSV file if it matches the schema\"\"\"\n", schema.name));
+            code.push_str(&format!("    if validate_schema(df, {}, \"{}\"):\n", schema_name, schema.name));
+            code.push_str("        return False\n");
+            code.push_str(&self.save_hook_check(schema.name));
+            code.push_str(&format!("    return write_csv(df, path, \"{}\"{}{})\n", schema.name, keep_hidden, write_args));
         }
         
-        code.push_str("\n");
+        code.push_str(&derived);
+        code.push_str(&shared);
+        code.push_str(&functions);
         
-        // Helper function for filtered show/show_editable
-        code.push_str("def _show_filtered(df, filters, editable=False, key_prefix=''):\n");
-        code.push_str("    \"\"\"Show dataframe with optional filters\"\"\"\n");
-        code.push_str("    # Create filter widgets (3 per row)\n");
-        code.push_str("    filter_values = []\n");
-        code.push_str("    num_filters = len(filters)\n");
-        code.push_str("    for i in range(0, num_filters, 3):\n");
-        code.push_str("        cols = st.columns(min(3, num_filters - i))\n");
-        code.push_str("        for j, (col_name, mode) in enumerate(filters[i:i+3]):\n");
-        code.push_str("            if mode == 'single':\n");
-        code.push_str("                val = cols[j].selectbox(col_name, ['All'] + sorted(df[col_name].unique().astype(str).tolist()), key=f'{key_prefix}_f_{i+j}')\n");
-        code.push_str("                filter_values.append((col_name, mode, val))\n");
-        code.push_str("            else:  # multi\n");
-        code.push_str("                val = cols[j].multiselect(col_name, sorted(df[col_name].unique().astype(str).tolist()), key=f'{key_prefix}_f_{i+j}')\n");
-        code.push_str("                filter_values.append((col_name, mode, val))\n");
-        code.push_str("    \n");
-        code.push_str("    # Apply filters and track filtered/non-filtered rows\n");
-        code.push_str("    mask = pd.Series([True] * len(df), index=df.index)\n");
-        code.push_str("    for col_name, mode, val in filter_values:\n");
-        code.push_str("        if mode == 'single' and val != 'All':\n");
-        code.push_str("            mask = mask & (df[col_name].astype(str) == val)\n");
-        code.push_str("        elif mode == 'multi' and val:\n");
-        code.push_str("            mask = mask & df[col_name].astype(str).isin(val)\n");
-        code.push_str("    \n");
-        code.push_str("    filtered = df[mask]\n");
-        code.push_str("    non_filtered = df[~mask]\n");
-        code.push_str("    \n");
-        code.push_str("    # Display\n");
-        code.push_str("    if editable:\n");
-        code.push_str("        edited = st.data_editor(filtered, key=f'{key_prefix}_editor', use_container_width=True)\n");
-        code.push_str("        # Merge edited filtered rows with non-filtered rows\n");
-        code.push_str("        return pd.concat([edited, non_filtered], ignore_index=True)\n");
-        code.push_str("    else:\n");
-        code.push_str("        st.dataframe(filtered)\n");
-        code.push_str("        return None\n");
-        code.push_str("\n");
+        // Last, as the modules star-import the helpers
+        if !self.modules.is_empty() {
+            code.push_str("\n\n# Modules of the program\n");
+            code.push_str(&self.module_imports());
+        }
+        Ok(code)
+    }
+    
+    /// A module of the program, with its functions and constants under
+    /// their names in the module
+    fn generate_module(&mut self, module: &str, ir_module: &IRModule) -> Result<String, String> {
+        self.current_module = Some(module.to_string());
+        let mut functions = String::new();
+        for item in &ir_module.items {
+            if let IRItem::FunctionDef { is_external: false, name, .. } = item {
+                if split_qualified(name).is_some_and(|(of, _)| of == module) {
+                    functions.push_str("\n\n");
+                    functions.push_str(&self.generate_function_from_ir(item)?);
+                }
+            }
+        }
         
-        // Page configuration
-        code.push_str(&format!("# Page: {}\n", page_name));
-        code.push_str("\n");
+        let mut code = String::new();
+        code.push_str(&format!("# Module {}, imported by the pages and helpers\n", module));
+        code.push_str("#\n");
+        code.push_str("# Generated by wtc from the functions, external functions and constants\n");
+        code.push_str("# of the module.\n");
+        code.push('\n');
+        code.push_str(&format!("from {} import *\n", HELPERS_MODULE));
+        code.push_str(&self.module_imports());
+        code.push_str(&self.external_imports());
+        code.push_str(&self.generate_constants());
+        code.push_str(&functions);
+        self.current_module = None;
+        Ok(code)
+    }
+    
+    /// `import` lines of the program's modules, but the one being generated
+    fn module_imports(&self) -> String {
+        self.modules.iter()
+            .filter(|module| Some(*module) != self.current_module.as_ref())
+            .map(|module| format!("import {}\n", module))
+            .collect()
+    }
+    
+    /// Imports of the external functions of the module being generated, or
+    /// of the helpers, grouped by Python module in a stable order
+    fn external_imports(&self) -> String {
+        let mut modules: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (func_name, ext_fn) in &self.external_functions {
+            if self.module_of(func_name) == self.current_module.as_deref() {
+                modules.entry(ext_fn.module.as_str())
+                    .or_default()
+                    .push(self.unqualified_name(func_name).to_string());
+            }
+        }
+        let mut code = String::new();
+        for (module, mut functions) in modules {
+            functions.sort();
+            code.push_str(&format!("from {} import {}\n", module, functions.join(", ")));
+        }
+        code
+    }
+    
+    /// The module a function or constant belongs to, if any
+    fn module_of<'a>(&self, name: &'a str) -> Option<&'a str> {
+        split_qualified(name).map(|(module, _)| module)
+    }
+    
+    /// Name of a function or constant in the Python module being
+    /// generated: those of a module are attributes of its Python module,
+    /// except within the module itself
+    fn unqualified_name<'a>(&self, name: &'a str) -> &'a str {
+        match split_qualified(name) {
+            Some((module, member)) if Some(module) == self.current_module.as_deref() => member,
+            _ => name,
+        }
+    }
+    
+    /// Python name of a variable, or of a function or constant of the
+    /// program, renamed if it is reserved in Python
+    fn python_name(&self, name: &str) -> String {
+        match split_qualified(self.unqualified_name(name)) {
+            Some((module, member)) => format!("{}.{}", module, python_safe(member)),
+            None => python_safe(self.unqualified_name(name)),
+        }
+    }
+    
+    /// Loader of a derived table, shared by all pages
+    ///
+    /// Cached tables are computed once per session. Materialized ones are
+    /// read from a CSV file: written by `wtc build` for `on_build`, and for
+    /// `daily` computed again once the file is a day old, or earlier by the
+    /// refresh script.
+    fn generate_derived_table(&mut self, name: Atom, value: &IRExpr, materialize: ir::Materialize) -> Result<String, String> {
+        let loader = table_loader_name(name);
+        let file = materialized_file_name(name);
+        let value_code = self.generate_ir_expr(value)?;
+        let returns = self.return_annotation(value.get_type());
+        let mut code = String::from("\n\n");
+        match materialize {
+            ir::Materialize::Cached => {
+                code.push_str(&format!("def {}(){}:\n", loader, returns));
+                code.push_str(&format!("    \"\"\"The {} table, computed once per session\"\"\"\n", name));
+                code.push_str(&format!("    return cached_table(\"{}\", lambda: {})\n", name, value_code));
+            }
+            ir::Materialize::Daily => {
+                code.push_str(&format!("def {}(){}:\n", table_compute_name(name), returns));
+                code.push_str(&format!("    \"\"\"Compute the {} table from the tables it is derived from\"\"\"\n", name));
+                code.push_str(&format!("    return {}\n", value_code));
+                code.push_str("\n\n");
+                code.push_str(&format!("def {}(){}:\n", loader, returns));
+                code.push_str(&format!("    \"\"\"The {} table, materialized daily in {}\"\"\"\n", name, file));
+                code.push_str(&format!("    return materialized_table(\"{}\", \"{}\", {}, max_age={})\n",
+                    name, file, table_compute_name(name), DAY_SECONDS));
+            }
+            ir::Materialize::OnBuild => {
+                code.push_str(&format!("def {}(){}:\n", loader, returns));
+                code.push_str(&format!("    \"\"\"The {} table, materialized in {} by wtc build\"\"\"\n", name, file));
+                code.push_str(&format!("    return materialized_table(\"{}\", \"{}\")\n", name, file));
+            }
+        }
+        Ok(code)
+    }
+
+    /// Function every page calls first, giving the shared variables their
+    /// initial values in a new session, or again with `reset`
+    fn generate_shared_state(&mut self) -> Result<String, String> {
+        if self.shared_variables.is_empty() {
+            return Ok(String::new());
+        }
         
-        // Generate IR nodes
-        for node in body {
-            code.push_str(&self.generate_ir_node(node)?);
+        let mut code = String::from("\n\n");
+        code.push_str("def init_shared_state(reset=False):\n");
+        code.push_str("    \"\"\"Give the variables shared by every page their initial values\"\"\"\n");
+        for (name, value) in self.shared_variables.clone() {
+            let key = shared_state_key(name);
+            code.push_str(&format!("    if reset or \"{}\" not in st.session_state:\n", key));
+            code.push_str(&format!("        st.session_state[\"{}\"] = {}\n", key, self.generate_ir_expr(&value)?));
+        }
+        Ok(code)
+    }
+
+    /// Script that computes the tables materialized daily, if there are any
+    /// The `const` declarations, already folded, at the top of every
+    /// module that may use them
+    fn generate_constants(&self) -> String {
+        let constants: Vec<_> = self.constants.iter()
+            .filter(|(name, _)| self.module_of(name) == self.current_module.as_deref())
+            .collect();
+        if constants.is_empty() {
+            return String::new();
+        }
+        let mut code = String::from("\n# Constants\n");
+        for (name, value) in constants {
+            code.push_str(&format!("{} = {}\n", self.python_name(name), self.generate_literal(value)));
+        }
+        code
+    }
+
+    fn generate_literal(&self, value: &Literal) -> String {
+        match value {
+            Literal::Int(n) => n.to_string(),
+            Literal::Float(f) => f.to_string(),
+            Literal::String(s) => python_string(s),
+            Literal::Bool(b) => if *b { "True" } else { "False" }.to_string(),
+            Literal::Date(date) => date_literal(date),
+            Literal::Currency { amount, .. } => currency_literal(amount),
+        }
+    }
+
+    fn generate_refresh_script(&self) -> Option<String> {
+        let daily: Vec<Atom> = self.derived_tables.iter()
+            .filter(|(_, _, materialize)| *materialize == ir::Materialize::Daily)
+            .map(|(name, _, _)| *name)
+            .collect();
+        if daily.is_empty() {
+            return None;
         }
         
+        let mut code = String::new();
+        code.push_str("# Refresh the tables materialized daily\n");
+        code.push_str("#\n");
+        code.push_str("# Schedule once a day from the app's directory, e.g. with cron:\n");
+        code.push_str(&format!("#     0 3 * * * cd /path/to/app && python {}.py\n", REFRESH_MODULE));
+        code.push('\n');
+        code.push_str(&format!("from {} import refresh_table\n", RUNTIME_MODULE));
+        let computes: Vec<String> = daily.iter().map(|name| table_compute_name(*name)).collect();
+        code.push_str(&format!("from {} import {}\n", HELPERS_MODULE, computes.join(", ")));
+        code.push('\n');
+        for name in daily {
+            code.push_str(&format!("refresh_table(\"{}\", {})\n", materialized_file_name(name), table_compute_name(name)));
+        }
+        Some(code)
+    }
+
+    /// Loader and saver of a table that is also kept in a database
+    ///
+    /// With a connection, `path` is the name of the database table. Tests
+    /// mock the loader, so either source is replaced by the mock.
+    fn generate_sql_table_helpers(&self, schema: &TableSchema) -> String {
+        let schema_name = table_schema_name(schema.name);
+        let (loaded, keep_hidden) = self.restricted_load_and_save(schema.name);
+        let mut code = String::new();
+        let returns = self.return_annotation(&ir::Type::Table(schema.clone()));
+        code.push_str(&format!("def {}(path, connection=None){}:\n", table_loader_name(schema.name), returns));
+        code.push_str(&format!("    \"\"\"Load the {} table from a CSV file, or from a database table\"\"\"\n", schema.name));
+        code.push_str("    df = read_csv(path) if connection is None else load_sql(connection, path)\n");
+        code.push_str(&format!("    validate_schema(df, {}, \"{}\")\n", schema_name, schema.name));
+        code.push_str(&self.loaded_rows(schema.name, &loaded));
+        code.push('\n');
+        code.push('\n');
+        code.push_str(&format!("def {}(df, path, connection=None, mode=\"append\"):\n", table_saver_name(schema.name)));
+        code.push_str(&format!("    \"\"\"Save the {} table to a CSV file, or to a database table, if it matches the schema\"\"\"\n", schema.name));
+        code.push_str(&format!("    if validate_schema(df, {}, \"{}\"):\n", schema_name, schema.name));
+        code.push_str("        return False\n");
+        code.push_str(&self.save_hook_check(schema.name));
+        code.push_str("    if connection is not None:\n");
+        code.push_str(&format!("        return save_sql(df, connection, path, mode, {}_CONSTRAINTS, \"{}\"{})\n", table_constant_prefix(schema.name), schema.name, keep_hidden));
+        code.push_str(&format!("    return write_csv(df, path, \"{}\"{})\n", schema.name, keep_hidden));
+        code
+    }
+    
+    /// End of a table's loader, returning its rows after its `on_load` hook
+    /// has seen them
+    fn loaded_rows(&self, table: Atom, loaded: &str) -> String {
+        if !self.hooks.contains(&(ir::HookEvent::Load, table)) {
+            return format!("    return {}\n", loaded);
+        }
+        let mut code = String::new();
+        if loaded != "df" {
+            code.push_str(&format!("    df = {}\n", loaded));
+        }
+        code.push_str(&format!("    {}(df)\n", hook_function_name(ir::HookEvent::Load, table)));
+        code.push_str("    return df\n");
+        code
+    }
+    
+    /// Check in a table's saver that its `on_save` hook does not cancel the
+    /// save by returning false
+    fn save_hook_check(&self, table: Atom) -> String {
+        if !self.hooks.contains(&(ir::HookEvent::Save, table)) {
+            return String::new();
+        }
+        let mut code = String::new();
+        code.push_str(&format!("    if {}(df) is False:\n", hook_function_name(ir::HookEvent::Save, table)));
+        code.push_str("        return False\n");
+        code
+    }
+    
+    /// Function telling which rows of a restricted table the user may see
+    ///
+    /// Every `restrict` rule of the table must hold for a row.
+    fn generate_visible_rows(&mut self, schema: &TableSchema) -> Result<String, String> {
+        let conditions = self.restrictions.get(&schema.name).cloned().unwrap_or_default();
+        let mut masks = Vec::new();
+        for condition in &conditions {
+            masks.push(self.generate_restriction_mask(condition, schema)?);
+        }
+        let mut code = String::new();
+        code.push_str(&format!("def {}(df):\n", table_visible_name(schema.name)));
+        code.push_str(&format!("    \"\"\"Which rows of the {} table the signed-in user may see\"\"\"\n", schema.name));
+        code.push_str(&format!("    return {}\n", masks.join(" & ")));
         Ok(code)
     }
+    
+    /// What a table's loader returns, and the extra arguments its saver
+    /// passes, so a restricted table loads only the rows the user may see
+    /// and saves without losing the others
+    fn restricted_load_and_save(&self, table: Atom) -> (String, String) {
+        if self.restrictions.contains_key(&table) {
+            let visible = table_visible_name(table);
+            (format!("df[{}(df)]", visible), format!(", visible={}", visible))
+        } else {
+            ("df".to_string(), String::new())
+        }
+    }
+    
+    /// Generate the constraint and reference constants of a table, used to
+    /// generate test data
+    fn generate_table_constraints(&self, schema: &TableSchema) -> String {
+        let mut constraints: Vec<(Atom, Vec<&str>)> = schema.fields.iter()
+            .map(|field| (field.name, Vec::new()))
+            .collect();
+        for constraint in &schema.constraints {
+            let (field, name) = match constraint {
+                ir::Constraint::PrimaryKey(field) => (field, "key"),
+                ir::Constraint::Unique(field) => (field, "unique"),
+                ir::Constraint::NonNull(field) => (field, "non_null"),
+            };
+            if let Some((_, names)) = constraints.iter_mut().find(|(f, _)| f == field) {
+                names.push(name);
+            }
+        }
+        let constraints: Vec<String> = constraints.into_iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(field, names)| {
+                let names: Vec<String> = names.iter().map(|n| python_string(n)).collect();
+                format!("{}: [{}]", python_string(field.as_str()), names.join(", "))
+            })
+            .collect();
+        
+        // A ref column holds key values of the referenced table
+        let refs: Vec<String> = schema.fields.iter()
+            .filter_map(|field| {
+                let ir::FieldType::Ref { table_name } = &field.ty else { return None };
+                let key = self.table_schemas.get(table_name)?.get_key_field()?;
+                Some(format!("{}: ({}, {}, {})",
+                    python_string(field.name.as_str()), python_string(table_name.as_str()),
+                    python_string(key.name.as_str()), python_string(&key.ty.to_string())))
+            })
+            .collect();
+        
+        format!("{}_CONSTRAINTS = {{{}}}\n{}_REFS = {{{}}}\n",
+            table_constant_prefix(schema.name), constraints.join(", "),
+            table_constant_prefix(schema.name), refs.join(", "))
+    }
+    
+    /// Generate a Python `def` for a user-defined function
+    fn generate_function_from_ir(&mut self, item: &IRItem) -> Result<String, String> {
+        let IRItem::FunctionDef { name, params, return_type, body, source_loc, .. } = item else {
+            return Err("Expected a function definition".to_string());
+        };
+        
+        let params: Vec<String> = params.iter().map(|p| self.annotated(&self.python_name(&p.name), &p.ty)).collect();
+        let mut code = format!("def {}({}){}:\n", self.python_name(name), params.join(", "), self.return_annotation(return_type));
+        
+        self.indent_level += 1;
+        if body.is_empty() {
+            code.push_str(&format!("{}pass\n", self.get_indent()));
+        }
+        for node in body {
+            code.push_str(&self.generate_statement(node)?);
+        }
+        self.indent_level -= 1;
+        
+        Ok(self.region(&format!("function {}", name), source_loc, code))
+    }
 
-    fn generate_ir_node(&mut self, node: &IRNode) -> Result<String, String> {
-        let indent = self.get_indent();
+    /// Generate a Python `def` for a hook, taking the table's rows
+    fn generate_hook(&mut self, item: &IRItem) -> Result<String, String> {
+        let IRItem::Hook { event, table, param, body, source_loc } = item else {
+            return Err("Expected a hook".to_string());
+        };
         
-        match node {
-            IRNode::ShowText { text, style, .. } => {
-                match style {
-                    TextStyle::Title => Ok(format!("{}st.title(\"{}\")\n", indent, self.escape_string(text))),
-                    TextStyle::Subtitle => Ok(format!("{}st.subheader(\"{}\")\n", indent, self.escape_string(text))),
-                    TextStyle::Normal => {
-                        let formatted = self.format_string_interpolation(text);
-                        Ok(format!("{}st.write({})\n", indent, formatted))
-                    }
-                }
+        let param = match self.table_schemas.get(table) {
+            Some(schema) => self.annotated(param.as_str(), &ir::Type::Table(schema.clone())),
+            None => param.to_string(),
+        };
+        let mut code = format!("def {}({}):\n", hook_function_name(*event, *table), param);
+        self.indent_level += 1;
+        if body.is_empty() {
+            code.push_str(&format!("{}pass\n", self.get_indent()));
+        }
+        for node in body {
+            code.push_str(&self.generate_statement(node)?);
+        }
+        self.indent_level -= 1;
+        
+        Ok(self.region(&format!("{} {}", hook_keyword(*event), table), source_loc, code))
+    }
+
+    /// Generate the pytest module with one `test_*` function per `test` item
+    fn generate_tests(&mut self, ir_module: &IRModule) -> Result<String, String> {
+        let mut code = String::new();
+        code.push_str("# Tests generated by wtc from the program's test blocks\n");
+        code.push_str("#\n");
+        code.push_str("# Run with: pytest\n");
+        code.push('\n');
+        code.push_str("import streamlit as st\n");
+        code.push_str("import pandas as pd\n");
+        code.push_str(&format!("import {}\n", HELPERS_MODULE));
+        code.push_str(&format!("from {} import show_filtered, check_generated\n", RUNTIME_MODULE));
+        code.push_str(&format!("from {} import assert_equal_tables, assert_row_count, assert_contains\n", RUNTIME_MODULE));
+        code.push_str(&format!("from {} import *\n", HELPERS_MODULE));
+        code.push_str(&self.module_imports());
+        code.push_str(&self.generate_constants());
+        
+        // Each test starts a fresh session: derived tables are computed again
+        // from its own mocks, and shared variables start at their initial
+        // values
+        let mut fresh = Vec::new();
+        if !self.derived_tables.is_empty() {
+            fresh.push("clear_derived_tables()");
+        }
+        if !self.shared_variables.is_empty() {
+            fresh.push("init_shared_state(reset=True)");
+        }
+        if !fresh.is_empty() {
+            code.push('\n');
+            code.push_str("import pytest\n");
+            if !self.derived_tables.is_empty() {
+                code.push_str(&format!("from {} import clear_derived_tables\n", RUNTIME_MODULE));
+            }
+            code.push_str("\n\n");
+            code.push_str("@pytest.fixture(autouse=True)\n");
+            code.push_str("def fresh_session():\n");
+            for statement in fresh {
+                code.push_str(&format!("    {}\n", statement));
             }
+        }
+        
+        let mut used_names = BTreeSet::new();
+        for item in &ir_module.items {
+            let IRItem::TestDef { name, body, source_loc } = item else { continue };
             
-            IRNode::Button { label, body, .. } => {
-                let mut code = format!("{}if st.button(\"{}\"):\n", indent, self.escape_string(label));
-                self.indent_level += 1;
-                for node in body {
-                    code.push_str(&self.generate_ir_node(node)?);
-                }
-                self.indent_level -= 1;
-                Ok(code)
+            let mut function_name = format!("test_{}", python_identifier(name));
+            let mut suffix = 2;
+            while !used_names.insert(function_name.clone()) {
+                function_name = format!("test_{}_{}", python_identifier(name), suffix);
+                suffix += 1;
             }
             
-            IRNode::Section { title, body, .. } => {
-                let mut code = format!("{}with st.container():\n", indent);
+            code.push_str("\n\n");
+            let start = code.len();
+            code.push_str(&format!("def {}(monkeypatch):\n", function_name));
+            self.indent_level += 1;
+            code.push_str(&format!("{}\"\"\"{}\"\"\"\n", self.get_indent(), self.escape_string(name)));
+            
+            // Tests on generated tables run their body once per generated
+            // data set, through the runtime's shrinking runner
+            let mut generated = Vec::new();
+            collect_generated_mocks(body, &mut generated);
+            if !generated.is_empty() {
+                code.push_str(&format!("{}def run(tables):\n", self.get_indent()));
                 self.indent_level += 1;
-                code.push_str(&format!("{}st.markdown(\"### {}\")\n", self.get_indent(), self.escape_string(title)));
-                for node in body {
-                    code.push_str(&self.generate_ir_node(node)?);
-                }
-                self.indent_level -= 1;
-                Ok(code)
             }
-            
-            IRNode::Binding { name, value, .. } => {
-                if let Some(val) = value {
-                    let value_code = self.generate_ir_expr(val)?;
-                    Ok(format!("{}{} = {}\n", indent, name, value_code))
-                } else {
-                    Ok(format!("{}{} = None  # Will be assigned later\n", indent, name))
-                }
+            for node in body {
+                code.push_str(&self.generate_statement(node)?);
             }
-            
-            IRNode::Assignment { target, value, .. } => {
-                let value_code = self.generate_ir_expr(value)?;
-                Ok(format!("{}{} = {}\n", indent, target, value_code))
+            if !generated.is_empty() {
+                self.indent_level -= 1;
+                let mut specs = Vec::new();
+                for (table, rows) in &generated {
+                    if !self.table_schemas.contains_key(table) {
+                        return Err(format!("generated() can only mock a table, not '{}'", table));
+                    }
+                    let rows = match rows {
+                        Some(rows) => self.generate_ir_expr(rows)?,
+                        None => "100".to_string(),
+                    };
+                    let prefix = table_constant_prefix(*table);
+                    specs.push(format!("\"{}\": ({}, {}_CONSTRAINTS, {}_REFS, {})",
+                        table, table_schema_name(*table), prefix, prefix, rows));
+                }
+                code.push_str(&format!("{}check_generated(run, {{{}}})\n", self.get_indent(), specs.join(", ")));
             }
-            
+            self.indent_level -= 1;
+            let test_code = code.split_off(start);
+            code.push_str(&self.region(&format!("test \"{}\"", self.escape_string(name)), source_loc, test_code));
+        }
+        
+        Ok(code)
+    }
+    
+    /// Python name a mock replaces: a table's loader, or the function itself
+    fn mock_target_name(&self, target: Atom) -> String {
+        if self.table_schemas.contains_key(&target) || self.is_derived_table(target) {
+            table_loader_name(target)
+        } else if self.functions.contains(&target) {
+            self.python_name(&target)
+        } else {
+            target.to_string()
+        }
+    }
+
+    /// Python code for a variable: shared ones live in the session state
+    fn variable_code(&self, name: Atom) -> String {
+        if self.shared_variables.iter().any(|(shared, _)| *shared == name) {
+            format!("st.session_state[\"{}\"]", shared_state_key(name))
+        } else {
+            self.python_name(&name)
+        }
+    }
+
+    fn is_derived_table(&self, name: Atom) -> bool {
+        self.derived_tables.iter().any(|(derived, _, _)| *derived == name)
+    }
+
+    fn generate_page_from_ir(&mut self, page_name: &str, body: &[IRNode], source_loc: &ir::SourceRange) -> Result<String, String> {
+        let mut code = String::new();
+        
+        // Standard imports
+        code.push_str("import streamlit as st\n");
+        code.push_str("import pandas as pd\n");
+        code.push_str("from datetime import datetime\n");
+        code.push_str("from decimal import Decimal\n");
+        
+        // Shared runtime and program helpers (external functions, table loaders)
+        if self.profile {
+            code.push_str(&format!("from {} import show_filtered, profile_start, show_profile\n", RUNTIME_MODULE));
+        } else {
+            code.push_str(&format!("from {} import show_filtered\n", RUNTIME_MODULE));
+        }
+        code.push_str(&format!("from {} import *\n", HELPERS_MODULE));
+        code.push_str(&self.module_imports());
+        code.push_str(&self.generate_constants());
+        code.push_str("\n");
+        
+        // Helper function for filtered show/show_editable
+        // Page configuration
+        code.push_str(&format!("# Page: {}\n", page_name));
+        code.push_str("\n");
+        
+        // Must come before anything else the page shows
+        if let Some(title) = &self.app_title {
+            code.push_str(&format!("st.set_page_config(page_title=\"{}\")\n", self.escape_string(title)));
+        }
+        if !self.shared_variables.is_empty() {
+            code.push_str("init_shared_state()\n");
+        }
+        if self.profile {
+            code.push_str("profile_start()\n");
+        }
+        
+        // Pages with translated text offer a choice of language
+        self.page_translated = false;
+        self.page_sections.clear();
+        collect_sections(body, &mut self.page_sections);
+        self.editors.clear();
+        let mut body_code = String::new();
+        for node in body {
+            body_code.push_str(&self.generate_statement(node)?);
+        }
+        if self.page_translated {
+            code.push_str("select_language()\n");
+        }
+        code.push_str(&self.region(&format!("page {}", page_name), source_loc, body_code));
+        
+        if self.profile {
+            code.push_str("show_profile()\n");
+        }
+        
+        Ok(code)
+    }
+
+    /// Generate a statement of a page, function, hook or test, marked with
+    /// where it was written; nested statements are inside its region
+    fn generate_statement(&mut self, node: &IRNode) -> Result<String, String> {
+        let code = self.generate_ir_node(node)?;
+        Ok(self.region(&self.construct_name(node), node.source_loc(), code))
+    }
+
+    /// Wrap generated code in `# region` / `# endregion` comments naming
+    /// the WTLang construct and its file and line, which editors can fold
+    ///
+    /// Code lowered from a statement without a source span is left as is.
+    fn region(&self, construct: &str, source_loc: &ir::SourceRange, code: String) -> String {
+        if source_loc.start.line == 0 {
+            return code;
+        }
+        let indent = self.get_indent();
+        let imported = source_loc.node.and_then(|node| {
+            self.imported_files.iter().find(|(_, items)| items.contains(&node.item)).map(|(file, _)| file)
+        });
+        let location = match imported.or(self.source_file.as_ref()) {
+            Some(file) => format!("{}:{}", file, source_loc.start.line),
+            None => format!("line {}", source_loc.start.line),
+        };
+        format!("{}# region {} ({})\n{}{}# endregion\n", indent, construct, location, code, indent)
+    }
+
+    /// The construct a statement was written as, e.g. `let total`
+    fn construct_name(&self, node: &IRNode) -> String {
+        let quoted = |keyword: &str, text: &DisplayText| match text {
+            DisplayText::Literal(text) => format!("{} \"{}\"", keyword, self.escape_string(text)),
+            DisplayText::Interpolated(_) | DisplayText::Translated { .. } => keyword.to_string(),
+        };
+        match node {
+            IRNode::ShowTable { editable: true, .. } => "show_editable".to_string(),
+            IRNode::ShowTable { .. } => "show".to_string(),
+            IRNode::ShowText { style: TextStyle::Title, .. } => "title".to_string(),
+            IRNode::ShowText { style: TextStyle::Subtitle, .. } => "subtitle".to_string(),
+            IRNode::ShowText { style: TextStyle::Normal, .. } => "text".to_string(),
+            IRNode::Button { label, .. } => quoted("button", label),
+            IRNode::Section { title, .. } => quoted("section", title),
+            IRNode::Confirm { message, .. } => quoted("confirm", message),
+            IRNode::Conditional { .. } => "if".to_string(),
+            IRNode::Match { .. } => "match".to_string(),
+            IRNode::Loop { variable, .. } => format!("forall {}", variable),
+            IRNode::Binding { name, .. } => format!("let {}", name),
+            IRNode::Assignment { target, .. } => format!("{} = ...", target),
+            IRNode::ExprStmt { expr, .. } => match expr.as_ref() {
+                IRExpr::FunctionCall { function, .. } => format!("{}(...)", function),
+                _ => "expression".to_string(),
+            },
+            IRNode::Return { .. } => "return".to_string(),
+            IRNode::Assert { .. } => "assert".to_string(),
+            IRNode::Mock { target, .. } => format!("mock {}", target),
+            IRNode::Refresh { seconds, .. } => format!("refresh every {}s", seconds),
+            IRNode::Python { .. } => "python".to_string(),
+        }
+    }
+
+    fn generate_ir_node(&mut self, node: &IRNode) -> Result<String, String> {
+        let indent = self.get_indent();
+        
+        match node {
+            IRNode::ShowText { text, style, .. } => {
+                let text = self.display_text(text)?;
+                match style {
+                    TextStyle::Title => Ok(format!("{}st.title({})\n", indent, text)),
+                    TextStyle::Subtitle => Ok(format!("{}st.subheader({})\n", indent, text)),
+                    TextStyle::Normal => Ok(format!("{}st.write({})\n", indent, text)),
+                }
+            }
+            
+            IRNode::Button { label, body, .. } => {
+                // The body stays open while one of its confirmations is pending
+                let outer_keys = std::mem::take(&mut self.confirm_keys);
+                let mut body_code = String::new();
+                self.indent_level += 1;
+                for node in body {
+                    body_code.push_str(&self.generate_ir_node(node)?);
+                }
+                self.indent_level -= 1;
+                let keys = std::mem::replace(&mut self.confirm_keys, outer_keys);
+                let mut condition = format!("st.button({})", self.display_text(label)?);
+                for key in keys {
+                    condition.push_str(&format!(" or st.session_state.get(\"{}\")", key));
+                }
+                Ok(format!("{}if {}:\n{}", indent, condition, body_code))
+            }
+            
+            IRNode::Confirm { message, body, .. } => {
+                self.uses_confirm = true;
+                let key = format!("confirm_{}", self.get_unique_key());
+                let mut code = format!("{}if confirmed(\"{}\", {}):\n", indent, key, self.display_text(message)?);
+                self.confirm_keys.push(key);
+                self.indent_level += 1;
+                for node in body {
+                    code.push_str(&self.generate_ir_node(node)?);
+                }
+                self.indent_level -= 1;
+                Ok(code)
+            }
+            
+            IRNode::Section { title, body, .. } => {
+                let mut code = format!("{}with st.container():\n", indent);
+                self.indent_level += 1;
+                let heading = match title {
+                    DisplayText::Literal(title) => format!("\"### {}\"", self.escape_string(title)),
+                    DisplayText::Interpolated(_) | DisplayText::Translated { .. } => format!("\"### \" + {}", self.display_text(title)?),
+                };
+                code.push_str(&format!("{}st.markdown({})\n", self.get_indent(), heading));
+                for node in body {
+                    code.push_str(&self.generate_ir_node(node)?);
+                }
+                self.indent_level -= 1;
+                Ok(code)
+            }
+            
+            IRNode::Binding { name, ty, value, source_loc } => {
+                if let Some(val) = value {
+                    let value_code = self.generate_stage(*name, val, source_loc)?;
+                    self.bind_editor(*name, val);
+                    Ok(format!("{}{} = {}\n", indent, self.annotated(&self.python_name(name), ty), value_code))
+                } else {
+                    Ok(format!("{}{} = None  # Will be assigned later\n", indent, self.python_name(name)))
+                }
+            }
+            
+            IRNode::Assignment { target, value, source_loc } => {
+                let value_code = self.generate_stage(*target, value, source_loc)?;
+                self.bind_editor(*target, value);
+                Ok(format!("{}{} = {}\n", indent, self.variable_code(*target), value_code))
+            }
+            
             IRNode::Conditional { condition, then_branch, else_branch, .. } => {
                 let cond_code = self.generate_ir_expr(condition)?;
                 let mut code = format!("{}if {}:\n", indent, cond_code);
@@ -211,9 +1318,69 @@ impl CodeGenerator {
                 Ok(code)
             }
             
+            // A match statement where Python has them and the patterns
+            // compare by equality
+            IRNode::Match { value, arms, .. } if self.python >= PythonVersion::V3_10 && arms.iter().all(|arm| arm.patterns.iter().all(is_value_pattern)) => {
+                let mut code = format!("{}match {}:\n", indent, self.generate_ir_expr(value)?);
+                for arm in arms {
+                    let mut patterns = Vec::new();
+                    for pattern in &arm.patterns {
+                        if let ir::MatchPattern::Literal(literal) = pattern {
+                            patterns.push(self.generate_ir_expr(literal)?);
+                        }
+                    }
+                    if arm.patterns.contains(&ir::MatchPattern::Wildcard) {
+                        patterns = vec!["_".to_string()];
+                    }
+                    code.push_str(&format!("{}    case {}:\n", indent, patterns.join(" | ")));
+                    self.indent_level += 2;
+                    if arm.body.is_empty() {
+                        code.push_str(&format!("{}pass\n", self.get_indent()));
+                    }
+                    for node in &arm.body {
+                        code.push_str(&self.generate_ir_node(node)?);
+                    }
+                    self.indent_level -= 2;
+                    // Later arms are never taken
+                    if arm.patterns.contains(&ir::MatchPattern::Wildcard) {
+                        break;
+                    }
+                }
+                Ok(code)
+            }
+            
+            // An if/elif chain over the value, kept in a variable of its own
+            // so nested matches don't overwrite it
+            IRNode::Match { value, arms, .. } => {
+                let subject = format!("_match_{}", self.get_unique_key());
+                let mut code = format!("{}{} = {}\n", indent, subject, self.generate_ir_expr(value)?);
+                for (i, arm) in arms.iter().enumerate() {
+                    let header = match self.match_condition(&subject, &arm.patterns)? {
+                        Some(condition) if i == 0 => format!("if {}:", condition),
+                        Some(condition) => format!("elif {}:", condition),
+                        None if i == 0 => "if True:".to_string(),
+                        None => "else:".to_string(),
+                    };
+                    code.push_str(&format!("{}{}\n", indent, header));
+                    self.indent_level += 1;
+                    if arm.body.is_empty() {
+                        code.push_str(&format!("{}pass\n", self.get_indent()));
+                    }
+                    for node in &arm.body {
+                        code.push_str(&self.generate_ir_node(node)?);
+                    }
+                    self.indent_level -= 1;
+                    // Later arms are never taken
+                    if arm.patterns.contains(&ir::MatchPattern::Wildcard) {
+                        break;
+                    }
+                }
+                Ok(code)
+            }
+            
             IRNode::Loop { variable, iterable, body, .. } => {
                 let iter_code = self.generate_ir_expr(iterable)?;
-                let mut code = format!("{}for {} in {}:\n", indent, variable, iter_code);
+                let mut code = format!("{}for {} in {}:\n", indent, self.python_name(variable), iter_code);
                 self.indent_level += 1;
                 for node in body {
                     code.push_str(&self.generate_ir_node(node)?);
@@ -236,6 +1403,60 @@ impl CodeGenerator {
                 Ok(format!("{}{}\n", indent, expr_code))
             }
             
+            IRNode::Assert { condition, .. } => {
+                let cond_code = self.generate_ir_expr(condition)?;
+                Ok(format!("{}assert {}\n", indent, cond_code))
+            }
+            
+            IRNode::Mock { target, value, .. } => {
+                // Patched in the helpers module, where pages and functions
+                // look it up, and in the test module's own star-imported copy
+                let name = self.mock_target_name(*target);
+                let fake = match value.as_ref() {
+                    IRExpr::Lambda { .. } => self.generate_ir_expr(value)?,
+                    value if generated_rows(value).is_some() => {
+                        format!("lambda *args, **kwargs: tables[\"{}\"].copy()", target)
+                    }
+                    _ => format!("lambda *args, **kwargs: {}", self.generate_ir_expr(value)?),
+                };
+                // Functions of a module are only looked up in the module
+                if let Some((module, member)) = split_qualified(&name) {
+                    let fake_var = format!("_mock_{}_{}", module, member);
+                    let mut code = format!("{}{} = {}\n", indent, fake_var, fake);
+                    code.push_str(&format!("{}monkeypatch.setattr({}, \"{}\", {})\n", indent, module, member, fake_var));
+                    return Ok(code);
+                }
+                let fake_var = format!("_mock_{}", name);
+                let mut code = format!("{}{} = {}\n", indent, fake_var, fake);
+                code.push_str(&format!("{}monkeypatch.setattr({}, \"{}\", {})\n", indent, HELPERS_MODULE, name, fake_var));
+                code.push_str(&format!("{}monkeypatch.setitem(globals(), \"{}\", {})\n", indent, name, fake_var));
+                Ok(code)
+            }
+            
+            IRNode::Refresh { seconds, .. } => {
+                self.uses_refresh = true;
+                let key = self.get_unique_key();
+                Ok(format!("{}refresh_every({}, \"refresh_{}\")\n", indent, seconds, key))
+            }
+            
+            IRNode::Python { inputs, outputs, code, .. } => {
+                // Variables a template renamed get the names the code uses
+                let mut lines: Vec<String> = inputs.iter()
+                    .filter(|input| input.name != self.python_name(&input.variable))
+                    .map(|input| format!("{} = {}", input.name, self.python_name(&input.variable)))
+                    .collect();
+                lines.extend(code.lines().map(String::from));
+                lines.extend(outputs.iter()
+                    .filter(|output| output.name != self.python_name(&output.variable))
+                    .map(|output| format!("{} = {}", self.python_name(&output.variable), output.name)));
+                if lines.is_empty() {
+                    lines.push("pass".to_string());
+                }
+                Ok(lines.iter()
+                    .map(|line| if line.is_empty() { "\n".to_string() } else { format!("{}{}\n", indent, line) })
+                    .collect())
+            }
+            
             IRNode::ShowTable { table, filters, editable, key, .. } => {
                 let table_expr = self.generate_ir_expr(table)?;
                 
@@ -245,33 +1466,52 @@ impl CodeGenerator {
                         Ok(format!("{}st.data_editor({}, key=\"editor_{}\", use_container_width=True)\n", 
                             indent, table_expr, key))
                     } else {
-                        Ok(format!("{}st.dataframe({})\n", indent, table_expr))
+                        Ok(format!("{}st.dataframe({})\n", indent, self.formatted_table(table, table_expr)))
                     }
                 } else {
                     // With filters
                     let filter_list: Vec<String> = filters.iter()
-                        .map(|f| format!("('{}', '{}')", f.column, if f.mode == ir::FilterMode::Single { "single" } else { "multi" }))
+                        .map(|f| format!("({}, '{}')", python_string(f.column.as_str()), if f.mode == ir::FilterMode::Single { "single" } else { "multi" }))
                         .collect();
                     
-                    Ok(format!("{}_show_filtered({}, [{}], editable={}, key_prefix='f_{}')\n",
-                        indent, table_expr, filter_list.join(", "), editable, key))
+                    let schema = match self.formatted_schema(table) {
+                        Some(schema) if !*editable => format!(", schema={}", schema),
+                        _ => String::new(),
+                    };
+                    Ok(format!("{}show_filtered({}, [{}], editable={}, key_prefix='f_{}'{})\n",
+                        indent, table_expr, filter_list.join(", "), editable, key, schema))
                 }
             }
         }
     }
 
+    /// Generate the value of a binding, timed when tracing or profiling a
+    /// table operation
+    fn generate_stage(&mut self, name: Atom, value: &IRExpr, source_loc: &ir::SourceRange) -> Result<String, String> {
+        let mut value_code = self.generate_ir_expr(value)?;
+        if !is_pipeline_stage(value) {
+            return Ok(value_code);
+        }
+        let line = source_loc.start.line;
+        if self.trace {
+            value_code = format!("traced(\"{}\", {}, lambda: {})", name, line, value_code);
+        }
+        if self.profile {
+            value_code = format!("profiled(\"{}\", {}, lambda: {})", name, line, value_code);
+        }
+        Ok(value_code)
+    }
+
     fn generate_ir_expr(&mut self, expr: &IRExpr) -> Result<String, String> {
         match expr {
-            IRExpr::Literal { value, .. } => {
-                match value {
-                    Literal::Int(n) => Ok(n.to_string()),
-                    Literal::Float(f) => Ok(f.to_string()),
-                    Literal::String(s) => Ok(format!("\"{}\"", self.escape_string(s))),
-                    Literal::Bool(b) => Ok(if *b { "True" } else { "False" }.to_string()),
-                }
+            IRExpr::Literal { value, .. } => Ok(self.generate_literal(value)),
+            
+            // Derived tables are loaded where they are used
+            IRExpr::Variable { name, .. } if self.is_derived_table(*name) => {
+                Ok(format!("{}()", table_loader_name(*name)))
             }
             
-            IRExpr::Variable { name, .. } => Ok(name.clone()),
+            IRExpr::Variable { name, .. } => Ok(self.variable_code(*name)),
             
             IRExpr::BinaryOp { op, left, right, .. } => {
                 let left_code = self.generate_ir_expr(left)?;
@@ -290,6 +1530,7 @@ impl CodeGenerator {
                     BinOp::Ge => ">=",
                     BinOp::And => "and",
                     BinOp::Or => "or",
+                    BinOp::In => "in",
                     BinOp::Union => {
                         // Set union - use pd.concat
                         return Ok(format!("pd.concat([{}, {}], ignore_index=True).drop_duplicates()",
@@ -315,16 +1556,62 @@ impl CodeGenerator {
                 let op_str = match op {
                     UnOp::Not => "not",
                     UnOp::Neg => "-",
+                    UnOp::IsNull => return Ok(format!("pd.isna({})", operand_code)),
+                    UnOp::IsNotNull => return Ok(format!("pd.notna({})", operand_code)),
                 };
                 Ok(format!("{} {}", op_str, operand_code))
             }
             
+            IRExpr::Between { value, low, high, .. } => {
+                let value_code = self.generate_ir_expr(value)?;
+                let low_code = self.generate_ir_expr(low)?;
+                let high_code = self.generate_ir_expr(high)?;
+                Ok(format!("({} <= {} <= {})", low_code, value_code, high_code))
+            }
+            
+            // Conditional expressions over the value, bound by a lambda; the
+            // match covers every value, so the last arm taken is the `else`
+            IRExpr::Match { value, arms, .. } => {
+                let value_code = self.generate_ir_expr(value)?;
+                let mut code = String::new();
+                for (i, arm) in arms.iter().enumerate() {
+                    let body_code = self.generate_ir_expr(&arm.body)?;
+                    match self.match_condition("_match", &arm.patterns)? {
+                        Some(condition) if i + 1 < arms.len() => {
+                            code.push_str(&format!("{} if {} else ", body_code, condition));
+                        }
+                        _ => {
+                            code.push_str(&body_code);
+                            break;
+                        }
+                    }
+                }
+                Ok(format!("(lambda _match: {})({})", code, value_code))
+            }
+            
             IRExpr::FunctionCall { function, args, .. } => {
+                if self.in_having {
+                    if let Some(code) = self.group_aggregate(function, args) {
+                        return Ok(code);
+                    }
+                }
                 self.generate_ir_function_call(function, args)
             }
             
             IRExpr::FieldAccess { object, field, .. } => {
                 let obj_code = self.generate_ir_expr(object)?;
+                // The user's claims are a dict, and a claim the identity
+                // provider does not send is None
+                if let IRExpr::FunctionCall { function, .. } = object.as_ref() {
+                    if function == "current_user" && !self.is_external(function) {
+                        return Ok(format!("{}.get(\"{}\")", obj_code, self.escape_string(field.as_str())));
+                    }
+                }
+                // Rows are Series, whose attributes include methods such as
+                // count, so their fields are read by label
+                if matches!(object.get_type(), ir::Type::Row(_)) {
+                    return Ok(format!("{}[\"{}\"]", obj_code, self.escape_string(field.as_str())));
+                }
                 Ok(format!("{}.{}", obj_code, field))
             }
             
@@ -339,10 +1626,17 @@ impl CodeGenerator {
                 // In Python, this is a function call: right(left)
                 let left_code = self.generate_ir_expr(left)?;
                 
-                // If right is a function call, insert left as first argument
+                // If right is a function call, left replaces its `_`
+                // placeholder or is inserted as first argument
                 if let IRExpr::FunctionCall { function, args, .. } = &**right {
-                    let mut all_args = vec![left.as_ref().clone()];
-                    all_args.extend(args.clone());
+                    let is_placeholder = |arg: &IRExpr| matches!(arg, IRExpr::Variable { name, .. } if name.as_str() == "_");
+                    let all_args = if args.iter().any(is_placeholder) {
+                        args.iter().map(|arg| if is_placeholder(arg) { left.as_ref().clone() } else { arg.clone() }).collect()
+                    } else {
+                        let mut all_args = vec![left.as_ref().clone()];
+                        all_args.extend(args.clone());
+                        all_args
+                    };
                     self.generate_ir_function_call(function, &all_args)
                 } else {
                     let right_code = self.generate_ir_expr(right)?;
@@ -351,15 +1645,44 @@ impl CodeGenerator {
             }
             
             IRExpr::Lambda { params, body, .. } => {
-                let params_str = params.join(", ");
+                let params_str = params.iter().map(|p| self.python_name(p)).collect::<Vec<_>>().join(", ");
                 let body_code = self.generate_ir_expr(body)?;
                 Ok(format!("lambda {}: {}", params_str, body_code))
             }
             
+            // Query strings cannot test membership in a variable's list,
+            // missing values or dates, so conditions using `in`, `between`,
+            // `is null` or a date select rows with a mask instead
+            IRExpr::Where { table, condition, .. } if needs_row_mask(condition) => {
+                let table_code = self.generate_ir_expr(table)?;
+                let schema = table.get_type().as_table()
+                    .and_then(|table| self.table_schemas.get(&table.declared()?))
+                    .cloned();
+                let is_column = |name: Atom, ty: &ir::Type| match &schema {
+                    Some(schema) => schema.has_field(&name),
+                    // Names the builder could not resolve are columns
+                    None => *ty == ir::Type::Error,
+                };
+                let mask = self.generate_row_mask(condition, &is_column)?;
+                Ok(format!("{}.loc[lambda df: {}]", table_code, mask))
+            }
+            
             IRExpr::Where { table, condition, .. } => {
                 let table_code = self.generate_ir_expr(table)?;
                 let condition_code = self.generate_where_condition(condition)?;
-                Ok(format!("{}.query(\"{}\")", table_code, condition_code))
+                Ok(format!("{}.query({})", table_code, python_string(&condition_code)))
+            }
+            
+            // The rows of the groups whose aggregates satisfy the condition
+            IRExpr::GroupBy { table, columns, having, .. } => {
+                let table_code = self.generate_ir_expr(table)?;
+                let columns_code: Vec<String> = columns.iter()
+                    .map(|column| python_string(column.as_str()))
+                    .collect();
+                self.in_having = true;
+                let having_code = self.generate_ir_expr(having);
+                self.in_having = false;
+                Ok(format!("{}.groupby([{}]).filter(lambda g: {})", table_code, columns_code.join(", "), having_code?))
             }
             
             IRExpr::SortBy { table, columns, .. } => {
@@ -369,24 +1692,56 @@ impl CodeGenerator {
                     return Ok(table_code);
                 }
                 
-                if columns.len() == 1 {
-                    let col = &columns[0];
-                    let asc_str = if col.ascending { "True" } else { "False" };
-                    Ok(format!("{}.sort_values(by='{}', ascending={})",
-                        table_code, col.column, asc_str))
+                // Keys other than bare columns are computed into helper
+                // columns, which are dropped once the rows are sorted
+                let schema = table.get_type().as_table()
+                    .and_then(|table| self.table_schemas.get(&table.declared()?))
+                    .cloned();
+                let is_column = |name: Atom, ty: &ir::Type| match &schema {
+                    Some(schema) => schema.has_field(&name),
+                    None => *ty == ir::Type::Error,
+                };
+                let mut col_names = Vec::new();
+                let mut helpers = Vec::new();
+                for (index, col) in columns.iter().enumerate() {
+                    if let IRExpr::Variable { name, .. } = &col.key {
+                        col_names.push(python_string(name.as_str()));
+                        continue;
+                    }
+                    let helper = format!("_sort_{}", index);
+                    let key_code = self.generate_row_mask(&col.key, &is_column)?;
+                    helpers.push((helper.clone(), key_code));
+                    col_names.push(python_string(&helper));
+                }
+                
+                let table_code = if helpers.is_empty() {
+                    table_code
                 } else {
-                    let col_names: Vec<String> = columns.iter()
-                        .map(|c| format!("'{}'", c.column))
+                    let assigned: Vec<String> = helpers.iter()
+                        .map(|(helper, key_code)| format!("{}=lambda df: {}", helper, key_code))
                         .collect();
+                    format!("{}.assign({})", table_code, assigned.join(", "))
+                };
+                let sorted = if columns.len() == 1 {
+                    let asc_str = if columns[0].ascending { "True" } else { "False" };
+                    format!("{}.sort_values(by={}, ascending={})", table_code, col_names[0], asc_str)
+                } else {
                     let ascending: Vec<String> = columns.iter()
                         .map(|c| if c.ascending { "True".to_string() } else { "False".to_string() })
                         .collect();
                     
-                    Ok(format!("{}.sort_values(by=[{}], ascending=[{}])",
+                    format!("{}.sort_values(by=[{}], ascending=[{}])",
                         table_code,
                         col_names.join(", "),
-                        ascending.join(", ")))
+                        ascending.join(", "))
+                };
+                if helpers.is_empty() {
+                    return Ok(sorted);
                 }
+                let helper_names: Vec<String> = helpers.iter()
+                    .map(|(helper, _)| python_string(helper))
+                    .collect();
+                Ok(format!("{}.drop(columns=[{}])", sorted, helper_names.join(", ")))
             }
             
             IRExpr::ColumnSelect { table, columns, .. } => {
@@ -397,13 +1752,23 @@ impl CodeGenerator {
                 }
                 
                 let cols = columns.iter()
-                    .map(|c| format!("'{}'", c))
+                    .map(|c| python_string(c.as_str()))
                     .collect::<Vec<_>>()
                     .join(", ");
                 
                 Ok(format!("{}[[{}]]", table_code, cols))
             }
             
+            IRExpr::Rename { table, columns, .. } => {
+                let table_code = self.generate_ir_expr(table)?;
+                Ok(format!("{}.rename(columns={})", table_code, renamed_columns(columns)))
+            }
+            
+            IRExpr::DropColumns { table, columns, .. } => {
+                let table_code = self.generate_ir_expr(table)?;
+                Ok(format!("{}.drop(columns={})", table_code, dropped_columns(columns)))
+            }
+            
             IRExpr::Union { left, right, .. } => {
                 let left_code = self.generate_ir_expr(left)?;
                 let right_code = self.generate_ir_expr(right)?;
@@ -440,17 +1805,29 @@ impl CodeGenerator {
                 let target_var = target_table.to_lowercase();
                 
                 // Get key field of target table (for now, assume it's 'id')
-                let target_key = self.get_table_key(target_table)
+                let target_key = self.get_table_key(*target_table)
                     .unwrap_or_else(|_| "id".to_string());
                 
-                Ok(format!("{}.merge({}, left_on='{}', right_on='{}', how='left')",
-                    object_code, target_var, field, target_key))
+                Ok(format!("{}.merge({}, left_on={}, right_on={}, how='left')",
+                    object_code, target_var, python_string(field.as_str()), python_string(&target_key)))
+            }
+            
+            IRExpr::TableConstructor { fields, .. } => {
+                // A single row, as a column -> value dict
+                let fields_code: Result<Vec<_>, String> = fields.iter()
+                    .map(|(name, value)| {
+                        let value_code = self.generate_ir_expr(value)?;
+                        Ok(format!("\"{}\": {}", self.escape_string(name.as_str()), value_code))
+                    })
+                    .collect();
+                Ok(format!("{{{}}}", fields_code?.join(", ")))
             }
             
-            IRExpr::TableConstructor { .. } |
-            IRExpr::ArrayConstructor { .. } => {
-                // These would need special handling
-                Ok("{}".to_string())
+            IRExpr::ArrayConstructor { elements, .. } => {
+                let elements_code: Result<Vec<_>, String> = elements.iter()
+                    .map(|element| self.generate_ir_expr(element))
+                    .collect();
+                Ok(format!("[{}]", elements_code?.join(", ")))
             }
         }
     }
@@ -461,19 +1838,201 @@ impl CodeGenerator {
             .collect();
         let args_code = args_code?;
         
+        // Builtins of plugins, with their code for this backend
+        if let Some(plugin) = self.plugins.get(function) {
+            let backend = Backend::Streamlit.name();
+            let code = plugin.generate(backend, &args_code)
+                .ok_or_else(|| format!("Builtin {}() has no code for the {} backend", function, backend))?;
+            for import in plugin.imports(backend) {
+                if !self.plugin_imports.contains(&import) {
+                    self.plugin_imports.push(import);
+                }
+            }
+            return Ok(code);
+        }
+        
         // Handle built-in functions
         match function {
             "load_csv" => {
                 if args_code.is_empty() {
                     return Err("load_csv requires at least a file path argument".to_string());
                 }
-                Ok(format!("pd.read_csv({})", args_code[0]))
+                // A table type argument loads through the table's helper,
+                // matching the headers loosely with a "normalize" argument
+                if let Some(IRExpr::Variable { name, .. }) = args.get(1) {
+                    if self.table_schemas.contains_key(name) {
+                        if let Some(IRExpr::Literal { value: Literal::String(columns), .. }) = args.get(2) {
+                            if columns == "normalize" {
+                                self.normalized_tables.insert(*name);
+                                return Ok(format!("{}({}, match_headers=True)", table_loader_name(*name), args_code[0]));
+                            }
+                        }
+                        return Ok(format!("{}({})", table_loader_name(*name), args_code[0]));
+                    }
+                }
+                Ok(format!("read_csv({})", args_code[0]))
             }
             "save_csv" => {
                 if args_code.len() < 2 {
                     return Err("save_csv requires table and file path arguments".to_string());
                 }
-                Ok(format!("{}.to_csv({}, index=False)", args_code[0], args_code[1]))
+                // Tables of a known type are validated before saving
+                if let Some(name) = args[0].get_type().as_table().and_then(|schema| schema.declared()) {
+                    if self.table_schemas.contains_key(&name) {
+                        return Ok(format!("{}({}, {})", table_saver_name(name), args_code[0], args_code[1]));
+                    }
+                }
+                Ok(format!("write_csv({}, {})", args_code[0], args_code[1]))
+            }
+            // load_sql(connection, name, Table?) and save_sql(table,
+            // connection, name, mode?) go through the table's helpers when
+            // its type is known, so the schema and constraints are checked
+            "load_sql" => {
+                if args_code.len() < 2 {
+                    return Err("load_sql requires connection and table name arguments".to_string());
+                }
+                self.uses_sql = true;
+                if let Some(IRExpr::Variable { name, .. }) = args.get(2) {
+                    if self.table_schemas.contains_key(name) {
+                        self.sql_tables.insert(*name);
+                        return Ok(format!("{}({}, connection={})", table_loader_name(*name), args_code[1], args_code[0]));
+                    }
+                }
+                Ok(format!("load_sql({}, {})", args_code[0], args_code[1]))
+            }
+            "save_sql" => {
+                if !(3..=4).contains(&args_code.len()) {
+                    return Err("save_sql requires table, connection, table name and optional mode arguments".to_string());
+                }
+                self.uses_sql = true;
+                let mode = args_code.get(3).cloned().unwrap_or_else(|| "\"append\"".to_string());
+                if let Some(schema) = args[0].get_type().as_table().and_then(|schema| self.table_schemas.get(&schema.declared()?)) {
+                    let has_key = schema.constraints.iter().any(|c| matches!(c, ir::Constraint::PrimaryKey(_)));
+                    if mode == "\"upsert\"" && !has_key {
+                        return Err(format!("save_sql: upsert needs a key field in table {}", schema.name));
+                    }
+                    let name = schema.name;
+                    self.sql_tables.insert(name);
+                    return Ok(format!("{}({}, {}, connection={}, mode={})", table_saver_name(name), args_code[0], args_code[2], args_code[1], mode));
+                }
+                Ok(format!("save_sql({}, {}, {}, {})", args_code[0], args_code[1], args_code[2], mode))
+            }
+            "log" => {
+                if args_code.is_empty() {
+                    return Err("log requires a message argument".to_string());
+                }
+                Ok(format!("log_message({})", args_code.join(", ")))
+            }
+            "t" => match args {
+                [IRExpr::Literal { value: Literal::String(key), .. }] => {
+                    self.display_text(&DisplayText::Translated { key: key.clone() })
+                }
+                _ => Err("t requires a string literal key".to_string()),
+            },
+            // An external function of the same name replaces the runtime's
+            "format_number" | "format_currency" | "format_date" if !self.is_external(function) => {
+                self.uses_formats = true;
+                Ok(format!("{}({})", function, args_code.join(", ")))
+            }
+            // Missing values stay missing rather than failing to round
+            "round" | "floor" | "ceil" if !self.is_external(function) => {
+                self.uses_numbers = true;
+                Ok(format!("{}_number({})", function, args_code.join(", ")))
+            }
+            "percent" if !self.is_external(function) => {
+                self.uses_numbers = true;
+                Ok(format!("percent({})", args_code.join(", ")))
+            }
+            // Each row's share of the column's total, or of its group's
+            "share" if !self.is_external(function) => {
+                let literal = |arg: Option<&IRExpr>| match arg {
+                    Some(IRExpr::Literal { value: Literal::String(value), .. }) => Some(value.clone()),
+                    _ => None,
+                };
+                let (Some(column), group) = (literal(args.get(1)), literal(args.get(2))) else {
+                    return Err("share requires a table and a column name".to_string());
+                };
+                let total = match group {
+                    Some(group) => format!("df.groupby({})[{}].transform(\"sum\")", python_string(&group), python_string(&column)),
+                    None => format!("df[{}].sum()", python_string(&column)),
+                };
+                let share = format!("{}_share", column);
+                let value = format!("lambda df: df[{}] / {}", python_string(&column), total);
+                // A keyword argument needs a name Python accepts as one
+                if is_plain_identifier(&share) {
+                    Ok(format!("{}.assign({}={})", args_code[0], share, value))
+                } else {
+                    Ok(format!("{}.assign(**{{{}: {}}})", args_code[0], python_string(&share), value))
+                }
+            }
+            // Missing values are skipped, unless "strict" makes them an error
+            "sum" | "average" if !self.is_external(function) && args.len() >= 2 => {
+                let method = if function == "sum" { "sum" } else { "mean" };
+                match args.get(2) {
+                    Some(IRExpr::Literal { value: Literal::String(mode), .. }) if mode == "strict" => {
+                        self.uses_strict_aggregates = true;
+                        Ok(format!("strict_aggregate({}, {}, \"{}\")", args_code[0], args_code[1], method))
+                    }
+                    _ => Ok(format!("{}[{}].{}(skipna=True)", args_code[0], args_code[1], method)),
+                }
+            }
+            "send_email" if !self.is_external(function) => {
+                if !(3..=4).contains(&args.len()) {
+                    return Err("send_email requires a recipient, subject, body and optional table attachment".to_string());
+                }
+                self.uses_email = true;
+                let mut call_args = args_code[..3].to_vec();
+                if let Some(attachment) = args.get(3) {
+                    call_args.push(format!("attach={}", args_code[3]));
+                    if let IRExpr::Variable { name, .. } = attachment {
+                        call_args.push(format!("attach_name=\"{}.csv\"", name));
+                    }
+                }
+                Ok(format!("send_email({})", call_args.join(", ")))
+            }
+            "export_pdf" if !self.is_external(function) => match args {
+                [IRExpr::Literal { value: Literal::String(section), .. }] => self.generate_pdf_export(section),
+                _ => Err("export_pdf requires a section title string literal".to_string()),
+            },
+            // Discarding the edits of the editors showing a table, and those
+            // whose result it holds, restores the table as loaded
+            "revert" if !self.is_external(function) => match args {
+                [IRExpr::Variable { name, .. }] => {
+                    self.uses_revert = true;
+                    let editors = self.editors.get(name).map(Vec::as_slice).unwrap_or_default()
+                        .iter()
+                        .map(|key| python_string(key))
+                        .collect::<Vec<_>>();
+                    Ok(format!("revert([{}])", editors.join(", ")))
+                }
+                _ => Err("revert requires a table variable".to_string()),
+            },
+            "current_user" if !self.is_external(function) => {
+                self.uses_current_user = true;
+                Ok("current_user()".to_string())
+            }
+            // Scalars are written formatted for their type, rows as their
+            // fields and tables like show
+            "display" if !self.is_external(function) => {
+                if args_code.len() != 1 {
+                    return Err("display requires one value argument".to_string());
+                }
+                let value = match args[0].get_type() {
+                    ir::Type::Table(_) => return Ok(format!("st.dataframe({})", self.formatted_table(&args[0], args_code[0].clone()))),
+                    ir::Type::Float => format!("format_number({})", args_code[0]),
+                    ir::Type::Currency => format!("format_currency({})", args_code[0]),
+                    ir::Type::Date => format!("format_date({})", args_code[0]),
+                    _ => return Ok(format!("st.write({})", args_code[0])),
+                };
+                self.uses_formats = true;
+                Ok(format!("st.write({})", value))
+            }
+            "first" | "last" | "single" if !self.is_external(function) => {
+                if args_code.len() != 1 {
+                    return Err(format!("{} requires a table argument", function));
+                }
+                self.uses_rows = true;
+                Ok(format!("{}_row({})", function, args_code[0]))
             }
             "where" => {
                 if args_code.is_empty() {
@@ -482,7 +2041,11 @@ impl CodeGenerator {
                 if args_code.len() < 2 {
                     return Ok(args_code[0].clone());
                 }
-                // Lambda expression for filter
+                // A lambda is called with each row
+                if matches!(args[1], IRExpr::Lambda { .. }) {
+                    self.uses_where_rows = true;
+                    return Ok(format!("where_rows({}, {})", args_code[0], args_code[1]));
+                }
                 Ok(format!("{}[{}]", args_code[0], args_code[1]))
             }
             "sort" => {
@@ -503,7 +2066,7 @@ impl CodeGenerator {
                     return Err("show requires a table argument".to_string());
                 }
                 // Generate st.dataframe() for show
-                Ok(format!("st.dataframe({})", args_code[0]))
+                Ok(format!("st.dataframe({})", self.formatted_table(&args[0], args_code[0].clone())))
             }
             "show_editable" => {
                 if args_code.is_empty() {
@@ -511,11 +2074,46 @@ impl CodeGenerator {
                 }
                 // Generate st.data_editor() for show_editable
                 let key_num = self.get_unique_key();
-                Ok(format!("st.data_editor({}, key=\"editor_{}\", use_container_width=True)", args_code[0], key_num))
+                let editor = format!("st.data_editor({}, key=\"editor_{}\", use_container_width=True)", args_code[0], key_num);
+                if let IRExpr::Variable { name, .. } = &args[0] {
+                    self.editors.entry(*name).or_default().push(format!("editor_{}", key_num));
+                }
+                self.last_editor = Some(format!("editor_{}", key_num));
+                
+                // An audit(...) option records every edit
+                let schema = args[0].get_type().as_table().and_then(|schema| self.table_schemas.get(&schema.declared()?));
+                let audit = args.iter().zip(&args_code).skip(1)
+                    .find(|(arg, _)| matches!(arg, IRExpr::FunctionCall { function, .. } if function == "audit"));
+                let mut edited = editor;
+                if let Some((_, audit_code)) = audit {
+                    self.uses_audit = true;
+                    let table = schema.map_or("table".to_string(), |schema| schema.name.to_string());
+                    let key = schema
+                        .and_then(|schema| schema.constraints.iter().find_map(|c| match c {
+                            ir::Constraint::PrimaryKey(field) => Some(python_string(field.as_str())),
+                            _ => None,
+                        }))
+                        .unwrap_or_else(|| "None".to_string());
+                    edited = format!("audit_edits({}, {}, {}, \"editor_{}\", {}, {})", edited, args_code[0], audit_code, key_num, python_string(&table), key);
+                }
+                
+                // The table's on_edit hook runs on every edit
+                if let Some(schema) = schema.filter(|schema| self.hooks.contains(&(ir::HookEvent::Edit, schema.name))) {
+                    self.uses_on_edit = true;
+                    edited = format!("on_edited({}, {}, \"editor_{}\", {})", edited, args_code[0], key_num,
+                        hook_function_name(ir::HookEvent::Edit, schema.name));
+                }
+                Ok(edited)
             }
             _ => {
-                // Regular function call
-                Ok(format!("{}({})", function, args_code.join(", ")))
+                // Regular function call; builtins passed through and
+                // external functions keep their names in Python
+                let name = if self.functions.iter().any(|defined| defined == function) {
+                    self.python_name(function)
+                } else {
+                    self.unqualified_name(function).to_string()
+                };
+                Ok(format!("{}({})", name, args_code.join(", ")))
             }
         }
     }
@@ -525,11 +2123,13 @@ impl CodeGenerator {
         match expr {
             Expr::IntLiteral(n) => Ok(n.to_string()),
             Expr::FloatLiteral(f) => Ok(f.to_string()),
-            Expr::StringLiteral(s) => Ok(format!("\"{}\"", self.escape_string(s))),
+            Expr::StringLiteral(s) => Ok(python_string(s)),
             Expr::BoolLiteral(b) => Ok(if *b { "True" } else { "False" }.to_string()),
-            Expr::Identifier(name) => Ok(name.clone()),
+            Expr::DateLiteral(date) => Ok(date_literal(date)),
+            Expr::CurrencyLiteral { amount, .. } => Ok(currency_literal(amount)),
+            Expr::Identifier(name) => Ok(name.to_string()),
             Expr::FunctionCall(call) => self.generate_function_call(call),
-            Expr::BinaryOp { op, left, right } => {
+            Expr::BinaryOp { op, left, right, .. } => {
                 let left_code = self.generate_expr(left)?;
                 let right_code = self.generate_expr(right)?;
                 let op_str = match op {
@@ -546,6 +2146,7 @@ impl CodeGenerator {
                     BinaryOp::GreaterThanEqual => ">=",
                     BinaryOp::And => "and",
                     BinaryOp::Or => "or",
+                    BinaryOp::In => "in",
                     BinaryOp::Union => {
                         return Ok(format!("pd.concat([{}, {}], ignore_index=True).drop_duplicates()",
                             left_code, right_code));
@@ -562,24 +2163,32 @@ impl CodeGenerator {
                 };
                 Ok(format!("({} {} {})", left_code, op_str, right_code))
             },
-            Expr::UnaryOp { op, operand } => {
+            Expr::UnaryOp { op, operand, .. } => {
                 let operand_code = self.generate_expr(operand)?;
                 let op_str = match op {
                     UnaryOp::Not => "not",
                     UnaryOp::Negate => "-",
+                    UnaryOp::IsNull => return Ok(format!("pd.isna({})", operand_code)),
+                    UnaryOp::IsNotNull => return Ok(format!("pd.notna({})", operand_code)),
                 };
                 Ok(format!("({} {})", op_str, operand_code))
             },
-            Expr::FieldAccess { object, field } => {
+            Expr::Between { value, low, high, .. } => {
+                let value_code = self.generate_expr(value)?;
+                let low_code = self.generate_expr(low)?;
+                let high_code = self.generate_expr(high)?;
+                Ok(format!("({} <= {} <= {})", low_code, value_code, high_code))
+            },
+            Expr::FieldAccess { object, field, .. } => {
                 let obj_code = self.generate_expr(object)?;
                 Ok(format!("{}[{:?}]", obj_code, field))
             },
-            Expr::Index { object, index } => {
+            Expr::Index { object, index, .. } => {
                 let obj_code = self.generate_expr(object)?;
                 let idx_code = self.generate_expr(index)?;
                 Ok(format!("{}.iloc[{}]", obj_code, idx_code))
             },
-            Expr::Chain { left, right } => {
+            Expr::Chain { left, right, .. } => {
                 // Function chaining: left -> right
                 // Right should be a function call with _ as first argument
                 let left_code = self.generate_expr(left)?;
@@ -591,7 +2200,7 @@ impl CodeGenerator {
                         let mut new_call = call.clone();
                         for arg in &mut new_call.args {
                             if matches!(arg, Expr::Identifier(name) if name == "_") {
-                                *arg = Expr::Identifier(left_code.clone());
+                                *arg = Expr::Identifier(Atom::new(&left_code));
                             }
                         }
                         // Use generate_function_call to handle special functions properly
@@ -608,25 +2217,30 @@ impl CodeGenerator {
                 // Array literals for filters are handled specially in show/show_editable
                 Err("Array literals must be handled in context (e.g., for filters)".to_string())
             },
-            Expr::Where { table, condition } => {
+            Expr::Where { table, condition, .. } => {
                 let table_code = self.generate_expr(table)?;
                 let condition_code = self.generate_where_condition_ast(condition)?;
-                Ok(format!("{}.query({})", table_code, condition_code))
+                Ok(format!("{}.query({})", table_code, python_string(&condition_code)))
             },
-            Expr::SortBy { table, columns } => {
+            Expr::SortBy { table, columns, .. } => {
                 let table_code = self.generate_expr(table)?;
                 
                 if columns.is_empty() {
                     return Ok(table_code);
                 }
                 
+                let mut names = Vec::new();
+                for col in columns {
+                    names.push(col.column().ok_or("Sorting by an expression needs the IR code generator")?);
+                }
+                
                 if columns.len() == 1 {
                     let col = &columns[0];
-                    Ok(format!("{}.sort_values(by='{}', ascending={})",
-                        table_code, col.name, col.ascending))
+                    Ok(format!("{}.sort_values(by={}, ascending={})",
+                        table_code, python_string(names[0].as_str()), col.ascending))
                 } else {
-                    let col_names: Vec<String> = columns.iter()
-                        .map(|c| format!("'{}'", c.name))
+                    let col_names: Vec<String> = names.iter()
+                        .map(|name| python_string(name.as_str()))
                         .collect();
                     let ascending: Vec<String> = columns.iter()
                         .map(|c| c.ascending.to_string())
@@ -638,19 +2252,30 @@ impl CodeGenerator {
                         ascending.join(", ")))
                 }
             },
-            Expr::ColumnSelect { table, columns } => {
+            Expr::ColumnSelect { table, columns, .. } => {
                 let table_code = self.generate_expr(table)?;
                 
                 if columns.is_empty() {
                     return Ok(table_code);
                 }
                 
-                let cols = columns.iter()
-                    .map(|c| format!("'{}'", c))
-                    .collect::<Vec<_>>()
-                    .join(", ");
+                let mut cols = Vec::new();
+                for col in columns {
+                    match col {
+                        SelectedColumn::Name(name) => cols.push(python_string(name.as_str())),
+                        SelectedColumn::Rest { .. } => return Err("Selecting '*' needs the IR code generator".to_string()),
+                    }
+                }
                 
-                Ok(format!("{}[[{}]]", table_code, cols))
+                Ok(format!("{}[[{}]]", table_code, cols.join(", ")))
+            },
+            Expr::Rename { table, columns, .. } => {
+                let table_code = self.generate_expr(table)?;
+                Ok(format!("{}.rename(columns={})", table_code, renamed_columns(columns)))
+            },
+            Expr::DropColumns { table, columns, .. } => {
+                let table_code = self.generate_expr(table)?;
+                Ok(format!("{}.drop(columns={})", table_code, dropped_columns(columns)))
             },
             _ => Err(format!("Unsupported expression: {:?}", expr)),
         }
@@ -668,26 +2293,15 @@ impl CodeGenerator {
                 // Check if a table type was specified as second argument
                 if call.args.len() >= 2 {
                     if let Expr::Identifier(table_name) = &call.args[1] {
-                        if let Some(table_def) = self.table_defs.get(table_name) {
-                            // Generate code with validation
-                            let field_names: Vec<String> = table_def.fields.iter()
-                                .map(|f| format!("\"{}\"", f.name))
-                                .collect();
-                            let expected_cols = format!("[{}]", field_names.join(", "));
-                            
-                            return Ok(format!(
-                                "(_df := pd.read_csv({}), \
-                                st.error(f'Invalid CSV: expected columns {}, got {{list(_df.columns)}}') \
-                                if not set({}).issubset(set(_df.columns)) else None, \
-                                _df)[2]",
-                                file_arg, expected_cols, expected_cols
-                            ));
+                        if self.table_defs.contains_key(table_name) {
+                            // The table's loader validates the schema
+                            return Ok(format!("{}({})", table_loader_name(*table_name), file_arg));
                         }
                     }
                 }
                 
                 // No table type specified, just load the CSV
-                return Ok(format!("pd.read_csv({})", file_arg));
+                return Ok(format!("read_csv({})", file_arg));
             },
             "show" => {
                 if call.args.len() < 1 {
@@ -724,7 +2338,7 @@ impl CodeGenerator {
                 }
                 let df_arg = self.generate_expr(&call.args[0])?;
                 let file_arg = self.generate_expr(&call.args[1])?;
-                return Ok(format!("{}.to_csv({}, index=False)", df_arg, file_arg));
+                return Ok(format!("write_csv({}, {})", df_arg, file_arg));
             },
             "export_excel" => {
                 if call.args.len() < 2 {
@@ -792,25 +2406,281 @@ impl CodeGenerator {
     }
 
     fn escape_string(&self, s: &str) -> String {
-        s.replace('\\', "\\\\")
-         .replace('"', "\\\"")
-         .replace('\n', "\\n")
-         .replace('\t', "\\t")
+        escape_python(s)
     }
 
-    fn format_string_interpolation(&self, text: &str) -> String {
-        // Simple f-string conversion for {var} syntax
-        if text.contains('{') {
-            format!("f\"{}\"", text.replace('"', "\\\""))
-        } else {
-            format!("\"{}\"", self.escape_string(text))
+    /// Python expression for text shown on a page
+    ///
+    /// Translations may use `{var}` placeholders for the variables in scope.
+    fn display_text(&mut self, text: &DisplayText) -> Result<String, String> {
+        match text {
+            DisplayText::Literal(text) => Ok(python_string(text)),
+            DisplayText::Interpolated(segments) => self.interpolated_text(segments),
+            DisplayText::Translated { key } => {
+                self.translation_keys.insert(key.clone());
+                self.page_translated = true;
+                Ok(format!("t(\"{}\", locals())", self.escape_string(key)))
+            }
+        }
+    }
+
+    /// An f-string showing the values of the text's expressions
+    ///
+    /// Before Python 3.12 an f-string's expressions cannot contain its quote
+    /// or backslashes, so their strings are single-quoted, and text whose
+    /// expressions need both quotes is concatenated instead. From 3.12 the
+    /// expressions are nested as they are.
+    fn interpolated_text(&mut self, segments: &[TextSegment]) -> Result<String, String> {
+        let mut codes = Vec::new();
+        for segment in segments {
+            if let TextSegment::Expr(expr) = segment {
+                codes.push(self.generate_ir_expr(expr)?);
+            }
+        }
+        let nests = self.python >= PythonVersion::V3_12;
+        let fits = nests || !codes.iter().any(|code| code.contains('\\') || (code.contains('"') && code.contains('\'')));
+        let mut codes = codes.into_iter();
+        if fits {
+            let text: String = segments.iter()
+                .map(|segment| match segment {
+                    TextSegment::Text(text) => self.escape_string(text).replace('{', "{{").replace('}', "}}"),
+                    TextSegment::Expr(_) if nests => format!("{{{}}}", codes.next().unwrap_or_default()),
+                    TextSegment::Expr(_) => format!("{{{}}}", codes.next().unwrap_or_default().replace('"', "'")),
+                })
+                .collect();
+            return Ok(format!("f\"{}\"", text));
+        }
+        let parts: Vec<String> = segments.iter()
+            .map(|segment| match segment {
+                TextSegment::Text(text) => python_string(text),
+                TextSegment::Expr(_) => format!("str({})", codes.next().unwrap_or_default()),
+            })
+            .collect();
+        Ok(parts.join(" + "))
+    }
+
+    fn is_external(&self, function: &str) -> bool {
+        self.external_functions.keys().any(|name| name == function)
+    }
+
+    /// `export_pdf` call passing the text and tables of a section of the page
+    fn generate_pdf_export(&mut self, section: &str) -> Result<String, String> {
+        let (title, body) = self.page_sections.iter()
+            .find(|(title, _)| match title {
+                DisplayText::Literal(text) => text == section,
+                DisplayText::Interpolated(_) => false,
+                DisplayText::Translated { key } => key == section,
+            })
+            .cloned()
+            .ok_or_else(|| format!("export_pdf: the page has no section \"{}\"", section))?;
+        self.uses_pdf_export = true;
+        let title = self.display_text(&title)?;
+        let mut parts = Vec::new();
+        self.pdf_parts(&body, &mut parts)?;
+        Ok(format!("export_pdf({}, [{}])", title, parts.join(", ")))
+    }
+
+    /// `(tag, text)` and `("table", table)` parts of a section's content;
+    /// widgets, bindings and control flow are not exported
+    fn pdf_parts(&mut self, body: &[IRNode], parts: &mut Vec<String>) -> Result<(), String> {
+        for node in body {
+            match node {
+                IRNode::ShowText { text, style, .. } => {
+                    let tag = match style {
+                        TextStyle::Title => "h1",
+                        TextStyle::Subtitle => "h2",
+                        TextStyle::Normal => "p",
+                    };
+                    let text = self.display_text(text)?;
+                    parts.push(format!("(\"{}\", {})", tag, text));
+                }
+                IRNode::Section { title, body, .. } => {
+                    let title = self.display_text(title)?;
+                    parts.push(format!("(\"h3\", {})", title));
+                    self.pdf_parts(body, parts)?;
+                }
+                IRNode::ShowTable { table, .. } => {
+                    let table = self.generate_ir_expr(table)?;
+                    parts.push(format!("(\"table\", {})", table));
+                }
+                IRNode::ExprStmt { expr, .. } => {
+                    if let IRExpr::FunctionCall { function, args, .. } = expr.as_ref() {
+                        if let ("show" | "show_editable", Some(table)) = (function.as_str(), args.first()) {
+                            let table = self.generate_ir_expr(table)?;
+                            parts.push(format!("(\"table\", {})", table));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// A read-only table shown with the project's formats, when it has any
+    fn formatted_table(&self, table: &IRExpr, table_code: String) -> String {
+        if self.formats.is_empty() {
+            return table_code;
+        }
+        match self.formatted_schema(table) {
+            Some(schema) => format!("formatted({}, {})", table_code, schema),
+            None => format!("formatted({})", table_code),
         }
     }
 
+    /// Schema constant telling `formatted` the column types of a table
+    fn formatted_schema(&self, table: &IRExpr) -> Option<String> {
+        if self.formats.is_empty() {
+            return None;
+        }
+        let name = table.get_type().as_table()?.declared()?;
+        self.table_schemas.contains_key(&name).then(|| table_schema_name(name))
+    }
+
+    /// Translations of the keys used by the program, passed to the runtime
+    fn generate_translations(&self) -> String {
+        let mut code = String::new();
+        code.push('\n');
+        code.push_str("# Page text by language, from the program's locale files\n");
+        code.push_str("TRANSLATIONS = {\n");
+        for (language, entries) in &self.locales {
+            code.push_str(&format!("    \"{}\": {{\n", self.escape_string(language)));
+            for key in &self.translation_keys {
+                if let Some(text) = entries.get(key).filter(|text| !text.is_empty()) {
+                    code.push_str(&format!("        \"{}\": \"{}\",\n", self.escape_string(key), self.escape_string(text)));
+                }
+            }
+            code.push_str("    },\n");
+        }
+        code.push_str("}\n");
+        let default = i18n::default_language(&self.locales)
+            .map_or("None".to_string(), python_string);
+        code.push_str(&format!("configure_translations(TRANSLATIONS, {})\n", default));
+        code
+    }
+
     fn get_indent(&self) -> String {
         "    ".repeat(self.indent_level)
     }
 
+    /// Python annotation of a value of type `ty`, if type hints are on and
+    /// the type has one
+    fn python_type(&self, ty: &ir::Type) -> Option<String> {
+        if self.type_hints == TypeHints::None {
+            return None;
+        }
+        let annotation = match ty {
+            ir::Type::Int => "int",
+            ir::Type::Float => "float",
+            ir::Type::String => "str",
+            ir::Type::Bool => "bool",
+            ir::Type::Date => "date",
+            ir::Type::Currency => "Decimal",
+            ir::Type::Table(schema) => {
+                if let Some(name) = schema.declared().filter(|name| self.type_hints == TypeHints::Pandera && self.table_schemas.contains_key(name)) {
+                    return Some(format!("DataFrame[{}]", table_model_name(name)));
+                }
+                "pd.DataFrame"
+            }
+            ir::Type::Row(_) => "pd.Series",
+            ir::Type::Array(element) => {
+                return Some(match self.python_type(element) {
+                    Some(element) => format!("list[{}]", element),
+                    None => "list".to_string(),
+                });
+            }
+            ir::Type::Unit => "None",
+            ir::Type::Filter { .. } | ir::Type::Function { .. } | ir::Type::Error => return None,
+        };
+        Some(annotation.to_string())
+    }
+
+    /// A variable or parameter with its type annotation, e.g. `total: int`
+    fn annotated(&self, name: &str, ty: &ir::Type) -> String {
+        match self.python_type(ty) {
+            Some(annotation) => format!("{}: {}", name, annotation),
+            None => name.to_string(),
+        }
+    }
+
+    /// The return annotation of a `def`, e.g. ` -> int`
+    fn return_annotation(&self, ty: &ir::Type) -> String {
+        self.python_type(ty).map_or_else(String::new, |annotation| format!(" -> {}", annotation))
+    }
+
+    /// pandera model of a table's columns, which `--pandera` annotates the
+    /// table's values with
+    ///
+    /// Columns are coerced, as CSV files give dates as text and integer
+    /// columns with missing values as floats.
+    fn generate_table_model(&self, schema: &TableSchema) -> String {
+        let mut code = format!("class {}(pa.DataFrameModel):\n", table_model_name(schema.name));
+        code.push_str(&format!("    \"\"\"Columns of the {} table\"\"\"\n", schema.name));
+        for field in &schema.fields {
+            let constrained = |wanted: fn(&ir::Constraint) -> Option<&Atom>| {
+                schema.constraints.iter().any(|constraint| wanted(constraint) == Some(&field.name))
+            };
+            let key = constrained(|c| if let ir::Constraint::PrimaryKey(f) = c { Some(f) } else { None });
+            let unique = key || constrained(|c| if let ir::Constraint::Unique(f) = c { Some(f) } else { None });
+            let non_null = key || constrained(|c| if let ir::Constraint::NonNull(f) = c { Some(f) } else { None });
+            let mut checks = Vec::new();
+            if unique {
+                checks.push("unique=True");
+            }
+            if !non_null {
+                checks.push("nullable=True");
+            }
+            code.push_str(&format!("    {}: Series[{}] = pa.Field({})\n", field.name, self.pandera_dtype(&field.ty), checks.join(", ")));
+        }
+        code.push('\n');
+        code.push_str("    class Config:\n");
+        code.push_str("        coerce = True\n");
+        code
+    }
+
+    /// pandera dtype of a column; a reference has the type of the key it
+    /// refers to
+    fn pandera_dtype(&self, ty: &ir::FieldType) -> &'static str {
+        match ty {
+            ir::FieldType::Int => "pd.Int64Dtype",
+            ir::FieldType::Float | ir::FieldType::Currency => "float",
+            ir::FieldType::String => "str",
+            ir::FieldType::Bool => "pd.BooleanDtype",
+            ir::FieldType::Date => "pa.DateTime",
+            ir::FieldType::Ref { table_name } => {
+                match self.table_schemas.get(table_name).and_then(|table| table.get_key_field()) {
+                    Some(key) if !matches!(key.ty, ir::FieldType::Ref { .. }) => self.pandera_dtype(&key.ty),
+                    _ => "str",
+                }
+            }
+        }
+    }
+
+    /// Record the editor a variable holds the result of, if its value is one
+    fn bind_editor(&mut self, name: Atom, value: &IRExpr) {
+        let editor = self.last_editor.take();
+        if let (IRExpr::FunctionCall { function, .. }, Some(editor)) = (value, editor) {
+            if function == "show_editable" {
+                self.editors.entry(name).or_default().push(editor);
+            }
+        }
+    }
+    
+    /// Condition on `subject` for an arm's patterns, none for a wildcard
+    fn match_condition(&mut self, subject: &str, patterns: &[ir::MatchPattern]) -> Result<Option<String>, String> {
+        let mut literals = Vec::new();
+        for pattern in patterns {
+            match pattern {
+                ir::MatchPattern::Literal(literal) => literals.push(self.generate_ir_expr(literal)?),
+                ir::MatchPattern::Wildcard => return Ok(None),
+            }
+        }
+        Ok(Some(match literals.as_slice() {
+            [literal] => format!("{} == {}", subject, literal),
+            _ => format!("{} in ({})", subject, literals.join(", ")),
+        }))
+    }
+
     fn get_unique_key(&mut self) -> usize {
         let key = self.key_counter;
         self.key_counter += 1;
@@ -853,12 +2723,12 @@ impl CodeGenerator {
                 ast::FilterMode::Single => "single",
                 ast::FilterMode::Multi => "multi",
             };
-            format!("('{}', '{}')", f.column, mode)
+            format!("({}, '{}')", python_string(f.column.as_str()), mode)
         }).collect();
         
         // Call the helper function
         Ok(format!(
-            "_show_filtered({}, [{}], editable={}, key_prefix='f_{}')",
+            "show_filtered({}, [{}], editable={}, key_prefix='f_{}')",
             df_expr,
             filter_list.join(", "),
             if is_editable { "True" } else { "False" },
@@ -889,22 +2759,23 @@ impl CodeGenerator {
             }
             
             IRExpr::FieldAccess { field, .. } => {
-                // In query string, just use column name (no quotes needed inside query string)
-                Ok(field.clone())
+                Ok(query_column(field.as_str()))
             }
             
             IRExpr::Variable { name, .. } => {
                 // In a where clause, bare identifiers are column names
-                // Just use the name directly in the query string
-                Ok(name.clone())
+                Ok(query_column(name.as_str()))
             }
             
             IRExpr::Literal { value, .. } => {
                 match value {
                     Literal::Int(n) => Ok(n.to_string()),
                     Literal::Float(f) => Ok(f.to_string()),
-                    Literal::String(s) => Ok(format!("'{}'", self.escape_string(s))),
+                    Literal::String(s) => Ok(query_string(s)),
                     Literal::Bool(b) => Ok(if *b { "True" } else { "False" }.to_string()),  // Python booleans in query string
+                    // Conditions with dates select rows with a mask instead
+                    Literal::Date(_) => Err("Unsupported date in where clause".to_string()),
+                    Literal::Currency { amount, .. } => Ok(amount.clone()),
                 }
             }
             
@@ -912,11 +2783,108 @@ impl CodeGenerator {
         }
     }
     
-    fn get_table_key(&self, table_name: &str) -> Result<String, String> {
+    /// Convert a restriction condition to a pandas mask over the rows of `df`
+    ///
+    /// Bare names are columns of the restricted table; anything else is a
+    /// single value, such as a field of `current_user()`.
+    fn generate_restriction_mask(&mut self, condition: &IRExpr, schema: &TableSchema) -> Result<String, String> {
+        self.generate_row_mask(condition, &|name, _| schema.has_field(&name))
+            .map_err(|e| format!("{} in the restriction of {}", e, schema.name))
+    }
+    
+    /// Convert a condition to a pandas mask over the rows of `df`, or a
+    /// sort key to their values, reading the names `is_column` accepts as
+    /// columns
+    fn generate_row_mask(&mut self, condition: &IRExpr, is_column: &dyn Fn(Atom, &ir::Type) -> bool) -> Result<String, String> {
+        match condition {
+            IRExpr::Variable { name, ty } if is_column(*name, ty) => {
+                Ok(format!("df[\"{}\"]", self.escape_string(name.as_str())))
+            }
+            
+            // Membership of a column's values, or of a single value
+            IRExpr::BinaryOp { op: BinOp::In, left, right, .. } => {
+                let left_str = self.generate_row_mask(left, is_column)?;
+                let right_str = self.generate_row_mask(right, is_column)?;
+                if matches!(&**left, IRExpr::Variable { name, ty } if is_column(*name, ty)) {
+                    Ok(format!("{}.isin({})", left_str, right_str))
+                } else {
+                    Ok(format!("({} in {})", left_str, right_str))
+                }
+            }
+            
+            IRExpr::BinaryOp { op, left, right, .. } => {
+                let op_str = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Mod => "%",
+                    BinOp::Eq => "==",
+                    BinOp::Ne => "!=",
+                    BinOp::Lt => "<",
+                    BinOp::Le => "<=",
+                    BinOp::Gt => ">",
+                    BinOp::Ge => ">=",
+                    BinOp::And => "&",
+                    BinOp::Or => "|",
+                    BinOp::In | BinOp::Union | BinOp::SetMinus | BinOp::Intersect => {
+                        return Err("Invalid operator".to_string());
+                    }
+                };
+                let left_str = self.generate_row_mask(left, is_column)?;
+                let right_str = self.generate_row_mask(right, is_column)?;
+                let left_str = dated_column(left_str, left, &[right], is_column);
+                let right_str = dated_column(right_str, right, &[left], is_column);
+                Ok(format!("({} {} {})", left_str, op_str, right_str))
+            }
+            
+            IRExpr::UnaryOp { op, operand, .. } => {
+                let operand_str = self.generate_row_mask(operand, is_column)?;
+                match op {
+                    UnOp::Not => Ok(format!("~{}", operand_str)),
+                    UnOp::Neg => Ok(format!("-{}", operand_str)),
+                    UnOp::IsNull => Ok(format!("pd.isna({})", operand_str)),
+                    UnOp::IsNotNull => Ok(format!("pd.notna({})", operand_str)),
+                }
+            }
+            
+            // A column's values in a range, or a single value
+            IRExpr::Between { value, low, high, .. } => {
+                let value_str = self.generate_row_mask(value, is_column)?;
+                let value_str = dated_column(value_str, value, &[low, high], is_column);
+                let low_str = self.generate_row_mask(low, is_column)?;
+                let high_str = self.generate_row_mask(high, is_column)?;
+                if matches!(&**value, IRExpr::Variable { name, ty } if is_column(*name, ty)) {
+                    Ok(format!("{}.between({}, {})", value_str, low_str, high_str))
+                } else {
+                    Ok(format!("({} <= {} <= {})", low_str, value_str, high_str))
+                }
+            }
+            
+            _ => self.generate_ir_expr(condition),
+        }
+    }
+    
+    /// `count()`, or `sum`, `average`, `min` or `max` of a column, over the
+    /// rows of the group `g`
+    fn group_aggregate(&self, function: &str, args: &[IRExpr]) -> Option<String> {
+        let method = match function {
+            "count" if args.is_empty() => return Some("len(g)".to_string()),
+            "sum" => "sum",
+            "average" => "mean",
+            "min" => "min",
+            "max" => "max",
+            _ => return None,
+        };
+        let [IRExpr::Variable { name, .. }] = args else { return None };
+        Some(format!("g[\"{}\"].{}()", self.escape_string(name.as_str()), method))
+    }
+    
+    fn get_table_key(&self, table_name: Atom) -> Result<String, String> {
         // Look up key field from table schema
-        if let Some(schema) = self.table_schemas.get(table_name) {
+        if let Some(schema) = self.table_schemas.get(&table_name) {
             if let Some(key_field) = schema.get_key_field() {
-                return Ok(key_field.name.clone());
+                return Ok(key_field.name.to_string());
             }
         }
         Err(format!("Table {} has no key field", table_name))
@@ -925,7 +2893,7 @@ impl CodeGenerator {
     fn generate_where_condition_ast(&mut self, condition: &ast::Expr) -> Result<String, String> {
         // Convert AST condition to pandas query string
         match condition {
-            ast::Expr::BinaryOp { op, left, right } => {
+            ast::Expr::BinaryOp { op, left, right, .. } => {
                 let left_str = self.generate_where_condition_ast(left)?;
                 let right_str = self.generate_where_condition_ast(right)?;
                 
@@ -945,21 +2913,2272 @@ impl CodeGenerator {
             }
             
             ast::Expr::FieldAccess { field, .. } => {
-                // In query string, just use column name
-                Ok(field.clone())
+                Ok(query_column(field.as_str()))
             }
             
             ast::Expr::Identifier(name) => {
                 // Variable reference in where clause
-                Ok(name.clone())
+                Ok(query_column(name.as_str()))
             }
             
             ast::Expr::IntLiteral(n) => Ok(n.to_string()),
             ast::Expr::FloatLiteral(f) => Ok(f.to_string()),
-            ast::Expr::StringLiteral(s) => Ok(format!("'{}'", self.escape_string(s))),
+            ast::Expr::StringLiteral(s) => Ok(query_string(s)),
             ast::Expr::BoolLiteral(b) => Ok(if *b { "True" } else { "False" }.to_string()),
             
             _ => Err("Unsupported expression in where clause".to_string()),
         }
     }
 }
+
+/// Name of the generated helper that loads a table from CSV
+/// pandera model of a table, with `--pandera`
+fn table_model_name(table: Atom) -> String {
+    format!("{}Model", table)
+}
+
+fn table_loader_name(table: Atom) -> String {
+    format!("load_{}", table.as_str().to_lowercase())
+}
+
+/// Name of the generated function running a table's hook for an event
+fn hook_function_name(event: ir::HookEvent, table: Atom) -> String {
+    format!("{}_{}", hook_keyword(event), table.as_str().to_lowercase())
+}
+
+/// Keyword declaring a hook for an event
+fn hook_keyword(event: ir::HookEvent) -> &'static str {
+    match event {
+        ir::HookEvent::Load => "on_load",
+        ir::HookEvent::Edit => "on_edit",
+        ir::HookEvent::Save => "on_save",
+    }
+}
+
+/// Session state key of a shared variable
+fn shared_state_key(name: Atom) -> String {
+    format!("shared_{}", name)
+}
+
+/// Name of the generated helper that computes a daily materialized table
+fn table_compute_name(table: Atom) -> String {
+    format!("compute_{}", table.as_str().to_lowercase())
+}
+
+/// CSV file a materialized derived table is kept in, next to the app
+pub fn materialized_file_name(table: Atom) -> String {
+    format!("{}.materialized.csv", table.as_str().to_lowercase())
+}
+
+/// Name of the generated helper that validates and saves a table to CSV
+fn table_saver_name(table: Atom) -> String {
+    format!("save_{}", table.as_str().to_lowercase())
+}
+
+/// Name of the generated helper telling which rows of a restricted table the
+/// user may see
+fn table_visible_name(table: Atom) -> String {
+    format!("visible_{}", table.as_str().to_lowercase())
+}
+
+/// Name of the generated constant holding a table's column types
+fn table_schema_name(table: Atom) -> String {
+    format!("{}_SCHEMA", table_constant_prefix(table))
+}
+
+/// Name of the generated constant mapping columns to their CSV headers
+fn table_headers_name(table: Atom) -> String {
+    format!("{}_HEADERS", table_constant_prefix(table))
+}
+
+/// Prefix of the generated constants describing a table
+fn table_constant_prefix(table: Atom) -> String {
+    table.as_str().to_uppercase()
+}
+
+/// The `rename(columns=...)` mapping of renamed columns
+fn renamed_columns(columns: &[(Atom, Atom)]) -> String {
+    let pairs = columns.iter()
+        .map(|(from, to)| format!("{}: {}", python_string(from.as_str()), python_string(to.as_str())))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", pairs.join(", "))
+}
+
+/// The `drop(columns=...)` list of dropped columns
+fn dropped_columns(columns: &[Atom]) -> String {
+    let names = columns.iter().map(|column| python_string(column.as_str())).collect::<Vec<_>>();
+    format!("[{}]", names.join(", "))
+}
+
+/// Whether a bound value is a table operation that `--trace` times
+fn is_pipeline_stage(value: &IRExpr) -> bool {
+    match value {
+        IRExpr::Where { .. } | IRExpr::SortBy { .. } | IRExpr::ColumnSelect { .. }
+        | IRExpr::Rename { .. } | IRExpr::DropColumns { .. }
+        | IRExpr::Union { .. } | IRExpr::Minus { .. } | IRExpr::Intersect { .. } => true,
+        IRExpr::FunctionCall { function, .. } if function == "load_csv" => true,
+        value => value.get_type().is_table(),
+    }
+}
+
+/// Whether a `match` pattern can be a pattern of a Python match statement:
+/// numbers and strings, which it compares by equality, unlike booleans,
+/// which it compares by identity
+fn is_value_pattern(pattern: &ir::MatchPattern) -> bool {
+    match pattern {
+        ir::MatchPattern::Literal(IRExpr::Literal { value, .. }) => {
+            matches!(value, Literal::Int(_) | Literal::Float(_) | Literal::String(_))
+        }
+        ir::MatchPattern::Literal(_) => false,
+        ir::MatchPattern::Wildcard => true,
+    }
+}
+
+/// A date literal as a Python `datetime.date`
+fn date_literal(date: &str) -> String {
+    format!("pd.Timestamp(\"{}\").date()", date)
+}
+
+/// A currency literal as an exact `Decimal`, not a float that would round
+/// its cents
+fn currency_literal(amount: &str) -> String {
+    format!("Decimal(\"{}\")", amount)
+}
+
+/// The values of a column compared with a date literal, parsed as dates,
+/// since date columns are read from CSV files as text
+fn dated_column(code: String, operand: &IRExpr, compared: &[&IRExpr], is_column: &dyn Fn(Atom, &ir::Type) -> bool) -> String {
+    let with_date = compared.iter().any(|other| matches!(other, IRExpr::Literal { value: Literal::Date(_), .. }));
+    match operand {
+        IRExpr::Variable { name, ty } if with_date && is_column(*name, ty) => format!("pd.to_datetime({}).dt.date", code),
+        _ => code,
+    }
+}
+
+/// Whether a condition uses `in`, `between`, `is null`, `is not null` or a
+/// date anywhere
+fn needs_row_mask(condition: &IRExpr) -> bool {
+    match condition {
+        IRExpr::BinaryOp { op: BinOp::In, .. } | IRExpr::Between { .. } => true,
+        IRExpr::Literal { value: Literal::Date(_), .. } => true,
+        IRExpr::UnaryOp { op: UnOp::IsNull | UnOp::IsNotNull, .. } => true,
+        IRExpr::BinaryOp { left, right, .. } => needs_row_mask(left) || needs_row_mask(right),
+        IRExpr::UnaryOp { operand, .. } => needs_row_mask(operand),
+        _ => false,
+    }
+}
+
+/// Sections among `nodes` with their bodies, nested ones included
+fn collect_sections(nodes: &[IRNode], sections: &mut Vec<(DisplayText, Vec<IRNode>)>) {
+    for node in nodes {
+        match node {
+            IRNode::Section { title, body, .. } => {
+                sections.push((title.clone(), body.clone()));
+                collect_sections(body, sections);
+            }
+            IRNode::Button { body, .. } | IRNode::Confirm { body, .. } | IRNode::Loop { body, .. } => {
+                collect_sections(body, sections);
+            }
+            IRNode::Conditional { then_branch, else_branch, .. } => {
+                collect_sections(then_branch, sections);
+                collect_sections(else_branch.as_deref().unwrap_or_default(), sections);
+            }
+            IRNode::Match { arms, .. } => {
+                for arm in arms {
+                    collect_sections(&arm.body, sections);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Row count argument of a `generated(n)` mock value
+///
+/// The outer `Option` is whether the value is a `generated` call at all.
+fn generated_rows(value: &IRExpr) -> Option<Option<&IRExpr>> {
+    match value {
+        IRExpr::FunctionCall { function, args, .. } if function == "generated" => Some(args.first()),
+        _ => None,
+    }
+}
+
+/// Tables mocked with `generated(n)` in a test body, with their row counts
+fn collect_generated_mocks<'a>(nodes: &'a [IRNode], mocks: &mut Vec<(Atom, Option<&'a IRExpr>)>) {
+    for node in nodes {
+        match node {
+            IRNode::Mock { target, value, .. } => {
+                if let Some(rows) = generated_rows(value) {
+                    mocks.push((*target, rows));
+                }
+            }
+            IRNode::Button { body, .. }
+            | IRNode::Section { body, .. }
+            | IRNode::Confirm { body, .. }
+            | IRNode::Loop { body, .. } => {
+                collect_generated_mocks(body, mocks);
+            }
+            IRNode::Conditional { then_branch, else_branch, .. } => {
+                collect_generated_mocks(then_branch, mocks);
+                collect_generated_mocks(else_branch.as_deref().unwrap_or_default(), mocks);
+            }
+            IRNode::Match { arms, .. } => {
+                for arm in arms {
+                    collect_generated_mocks(&arm.body, mocks);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A name of the program as written, or with a trailing `_` if it is
+/// reserved in Python
+fn python_safe(name: &str) -> String {
+    if is_python_reserved(name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Text escaped for a double-quoted Python string literal
+fn escape_python(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+/// A Python string literal of text from the program, such as a column name,
+/// whatever quotes, brackets or backslashes it contains
+fn python_string(text: &str) -> String {
+    format!("\"{}\"", escape_python(text))
+}
+
+/// Whether a name can be written as is where Python expects an identifier
+fn is_plain_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !PYTHON_KEYWORDS.contains(&name)
+}
+
+/// A column in a pandas query string, quoted with backticks unless it is a
+/// plain identifier
+fn query_column(name: &str) -> String {
+    if is_plain_identifier(name) {
+        name.to_string()
+    } else {
+        format!("`{}`", name)
+    }
+}
+
+/// A string literal in a pandas query string, which is itself put in a
+/// Python string literal with `python_string`
+fn query_string(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("'{}'", escaped)
+}
+
+/// Turn a test description into a lowercase Python identifier
+fn python_identifier(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect();
+    if words.is_empty() {
+        "unnamed".to_string()
+    } else {
+        words.join("_")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::Command;
+    use wtlang_core::{Lexer, Parser};
+
+    fn build(source: &str) -> BTreeMap<String, String> {
+        build_with_tests(source, false)
+    }
+
+    fn build_with_tests(source: &str, with_tests: bool) -> BTreeMap<String, String> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        CodeGenerator::new().with_tests(with_tests).generate(&program).unwrap()
+    }
+
+    /// Run Python code with python3, returning its stdout, or None when no
+    /// interpreter is available. The skip is written straight to stderr,
+    /// past the test harness's capture, and is an error in CI, where the
+    /// generated runtime must actually be exercised.
+    fn run_python(code: &str) -> Option<String> {
+        let output = match Command::new("python3").arg("-c").arg(code).output() {
+            Ok(output) => output,
+            Err(error) => {
+                assert!(std::env::var_os("CI").is_none(), "python3 is required in CI: {}", error);
+                let test = std::thread::current().name().unwrap_or("test").to_string();
+                let _ = writeln!(std::io::stderr(), "skipping {}: python3 is not available ({})", test, error);
+                return None;
+            }
+        };
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// The `def` blocks of a generated module, without its imports
+    fn function_defs(module: &str) -> String {
+        let start = module.find("def ").unwrap();
+        module[start..].to_string()
+    }
+
+    const FUNCTIONS: &str = r#"
+function clamp(value: int, low: int, high: int) -> int {
+    if value < low {
+        return low
+    } else {
+        if value > high {
+            return high
+        }
+    }
+    return value
+}
+
+function sum_all(values: int[]) -> int {
+    let total: int = 0
+    forall v in values {
+        total = total + v
+    }
+    return total
+}
+
+function scaled(value: int, factor: int) -> int {
+    let result: int = value * factor
+    return result
+}
+
+page Home {
+    let x = clamp(15, 0, 10)
+    text "Clamped: {x}"
+}
+"#;
+
+    #[test]
+    fn test_functions_are_emitted_into_helpers() {
+        let files = build(FUNCTIONS);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("# region function clamp (line 2)\ndef clamp(value, low, high):\n    # region if (line 3)\n    if (value < low):\n        return low\n"));
+        assert!(helpers.contains("def sum_all(values):\n    # region let total (line 14)\n    total = 0\n    # endregion\n    # region forall v (line 15)\n    for v in values:\n        total = (total + v)\n    # endregion\n    return total\n# endregion\n"));
+        assert!(helpers.contains("def scaled(value, factor):\n"));
+        assert!(files["Home.py"].contains("from helpers import *"));
+        assert!(files["Home.py"].contains("x = clamp(15, 0, 10)"));
+    }
+
+    #[test]
+    fn test_pages_import_shared_code_from_helpers() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+function double(value: float) -> float {
+    return value * 2
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    text "Twice: {double(1.5)}"
+}
+
+page Orders {
+    show(load_csv("orders.csv", Order))
+}
+"#;
+        let files = build(source);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("ORDER_SCHEMA = {\"id\": \"int\", \"amount\": \"float\"}\n"), "{}", helpers);
+        assert!(helpers.contains("def load_order(path):\n"), "{}", helpers);
+        assert!(helpers.contains("def double(value):\n"), "{}", helpers);
+        assert!(!helpers.contains("import helpers"), "{}", helpers);
+        for name in ["Home.py", "Orders.py"] {
+            let page = &files[name];
+            assert!(page.contains("from wt_runtime import show_filtered\nfrom helpers import *\n"), "{}", page);
+            // Tables and functions are defined once, in helpers.py
+            assert!(!page.contains("def "), "{}", page);
+            assert!(!page.contains("ORDER_SCHEMA"), "{}", page);
+        }
+        assert!(files["Home.py"].contains("orders = load_order(\"orders.csv\")\n"), "{}", files["Home.py"]);
+        assert!(files["Home.py"].contains("{double(1.5)}"), "{}", files["Home.py"]);
+        assert!(files["Orders.py"].contains("st.dataframe(load_order(\"orders.csv\"))"), "{}", files["Orders.py"]);
+    }
+
+    #[test]
+    fn test_region_markers() {
+        let source = "page Home {\n    title \"Orders\"\n    button \"Save\" {\n        let x = 1\n    }\n}\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let files = CodeGenerator::new().with_source_file("app.wt").generate(&program).unwrap();
+        let page = &files["Home.py"];
+        assert!(page.contains("# region page Home (app.wt:1)\nst.title(\"Orders\")\n# region button \"Save\" (app.wt:3)\nif st.button(\"Save\"):\n    x = 1\n# endregion\n# endregion\n"), "{}", page);
+    }
+
+    #[test]
+    fn test_type_hints() {
+        let source = r#"
+table Order {
+    id: int [key]
+    customer: string [non_null]
+    placed: date
+}
+
+function scaled(value: int, factor: float) -> float {
+    return value * factor
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    let total = 12.50 EUR
+    text "{total}"
+}
+"#;
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let generate = |hints| CodeGenerator::new().with_type_hints(hints).generate(&program).unwrap();
+        
+        let files = generate(TypeHints::Plain);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("def scaled(value: int, factor: float) -> float:\n"), "{}", helpers);
+        assert!(helpers.contains("def load_order(path) -> pd.DataFrame:\n"), "{}", helpers);
+        assert!(!helpers.contains("pandera"), "{}", helpers);
+        assert!(files["Home.py"].contains("orders: pd.DataFrame = load_order(\"orders.csv\")\n"), "{}", files["Home.py"]);
+        assert!(files["Home.py"].contains("total: Decimal = Decimal(\"12.50\")\n"), "{}", files["Home.py"]);
+        
+        let files = generate(TypeHints::Pandera);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("from pandera.typing import DataFrame, Series\n"), "{}", helpers);
+        assert!(helpers.contains("class OrderModel(pa.DataFrameModel):\n    \"\"\"Columns of the Order table\"\"\"\n    id: Series[pd.Int64Dtype] = pa.Field(unique=True)\n    customer: Series[str] = pa.Field()\n    placed: Series[pa.DateTime] = pa.Field(nullable=True)\n\n    class Config:\n        coerce = True\n"), "{}", helpers);
+        assert!(helpers.contains("def load_order(path) -> DataFrame[OrderModel]:\n"), "{}", helpers);
+        assert!(files["Home.py"].contains("orders: DataFrame[OrderModel] = load_order("), "{}", files["Home.py"]);
+        
+        assert!(!build(source)["helpers.py"].contains("def scaled(value: int"));
+    }
+
+    #[test]
+    fn test_generated_functions_can_be_called() {
+        let helpers = &build(FUNCTIONS)["helpers.py"];
+        let script = format!(
+            "{}\nprint(clamp(15, 0, 10), clamp(-3, 0, 10), clamp(4, 0, 10), sum_all([1, 2, 3]), scaled(6, 7))",
+            function_defs(helpers)
+        );
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output.trim(), "10 0 4 6 42");
+    }
+
+    const TESTS: &str = r#"
+external function tax_rate() -> float from "finance"
+
+function taxed(amount: float) -> float {
+    return amount * tax_rate()
+}
+
+test "taxed amount uses the rate" {
+    mock tax_rate with 2
+    assert taxed(10) == 20
+}
+
+test "Taxed amount uses the rate!" {
+    mock tax_rate with 3
+    let x = taxed(2)
+    assert x == 6
+}
+"#;
+
+    #[test]
+    fn test_tests_are_compiled_to_pytest() {
+        assert!(!build(TESTS).contains_key("test_app.py"));
+        
+        let tests = &build_with_tests(TESTS, true)["test_app.py"];
+        assert!(tests.contains("import helpers\n"));
+        assert!(tests.contains("def test_taxed_amount_uses_the_rate(monkeypatch):\n"));
+        assert!(tests.contains("def test_taxed_amount_uses_the_rate_2(monkeypatch):\n"));
+        assert!(tests.contains("    monkeypatch.setattr(helpers, \"tax_rate\", _mock_tax_rate)\n"));
+        assert!(tests.contains("    assert (taxed(10) == 20)\n"));
+    }
+
+    #[test]
+    fn test_table_assertions() {
+        let source = r#"
+test "rows" {
+    let rows = [{ id: 1, name: "a" }, { id: 2, name: "b" }]
+    assert_row_count(rows, 2)
+    assert_contains(rows, { id: 2 })
+    assert_equal_tables(rows, [{ id: 2, name: "b" }, { id: 1, name: "a" }], true)
+}
+"#;
+        let files = build_with_tests(source, true);
+        let tests = &files["test_app.py"];
+        assert!(tests.contains("    rows = [{\"id\": 1, \"name\": \"a\"}, {\"id\": 2, \"name\": \"b\"}]\n"));
+        assert!(tests.contains("    assert_contains(rows, {\"id\": 2})\n"));
+        
+        let script = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+rows = [{{"id": 1, "name": "a"}}, {{"id": 2, "name": "b"}}]
+assert_row_count(rows, 2)
+assert_contains(rows, {{"id": 2}})
+assert_equal_tables(rows, list(reversed(rows)), True)
+try:
+    assert_equal_tables(rows, [{{"id": 1, "name": "a"}}, {{"id": 2, "name": "c"}}, {{"id": 3, "name": "d"}}])
+except AssertionError as error:
+    print(error)
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert!(output.contains("- removed row {id: 3, name: 'd'}"), "{}", output);
+        assert!(output.contains("~ changed row 1: name: 'c' -> 'b'"), "{}", output);
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        // pandas is stubbed with columns of values and a dtype name
+        let script = format!(r#"
+import datetime as dates, sys, types
+st = types.ModuleType("streamlit")
+shown = []
+st.error = shown.append
+sys.modules["streamlit"] = st
+class Series:
+    def __init__(self, values, dtype):
+        self.values, self.dtype = values, dtype
+    @property
+    def empty(self):
+        return not self.values
+    def dropna(self):
+        return Series([v for v in self.values if v is not None], self.dtype)
+    def notna(self):
+        return Series([v is not None for v in self.values], "bool")
+    def all(self):
+        return all(self.values)
+    def __mod__(self, n):
+        return Series([v % n for v in self.values], "float64")
+    def __eq__(self, other):
+        return Series([v == other for v in self.values], "bool")
+class DataFrame(dict):
+    @property
+    def columns(self):
+        return list(self)
+def to_datetime(series, errors):
+    def parse(value):
+        try:
+            return dates.date.fromisoformat(value)
+        except ValueError:
+            return None
+    return Series([parse(v) for v in series.values], "datetime64[ns]")
+pd = types.ModuleType("pandas")
+pd.DataFrame = DataFrame
+pd.to_datetime = to_datetime
+pd.api = types.SimpleNamespace(types=types.SimpleNamespace(
+    is_bool_dtype=lambda s: s.dtype == "bool",
+    is_integer_dtype=lambda s: s.dtype == "int64",
+    is_float_dtype=lambda s: s.dtype == "float64",
+    is_numeric_dtype=lambda s: s.dtype in ("int64", "float64", "bool"),
+    is_datetime64_any_dtype=lambda s: s.dtype.startswith("datetime64"),
+))
+sys.modules["pandas"] = pd
+exec(compile({:?}, "wt_runtime", "exec"))
+schema = {{"id": "int", "amount": "float", "paid": "bool", "placed": "date", "note": "string"}}
+valid = DataFrame(
+    id=Series([1, 2], "int64"),
+    amount=Series([1.5, None], "float64"),
+    paid=Series([True, False], "bool"),
+    placed=Series(["2024-01-31", None], "object"),
+    note=Series(["a", 3], "object"),
+)
+print(validate_schema(valid, schema, "Order"))
+# Integer columns with missing values are read as float
+print(validate_schema(DataFrame(id=Series([1.0, None], "float64")), {{"id": "int"}}, "Order"))
+print(validate_schema(DataFrame(id=Series([1.5], "float64")), {{"id": "int"}}, "Order"))
+invalid = DataFrame(
+    id=Series([True], "bool"),
+    amount=Series(["1.5"], "object"),
+    paid=Series([1], "int64"),
+    note=Series(["a"], "object"),
+    extra=Series([1], "int64"),
+)
+problems = validate_schema(invalid, schema, "Order")
+assert shown[-len(problems):] == problems
+print("\n".join(problems))
+print(validate_schema(DataFrame(placed=Series(["soon"], "object")), {{"placed": "date"}}, "Order"))
+"#, RUNTIME_SOURCE);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "[]\n[]\n[\"Order: column 'id' should be int, but its values have dtype float64\"]\n\
+            Order: missing column(s) placed\n\
+            Order: unexpected column(s) extra\n\
+            Order: column 'id' should be int, but its values have dtype bool\n\
+            Order: column 'amount' should be float, but its values have dtype object\n\
+            Order: column 'paid' should be bool, but its values have dtype int64\n\
+            [\"Order: column 'placed' should be date, but its values have dtype object\"]\n");
+    }
+
+    #[test]
+    fn test_generated_mocks_run_through_check_generated() {
+        let source = r#"
+table Customer {
+    id: int [key],
+    name: string [non_null]
+}
+
+table Order {
+    order_id: int [key],
+    customer: ref Customer
+}
+
+test "orders" {
+    mock Customer with generated(20)
+    mock Order with generated(1000)
+    let orders = load_csv("orders.csv", Order)
+}
+"#;
+        let files = build_with_tests(source, true);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("CUSTOMER_CONSTRAINTS = {\"id\": [\"key\"], \"name\": [\"non_null\"]}\n"));
+        assert!(helpers.contains("ORDER_REFS = {\"customer\": (\"Customer\", \"id\", \"int\")}\n"));
+        
+        let tests = &files["test_app.py"];
+        assert!(tests.contains("    def run(tables):\n        # region mock Customer (line 13)\n        _mock_load_customer = lambda *args, **kwargs: tables[\"Customer\"].copy()\n"));
+        assert!(tests.contains("        orders = load_order(\"orders.csv\")\n"));
+        assert!(tests.contains("    check_generated(run, {\"Customer\": (CUSTOMER_SCHEMA, CUSTOMER_CONSTRAINTS, CUSTOMER_REFS, 20), \"Order\": (ORDER_SCHEMA, ORDER_CONSTRAINTS, ORDER_REFS, 1000)})\n"));
+    }
+
+    #[test]
+    fn test_generated_tests_pass_with_mocks() {
+        // Run the generated tests without pytest, pandas or streamlit: the
+        // third-party modules are stubbed and the external module fails if
+        // the mock is not applied
+        let dir = std::env::temp_dir().join(format!("wtc_tests_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, code) in build_with_tests(TESTS, true) {
+            std::fs::write(dir.join(name), code).unwrap();
+        }
+        std::fs::write(dir.join("finance.py"), "def tax_rate():\n    raise RuntimeError('not mocked')\n").unwrap();
+        
+        let harness = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+sys.modules["pandas"] = types.ModuleType("pandas")
+sys.path.insert(0, {:?})
+import test_app
+
+class MonkeyPatch:
+    def __init__(self):
+        self.undo = []
+    def setattr(self, target, name, value):
+        self.undo.append((setattr, target, name, getattr(target, name)))
+        setattr(target, name, value)
+    def setitem(self, mapping, name, value):
+        self.undo.append((type(mapping).__setitem__, mapping, name, mapping[name]))
+        mapping[name] = value
+    def restore(self):
+        for restore, target, name, value in reversed(self.undo):
+            restore(target, name, value)
+
+for name in sorted(dir(test_app)):
+    if name.startswith("test_"):
+        patch = MonkeyPatch()
+        getattr(test_app, name)(patch)
+        patch.restore()
+        print(name)
+"#, dir.display().to_string());
+        let output = run_python(&harness);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let Some(output) = output else { return };
+        assert_eq!(output.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_log_and_trace() {
+        let source = r#"
+table Order {
+    id: int [key],
+    amount: float
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    let big = orders where amount > 100.0
+    let total = count(big)
+    log("loaded orders")
+    log("few big orders", "warn")
+}
+"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let files = CodeGenerator::new().with_trace(true).generate(&program).unwrap();
+        let page = &files["Home.py"];
+        assert!(page.contains("orders = traced(\"orders\", 8, lambda: load_order(\"orders.csv\"))\n"), "{}", page);
+        assert!(page.contains("big = traced(\"big\", 9, lambda: "), "{}", page);
+        assert!(page.contains("total = count(big)\n"), "{}", page);
+        assert!(page.contains("log_message(\"few big orders\", \"warn\")\n"), "{}", page);
+        assert!(files["helpers.py"].contains("configure_logging(LOGGING_CONFIG)\n"));
+        
+        let untraced = build_with_tests(source, false);
+        assert!(untraced["Home.py"].contains("orders = load_order(\"orders.csv\")\n"));
+        
+        let script = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+pd = types.ModuleType("pandas")
+pd.DataFrame = list
+sys.modules["pandas"] = pd
+exec(compile({:?}, "wt_runtime", "exec"))
+configure_logging({{
+    "version": 1,
+    "formatters": {{"plain": {{"format": "%(levelname)s %(name)s: %(message)s"}}}},
+    "handlers": {{"out": {{"class": "logging.StreamHandler", "stream": "ext://sys.stdout", "formatter": "plain"}}}},
+    "loggers": {{"wtlang": {{"handlers": ["out"], "level": "INFO"}}}},
+}})
+log_message("few big orders", "warn")
+rows = traced("big", 9, lambda: [1, 2, 3])
+assert rows == [1, 2, 3]
+try:
+    log_message("x", "debug")
+except ValueError as error:
+    print(error)
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert!(output.contains("WARNING wtlang.app: few big orders"), "{}", output);
+        assert!(output.contains("INFO wtlang.trace: big (line 9): "), "{}", output);
+        assert!(output.contains(" ms, 3 rows"), "{}", output);
+        assert!(output.contains("unknown log level 'debug'"), "{}", output);
+    }
+
+    #[test]
+    fn test_profile_instrument() {
+        let source = r#"
+table Order {
+    id: int [key],
+    amount: float
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    let big = orders where amount > 100.0
+    show(big)
+}
+"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let files = CodeGenerator::new().with_trace(true).with_profile(true).generate(&program).unwrap();
+        let page = &files["Home.py"];
+        assert!(page.contains("import show_filtered, profile_start, show_profile\n"), "{}", page);
+        assert!(page.contains("# Page: Home\n\nprofile_start()\n"), "{}", page);
+        assert!(page.contains("orders = profiled(\"orders\", 8, lambda: traced(\"orders\", 8, lambda: load_order(\"orders.csv\")))\n"), "{}", page);
+        assert!(page.ends_with("show_profile()\n"), "{}", page);
+        
+        let script = format!(r#"
+import contextlib, sys, types
+st = types.ModuleType("streamlit")
+st.expander = lambda label: (print(label), contextlib.nullcontext())[1]
+st.dataframe = lambda df: print(list(df))
+st.caption = print
+st.write = print
+sys.modules["streamlit"] = st
+pd = types.ModuleType("pandas")
+class DataFrame(list):
+    def __init__(self, data=(), columns=None):
+        super().__init__(data)
+pd.DataFrame = DataFrame
+sys.modules["pandas"] = pd
+exec(compile({:?}, "wt_runtime", "exec"))
+show_profile()
+profile_start()
+orders = profiled("orders", 8, lambda: DataFrame([1, 2, 3]))
+total = profiled("total", 9, lambda: 3)
+show_profile()
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert!(output.starts_with("Performance\nNo table operations ran.\nPerformance\n"), "{}", output);
+        assert!(output.contains("('orders', 8, "), "{}", output);
+        assert!(output.contains(", 3), ('total', 9, "), "{}", output);
+        assert!(output.contains("2 stage(s), "), "{}", output);
+    }
+
+    #[test]
+    fn test_translations() {
+        let source = r#"
+page Home {
+    title t("home.title")
+    section t("home.orders") {
+        let total = 3
+        text t("home.total")
+    }
+    button "Refresh" {
+        text "Plain"
+    }
+}
+
+page About {
+    title "About"
+}
+"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let locales = Locales::from([
+            ("en".to_string(), BTreeMap::from([
+                ("home.title".to_string(), "Orders".to_string()),
+                ("home.total".to_string(), "{total} orders".to_string()),
+                ("unused".to_string(), "Not emitted".to_string()),
+            ])),
+            ("fr".to_string(), BTreeMap::from([
+                ("home.title".to_string(), "Commandes".to_string()),
+                ("home.total".to_string(), String::new()),
+            ])),
+        ]);
+        let files = CodeGenerator::new().with_locales(locales).generate(&program).unwrap();
+        let page = &files["Home.py"];
+        assert!(page.contains("# Page: Home\n\nselect_language()\n# region page Home (line 2)\nst.title(t(\"home.title\", locals()))\n"), "{}", page);
+        assert!(page.contains("st.markdown(\"### \" + t(\"home.orders\", locals()))\n"), "{}", page);
+        assert!(page.contains("if st.button(\"Refresh\"):\n    st.write(\"Plain\")\n"), "{}", page);
+        assert!(!files["About.py"].contains("select_language"), "{}", files["About.py"]);
+        
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, configure_translations, select_language, t\n"), "{}", helpers);
+        assert!(helpers.contains("TRANSLATIONS = {\n    \"en\": {\n        \"home.title\": \"Orders\",\n        \"home.total\": \"{total} orders\",\n    },\n    \"fr\": {\n        \"home.title\": \"Commandes\",\n    },\n}\nconfigure_translations(TRANSLATIONS, \"en\")\n"), "{}", helpers);
+        assert!(!build_with_tests(r#"page Home { title "Plain" }"#, false)["helpers.py"].contains("TRANSLATIONS"));
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+st.session_state = {{}}
+st.sidebar = types.SimpleNamespace(selectbox=lambda label, options, index, key: print(label, options, index))
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+configure_translations({{"en": {{"home.title": "Orders", "home.total": "{{total}} orders"}}, "fr": {{"home.title": "Commandes"}}}}, "en")
+select_language()
+st.session_state["wt_language"] = "fr"
+print(t("home.title"), "|", t("home.total", {{"total": 3}}), "|", t("home.missing"), "|", t("home.total"))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "Language ['en', 'fr'] 0\nCommandes | 3 orders | home.missing | {total} orders\n");
+    }
+
+    #[test]
+    fn test_config() {
+        let source = r#"
+config {
+    title = "Sales"
+    theme = "dark"
+    data_dir = "data"
+    decimal_separator = ","
+    thousands_separator = "."
+}
+
+table Order {
+    id: int [key]
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    show(orders)
+}
+"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        // wt.toml takes precedence over the config block
+        let formats = Formats::from([("thousands_separator".to_string(), " ".to_string())]);
+        let files = CodeGenerator::new().with_formats(formats).generate(&program).unwrap();
+        let page = &files["Home.py"];
+        assert!(page.contains("# Page: Home\n\nst.set_page_config(page_title=\"Sales\")\n"), "{}", page);
+        assert_eq!(files[".streamlit/config.toml"], "[theme]\nbase = \"dark\"\n");
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("FORMATS = {\n    \"decimal_separator\": \",\",\n    \"thousands_separator\": \" \",\n}\n"), "{}", helpers);
+        assert!(helpers.contains("\nconfigure_data_dir(\"data\")\n"), "{}", helpers);
+        
+        let files = build_with_tests("page Home {\n    text \"Hi\"\n}\n", false);
+        assert!(!files["Home.py"].contains("set_page_config"));
+        assert!(!files.contains_key(".streamlit/config.toml"));
+        assert!(!files["helpers.py"].contains("configure_data_dir"));
+    }
+
+    #[test]
+    fn test_formats() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: currency
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    show(orders)
+    show_editable(orders)
+    let price = format_currency(1234.5)
+    text "Price: {price}"
+}
+"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let formats = Formats::from([
+            ("currency_symbol".to_string(), "\u{20ac}".to_string()),
+            ("decimal_separator".to_string(), ",".to_string()),
+            ("thousands_separator".to_string(), ".".to_string()),
+        ]);
+        let files = CodeGenerator::new().with_formats(formats).generate(&program).unwrap();
+        let page = &files["Home.py"];
+        assert!(page.contains("st.dataframe(formatted(orders, ORDER_SCHEMA))\n"), "{}", page);
+        assert!(page.contains("st.data_editor(orders, key="), "{}", page);
+        assert!(page.contains("price = format_currency(1234.5)\n"), "{}", page);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, configure_formats, formatted, format_number, format_currency, format_date\n"), "{}", helpers);
+        assert!(helpers.contains("FORMATS = {\n    \"currency_symbol\": \"\u{20ac}\",\n    \"decimal_separator\": \",\",\n    \"thousands_separator\": \".\",\n}\nconfigure_formats(FORMATS)\n"), "{}", helpers);
+        
+        // Without wt.toml tables are shown as before
+        let files = build_with_tests(source, false);
+        assert!(files["Home.py"].contains("st.dataframe(orders)\n"), "{}", files["Home.py"]);
+        assert!(!files["helpers.py"].contains("FORMATS"));
+        
+        let script = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+pd = types.ModuleType("pandas")
+pd.isna = lambda value: value != value
+sys.modules["pandas"] = pd
+exec(compile({:?}, "wt_runtime", "exec"))
+print(format_number(1234567.891), "|", format_currency(-5), "|", format_number(float("nan")))
+configure_formats({{"decimal_separator": ",", "thousands_separator": ".", "currency_symbol": "EUR", "currency_position": "after"}})
+print(format_number(1234567.891), "|", format_currency(-1234.5), "|", format_number(42, 0))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "1,234,567.89 | -$5.00 | \n1.234.567,89 | -1.234,50 EUR | 42\n");
+    }
+
+    #[test]
+    fn test_number_functions() {
+        let source = r##"
+table Order {
+    id: int [key]
+    amount: currency
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    let total = round(sum(orders, "amount"), 2)
+    let pages = ceil(count(orders) / 20)
+    let gap = abs(total - 100)
+    let shown = format_number(total, "#,##0.0")
+    let share = percent(total / 1000)
+    text "{pages} {gap} {shown} {share}"
+}
+"##;
+        let files = build_with_tests(source, false);
+        let page = &files["Home.py"];
+        assert!(page.contains("total = round_number(orders[\"amount\"].sum(skipna=True), 2)\n"), "{}", page);
+        assert!(page.contains("pages = ceil_number((count(orders) / 20))\n"), "{}", page);
+        assert!(page.contains("gap = abs((total - 100))\n"), "{}", page);
+        assert!(page.contains("shown = format_number(total, \"#,##0.0\")\n"), "{}", page);
+        assert!(page.contains("share = percent((total / 1000))\n"), "{}", page);
+        assert!(files["helpers.py"].contains(", round_number, floor_number, ceil_number, percent"), "{}", files["helpers.py"]);
+        
+        let script = format!(r##"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+pd = types.ModuleType("pandas")
+pd.isna = lambda value: value != value
+sys.modules["pandas"] = pd
+exec(compile({:?}, "wt_runtime", "exec"))
+print(format_number(1234567.891, "#,##0.0"), "|", format_number(1234.5, "0"), "|", percent(0.125), "|", percent(None))
+print(round_number(2.567, 2), round_number(2.5), floor_number(-2.5), ceil_number(2.1), round_number(float("nan")))
+"##, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "1,234,567.9 | 1234 | 12.5% | \n2.57 2 -3 3 None\n");
+    }
+
+    #[test]
+    fn test_email_and_pdf_export() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+page Report {
+    let orders = load_csv("orders.csv", Order)
+    section "Summary" {
+        subtitle "Open orders"
+        let total = count(orders)
+        text "{total} orders"
+        show(orders)
+        button "Refresh" {
+            text "Not exported"
+        }
+    }
+    button "Send" {
+        send_email("ops@example.com", "Orders", "Attached", orders)
+        export_pdf("Summary")
+    }
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Report.py"];
+        assert!(page.contains("    send_email(\"ops@example.com\", \"Orders\", \"Attached\", attach=orders, attach_name=\"orders.csv\")\n"), "{}", page);
+        assert!(page.contains("    export_pdf(\"Summary\", [(\"h2\", \"Open orders\"), (\"p\", f\"{total} orders\"), (\"table\", orders)])\n"), "{}", page);
+        assert!(files["helpers.py"].contains("profiled, send_email, export_pdf\n"), "{}", files["helpers.py"]);
+        
+        // An external function replaces the generated implementation
+        let external = source.replace("page Report", "external function send_email(to: string, subject: string, body: string) -> bool from \"mail\"\n\npage Report");
+        let files = build_with_tests(&external, false);
+        assert!(files["Report.py"].contains("    send_email(\"ops@example.com\", \"Orders\", \"Attached\", orders)\n"), "{}", files["Report.py"]);
+        assert!(files["helpers.py"].contains("from mail import send_email\n"), "{}", files["helpers.py"]);
+        assert!(files["helpers.py"].contains("profiled, export_pdf\n"), "{}", files["helpers.py"]);
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+st.secrets = {{}}
+st.error = lambda message: print("error:", message.split(":")[0])
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+print(send_email("ops@example.com", "Orders", "Attached"))
+class FakeSMTP:
+    def __init__(self, host, port): print("connect", host, port)
+    def __enter__(self): return self
+    def __exit__(self, *args): pass
+    def starttls(self): print("starttls")
+    def login(self, username, password): print("login", username)
+    def send_message(self, message): print("send", message["From"], message["To"], message["Subject"])
+smtplib.SMTP = FakeSMTP
+st.secrets = {{"smtp": {{"host": "mail.example.com", "port": "2525", "username": "app", "password": "secret", "sender": "reports@example.com"}}}}
+print(send_email(["ops@example.com", "cfo@example.com"], "Orders", "Attached"))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "error: Email is not configured\nFalse\nconnect mail.example.com 2525\nstarttls\nlogin app\nsend reports@example.com ops@example.com, cfo@example.com Orders\nTrue\n");
+    }
+
+    #[test]
+    fn test_sql_tables() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+table Note {
+    body: string
+}
+
+page Orders {
+    let orders = load_sql("db", "orders", Order)
+    let notes = load_csv("notes.csv", Note)
+    let raw = load_sql("db", "audit")
+    button "Save" {
+        save_sql(orders, "db", "orders", "upsert")
+        save_sql(raw, "db", "audit_copy")
+    }
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("orders = load_order(\"orders\", connection=\"db\")\n"), "{}", page);
+        assert!(page.contains("raw = load_sql(\"db\", \"audit\")\n"), "{}", page);
+        assert!(page.contains("    save_order(orders, \"orders\", connection=\"db\", mode=\"upsert\")\n"), "{}", page);
+        assert!(page.contains("    save_sql(raw, \"db\", \"audit_copy\", \"append\")\n"), "{}", page);
+        
+        // Only tables kept in a database get the database-aware helpers
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, load_sql, save_sql\n"), "{}", helpers);
+        assert!(helpers.contains("def load_order(path, connection=None):\n"), "{}", helpers);
+        assert!(helpers.contains("        return save_sql(df, connection, path, mode, ORDER_CONSTRAINTS, \"Order\")\n"), "{}", helpers);
+        assert!(helpers.contains("def load_note(path):\n"), "{}", helpers);
+        
+        let no_key = source.replace("id: int [key]", "id: int");
+        let tokens = Lexer::new(&no_key).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(CodeGenerator::new().generate(&program).unwrap_err(), "save_sql: upsert needs a key field in table Order");
+    }
+
+    #[test]
+    fn test_csv_writes_are_atomic_and_detect_conflicts() {
+        let files = build_with_tests("table Order {\n    id: int [key]\n}\npage Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    save_csv(orders, \"orders.csv\")\n}\n", false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("    df = read_csv(path)\n"), "{}", helpers);
+        assert!(helpers.contains("    return write_csv(df, path, \"Order\")\n"), "{}", helpers);
+        
+        let script = format!(r#"
+import os, sys, tempfile, types
+st = types.ModuleType("streamlit")
+st.session_state = {{}}
+st.warning = lambda message: print("warning:", message.split(";")[0])
+sys.modules["streamlit"] = st
+pd = types.ModuleType("pandas")
+pd.read_csv = lambda data: data.read().decode()
+sys.modules["pandas"] = pd
+class Frame:
+    def __init__(self, text): self.text = text
+    def to_csv(self, file, index): file.write(self.text)
+exec(compile({:?}, "wt_runtime", "exec"))
+path = os.path.join(tempfile.mkdtemp(), "orders.csv")
+with open(path, "w") as file: file.write("id\n1\n")
+print(read_csv(path).split())
+print(write_csv(Frame("id\n2\n"), path, "Order"), open(path).read().split())
+with open(path, "w") as file: file.write("id\n3\n")
+print(write_csv(Frame("id\n4\n"), path, "Order"), open(path).read().split())
+print(sorted(os.listdir(os.path.dirname(path))))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "['id', '1']\nTrue ['id', '2']\nwarning: Order: orders.csv was saved by another session since this page loaded it\nFalse ['id', '3']\n['orders.csv', 'orders.csv.lock']\n");
+    }
+
+    #[test]
+    fn test_audited_edits() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let edited = show_editable(orders, audit("orders_audit.csv"))
+    let logged = show_editable(orders, audit("db", "order_audit"))
+    let plain = show_editable(orders)
+    save_csv(edited, "orders.csv")
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("edited = audit_edits(st.data_editor(orders, key=\"editor_0\", use_container_width=True), orders, audit(\"orders_audit.csv\"), \"editor_0\", \"Order\", \"id\")\n"), "{}", page);
+        assert!(page.contains("logged = audit_edits(st.data_editor(orders, key=\"editor_1\", use_container_width=True), orders, audit(\"db\", \"order_audit\"), \"editor_1\", \"Order\", \"id\")\n"), "{}", page);
+        assert!(page.contains("plain = st.data_editor(orders, key=\"editor_2\", use_container_width=True)\n"), "{}", page);
+        assert!(files["helpers.py"].contains("profiled, audit, audit_edits\n"), "{}", files["helpers.py"]);
+        
+        let script = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+before = [{{"id": 1, "amount": 5.0}}, {{"id": 2, "amount": 7.5}}]
+after = [{{"id": 2, "amount": 8.0}}, {{"id": 3, "amount": None}}]
+for change in table_changes(before, after, "id"):
+    print(change)
+print(len(table_changes(before, list(reversed(before)))))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "{'action': 'changed', 'key': '2', 'column': 'amount', 'before': '7.5', 'after': '8.0'}\n{'action': 'added', 'key': '3', 'column': '', 'before': '', 'after': '{id: 3, amount: None}'}\n{'action': 'removed', 'key': '1', 'column': '', 'before': '{id: 1, amount: 5.0}', 'after': ''}\n4\n");
+    }
+    
+    #[test]
+    fn test_confirm_and_revert() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let edited = show_editable(orders)
+    button "Save" {
+        save_csv(edited, "orders.csv")
+    }
+    button "Delete all" {
+        log("delete requested")
+        confirm "Delete all orders?" {
+            save_csv(orders where amount < 0, "orders.csv")
+        }
+    }
+    button "Undo" {
+        revert(edited)
+    }
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("if st.button(\"Save\"):\n"), "{}", page);
+        assert!(page.contains("if st.button(\"Delete all\") or st.session_state.get(\"confirm_1\"):\n    log_message(\"delete requested\")\n    if confirmed(\"confirm_1\", \"Delete all orders?\"):\n        save_order("), "{}", page);
+        assert!(page.contains("if st.button(\"Undo\"):\n    revert([\"editor_0\"])\n"), "{}", page);
+        assert!(files["helpers.py"].contains("profiled, confirmed, revert\n"), "{}", files["helpers.py"]);
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+st.session_state = {{"editor_0": {{"edited_rows": {{0: {{"amount": 1.0}}}}}}}}
+clicks = set()
+class Column:
+    def button(self, label, key):
+        return key in clicks
+st.columns = lambda n: [Column() for _ in range(n)]
+st.warning = lambda message: print("warning:", message)
+st.rerun = lambda: print("rerun")
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+print(confirmed("confirm_1", "Delete?"), st.session_state.get("confirm_1"))
+clicks.add("confirm_1_confirm")
+print(confirmed("confirm_1", "Delete?"), st.session_state.get("confirm_1"))
+revert(["editor_0"])
+print(st.session_state)
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "warning: Delete?\nFalse True\nwarning: Delete?\nTrue None\nrerun\n{}\n");
+    }
+    
+    #[test]
+    fn test_row_restrictions() {
+        let source = r#"
+table Order {
+    id: int [key]
+    region: string
+    amount: float
+}
+
+table Note {
+    body: string
+}
+
+restrict Order where region == current_user().region
+restrict Order where !(amount < 0)
+
+page Orders {
+    let orders = load_sql("db", "orders", Order)
+    let notes = load_csv("notes.csv", Note)
+    save_sql(orders, "db", "orders", "replace")
+}
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, load_sql, save_sql, current_user\n"), "{}", helpers);
+        assert!(helpers.contains("def visible_order(df):\n    \"\"\"Which rows of the Order table the signed-in user may see\"\"\"\n    return (df[\"region\"] == current_user().get(\"region\")) & ~(df[\"amount\"] < 0)\n"), "{}", helpers);
+        assert!(helpers.contains("    validate_schema(df, ORDER_SCHEMA, \"Order\")\n    return df[visible_order(df)]\n"), "{}", helpers);
+        assert!(helpers.contains("        return save_sql(df, connection, path, mode, ORDER_CONSTRAINTS, \"Order\", visible=visible_order)\n"), "{}", helpers);
+        assert!(helpers.contains("    return write_csv(df, path, \"Order\", visible=visible_order)\n"), "{}", helpers);
+        assert!(helpers.contains("    validate_schema(df, NOTE_SCHEMA, \"Note\")\n    return df\n"), "{}", helpers);
+        assert!(!helpers.contains("visible_note"), "{}", helpers);
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+print(current_user())
+st.user = {{"is_logged_in": False}}
+print(current_user())
+st.user = {{"is_logged_in": True, "email": "ann@example.com", "region": "EU"}}
+print(current_user().get("region"), current_user().get("team"))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "{}\n{}\nEU None\n");
+    }
+    
+    #[test]
+    fn test_derived_tables() {
+        let source = r#"
+table User {
+    name: string
+    active: bool
+}
+
+table ActiveUsers = load_csv("users.csv", User) where active == true
+table Names = ActiveUsers[name]
+
+page Users {
+    show(Names)
+}
+
+test "names" {
+    mock ActiveUsers with [{ name: "Ada", active: true }]
+    assert_row_count(Names, 1)
+}
+"#;
+        let files = build_with_tests(source, true);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, cached_table\n"), "{}", helpers);
+        assert!(helpers.contains("def load_activeusers():\n    \"\"\"The ActiveUsers table, computed once per session\"\"\"\n    return cached_table(\"ActiveUsers\", lambda: load_user(\"users.csv\").query(\"(active == True)\"))\n"), "{}", helpers);
+        assert!(helpers.contains("    return cached_table(\"Names\", lambda: load_activeusers()[[\"name\"]])\n"), "{}", helpers);
+        assert!(files["Users.py"].contains("st.dataframe(load_names())"), "{}", files["Users.py"]);
+        let tests = &files["test_app.py"];
+        assert!(tests.contains("@pytest.fixture(autouse=True)\ndef fresh_session():\n    clear_derived_tables()\n"), "{}", tests);
+        assert!(tests.contains("monkeypatch.setattr(helpers, \"load_activeusers\", _mock_load_activeusers)"), "{}", tests);
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+st.session_state = {{}}
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+calls = []
+def compute():
+    calls.append(1)
+    return [len(calls)]
+print(cached_table("T", compute), cached_table("T", compute))
+clear_derived_tables()
+print(cached_table("T", compute))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "[1] [1]\n[2]\n");
+    }
+    
+    #[test]
+    fn test_materialized_tables() {
+        let source = r#"
+table Sale {
+    region: string
+    amount: float
+}
+
+table BigSales = load_csv("sales.csv", Sale) where amount > 100 materialize daily
+table Regions = load_csv("sales.csv", Sale)[region] materialize on_build
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, cached_table, materialized_table\n"), "{}", helpers);
+        assert!(helpers.contains("def compute_bigsales():\n    \"\"\"Compute the BigSales table from the tables it is derived from\"\"\"\n    return load_sale(\"sales.csv\").query(\"(amount > 100)\")\n"), "{}", helpers);
+        assert!(helpers.contains("    return materialized_table(\"BigSales\", \"bigsales.materialized.csv\", compute_bigsales, max_age=86400)\n"), "{}", helpers);
+        assert!(helpers.contains("def load_regions():\n    \"\"\"The Regions table, materialized in regions.materialized.csv by wtc build\"\"\"\n    return materialized_table(\"Regions\", \"regions.materialized.csv\")\n"), "{}", helpers);
+        let refresh = &files["refresh_tables.py"];
+        assert!(refresh.contains("from helpers import compute_bigsales\n\nrefresh_table(\"bigsales.materialized.csv\", compute_bigsales)\n"), "{}", refresh);
+        assert!(!build_with_tests("table T { a: int }", false).contains_key("refresh_tables.py"));
+        
+        // Materialized files are kept in the data directory
+        let dir = std::env::temp_dir().join(format!("wtlang_materialized_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = format!(r#"
+import os, sys, types
+st = types.ModuleType("streamlit")
+st.session_state = {{}}
+sys.modules["streamlit"] = st
+pd = types.ModuleType("pandas")
+pd.read_csv = lambda path: [os.path.relpath(path, {dir:?})]
+sys.modules["pandas"] = pd
+exec(compile({runtime:?}, "wt_runtime", "exec"))
+class Table:
+    def to_csv(self, file, index):
+        file.write("region\n")
+configure_data_dir(os.path.join({dir:?}, "data"))
+os.makedirs(os.path.join({dir:?}, "data"))
+print(materialized_table("Regions", "regions.materialized.csv", Table))
+refresh_table("sales.materialized.csv", Table)
+print(sorted(os.listdir(os.path.join({dir:?}, "data"))))
+"#, dir = dir.display().to_string(), runtime = files["wt_runtime.py"]);
+        let output = run_python(&script);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let Some(output) = output else { return };
+        assert_eq!(output, "['data/regions.materialized.csv']\n['regions.materialized.csv', 'regions.materialized.csv.lock', 'sales.materialized.csv', 'sales.materialized.csv.lock']\n");
+    }
+
+    #[test]
+    fn test_table_hooks() {
+        let source = r#"
+table Order {
+    id: int [key]
+    region: string
+    amount: float
+}
+
+restrict Order where region == current_user().region
+
+on_load Order {
+    log("orders loaded")
+}
+
+on_edit Order {
+    log("orders edited")
+}
+
+on_save Order {
+    if aggregate(saved, "amount", "min") < 0 {
+        return false
+    }
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let edited = show_editable(orders, audit("orders_audit.csv"))
+    save_csv(edited, "orders.csv")
+}
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, audit, audit_edits, on_edited, current_user\n"), "{}", helpers);
+        assert!(helpers.contains("    validate_schema(df, ORDER_SCHEMA, \"Order\")\n    df = df[visible_order(df)]\n    on_load_order(df)\n    return df\n"), "{}", helpers);
+        assert!(helpers.contains("        return False\n    if on_save_order(df) is False:\n        return False\n    return write_csv("), "{}", helpers);
+        assert!(helpers.contains("def on_load_order(loaded):\n    # region log(...) (line 11)\n    log_message(\"orders loaded\")\n"), "{}", helpers);
+        assert!(helpers.contains("def on_save_order(saved):\n    # region if (line 19)\n    if (saved[\"amount\"].min() < 0):\n        return False\n"), "{}", helpers);
+        let page = &files["Orders.py"];
+        assert!(page.contains("edited = on_edited(audit_edits(st.data_editor(orders, key=\"editor_0\", use_container_width=True), orders, audit(\"orders_audit.csv\"), \"editor_0\", \"Order\", \"id\"), orders, \"editor_0\", on_edit_order)\n"), "{}", page);
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+st.session_state = {{}}
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+original = [{{"id": 1, "amount": 5.0}}]
+edited = [{{"id": 1, "amount": 7.0}}]
+on_edited(original, original, "editor_0", lambda rows: print("hook", rows))
+on_edited(edited, original, "editor_0", lambda rows: print("hook", rows))
+on_edited(edited, original, "editor_0", lambda rows: print("hook", rows))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "hook [{'id': 1, 'amount': 7.0}]\n");
+    }
+
+    #[test]
+    fn test_normalized_headers() {
+        let source = r#"
+table Order {
+    id: int [key]
+    order_date: date
+}
+
+table Customer {
+    id: int [key]
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order, "normalize")
+    let customers = load_csv("customers.csv", Customer, "exact")
+    show(orders)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("profiled, match_columns\n"), "{}", helpers);
+        assert!(helpers.contains("def load_order(path, match_headers=False):\n"), "{}", helpers);
+        assert!(helpers.contains("    df = read_csv(path)\n    if match_headers:\n        df = match_columns(df, ORDER_SCHEMA)\n    validate_schema(df, ORDER_SCHEMA, \"Order\")\n"), "{}", helpers);
+        assert!(helpers.contains("def load_customer(path):\n"), "{}", helpers);
+        let page = &files["Orders.py"];
+        assert!(page.contains("orders = load_order(\"orders.csv\", match_headers=True)\n"), "{}", page);
+        assert!(page.contains("customers = load_customer(\"customers.csv\")\n"), "{}", page);
+        
+        let script = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+class Frame:
+    def __init__(self, columns):
+        self.columns = columns
+    def rename(self, columns):
+        return Frame([columns.get(column, column) for column in self.columns])
+schema = {{"id": "int", "order_date": "date"}}
+print(match_columns(Frame([" ID", "Order  Date", "order-date", "notes"]), schema).columns)
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "['id', 'order_date', 'order-date', 'notes']\n");
+    }
+
+    #[test]
+    fn test_column_headers() {
+        let source = r#"
+table Sale {
+    id: int [key]
+    revenue: currency from "Total Revenue (€)"
+}
+
+restrict Sale where id > 0
+
+page Sales {
+    let sales = load_csv("sales.csv", Sale)
+    save_csv(sales, "sales.csv")
+}
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("SALE_SCHEMA = {\"id\": \"int\", \"revenue\": \"currency\"}\nSALE_HEADERS = {\"revenue\": \"Total Revenue (€)\"}\n"), "{}", helpers);
+        assert!(helpers.contains("    df = read_csv(path, SALE_HEADERS)\n    validate_schema(df, SALE_SCHEMA, \"Sale\")\n"), "{}", helpers);
+        assert!(helpers.contains("    return write_csv(df, path, \"Sale\", visible=visible_sale, headers=SALE_HEADERS)\n"), "{}", helpers);
+        
+        let script = format!(r#"
+import sys, types, tempfile
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+class Frame:
+    def __init__(self, columns):
+        self.columns = columns
+    def rename(self, columns):
+        return Frame([columns.get(column, column) for column in self.columns])
+sys.modules["pandas"].read_csv = lambda data: Frame(data.read().decode().splitlines()[0].split(","))
+with tempfile.NamedTemporaryFile("w", suffix=".csv", delete=False) as file:
+    file.write("id,Total Revenue\n1,2\n")
+print(read_csv(file.name, {{"revenue": "Total Revenue"}}).columns)
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "['id', 'revenue']\n");
+    }
+
+    #[test]
+    fn test_membership() {
+        let source = r#"
+table Order {
+    id: int [key]
+    status: string
+    region: string
+}
+
+restrict Order where region in ["EU", "UK"]
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let allowed = ["open", "paid"]
+    let region = "EU"
+    if region in ["EU", "US"] {
+        text "Served"
+    }
+    show(orders where status in allowed && id > 2)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("    return df[\"region\"].isin([\"EU\", \"UK\"])\n"), "{}", helpers);
+        let page = &files["Orders.py"];
+        assert!(page.contains("if (region in [\"EU\", \"US\"]):\n"), "{}", page);
+        assert!(page.contains("orders.loc[lambda df: (df[\"status\"].isin(allowed) & (df[\"id\"] > 2))]"), "{}", page);
+    }
+
+    #[test]
+    fn test_between_and_null_checks() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+    note: string
+}
+
+restrict Order where note is not null
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let limit = 100
+    let amount = 20
+    if amount between 10 and limit {
+        text "In range"
+    }
+    show(orders where amount between 10 and limit && note is null)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("    return pd.notna(df[\"note\"])\n"), "{}", helpers);
+        let page = &files["Orders.py"];
+        assert!(page.contains("if (10 <= amount <= limit):\n"), "{}", page);
+        assert!(page.contains("orders.loc[lambda df: (df[\"amount\"].between(10, limit) & pd.isna(df[\"note\"]))]"), "{}", page);
+    }
+
+    #[test]
+    fn test_group_by() {
+        let source = r#"
+table Order {
+    id: int [key]
+    region: string
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    show(orders group by region having sum(amount) > 1000 && count() >= 2)
+    show(orders where average(amount) < 10 group by region)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("orders.groupby([\"region\"]).filter(lambda g: ((g[\"amount\"].sum() > 1000) and (len(g) >= 2)))"), "{}", page);
+        assert!(page.contains("orders.groupby([\"region\"]).filter(lambda g: (g[\"amount\"].mean() < 10))"), "{}", page);
+    }
+
+    #[test]
+    fn test_null_handling() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let total = sum(orders, "amount", "skip_nulls")
+    let average_amount = average(orders, "amount", "strict")
+    text "{total} {average_amount}"
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("total = orders[\"amount\"].sum(skipna=True)\n"), "{}", page);
+        assert!(page.contains("average_amount = strict_aggregate(orders, \"amount\", \"mean\")\n"), "{}", page);
+        assert!(files["helpers.py"].contains(", strict_aggregate"), "{}", files["helpers.py"]);
+        
+        let script = format!(r#"
+import sys, types
+sys.modules["streamlit"] = types.ModuleType("streamlit")
+sys.modules["pandas"] = types.ModuleType("pandas")
+exec(compile({:?}, "wt_runtime", "exec"))
+class Column(list):
+    def isna(self):
+        return Column([value is None for value in self])
+    def sum(self):
+        return sum(self)
+    def mean(self):
+        return sum(self) / len(self)
+print(strict_aggregate({{"amount": Column([1.0, 2.0])}}, "amount", "mean"))
+try:
+    strict_aggregate({{"amount": Column([1.0, None])}}, "amount", "mean")
+except ValueError as error:
+    print(error)
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "1.5\naverage of 'amount' found 1 missing value(s)\n");
+    }
+
+    #[test]
+    fn test_share() {
+        let source = r#"
+table Order {
+    id: int [key]
+    region: string
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    show(share(orders, "amount"))
+    show(share(orders where amount > 0, "amount" by "region"))
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("st.dataframe(orders.assign(amount_share=lambda df: df[\"amount\"] / df[\"amount\"].sum()))\n"), "{}", page);
+        assert!(page.contains(".assign(amount_share=lambda df: df[\"amount\"] / df.groupby(\"region\")[\"amount\"].transform(\"sum\")))\n"), "{}", page);
+    }
+
+    #[test]
+    fn test_hostile_column_names() {
+        let source = r#"
+table Order {
+    id: int [key]
+    class: string
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    show(orders where class == "it's \\ \"A\"")
+    show(share(orders, "amount" by "class"))
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("orders.query(\"(`class` == 'it\\\\'s \\\\\\\\ \\\"A\\\"')\")"), "{}", page);
+        assert!(page.contains("df.groupby(\"class\")[\"amount\"]"), "{}", page);
+        assert_eq!(python_string("it's \"x\" [a]\\\n"), "\"it's \\\"x\\\" [a]\\\\\\n\"");
+        assert_eq!(query_column("amount"), "amount");
+        assert_eq!(query_column("unit price"), "`unit price`");
+        assert_eq!(query_column("lambda"), "`lambda`");
+        // The page is valid Python, whose query is the one pandas expects
+        let check = format!("import ast\nfor node in ast.walk(ast.parse({:?})):\n    if isinstance(node, ast.Call) and getattr(node.func, 'attr', '') == 'query':\n        print(node.args[0].value)\n", page);
+        if let Some(output) = run_python(&check) {
+            assert_eq!(output, "(`class` == 'it\\'s \\\\ \"A\"')\n");
+        }
+    }
+
+    #[test]
+    fn test_interpolated_text() {
+        let source = r##"
+table Order {
+    id: int [key]
+    note: string
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let count = 2
+    title "Orders ({count})"
+    text "First: {first(orders).id} \{not a placeholder}"
+    text "Note: {first(orders).note == \"it's\"}"
+    text "No placeholders {"
+}
+"##;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("st.title(f\"Orders ({count})\")\n"), "{}", page);
+        assert!(page.contains("st.write(f\"First: {first_row(orders)['id']} {{not a placeholder}}\")\n"), "{}", page);
+        assert!(page.contains("st.write(\"Note: \" + str((first_row(orders)[\"note\"] == \"it's\")))\n"), "{}", page);
+        assert!(page.contains("st.write(\"No placeholders {\")\n"), "{}", page);
+    }
+
+    #[test]
+    fn test_sort_by_expression() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+    quantity: int
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    show(orders sort by amount / quantity desc, id)
+    show(orders sort by id desc)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("orders.assign(_sort_0=lambda df: (df[\"amount\"] / df[\"quantity\"])).sort_values(by=[\"_sort_0\", \"id\"], ascending=[False, True]).drop(columns=[\"_sort_0\"])"), "{}", page);
+        assert!(page.contains("orders.sort_values(by=\"id\", ascending=False)"), "{}", page);
+    }
+
+    #[test]
+    fn test_date_literals() {
+        let source = r#"
+table Order {
+    id: int [key]
+    placed: date
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let since = d"2024-01-15"
+    show(orders where placed >= d"2024-01-15" && id > 10)
+    show(orders where placed between d"2024-01-01" and d"2024-03-31")
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("since = pd.Timestamp(\"2024-01-15\").date()\n"), "{}", page);
+        assert!(page.contains("orders.loc[lambda df: ((pd.to_datetime(df[\"placed\"]).dt.date >= pd.Timestamp(\"2024-01-15\").date()) & (df[\"id\"] > 10))]"), "{}", page);
+        assert!(page.contains("pd.to_datetime(df[\"placed\"]).dt.date.between(pd.Timestamp(\"2024-01-01\").date(), pd.Timestamp(\"2024-03-31\").date())"), "{}", page);
+    }
+
+    #[test]
+    fn test_currency_literals() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: currency
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let fee = 12.50 EUR
+    show(orders where amount > $1,000)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("from decimal import Decimal\n"), "{}", page);
+        assert!(page.contains("fee = Decimal(\"12.50\")\n"), "{}", page);
+        assert!(page.contains("orders.query(\"(amount > 1000)\")"), "{}", page);
+    }
+
+    #[test]
+    fn test_column_patterns() {
+        let source = r#"
+table User {
+    id: int [key]
+    name: string
+    password: string
+    team: string
+}
+
+page Users {
+    let users = load_csv("users.csv", User)
+    show(users[* except password])
+    show(users[name, *])
+    show((users rename team to squad drop id)[squad, *])
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Users.py"];
+        assert!(page.contains("users[[\"id\", \"name\", \"team\"]]"), "{}", page);
+        assert!(page.contains("users[[\"name\", \"id\", \"password\", \"team\"]]"), "{}", page);
+        assert!(page.contains(".drop(columns=[\"id\"])[[\"squad\", \"name\", \"password\"]]"), "{}", page);
+    }
+
+    #[test]
+    fn test_rename_and_drop() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+    internal_notes: string
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let revenue = orders rename amount to revenue, id to order_id drop internal_notes
+    show(revenue)
+    save_csv(revenue, "revenue.csv")
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("orders.rename(columns={\"amount\": \"revenue\", \"id\": \"order_id\"}).drop(columns=[\"internal_notes\"])"), "{}", page);
+        // The renamed table no longer follows Order's schema
+        assert!(page.contains("write_csv(revenue, \"revenue.csv\")"), "{}", page);
+    }
+
+    #[test]
+    fn test_row_accessors() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let latest = last(orders sort by id)
+    let order = single(orders where id == 2)
+    if first(orders).amount > 10 {
+        text "Large first order"
+    }
+    text "{latest.amount}"
+}
+"#;
+        let files = build_with_tests(source, false);
+        assert!(files["helpers.py"].contains(", first_row, last_row, single_row"), "{}", files["helpers.py"]);
+        let page = &files["Orders.py"];
+        assert!(page.contains("latest = last_row(orders.sort_values(by=\"id\", ascending=True))\n"), "{}", page);
+        assert!(page.contains("order = single_row(orders.query(\"(id == 2)\"))\n"), "{}", page);
+        assert!(page.contains("if (first_row(orders)[\"amount\"] > 10):\n"), "{}", page);
+    }
+
+    #[test]
+    fn test_display() {
+        let source = r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let average_amount = 2.5
+    display average_amount
+    display first(orders)
+    display(orders)
+}
+"#;
+        let files = build_with_tests(source, false);
+        let page = &files["Orders.py"];
+        assert!(page.contains("st.write(format_number(average_amount))\n"), "{}", page);
+        assert!(page.contains("st.write(first_row(orders))\n"), "{}", page);
+        assert!(page.contains("st.dataframe(orders)\n"), "{}", page);
+        assert!(files["helpers.py"].contains("format_number"), "{}", files["helpers.py"]);
+    }
+
+    #[test]
+    fn test_match() {
+        let source = r#"
+function size(count: int) -> string {
+    return match count { 0 => "none", 1, 2 => "few", _ => "many" }
+}
+
+page Counts {
+    let count = 3
+    match count {
+        0 => { text "empty" }
+        _ => {}
+    }
+}
+"#;
+        let mut codegen = CodeGenerator::new();
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let files = codegen.generate(&Parser::new(tokens).parse().unwrap()).unwrap();
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains(
+            "return (lambda _match: \"none\" if _match == 0 else \"few\" if _match in (1, 2) else \"many\")(count)\n"
+        ), "{}", helpers);
+        assert!(files["Counts.py"].contains(
+            "_match_0 = count\nif _match_0 == 0:\n    st.write(\"empty\")\nelse:\n    pass\n"
+        ), "{}", files["Counts.py"]);
+    }
+
+    #[test]
+    fn test_page_file_collisions() {
+        let build = |source: &str| {
+            let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+            IRBuilder::new().build(&program).unwrap()
+        };
+        // The semantic analysis rejects duplicate pages, so only an IR built
+        // some other way has them
+        let mut module = build("page Home {\n    title \"One\"\n}\n");
+        module.items.push(module.items[0].clone());
+        let error = CodeGenerator::new().generate_from_ir(&module).unwrap_err();
+        assert_eq!(error, "Two pages are named 'Home'; rename one of them");
+        let error = CodeGenerator::new().generate_from_ir(&build("page helpers {\n    title \"Help\"\n}\n")).unwrap_err();
+        assert_eq!(error, "Page 'helpers' would overwrite the generated helpers.py; rename the page");
+    }
+
+    #[test]
+    fn test_output_is_deterministic() {
+        let source = r#"
+table Order {
+    id: int [key]
+    region: string
+    amount: float
+}
+
+table Customer {
+    id: int [key]
+    name: string
+}
+
+table Note {
+    body: string
+}
+
+external function tax_rate() -> float from "finance"
+external function fx(amount: float) -> float from "finance"
+external function notify(to: string) -> bool from "mail"
+
+restrict Order where region == current_user().region
+restrict Customer where id > 0
+
+on_load Order {
+    log("orders loaded")
+}
+
+on_edit Customer {
+    log("customers edited")
+}
+
+test "totals" {
+    assert tax_rate() >= 0
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    show_editable(orders)
+    text "Tax: {tax_rate()} {fx(1.0)}"
+}
+
+page Customers {
+    let customers = load_csv("customers.csv", Customer)
+    show_editable(customers)
+    let sent = notify("ops")
+}
+"#;
+        // Every generator hashes differently, so unordered maps would show
+        let first = build_with_tests(source, true);
+        for _ in 0..4 {
+            assert_eq!(build_with_tests(source, true), first);
+        }
+        assert!(first["helpers.py"].contains("from finance import fx, tax_rate\nfrom mail import notify\n"), "{}", first["helpers.py"]);
+    }
+
+    #[test]
+    fn test_python_version() {
+        let source = r#"
+table Order {
+    id: int [key]
+    note: string
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let count = 3
+    match count {
+        0 => { text "empty" }
+        1, 2 => {}
+        _ => { text "Note: {first(orders).note == \"it's\"}" }
+    }
+    match count > 1 {
+        true => { text "many" }
+        _ => {}
+    }
+}
+"#;
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let generate = |python| CodeGenerator::new().with_python(python).with_type_hints(TypeHints::Plain).generate(&program).unwrap();
+        
+        let files = generate(PythonVersion::V3_8);
+        let page = &files["Orders.py"];
+        assert!(page.starts_with("from __future__ import annotations\n"), "{}", page);
+        assert!(page.contains("_match_0 = count\nif _match_0 == 0:\n"), "{}", page);
+        assert!(page.contains("st.write(\"Note: \" + str((first_row(orders)[\"note\"] == \"it's\")))\n"), "{}", page);
+        assert!(!files["wt_runtime.py"].starts_with("from __future__"));
+        
+        let files = generate(PythonVersion::V3_10);
+        let page = &files["Orders.py"];
+        assert!(!page.contains("from __future__"), "{}", page);
+        assert!(page.contains(
+            "match count:\n    case 0:\n        st.write(\"empty\")\n    case 1 | 2:\n        pass\n    case _:\n        st.write("
+        ), "{}", page);
+        // Booleans are compared by identity in match statements
+        assert!(page.contains("if _match_0 == True:\n"), "{}", page);
+        
+        let page = &generate(PythonVersion::V3_12)["Orders.py"];
+        assert!(page.contains("st.write(f\"Note: {(first_row(orders)[\"note\"] == \"it's\")}\")\n"), "{}", page);
+    }
+
+    #[test]
+    fn test_constants() {
+        let source = r#"
+const TAX_RATE: float = 0.22
+const LIMIT = 10 * 2
+const SHOP = "Main " + "street"
+
+function taxed(price: float) -> float {
+    return price * (1.5 + TAX_RATE)
+}
+
+page Prices {
+    let limit = LIMIT
+    text "{SHOP}: {taxed(10.5)}"
+}
+"#;
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let files = CodeGenerator::new().generate(&program).unwrap();
+        let constants = "\n# Constants\nTAX_RATE = 0.22\nLIMIT = 20\nSHOP = \"Main street\"\n";
+        let page = &files["Prices.py"];
+        assert!(page.contains(&format!("from helpers import *\n{}", constants)), "{}", page);
+        assert!(page.contains("limit = LIMIT\n"), "{}", page);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains(constants), "{}", helpers);
+        assert!(helpers.contains("(1.5 + TAX_RATE)"), "{}", helpers);
+    }
+
+    #[test]
+    fn test_modules() {
+        // A module is read with its functions and constants named after it,
+        // as the module loader does, then the program importing it
+        let with_module = |name: &str, module: &str, members: &[&str], app: &str| {
+            let members = members.iter().map(|member| Atom::new(member)).collect();
+            let mut program = Parser::new(Lexer::new(module).tokenize().unwrap())
+                .with_namespace(Atom::new(name), members)
+                .parse().unwrap();
+            let app = Parser::new(Lexer::new(app).tokenize().unwrap())
+                .with_first_item(program.items.len() as u32)
+                .parse().unwrap();
+            program.items.extend(app.items.into_iter().filter(|item| !matches!(item, ProgramItem::Import(_))));
+            program
+        };
+        let program = with_module(
+            "stats",
+            "const RATE = 2\n\nfunction double(n: int) -> int {\n    return n * RATE\n}\n\nfunction quadruple(n: int) -> int {\n    return double(double(n))\n}\n",
+            &["RATE", "double", "quadruple"],
+            "import stats from \"stats.wt\"\n\npage Home {\n    let n = stats.quadruple(stats.RATE)\n    text \"{n}\"\n}\n\ntest \"quadruple\" {\n    mock stats.double with n => n\n    assert stats.quadruple(3) == 3\n}\n",
+        );
+        let files = CodeGenerator::new().with_tests(true).generate(&program).unwrap();
+        
+        // The module's own names are unqualified within it
+        let stats = &files["stats.py"];
+        assert!(stats.contains("from helpers import *\n\n# Constants\nRATE = 2\n"), "{}", stats);
+        assert!(stats.contains("def quadruple(n):\n    return double(double(n))\n"), "{}", stats);
+        assert!(stats.contains("    return (n * RATE)\n"), "{}", stats);
+        let page = &files["Home.py"];
+        assert!(page.contains("from helpers import *\nimport stats\n"), "{}", page);
+        assert!(page.contains("n = stats.quadruple(stats.RATE)\n"), "{}", page);
+        assert!(!page.contains("RATE = 2"), "{}", page);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.ends_with("\n\n# Modules of the program\nimport stats\n"), "{}", helpers);
+        assert!(!helpers.contains("def double"), "{}", helpers);
+        let tests = &files["test_app.py"];
+        assert!(tests.contains("monkeypatch.setattr(stats, \"double\", _mock_stats_double)\n"), "{}", tests);
+        
+        // Modules are named apart from the generated modules
+        let program = with_module("helpers", "const LIMIT = 1\n", &["LIMIT"], "import helpers from \"limits.wt\"\n\npage Home {\n    text \"{helpers.LIMIT}\"\n}\n");
+        let error = CodeGenerator::new().generate(&program).unwrap_err();
+        assert_eq!(error, "Module 'helpers' would overwrite the generated helpers.py; import it under another name");
+    }
+
+    #[test]
+    fn test_python_reserved_names() {
+        let source = r#"
+const max = 10
+
+function len(values: int[]) -> int {
+    let total = 0
+    forall st in values {
+        total = total + st
+    }
+    return total
+}
+
+page Home {
+    let class = len([1, 2, 3])
+    text "{class} {max} {round(1.5)}"
+}
+
+test "len" {
+    mock len with values => 0
+    assert len([1]) == 0
+}
+"#;
+        let files = build_with_tests(source, true);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("max_ = 10\n"), "{}", helpers);
+        assert!(helpers.contains("def len_(values):\n"), "{}", helpers);
+        assert!(helpers.contains("    for st_ in values:\n        total = (total + st_)\n"), "{}", helpers);
+        let page = &files["Home.py"];
+        assert!(page.contains("class_ = len_([1, 2, 3])\n"), "{}", page);
+        assert!(page.contains("{class_} {max_} {round_number(1.5)}"), "{}", page);
+        let tests = &files["test_app.py"];
+        assert!(tests.contains("monkeypatch.setattr(helpers, \"len_\", _mock_len_)\n"), "{}", tests);
+        
+        let Some(output) = run_python(&format!("max_ = 10\n{}\nprint(len_([1, 2, 3]), max_)", function_defs(helpers))) else { return };
+        assert_eq!(output.trim(), "6 10");
+    }
+
+    #[test]
+    fn test_where_lambda() {
+        let source = r#"
+table Order {
+    id: int [key],
+    amount: float
+}
+
+page Orders {
+    let orders = load_csv("orders.csv", Order)
+    let big = where(orders, row => row.amount > 100)
+    show(orders -> where(_, (o) => o.amount < 5 && o.id != 3))
+}
+"#;
+        let mut codegen = CodeGenerator::new();
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let files = codegen.generate(&Parser::new(tokens).parse().unwrap()).unwrap();
+        let page = &files["Orders.py"];
+        assert!(page.contains("big = where_rows(orders, lambda row: (row[\"amount\"] > 100))\n"), "{}", page);
+        assert!(page.contains(
+            "st.dataframe(where_rows(orders, lambda o: ((o[\"amount\"] < 5) and (o[\"id\"] != 3))))\n"
+        ), "{}", page);
+        assert!(files["helpers.py"].contains(", where_rows\n"), "{}", files["helpers.py"]);
+    }
+
+    #[test]
+    fn test_plugin_builtin() {
+        use wtlang_core::ManifestBuiltin;
+        let settings = BTreeMap::from([
+            ("signature".to_string(), "(old: float, new: float) -> float".to_string()),
+            ("import".to_string(), "from acme.finance import pct_change".to_string()),
+            ("streamlit".to_string(), "pct_change({old}, {new})".to_string()),
+        ]);
+        let mut plugins = PluginRegistry::new();
+        plugins.register(ManifestBuiltin::new("pct_change", &settings).unwrap()).unwrap();
+        let source = r#"
+page Growth {
+    let change = pct_change(80, 100.5)
+    text "{change}"
+}
+"#;
+        let mut codegen = CodeGenerator::new().with_plugins(plugins);
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let files = codegen.generate(&Parser::new(tokens).parse().unwrap()).unwrap();
+        assert!(files["Growth.py"].contains("change = pct_change(80, 100.5)\n"), "{}", files["Growth.py"]);
+        assert!(files["helpers.py"].contains("\nfrom acme.finance import pct_change\n"), "{}", files["helpers.py"]);
+    }
+
+    #[test]
+    fn test_python_block() {
+        let source = r#"
+template top(t: table, n: int) {
+    python(t: table, n: int) -> (best: float) {
+        best = t["amount"].nlargest(n).sum()
+    }
+    text "{best}"
+}
+
+page Sales {
+    let sales = load_csv("sales.csv")
+    python(sales: table) -> (labels: string[]) {
+        labels = [
+            f"{row.amount:.2f}"  # one per row }
+            for row in sales.itertuples()
+        ]
+
+        st.caption("}")
+    }
+    use top(sales, 3)
+}
+"#;
+        let files = build(source);
+        let page = &files["Sales.py"];
+        assert!(page.contains(
+            "labels = [\n    f\"{row.amount:.2f}\"  # one per row }\n    for row in sales.itertuples()\n]\n\nst.caption(\"}\")\n"
+        ), "{}", page);
+        // A template's python block reads and sets the variables it was used with
+        assert!(page.contains(
+            "n__top_1 = 3\n# endregion\n# region python (line 3)\nt = sales\nn = n__top_1\nbest = t[\"amount\"].nlargest(n).sum()\nbest__top_1 = best\n"
+        ), "{}", page);
+    }
+
+    #[test]
+    fn test_refresh() {
+        let source = r#"
+page Dashboard {
+    refresh every 5m
+    title "Orders"
+}
+"#;
+        let mut codegen = CodeGenerator::new();
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let files = codegen.generate(&Parser::new(tokens).parse().unwrap()).unwrap();
+        assert!(codegen.runtime_features().contains(&RuntimeFeature::Refresh));
+        assert!(files["Dashboard.py"].contains("refresh_every(300, \"refresh_0\")\n# endregion\nst.title(\"Orders\")\n"), "{}", files["Dashboard.py"]);
+        assert!(files["helpers.py"].contains("profiled, refresh_every\n"), "{}", files["helpers.py"]);
+        
+        let script = format!(r#"
+import sys, types
+st = types.ModuleType("streamlit")
+st.session_state = {{}}
+sys.modules["streamlit"] = st
+sys.modules["pandas"] = types.ModuleType("pandas")
+autorefresh = types.ModuleType("streamlit_autorefresh")
+ticks = iter([0, 0, 1])
+def st_autorefresh(interval, key):
+    print("autorefresh", interval, key)
+    return next(ticks)
+autorefresh.st_autorefresh = st_autorefresh
+sys.modules["streamlit_autorefresh"] = autorefresh
+exec(compile({:?}, "wt_runtime", "exec"))
+for _ in range(3):
+    _derived_tables()["T"] = [1]
+    refresh_every(300, "refresh_0")
+    print(sorted(_derived_tables()))
+"#, files["wt_runtime.py"]);
+        let Some(output) = run_python(&script) else { return };
+        assert_eq!(output, "autorefresh 300000 refresh_0\n['T']\nautorefresh 300000 refresh_0\n['T']\nautorefresh 300000 refresh_0\n[]\n");
+    }
+
+    #[test]
+    fn test_shared_state() {
+        let source = r#"
+shared let region: string = "North"
+shared let visits: int = 0
+
+page Home {
+    visits = visits + 1
+    text "Region {region}"
+}
+
+test "visits" {
+    assert(visits == 0)
+}
+"#;
+        let files = build_with_tests(source, true);
+        let helpers = &files["helpers.py"];
+        assert!(helpers.contains("def init_shared_state(reset=False):\n    \"\"\"Give the variables shared by every page their initial values\"\"\"\n    if reset or \"shared_region\" not in st.session_state:\n        st.session_state[\"shared_region\"] = \"North\"\n"), "{}", helpers);
+        let page = &files["Home.py"];
+        assert!(page.contains("from helpers import *\n"), "{}", page);
+        assert!(page.contains("init_shared_state()\n"), "{}", page);
+        assert!(page.contains("st.session_state[\"shared_visits\"] = (st.session_state[\"shared_visits\"] + 1)\n"), "{}", page);
+        assert!(page.contains("st.write(f\"Region {st.session_state['shared_region']}\")"), "{}", page);
+        let tests = &files["test_app.py"];
+        assert!(tests.contains("@pytest.fixture(autouse=True)\ndef fresh_session():\n    init_shared_state(reset=True)\n"), "{}", tests);
+        assert!(!tests.contains("clear_derived_tables"), "{}", tests);
+    }
+}
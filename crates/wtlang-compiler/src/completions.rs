@@ -0,0 +1,395 @@
+// Shell completion scripts and the man page of wtc
+//
+// Used by `wtc completions` and `wtc man`. Both are generated from the clap
+// definition of the commands, so new subcommands and options show up
+// without editing the scripts by hand.
+
+use clap::{Arg, ArgAction, Command, ValueEnum};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Completion script of a built command for a shell
+pub fn completions(cmd: &Command, shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(cmd),
+        Shell::Zsh => zsh(cmd),
+        Shell::Fish => fish(cmd),
+        Shell::Powershell => powershell(cmd),
+    }
+}
+
+/// Subcommands shown to users, without clap's `help`
+fn subcommands(cmd: &Command) -> Vec<&Command> {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .collect()
+}
+
+fn options(cmd: &Command) -> Vec<&Arg> {
+    cmd.get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .collect()
+}
+
+fn has_positionals(cmd: &Command) -> bool {
+    cmd.get_arguments().any(|arg| arg.is_positional() && !arg.is_hide_set())
+}
+
+/// Values a subcommand's arguments accept, such as the shells of
+/// `completions`; other arguments are completed as file names
+fn positional_values(cmd: &Command) -> Vec<String> {
+    cmd.get_arguments()
+        .filter(|arg| arg.is_positional() && !arg.is_hide_set())
+        .flat_map(possible_values)
+        .collect()
+}
+
+/// `-o` and `--output` forms of an option
+fn flags(arg: &Arg) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("--{}", long));
+    }
+    flags
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values().iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+/// First line of an option's help
+fn arg_help(arg: &Arg) -> String {
+    arg.get_help().map(|help| first_line(&help.to_string())).unwrap_or_default()
+}
+
+fn command_help(cmd: &Command) -> String {
+    cmd.get_about().map(|about| first_line(&about.to_string())).unwrap_or_default()
+}
+
+fn first_line(text: &str) -> String {
+    text.lines().next().unwrap_or_default().to_string()
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map_or_else(|| arg.get_id().as_str().to_uppercase(), |name| name.to_string())
+}
+
+fn bash(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let names: Vec<&str> = subcommands(cmd).iter().map(|sub| sub.get_name()).collect();
+
+    let mut script = String::new();
+    script.push_str(&format!("{}() {{\n", function));
+    script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\" opts=\"\" values=\"\"\n");
+    script.push_str("    if [[ $COMP_CWORD -eq 1 ]]; then\n");
+    script.push_str(&format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", names.join(" ")));
+    script.push_str("        return\n");
+    script.push_str("    fi\n");
+    script.push_str("    case \"${COMP_WORDS[1]}\" in\n");
+    for sub in subcommands(cmd) {
+        script.push_str(&format!("        {})\n", sub.get_name()));
+        let with_values: Vec<&Arg> = options(sub).into_iter().filter(|arg| !possible_values(arg).is_empty()).collect();
+        if !with_values.is_empty() {
+            script.push_str("            case \"$prev\" in\n");
+            for arg in with_values {
+                script.push_str(&format!(
+                    "                {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;\n",
+                    flags(arg).join("|"), possible_values(arg).join(" ")
+                ));
+            }
+            script.push_str("            esac\n");
+        }
+        let all: Vec<String> = options(sub).into_iter().flat_map(flags).collect();
+        script.push_str(&format!("            opts=\"{}\"\n", all.join(" ")));
+        let values = positional_values(sub);
+        if !values.is_empty() {
+            script.push_str(&format!("            values=\"{}\"\n", values.join(" ")));
+        }
+        script.push_str("            ;;\n");
+    }
+    script.push_str("    esac\n");
+    script.push_str("    if [[ \"$cur\" == -* ]]; then\n");
+    script.push_str("        COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))\n");
+    script.push_str("    elif [[ -n \"$values\" ]]; then\n");
+    script.push_str("        COMPREPLY=($(compgen -W \"$values\" -- \"$cur\"))\n");
+    script.push_str("    else\n");
+    script.push_str("        COMPREPLY=($(compgen -f -- \"$cur\"))\n");
+    script.push_str("    fi\n");
+    script.push_str("}\n");
+    script.push_str(&format!("complete -o filenames -F {} {}\n", function, name));
+    script
+}
+
+/// Text inside single quotes of a zsh `_arguments` spec
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:")
+}
+
+fn zsh(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut script = String::new();
+    script.push_str(&format!("#compdef {}\n\n", name));
+    script.push_str(&format!("{}() {{\n", function));
+    script.push_str("    local -a commands\n");
+    script.push_str("    commands=(\n");
+    for sub in subcommands(cmd) {
+        script.push_str(&format!("        '{}:{}'\n", sub.get_name(), zsh_escape(&command_help(sub))));
+    }
+    script.push_str("    )\n");
+    script.push_str("    if (( CURRENT == 2 )); then\n");
+    script.push_str("        _describe 'command' commands\n");
+    script.push_str("        return\n");
+    script.push_str("    fi\n");
+    script.push_str("    local command=$words[2]\n");
+    script.push_str("    shift words\n");
+    script.push_str("    (( CURRENT-- ))\n");
+    script.push_str("    case $command in\n");
+    for sub in subcommands(cmd) {
+        let mut specs = Vec::new();
+        for arg in options(sub) {
+            let repeat = if matches!(arg.get_action(), ArgAction::Append | ArgAction::Count) { "*" } else { "" };
+            let value = if !takes_value(arg) {
+                String::new()
+            } else if possible_values(arg).is_empty() {
+                format!(":{}:_files", value_name(arg).to_lowercase())
+            } else {
+                format!(":{}:({})", value_name(arg).to_lowercase(), possible_values(arg).join(" "))
+            };
+            for flag in flags(arg) {
+                specs.push(format!("'{}{}[{}]{}'", repeat, flag, zsh_escape(&arg_help(arg)), value));
+            }
+        }
+        let values = positional_values(sub);
+        if !values.is_empty() {
+            specs.push(format!("'1:value:({})'", values.join(" ")));
+        } else if has_positionals(sub) {
+            specs.push("'*:file:_files'".to_string());
+        }
+        script.push_str(&format!("        ({})\n", sub.get_name()));
+        script.push_str("            _arguments \\\n");
+        script.push_str(&format!("                {}\n", specs.join(" \\\n                ")));
+        script.push_str("            ;;\n");
+    }
+    script.push_str("    esac\n");
+    script.push_str("}\n\n");
+    script.push_str(&format!("{} \"$@\"\n", function));
+    script
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let mut script = String::new();
+    for sub in subcommands(cmd) {
+        script.push_str(&format!(
+            "complete -c {} -n __fish_use_subcommand -f -a {} -d {}\n",
+            name, sub.get_name(), fish_quote(&command_help(sub))
+        ));
+    }
+    for sub in subcommands(cmd) {
+        let condition = fish_quote(&format!("__fish_seen_subcommand_from {}", sub.get_name()));
+        for arg in options(sub) {
+            let mut line = format!("complete -c {} -n {}", name, condition);
+            if let Some(short) = arg.get_short() {
+                line.push_str(&format!(" -s {}", short));
+            }
+            if let Some(long) = arg.get_long() {
+                line.push_str(&format!(" -l {}", long));
+            }
+            if takes_value(arg) {
+                line.push_str(" -r");
+                let values = possible_values(arg);
+                if !values.is_empty() {
+                    line.push_str(&format!(" -f -a {}", fish_quote(&values.join(" "))));
+                }
+            }
+            line.push_str(&format!(" -d {}\n", fish_quote(&arg_help(arg))));
+            script.push_str(&line);
+        }
+        let values = positional_values(sub);
+        if !values.is_empty() {
+            script.push_str(&format!("complete -c {} -n {} -f -a {}\n", name, condition, fish_quote(&values.join(" "))));
+        }
+    }
+    script
+}
+
+fn powershell_list(items: &[String]) -> String {
+    let quoted: Vec<String> = items.iter().map(|item| format!("'{}'", item.replace('\'', "''"))).collect();
+    format!("@({})", quoted.join(", "))
+}
+
+fn powershell(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let names: Vec<String> = subcommands(cmd).iter().map(|sub| sub.get_name().to_string()).collect();
+    let mut script = String::new();
+    script.push_str(&format!("Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{\n", name));
+    script.push_str("    param($wordToComplete, $commandAst, $cursorPosition)\n");
+    script.push_str("    $elements = @($commandAst.CommandElements | ForEach-Object { $_.ToString() })\n");
+    script.push_str("    if ($elements.Count -le 1 -or ($elements.Count -eq 2 -and $wordToComplete)) {\n");
+    script.push_str(&format!("        $candidates = {}\n", powershell_list(&names)));
+    script.push_str("    } else {\n");
+    script.push_str("        $candidates = switch ($elements[1]) {\n");
+    for sub in subcommands(cmd) {
+        let mut all: Vec<String> = options(sub).into_iter().flat_map(flags).collect();
+        all.extend(positional_values(sub));
+        script.push_str(&format!("            '{}' {{ {} }}\n", sub.get_name(), powershell_list(&all)));
+    }
+    script.push_str("            default { @() }\n");
+    script.push_str("        }\n");
+    script.push_str("    }\n");
+    script.push_str("    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n");
+    script.push_str("        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n");
+    script.push_str("    }\n");
+    script.push_str("}\n");
+    script
+}
+
+/// Text of a man page line, with roff's special characters escaped
+fn roff(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Paragraphs of help text, separated by `.PP`
+fn roff_paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(|paragraph| paragraph.lines().map(roff).collect::<Vec<_>>().join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n.PP\n")
+}
+
+/// Man page of a built command, in roff, with a section per subcommand
+pub fn man_page(cmd: &Command, version: &str) -> String {
+    let name = cmd.get_name();
+    let mut page = String::new();
+    page.push_str(&format!(".TH {} 1 \"\" \"{} {}\"\n", name.to_uppercase(), name, version));
+    page.push_str(".SH NAME\n");
+    page.push_str(&format!("{} \\- {}\n", name, roff(&command_help(cmd))));
+    page.push_str(".SH SYNOPSIS\n");
+    page.push_str(&format!("\\fB{}\\fR \\fICOMMAND\\fR [\\fIOPTIONS\\fR]\n", name));
+    page.push_str(".SH COMMANDS\n");
+    for sub in subcommands(cmd) {
+        page.push_str(&format!(".SS \"{} {}\"\n", name, sub.get_name()));
+        let about = sub.get_long_about().or(sub.get_about()).map(|about| about.to_string()).unwrap_or_default();
+        page.push_str(&roff_paragraphs(&about));
+        page.push('\n');
+        for arg in sub.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            page.push_str(".TP\n");
+            if arg.is_positional() {
+                page.push_str(&format!("\\fI{}\\fR\n", value_name(arg)));
+            } else {
+                let mut forms: Vec<String> = flags(arg).iter().map(|flag| format!("\\fB{}\\fR", roff(flag))).collect();
+                if takes_value(arg) {
+                    let last = forms.pop().unwrap_or_default();
+                    forms.push(format!("{} \\fI{}\\fR", last, value_name(arg)));
+                }
+                page.push_str(&forms.join(", "));
+                page.push('\n');
+            }
+            let mut help = arg.get_long_help().or(arg.get_help()).map(|help| help.to_string()).unwrap_or_default();
+            if let Some(default) = arg.get_default_values().first().filter(|_| takes_value(arg)) {
+                help.push_str(&format!(" [default: {}]", default.to_string_lossy()));
+            }
+            let values = possible_values(arg);
+            if !values.is_empty() {
+                help.push_str(&format!(" [possible values: {}]", values.join(", ")));
+            }
+            page.push_str(&roff_paragraphs(help.trim()));
+            page.push('\n');
+        }
+    }
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        let mut cmd = Command::new("wtc")
+            .about("WTLang Compiler")
+            .subcommand(Command::new("build")
+                .about("Build WTLang source files\n\nLonger text")
+                .arg(Arg::new("input").required(true).help("Input file"))
+                .arg(Arg::new("output").short('o').long("output").default_value("output").help("Output directory"))
+                .arg(Arg::new("deny").short('D').long("deny").action(ArgAction::Append).value_name("LINT").help("Report these warnings as errors")))
+            .subcommand(Command::new("completions")
+                .about("Print a completion script")
+                .arg(Arg::new("shell").required(true).value_parser(["bash", "zsh"]))
+                .arg(Arg::new("format").long("format").value_parser(["plain", "json"]).help("Output [format]")));
+        cmd.build();
+        cmd
+    }
+
+    #[test]
+    fn test_bash() {
+        let script = completions(&command(), Shell::Bash);
+        assert!(script.contains("COMPREPLY=($(compgen -W \"build completions\" -- \"$cur\"))\n"), "{}", script);
+        assert!(script.contains("            opts=\"-o --output -D --deny -h --help\"\n"), "{}", script);
+        assert!(script.contains("                --format) COMPREPLY=($(compgen -W \"plain json\" -- \"$cur\")); return ;;\n"), "{}", script);
+        assert!(script.contains("            values=\"bash zsh\"\n"), "{}", script);
+        assert!(script.ends_with("complete -o filenames -F _wtc wtc\n"));
+    }
+
+    #[test]
+    fn test_zsh() {
+        let script = completions(&command(), Shell::Zsh);
+        assert!(script.starts_with("#compdef wtc\n"));
+        assert!(script.contains("        'build:Build WTLang source files'\n"), "{}", script);
+        assert!(script.contains("'-o[Output directory]:output:_files'"), "{}", script);
+        assert!(script.contains("'*--deny[Report these warnings as errors]:lint:_files'"), "{}", script);
+        assert!(script.contains("'--format[Output \\[format\\]]:format:(plain json)'"), "{}", script);
+        assert!(script.contains("'*:file:_files'"), "{}", script);
+        assert!(script.contains("'1:value:(bash zsh)'"), "{}", script);
+    }
+
+    #[test]
+    fn test_fish_and_powershell() {
+        let fish = completions(&command(), Shell::Fish);
+        assert!(fish.contains("complete -c wtc -n __fish_use_subcommand -f -a build -d 'Build WTLang source files'\n"), "{}", fish);
+        assert!(fish.contains("complete -c wtc -n '__fish_seen_subcommand_from build' -s o -l output -r -d 'Output directory'\n"), "{}", fish);
+        assert!(fish.contains("-l format -r -f -a 'plain json'"), "{}", fish);
+
+        let powershell = completions(&command(), Shell::Powershell);
+        assert!(powershell.contains("$candidates = @('build', 'completions')\n"), "{}", powershell);
+        assert!(powershell.contains("'build' { @('-o', '--output', '-D', '--deny', '-h', '--help') }\n"), "{}", powershell);
+    }
+
+    #[test]
+    fn test_man_page() {
+        let page = man_page(&command(), "0.1.0");
+        assert!(page.starts_with(".TH WTC 1 \"\" \"wtc 0.1.0\"\n.SH NAME\nwtc \\- WTLang Compiler\n"), "{}", page);
+        assert!(page.contains(".SS \"wtc build\"\nBuild WTLang source files\n.PP\nLonger text\n"), "{}", page);
+        assert!(page.contains(".TP\n\\fIINPUT\\fR\nInput file\n"), "{}", page);
+        assert!(page.contains(".TP\n\\fB\\-o\\fR, \\fB\\-\\-output\\fR \\fIOUTPUT\\fR\nOutput directory [default: output]\n"), "{}", page);
+        assert!(page.contains("[possible values: plain, json]"), "{}", page);
+    }
+}
@@ -0,0 +1,173 @@
+// Line diffs between generated files
+//
+// Produces unified diffs in the format of `diff -u`, used to show what a
+// build would change in the output directory.
+
+/// Lines of context around each change
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Unified diff from `old` to `new`, or an empty string when they have the
+/// same lines
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let edits = edit_script(&old_lines, &new_lines);
+    if edits.iter().all(|edit| *edit == Edit::Keep) {
+        return String::new();
+    }
+
+    // Line numbers in the old and new file before each edit
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in &edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Keep => { old_pos += 1; new_pos += 1; }
+            Edit::Delete => old_pos += 1,
+            Edit::Insert => new_pos += 1,
+        }
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let changes: Vec<usize> = (0..edits.len()).filter(|&i| edits[i] != Edit::Keep).collect();
+    let mut group_start = 0;
+    while group_start < changes.len() {
+        // Changes separated by less than twice the context share a hunk
+        let mut group_end = group_start;
+        while group_end + 1 < changes.len() && changes[group_end + 1] - changes[group_end] <= 2 * CONTEXT {
+            group_end += 1;
+        }
+        let start = changes[group_start].saturating_sub(CONTEXT);
+        let end = (changes[group_end] + CONTEXT + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        let old_count = hunk.iter().filter(|e| **e != Edit::Insert).count();
+        let new_count = hunk.iter().filter(|e| **e != Edit::Delete).count();
+        let (old_start, new_start) = positions[start];
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        for (offset, edit) in hunk.iter().enumerate() {
+            let (old_pos, new_pos) = positions[start + offset];
+            match edit {
+                Edit::Keep => diff.push_str(&format!(" {}\n", old_lines[old_pos])),
+                Edit::Delete => diff.push_str(&format!("-{}\n", old_lines[old_pos])),
+                Edit::Insert => diff.push_str(&format!("+{}\n", new_lines[new_pos])),
+            }
+        }
+        group_start = group_end + 1;
+    }
+    diff
+}
+
+/// `start,count` of a hunk, where an empty range names the line before it
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Shortest edit script turning `old` into `new` (Myers' algorithm)
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    // v[k] is the furthest x reached on diagonal k = x - y
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back from the end through the recorded rounds
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == prev_x { Edit::Insert } else { Edit::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nb\nc\nD\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(unified_diff(old, old, "a/x", "b/x"), "");
+        assert_eq!(unified_diff(old, new, "a/x", "b/x"), "\
+--- a/x
++++ b/x
+@@ -1,7 +1,7 @@
+ a
+ b
+ c
+-d
++D
+ e
+ f
+ g
+@@ -8,3 +8,4 @@
+ h
+ i
+ j
++k
+");
+    }
+
+    #[test]
+    fn test_diff_against_empty_file() {
+        assert_eq!(unified_diff("", "x\ny\n", "/dev/null", "b/new.py"), "--- /dev/null\n+++ b/new.py\n@@ -0,0 +1,2 @@\n+x\n+y\n");
+        assert_eq!(unified_diff("x\n", "", "a/old.py", "/dev/null"), "--- a/old.py\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n");
+    }
+}
@@ -0,0 +1,277 @@
+// Entity-relationship diagrams of a program's tables
+//
+// Used by `wtc erd` to document the tables of a project: each table with its
+// fields, keys and unique fields, and a relationship for every `ref` field
+// and `references` constraint, as Mermaid or Graphviz DOT.
+
+use clap::ValueEnum;
+use wtlang_core::ir::{Constraint, Field, FieldType, IRModule, TableSchema};
+use wtlang_core::Atom;
+
+/// Kind of diagram to generate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A Mermaid `erDiagram`, rendered by GitHub, GitLab and most wikis
+    Mermaid,
+    /// A Graphviz graph, rendered with `dot -Tsvg`
+    Dot,
+}
+
+/// A field of one table holding keys of another
+struct Relationship {
+    from_table: Atom,
+    from_field: Atom,
+    to_table: Atom,
+    to_field: Option<Atom>,
+    /// Whether a row may refer to no row
+    optional: bool,
+    /// Whether a row is referred to by at most one row
+    unique: bool,
+}
+
+/// What a field is marked with in the diagram
+struct Markers {
+    key: bool,
+    unique: bool,
+    non_null: bool,
+}
+
+fn markers(schema: &TableSchema, field: Atom) -> Markers {
+    let has = |wanted: fn(&Constraint) -> bool| {
+        schema.constraints.iter().any(|constraint| wanted(constraint) && constraint.field() == field)
+    };
+    Markers {
+        key: has(|c| matches!(c, Constraint::PrimaryKey(_))),
+        unique: has(|c| matches!(c, Constraint::Unique(_))),
+        non_null: has(|c| matches!(c, Constraint::NonNull(_))),
+    }
+}
+
+/// `PK`, `FK` and `UK`, as the field is a key, a reference or unique
+fn key_marks(schema: &TableSchema, field: &Field, module: &IRModule) -> Vec<&'static str> {
+    let markers = markers(schema, field.name);
+    let mut keys = Vec::new();
+    if markers.key {
+        keys.push("PK");
+    }
+    if referenced(field, module).is_some() {
+        keys.push("FK");
+    }
+    if markers.unique {
+        keys.push("UK");
+    }
+    keys
+}
+
+fn tables(module: &IRModule) -> Vec<&TableSchema> {
+    module.table_names().into_iter()
+        .filter_map(|name| module.find_table(name))
+        .collect()
+}
+
+/// The table and field a field refers to, if any
+fn referenced(field: &Field, module: &IRModule) -> Option<(Atom, Option<Atom>)> {
+    match (&field.ty, field.references) {
+        (FieldType::Ref { table_name }, _) => {
+            let key = module.find_table(table_name).and_then(|table| table.get_key_field());
+            Some((*table_name, key.map(|key| key.name)))
+        }
+        (_, Some((table, field))) => Some((table, Some(field))),
+        _ => None,
+    }
+}
+
+fn relationships(module: &IRModule) -> Vec<Relationship> {
+    let mut relationships = Vec::new();
+    for schema in tables(module) {
+        for field in &schema.fields {
+            let Some((to_table, to_field)) = referenced(field, module) else { continue };
+            // Undefined tables are reported by `wtc check`
+            if module.find_table(to_table.as_str()).is_none() {
+                continue;
+            }
+            let markers = markers(schema, field.name);
+            relationships.push(Relationship {
+                from_table: schema.name,
+                from_field: field.name,
+                to_table,
+                to_field,
+                optional: !(markers.key || markers.non_null),
+                unique: markers.key || markers.unique,
+            });
+        }
+    }
+    relationships
+}
+
+/// Type shown for a field; a reference shows the type of the key it holds
+fn type_name(ty: &FieldType, module: &IRModule) -> String {
+    match ty {
+        FieldType::Ref { table_name } => match module.find_table(table_name).and_then(|table| table.get_key_field()) {
+            Some(key) if !matches!(key.ty, FieldType::Ref { .. }) => key.ty.to_string(),
+            _ => "string".to_string(),
+        },
+        ty => ty.to_string(),
+    }
+}
+
+/// The diagram of a program's tables in the given format
+pub fn diagram(module: &IRModule, format: Format) -> String {
+    match format {
+        Format::Mermaid => mermaid(module),
+        Format::Dot => dot(module),
+    }
+}
+
+fn mermaid(module: &IRModule) -> String {
+    let mut code = String::from("erDiagram\n");
+    for schema in tables(module) {
+        code.push_str(&format!("    {} {{\n", schema.name));
+        for field in &schema.fields {
+            let keys = key_marks(schema, field, module);
+            let mut line = format!("        {} {}", type_name(&field.ty, module), field.name);
+            if !keys.is_empty() {
+                line.push_str(&format!(" {}", keys.join(", ")));
+            }
+            if let Some(header) = &field.header {
+                line.push_str(&format!(" \"{}\"", header.replace('"', "'")));
+            }
+            code.push_str(&line);
+            code.push('\n');
+        }
+        code.push_str("    }\n");
+    }
+    for relationship in relationships(module) {
+        // Crow's foot notation, from the referenced table's side
+        let referenced = if relationship.optional { "|o" } else { "||" };
+        let referencing = if relationship.unique { "o|" } else { "o{" };
+        code.push_str(&format!(
+            "    {} {}--{} {} : {}\n",
+            relationship.to_table, referenced, referencing, relationship.from_table, relationship.from_field
+        ));
+    }
+    code
+}
+
+fn dot(module: &IRModule) -> String {
+    let mut code = String::from("digraph erd {\n");
+    code.push_str("    rankdir=LR;\n");
+    code.push_str("    node [shape=plaintext, fontname=\"Helvetica\"];\n");
+    code.push_str("    edge [dir=both, fontname=\"Helvetica\", fontsize=10];\n");
+    for schema in tables(module) {
+        code.push('\n');
+        code.push_str(&format!("    \"{}\" [label=<\n", schema.name));
+        code.push_str("        <table border=\"0\" cellborder=\"1\" cellspacing=\"0\" cellpadding=\"4\">\n");
+        code.push_str(&format!(
+            "            <tr><td colspan=\"3\" bgcolor=\"lightgrey\"><b>{}</b></td></tr>\n",
+            html_escape(schema.name.as_str())
+        ));
+        for field in &schema.fields {
+            let markers = markers(schema, field.name);
+            let name = if markers.key {
+                format!("<u>{}</u>", html_escape(field.name.as_str()))
+            } else {
+                html_escape(field.name.as_str())
+            };
+            let keys = key_marks(schema, field, module);
+            code.push_str(&format!(
+                "            <tr><td port=\"{}\" align=\"left\">{}</td><td align=\"left\">{}</td><td>{}</td></tr>\n",
+                html_escape(field.name.as_str()), name, html_escape(&type_name(&field.ty, module)), keys.join(", ")
+            ));
+        }
+        code.push_str("        </table>\n");
+        code.push_str("    >];\n");
+    }
+    let relationships = relationships(module);
+    if !relationships.is_empty() {
+        code.push('\n');
+    }
+    for relationship in relationships {
+        let target = match relationship.to_field {
+            Some(field) => format!("\"{}\":\"{}\"", relationship.to_table, field),
+            None => format!("\"{}\"", relationship.to_table),
+        };
+        code.push_str(&format!(
+            "    \"{}\":\"{}\" -> {} [arrowhead={}, arrowtail={}];\n",
+            relationship.from_table,
+            relationship.from_field,
+            target,
+            if relationship.optional { "teeodot" } else { "teetee" },
+            if relationship.unique { "teeodot" } else { "crowodot" },
+        ));
+    }
+    code.push_str("}\n");
+    code
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{IRBuilder, Lexer, Parser};
+
+    fn module(source: &str) -> IRModule {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        IRBuilder::new().build(&program).unwrap()
+    }
+
+    const SOURCE: &str = r#"
+table Customer {
+    id: int [key],
+    email: string [unique]
+}
+
+table Order {
+    number: int [key],
+    customer: ref Customer [non_null],
+    total: currency from "Total (€)"
+}
+
+table Invoice {
+    id: int [key],
+    order_number: int [references Order.number, unique]
+}
+"#;
+
+    #[test]
+    fn test_mermaid() {
+        assert_eq!(diagram(&module(SOURCE), Format::Mermaid), concat!(
+            "erDiagram\n",
+            "    Customer {\n",
+            "        int id PK\n",
+            "        string email UK\n",
+            "    }\n",
+            "    Order {\n",
+            "        int number PK\n",
+            "        int customer FK\n",
+            "        currency total \"Total (€)\"\n",
+            "    }\n",
+            "    Invoice {\n",
+            "        int id PK\n",
+            "        int order_number FK, UK\n",
+            "    }\n",
+            "    Customer ||--o{ Order : customer\n",
+            "    Order |o--o| Invoice : order_number\n",
+        ));
+    }
+
+    #[test]
+    fn test_dot() {
+        let code = diagram(&module(SOURCE), Format::Dot);
+        assert!(code.starts_with("digraph erd {\n"), "{}", code);
+        assert!(code.contains(
+            "<tr><td port=\"number\" align=\"left\"><u>number</u></td><td align=\"left\">int</td><td>PK</td></tr>\n"
+        ), "{}", code);
+        assert!(code.contains(
+            "    \"Order\":\"customer\" -> \"Customer\":\"id\" [arrowhead=teetee, arrowtail=crowodot];\n"
+        ), "{}", code);
+        assert!(code.contains(
+            "    \"Invoice\":\"order_number\" -> \"Order\":\"number\" [arrowhead=teeodot, arrowtail=teeodot];\n"
+        ), "{}", code);
+        assert!(code.ends_with("}\n"), "{}", code);
+    }
+}
@@ -0,0 +1,268 @@
+// Translations of page text
+//
+// Page text written as `t("key")` is looked up at runtime in the program's
+// locale files: `locales/<language>.toml` next to the source file, each a
+// flat list of `key = "text"` lines. `[section]` headers prefix the keys
+// below them with `section.`. The compiler embeds the translations in the
+// generated app, warns about keys a locale file lacks, and `wtc locales`
+// adds those keys to the files for translators to fill in.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wtlang_core::ast::{Expr, PageText, ProgramItem, Statement};
+use wtlang_core::Program;
+
+/// Language to key to text
+pub type Locales = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Language the app starts in, and falls back to for missing keys
+pub fn default_language(locales: &Locales) -> Option<&str> {
+    if locales.contains_key("en") {
+        return Some("en");
+    }
+    locales.keys().next().map(String::as_str)
+}
+
+/// Directory holding the locale files of a source file
+pub fn locales_dir(input: &Path) -> PathBuf {
+    input.parent().unwrap_or(Path::new("")).join("locales")
+}
+
+/// Read every `<language>.toml` in `dir`, or none if it does not exist
+pub fn load_locales(dir: &Path) -> Result<Locales> {
+    let mut locales = Locales::new();
+    if !dir.is_dir() {
+        return Ok(locales);
+    }
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "toml") {
+            continue;
+        }
+        let Some(language) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let entries = parse_locale(&text)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        locales.insert(language.to_string(), entries);
+    }
+    Ok(locales)
+}
+
+/// Parse a locale or project file: `key = "text"` lines, `[section]`
+/// headers and `#` comments
+pub fn parse_locale(text: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut entries = BTreeMap::new();
+    let mut section = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = format!("{}.", name.trim());
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected `key = \"text\"`", line_number));
+        };
+        let key = key.trim();
+        let key = match key.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+            Some(quoted) => quoted,
+            None => key,
+        };
+        let value = parse_string(value.trim())
+            .ok_or_else(|| format!("line {}: the text of '{}' must be a double-quoted string", line_number, key))?;
+        entries.insert(format!("{}{}", section, key), value);
+    }
+    Ok(entries)
+}
+
+/// A double-quoted TOML basic string, ignoring a trailing comment
+fn parse_string(text: &str) -> Option<String> {
+    let mut chars = text.strip_prefix('"')?.chars();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => value.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                other => other,
+            }),
+            c => value.push(c),
+        }
+    }
+    let rest = chars.as_str().trim();
+    (rest.is_empty() || rest.starts_with('#')).then_some(value)
+}
+
+/// Keys of the program's translated text and `t("key")` calls
+pub fn translation_keys(program: &Program) -> BTreeSet<String> {
+    let mut keys = BTreeSet::new();
+    for item in &program.items {
+        match item {
+            ProgramItem::Page(page) => collect_statements(&page.statements, &mut keys),
+            ProgramItem::FunctionDef(function) => collect_statements(&function.body, &mut keys),
+            ProgramItem::Test(test) => collect_statements(&test.body, &mut keys),
+            ProgramItem::Hook(hook) => collect_statements(&hook.body, &mut keys),
+            ProgramItem::TableDef(_) | ProgramItem::ExternalFunction(_)
+            | ProgramItem::Restriction(_) | ProgramItem::DerivedTable(_) | ProgramItem::SharedVariable(_)
+            | ProgramItem::Constant(_) | ProgramItem::Config(_) | ProgramItem::Import(_) => {}
+        }
+    }
+    keys
+}
+
+fn collect_statements(statements: &[Statement], keys: &mut BTreeSet<String>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Title(text) | Statement::Subtitle(text) | Statement::Text(text) => collect_text(text, keys),
+            Statement::Button { label: text, body, .. }
+            | Statement::Section { title: text, body, .. }
+            | Statement::Confirm { message: text, body, .. } => {
+                collect_text(text, keys);
+                collect_statements(body, keys);
+            }
+            Statement::Let { value, .. } => {
+                if let Some(value) = value {
+                    collect_expr(value, keys);
+                }
+            }
+            Statement::Assign { value, .. } | Statement::Mock { value, .. } => collect_expr(value, keys),
+            Statement::If { condition, then_branch, else_branch, .. } => {
+                collect_expr(condition, keys);
+                collect_statements(then_branch, keys);
+                collect_statements(else_branch.as_deref().unwrap_or_default(), keys);
+            }
+            Statement::Forall { iterable, body, .. } => {
+                collect_expr(iterable, keys);
+                collect_statements(body, keys);
+            }
+            Statement::Match { value, arms, .. } => {
+                collect_expr(value, keys);
+                for arm in arms {
+                    collect_statements(&arm.body, keys);
+                }
+            }
+            Statement::Return(value) | Statement::Assert { condition: value, .. } => collect_expr(value, keys),
+            Statement::FunctionCall(call) => collect_expr(&Expr::FunctionCall(call.clone()), keys),
+            Statement::Refresh { .. } | Statement::Python { .. } => {}
+        }
+    }
+}
+
+fn collect_text(text: &PageText, keys: &mut BTreeSet<String>) {
+    if let PageText::Translated(key) = text {
+        keys.insert(key.clone());
+    }
+}
+
+fn collect_expr(expr: &Expr, keys: &mut BTreeSet<String>) {
+    match expr {
+        Expr::FunctionCall(call) => {
+            if let (true, [Expr::StringLiteral(key)]) = (call.name.as_str() == "t", call.args.as_slice()) {
+                keys.insert(key.clone());
+            }
+            for arg in &call.args {
+                collect_expr(arg, keys);
+            }
+        }
+        Expr::BinaryOp { left, right, .. } | Expr::Chain { left, right, .. } => {
+            collect_expr(left, keys);
+            collect_expr(right, keys);
+        }
+        Expr::Where { table: left, condition: right, .. }
+        | Expr::GroupBy { table: left, having: right, .. }
+        | Expr::Index { object: left, index: right, .. } => {
+            collect_expr(left, keys);
+            collect_expr(right, keys);
+        }
+        Expr::UnaryOp { operand: inner, .. }
+        | Expr::Lambda { body: inner, .. }
+        | Expr::FieldAccess { object: inner, .. }
+        | Expr::SortBy { table: inner, .. }
+        | Expr::ColumnSelect { table: inner, .. }
+        | Expr::Rename { table: inner, .. }
+        | Expr::DropColumns { table: inner, .. } => collect_expr(inner, keys),
+        Expr::TableLiteral(fields) => {
+            for (_, value) in fields {
+                collect_expr(value, keys);
+            }
+        }
+        Expr::ArrayLiteral(items) => {
+            for item in items {
+                collect_expr(item, keys);
+            }
+        }
+        Expr::Between { value, low, high, .. } => {
+            collect_expr(value, keys);
+            collect_expr(low, keys);
+            collect_expr(high, keys);
+        }
+        Expr::Match { value, arms, .. } => {
+            collect_expr(value, keys);
+            for arm in arms {
+                collect_expr(&arm.body, keys);
+            }
+        }
+        Expr::IntLiteral(_) | Expr::FloatLiteral(_) | Expr::StringLiteral(_) | Expr::BoolLiteral(_) | Expr::DateLiteral(_)
+        | Expr::CurrencyLiteral { .. }
+        | Expr::Identifier(_) | Expr::FilterLiteral(_) => {}
+    }
+}
+
+/// A locale file's text with an empty entry appended for each missing key
+pub fn add_missing_keys(text: &str, missing: &[&String]) -> String {
+    let mut text = text.to_string();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    for key in missing {
+        text.push_str(&format!("{} = \"\"\n", quote_key(key)));
+    }
+    text
+}
+
+/// Keys other than letters, digits, `_`, `-` and `.` are written quoted; a
+/// dotted key reads back the same as under a `[section]` header
+fn quote_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        key.to_string()
+    } else {
+        format!("\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{Lexer, Parser};
+
+    #[test]
+    fn test_parse_locale() {
+        let text = "# French\nwelcome = \"Bienvenue\"\n\"total.label\" = \"Total : {total}\"  # shown under the table\n\n[orders]\ntitle = \"Commandes \\\"ouvertes\\\"\"\n";
+        let entries = parse_locale(text).unwrap();
+        assert_eq!(entries["welcome"], "Bienvenue");
+        assert_eq!(entries["total.label"], "Total : {total}");
+        assert_eq!(entries["orders.title"], "Commandes \"ouvertes\"");
+        assert_eq!(parse_locale("welcome = Bienvenue").unwrap_err(), "line 1: the text of 'welcome' must be a double-quoted string");
+    }
+
+    #[test]
+    fn test_translation_keys_and_missing_entries() {
+        let source = "page Home {\n  title t(\"home.title\")\n  text \"Plain\"\n  section t(\"orders\") {\n    let label = t(\"total label\")\n    text \"{label}\"\n  }\n}\n";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let keys = translation_keys(&program);
+        assert_eq!(keys.iter().collect::<Vec<_>>(), ["home.title", "orders", "total label"]);
+
+        let existing = "orders = \"Commandes\"";
+        let missing: Vec<&String> = keys.iter().filter(|key| *key != "orders").collect();
+        let updated = add_missing_keys(existing, &missing);
+        assert_eq!(updated, "orders = \"Commandes\"\nhome.title = \"\"\n\"total label\" = \"\"\n");
+        assert_eq!(parse_locale(&updated).unwrap().len(), 3);
+    }
+}
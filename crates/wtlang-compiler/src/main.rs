@@ -1,11 +1,37 @@
+mod backend;
 mod codegen_legacy;
+mod completions;
+mod diff;
+mod erd;
+mod i18n;
+mod migrate;
+mod mockdata;
+mod output;
+mod project;
+mod requirements;
+mod schema;
+mod timings;
+mod upgrade;
+mod validation;
 
-use wtlang_core::{Lexer, Parser, SemanticAnalyzer};
+use wtlang_core::{Lexer, LanguageVersion, Parser, LoadedProgram, ModuleLoader, PluginRegistry, Program, ProgramItem, Materialize, Logger, SemanticAnalyzer, IRBuilder, IRModule, Interpreter, Directives, DiagnosticBag, ErrorCode, Level, LintLevels, Severity, EvalError, apply_fixes, eval_const};
 use codegen_legacy as codegen;
-use clap::{Parser as ClapParser, Subcommand};
+use clap::{ArgAction, Args, CommandFactory, Parser as ClapParser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use anyhow::{Result, Context};
+use timings::Timings;
+use tracing::level_filters::LevelFilter;
+
+// Exit codes of `wtc check`, which CI scripts rely on
+const EXIT_OK: u8 = 0;
+const EXIT_ERRORS: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_INTERNAL: u8 = 3;
 
 #[derive(ClapParser)]
 #[command(name = "wtc")]
@@ -13,6 +39,99 @@ use anyhow::{Result, Context};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    
+    /// Log each stage of a build as it finishes, with what it produced;
+    /// repeat (-vv) to also log the program's items and the files written.
+    /// `WTLANG_LOG` takes precedence, e.g. `WTLANG_LOG=debug`
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+/// Warning level flags shared by `build` and `check`
+///
+/// Each takes `warnings`, a lint group such as `unused`, or a warning code.
+#[derive(Args)]
+struct LintArgs {
+    /// Report these warnings as errors
+    #[arg(short = 'D', long = "deny", value_name = "LINT")]
+    deny: Vec<String>,
+    
+    /// Report these as warnings
+    #[arg(short = 'W', long = "warn", value_name = "LINT")]
+    warn: Vec<String>,
+    
+    /// Do not report these warnings
+    #[arg(short = 'A', long = "allow", value_name = "LINT")]
+    allow: Vec<String>,
+}
+
+impl LintArgs {
+    fn levels(&self) -> Result<LintLevels> {
+        let mut levels = LintLevels::new();
+        let flags = [(&self.allow, Level::Allow), (&self.warn, Level::Warn), (&self.deny, Level::Deny)];
+        for (selectors, level) in flags {
+            for selector in selectors {
+                levels.set(selector, level).map_err(|e| anyhow::anyhow!(e))?;
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// What `build` generates besides the pages
+#[derive(Args)]
+struct CodegenArgs {
+    /// Also compile `test` blocks into a pytest module
+    #[arg(long)]
+    with_tests: bool,
+    
+    /// Log the duration and row count of every table operation
+    #[arg(long)]
+    trace: bool,
+    
+    /// Show the duration and row count of every table operation in a
+    /// Performance section at the end of each page
+    #[arg(long)]
+    profile_instrument: bool,
+    
+    /// Annotate functions, variables and table helpers with Python type
+    /// hints, for mypy and IDEs
+    #[arg(long)]
+    type_hints: bool,
+    
+    /// Like --type-hints, with a pandera schema per table that table
+    /// values are annotated with
+    #[arg(long)]
+    pandera: bool,
+    
+    /// Oldest Python version the app must run on; newer ones let the code
+    /// use `match` statements and nested f-strings
+    #[arg(long, value_enum, value_name = "VERSION", default_value = "3.8")]
+    python: codegen::PythonVersion,
+}
+
+impl CodegenArgs {
+    fn type_hints(&self) -> codegen::TypeHints {
+        if self.pandera {
+            codegen::TypeHints::Pandera
+        } else if self.type_hints {
+            codegen::TypeHints::Plain
+        } else {
+            codegen::TypeHints::None
+        }
+    }
+}
+
+/// How `build` treats the output directory
+#[derive(Args)]
+struct OutputArgs {
+    /// Print a diff of what the build would change instead of writing files
+    #[arg(long)]
+    dry_run: bool,
+    
+    /// Overwrite generated files even if they were edited by hand
+    #[arg(long)]
+    force: bool,
 }
 
 #[derive(Subcommand)]
@@ -25,139 +144,1223 @@ enum Commands {
         /// Output directory
         #[arg(short, long, default_value = "output")]
         output: PathBuf,
+        
+        #[command(flatten)]
+        codegen: CodegenArgs,
+        
+        #[command(flatten)]
+        output_args: OutputArgs,
+        
+        #[command(flatten)]
+        lints: LintArgs,
+        
+        /// Print how long each stage of the build took
+        #[arg(long)]
+        timings: bool,
     },
     
     /// Check WTLang source for errors without generating code
+    ///
+    /// Exits with 0 when no errors are found, 1 when any file has errors,
+    /// 2 on invalid arguments or unreadable files and 3 on an internal
+    /// compiler error.
     Check {
-        /// Input WTLang source file
+        /// Input WTLang source files, or `-` to read from stdin
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        
+        /// Only print diagnostics, without progress messages or the summary
+        #[arg(short, long)]
+        quiet: bool,
+        
+        /// Stop printing errors after this many have been reported
+        #[arg(long, value_name = "N")]
+        max_errors: Option<usize>,
+        
+        /// Rewrite the files with the fixes suggested by diagnostics, such as
+        /// parentheses around ambiguous operators or `;` between statements
+        #[arg(long)]
+        fix: bool,
+        
+        #[command(flatten)]
+        lints: LintArgs,
+    },
+    
+    /// Type-check a single expression and evaluate it if it is constant
+    Eval {
+        /// Expression to evaluate, or `-` to read it from stdin
+        expr: String,
+        
+        /// WTLang source file whose tables and functions are in scope
+        #[arg(short, long)]
+        project: Option<PathBuf>,
+    },
+    
+    /// Generate fake CSV data for a table, to demo pages without real data
+    ///
+    /// Tables the table refers to are generated too, so references point at
+    /// existing keys.
+    Mockdata {
+        /// Table to generate rows for
+        table: String,
+        
+        /// WTLang source file defining the table
+        #[arg(short, long)]
+        project: PathBuf,
+        
+        /// Number of rows to generate
+        #[arg(long, default_value_t = 100)]
+        rows: usize,
+        
+        /// Output directory for the CSV files
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        
+        /// Seed for the random generator; the same seed gives the same data
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    
+    /// Remove the files generated by the last build in an output directory
+    ///
+    /// Only files recorded in the directory's lockfile are removed.
+    Clean {
+        /// Output directory
+        #[arg(short, long, default_value = "output")]
+        output: PathBuf,
+    },
+    
+    /// Check that generated files have not been edited since they were built
+    Verify {
+        /// Output directory
+        #[arg(short, long, default_value = "output")]
+        output: PathBuf,
+    },
+    
+    /// Add the program's untranslated `t("key")` keys to its locale files
+    ///
+    /// Locale files are `locales/<language>.toml` next to the source file;
+    /// each missing key is added with empty text for translators to fill in.
+    Locales {
+        /// WTLang source file
+        input: PathBuf,
+        
+        /// Also create the locale file of this language
+        #[arg(long = "add", value_name = "LANGUAGE")]
+        add: Vec<String>,
+    },
+    
+    /// Show how the tables of a program changed between two versions of it
+    ///
+    /// Lists added, removed and retyped fields and changed constraints of
+    /// each table, and tables only one version defines.
+    SchemaDiff {
+        /// Old version of the WTLang source file
+        old: PathBuf,
+        
+        /// New version of the WTLang source file
+        new: PathBuf,
+    },
+    
+    /// Generate a Python script that migrates CSV data to changed table definitions
+    ///
+    /// Renamed columns are renamed, removed ones dropped, added ones filled
+    /// with a default and retyped ones converted, reporting values that do
+    /// not convert.
+    Migrate {
+        /// Old version of the WTLang source file
+        old: PathBuf,
+        
+        /// New version of the WTLang source file
+        new: PathBuf,
+        
+        /// Field renamed between the versions, as `Table.old=new`
+        #[arg(long = "rename", value_name = "TABLE.OLD=NEW")]
+        renames: Vec<String>,
+        
+        /// Output file for the script
+        #[arg(short, long, default_value = "migrate.py")]
+        output: PathBuf,
+    },
+    
+    /// Generate files that validate CSV data against the table definitions
+    ///
+    /// For data-quality pipelines outside the app: a module of pandera
+    /// schemas, or a Great Expectations suite per table.
+    ExportValidation {
+        /// WTLang source file
+        input: PathBuf,
+        
+        /// Kind of validation files
+        #[arg(long, value_enum, default_value = "pandera")]
+        format: validation::Format,
+        
+        /// Output directory
+        #[arg(short, long, default_value = "validation")]
+        output: PathBuf,
+    },
+    
+    /// Draw the tables and their references as an entity-relationship diagram
+    ///
+    /// Each table with its fields, keys and unique fields, and a
+    /// relationship for every `ref` field and `references` constraint.
+    Erd {
+        /// WTLang source file
+        input: PathBuf,
+        
+        /// Kind of diagram
+        #[arg(long, value_enum, default_value = "mermaid")]
+        format: erd::Format,
+        
+        /// Output file; the diagram is printed when not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Export the table definitions as a JSON Schema or an OpenAPI document
+    ///
+    /// For tools outside WTLang: each table's fields with their types,
+    /// required fields, keys, unique constraints and references.
+    ExportSchema {
+        /// WTLang source file
         input: PathBuf,
+        
+        /// Kind of document
+        #[arg(long, value_enum, default_value = "jsonschema")]
+        format: schema::Format,
+        
+        /// Output file; the document is printed when not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Upgrade source files to a newer language version, in place
+    ///
+    /// Rewrites the syntax the newer version reads differently, such as
+    /// names that became keywords, and raises the `#version` line or the
+    /// `language_version` of wt.toml.
+    Upgrade {
+        /// WTLang source files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        
+        /// Version the files are written for, when they do not pin one
+        #[arg(long, value_parser = parse_language_version)]
+        from: Option<LanguageVersion>,
+        
+        /// Version to upgrade to
+        #[arg(long, value_parser = parse_language_version, default_value_t = LanguageVersion::CURRENT)]
+        to: LanguageVersion,
+        
+        /// Print a diff of what the upgrade would change instead of writing files
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Explain an error code, or list all codes when none is given
+    Explain {
+        /// Error code to explain (e.g. E3007)
+        code: Option<String>,
+        
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
     },
+    
+    /// Print a shell completion script for wtc
+    ///
+    /// For bash, add `source <(wtc completions bash)` to ~/.bashrc; for
+    /// zsh, save the script as `_wtc` in a directory of $fpath.
+    Completions {
+        /// Shell to complete in
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+    
+    /// Print the man page of wtc, e.g. `wtc man > wtc.1`
+    Man,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
     
-    match cli.command {
-        Commands::Build { input, output } => {
-            build_command(input, output)?;
+    let default_level = match cli.verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    match Logger::from_env(default_level) {
+        Ok(logger) => logger.install(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+    
+    let result = match cli.command {
+        Commands::Build { input, output, codegen, output_args, lints, timings } => {
+            lints.levels().and_then(|levels| build_command(input, output, codegen, output_args, levels, timings))
         },
-        Commands::Check { input } => {
-            check_command(input)?;
+        Commands::Check { inputs, quiet, max_errors, fix, lints } => {
+            let levels = match lints.levels() {
+                Ok(levels) => levels,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::from(EXIT_USAGE);
+                }
+            };
+            return ExitCode::from(check_command(inputs, levels, quiet, max_errors, fix));
         },
-    }
+        Commands::Eval { expr, project } => eval_command(expr, project),
+        Commands::Mockdata { table, project, rows, output, seed } => {
+            mockdata_command(table, project, rows, output, seed)
+        },
+        Commands::Clean { output } => clean_command(output),
+        Commands::Verify { output } => verify_command(output),
+        Commands::Locales { input, add } => locales_command(input, add),
+        Commands::SchemaDiff { old, new } => schema_diff_command(old, new),
+        Commands::Migrate { old, new, renames, output } => migrate_command(old, new, renames, output),
+        Commands::ExportValidation { input, format, output } => export_validation_command(input, format, output),
+        Commands::ExportSchema { input, format, output } => export_schema_command(input, format, output),
+        Commands::Erd { input, format, output } => erd_command(input, format, output),
+        Commands::Upgrade { inputs, from, to, dry_run } => upgrade_command(inputs, from, to, dry_run),
+        Commands::Explain { code, json } => explain_command(code, json),
+        Commands::Completions { shell } => {
+            print!("{}", completions::completions(&cli_command(), shell));
+            Ok(())
+        },
+        Commands::Man => {
+            print!("{}", completions::man_page(&cli_command(), output::COMPILER_VERSION));
+            Ok(())
+        },
+    };
     
-    Ok(())
+    match result {
+        Ok(()) => ExitCode::from(EXIT_OK),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_ERRORS)
+        }
+    }
 }
 
-fn build_command(input: PathBuf, output: PathBuf) -> Result<()> {
+fn build_command(
+    input: PathBuf,
+    output: PathBuf,
+    options: CodegenArgs,
+    output_args: OutputArgs,
+    levels: LintLevels,
+    show_timings: bool,
+) -> Result<()> {
     println!("Compiling {} to {}", input.display(), output.display());
+    let mut timings = Timings::new();
     
     // Read source file
     let source = fs::read_to_string(&input)
         .with_context(|| format!("Failed to read input file: {}", input.display()))?;
     
-    // Lexical analysis
-    let mut lexer = Lexer::new(&source);
-    let tokens = lexer.tokenize()
-        .map_err(|diag| {
-            eprintln!("\nLexical errors found:\n{}", diag.format_all());
-            anyhow::anyhow!("Lexical analysis failed")
-        })?;
-    
-    // Parsing
-    let mut parser = Parser::new(tokens);
-    let program = parser.parse()
+    // Lexical analysis and parsing of the file and those it imports, as
+    // the language version the project pins. Lexical errors are reported
+    // with the errors of later stages
+    let project_file = project::project_file(&input);
+    let version = project::load_language_version(&project_file)?;
+    let plugins = project::load_builtins(&project_file)?;
+    let loaded = timings.time("parse", || ModuleLoader::new().with_language_version(version).load(&input, &source))
         .map_err(|diag| {
             eprintln!("\nSyntax errors found:\n{}", diag.format_all());
             anyhow::anyhow!("Parsing failed")
         })?;
+    let program = &loaded.program;
+    timings.detail(format!("{} items, {} statements", program.items.len(), timings::statement_count(program)));
     
     println!("Successfully parsed {} items", program.items.len());
+    for item in &program.items {
+        tracing::debug!("{}", timings::item_summary(item));
+    }
     
-    // Semantic analysis
-    let mut analyzer = SemanticAnalyzer::new();
-    if let Err(errors) = analyzer.analyze(&program) {
-        eprintln!("\nSemantic errors found:");
-        for error in &errors {
-            eprintln!("  - {}", error);
-        }
-        return Err(anyhow::anyhow!("Semantic analysis failed with {} error(s)", errors.len()));
+    // Semantic analysis and the backend's capabilities, honoring the
+    // allow/expect-error comments of each file
+    let mut analyzer = SemanticAnalyzer::new().with_plugins(plugins.clone());
+    let (diagnostics, found_errors) = timings.time("semantics", || {
+        let _ = analyzer.analyze(program);
+        let mut diagnostics = loaded.diagnostics.clone();
+        diagnostics.extend(analyzer.diagnostics());
+        diagnostics.extend(backend::Backend::Streamlit.check(program));
+        let found_errors = diagnostics.error_count();
+        (levels.apply(&loaded.apply_directives(&loaded.locate(&diagnostics))), found_errors)
+    });
+    timings.detail(format!("{} diagnostics", diagnostics.diagnostics().len()));
+    if loaded.diagnostics.has_errors() {
+        eprintln!("\nLexical errors found:\n{}", diagnostics.format_all());
+        return Err(anyhow::anyhow!("Lexical analysis failed"));
+    }
+    if diagnostics.has_errors() {
+        eprintln!("\nSemantic errors found:\n{}", diagnostics.format_all());
+        return Err(anyhow::anyhow!("Semantic analysis failed with {} error(s)", diagnostics.error_count()));
+    }
+    if !diagnostics.is_empty() {
+        eprintln!("\n{}", diagnostics.format_all());
+    }
+    // Comments silence errors for `wtc check`, but code cannot be generated
+    // for a program that has them
+    if found_errors > 0 {
+        return Err(anyhow::anyhow!(
+            "Semantic analysis found {} error(s) that allow/expect-error comments suppress; fix them to build",
+            found_errors
+        ));
     }
     
     println!("[OK] Semantic analysis passed");
     
     // Code generation
-    let mut codegen = codegen::CodeGenerator::new();
-    let output_files = codegen.generate(&program)
+    // Translations of `t("key")` text
+    let locales_dir = i18n::locales_dir(&input);
+    let locales = i18n::load_locales(&locales_dir)?;
+    let translation_keys = i18n::translation_keys(program);
+    if !translation_keys.is_empty() && locales.is_empty() {
+        tracing::warn!("{} has no locale files; translated text shows its key", locales_dir.display());
+    }
+    for (language, entries) in &locales {
+        let missing = translation_keys.iter()
+            .filter(|key| entries.get(*key).is_none_or(|text| text.is_empty()))
+            .count();
+        if missing > 0 {
+            tracing::warn!(
+                "{}.toml has no translation for {} key(s); `wtc locales {}` adds the missing ones",
+                locales_dir.join(language).display(), missing, input.display()
+            );
+        }
+    }
+    
+    // Number, date and currency formats of the project
+    let formats = project::load_formats(&project_file)?;
+    
+    let mut codegen = codegen::CodeGenerator::new()
+        .with_tests(options.with_tests)
+        .with_trace(options.trace)
+        .with_profile(options.profile_instrument)
+        .with_type_hints(options.type_hints())
+        .with_python(options.python)
+        .with_locales(locales.clone())
+        .with_formats(formats)
+        .with_plugins(plugins)
+        .with_source_file(input.file_name().map_or_else(|| input.display().to_string(), |name| name.to_string_lossy().into_owned()))
+        .with_imported_files(imported_files(&input, &loaded));
+    let mut output_files = timings.time("codegen", || codegen.generate(program))
         .map_err(|e| anyhow::anyhow!("Code generation error: {}", e))?;
+    timings.detail(format!("{} files, {} bytes", output_files.len(), output_files.values().map(String::len).sum::<usize>()));
+    
+    // Exactly the packages the app uses, at the versions the project pins
+    let pinned = project::load_requirements(&project_file)?;
+    let requirements = requirements::requirements_txt(codegen.runtime_features(), options.with_tests, options.pandera, &pinned);
+    output_files.insert("requirements.txt".to_string(), requirements);
+    
+    // Every file names the compiler and source it came from
+    let source_name = input.file_name().map_or_else(|| input.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let source_hash = output::content_hash(&source);
+    let header = output::header(&source_name, &source_hash);
+    for code in output_files.values_mut() {
+        code.insert_str(0, &header);
+    }
+    
+    // Tables materialized on build are computed now, from the data files
+    // next to the source, or in the config block's data directory, and
+    // written to that directory of the app, where the runtime reads them
+    let mut data_dir = input.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let mut app_data_dir = PathBuf::new();
+    for item in &program.items {
+        if let ProgramItem::Config(config) = item {
+            if let Some(dir) = config.settings.iter().find(|s| s.name == "data_dir") {
+                data_dir = data_dir.join(&dir.value);
+                app_data_dir = PathBuf::from(&dir.value);
+            }
+        }
+    }
+    let mut interpreter = Interpreter::new(program).with_base_dir(data_dir);
+    let materialized = timings.time("materialize", || -> Result<usize> {
+        let mut materialized = 0;
+        for item in &program.items {
+            if let ProgramItem::DerivedTable(derived) = item {
+                if derived.materialize == Materialize::OnBuild {
+                    let csv = interpreter.derived_table_csv(&derived.name)
+                        .map_err(|e| anyhow::anyhow!("Failed to materialize {}: {}", derived.name, e))?;
+                    let file = app_data_dir.join(codegen::materialized_file_name(derived.name));
+                    output_files.insert(file.to_string_lossy().into_owned(), csv);
+                    materialized += 1;
+                }
+            }
+        }
+        Ok(materialized)
+    })?;
+    timings.detail(format!("{} tables", materialized));
+    
+    let stale = output::stale_files(&output, &output_files)?;
+    let edited = hand_edited_files(&output, &output_files)?;
+    if output_args.dry_run {
+        return print_build_diff(&output, &output_files, &stale, &edited);
+    }
+    
+    // Refuse to lose fixes made directly in the generated code
+    if !edited.is_empty() {
+        for filename in &edited {
+            let path = output.join(filename);
+            let existing = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let new_name = output_files.get(filename).map_or("/dev/null".to_string(), |_| format!("b/{}", filename));
+            let new = output_files.get(filename).map_or("", String::as_str);
+            if output_args.force {
+                println!("Overwriting hand-edited: {}", path.display());
+            } else {
+                eprintln!("\nhand-edited: {}", path.display());
+                eprint!("{}", diff::unified_diff(&existing, new, &format!("a/{}", filename), &new_name));
+            }
+        }
+        if !output_args.force {
+            return Err(anyhow::anyhow!(
+                "{} generated file(s) were edited by hand since the last build; move the changes into the source, or pass --force to overwrite them",
+                edited.len()
+            ));
+        }
+    }
     
     // Create output directory
     fs::create_dir_all(&output)
         .with_context(|| format!("Failed to create output directory: {}", output.display()))?;
     
     // Write output files
-    for (filename, code) in output_files {
-        let output_path = output.join(&filename);
-        fs::write(&output_path, code)
-            .with_context(|| format!("Failed to write output file: {}", output_path.display()))?;
-        println!("Generated: {}", output_path.display());
+    timings.time("write", || -> Result<()> {
+        for (filename, code) in &output_files {
+            let output_path = output.join(filename);
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create output directory: {}", parent.display()))?;
+            }
+            fs::write(&output_path, code)
+                .with_context(|| format!("Failed to write output file: {}", output_path.display()))?;
+            println!("Generated: {}", output_path.display());
+        }
+        Ok(())
+    })?;
+    timings.detail(format!("{} files", output_files.len()));
+    for (filename, code) in &output_files {
+        tracing::debug!(bytes = code.len(), "wrote {}", filename);
     }
     
-    // Generate requirements.txt
-    let requirements = "streamlit>=1.28.0\npandas>=2.0.0\nopenpyxl>=3.1.0\n";
-    let req_path = output.join("requirements.txt");
-    fs::write(&req_path, requirements)
-        .with_context(|| format!("Failed to write requirements.txt: {}", req_path.display()))?;
-    println!("Generated: {}", req_path.display());
+    // Remove what earlier builds generated but this one no longer does
+    for file in output::remove_files(&output, &stale)? {
+        println!("Removed stale: {}", output.join(file).display());
+    }
+    let python = options.python.to_possible_value().map(|value| value.get_name().to_string());
+    let backend_options = BTreeMap::from([
+        ("with_tests".to_string(), options.with_tests.into()),
+        ("trace".to_string(), options.trace.into()),
+        ("profile_instrument".to_string(), options.profile_instrument.into()),
+        ("type_hints".to_string(), options.type_hints.into()),
+        ("pandera".to_string(), options.pandera.into()),
+        ("python".to_string(), python.into()),
+    ]);
+    let mut sources: BTreeMap<String, String> = loaded.files.iter()
+        .map(|file| (file.path.display().to_string(), output::content_hash(&file.source)))
+        .collect();
+    if !translation_keys.is_empty() {
+        for language in locales.keys() {
+            let path = locales_dir.join(format!("{}.toml", language));
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            sources.insert(path.display().to_string(), output::content_hash(&content));
+        }
+    }
+    if project_file.exists() {
+        let content = fs::read_to_string(&project_file)
+            .with_context(|| format!("Failed to read {}", project_file.display()))?;
+        sources.insert(project_file.display().to_string(), output::content_hash(&content));
+    }
+    output::Lock::new(sources, backend_options, &output_files).write(&output)?;
+    
+    if show_timings {
+        print!("{}", timings.report());
+    }
     
     println!("\n[OK] Compilation successful!");
     println!("\nTo run your application:");
     println!("  cd {}", output.display());
     println!("  pip install -r requirements.txt");
     println!("  streamlit run <PageName>.py");
+    if options.with_tests {
+        println!("\nTo run the tests:");
+        println!("  pytest");
+    }
     
     Ok(())
 }
 
-fn check_command(input: PathBuf) -> Result<()> {
-    println!("Checking {} for errors", input.display());
+/// Files imported by `input`, named relative to its directory, and the
+/// numbers of their items
+fn imported_files(input: &Path, loaded: &LoadedProgram) -> Vec<(String, std::ops::Range<u32>)> {
+    let directory = input.parent().unwrap_or(Path::new(""));
+    loaded.files.iter()
+        .filter(|file| file.path != input)
+        .map(|file| {
+            let name = file.path.strip_prefix(directory).unwrap_or(&file.path);
+            (name.display().to_string(), file.items.clone())
+        })
+        .collect()
+}
+
+/// Generated files edited since the last build whose changes this build
+/// would overwrite or remove
+fn hand_edited_files(output: &Path, files: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    let Some(lock) = output::Lock::read(output)? else {
+        return Ok(Vec::new());
+    };
+    let (modified, _) = lock.verify(output)?;
+    let mut edited = Vec::new();
+    for filename in modified {
+        let path = output.join(&filename);
+        let existing = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if files.get(&filename) != Some(&existing) {
+            edited.push(filename);
+        }
+    }
+    Ok(edited)
+}
+
+/// Print how the generated files differ from those in the output
+/// directory, without writing anything
+fn print_build_diff(output: &Path, files: &BTreeMap<String, String>, stale: &[String], edited: &[String]) -> Result<()> {
+    let (mut added, mut changed, mut unchanged) = (0, 0, 0);
+    for (filename, code) in files {
+        let path = output.join(filename);
+        let new_name = format!("b/{}", filename);
+        if !path.exists() {
+            added += 1;
+            println!("\nnew file: {}", path.display());
+            print!("{}", diff::unified_diff("", code, "/dev/null", &new_name));
+            continue;
+        }
+        
+        let existing = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let diff = diff::unified_diff(&existing, code, &format!("a/{}", filename), &new_name);
+        if diff.is_empty() {
+            unchanged += 1;
+        } else {
+            changed += 1;
+            let label = if edited.contains(filename) { "changed (hand-edited)" } else { "changed" };
+            println!("\n{}: {}", label, path.display());
+            print!("{}", diff);
+        }
+    }
     
-    // Read source file
-    let source = fs::read_to_string(&input)
-        .with_context(|| format!("Failed to read input file: {}", input.display()))?;
+    for filename in stale {
+        let path = output.join(filename);
+        let existing = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let label = if edited.contains(filename) { "removed (hand-edited)" } else { "removed" };
+        println!("\n{}: {}", label, path.display());
+        print!("{}", diff::unified_diff(&existing, "", &format!("a/{}", filename), "/dev/null"));
+    }
     
-    // Lexical analysis
-    let mut lexer = Lexer::new(&source);
-    let tokens = lexer.tokenize()
+    println!("\n[DRY RUN] {} new, {} changed, {} removed, {} unchanged file(s); nothing was written",
+        added, changed, stale.len(), unchanged);
+    if !edited.is_empty() {
+        println!("[DRY RUN] {} of them edited by hand; building would need --force", edited.len());
+    }
+    Ok(())
+}
+
+fn locales_command(input: PathBuf, add: Vec<String>) -> Result<()> {
+    let program = parse_project(&input)?;
+    let keys = i18n::translation_keys(&program);
+    let dir = i18n::locales_dir(&input);
+    let mut locales = i18n::load_locales(&dir)?;
+    for language in add {
+        locales.entry(language).or_default();
+    }
+    if locales.is_empty() {
+        println!("No locale files in {}; create one with --add <language>", dir.display());
+        return Ok(());
+    }
+    
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for (language, entries) in &locales {
+        let path = dir.join(format!("{}.toml", language));
+        let missing: Vec<&String> = keys.iter().filter(|key| !entries.contains_key(*key)).collect();
+        let unused = entries.keys().filter(|key| !keys.contains(*key)).count();
+        if !missing.is_empty() || !path.exists() {
+            let text = if path.exists() {
+                fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?
+            } else {
+                String::new()
+            };
+            fs::write(&path, i18n::add_missing_keys(&text, &missing))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        println!("{}: added {} key(s)", path.display(), missing.len());
+        if unused > 0 {
+            println!("  note: {} key(s) are no longer used by {}", unused, input.display());
+        }
+    }
+    Ok(())
+}
+
+fn schema_diff_command(old: PathBuf, new: PathBuf) -> Result<()> {
+    let old_module = lower_project(&old)?;
+    let new_module = lower_project(&new)?;
+    
+    let mut changed = 0;
+    for name in old_module.table_names() {
+        let old_schema = old_module.find_table(name).expect("listed table");
+        match new_module.find_table(name) {
+            None => println!("table {}: removed", name),
+            Some(new_schema) => {
+                let diff = old_schema.diff(new_schema);
+                if diff.is_empty() {
+                    continue;
+                }
+                println!("table {}:", name);
+                for line in diff.to_string().lines() {
+                    println!("  {}", line);
+                }
+            }
+        }
+        changed += 1;
+    }
+    for name in new_module.table_names() {
+        if old_module.find_table(name).is_none() {
+            println!("table {}: added", name);
+            changed += 1;
+        }
+    }
+    
+    if changed == 0 {
+        println!("[OK] No table changed between {} and {}", old.display(), new.display());
+    }
+    Ok(())
+}
+
+fn migrate_command(old: PathBuf, new: PathBuf, renames: Vec<String>, output: PathBuf) -> Result<()> {
+    let renames = renames.iter()
+        .map(|rename| migrate::Rename::parse(rename))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let old_module = lower_project(&old)?;
+    let new_module = lower_project(&new)?;
+    
+    let migrations = migrate::plan(&old_module, &new_module, &renames).map_err(|e| anyhow::anyhow!(e))?;
+    if migrations.is_empty() {
+        println!("[OK] No CSV column changed between {} and {}; nothing to migrate", old.display(), new.display());
+        return Ok(());
+    }
+    for migration in &migrations {
+        println!("table {}: {} renamed, {} dropped, {} added, {} retyped column(s)",
+            migration.table, migration.renamed.len(), migration.dropped.len(),
+            migration.added.len(), migration.retyped.len());
+    }
+    
+    let source = new.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    fs::write(&output, migrate::script(&migrations, &source))
+        .with_context(|| format!("Failed to write output file: {}", output.display()))?;
+    println!("Generated: {}", output.display());
+    Ok(())
+}
+
+fn export_validation_command(input: PathBuf, format: validation::Format, output: PathBuf) -> Result<()> {
+    let ir_module = lower_project(&input)?;
+    if ir_module.table_names().is_empty() {
+        println!("[OK] {} defines no table; nothing to validate", input.display());
+        return Ok(());
+    }
+    
+    let source = input.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let files = match format {
+        validation::Format::Pandera => {
+            vec![(validation::PANDERA_MODULE.to_string(), validation::pandera_module(&ir_module, &source))]
+        }
+        validation::Format::GreatExpectations => ir_module.table_names().into_iter()
+            .filter_map(|name| ir_module.find_table(name))
+            .map(|schema| (validation::suite_file_name(&schema.name), validation::expectation_suite(schema, &ir_module, &source)))
+            .collect(),
+    };
+    
+    fs::create_dir_all(&output)
+        .with_context(|| format!("Failed to create output directory: {}", output.display()))?;
+    for (name, content) in files {
+        let path = output.join(name);
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write output file: {}", path.display()))?;
+        println!("Generated: {}", path.display());
+    }
+    Ok(())
+}
+
+fn export_schema_command(input: PathBuf, format: schema::Format, output: Option<PathBuf>) -> Result<()> {
+    let ir_module = lower_project(&input)?;
+    let source = input.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let document = serde_json::to_string_pretty(&schema::export(&ir_module, format, &source))?;
+    match output {
+        Some(output) => {
+            fs::write(&output, format!("{}\n", document))
+                .with_context(|| format!("Failed to write output file: {}", output.display()))?;
+            println!("Generated: {}", output.display());
+        }
+        None => println!("{}", document),
+    }
+    Ok(())
+}
+
+fn erd_command(input: PathBuf, format: erd::Format, output: Option<PathBuf>) -> Result<()> {
+    let ir_module = lower_project(&input)?;
+    let diagram = erd::diagram(&ir_module, format);
+    match output {
+        Some(output) => {
+            fs::write(&output, diagram)
+                .with_context(|| format!("Failed to write output file: {}", output.display()))?;
+            println!("Generated: {}", output.display());
+        }
+        None => print!("{}", diagram),
+    }
+    Ok(())
+}
+
+fn parse_language_version(text: &str) -> Result<LanguageVersion, String> {
+    LanguageVersion::parse(text)
+        .ok_or_else(|| format!("unknown language version '{}'; the known versions are {}", text, LanguageVersion::known()))
+}
+
+fn upgrade_command(inputs: Vec<PathBuf>, from: Option<LanguageVersion>, to: LanguageVersion, dry_run: bool) -> Result<()> {
+    // Project files pinning an older version for files that pin none
+    let mut manifests = Vec::new();
+    let mut changed = 0;
+    for input in &inputs {
+        let source = fs::read_to_string(input)
+            .with_context(|| format!("Failed to read input file: {}", input.display()))?;
+        let manifest = project::project_file(input);
+        let pinned = upgrade::pinned_version(&source);
+        let manifest_version = project::load_language_version(&manifest)?;
+        let written_for = from.or(pinned).unwrap_or(manifest_version);
+        if written_for > to {
+            anyhow::bail!("{} is written for {}, which is newer than {}", input.display(), written_for, to);
+        }
+        if pinned.is_none() && manifest_version < to && !manifests.contains(&manifest) {
+            manifests.push(manifest);
+        }
+        
+        let upgraded = upgrade::upgrade(&source, written_for, to)
+            .map_err(|e| anyhow::anyhow!("Failed to upgrade {}:\n{}", input.display(), e))?;
+        if upgraded.source == source {
+            println!("Up to date: {}", input.display());
+            continue;
+        }
+        changed += 1;
+        if dry_run {
+            let name = input.display().to_string();
+            print!("{}", diff::unified_diff(&source, &upgraded.source, &format!("a/{}", name), &format!("b/{}", name)));
+        } else {
+            fs::write(input, &upgraded.source)
+                .with_context(|| format!("Failed to write {}", input.display()))?;
+            println!("Upgraded {} from {} to {}", input.display(), written_for, to);
+        }
+        for applied in &upgraded.applied {
+            println!("  {}: {} ({} change(s))", applied.migration.to, applied.migration.description, applied.changes);
+        }
+    }
+    
+    for manifest in &manifests {
+        let text = fs::read_to_string(manifest)
+            .with_context(|| format!("Failed to read {}", manifest.display()))?;
+        let upgraded = project::set_language_version(&text, to);
+        changed += 1;
+        if dry_run {
+            let name = manifest.display().to_string();
+            print!("{}", diff::unified_diff(&text, &upgraded, &format!("a/{}", name), &format!("b/{}", name)));
+        } else {
+            fs::write(manifest, upgraded)
+                .with_context(|| format!("Failed to write {}", manifest.display()))?;
+            println!("Upgraded {} to language_version {}", manifest.display(), to);
+        }
+    }
+    
+    if dry_run {
+        println!("\n[DRY RUN] {} file(s) would change; nothing was written", changed);
+    }
+    Ok(())
+}
+
+fn clean_command(output: PathBuf) -> Result<()> {
+    if !output.join(output::LOCK_FILE).exists() {
+        println!("Nothing to clean: {} has no {}", output.display(), output::LOCK_FILE);
+        return Ok(());
+    }
+    let removed = output::clean(&output)?;
+    for file in &removed {
+        println!("Removed: {}", output.join(file).display());
+    }
+    println!("\n[OK] Removed {} generated file(s)", removed.len());
+    Ok(())
+}
+
+fn verify_command(output: PathBuf) -> Result<()> {
+    let lock = output::Lock::read(&output)?
+        .with_context(|| format!("{} has no {}; build it first", output.display(), output::LOCK_FILE))?;
+    if lock.compiler != output::COMPILER_VERSION {
+        println!("note: built by wtc v{}, this is wtc v{}", lock.compiler, output::COMPILER_VERSION);
+    }
+    
+    let (modified, missing) = lock.verify(&output)?;
+    for file in &modified {
+        println!("modified: {}", output.join(file).display());
+    }
+    for file in &missing {
+        println!("missing: {}", output.join(file).display());
+    }
+    // Sources still where they were built from must match too
+    for (source, hash) in &lock.sources {
+        if let Ok(content) = fs::read_to_string(source) {
+            if output::content_hash(&content) != *hash {
+                println!("out of date: {} changed since the build", source);
+            }
+        }
+    }
+    
+    if !modified.is_empty() || !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} generated file(s) modified and {} missing; rebuild to restore them",
+            modified.len(), missing.len()
+        ));
+    }
+    println!("[OK] {} generated file(s) match {}", lock.files.len(), output::LOCK_FILE);
+    Ok(())
+}
+
+/// The command definitions, with clap's generated `--help` options
+fn cli_command() -> clap::Command {
+    let mut cmd = Cli::command();
+    cmd.build();
+    cmd
+}
+
+/// Read and parse a source file whose declarations a command uses, with
+/// the files it imports
+fn parse_project(project: &PathBuf) -> Result<Program> {
+    let source = read_source(project)?;
+    let version = project::load_language_version(&project::project_file(project))?;
+    let loaded = ModuleLoader::new().with_language_version(version).load(project, &source)
         .map_err(|diag| {
-            eprintln!("\nLexical errors found:\n{}", diag.format_all());
-            anyhow::anyhow!("Lexical analysis failed")
+            eprintln!("\nSyntax errors found in {}:\n{}", project.display(), diag.format_all());
+            anyhow::anyhow!("Parsing failed")
         })?;
+    if loaded.diagnostics.has_errors() {
+        eprintln!("\nLexical errors found in {}:\n{}", project.display(), loaded.diagnostics.format_all());
+        return Err(anyhow::anyhow!("Lexical analysis failed"));
+    }
+    Ok(loaded.program)
+}
+
+/// Parse, check and lower a source file whose tables a command uses
+fn lower_project(project: &PathBuf) -> Result<IRModule> {
+    let program = parse_project(project)?;
+    let plugins = project::load_builtins(&project::project_file(project))?;
+    if let Err(errors) = SemanticAnalyzer::new().with_plugins(plugins.clone()).analyze(&program) {
+        return Err(anyhow::anyhow!("{} has {} semantic error(s); run `wtc check` on it first", project.display(), errors.len()));
+    }
+    IRBuilder::new().with_plugins(plugins).build(&program)
+        .map_err(|e| anyhow::anyhow!("IR generation error: {}", e))
+}
+
+fn mockdata_command(table: String, project: PathBuf, rows: usize, output: PathBuf, seed: u64) -> Result<()> {
+    let ir_module = lower_project(&project)?;
+    
+    let tables = mockdata::MockDataGenerator::new(&ir_module, seed)
+        .generate(&table, rows)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    
+    fs::create_dir_all(&output)
+        .with_context(|| format!("Failed to create output directory: {}", output.display()))?;
+    for table in tables {
+        let path = output.join(table.file_name());
+        fs::write(&path, table.to_csv())
+            .with_context(|| format!("Failed to write output file: {}", path.display()))?;
+        println!("Generated: {} ({} rows)", path.display(), table.rows.len());
+    }
     
-    println!("[OK] Lexical analysis passed ({} tokens)", tokens.len());
+    Ok(())
+}
+
+/// Read a source file, or stdin when the path is `-`
+fn read_source(input: &PathBuf) -> Result<String> {
+    if input.as_os_str() == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)
+            .context("Failed to read from stdin")?;
+        return Ok(source);
+    }
+    fs::read_to_string(input)
+        .with_context(|| format!("Failed to read input file: {}", input.display()))
+}
+
+/// Outcome of checking one file
+struct FileReport {
+    name: String,
+    errors: usize,
+    warnings: usize,
+}
+
+fn check_command(inputs: Vec<PathBuf>, levels: LintLevels, quiet: bool, max_errors: Option<usize>, fix: bool) -> u8 {
+    let mut exit_code = EXIT_OK;
+    let mut reports = Vec::new();
+    let mut errors_shown = 0;
+    let mut errors_hidden = 0;
+    
+    for input in inputs {
+        let name = if input.as_os_str() == "-" { "<stdin>".to_string() } else { input.display().to_string() };
+        if !quiet {
+            println!("Checking {} for errors", name);
+        }
+        
+        let source = match read_source(&input) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                exit_code = exit_code.max(EXIT_USAGE);
+                continue;
+            }
+        };
+        
+        // Source read from stdin belongs to no project
+        let settings = if input.as_os_str() == "-" {
+            Ok((LanguageVersion::CURRENT, PluginRegistry::new()))
+        } else {
+            let project_file = project::project_file(&input);
+            project::load_language_version(&project_file)
+                .and_then(|version| Ok((version, project::load_builtins(&project_file)?)))
+        };
+        let (version, plugins) = match settings {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                exit_code = exit_code.max(EXIT_USAGE);
+                continue;
+            }
+        };
+        
+        let checked = panic::catch_unwind(AssertUnwindSafe(|| check_source(Path::new(&name), &source, version, &plugins, &levels, quiet)));
+        let diagnostics = match checked {
+            Ok(diagnostics) => diagnostics,
+            Err(_) => {
+                eprintln!("error: internal compiler error while checking {}", name);
+                exit_code = exit_code.max(EXIT_INTERNAL);
+                continue;
+            }
+        };
+        
+        for diagnostic in diagnostics.diagnostics() {
+            if diagnostic.severity == Severity::Error {
+                if max_errors.is_some_and(|max| errors_shown >= max) {
+                    errors_hidden += 1;
+                    continue;
+                }
+                errors_shown += 1;
+            }
+            let mut diagnostic = diagnostic.clone();
+            diagnostic.location.file.get_or_insert_with(|| name.clone());
+            eprintln!("{}", diagnostic.format());
+        }
+        
+        // Only fixes of the file itself, not of those it imports
+        let fixes: Vec<_> = diagnostics.diagnostics().iter()
+            .filter(|d| d.location.file.as_ref().is_none_or(|file| *file == name))
+            .filter_map(|d| d.fix.as_ref())
+            .collect();
+        if fix && !fixes.is_empty() {
+            if input.as_os_str() == "-" {
+                tracing::warn!("fixes are not applied to <stdin>");
+            } else if let Err(e) = fs::write(&input, apply_fixes(&source, fixes.iter().copied())) {
+                eprintln!("Error: failed to write {}: {}", name, e);
+                exit_code = exit_code.max(EXIT_USAGE);
+            } else if !quiet {
+                println!("Applied {} fix(es) to {}", fixes.len(), name);
+            }
+        }
+        
+        if diagnostics.has_errors() {
+            exit_code = exit_code.max(EXIT_ERRORS);
+        }
+        reports.push(FileReport {
+            name,
+            errors: diagnostics.error_count(),
+            warnings: diagnostics.warning_count(),
+        });
+    }
+    
+    if errors_hidden > 0 {
+        eprintln!("... {} more error(s) not shown (--max-errors)", errors_hidden);
+    }
     
-    // Parsing
-    let mut parser = Parser::new(tokens);
-    let program = parser.parse()
+    if !quiet {
+        println!("\nSummary:");
+        for report in &reports {
+            println!("  {}: {} error(s), {} warning(s)", report.name, report.errors, report.warnings);
+        }
+        let errors: usize = reports.iter().map(|r| r.errors).sum();
+        let warnings: usize = reports.iter().map(|r| r.warnings).sum();
+        println!("Found {} error(s) and {} warning(s) in {} file(s)", errors, warnings, reports.len());
+        if exit_code == EXIT_OK {
+            println!("\n[OK] No errors found!");
+        }
+    }
+    
+    exit_code
+}
+
+/// Run every stage on one source file and the files it imports, stopping
+/// at the first stage with errors, and return its diagnostics, each naming
+/// its file
+///
+/// `// wtlang: allow(...)` and `// expect-error ...` comments apply to
+/// every stage of their file; warning levels are applied last.
+fn check_source(path: &Path, source: &str, version: LanguageVersion, plugins: &PluginRegistry, levels: &LintLevels, quiet: bool) -> DiagnosticBag {
+    // Lexical analysis and parsing, past invalid tokens to report later
+    // errors too
+    let loaded = match ModuleLoader::new().with_language_version(version).load(path, source) {
+        Ok(loaded) => loaded,
+        Err(diagnostics) => {
+            // The comments of the file itself apply to its own errors
+            let name = path.display().to_string();
+            let mut own = DiagnosticBag::new();
+            let mut imported = DiagnosticBag::new();
+            for diagnostic in diagnostics.diagnostics() {
+                if diagnostic.location.file.as_ref() == Some(&name) {
+                    own.add(diagnostic.clone());
+                } else {
+                    imported.add(diagnostic.clone());
+                }
+            }
+            let mut diagnostics = Directives::parse(source).apply(&own);
+            diagnostics.extend(imported);
+            return levels.apply(&diagnostics);
+        }
+    };
+    if !quiet && !loaded.diagnostics.has_errors() {
+        println!("[OK] Lexical analysis passed");
+    }
+    if !quiet {
+        println!("[OK] Parsing passed ({} items from {} file(s))", loaded.program.items.len(), loaded.files.len());
+    }
+    
+    // Semantic analysis, reported with the lexer's errors and the parser's
+    // warnings
+    let mut analyzer = SemanticAnalyzer::new().with_plugins(plugins.clone());
+    let _ = analyzer.analyze(&loaded.program);
+    let mut diagnostics = loaded.diagnostics.clone();
+    diagnostics.extend(analyzer.diagnostics());
+    let diagnostics = levels.apply(&loaded.apply_directives(&loaded.locate(&diagnostics)));
+    if !quiet && !diagnostics.has_errors() {
+        println!("[OK] Semantic analysis passed");
+    }
+    diagnostics
+}
+
+fn eval_command(expr: String, project: Option<PathBuf>) -> Result<()> {
+    let expr_source = if expr == "-" {
+        read_source(&PathBuf::from("-"))?
+    } else {
+        expr
+    };
+    
+    // Bring the project's tables and functions into scope
+    let mut analyzer = SemanticAnalyzer::new();
+    if let Some(project) = project {
+        let program = parse_project(&project)?;
+        if let Err(errors) = analyzer.analyze(&program) {
+            return Err(anyhow::anyhow!("{} has {} semantic error(s); run `wtc check` on it first", project.display(), errors.len()));
+        }
+    }
+    
+    let tokens = Lexer::new(&expr_source).tokenize()
         .map_err(|diag| {
-            eprintln!("\nSyntax errors found:\n{}", diag.format_all());
+            eprintln!("{}", diag.format_all());
+            anyhow::anyhow!("Lexical analysis failed")
+        })?;
+    let parsed = Parser::new(tokens).parse_standalone_expression()
+        .map_err(|diag| {
+            eprintln!("{}", diag.format_all());
             anyhow::anyhow!("Parsing failed")
         })?;
     
-    println!("[OK] Parsing passed ({} items)", program.items.len());
+    let expr_type = analyzer.analyze_expression(&parsed)
+        .map_err(|errors| {
+            for error in &errors {
+                eprintln!("error[{}]: {}", error.code(), error);
+            }
+            anyhow::anyhow!("Semantic analysis failed with {} error(s)", errors.len())
+        })?;
     
-    // Semantic analysis
-    let mut analyzer = SemanticAnalyzer::new();
-    if let Err(errors) = analyzer.analyze(&program) {
-        eprintln!("\nSemantic errors found:");
-        for error in &errors {
-            eprintln!("  - {}", error);
+    match eval_const(&parsed) {
+        Ok(value) => {
+            println!("type: {}", value.type_of());
+            println!("value: {}", value);
         }
-        return Err(anyhow::anyhow!("Semantic analysis failed with {} error(s)", errors.len()));
+        Err(EvalError::NotConstant) => println!("type: {}", expr_type),
+        Err(e) => return Err(anyhow::anyhow!("Evaluation failed: {}", e)),
     }
     
-    println!("[OK] Semantic analysis passed");
-    println!("\n[OK] No errors found!");
+    Ok(())
+}
+
+fn explain_command(code: Option<String>, json: bool) -> Result<()> {
+    let Some(code) = code else {
+        if json {
+            let codes: Vec<_> = ErrorCode::ALL.iter()
+                .map(|c| serde_json::json!({ "code": c.code(), "description": c.description() }))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&codes)?);
+        } else {
+            for c in ErrorCode::ALL {
+                println!("{}  {}", c.code(), c.description());
+            }
+        }
+        return Ok(());
+    };
+    
+    let error_code = ErrorCode::from_code(&code)
+        .ok_or_else(|| anyhow::anyhow!("Unknown error code '{}'. Run `wtc explain` to list all codes", code))?;
+    let explanation = error_code.explanation();
+    
+    if json {
+        let value = serde_json::json!({
+            "code": error_code.code(),
+            "description": error_code.description(),
+            "explanation": explanation.explanation,
+            "example": explanation.example,
+            "corrected": explanation.corrected,
+            "help": error_code.help(),
+            "url": error_code.doc_url(),
+        });
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+    
+    println!("{}: {}\n", error_code.code(), error_code.description());
+    println!("{}", explanation.explanation);
+    if let Some(example) = explanation.example {
+        println!("\nExample of erroneous code:\n");
+        for line in example.lines() {
+            println!("    {}", line);
+        }
+    }
+    if let Some(corrected) = explanation.corrected {
+        println!("\nCorrected code:\n");
+        for line in corrected.lines() {
+            println!("    {}", line);
+        }
+    }
+    if let Some(help) = error_code.help() {
+        println!("\nHelp: {}", help);
+    }
+    println!("\nSee: {}", error_code.doc_url());
     
     Ok(())
 }
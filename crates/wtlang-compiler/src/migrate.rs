@@ -0,0 +1,334 @@
+// Migration scripts for CSV data after table definitions change
+//
+// Used by `wtc migrate` so the data files of editable tables stay loadable
+// after a schema edit. The generated Python script rewrites each CSV file:
+// renamed columns are renamed, removed ones dropped, added ones filled with
+// a default and retyped ones converted, reporting values that do not convert.
+
+use wtlang_core::ir::{Constraint, FieldType, IRModule, SchemaDiff, TableSchema};
+use wtlang_core::Atom;
+
+/// A field renamed between the two versions, given as `Table.old=new`
+///
+/// Schemas alone cannot tell a renamed field from a removed and an added
+/// one, so renames are given explicitly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub table: Atom,
+    pub from: Atom,
+    pub to: Atom,
+}
+
+impl Rename {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid rename '{}': expected Table.old=new", text);
+        let (field, to) = text.split_once('=').ok_or_else(invalid)?;
+        let (table, from) = field.split_once('.').ok_or_else(invalid)?;
+        if [table, from, to].iter().any(|part| part.trim().is_empty()) {
+            return Err(invalid());
+        }
+        Ok(Rename {
+            table: Atom::from(table.trim()),
+            from: Atom::from(from.trim()),
+            to: Atom::from(to.trim()),
+        })
+    }
+}
+
+/// Changes to the CSV file of one table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableMigration {
+    pub table: Atom,
+    /// CSV columns to rename, from the old header to the new one
+    pub renamed: Vec<(String, String)>,
+    pub dropped: Vec<String>,
+    /// Added columns with the value existing rows get
+    pub added: Vec<(String, &'static str)>,
+    /// Columns converted to another type, with the type's name
+    pub retyped: Vec<(String, &'static str)>,
+}
+
+impl TableMigration {
+    pub fn is_empty(&self) -> bool {
+        self.renamed.is_empty() && self.dropped.is_empty() && self.added.is_empty() && self.retyped.is_empty()
+    }
+}
+
+/// Work out how the CSV file of each table defined by both versions changes
+pub fn plan(old: &IRModule, new: &IRModule, renames: &[Rename]) -> Result<Vec<TableMigration>, String> {
+    for rename in renames {
+        let old_schema = old.find_table(&rename.table)
+            .ok_or_else(|| format!("table '{}' is not defined by the old version", rename.table))?;
+        let new_schema = new.find_table(&rename.table)
+            .ok_or_else(|| format!("table '{}' is not defined by the new version", rename.table))?;
+        if !old_schema.has_field(&rename.from) {
+            return Err(format!("table '{}' had no field '{}'", rename.table, rename.from));
+        }
+        if !new_schema.has_field(&rename.to) {
+            return Err(format!("table '{}' has no field '{}'", rename.table, rename.to));
+        }
+    }
+
+    let mut migrations = Vec::new();
+    for name in old.table_names() {
+        let (Some(old_schema), Some(new_schema)) = (old.find_table(name), new.find_table(name)) else {
+            continue;
+        };
+        let table_renames: Vec<&Rename> = renames.iter().filter(|r| r.table == name).collect();
+        let renamed_schema = apply_renames(old_schema, &table_renames);
+        let diff = renamed_schema.diff(new_schema);
+        let migration = table_migration(old_schema, &renamed_schema, new_schema, &diff);
+        if !migration.is_empty() {
+            migrations.push(migration);
+        }
+    }
+    Ok(migrations)
+}
+
+/// The old schema with its renamed fields under their new names
+fn apply_renames(schema: &TableSchema, renames: &[&Rename]) -> TableSchema {
+    let mut renamed = schema.clone();
+    for rename in renames {
+        for field in renamed.fields.iter_mut().filter(|f| f.name == rename.from) {
+            field.name = rename.to;
+        }
+        for constraint in renamed.constraints.iter_mut().filter(|c| c.field() == rename.from) {
+            *constraint.field_mut() = rename.to;
+        }
+    }
+    renamed
+}
+
+fn table_migration(old: &TableSchema, renamed: &TableSchema, new: &TableSchema, diff: &SchemaDiff) -> TableMigration {
+    // Fields keep their position when renamed, so old and renamed line up
+    let renamed_columns = old.fields.iter().zip(&renamed.fields)
+        .filter_map(|(old_field, field)| {
+            let new_field = new.get_field(&field.name)?;
+            let from = csv_column(old_field.name, &old_field.header);
+            let to = csv_column(new_field.name, &new_field.header);
+            (from != to).then_some((from, to))
+        })
+        .collect();
+    let non_null = |field: Atom| new.constraints.iter().any(|c| matches!(c, Constraint::NonNull(f) | Constraint::PrimaryKey(f) if *f == field));
+    TableMigration {
+        table: new.name,
+        renamed: renamed_columns,
+        dropped: diff.removed.iter().map(|f| csv_column(f.name, &f.header)).collect(),
+        added: diff.added.iter()
+            .map(|f| {
+                let default = if non_null(f.name) { default_value(&f.ty) } else { "" };
+                (csv_column(f.name, &f.header), default)
+            })
+            .collect(),
+        retyped: diff.retyped.iter()
+            .filter_map(|f| {
+                let field = new.get_field(&f.name)?;
+                Some((csv_column(field.name, &field.header), type_name(&f.new)))
+            })
+            .collect(),
+    }
+}
+
+/// Header of a field's column in CSV files
+fn csv_column(name: Atom, header: &Option<String>) -> String {
+    header.clone().unwrap_or_else(|| name.to_string())
+}
+
+/// Value given to existing rows for an added field that may not be empty
+fn default_value(ty: &FieldType) -> &'static str {
+    match ty {
+        FieldType::Int | FieldType::Float | FieldType::Currency => "0",
+        FieldType::Bool => "false",
+        FieldType::String | FieldType::Date | FieldType::Ref { .. } => "",
+    }
+}
+
+/// Type a column is converted to by the script's `coerce`
+fn type_name(ty: &FieldType) -> &'static str {
+    match ty {
+        FieldType::Int => "int",
+        FieldType::Float => "float",
+        FieldType::Currency => "currency",
+        FieldType::Bool => "bool",
+        FieldType::Date => "date",
+        FieldType::String | FieldType::Ref { .. } => "string",
+    }
+}
+
+fn migration_function_name(table: Atom) -> String {
+    format!("migrate_{}", table.as_str().to_lowercase())
+}
+
+fn py_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+const COERCE: &str = r#"def coerce(df, table, column, ty):
+    """Convert a column to a type; values that do not convert are emptied and reported"""
+    values = df[column]
+    if ty == "int":
+        numbers = pd.to_numeric(values, errors="coerce")
+        numbers = numbers.where(numbers % 1 == 0)
+        converted = numbers.map(lambda n: "" if pd.isna(n) else str(int(n)))
+    elif ty in ("float", "currency"):
+        converted = values.where(pd.to_numeric(values, errors="coerce").notna(), "")
+    elif ty == "bool":
+        converted = values.str.lower().map(BOOLEANS).fillna("")
+    elif ty == "date":
+        converted = pd.to_datetime(values, errors="coerce").dt.strftime("%Y-%m-%d").fillna("")
+    else:
+        converted = values
+    failed = (values != "") & (converted == "")
+    for row in df.index[failed]:
+        print(f"{table}.{column} row {row + 2}: cannot convert {values[row]!r} to {ty}")
+    df[column] = converted
+    return int(failed.sum())
+"#;
+
+const MAIN: &str = r#"if __name__ == "__main__":
+    problems = 0
+    for arg in sys.argv[1:]:
+        table, _, path = arg.partition("=")
+        if table not in MIGRATIONS or not path:
+            sys.exit(f"usage: python {sys.argv[0]} Table=file.csv ...; tables: {', '.join(MIGRATIONS)}")
+        problems += MIGRATIONS[table](path)
+        print(f"Migrated: {path}")
+    if problems:
+        print(f"{problems} value(s) did not convert; the original files are kept as .bak")
+        sys.exit(1)
+"#;
+
+/// Python script applying the migrations to CSV files given on its command line
+pub fn script(migrations: &[TableMigration], source: &str) -> String {
+    let mut code = String::new();
+    code.push_str(&format!("# Migrate CSV files to the table definitions of {}\n", source));
+    code.push_str("#\n");
+    code.push_str("# Run with the CSV file of each table, e.g.:\n");
+    if let Some(first) = migrations.first() {
+        code.push_str(&format!("#     python migrate.py {}=data/{}.csv\n", first.table, first.table.as_str().to_lowercase()));
+    }
+    code.push_str("# Each file is rewritten; the original is kept next to it as <file>.bak.\n");
+    code.push('\n');
+    code.push_str("import shutil\n");
+    code.push_str("import sys\n");
+    code.push('\n');
+    code.push_str("import pandas as pd\n");
+    code.push('\n');
+    code.push_str("BOOLEANS = {\"true\": \"true\", \"false\": \"false\", \"1\": \"true\", \"0\": \"false\", \"yes\": \"true\", \"no\": \"false\"}\n");
+    code.push_str("\n\n");
+    code.push_str(COERCE);
+
+    for migration in migrations {
+        code.push_str("\n\n");
+        code.push_str(&format!("def {}(path):\n", migration_function_name(migration.table)));
+        code.push_str(&format!("    \"\"\"Migrate a CSV file of the {} table, returning how many values did not convert\"\"\"\n", migration.table));
+        code.push_str("    df = pd.read_csv(path, dtype=str, keep_default_na=False)\n");
+        code.push_str("    problems = 0\n");
+        if !migration.renamed.is_empty() {
+            let columns: Vec<String> = migration.renamed.iter()
+                .map(|(from, to)| format!("{}: {}", py_string(from), py_string(to)))
+                .collect();
+            code.push_str(&format!("    df = df.rename(columns={{{}}})\n", columns.join(", ")));
+        }
+        if !migration.dropped.is_empty() {
+            let columns: Vec<String> = migration.dropped.iter().map(|c| py_string(c)).collect();
+            code.push_str(&format!("    df = df.drop(columns=[{}], errors=\"ignore\")\n", columns.join(", ")));
+        }
+        for (column, default) in &migration.added {
+            code.push_str(&format!("    df[{}] = {}\n", py_string(column), py_string(default)));
+        }
+        for (column, ty) in &migration.retyped {
+            code.push_str(&format!("    problems += coerce(df, \"{}\", {}, \"{}\")\n", migration.table, py_string(column), ty));
+        }
+        code.push_str("    shutil.copyfile(path, path + \".bak\")\n");
+        code.push_str("    df.to_csv(path, index=False)\n");
+        code.push_str("    return problems\n");
+    }
+
+    code.push_str("\n\n");
+    let entries: Vec<String> = migrations.iter()
+        .map(|m| format!("\"{}\": {}", m.table, migration_function_name(m.table)))
+        .collect();
+    code.push_str(&format!("MIGRATIONS = {{{}}}\n", entries.join(", ")));
+    code.push('\n');
+    code.push_str(MAIN);
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{IRBuilder, Lexer, Parser};
+
+    fn module(source: &str) -> IRModule {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        IRBuilder::new().build(&program).unwrap()
+    }
+
+    const OLD: &str = r#"
+table Customer {
+    id: int [key],
+    name: string,
+    score: string,
+    legacy: string
+}
+"#;
+
+    const NEW: &str = r#"
+table Customer {
+    id: int [key],
+    full_name: string,
+    score: int,
+    active: bool [non_null],
+    notes: string
+}
+"#;
+
+    #[test]
+    fn test_plan_with_rename() {
+        let renames = vec![Rename::parse("Customer.name=full_name").unwrap()];
+        let migrations = plan(&module(OLD), &module(NEW), &renames).unwrap();
+        assert_eq!(migrations, vec![TableMigration {
+            table: Atom::from("Customer"),
+            renamed: vec![("name".to_string(), "full_name".to_string())],
+            dropped: vec!["legacy".to_string()],
+            added: vec![("active".to_string(), "false"), ("notes".to_string(), "")],
+            retyped: vec![("score".to_string(), "int")],
+        }]);
+    }
+
+    #[test]
+    fn test_without_rename_the_field_is_dropped_and_added() {
+        let migrations = plan(&module(OLD), &module(NEW), &[]).unwrap();
+        assert!(migrations[0].renamed.is_empty());
+        assert_eq!(migrations[0].dropped, vec!["name".to_string(), "legacy".to_string()]);
+        assert_eq!(migrations[0].added[0], ("full_name".to_string(), ""));
+    }
+
+    #[test]
+    fn test_script() {
+        let renames = vec![Rename::parse("Customer.name=full_name").unwrap()];
+        let migrations = plan(&module(OLD), &module(NEW), &renames).unwrap();
+        let code = script(&migrations, "new.wt");
+        assert!(code.contains("def migrate_customer(path):"));
+        assert!(code.contains("    df = df.rename(columns={\"name\": \"full_name\"})\n"));
+        assert!(code.contains("    df = df.drop(columns=[\"legacy\"], errors=\"ignore\")\n"));
+        assert!(code.contains("    df[\"active\"] = \"false\"\n"));
+        assert!(code.contains("    problems += coerce(df, \"Customer\", \"score\", \"int\")\n"));
+        assert!(code.contains("MIGRATIONS = {\"Customer\": migrate_customer}\n"));
+    }
+
+    #[test]
+    fn test_unchanged_tables_need_no_migration() {
+        assert!(plan(&module(OLD), &module(OLD), &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_renames() {
+        assert!(Rename::parse("Customer.name").is_err());
+        assert!(Rename::parse("name=full_name").is_err());
+        let unknown = vec![Rename::parse("Customer.missing=full_name").unwrap()];
+        assert!(plan(&module(OLD), &module(NEW), &unknown).is_err());
+    }
+}
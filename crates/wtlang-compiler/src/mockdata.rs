@@ -0,0 +1,372 @@
+// Fake CSV data generated from table definitions
+//
+// Used by `wtc mockdata` so pages can be demoed before real data exists.
+// Values follow the field types and constraints: keys and unique fields
+// never repeat, non_null fields are always filled and ref fields only use
+// keys of the generated rows of the referenced table.
+
+use std::collections::{HashMap, HashSet};
+use wtlang_core::ir::{Constraint, FieldType, IRItem, IRModule, TableSchema};
+use wtlang_core::Atom;
+
+/// Share of empty values in fields that may be null
+const NULL_RATE: f64 = 0.05;
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Bob", "Carla", "David", "Elena", "Frank", "Grace", "Hugo",
+    "Irene", "Jamal", "Keiko", "Luis", "Maria", "Noah", "Olga", "Priya",
+];
+const LAST_NAMES: &[&str] = &[
+    "Rossi", "Smith", "Garcia", "Müller", "Tanaka", "Okafor", "Novak", "Silva",
+    "Kowalski", "Dubois", "Jensen", "Khan", "Moreau", "Costa", "Ivanova", "Lee",
+];
+const CITIES: &[&str] = &[
+    "Milan", "London", "Madrid", "Berlin", "Tokyo", "Lagos", "Prague", "Lisbon",
+    "Warsaw", "Paris", "Oslo", "Karachi", "Lyon", "Porto", "Kyiv", "Seoul",
+];
+const COUNTRIES: &[&str] = &[
+    "Italy", "United Kingdom", "Spain", "Germany", "Japan", "Nigeria", "Portugal", "France",
+];
+const DEPARTMENTS: &[&str] = &["Sales", "Engineering", "Finance", "Marketing", "Support", "Operations"];
+const STATUSES: &[&str] = &["active", "pending", "shipped", "cancelled", "closed"];
+const WORDS: &[&str] = &[
+    "quick", "order", "review", "update", "sample", "priority", "customer", "report",
+    "delivery", "invoice", "standard", "monthly", "new", "archived", "draft", "final",
+];
+
+/// Small deterministic generator so the same seed always gives the same data
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in `low..=high`
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        let span = (high - low) as u64 + 1;
+        low + (self.next_u64() % span) as i64
+    }
+
+    /// Uniform float in `0.0..1.0`
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.range(0, items.len() as i64 - 1) as usize]
+    }
+}
+
+/// Rows of one generated table, as CSV cell text
+#[derive(Debug, Clone)]
+pub struct MockTable {
+    pub name: Atom,
+    pub columns: Vec<Atom>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl MockTable {
+    /// Render as CSV with a header row
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let header: Vec<String> = self.columns.iter().map(|c| csv_cell(c.as_str())).collect();
+        csv.push_str(&header.join(","));
+        csv.push('\n');
+        for row in &self.rows {
+            let cells: Vec<String> = row.iter().map(|c| csv_cell(c)).collect();
+            csv.push_str(&cells.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// File name the generated data is written to
+    pub fn file_name(&self) -> String {
+        format!("{}.csv", self.name.as_str().to_lowercase())
+    }
+}
+
+pub struct MockDataGenerator<'a> {
+    schemas: HashMap<Atom, &'a TableSchema>,
+    rng: Rng,
+    /// Key values of tables generated so far, for ref fields
+    keys: HashMap<Atom, Vec<String>>,
+}
+
+impl<'a> MockDataGenerator<'a> {
+    pub fn new(ir_module: &'a IRModule, seed: u64) -> Self {
+        let schemas = ir_module.items.iter()
+            .filter_map(|item| match item {
+                IRItem::TableDef { name, schema, .. } => Some((*name, schema)),
+                _ => None,
+            })
+            .collect();
+        MockDataGenerator { schemas, rng: Rng::new(seed), keys: HashMap::new() }
+    }
+
+    /// Generate `rows` rows of a table, preceded by every table it refers
+    /// to, directly or not
+    ///
+    /// Referenced tables get a quarter as many rows, so references repeat
+    /// the way foreign keys usually do.
+    pub fn generate(&mut self, table: &str, rows: usize) -> Result<Vec<MockTable>, String> {
+        let name = Atom::get(table)
+            .filter(|name| self.schemas.contains_key(name))
+            .ok_or_else(|| format!("Table '{}' is not defined", table))?;
+
+        let mut order = Vec::new();
+        self.dependency_order(name, &mut Vec::new(), &mut order)?;
+
+        let mut tables = Vec::new();
+        for dependency in order {
+            let count = if dependency == name { rows } else { rows.div_ceil(4).max(1) };
+            tables.push(self.generate_table(dependency, count));
+        }
+        Ok(tables)
+    }
+
+    /// Tables reachable through ref fields, referenced tables first
+    fn dependency_order(&self, table: Atom, visiting: &mut Vec<Atom>, order: &mut Vec<Atom>) -> Result<(), String> {
+        if order.contains(&table) {
+            return Ok(());
+        }
+        if visiting.contains(&table) {
+            return Err(format!("Tables '{}' reference each other in a cycle", table));
+        }
+        let schema = self.schemas.get(&table)
+            .ok_or_else(|| format!("Table '{}' is not defined", table))?;
+
+        visiting.push(table);
+        for field in &schema.fields {
+            if let FieldType::Ref { table_name } = &field.ty {
+                if *table_name != table {
+                    self.dependency_order(*table_name, visiting, order)?;
+                }
+            }
+        }
+        visiting.pop();
+        order.push(table);
+        Ok(())
+    }
+
+    fn generate_table(&mut self, table: Atom, rows: usize) -> MockTable {
+        let schema = self.schemas[&table];
+        let key = schema.get_key_field().map(|f| f.name);
+        let has = |constraint: fn(Atom) -> Constraint, field: Atom| schema.constraints.contains(&constraint(field));
+
+        let mut columns = Vec::new();
+        let mut cells: Vec<Vec<String>> = vec![Vec::new(); rows];
+        for field in &schema.fields {
+            let is_key = key == Some(field.name);
+            let unique = is_key || has(Constraint::Unique, field.name);
+            let non_null = is_key || has(Constraint::NonNull, field.name);
+
+            let mut seen = HashSet::new();
+            for (index, row) in cells.iter_mut().enumerate() {
+                let value = if !non_null && self.rng.unit() < NULL_RATE {
+                    String::new()
+                } else if unique {
+                    self.unique_value(field.name, &field.ty, index, &mut seen)
+                } else {
+                    self.value(field.name, &field.ty, table)
+                };
+                row.push(value);
+            }
+            // Columns are named as the loader expects them in the file
+            columns.push(field.header.as_deref().map_or(field.name, Atom::new));
+        }
+
+        if let Some(key) = key {
+            let position = schema.fields.iter().position(|f| f.name == key).unwrap_or(0);
+            self.keys.insert(table, cells.iter().map(|row| row[position].clone()).collect());
+        }
+
+        MockTable { name: table, columns, rows: cells }
+    }
+
+    /// A value that no earlier row of the column has used
+    fn unique_value(&mut self, field: Atom, ty: &FieldType, index: usize, seen: &mut HashSet<String>) -> String {
+        let value = match ty {
+            // Sequential ids read naturally and can never collide
+            FieldType::Int => (index + 1).to_string(),
+            FieldType::String if is_code_like(field.as_str()) => {
+                format!("{}-{:04}", field.as_str().to_uppercase(), index + 1)
+            }
+            _ => {
+                // Draw until a new value comes up, then fall back to a suffix
+                for _ in 0..20 {
+                    let candidate = self.value(field, ty, field);
+                    if seen.insert(candidate.clone()) {
+                        return candidate;
+                    }
+                }
+                match ty {
+                    FieldType::String => format!("{} {}", self.value(field, ty, field), index + 1),
+                    _ => (index + 1).to_string(),
+                }
+            }
+        };
+        seen.insert(value.clone());
+        value
+    }
+
+    fn value(&mut self, field: Atom, ty: &FieldType, table: Atom) -> String {
+        let name = field.as_str().to_lowercase();
+        let rng = &mut self.rng;
+        match ty {
+            FieldType::Int => {
+                let (low, high) = if name.contains("age") {
+                    (18, 80)
+                } else if name.contains("year") {
+                    (1990, 2025)
+                } else if name.contains("qty") || name.contains("quantity") || name.contains("count") {
+                    (1, 20)
+                } else {
+                    (1, 1000)
+                };
+                rng.range(low, high).to_string()
+            }
+            FieldType::Float => format!("{:.2}", rng.unit() * 1000.0),
+            FieldType::Currency => format!("{:.2}", 1.0 + rng.unit() * 999.0),
+            FieldType::Bool => if rng.unit() < 0.5 { "true" } else { "false" }.to_string(),
+            FieldType::Date => format!("{}-{:02}-{:02}", rng.range(2023, 2025), rng.range(1, 12), rng.range(1, 28)),
+            FieldType::String => string_value(rng, &name, table),
+            FieldType::Ref { table_name } => {
+                match self.keys.get(table_name).filter(|keys| !keys.is_empty()) {
+                    Some(keys) => keys[rng.range(0, keys.len() as i64 - 1) as usize].clone(),
+                    None => String::new(),
+                }
+            }
+        }
+    }
+}
+
+/// A plausible string for a field, guessed from its name
+fn string_value(rng: &mut Rng, field: &str, table: Atom) -> String {
+    let first = rng.pick(FIRST_NAMES);
+    let last = rng.pick(LAST_NAMES);
+    if field.contains("email") {
+        format!("{}.{}@example.com", first.to_lowercase(), last.to_lowercase())
+    } else if field.contains("first") {
+        first.to_string()
+    } else if field.contains("last") || field.contains("surname") {
+        last.to_string()
+    } else if field.contains("name") || field.contains("customer") || field.contains("employee") {
+        format!("{} {}", first, last)
+    } else if field.contains("city") {
+        rng.pick(CITIES).to_string()
+    } else if field.contains("country") {
+        rng.pick(COUNTRIES).to_string()
+    } else if field.contains("phone") {
+        format!("+1 555 01{:02}", rng.range(0, 99))
+    } else if field.contains("dept") || field.contains("department") {
+        rng.pick(DEPARTMENTS).to_string()
+    } else if field.contains("status") {
+        rng.pick(STATUSES).to_string()
+    } else if is_code_like(field) {
+        format!("{}-{:04}", table.as_str().to_uppercase(), rng.range(1, 9999))
+    } else {
+        format!("{} {}", rng.pick(WORDS), rng.pick(WORDS))
+    }
+}
+
+/// Whether a string field looks like an identifier rather than text
+fn is_code_like(field: &str) -> bool {
+    let field = field.to_lowercase();
+    field == "id" || field.ends_with("_id") || field.contains("code") || field.contains("sku")
+}
+
+fn csv_cell(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{IRBuilder, Lexer, Parser};
+
+    const SOURCE: &str = r#"
+table Customer {
+    id: int [key],
+    name: string [non_null],
+    email: string [unique]
+}
+
+table Order {
+    order_id: int [key],
+    customer: ref Customer,
+    amount: currency [non_null],
+    placed: date,
+    status: string
+}
+"#;
+
+    fn generate(table: &str, rows: usize, seed: u64) -> Vec<MockTable> {
+        let tokens = Lexer::new(SOURCE).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let module = IRBuilder::new().build(&program).unwrap();
+        MockDataGenerator::new(&module, seed).generate(table, rows).unwrap()
+    }
+
+    fn column<'t>(table: &'t MockTable, name: &str) -> Vec<&'t str> {
+        let index = table.columns.iter().position(|c| *c == name).unwrap();
+        table.rows.iter().map(|row| row[index].as_str()).collect()
+    }
+
+    #[test]
+    fn test_referenced_tables_are_generated_first() {
+        let tables = generate("Order", 40, 1);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].name, "Customer");
+        assert_eq!(tables[0].rows.len(), 10);
+        assert_eq!(tables[1].rows.len(), 40);
+
+        let customer_ids: HashSet<&str> = column(&tables[0], "id").into_iter().collect();
+        for customer in column(&tables[1], "customer") {
+            assert!(customer.is_empty() || customer_ids.contains(customer), "dangling ref {}", customer);
+        }
+    }
+
+    #[test]
+    fn test_constraints_are_respected() {
+        let tables = generate("Customer", 200, 7);
+        let customers = &tables[0];
+
+        let ids = column(customers, "id");
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+        let emails: Vec<&str> = column(customers, "email").into_iter().filter(|e| !e.is_empty()).collect();
+        assert_eq!(emails.iter().collect::<HashSet<_>>().len(), emails.len());
+        assert!(column(customers, "name").iter().all(|name| !name.is_empty()));
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_data() {
+        let first = generate("Order", 20, 3);
+        let second = generate("Order", 20, 3);
+        assert_eq!(first[1].to_csv(), second[1].to_csv());
+        assert!(first[1].to_csv().starts_with("order_id,customer,amount,placed,status\n"));
+        assert_ne!(first[1].to_csv(), generate("Order", 20, 4)[1].to_csv());
+    }
+
+    #[test]
+    fn test_unknown_table_is_an_error() {
+        let tokens = Lexer::new(SOURCE).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let module = IRBuilder::new().build(&program).unwrap();
+        assert!(MockDataGenerator::new(&module, 0).generate("Missing", 5).is_err());
+    }
+}
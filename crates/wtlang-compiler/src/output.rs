@@ -0,0 +1,248 @@
+// Generated files in the output directory
+//
+// Each build records what it wrote in a lockfile: the compiler version, the
+// hashes of the sources and of every generated file, and the backend options.
+// The next build uses it to remove files it no longer produces (e.g. the page
+// of a renamed `page`), `wtc clean` to remove them all, and `wtc verify` to
+// find generated files edited by hand. Files not in the lockfile are never
+// touched.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path};
+
+pub const LOCK_FILE: &str = "wt.lock";
+
+pub const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What a build wrote to the output directory
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lock {
+    pub compiler: String,
+    /// Source file to content hash
+    pub sources: BTreeMap<String, String>,
+    /// Backend options the files were generated with, including the
+    /// target Python version
+    pub options: BTreeMap<String, Value>,
+    /// Generated file to content hash
+    pub files: BTreeMap<String, String>,
+}
+
+impl Lock {
+    pub fn new(sources: BTreeMap<String, String>, options: BTreeMap<String, Value>, files: &BTreeMap<String, String>) -> Self {
+        Lock {
+            compiler: COMPILER_VERSION.to_string(),
+            sources,
+            options,
+            files: files.iter().map(|(file, content)| (file.clone(), content_hash(content))).collect(),
+        }
+    }
+
+    /// The lockfile of the last build in `dir`, if it has one
+    pub fn read(dir: &Path) -> Result<Option<Lock>> {
+        let path = dir.join(LOCK_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let lock: Value = serde_json::from_str(&text)
+            .with_context(|| format!("{} is not a valid lockfile", path.display()))?;
+        let files = lock["files"].as_object()
+            .with_context(|| format!("{} has no file list", path.display()))?;
+
+        Ok(Some(Lock {
+            compiler: lock["compiler"].as_str().unwrap_or_default().to_string(),
+            sources: string_map(&lock["sources"]),
+            options: lock["options"].as_object()
+                .map(|options| options.clone().into_iter().collect())
+                .unwrap_or_default(),
+            // Only plain file names are accepted, so a damaged lockfile
+            // cannot point outside the output directory
+            files: string_map(&Value::Object(files.clone()))
+                .into_iter()
+                .filter(|(file, _)| is_plain_file_name(file))
+                .collect(),
+        }))
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let lock = json!({
+            "compiler": self.compiler,
+            "sources": self.sources,
+            "options": self.options,
+            "files": self.files,
+        });
+        let path = dir.join(LOCK_FILE);
+        fs::write(&path, format!("{:#}\n", lock))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Generated files whose content no longer matches the recorded hash,
+    /// and those that are missing
+    pub fn verify(&self, dir: &Path) -> Result<(Vec<String>, Vec<String>)> {
+        let (mut modified, mut missing) = (Vec::new(), Vec::new());
+        for (file, hash) in &self.files {
+            let path = dir.join(file);
+            if !path.exists() {
+                missing.push(file.clone());
+                continue;
+            }
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            if content_hash(&content) != *hash {
+                modified.push(file.clone());
+            }
+        }
+        Ok((modified, missing))
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of a file's content, in hex
+///
+/// Unlike the standard library's hasher it does not change between Rust
+/// releases, so lockfiles stay comparable across compiler builds.
+pub fn content_hash(content: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Comment line opening every generated file
+pub fn header(source: &str, source_hash: &str) -> String {
+    format!("# Generated by wtc v{} from {}@{} \u{2014} do not edit\n", COMPILER_VERSION, source, source_hash)
+}
+
+/// Files of the last build that the current build no longer produces
+pub fn stale_files(dir: &Path, files: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    Ok(Lock::read(dir)?
+        .map(|lock| lock.files.into_keys()
+            .filter(|file| !files.contains_key(file) && dir.join(file).exists())
+            .collect())
+        .unwrap_or_default())
+}
+
+/// Remove the given generated files, returning the ones that existed
+pub fn remove_files(dir: &Path, files: &[String]) -> Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for file in files {
+        let path = dir.join(file);
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            removed.push(file.clone());
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove every file of the last build and the lockfile itself, and the
+/// directory too if nothing else is left in it
+pub fn clean(dir: &Path) -> Result<Vec<String>> {
+    let files: Vec<String> = Lock::read(dir)?
+        .map(|lock| lock.files.into_keys().collect())
+        .unwrap_or_default();
+    let removed = remove_files(dir, &files)?;
+    let lock = dir.join(LOCK_FILE);
+    if lock.exists() {
+        fs::remove_file(&lock)
+            .with_context(|| format!("Failed to remove {}", lock.display()))?;
+    }
+    if fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none()) {
+        fs::remove_dir(dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    Ok(removed)
+}
+
+fn string_map(value: &Value) -> BTreeMap<String, String> {
+    value.as_object()
+        .map(|entries| entries.iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .collect())
+        .unwrap_or_default()
+}
+
+fn is_plain_file_name(file: &str) -> bool {
+    let mut components = Path::new(file).components();
+    matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtc_output_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn files(names: &[&str]) -> BTreeMap<String, String> {
+        names.iter().map(|name| (name.to_string(), format!("# {}\n", name))).collect()
+    }
+
+    fn write_build(dir: &Path, files: &BTreeMap<String, String>) -> Lock {
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        let lock = Lock::new(BTreeMap::new(), BTreeMap::new(), files);
+        lock.write(dir).unwrap();
+        lock
+    }
+
+    #[test]
+    fn test_stale_files_and_clean() {
+        let dir = temp_dir("stale");
+        write_build(&dir, &files(&["Home.py", "Orders.py"]));
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        // Orders was renamed to Sales; notes.txt was never generated
+        assert_eq!(stale_files(&dir, &files(&["Home.py", "Sales.py"])).unwrap(), ["Orders.py"]);
+
+        assert_eq!(clean(&dir).unwrap(), ["Home.py", "Orders.py"]);
+        assert!(dir.join("notes.txt").exists());
+        assert!(!dir.join(LOCK_FILE).exists());
+        fs::remove_file(dir.join("notes.txt")).unwrap();
+        assert!(clean(&dir).unwrap().is_empty());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_lockfile_round_trip_and_verify() {
+        let dir = temp_dir("verify");
+        let mut lock = write_build(&dir, &files(&["Home.py", "helpers.py", "requirements.txt"]));
+        lock.sources.insert("app.wt".to_string(), content_hash("page Home {}"));
+        lock.options.insert("trace".to_string(), true.into());
+        lock.options.insert("python".to_string(), "3.12".into());
+        lock.write(&dir).unwrap();
+        assert_eq!(Lock::read(&dir).unwrap(), Some(lock.clone()));
+        assert_eq!(lock.verify(&dir).unwrap(), (vec![], vec![]));
+
+        fs::write(dir.join("Home.py"), "# Home.py\nst.write('patched')\n").unwrap();
+        fs::remove_file(dir.join("requirements.txt")).unwrap();
+        assert_eq!(lock.verify(&dir).unwrap(), (vec!["Home.py".to_string()], vec!["requirements.txt".to_string()]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lockfile_cannot_point_outside_the_directory() {
+        let dir = temp_dir("escape");
+        fs::write(dir.join(LOCK_FILE), r#"{"files": {"../victim.py": "0", "/etc/passwd": "0", "sub/x.py": "0", "ok.py": "0"}}"#).unwrap();
+        let lock = Lock::read(&dir).unwrap().unwrap();
+        assert_eq!(lock.files.into_keys().collect::<Vec<_>>(), ["ok.py"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("a"), "af63dc4c8601ec8c");
+    }
+}
@@ -0,0 +1,244 @@
+// Project settings
+//
+// An optional `wt.toml` next to the source file holds settings for the whole
+// app, in the same format as the locale files. Its `[format]` section sets how
+// numbers, dates and currency amounts are shown, both in `show` tables and by
+// the `format_*` builtins; settings left out keep the US conventions. A
+// top-level `language_version` pins the version of the language the sources
+// are written for, each `[builtins.<name>]` section adds a builtin
+// calling the organization's own Python code, and the `[requirements]`
+// section pins the versions of the Python packages the app installs.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wtlang_core::{LanguageVersion, ManifestBuiltin, PluginRegistry};
+use crate::backend::Backend;
+use crate::i18n;
+
+pub const PROJECT_FILE: &str = "wt.toml";
+
+/// Top-level setting pinning the language version
+pub const LANGUAGE_VERSION_SETTING: &str = "language_version";
+
+/// Sections of the builtins a project adds, e.g. `[builtins.pct_change]`
+pub const BUILTINS_SECTION: &str = "builtins.";
+
+/// Section of the package versions, e.g. `requirements.pandas = "==2.2.1"`
+pub const REQUIREMENTS_SECTION: &str = "requirements.";
+
+/// Package to version specifier, e.g. `pandas` to `==2.2.1`
+pub type Requirements = BTreeMap<String, String>;
+
+/// `[format]` setting to value, e.g. `decimal_separator` to `,`
+pub type Formats = BTreeMap<String, String>;
+
+/// Settings of the `[format]` section, with their US defaults
+pub const FORMAT_SETTINGS: &[(&str, &str)] = &[
+    ("decimal_separator", "."),
+    ("thousands_separator", ","),
+    ("date_format", "%Y-%m-%d"),
+    ("currency_symbol", "$"),
+    ("currency_position", "before"),
+];
+
+/// Project file of a source file
+pub fn project_file(input: &Path) -> PathBuf {
+    input.parent().unwrap_or(Path::new("")).join(PROJECT_FILE)
+}
+
+/// The `[format]` settings of a project file, or none if it does not exist
+pub fn load_formats(path: &Path) -> Result<Formats> {
+    if !path.exists() {
+        return Ok(Formats::new());
+    }
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_formats(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+pub fn parse_formats(text: &str) -> Result<Formats, String> {
+    let mut formats = Formats::new();
+    for (key, value) in i18n::parse_locale(text)? {
+        if key == LANGUAGE_VERSION_SETTING || key.starts_with(BUILTINS_SECTION) || key.starts_with(REQUIREMENTS_SECTION) {
+            continue;
+        }
+        let name = key.strip_prefix("format.")
+            .filter(|name| FORMAT_SETTINGS.iter().any(|(setting, _)| setting == name))
+            .ok_or_else(|| format!("unknown setting '{}'", key))?;
+        formats.insert(name.to_string(), value);
+    }
+
+    let setting = |name: &str| {
+        formats.get(name).map(String::as_str)
+            .or_else(|| FORMAT_SETTINGS.iter().find(|(setting, _)| *setting == name).map(|(_, default)| *default))
+            .unwrap_or_default()
+    };
+    if setting("decimal_separator").chars().count() != 1 {
+        return Err("decimal_separator must be a single character".to_string());
+    }
+    if setting("thousands_separator").chars().count() > 1 {
+        return Err("thousands_separator must be a single character, or empty for none".to_string());
+    }
+    if setting("decimal_separator") == setting("thousands_separator") {
+        return Err("decimal_separator and thousands_separator must differ".to_string());
+    }
+    if !setting("date_format").contains('%') {
+        return Err("date_format must be a strftime format such as \"%d/%m/%Y\"".to_string());
+    }
+    if !matches!(setting("currency_position"), "before" | "after") {
+        return Err("currency_position must be \"before\" or \"after\"".to_string());
+    }
+    Ok(formats)
+}
+
+/// The language version a project file pins, or the current one if it
+/// pins none or does not exist
+pub fn load_language_version(path: &Path) -> Result<LanguageVersion> {
+    if !path.exists() {
+        return Ok(LanguageVersion::CURRENT);
+    }
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_language_version(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+pub fn parse_language_version(text: &str) -> Result<LanguageVersion, String> {
+    match i18n::parse_locale(text)?.get(LANGUAGE_VERSION_SETTING) {
+        None => Ok(LanguageVersion::CURRENT),
+        Some(value) => LanguageVersion::parse(value).ok_or_else(|| {
+            format!("unknown language_version '{}'; the known versions are {}", value, LanguageVersion::known())
+        }),
+    }
+}
+
+/// The builtins a project file adds, or none if it does not exist
+pub fn load_builtins(path: &Path) -> Result<PluginRegistry> {
+    if !path.exists() {
+        return Ok(PluginRegistry::new());
+    }
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_builtins(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+pub fn parse_builtins(text: &str) -> Result<PluginRegistry, String> {
+    let mut builtins: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    for (key, value) in i18n::parse_locale(text)? {
+        let Some(setting) = key.strip_prefix(BUILTINS_SECTION) else { continue };
+        let (name, setting) = setting.rsplit_once('.')
+            .ok_or_else(|| format!("unknown setting '{}'; builtins are declared in [builtins.<name>] sections", key))?;
+        builtins.entry(name.to_string()).or_default().insert(setting.to_string(), value);
+    }
+
+    let mut plugins = PluginRegistry::new();
+    for (name, settings) in builtins {
+        let builtin = ManifestBuiltin::new(&name, &settings)?;
+        if let Some(backend) = builtin.backends().find(|backend| Backend::ALL.iter().all(|b| b.name() != *backend)) {
+            return Err(format!("unknown setting '{}' of builtin '{}'; expected signature, import or a backend", backend, name));
+        }
+        plugins.register(builtin)?;
+    }
+    Ok(plugins)
+}
+
+/// The package versions a project file pins, or none if it does not exist
+pub fn load_requirements(path: &Path) -> Result<Requirements> {
+    if !path.exists() {
+        return Ok(Requirements::new());
+    }
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_requirements(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+pub fn parse_requirements(text: &str) -> Result<Requirements, String> {
+    let mut requirements = Requirements::new();
+    for (key, value) in i18n::parse_locale(text)? {
+        let Some(package) = key.strip_prefix(REQUIREMENTS_SECTION) else { continue };
+        let version = value.trim();
+        if !["==", ">=", "<=", "~=", "!=", ">", "<"].iter().any(|operator| version.starts_with(operator)) {
+            return Err(format!("version of {} must be a specifier such as \">=2.0\" or \"==2.2.1\", found \"{}\"", package, value));
+        }
+        requirements.insert(package.to_string(), version.to_string());
+    }
+    Ok(requirements)
+}
+
+/// A project file's text pinning `version` instead of the version it
+/// pinned; other lines are kept as written
+pub fn set_language_version(text: &str, version: LanguageVersion) -> String {
+    let setting = format!("{} = \"{}\"", LANGUAGE_VERSION_SETTING, version);
+    let mut in_section = false;
+    text.split_inclusive('\n')
+        .map(|line| {
+            let trimmed = line.trim();
+            in_section |= trimmed.starts_with('[');
+            let key = trimmed.split('=').next().unwrap_or_default().trim();
+            if in_section || key != LANGUAGE_VERSION_SETTING {
+                return line.to_string();
+            }
+            let ending = &line[line.trim_end().len()..];
+            format!("{}{}", setting, ending)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let text = "[format]\ndecimal_separator = \",\"\nthousands_separator = \".\"\ncurrency_symbol = \"\u{20ac}\"\ncurrency_position = \"after\"\n";
+        let formats = parse_formats(text).unwrap();
+        assert_eq!(formats.len(), 4);
+        assert_eq!(formats["decimal_separator"], ",");
+        assert_eq!(formats["currency_symbol"], "\u{20ac}");
+
+        assert_eq!(parse_formats("[format]\ndecimal_separator = \",\"").unwrap_err(), "decimal_separator and thousands_separator must differ");
+        assert_eq!(parse_formats("[format]\ndate = \"%d.%m.%Y\"").unwrap_err(), "unknown setting 'format.date'");
+        assert_eq!(parse_formats("[format]\ndate_format = \"dd/mm/yyyy\"").unwrap_err(), "date_format must be a strftime format such as \"%d/%m/%Y\"");
+    }
+
+    #[test]
+    fn test_parse_requirements() {
+        let text = "language_version = \"0.2\"\n\n[requirements]\npandas = \"==2.2.1\"\nstreamlit = \" >=1.35\"\n";
+        let requirements = parse_requirements(text).unwrap();
+        assert_eq!(requirements["pandas"], "==2.2.1");
+        assert_eq!(requirements["streamlit"], ">=1.35");
+        assert!(parse_formats(text).unwrap().is_empty());
+        assert_eq!(
+            parse_requirements("[requirements]\npandas = \"2.2\"").unwrap_err(),
+            "version of pandas must be a specifier such as \">=2.0\" or \"==2.2.1\", found \"2.2\""
+        );
+    }
+
+    #[test]
+    fn test_parse_builtins() {
+        let text = "[format]\ncurrency_symbol = \"\u{20ac}\"\n\n[builtins.pct_change]\nsignature = \"(old: float, new: float) -> float\"\nimport = \"from acme.finance import pct_change\"\nstreamlit = \"pct_change({old}, {new})\"\n";
+        let plugins = parse_builtins(text).unwrap();
+        let builtin = plugins.get("pct_change").unwrap();
+        assert_eq!(builtin.params().len(), 2);
+        assert_eq!(builtin.imports("streamlit"), vec!["from acme.finance import pct_change"]);
+        assert_eq!(parse_formats(text).unwrap().len(), 1);
+        assert!(parse_builtins("[format]\ndate_format = \"%d.%m.%Y\"").unwrap().is_empty());
+
+        let unknown = "[builtins.f]\nsignature = \"() -> int\"\ndash = \"f()\"\n";
+        assert_eq!(parse_builtins(unknown).unwrap_err(), "unknown setting 'dash' of builtin 'f'; expected signature, import or a backend");
+    }
+
+    #[test]
+    fn test_parse_language_version() {
+        let text = "language_version = \"0.1\"\n\n[format]\ndecimal_separator = \",\"\nthousands_separator = \".\"\n";
+        assert_eq!(parse_language_version(text).unwrap(), LanguageVersion::V0_1);
+        assert_eq!(parse_formats(text).unwrap().len(), 2);
+        assert_eq!(parse_language_version("[format]\ncurrency_symbol = \"$\"").unwrap(), LanguageVersion::CURRENT);
+        assert_eq!(parse_language_version("language_version = \"1.0\"").unwrap_err(), "unknown language_version '1.0'; the known versions are 0.1, 0.2");
+        
+        let upgraded = set_language_version(text, LanguageVersion::V0_2);
+        assert_eq!(upgraded, text.replace("0.1", "0.2"));
+        assert_eq!(parse_language_version(&upgraded).unwrap(), LanguageVersion::V0_2);
+    }
+}
@@ -0,0 +1,92 @@
+// The generated app's requirements.txt
+//
+// Lists Streamlit and pandas, which every app needs, and the packages of the
+// runtime features the program uses: openpyxl only when it exports Excel
+// files, a plotting library only when a `python` block imports one, and so
+// on. The `[requirements]` section of the project's `wt.toml` overrides the
+// version of a package the app needs.
+
+use std::collections::BTreeSet;
+use wtlang_core::ir::RuntimeFeature;
+use crate::project::Requirements;
+
+/// Packages every app needs
+const BASE_PACKAGES: &[&str] = &["streamlit", "pandas"];
+
+/// Version of each package the app may need, unless the project overrides it
+const DEFAULT_VERSIONS: &[(&str, &str)] = &[
+    ("streamlit", ">=1.28.0"),
+    ("pandas", ">=2.0.0"),
+    ("openpyxl", ">=3.1.0"),
+    ("SQLAlchemy", ">=2.0.0"),
+    ("pdfkit", ">=1.0.0"),
+    ("streamlit-autorefresh", ">=1.0.1"),
+    ("matplotlib", ">=3.7.0"),
+    ("plotly", ">=5.15.0"),
+    ("altair", ">=5.0.0"),
+    ("seaborn", ">=0.12.0"),
+    ("bokeh", ">=3.2.0"),
+    ("requests", ">=2.31.0"),
+    ("httpx", ">=0.24.0"),
+    ("pytest", ">=7.0.0"),
+    ("pandera", ">=0.18.0"),
+];
+
+/// The package providing a runtime feature
+fn package(feature: &RuntimeFeature) -> &str {
+    match feature {
+        RuntimeFeature::Excel => "openpyxl",
+        RuntimeFeature::Sql => "SQLAlchemy",
+        RuntimeFeature::PdfExport => "pdfkit",
+        RuntimeFeature::Refresh => "streamlit-autorefresh",
+        // Their modules are named after the packages
+        RuntimeFeature::Plotting(module) | RuntimeFeature::Requests(module) => module,
+    }
+}
+
+/// The requirements.txt of an app using `features`, with pytest when its
+/// tests are generated and pandera when it validates tables with pandera
+pub fn requirements_txt(features: &BTreeSet<RuntimeFeature>, with_tests: bool, pandera: bool, overrides: &Requirements) -> String {
+    let mut packages: Vec<&str> = BASE_PACKAGES.to_vec();
+    packages.extend(features.iter().map(package));
+    if with_tests {
+        packages.push("pytest");
+    }
+    if pandera {
+        packages.push("pandera");
+    }
+
+    let mut requirements = String::new();
+    for package in packages {
+        let version = overrides.get(package).map(String::as_str)
+            .or_else(|| DEFAULT_VERSIONS.iter().find(|(name, _)| *name == package).map(|(_, version)| *version))
+            .unwrap_or_default();
+        requirements.push_str(&format!("{}{}\n", package, version));
+    }
+    requirements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requirements_txt() {
+        let none = Requirements::new();
+        assert_eq!(requirements_txt(&BTreeSet::new(), false, false, &none), "streamlit>=1.28.0\npandas>=2.0.0\n");
+
+        let features = BTreeSet::from([
+            RuntimeFeature::Excel,
+            RuntimeFeature::Sql,
+            RuntimeFeature::Plotting("plotly".to_string()),
+        ]);
+        let overrides = Requirements::from([
+            ("pandas".to_string(), "==2.2.1".to_string()),
+            ("pdfkit".to_string(), ">=1.0.0".to_string()),
+        ]);
+        assert_eq!(
+            requirements_txt(&features, true, false, &overrides),
+            "streamlit>=1.28.0\npandas==2.2.1\nopenpyxl>=3.1.0\nSQLAlchemy>=2.0.0\nplotly>=5.15.0\npytest>=7.0.0\n"
+        );
+    }
+}
@@ -0,0 +1,1205 @@
+# WTLang runtime library
+#
+# Generated by wtc. Shared by every page of a WTLang application; the same
+# file is emitted for every program.
+
+import contextlib
+import getpass
+import hashlib
+import html
+import io
+import logging
+import logging.config
+import math
+import os
+import random
+import smtplib
+import tempfile
+import threading
+import time
+from datetime import datetime, timezone
+from email.message import EmailMessage
+
+import streamlit as st
+import pandas as pd
+
+try:
+    import fcntl
+except ImportError:  # Windows
+    fcntl = None
+    import msvcrt
+
+
+def show_filtered(df, filters, editable=False, key_prefix='', schema=None):
+    """Show dataframe with optional filters"""
+    # Create filter widgets (3 per row)
+    filter_values = []
+    num_filters = len(filters)
+    for i in range(0, num_filters, 3):
+        cols = st.columns(min(3, num_filters - i))
+        for j, (col_name, mode) in enumerate(filters[i:i+3]):
+            if mode == 'single':
+                val = cols[j].selectbox(col_name, ['All'] + sorted(df[col_name].unique().astype(str).tolist()), key=f'{key_prefix}_f_{i+j}')
+                filter_values.append((col_name, mode, val))
+            else:  # multi
+                val = cols[j].multiselect(col_name, sorted(df[col_name].unique().astype(str).tolist()), key=f'{key_prefix}_f_{i+j}')
+                filter_values.append((col_name, mode, val))
+    
+    # Apply filters and track filtered/non-filtered rows
+    mask = pd.Series([True] * len(df), index=df.index)
+    for col_name, mode, val in filter_values:
+        if mode == 'single' and val != 'All':
+            mask = mask & (df[col_name].astype(str) == val)
+        elif mode == 'multi' and val:
+            mask = mask & df[col_name].astype(str).isin(val)
+    
+    filtered = df[mask]
+    non_filtered = df[~mask]
+    
+    # Display
+    if editable:
+        edited = st.data_editor(filtered, key=f'{key_prefix}_editor', use_container_width=True)
+        # Merge edited filtered rows with non-filtered rows
+        return pd.concat([edited, non_filtered], ignore_index=True)
+    else:
+        st.dataframe(formatted(filtered, schema))
+        return None
+
+
+def validate_schema(df, schema, table='table'):
+    """Check a dataframe against a WTLang table schema
+
+    `schema` maps each column to its WTLang type: "int", "float", "string",
+    "bool", "date", "currency" or "ref". Every problem found (missing
+    columns, unexpected columns, values of the wrong type) is shown with
+    st.error and returned; an empty list means the data matches.
+    """
+    problems = []
+    
+    missing = [column for column in schema if column not in df.columns]
+    if missing:
+        problems.append(f"{table}: missing column(s) {', '.join(missing)}")
+    
+    extra = [str(column) for column in df.columns if column not in schema]
+    if extra:
+        problems.append(f"{table}: unexpected column(s) {', '.join(extra)}")
+    
+    for column, wt_type in schema.items():
+        if column in df.columns and not _matches_type(df[column], wt_type):
+            problems.append(
+                f"{table}: column '{column}' should be {wt_type}, "
+                f"but its values have dtype {df[column].dtype}"
+            )
+    
+    for problem in problems:
+        st.error(problem)
+    return problems
+
+
+def _matches_type(series, wt_type):
+    """Check whether the non-missing values of a column fit a WTLang type"""
+    values = series.dropna()
+    if values.empty:
+        return True
+    
+    types = pd.api.types
+    if wt_type == 'int':
+        if types.is_bool_dtype(series):
+            return False
+        if types.is_integer_dtype(series):
+            return True
+        # Integer columns with missing values are read as float
+        return types.is_float_dtype(series) and bool((values % 1 == 0).all())
+    if wt_type in ('float', 'currency'):
+        return types.is_numeric_dtype(series) and not types.is_bool_dtype(series)
+    if wt_type == 'bool':
+        return types.is_bool_dtype(series)
+    if wt_type == 'date':
+        if types.is_datetime64_any_dtype(series):
+            return True
+        return bool(pd.to_datetime(values, errors='coerce').notna().all())
+    
+    # Any value can be shown as a string, and references take the type of
+    # the referenced table's key
+    return True
+
+
+# CSV files
+#
+# Several sessions of an app may save the same file at once. read_csv
+# remembers the checksum of each file a session loads. write_csv holds a lock
+# on `<file>.lock` while it writes to a temporary file that then replaces the
+# original, so no reader sees half a file, and refuses to save when the file
+# changed since the session loaded it, which would lose the other save.
+# Tables with `restrict` rules pass the rows the user may see as `visible`;
+# the user may only save those, and the other rows of the file are kept.
+# load_csv(path, Table, "normalize") renames the headers of the file to the
+# table's columns with match_columns before they are validated; saving the
+# table writes the column names of the table. Relative paths are in the
+# `data_dir` of the program's config block, when it sets one.
+
+_checksums_outside_streamlit = {}
+_data_dir = None
+
+
+def configure_data_dir(path):
+    global _data_dir
+    _data_dir = path
+
+
+def _data_path(path):
+    if _data_dir is None or os.path.isabs(path):
+        return path
+    return os.path.join(_data_dir, path)
+
+
+def read_csv(path, headers=None):
+    """Load a CSV file, remembering its checksum for write_csv
+
+    headers maps columns to the CSV headers they are stored under, and
+    those columns are renamed from their headers.
+    """
+    path = _data_path(path)
+    with open(path, 'rb') as file:
+        data = file.read()
+    _loaded_checksums()[os.path.abspath(path)] = hashlib.sha256(data).hexdigest()
+    df = pd.read_csv(io.BytesIO(data))
+    return _from_headers(df, headers)
+
+
+def _from_headers(df, headers):
+    if not headers:
+        return df
+    return df.rename(columns={header: column for column, header in headers.items()})
+
+
+def match_columns(df, schema):
+    """Rename the columns of a dataframe to the schema columns they match
+    once trimmed, case-folded and with runs of spaces and dashes read as
+    underscores, so "Order Date " matches order_date
+
+    Columns matching no schema column, or one already matched, keep their
+    name and are reported by validate_schema.
+    """
+    columns = {_column_key(column): column for column in schema}
+    renames = {}
+    for column in df.columns:
+        target = columns.get(_column_key(str(column)))
+        if target is not None and target not in renames.values():
+            renames[column] = target
+    return df.rename(columns=renames)
+
+
+def _column_key(name):
+    return "_".join(name.replace("-", " ").split()).casefold()
+
+
+def write_csv(df, path, table='table', visible=None, headers=None):
+    """Replace a CSV file with a table; True once it is saved
+
+    Columns named in headers are written under their CSV headers.
+    """
+    path = os.path.abspath(_data_path(path))
+    if visible is not None and not _only_visible_rows(df, visible, table):
+        return False
+    with _file_lock(path + '.lock'):
+        loaded = _loaded_checksums().get(path)
+        if loaded is not None and os.path.exists(path) and _file_checksum(path) != loaded:
+            st.warning(
+                f'{table}: {os.path.basename(path)} was saved by another session since this page '
+                'loaded it; reload the page to see those changes, then save again'
+            )
+            return False
+        if visible is not None and os.path.exists(path):
+            existing = _from_headers(pd.read_csv(path), headers)
+            df = pd.concat([existing[~visible(existing)], df], ignore_index=True)
+        if headers:
+            df = df.rename(columns=headers)
+        descriptor, temp_path = tempfile.mkstemp(dir=os.path.dirname(path), prefix=f'.{os.path.basename(path)}.', suffix='.tmp')
+        try:
+            with os.fdopen(descriptor, 'w', newline='') as file:
+                df.to_csv(file, index=False)
+            os.replace(temp_path, path)
+        except BaseException:
+            if os.path.exists(temp_path):
+                os.remove(temp_path)
+            raise
+        _loaded_checksums()[path] = _file_checksum(path)
+    clear_derived_tables()
+    return True
+
+
+def _loaded_checksums():
+    """Checksums of the files loaded by the current session"""
+    session_state = getattr(st, 'session_state', None)
+    if session_state is None:
+        return _checksums_outside_streamlit
+    return session_state.setdefault('wt_loaded_checksums', {})
+
+
+def _file_checksum(path):
+    with open(path, 'rb') as file:
+        return hashlib.sha256(file.read()).hexdigest()
+
+
+@contextlib.contextmanager
+def _file_lock(path):
+    """Exclusive lock on a file, held across processes"""
+    with open(path, 'a') as lock:
+        if fcntl:
+            fcntl.flock(lock, fcntl.LOCK_EX)
+        else:
+            msvcrt.locking(lock.fileno(), msvcrt.LK_LOCK, 1)
+        try:
+            yield
+        finally:
+            if fcntl:
+                fcntl.flock(lock, fcntl.LOCK_UN)
+            else:
+                lock.seek(0)
+                msvcrt.locking(lock.fileno(), msvcrt.LK_UNLCK, 1)
+
+
+# SQL databases
+#
+# load_sql and save_sql use the Streamlit SQL connection of the given name,
+# configured as [connections.<name>] with a url in .streamlit/secrets.toml.
+# save_sql checks the table's constraints, then writes it in one transaction:
+# append inserts the rows, replace deletes the existing rows first, and upsert
+# replaces the rows whose key already exists and inserts the others. On any
+# problem nothing is written. As with write_csv, a restricted table saves only
+# rows the user may see, and replace keeps the others.
+
+SAVE_MODES = ('append', 'replace', 'upsert')
+
+
+def load_sql(connection, name):
+    """Load every row of a database table"""
+    conn = st.connection(connection, type='sql')
+    return conn.query(f'SELECT * FROM {_sql_name(conn.engine, name)}', ttl=0)
+
+
+def save_sql(df, connection, name, mode='append', constraints=None, table='table', visible=None):
+    """Write a table to a database table; True once it is written"""
+    import sqlalchemy
+
+    if visible is not None and not _only_visible_rows(df, visible, table):
+        return False
+    constraints = constraints or {}
+    problems = constraint_violations(df, constraints, table)
+    key = next((column for column, names in constraints.items() if 'key' in names), None)
+    if mode not in SAVE_MODES:
+        problems.append(f"{table}: unknown save mode '{mode}'")
+    elif mode == 'upsert' and key is None:
+        problems.append(f'{table}: upsert needs a key field')
+    for problem in problems:
+        st.error(problem)
+    if problems:
+        return False
+
+    engine = st.connection(connection, type='sql').engine
+    quoted = _sql_name(engine, name)
+    try:
+        with engine.begin() as db:
+            exists = sqlalchemy.inspect(db).has_table(name)
+            if exists and mode == 'replace':
+                if visible is not None:
+                    existing = pd.read_sql(sqlalchemy.text(f'SELECT * FROM {quoted}'), db)
+                    df = pd.concat([existing[~visible(existing)], df], ignore_index=True)
+                db.execute(sqlalchemy.text(f'DELETE FROM {quoted}'))
+            elif exists and mode == 'upsert':
+                delete = sqlalchemy.text(f'DELETE FROM {quoted} WHERE {_sql_name(engine, key)} = :key')
+                db.execute(delete, [{'key': value} for value in df[key].tolist()])
+            df.to_sql(name, db, if_exists='append', index=False)
+    except sqlalchemy.exc.SQLAlchemyError as error:
+        st.error(f'{table}: could not save to {name}: {error}')
+        return False
+    clear_derived_tables()
+    return True
+
+
+def constraint_violations(df, constraints, table='table'):
+    """Rows breaking the key, unique and non-null constraints of a table"""
+    problems = []
+    for column, names in constraints.items():
+        if column not in df.columns:
+            continue
+        values = df[column]
+        if ('key' in names or 'non_null' in names) and values.isna().any():
+            problems.append(f"{table}: column '{column}' has {int(values.isna().sum())} missing value(s)")
+        if 'key' in names or 'unique' in names:
+            duplicates = values.dropna()[values.dropna().duplicated()].unique().tolist()
+            if duplicates:
+                shown = ', '.join(str(value) for value in duplicates[:5])
+                problems.append(f"{table}: column '{column}' has duplicate value(s) {shown}")
+    return problems
+
+
+def _sql_name(engine, name):
+    return engine.dialect.identifier_preparer.quote(name)
+
+
+# Derived tables
+#
+# `table Name = expression` items are loaded through cached_table, which
+# computes each derived table once per session and shares it between pages.
+# Every save with write_csv or save_sql clears the cache, so derived tables
+# are computed again from the saved data. The cache is kept per session, as
+# restricted tables differ between users.
+#
+# Materialized tables are kept instead in a CSV file in the data directory,
+# shared by every session: `materialize on_build` files are written by wtc
+# build, and `materialize daily` ones by refresh_tables.py, or by the first
+# load once the file is a day old.
+
+_derived_outside_streamlit = {}
+
+
+def cached_table(name, compute):
+    """A derived table, computed by compute the first time the session uses it"""
+    cache = _derived_tables()
+    if name not in cache:
+        cache[name] = compute()
+    return cache[name].copy()
+
+
+def materialized_table(name, path, compute=None, max_age=None):
+    """A derived table kept in a CSV file, computed again by compute once
+    the file is older than max_age seconds"""
+    path = _data_path(path)
+    if compute is not None:
+        stale = not os.path.exists(path) or (max_age is not None and time.time() - os.path.getmtime(path) > max_age)
+        if stale:
+            refresh_table(path, compute)
+    return cached_table(name, lambda: pd.read_csv(path))
+
+
+def refresh_table(path, compute):
+    """Compute a materialized table and replace its CSV file"""
+    df = compute()
+    path = os.path.abspath(_data_path(path))
+    with _file_lock(path + '.lock'):
+        descriptor, temp_path = tempfile.mkstemp(dir=os.path.dirname(path), prefix=f'.{os.path.basename(path)}.', suffix='.tmp')
+        try:
+            with os.fdopen(descriptor, 'w', newline='') as file:
+                df.to_csv(file, index=False)
+            os.replace(temp_path, path)
+        except BaseException:
+            if os.path.exists(temp_path):
+                os.remove(temp_path)
+            raise
+
+
+def clear_derived_tables():
+    """Compute every derived table again when it is next used"""
+    _derived_tables().clear()
+
+
+def _derived_tables():
+    session_state = getattr(st, 'session_state', None)
+    if session_state is None:
+        return _derived_outside_streamlit
+    return session_state.setdefault('wt_derived_tables', {})
+
+
+# Scheduled refresh
+#
+# `refresh every 5m` reruns a page on a timer with streamlit-autorefresh, for
+# dashboards that follow changing data. Timed reruns clear the session's
+# derived tables, so they are computed again from the current data; reruns
+# caused by the user keep them.
+
+
+def refresh_every(seconds, key):
+    """Rerun the page every `seconds`, computing the derived tables again on each timed rerun"""
+    from streamlit_autorefresh import st_autorefresh
+    count = st_autorefresh(interval=seconds * 1000, key=key)
+    state_key = f'wt_{key}_count'
+    if count and st.session_state.get(state_key) != count:
+        clear_derived_tables()
+    st.session_state[state_key] = count
+
+
+# Audit trail
+#
+# show_editable(table, audit(...)) records every edit made in the editor: when
+# and by whom, and which rows were added or removed and which values changed,
+# one record per change, appended to a CSV file (audit("path")) or a database
+# table (audit("connection", "name")). Streamlit reruns the page after each
+# edit, so every run records what changed since the previous one.
+
+AUDIT_COLUMNS = ['at', 'user', 'table', 'action', 'key', 'column', 'before', 'after']
+
+
+def audit(target, name=None):
+    """Where the edits of an editable table are recorded"""
+    return (target, name)
+
+
+def audit_edits(edited, original, target, editor, table='table', key=None):
+    """Record the edits made since the last run of the page; returns the edited table"""
+    session_state = getattr(st, 'session_state', {})
+    state_key = f'wt_audit_{editor}'
+    previous = session_state.get(state_key, _records(original))
+    current = _records(edited)
+    session_state[state_key] = current
+    changes = table_changes(previous, current, key)
+    if changes:
+        at = datetime.now(timezone.utc).isoformat(timespec='seconds')
+        user = _current_user()
+        _append_audit([{'at': at, 'user': user, 'table': table, **change} for change in changes], target)
+    return edited
+
+
+def table_changes(before, after, key=None):
+    """Added and removed rows and changed values between two lists of rows
+
+    Rows are matched by their `key` column, or by position without one.
+    """
+    def by_key(rows):
+        return {(row.get(key) if key else index): row for index, row in enumerate(rows)}
+
+    old, new = by_key(before), by_key(after)
+    changes = []
+    for row_key, row in new.items():
+        if row_key not in old:
+            changes.append(_audit_record('added', row_key, '', None, _format_row(row)))
+            continue
+        for column in dict.fromkeys([*old[row_key], *row]):
+            if old[row_key].get(column) != row.get(column):
+                changes.append(_audit_record('changed', row_key, column, old[row_key].get(column), row.get(column)))
+    for row_key, row in old.items():
+        if row_key not in new:
+            changes.append(_audit_record('removed', row_key, '', _format_row(row), None))
+    return changes
+
+
+def _audit_record(action, key, column, before, after):
+    # Stored as text, so the audit table has the same columns for every table
+    text = lambda value: '' if value is None else str(value)
+    return {'action': action, 'key': text(key), 'column': column, 'before': text(before), 'after': text(after)}
+
+
+def _current_user():
+    """Email of the signed-in user, or else the account the app runs under"""
+    email = getattr(getattr(st, 'user', None), 'email', None)
+    if email:
+        return email
+    try:
+        return getpass.getuser()
+    except (KeyError, OSError):
+        return 'unknown'
+
+
+def _append_audit(records, target):
+    location, name = target
+    frame = pd.DataFrame(records, columns=AUDIT_COLUMNS)
+    if name is not None:
+        frame.to_sql(name, st.connection(location, type='sql').engine, if_exists='append', index=False)
+        return
+    path = os.path.abspath(location)
+    with _file_lock(path + '.lock'):
+        header = not os.path.exists(path)
+        with open(path, 'a', newline='') as file:
+            frame.to_csv(file, header=header, index=False)
+
+
+# Table hooks
+#
+# `on_load`, `on_edit` and `on_save` hooks are generated as functions of
+# helpers.py taking the table's rows. Table loaders call on_load hooks and
+# savers call on_save hooks, which cancel the save by returning False. An
+# editor of a table with an on_edit hook passes its result to on_edited, which
+# calls the hook when the rows changed since the previous run of the page.
+
+
+def on_edited(edited, original, editor, hook):
+    """Run an on_edit hook if the table was edited since the last run of the page; returns the edited table"""
+    session_state = getattr(st, 'session_state', {})
+    state_key = f'wt_on_edit_{editor}'
+    previous = session_state.get(state_key, _records(original))
+    current = _records(edited)
+    session_state[state_key] = current
+    if current != previous:
+        hook(edited)
+    return edited
+
+
+# Rows of tables
+#
+# first(table), last(table) and single(table) return one row as a Series,
+# whose fields the generated code reads with row["field"].
+
+
+def first_row(df):
+    """The first row of a table; an empty table is an error"""
+    return _row_at(df, 0, 'first')
+
+
+def last_row(df):
+    """The last row of a table; an empty table is an error"""
+    return _row_at(df, -1, 'last')
+
+
+def single_row(df):
+    """The only row of a table; a table without exactly one row is an error"""
+    if len(df) != 1:
+        raise ValueError(f"single expects exactly one row, found {len(df)}")
+    return df.iloc[0]
+
+
+def _row_at(df, position, name):
+    if df.empty:
+        raise ValueError(f"{name} expects at least one row, found 0")
+    return df.iloc[position]
+
+
+def where_rows(df, condition):
+    """The rows of a table for which condition(row) holds"""
+    return df.loc[[bool(condition(row)) for _, row in df.iterrows()]]
+
+
+# Aggregates
+#
+# sum(table, "column") and average skip missing values, which the generated
+# code makes explicit with skipna=True. With "strict" they go through
+# strict_aggregate, which stops on missing values instead.
+
+
+def strict_aggregate(df, column, method):
+    """The sum or mean of a column; a missing value in it is an error"""
+    missing = int(df[column].isna().sum())
+    if missing:
+        name = 'average' if method == 'mean' else method
+        raise ValueError(f"{name} of '{column}' found {missing} missing value(s)")
+    return getattr(df[column], method)()
+
+
+# Row-level security
+#
+# `restrict Table where condition` rules limit every load of a table to the
+# rows the signed-in user may see. current_user returns the claims of the user
+# signed in through Streamlit's authentication (st.login); a claim the
+# identity provider does not send is None and so matches no row.
+
+
+def current_user():
+    """Claims of the signed-in user; empty when no one is signed in"""
+    user = getattr(st, 'user', None)
+    if user is None or not user.get('is_logged_in', False):
+        return {}
+    return dict(user)
+
+
+def _only_visible_rows(df, visible, table):
+    """Whether every row of a table is one the user may see, warning if not"""
+    hidden = int((~visible(df)).sum())
+    if hidden:
+        st.error(f'{table}: {hidden} row(s) are not ones you may see, so they cannot be saved')
+        return False
+    return True
+
+
+# Confirmations
+#
+# A `confirm "message" { ... }` block in a button shows the message with
+# Confirm and Cancel buttons once the button is clicked. Clicking either
+# reruns the page, so the button's body is kept open by the pending
+# confirmation's session state until one of them is clicked.
+
+
+def confirmed(key, message):
+    """Whether the user confirmed the message; asks them on first call"""
+    st.session_state[key] = True
+    st.warning(message)
+    confirm, cancel = st.columns(2)
+    if confirm.button('Confirm', key=f'{key}_confirm'):
+        del st.session_state[key]
+        return True
+    if cancel.button('Cancel', key=f'{key}_cancel'):
+        del st.session_state[key]
+        st.rerun()
+    return False
+
+
+def revert(editors):
+    """Discard the edits made in the given editors and rerun the page
+
+    The editors then show the tables they were given again, as loaded.
+    """
+    for editor in editors:
+        st.session_state.pop(editor, None)
+    st.rerun()
+
+
+# Logging
+#
+# helpers.py passes its generated LOGGING_CONFIG to configure_logging. The
+# WT_LOG_LEVEL environment variable overrides the configured level.
+
+_LOG_LEVELS = {'info': logging.INFO, 'warn': logging.WARNING, 'error': logging.ERROR}
+
+
+def configure_logging(config):
+    """Apply a logging config once per process
+
+    Streamlit reruns every page on each interaction, so repeated calls are
+    ignored rather than adding handlers again.
+    """
+    if getattr(configure_logging, 'done', False):
+        return
+    config = dict(config, loggers={
+        name: dict(logger, level=os.environ.get('WT_LOG_LEVEL', logger.get('level', 'INFO')).upper())
+        for name, logger in config.get('loggers', {}).items()
+    })
+    logging.config.dictConfig(config)
+    configure_logging.done = True
+
+
+def log_message(message, level='info'):
+    """The `log` builtin"""
+    if level not in _LOG_LEVELS:
+        raise ValueError(f"unknown log level {level!r}; expected 'info', 'warn' or 'error'")
+    logging.getLogger('wtlang.app').log(_LOG_LEVELS[level], message)
+
+
+def traced(stage, line, compute):
+    """Run one pipeline stage, logging how long it took and how many rows it produced"""
+    start = time.perf_counter()
+    result = compute()
+    elapsed = (time.perf_counter() - start) * 1000
+    rows = f', {len(result)} rows' if isinstance(result, pd.DataFrame) else ''
+    logging.getLogger('wtlang.trace').info(f'{stage} (line {line}): {elapsed:.1f} ms{rows}')
+    return result
+
+
+# Profiling
+#
+# Pages built with --profile-instrument call profile_start first, time each
+# table operation with profiled and end with show_profile. Streamlit runs
+# each session's script in its own thread, so the records are per thread.
+
+_profile = threading.local()
+
+
+def profile_start():
+    """Forget the stages recorded by the previous run of the page"""
+    _profile.records = []
+
+
+def profiled(stage, line, compute):
+    """Run one pipeline stage, recording how long it took and how many rows it produced"""
+    start = time.perf_counter()
+    result = compute()
+    elapsed = (time.perf_counter() - start) * 1000
+    rows = len(result) if isinstance(result, pd.DataFrame) else None
+    if not hasattr(_profile, 'records'):
+        profile_start()
+    _profile.records.append((stage, line, round(elapsed, 2), rows))
+    return result
+
+
+def show_profile():
+    """Show the recorded stages in a collapsed Performance section"""
+    records = getattr(_profile, 'records', [])
+    with st.expander('Performance'):
+        if not records:
+            st.write('No table operations ran.')
+            return
+        st.dataframe(pd.DataFrame(records, columns=['stage', 'line', 'ms', 'rows']))
+        st.caption(f'{len(records)} stage(s), {sum(record[2] for record in records):.1f} ms in total')
+
+
+# Translations
+#
+# helpers.py passes the translations of the program's t("key") text to
+# configure_translations. Pages with translated text call select_language,
+# which offers the languages in the sidebar; t looks a key up in the chosen
+# language, then in the default one, and otherwise shows the key itself.
+
+_translations = {}
+_default_language = None
+
+
+def configure_translations(translations, default_language):
+    global _translations, _default_language
+    _translations = translations
+    _default_language = default_language
+
+
+def select_language():
+    """Language selector in the sidebar, when there is more than one language"""
+    languages = sorted(_translations)
+    if len(languages) > 1:
+        st.sidebar.selectbox('Language', languages, index=languages.index(_default_language), key='wt_language')
+
+
+def t(key, values=None):
+    """Text of a key in the current language, with {name} placeholders filled from values"""
+    language = st.session_state.get('wt_language', _default_language) if _translations else None
+    text = _translations.get(language, {}).get(key) or _translations.get(_default_language, {}).get(key) or key
+    if values:
+        text = text.format_map(_Placeholders(values))
+    return text
+
+
+class _Placeholders(dict):
+    """Leaves placeholders without a value as written"""
+
+    def __missing__(self, name):
+        return '{' + name + '}'
+
+
+# Number, date and currency formats
+#
+# helpers.py passes the [format] settings of the project's wt.toml to
+# configure_formats; settings left out keep the US conventions. Read-only
+# tables are shown through formatted, and format_number, format_currency,
+# format_date and percent turn single values into text. round_number,
+# floor_number and ceil_number keep missing values missing.
+
+_formats = {
+    'decimal_separator': '.',
+    'thousands_separator': ',',
+    'date_format': '%Y-%m-%d',
+    'currency_symbol': '$',
+    'currency_position': 'before',
+}
+_formats_configured = False
+
+
+def configure_formats(formats):
+    global _formats_configured
+    _formats.update(formats)
+    _formats_configured = True
+
+
+def format_number(value, decimals=2):
+    """A number with the configured separators
+
+    `decimals` is a number of decimals, or a pattern such as '#,##0.0' whose
+    ',' groups thousands and whose digits after '.' are the decimals shown.
+    """
+    if value is None or pd.isna(value):
+        return ''
+    grouping = ','
+    if isinstance(decimals, str):
+        whole, _, fraction = decimals.partition('.')
+        grouping = ',' if ',' in whole else ''
+        decimals = len(fraction)
+    text = f'{value:{grouping}.{decimals}f}'
+    separators = {',': _formats['thousands_separator'], '.': _formats['decimal_separator']}
+    return ''.join(separators.get(c, c) for c in text)
+
+
+def format_currency(value, decimals=2):
+    """An amount with the configured currency symbol"""
+    if value is None or pd.isna(value):
+        return ''
+    sign = '-' if value < 0 else ''
+    number = format_number(abs(value), decimals)
+    if _formats['currency_position'] == 'after':
+        return f"{sign}{number} {_formats['currency_symbol']}"
+    return f"{sign}{_formats['currency_symbol']}{number}"
+
+
+def percent(value, decimals=1):
+    """A fraction as a percentage, 0.125 as 12.5%"""
+    if value is None or pd.isna(value):
+        return ''
+    return f'{format_number(value * 100, decimals)}%'
+
+
+def round_number(value, decimals=None):
+    """A number rounded to whole units, or to a number of decimals"""
+    if value is None or pd.isna(value):
+        return None
+    return round(value, decimals) if decimals is not None else int(round(value))
+
+
+def floor_number(value):
+    """The largest whole number not above a number"""
+    if value is None or pd.isna(value):
+        return None
+    return math.floor(value)
+
+
+def ceil_number(value):
+    """The smallest whole number not below a number"""
+    if value is None or pd.isna(value):
+        return None
+    return math.ceil(value)
+
+
+def format_date(value):
+    """A date, or a date string, in the configured format"""
+    if value is None or pd.isna(value):
+        return ''
+    try:
+        return pd.Timestamp(value).strftime(_formats['date_format'])
+    except (ValueError, TypeError):
+        return str(value)
+
+
+def formatted(df, schema=None):
+    """A table styled with the configured formats, or unchanged without any
+
+    `schema` maps columns to their WTLang types, so that currency and date
+    columns are recognized; other columns are formatted by their dtype.
+    """
+    if not _formats_configured or not isinstance(df, pd.DataFrame):
+        return df
+    schema = schema or {}
+    formatters = {}
+    for column in df.columns:
+        wt_type = schema.get(column)
+        if wt_type == 'currency':
+            formatters[column] = format_currency
+        elif wt_type == 'date' or pd.api.types.is_datetime64_any_dtype(df[column]):
+            formatters[column] = format_date
+        elif wt_type == 'float' or pd.api.types.is_float_dtype(df[column]):
+            formatters[column] = format_number
+        elif wt_type == 'int' or pd.api.types.is_integer_dtype(df[column]):
+            formatters[column] = lambda value: format_number(value, 0)
+    return df.style.format(formatters)
+
+
+# Email and PDF export
+#
+# send_email sends through the SMTP server in the [smtp] section of the app's
+# .streamlit/secrets.toml; export_pdf renders a page section with pdfkit,
+# which needs wkhtmltopdf (its path can be set as wkhtmltopdf in a [pdf]
+# section). Without that configuration they show an error on the page rather
+# than failing. An external function of the same name replaces either one.
+
+def send_email(to, subject, body, attach=None, attach_name='data.csv'):
+    """Send an email, with a table attached as CSV; True once it is sent"""
+    try:
+        smtp = st.secrets['smtp']
+    except (KeyError, FileNotFoundError):
+        st.error('Email is not configured: add an [smtp] section with host, port, username, password and sender to .streamlit/secrets.toml')
+        return False
+    message = EmailMessage()
+    message['From'] = smtp.get('sender') or smtp.get('username')
+    message['To'] = ', '.join(to) if isinstance(to, (list, tuple)) else to
+    message['Subject'] = subject
+    message.set_content(body)
+    if attach is not None:
+        data = pd.DataFrame(attach).to_csv(index=False).encode()
+        message.add_attachment(data, maintype='text', subtype='csv', filename=attach_name)
+    try:
+        with smtplib.SMTP(smtp['host'], int(smtp.get('port', 587))) as server:
+            if smtp.get('starttls', True):
+                server.starttls()
+            if smtp.get('username'):
+                server.login(smtp['username'], smtp['password'])
+            server.send_message(message)
+    except (OSError, smtplib.SMTPException) as error:
+        st.error(f'Could not send the email: {error}')
+        return False
+    return True
+
+
+def export_pdf(title, parts):
+    """Download button for a PDF of a page section; True once it is offered
+
+    `parts` are the section's (tag, text) headings and paragraphs and its
+    ('table', table) tables, in order.
+    """
+    body = [f'<h1>{html.escape(str(title))}</h1>']
+    for tag, value in parts:
+        if tag == 'table':
+            body.append(formatted(pd.DataFrame(value)).to_html())
+        else:
+            body.append(f'<{tag}>{html.escape(str(value))}</{tag}>')
+    document = f'<html><head><meta charset="utf-8"></head><body>{"".join(body)}</body></html>'
+    try:
+        import pdfkit
+        try:
+            options = {'wkhtmltopdf': st.secrets['pdf']['wkhtmltopdf']}
+        except (KeyError, FileNotFoundError):
+            options = {}
+        pdf = pdfkit.from_string(document, False, configuration=pdfkit.configuration(**options))
+    except (ImportError, OSError) as error:
+        st.error(f'PDF export is not available: install pdfkit and wkhtmltopdf ({error})')
+        return False
+    st.download_button(f'Download {title} (PDF)', pdf, file_name=f'{title}.pdf', mime='application/pdf', key=f'wt_pdf_{title}')
+    return True
+
+
+# Table assertions
+#
+# Used by test blocks. Tables may be dataframes or lists of row dicts; a
+# failed assertion explains which rows differ.
+
+def assert_equal_tables(actual, expected, ignore_order=False):
+    """Assert that two tables have the same columns and rows
+
+    Rows are compared in order unless `ignore_order` is set. The failure
+    message lists added, removed and changed rows.
+    """
+    actual_columns, actual_rows = _columns(actual), _records(actual)
+    expected_columns, expected_rows = _columns(expected), _records(expected)
+    
+    problems = []
+    missing = [c for c in expected_columns if c not in actual_columns]
+    extra = [c for c in actual_columns if c not in expected_columns]
+    if missing:
+        problems.append(f"missing column(s): {', '.join(map(str, missing))}")
+    if extra:
+        problems.append(f"unexpected column(s): {', '.join(map(str, extra))}")
+    
+    if ignore_order:
+        remaining = list(expected_rows)
+        added = []
+        for row in actual_rows:
+            if row in remaining:
+                remaining.remove(row)
+            else:
+                added.append(row)
+        removed, changed = remaining, []
+    else:
+        changed = [
+            (index, want, got)
+            for index, (got, want) in enumerate(zip(actual_rows, expected_rows))
+            if got != want
+        ]
+        added = actual_rows[len(expected_rows):]
+        removed = expected_rows[len(actual_rows):]
+    
+    for row in added:
+        problems.append(f"+ added row {_format_row(row)}")
+    for row in removed:
+        problems.append(f"- removed row {_format_row(row)}")
+    for index, want, got in changed:
+        differences = ', '.join(
+            f"{column}: {want.get(column)!r} -> {got.get(column)!r}"
+            for column in dict.fromkeys([*want, *got])
+            if want.get(column) != got.get(column)
+        )
+        problems.append(f"~ changed row {index}: {differences}")
+    
+    if problems:
+        raise AssertionError(
+            f"tables differ ({len(actual_rows)} actual rows, {len(expected_rows)} expected):\n"
+            + '\n'.join(problems)
+        )
+
+
+def assert_row_count(table, count):
+    """Assert that a table has exactly `count` rows"""
+    rows = _records(table)
+    if len(rows) != count:
+        preview = '\n'.join(_format_row(row) for row in rows[:5])
+        more = f"\n... {len(rows) - 5} more row(s)" if len(rows) > 5 else ''
+        raise AssertionError(f"expected {count} row(s), found {len(rows)}:\n{preview}{more}")
+
+
+def assert_contains(table, row):
+    """Assert that some row of a table has all the values in `row`"""
+    rows = _records(table)
+    wanted = {column: _normalize(value) for column, value in row.items()}
+    if any(all(r.get(c) == v for c, v in wanted.items()) for r in rows):
+        return
+    
+    # Show the rows sharing the most values, to point at what differs
+    def matches(r):
+        return sum(r.get(c) == v for c, v in wanted.items())
+    closest = sorted(rows, key=matches, reverse=True)[:3]
+    preview = '\n'.join(_format_row(r) for r in closest) or '(table is empty)'
+    raise AssertionError(f"no row matches {_format_row(wanted)}; closest rows:\n{preview}")
+
+
+def _columns(table):
+    if isinstance(table, list):
+        return list(dict.fromkeys(column for row in table for column in row))
+    return list(table.columns)
+
+
+def _records(table):
+    rows = table if isinstance(table, list) else table.to_dict('records')
+    return [{column: _normalize(value) for column, value in row.items()} for row in rows]
+
+
+def _normalize(value):
+    """Treat every kind of missing value as None"""
+    if value is None or (isinstance(value, float) and math.isnan(value)):
+        return None
+    return value
+
+
+def _format_row(row):
+    return '{' + ', '.join(f"{column}: {value!r}" for column, value in row.items()) + '}'
+
+
+# Test data generation
+#
+# `mock Table with generated(n)` in a test block runs the test on generated
+# tables: first on edge cases (no rows, one row, a few rows), then on n
+# rows. When the test fails, the failing tables are shrunk to the fewest
+# rows that still fail before the failure is reported.
+
+_EDGE_VALUES = {
+    'int': [0, 1, -1, 2, 7, 100, 2**31 - 1],
+    'float': [0.0, 0.5, -1.5, 3.14, 1e6],
+    'currency': [0.0, 0.01, 9.99, 100.0, 12345.67],
+    'string': ['', 'a', 'A b', 'x' * 50, 'ünïcödé', "O'Brien", 'a,b'],
+    'bool': [True, False],
+    'date': ['2024-01-01', '2024-02-29', '2000-12-31', '1970-01-01', '2099-06-15'],
+}
+
+
+def generate_tables(specs, seed=0, rows=None):
+    """Generate one dataframe per table spec
+
+    `specs` maps table names to `(schema, constraints, refs, n)`:
+    `constraints` maps columns to a list of "key", "unique" and "non_null",
+    and `refs` maps ref columns to `(table, key_column, key_type)`. Ref
+    columns take key values of the referenced table when it is generated
+    too. `rows` overrides every table's row count.
+    """
+    rng = random.Random(seed)
+    frames = {}
+    for table in _ref_order(specs):
+        schema, constraints, refs, n = specs[table]
+        count = n if rows is None else min(rows, n)
+        columns = {}
+        for column, wt_type in schema.items():
+            column_constraints = constraints.get(column, [])
+            unique = 'key' in column_constraints or 'unique' in column_constraints
+            nullable = not unique and 'non_null' not in column_constraints
+            if column in refs:
+                target, key, key_type = refs[column]
+                if target in frames and len(frames[target]):
+                    pool = frames[target][key].tolist()
+                    values = [rng.choice(pool) for _ in range(count)]
+                else:
+                    values = [_generate_value(rng, key_type) for _ in range(count)]
+            elif unique:
+                values = _unique_values(rng, wt_type, count)
+            else:
+                values = [_generate_value(rng, wt_type) for _ in range(count)]
+            if nullable:
+                values = [None if rng.random() < 0.2 else value for value in values]
+            columns[column] = values
+        frames[table] = pd.DataFrame(columns, columns=list(schema))
+    return frames
+
+
+def check_generated(run, specs, seed=0):
+    """Run a test body on generated tables, shrinking them on failure"""
+    largest = max((spec[3] for spec in specs.values()), default=0)
+    sizes = sorted({0, 1, min(5, largest), largest})
+    for attempt, size in enumerate(sizes):
+        frames = generate_tables(specs, seed + attempt, rows=size)
+        error = _failure(run, frames)
+        if error is None:
+            continue
+        frames = _shrink(run, frames, specs, type(error))
+        report = '\n'.join(
+            f"{table} ({len(df)} rows):\n{df.to_string(index=False)}"
+            for table, df in frames.items()
+        )
+        reason = f"{type(error).__name__}: {error}" if str(error) else type(error).__name__
+        raise AssertionError(
+            f"test failed on generated data ({reason}); smallest failing tables:\n{report}"
+        ) from error
+
+
+def _failure(run, frames):
+    """Run the test body, returning the exception it raised, if any"""
+    try:
+        run({table: df.copy() for table, df in frames.items()})
+    except Exception as error:
+        return error
+    return None
+
+
+def _shrink(run, frames, specs, error_type, budget=500):
+    """Remove rows while the test keeps failing with the same error type
+
+    Referring tables are shrunk first, and rows whose key is still referenced
+    are kept so the shrunk tables stay consistent.
+    """
+    frames = dict(frames)
+    for table in reversed(list(frames)):
+        chunk = max(len(frames[table]) // 2, 1)
+        while chunk >= 1 and budget > 0:
+            start = 0
+            while start < len(frames[table]) and budget > 0:
+                df = frames[table]
+                removed = df.iloc[start:start + chunk]
+                if _still_referenced(frames, specs, table, removed):
+                    start += chunk
+                    continue
+                candidate = dict(frames)
+                candidate[table] = pd.concat([df.iloc[:start], df.iloc[start + chunk:]], ignore_index=True)
+                budget -= 1
+                if isinstance(_failure(run, candidate), error_type):
+                    frames = candidate
+                else:
+                    start += chunk
+            chunk //= 2
+    return frames
+
+
+def _still_referenced(frames, specs, table, rows):
+    """Whether another generated table refers to the key of any of `rows`"""
+    for other, (_, _, refs, _) in specs.items():
+        for column, (target, key, _) in refs.items():
+            if target == table and other in frames:
+                referenced = set(frames[other][column].tolist())
+                if any(value in referenced for value in rows[key].tolist()):
+                    return True
+    return False
+
+
+def _ref_order(specs):
+    """Table names with referenced tables before the tables referring to them"""
+    order = []
+    
+    def visit(table, visiting):
+        if table in order or table in visiting or table not in specs:
+            return
+        visiting.add(table)
+        for target, _, _ in specs[table][2].values():
+            visit(target, visiting)
+        order.append(table)
+    
+    for table in specs:
+        visit(table, set())
+    return order
+
+
+def _generate_value(rng, wt_type):
+    """A random value of a WTLang type, often one of its edge cases"""
+    if rng.random() < 0.3:
+        return rng.choice(_EDGE_VALUES.get(wt_type, _EDGE_VALUES['string']))
+    if wt_type == 'int':
+        return rng.randint(-1000, 1000)
+    if wt_type == 'float':
+        return round(rng.uniform(-1000, 1000), 3)
+    if wt_type == 'currency':
+        return round(rng.uniform(0, 10000), 2)
+    if wt_type == 'bool':
+        return rng.random() < 0.5
+    if wt_type == 'date':
+        return f"{rng.randint(1990, 2030)}-{rng.randint(1, 12):02}-{rng.randint(1, 28):02}"
+    return ''.join(rng.choice('abcdefghij ') for _ in range(rng.randint(1, 12)))
+
+
+def _unique_values(rng, wt_type, count):
+    """`count` distinct values of a WTLang type"""
+    if wt_type == 'int':
+        return rng.sample(range(-count, 10 * count + 10), count)
+    if wt_type == 'bool':
+        if count > 2:
+            raise ValueError(f"cannot generate {count} distinct bool values")
+        return [True, False][:count]
+    if wt_type in ('float', 'currency'):
+        return [round(value * 0.5, 2) for value in rng.sample(range(10 * count + 10), count)]
+    if wt_type == 'date':
+        start = pd.Timestamp('2000-01-01')
+        days = rng.sample(range(20000), count)
+        return [(start + pd.Timedelta(days=day)).strftime('%Y-%m-%d') for day in days]
+    return [f"{_generate_value(rng, 'string')}#{index}" for index in range(count)]
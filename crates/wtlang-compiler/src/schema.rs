@@ -0,0 +1,193 @@
+// JSON Schema and OpenAPI documents of a program's tables
+//
+// Used by `wtc export-schema` so tools outside WTLang, such as API gateways
+// and form generators, can read the tables: a schema per table with the
+// fields' types, which of them are required, and the constraints and
+// references JSON Schema has no words for, as `x-wtlang-*` keywords.
+// OpenAPI 3.1 uses JSON Schema 2020-12, so both formats share the schemas.
+
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use wtlang_core::ir::{Constraint, FieldType, IRItem, IRModule, TableSchema};
+
+/// Kind of document to export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A JSON Schema with the tables under `$defs`
+    #[value(name = "jsonschema")]
+    JsonSchema,
+    /// An OpenAPI 3.1 document with the tables under `components.schemas`
+    #[value(name = "openapi")]
+    OpenApi,
+}
+
+/// The tables of a program in the given format
+pub fn export(module: &IRModule, format: Format, source: &str) -> Value {
+    let title = app_title(module).unwrap_or(source);
+    let schemas: Map<String, Value> = module.table_names().into_iter()
+        .filter_map(|name| module.find_table(name))
+        .map(|schema| (schema.name.to_string(), table_schema(schema, module)))
+        .collect();
+    match format {
+        Format::JsonSchema => json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": title,
+            "$defs": schemas,
+        }),
+        Format::OpenApi => json!({
+            "openapi": "3.1.0",
+            "info": { "title": title, "version": "1.0.0" },
+            "paths": {},
+            "components": { "schemas": schemas },
+        }),
+    }
+}
+
+/// Title from the program's `config` block
+fn app_title(module: &IRModule) -> Option<&str> {
+    module.items.iter().find_map(|item| match item {
+        IRItem::Config { settings, .. } => settings.iter()
+            .find(|(name, _)| *name == "title")
+            .map(|(_, value)| value.as_str()),
+        _ => None,
+    })
+}
+
+/// Schema of one row of a table
+fn table_schema(schema: &TableSchema, module: &IRModule) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for field in &schema.fields {
+        let constrained = |wanted: fn(&Constraint) -> bool| {
+            schema.constraints.iter().any(|constraint| wanted(constraint) && constraint.field() == field.name)
+        };
+        let key = constrained(|c| matches!(c, Constraint::PrimaryKey(_)));
+        let unique = constrained(|c| matches!(c, Constraint::Unique(_)));
+        let non_null = constrained(|c| matches!(c, Constraint::NonNull(_)));
+
+        let mut property = field_schema(&field.ty, module, !(key || non_null));
+        if key {
+            property.insert("x-wtlang-key".to_string(), json!(true));
+        }
+        if unique {
+            property.insert("x-wtlang-unique".to_string(), json!(true));
+        }
+        if let Some(header) = &field.header {
+            property.insert("x-wtlang-header".to_string(), json!(header));
+        }
+        if key || non_null {
+            required.push(field.name.to_string());
+        }
+        properties.insert(field.name.to_string(), Value::Object(property));
+    }
+
+    json!({
+        "type": "object",
+        "title": schema.name.as_str(),
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Schema of a field's values; a reference holds a key of the table it
+/// refers to, so it takes that key's type
+fn field_schema(ty: &FieldType, module: &IRModule, nullable: bool) -> Map<String, Value> {
+    let mut property = Map::new();
+    let type_name = match ty {
+        FieldType::Int => "integer",
+        FieldType::Float | FieldType::Currency => "number",
+        FieldType::String | FieldType::Date => "string",
+        FieldType::Bool => "boolean",
+        FieldType::Ref { table_name } => {
+            let key = module.find_table(table_name).and_then(|table| table.get_key_field());
+            let mut property = match key {
+                Some(key) if !matches!(key.ty, FieldType::Ref { .. }) => field_schema(&key.ty, module, nullable),
+                _ => field_schema(&FieldType::String, module, nullable),
+            };
+            let mut reference = Map::new();
+            reference.insert("table".to_string(), json!(table_name.as_str()));
+            if let Some(key) = key {
+                reference.insert("field".to_string(), json!(key.name.as_str()));
+            }
+            property.insert("x-wtlang-ref".to_string(), Value::Object(reference));
+            return property;
+        }
+    };
+    property.insert("type".to_string(), if nullable { json!([type_name, "null"]) } else { json!(type_name) });
+    match ty {
+        FieldType::Date => {
+            property.insert("format".to_string(), json!("date"));
+        }
+        FieldType::Currency => {
+            property.insert("x-wtlang-type".to_string(), json!("currency"));
+        }
+        _ => {}
+    }
+    property
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{IRBuilder, Lexer, Parser};
+
+    fn module(source: &str) -> IRModule {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        IRBuilder::new().build(&program).unwrap()
+    }
+
+    const SOURCE: &str = r#"
+table Customer {
+    id: int [key],
+    name: string [non_null],
+    email: string [unique]
+}
+
+table Order {
+    number: int [key],
+    customer: ref Customer,
+    placed: date,
+    total: currency from "Total (€)"
+}
+"#;
+
+    #[test]
+    fn test_json_schema() {
+        let document = export(&module(SOURCE), Format::JsonSchema, "shop.wt");
+        assert_eq!(document["title"], "shop.wt");
+        assert_eq!(document["$defs"]["Customer"], json!({
+            "type": "object",
+            "title": "Customer",
+            "properties": {
+                "id": { "type": "integer", "x-wtlang-key": true },
+                "name": { "type": "string" },
+                "email": { "type": ["string", "null"], "x-wtlang-unique": true },
+            },
+            "required": ["id", "name"],
+            "additionalProperties": false,
+        }));
+        let order = &document["$defs"]["Order"]["properties"];
+        assert_eq!(order["customer"], json!({
+            "type": ["integer", "null"],
+            "x-wtlang-ref": { "table": "Customer", "field": "id" },
+        }));
+        assert_eq!(order["placed"], json!({ "type": ["string", "null"], "format": "date" }));
+        assert_eq!(order["total"], json!({
+            "type": ["number", "null"],
+            "x-wtlang-type": "currency",
+            "x-wtlang-header": "Total (€)",
+        }));
+    }
+
+    #[test]
+    fn test_openapi() {
+        let source = format!("config {{ title = \"Shop\" }}\n{}", SOURCE);
+        let module = module(&source);
+        let document = export(&module, Format::OpenApi, "shop.wt");
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(document["info"]["title"], "Shop");
+        assert_eq!(document["components"]["schemas"]["Order"], export(&module, Format::JsonSchema, "shop.wt")["$defs"]["Order"]);
+    }
+}
@@ -0,0 +1,152 @@
+// Durations of the stages of a build, for `-v` and `--timings`
+
+use std::time::{Duration, Instant};
+use wtlang_core::{Program, ProgramItem, Statement};
+
+/// A finished stage and what it produced, e.g. `1234 tokens`
+struct Stage {
+    name: &'static str,
+    duration: Duration,
+    detail: String,
+}
+
+/// Stage durations of one build
+///
+/// Each stage is logged at info level as it finishes, which `-v` shows;
+/// `report` gives the table `--timings` prints at the end.
+#[derive(Default)]
+pub struct Timings {
+    stages: Vec<Stage>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Timings::default()
+    }
+
+    /// Run a stage, recording how long it took
+    pub fn time<T>(&mut self, name: &'static str, stage: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = stage();
+        self.stages.push(Stage { name, duration: start.elapsed(), detail: String::new() });
+        result
+    }
+
+    /// Describe what the last stage produced, and log the stage
+    pub fn detail(&mut self, detail: String) {
+        if let Some(stage) = self.stages.last_mut() {
+            stage.detail = detail;
+            tracing::info!(
+                stage = stage.name,
+                duration_ms = (stage.duration.as_secs_f64() * 1e6).round() / 1000.0,
+                "{}",
+                stage.detail
+            );
+        }
+    }
+
+    /// One line per stage and the total
+    pub fn report(&self) -> String {
+        let mut report = String::from("\nTimings:\n");
+        let total: Duration = self.stages.iter().map(|stage| stage.duration).sum();
+        for stage in &self.stages {
+            let share = match total.as_nanos() {
+                0 => 0.0,
+                all => stage.duration.as_nanos() as f64 * 100.0 / all as f64,
+            };
+            report.push_str(&format!(
+                "  {:<11} {:>9} {:>5.1}%  {}\n",
+                stage.name, format_duration(stage.duration), share, stage.detail
+            ));
+        }
+        report.push_str(&format!("  {:<11} {:>9}\n", "total", format_duration(total)));
+        report
+    }
+}
+
+/// Milliseconds with a precision that suits the duration
+fn format_duration(duration: Duration) -> String {
+    let ms = duration.as_secs_f64() * 1000.0;
+    if ms < 10.0 {
+        format!("{:.2}ms", ms)
+    } else {
+        format!("{:.0}ms", ms)
+    }
+}
+
+/// An item's kind and name, e.g. `page Home`
+pub fn item_summary(item: &ProgramItem) -> String {
+    match item {
+        ProgramItem::TableDef(table) => format!("table {} ({} fields)", table.name, table.fields.len()),
+        ProgramItem::Page(page) => format!("page {} ({} statements)", page.name, count_statements(&page.statements)),
+        ProgramItem::FunctionDef(func) => format!("function {} ({} statements)", func.name, count_statements(&func.body)),
+        ProgramItem::ExternalFunction(func) => format!("external function {}", func.name),
+        ProgramItem::Test(test) => format!("test \"{}\" ({} statements)", test.name, count_statements(&test.body)),
+        ProgramItem::Restriction(restriction) => format!("restriction on {}", restriction.table),
+        ProgramItem::DerivedTable(derived) => format!("derived table {}", derived.name),
+        ProgramItem::SharedVariable(shared) => format!("shared {}", shared.name),
+        ProgramItem::Constant(constant) => format!("const {}", constant.name),
+        ProgramItem::Import(import) => format!("import \"{}\"", import.path),
+        ProgramItem::Hook(hook) => format!("{} hook of {} ({} statements)", hook.event.keyword(), hook.table, count_statements(&hook.body)),
+        ProgramItem::Config(config) => format!("config ({} settings)", config.settings.len()),
+    }
+}
+
+/// Statements in the program, counting nested ones
+pub fn statement_count(program: &Program) -> usize {
+    program.items.iter()
+        .map(|item| match item {
+            ProgramItem::Page(page) => count_statements(&page.statements),
+            ProgramItem::FunctionDef(func) => count_statements(&func.body),
+            ProgramItem::Test(test) => count_statements(&test.body),
+            ProgramItem::Hook(hook) => count_statements(&hook.body),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn count_statements(statements: &[Statement]) -> usize {
+    statements.iter()
+        .map(|stmt| 1 + match stmt {
+            Statement::Button { body, .. }
+            | Statement::Section { body, .. }
+            | Statement::Confirm { body, .. }
+            | Statement::Forall { body, .. } => count_statements(body),
+            Statement::If { then_branch, else_branch, .. } => {
+                count_statements(then_branch) + else_branch.as_deref().map_or(0, count_statements)
+            }
+            Statement::Match { arms, .. } => arms.iter().map(|arm| count_statements(&arm.body)).sum(),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{Lexer, Parser};
+
+    #[test]
+    fn test_statement_count() {
+        let source = "page Home {\n  title \"Home\"\n  if true {\n    text \"a\"\n  } else {\n    button \"b\" { text \"c\" }\n  }\n}";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(statement_count(&program), 5);
+    }
+
+    #[test]
+    fn test_report_lists_stages_and_total() {
+        let mut timings = Timings::new();
+        let value = timings.time("lex", || 42);
+        timings.detail("3 tokens".to_string());
+        timings.time("parse", || ());
+        assert_eq!(value, 42);
+
+        let report = timings.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[1], "Timings:");
+        assert!(lines[2].trim_start().starts_with("lex") && lines[2].ends_with("3 tokens"));
+        assert!(lines[3].trim_start().starts_with("parse"));
+        assert!(lines[4].trim_start().starts_with("total"));
+    }
+}
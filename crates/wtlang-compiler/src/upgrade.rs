@@ -0,0 +1,175 @@
+// Upgrades of source files to a newer language version
+//
+// Used by `wtc upgrade` when a language version reads some of a program's
+// syntax differently than the version it was written for. Each migration
+// rewrites only the tokens concerned, so comments and layout stay as
+// written, and the `#version` line is raised to the version upgraded to.
+
+use std::collections::HashSet;
+use wtlang_core::lexer::StringPart;
+use wtlang_core::{LanguageVersion, Lexer, Token, TokenType};
+
+/// Replacement of the source's bytes from `start` up to (not including) `end`
+type Edit = (usize, usize, String);
+
+/// Rewrites taking a program to version `to` from the one before it
+pub struct Migration {
+    pub to: LanguageVersion,
+    pub description: &'static str,
+    rewrite: fn(&[Token], &str) -> Vec<Edit>,
+}
+
+/// Every migration, oldest version first
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: LanguageVersion::V0_2,
+        description: "names that became keywords, such as `shared` and `restrict`, get a trailing `_`",
+        rewrite: rename_new_keywords,
+    },
+];
+
+/// A migration applied to a file, with how many places it rewrote
+pub struct Applied {
+    pub migration: &'static Migration,
+    pub changes: usize,
+}
+
+/// A file's source upgraded to a newer version
+pub struct Upgrade {
+    pub source: String,
+    pub applied: Vec<Applied>,
+}
+
+/// The version a file's `#version` line pins, if any
+pub fn pinned_version(source: &str) -> Option<LanguageVersion> {
+    let (tokens, _) = Lexer::new(source).tokenize_recovering();
+    tokens.iter().find_map(|token| match &token.token_type {
+        TokenType::Version(text) => LanguageVersion::parse(text),
+        _ => None,
+    })
+}
+
+/// Upgrade a program written for `from` to `to`, one migration at a time
+pub fn upgrade(source: &str, from: LanguageVersion, to: LanguageVersion) -> Result<Upgrade, String> {
+    let mut source = source.to_string();
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| from < migration.to && migration.to <= to) {
+        let tokens = Lexer::new(&source).tokenize().map_err(|diagnostics| diagnostics.format_all())?;
+        let edits = (migration.rewrite)(&tokens, &source);
+        if !edits.is_empty() {
+            applied.push(Applied { migration, changes: edits.len() });
+            source = apply(&source, edits);
+        }
+    }
+
+    let tokens = Lexer::new(&source).tokenize().map_err(|diagnostics| diagnostics.format_all())?;
+    if let Some(version) = tokens.iter().find(|token| matches!(token.token_type, TokenType::Version(_))) {
+        let line = format!("#version \"{}\"", to);
+        let written = version.text(&source).trim_end();
+        if written != line {
+            source = apply(&source, vec![(version.start, version.start + written.len(), line)]);
+        }
+    }
+    Ok(Upgrade { source, applied })
+}
+
+fn apply(source: &str, mut edits: Vec<Edit>) -> String {
+    edits.sort_by_key(|(start, _, _)| *start);
+    let mut result = String::with_capacity(source.len());
+    let mut copied = 0;
+    for (start, end, text) in edits {
+        result.push_str(&source[copied..start]);
+        result.push_str(&text);
+        copied = end;
+    }
+    result.push_str(&source[copied..]);
+    result
+}
+
+/// Keywords version 0.2 introduced, which earlier programs may use as names
+fn is_new_keyword(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Restrict | TokenType::Shared | TokenType::OnLoad | TokenType::OnEdit | TokenType::OnSave
+    )
+}
+
+/// Rename variables, fields and functions named after a new keyword,
+/// including where text interpolates them
+fn rename_new_keywords(tokens: &[Token], source: &str) -> Vec<Edit> {
+    let mut names: HashSet<String> = tokens.iter()
+        .filter_map(|token| match &token.token_type {
+            TokenType::Identifier(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut renamed = |word: &str| {
+        let mut name = format!("{}_", word);
+        while names.contains(&name) {
+            name.push('_');
+        }
+        names.insert(name.clone());
+        name
+    };
+
+    let mut edits = Vec::new();
+    let mut renames: Vec<(String, String)> = Vec::new();
+    let mut rename = |word: &str| match renames.iter().find(|(from, _)| from == word) {
+        Some((_, to)) => to.clone(),
+        None => {
+            let to = renamed(word);
+            renames.push((word.to_string(), to.clone()));
+            to
+        }
+    };
+    for token in tokens {
+        match &token.token_type {
+            token_type if is_new_keyword(token_type) => {
+                edits.push((token.start, token.end, rename(token.text(source))));
+            }
+            TokenType::InterpolatedString(parts) => {
+                let text = token.text(source);
+                let mut cursor = 0;
+                for part in parts {
+                    let StringPart::Code { source: code, .. } = part else { continue };
+                    let Some(found) = text[cursor..].find(&format!("{{{}", code)) else { continue };
+                    let offset = token.start + cursor + found + 1;
+                    cursor += found + 1 + code.len();
+                    let (code_tokens, _) = Lexer::new(code).tokenize_recovering();
+                    for code_token in code_tokens.iter().filter(|t| is_new_keyword(&t.token_type)) {
+                        edits.push((offset + code_token.start, offset + code_token.end, rename(code_token.text(code))));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_renames_new_keywords() {
+        let source = "#version \"0.1\"\n// Totals\npage Home {\n    let shared = 1\n    let shared_ = 2  // taken\n    text \"{shared} and {shared_}\"\n}\n";
+        let upgraded = upgrade(source, LanguageVersion::V0_1, LanguageVersion::V0_2).unwrap();
+        assert_eq!(
+            upgraded.source,
+            "#version \"0.2\"\n// Totals\npage Home {\n    let shared__ = 1\n    let shared_ = 2  // taken\n    text \"{shared__} and {shared_}\"\n}\n"
+        );
+        assert_eq!(upgraded.applied.len(), 1);
+        assert_eq!(upgraded.applied[0].changes, 2);
+        assert_eq!(pinned_version(&upgraded.source), Some(LanguageVersion::V0_2));
+    }
+
+    #[test]
+    fn test_upgrade_current_program() {
+        let source = "shared let visits: int = 0\npage Home {\n    text \"hi\"\n}\n";
+        let upgraded = upgrade(source, LanguageVersion::CURRENT, LanguageVersion::CURRENT).unwrap();
+        assert_eq!(upgraded.source, source);
+        assert!(upgraded.applied.is_empty());
+        assert_eq!(pinned_version(source), None);
+    }
+}
@@ -0,0 +1,272 @@
+// Validation files for the CSV data of a program's tables
+//
+// Used by `wtc export-validation` so data teams can check incoming CSV files
+// in their own data-quality pipelines, without running the app: a module of
+// pandera schemas, or a Great Expectations suite per table. Columns are named
+// by their CSV headers, as they appear in the files.
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use wtlang_core::ir::{Constraint, FieldType, IRModule, TableSchema};
+use wtlang_core::Atom;
+
+/// Kind of validation files to generate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A Python module with a pandera schema per table
+    Pandera,
+    /// A Great Expectations suite per table, as JSON
+    GreatExpectations,
+}
+
+/// Name of the pandera module
+pub const PANDERA_MODULE: &str = "schemas.py";
+
+/// How a column is checked, from its field and the table's constraints
+struct Column<'a> {
+    header: &'a str,
+    /// The field's type; a reference takes the type of the key it refers to
+    ty: &'a FieldType,
+    unique: bool,
+    nullable: bool,
+}
+
+fn columns<'a>(schema: &'a TableSchema, module: &'a IRModule) -> Vec<Column<'a>> {
+    let has = |field: Atom, wanted: fn(&Constraint) -> Option<Atom>| {
+        schema.constraints.iter().any(|constraint| wanted(constraint) == Some(field))
+    };
+    schema.fields.iter()
+        .map(|field| {
+            let key = has(field.name, |c| if let Constraint::PrimaryKey(f) = c { Some(*f) } else { None });
+            let unique = has(field.name, |c| if let Constraint::Unique(f) = c { Some(*f) } else { None });
+            let non_null = has(field.name, |c| if let Constraint::NonNull(f) = c { Some(*f) } else { None });
+            Column {
+                header: field.header.as_deref().unwrap_or(field.name.as_str()),
+                ty: referenced_type(&field.ty, module),
+                unique: key || unique,
+                nullable: !(key || non_null),
+            }
+        })
+        .collect()
+}
+
+fn referenced_type<'a>(ty: &'a FieldType, module: &'a IRModule) -> &'a FieldType {
+    match ty {
+        FieldType::Ref { table_name } => match module.find_table(table_name).and_then(|table| table.get_key_field()) {
+            Some(key) if !matches!(key.ty, FieldType::Ref { .. }) => &key.ty,
+            _ => &FieldType::String,
+        },
+        ty => ty,
+    }
+}
+
+/// Python module with a pandera schema per table, which also validates the
+/// CSV files given on its command line
+pub fn pandera_module(module: &IRModule, source: &str) -> String {
+    let mut code = String::new();
+    code.push_str(&format!("# pandera schemas of the tables of {}\n", source));
+    code.push_str("#\n");
+    code.push_str("# Validate CSV files of the tables, e.g.:\n");
+    if let Some(first) = module.table_names().first() {
+        code.push_str(&format!("#     python schemas.py {}=data/{}.csv\n", first, first.to_lowercase()));
+    }
+    code.push_str("# or import SCHEMAS and call SCHEMAS[\"Table\"].validate(df).\n");
+    code.push('\n');
+    code.push_str("import sys\n");
+    code.push('\n');
+    code.push_str("import pandas as pd\n");
+    code.push_str("import pandera as pa\n");
+
+    let mut names = Vec::new();
+    for name in module.table_names() {
+        let schema = module.find_table(name).expect("listed table");
+        let constant = name.to_uppercase();
+        code.push_str("\n\n");
+        code.push_str(&format!("{} = pa.DataFrameSchema(\n", constant));
+        code.push_str("    {\n");
+        for column in columns(schema, module) {
+            let mut args = vec![pandera_dtype(column.ty).to_string()];
+            if column.unique {
+                args.push("unique=True".to_string());
+            }
+            if column.nullable {
+                args.push("nullable=True".to_string());
+            }
+            // Text needs no conversion; CSV files give dates as text and
+            // integer columns with missing values as floats
+            if *column.ty != FieldType::String {
+                args.push("coerce=True".to_string());
+            }
+            code.push_str(&format!("        {}: pa.Column({}),\n", py_string(column.header), args.join(", ")));
+        }
+        code.push_str("    },\n");
+        code.push_str("    strict=True,\n");
+        code.push_str(&format!("    name={},\n", py_string(name)));
+        code.push_str(")\n");
+        names.push(format!("{}: {}", py_string(name), constant));
+    }
+
+    code.push_str("\n\n");
+    code.push_str(&format!("SCHEMAS = {{{}}}\n", names.join(", ")));
+    code.push_str("\n\n");
+    code.push_str(MAIN);
+    code
+}
+
+fn pandera_dtype(ty: &FieldType) -> &'static str {
+    match ty {
+        FieldType::Int => "\"Int64\"",
+        FieldType::Float | FieldType::Currency => "float",
+        FieldType::Bool => "\"boolean\"",
+        FieldType::Date => "\"datetime64[ns]\"",
+        FieldType::String | FieldType::Ref { .. } => "str",
+    }
+}
+
+const MAIN: &str = r#"if __name__ == "__main__":
+    invalid = 0
+    for arg in sys.argv[1:]:
+        table, _, path = arg.partition("=")
+        if table not in SCHEMAS or not path:
+            sys.exit(f"usage: python {sys.argv[0]} Table=file.csv ...; tables: {', '.join(SCHEMAS)}")
+        try:
+            SCHEMAS[table].validate(pd.read_csv(path), lazy=True)
+            print(f"Valid: {path}")
+        except pa.errors.SchemaErrors as errors:
+            print(f"Invalid: {path}")
+            print(errors.failure_cases.to_string())
+            invalid += 1
+    if invalid:
+        sys.exit(1)
+"#;
+
+/// Name of the Great Expectations suite file of a table
+pub fn suite_file_name(table: &str) -> String {
+    format!("{}.json", table.to_lowercase())
+}
+
+/// Great Expectations suite checking the CSV file of a table: its columns,
+/// their types, and the keys, unique and non-null constraints
+pub fn expectation_suite(schema: &TableSchema, module: &IRModule, source: &str) -> String {
+    let columns = columns(schema, module);
+    let headers: Vec<&str> = columns.iter().map(|column| column.header).collect();
+    let mut expectations = vec![expectation("expect_table_columns_to_match_set", json!({
+        "column_set": headers,
+        "exact_match": true,
+    }))];
+    for column in &columns {
+        if !column.nullable {
+            expectations.push(expectation("expect_column_values_to_not_be_null", json!({ "column": column.header })));
+        }
+        if column.unique {
+            expectations.push(expectation("expect_column_values_to_be_unique", json!({ "column": column.header })));
+        }
+        // Integer columns with missing values are read as floats
+        let type_list: &[&str] = match column.ty {
+            FieldType::Int if column.nullable => &["int64", "Int64", "float64"],
+            FieldType::Int => &["int64", "Int64"],
+            FieldType::Float | FieldType::Currency => &["float64", "int64", "Int64"],
+            FieldType::Bool => &["bool", "boolean"],
+            FieldType::Date => {
+                expectations.push(expectation("expect_column_values_to_be_dateutil_parseable", json!({ "column": column.header })));
+                continue;
+            }
+            // Any value can be read as text
+            FieldType::String | FieldType::Ref { .. } => continue,
+        };
+        expectations.push(expectation("expect_column_values_to_be_in_type_list", json!({
+            "column": column.header,
+            "type_list": type_list,
+        })));
+    }
+
+    let suite = json!({
+        "expectation_suite_name": schema.name.as_str().to_lowercase(),
+        "expectations": expectations,
+        "meta": { "notes": format!("Checks the CSV file of the {} table of {}; generated by wtc", schema.name, source) },
+    });
+    let mut text = serde_json::to_string_pretty(&suite).expect("JSON value");
+    text.push('\n');
+    text
+}
+
+fn expectation(expectation_type: &str, kwargs: Value) -> Value {
+    json!({ "expectation_type": expectation_type, "kwargs": kwargs })
+}
+
+fn py_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wtlang_core::{IRBuilder, Lexer, Parser};
+
+    fn module(source: &str) -> IRModule {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        IRBuilder::new().build(&program).unwrap()
+    }
+
+    const SOURCE: &str = r#"
+table Customer {
+    id: int [key],
+    name: string [non_null],
+    email: string [unique]
+}
+
+table Order {
+    number: int [key],
+    customer: ref Customer,
+    placed: date,
+    total: currency from "Total (€)",
+    paid: bool
+}
+"#;
+
+    #[test]
+    fn test_pandera_module() {
+        let code = pandera_module(&module(SOURCE), "shop.wt");
+        assert!(code.starts_with("# pandera schemas of the tables of shop.wt\n"), "{}", code);
+        assert!(code.contains("#     python schemas.py Customer=data/customer.csv\n"), "{}", code);
+        assert!(code.contains(concat!(
+            "CUSTOMER = pa.DataFrameSchema(\n",
+            "    {\n",
+            "        \"id\": pa.Column(\"Int64\", unique=True, coerce=True),\n",
+            "        \"name\": pa.Column(str),\n",
+            "        \"email\": pa.Column(str, unique=True, nullable=True),\n",
+            "    },\n",
+            "    strict=True,\n",
+            "    name=\"Customer\",\n",
+            ")\n",
+        )), "{}", code);
+        assert!(code.contains("        \"customer\": pa.Column(\"Int64\", nullable=True, coerce=True),\n"), "{}", code);
+        assert!(code.contains("        \"Total (€)\": pa.Column(float, nullable=True, coerce=True),\n"), "{}", code);
+        assert!(code.contains("SCHEMAS = {\"Customer\": CUSTOMER, \"Order\": ORDER}\n"), "{}", code);
+    }
+
+    #[test]
+    fn test_expectation_suite() {
+        let module = module(SOURCE);
+        let suite: Value = serde_json::from_str(&expectation_suite(module.find_table("Order").unwrap(), &module, "shop.wt")).unwrap();
+        assert_eq!(suite["expectation_suite_name"], "order");
+        let expectations = suite["expectations"].as_array().unwrap();
+        assert_eq!(expectations[0]["kwargs"]["column_set"], json!(["number", "customer", "placed", "Total (€)", "paid"]));
+        let types: Vec<(&str, &str)> = expectations.iter()
+            .map(|e| (e["expectation_type"].as_str().unwrap(), e["kwargs"]["column"].as_str().unwrap_or("")))
+            .collect();
+        assert_eq!(types, vec![
+            ("expect_table_columns_to_match_set", ""),
+            ("expect_column_values_to_not_be_null", "number"),
+            ("expect_column_values_to_be_unique", "number"),
+            ("expect_column_values_to_be_in_type_list", "number"),
+            ("expect_column_values_to_be_in_type_list", "customer"),
+            ("expect_column_values_to_be_dateutil_parseable", "placed"),
+            ("expect_column_values_to_be_in_type_list", "Total (€)"),
+            ("expect_column_values_to_be_in_type_list", "paid"),
+        ]);
+        assert_eq!(expectations[4]["kwargs"]["type_list"], json!(["int64", "Int64", "float64"]));
+        assert_eq!(suite_file_name("Order"), "order.json");
+    }
+}
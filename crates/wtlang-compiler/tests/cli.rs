@@ -0,0 +1,176 @@
+// Tests of the wtc command line, run against the built binary
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wtc_cli_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn wtc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_wtc")).args(args).output().unwrap()
+}
+
+/// Run wtc with `input` on its stdin
+fn wtc_with_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wtc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const VALID: &str = "page Home {\n    text \"Hello\"\n}\n";
+
+const THREE_ERRORS: &str = "page Home {\n    first = 1\n    second = 2\n    third = 3\n}\n";
+
+#[test]
+fn test_check_exit_codes() {
+    let dir = temp_dir("exit_codes");
+    let valid = dir.join("valid.wt");
+    let invalid = dir.join("invalid.wt");
+    fs::write(&valid, VALID).unwrap();
+    fs::write(&invalid, THREE_ERRORS).unwrap();
+
+    let ok = wtc(&["check", path(&valid)]);
+    assert_eq!(ok.status.code(), Some(0), "{}", stderr(&ok));
+    assert!(stdout(&ok).contains("[OK] No errors found!"), "{}", stdout(&ok));
+
+    let errors = wtc(&["check", path(&valid), path(&invalid)]);
+    assert_eq!(errors.status.code(), Some(1));
+    assert!(stdout(&errors).contains("Found 3 error(s) and 0 warning(s) in 2 file(s)"), "{}", stdout(&errors));
+
+    // A missing file is reported, and the others are still checked
+    let missing = wtc(&["check", path(&dir.join("missing.wt")), path(&invalid)]);
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(stderr(&missing).contains("missing.wt"), "{}", stderr(&missing));
+    assert!(stderr(&missing).contains("E3001"), "{}", stderr(&missing));
+
+    assert_eq!(wtc(&["check", "--no-such-flag", path(&valid)]).status.code(), Some(2));
+    assert_eq!(wtc(&["check"]).status.code(), Some(2));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check_max_errors_and_quiet() {
+    let dir = temp_dir("max_errors");
+    let invalid = dir.join("invalid.wt");
+    fs::write(&invalid, THREE_ERRORS).unwrap();
+
+    let limited = wtc(&["check", "--quiet", "--max-errors", "1", path(&invalid)]);
+    assert_eq!(limited.status.code(), Some(1));
+    let errors = stderr(&limited);
+    assert_eq!(errors.matches("error[E3001]").count(), 1, "{}", errors);
+    assert!(errors.contains("'first'"), "{}", errors);
+    assert!(errors.contains("... 2 more error(s) not shown (--max-errors)"), "{}", errors);
+    assert_eq!(stdout(&limited), "");
+
+    let all = wtc(&["check", path(&invalid)]);
+    assert_eq!(stderr(&all).matches("error[E3001]").count(), 3, "{}", stderr(&all));
+    assert!(!stderr(&all).contains("not shown"), "{}", stderr(&all));
+    assert!(stdout(&all).contains("Checking"), "{}", stdout(&all));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check_reads_stdin() {
+    let ok = wtc_with_stdin(&["check", "-"], VALID);
+    assert_eq!(ok.status.code(), Some(0), "{}", stderr(&ok));
+    assert!(stdout(&ok).contains("<stdin>: 0 error(s), 0 warning(s)"), "{}", stdout(&ok));
+
+    let errors = wtc_with_stdin(&["check", "-q", "-"], THREE_ERRORS);
+    assert_eq!(errors.status.code(), Some(1));
+    assert!(stderr(&errors).contains("<stdin>:2:5"), "{}", stderr(&errors));
+}
+
+#[test]
+fn test_build_stops_on_suppressed_errors() {
+    let dir = temp_dir("suppressed");
+    let source = dir.join("app.wt");
+    fs::write(&source, "page Home {\n    // wtlang: allow(E3001)\n    first = 1\n}\n").unwrap();
+    assert_eq!(wtc(&["check", path(&source)]).status.code(), Some(0));
+
+    let output = dir.join("out");
+    let build = wtc(&["build", path(&source), "-o", path(&output)]);
+    let errors = stderr(&build);
+    assert_eq!(build.status.code(), Some(1), "{}", errors);
+    assert!(errors.contains("found 1 error(s) that allow/expect-error comments suppress"), "{}", errors);
+    assert!(!errors.contains("Code generation error"), "{}", errors);
+    assert!(!output.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_build_keeps_hand_edited_files() {
+    let dir = temp_dir("hand_edited");
+    let source = dir.join("app.wt");
+    let output = dir.join("out");
+    fs::write(&source, VALID).unwrap();
+    let first = wtc(&["build", path(&source), "-o", path(&output)]);
+    assert_eq!(first.status.code(), Some(0), "{}", stderr(&first));
+
+    let page = output.join("Home.py");
+    let generated = fs::read_to_string(&page).unwrap();
+    let edited = format!("{}st.write(\"patched\")\n", generated);
+    fs::write(&page, &edited).unwrap();
+
+    // The edit is shown and kept
+    let refused = wtc(&["build", path(&source), "-o", path(&output)]);
+    assert_eq!(refused.status.code(), Some(1));
+    assert!(stderr(&refused).contains("hand-edited: "), "{}", stderr(&refused));
+    assert!(stderr(&refused).contains("-st.write(\"patched\")"), "{}", stderr(&refused));
+    assert!(stderr(&refused).contains("1 generated file(s) were edited by hand"), "{}", stderr(&refused));
+    assert_eq!(fs::read_to_string(&page).unwrap(), edited);
+
+    let forced = wtc(&["build", path(&source), "-o", path(&output), "--force"]);
+    assert_eq!(forced.status.code(), Some(0), "{}", stderr(&forced));
+    assert!(stdout(&forced).contains("Overwriting hand-edited: "), "{}", stdout(&forced));
+    assert_eq!(fs::read_to_string(&page).unwrap(), generated);
+
+    // Once overwritten, the file is the build's again
+    let rebuilt = wtc(&["build", path(&source), "-o", path(&output)]);
+    assert_eq!(rebuilt.status.code(), Some(0), "{}", stderr(&rebuilt));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_build_records_options_in_lock() {
+    let dir = temp_dir("lock_options");
+    let source = dir.join("app.wt");
+    let output = dir.join("out");
+    fs::write(&source, VALID).unwrap();
+    let build = wtc(&["build", path(&source), "-o", path(&output), "--python", "3.12", "--trace"]);
+    assert_eq!(build.status.code(), Some(0), "{}", stderr(&build));
+    let lock = fs::read_to_string(output.join("wt.lock")).unwrap();
+    assert!(lock.contains("\"python\": \"3.12\""), "{}", lock);
+    assert!(lock.contains("\"trace\": true"), "{}", lock);
+
+    // The default target is recorded too
+    let build = wtc(&["build", path(&source), "-o", path(&output)]);
+    assert_eq!(build.status.code(), Some(0), "{}", stderr(&build));
+    let lock = fs::read_to_string(output.join("wt.lock")).unwrap();
+    assert!(lock.contains("\"python\": \"3.8\""), "{}", lock);
+    assert!(lock.contains("\"trace\": false"), "{}", lock);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,33 +1,155 @@
 // AST (Abstract Syntax Tree) definitions for WTLang
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::intern::Atom;
+
+/// Source span of a construct, from the start of its first token to the
+/// start of its last token (1-based lines and columns, inclusive)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, end_line: usize, end_column: usize) -> Self {
+        Span { line, column, end_line, end_column }
+    }
+    
+    /// Check whether a 1-based line/column position falls inside this span
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        (line, column) >= (self.line, self.column) && (line, column) <= (self.end_line, self.end_column)
+    }
+    
+    /// The same span `lines` lines further down (up when negative); spans
+    /// without a position, on line 0, are left as they are
+    pub fn moved(self, lines: isize) -> Span {
+        if self.line == 0 {
+            return self;
+        }
+        let move_line = |line: usize| line.saturating_add_signed(lines);
+        Span { line: move_line(self.line), end_line: move_line(self.end_line), ..self }
+    }
+}
+
+/// Identity of a node, under which analysis results such as types,
+/// definitions and diagnostics are recorded
+///
+/// The parser numbers the nodes of each top-level item from 0, in the order
+/// it finishes them, so the same source always gets the same ids, and an
+/// edit inside one item leaves the ids of the other items as they were.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, serde::Serialize, serde::Deserialize)]
+pub struct NodeId {
+    /// Position of the top-level item among the program's items
+    pub item: u32,
+    /// Number of the node within the item
+    pub index: u32,
+}
+
+impl NodeId {
+    pub fn new(item: u32, index: u32) -> Self {
+        NodeId { item, index }
+    }
+    
+    /// The same node of an item that moved to position `item`
+    pub fn in_item(self, item: u32) -> NodeId {
+        NodeId { item, ..self }
+    }
+}
+
+impl std::fmt::Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}.{}", self.item, self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Program {
     pub items: Vec<ProgramItem>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum ProgramItem {
     TableDef(TableDef),
     Page(Page),
     FunctionDef(FunctionDef),
     ExternalFunction(ExternalFunction),
     Test(Test),
+    Restriction(Restriction),
+    DerivedTable(DerivedTable),
+    SharedVariable(SharedVariable),
+    Constant(Constant),
+    Hook(Hook),
+    Config(Config),
+    Import(Import),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl ProgramItem {
+    pub fn id(&self) -> NodeId {
+        match self {
+            ProgramItem::TableDef(table) => table.id,
+            ProgramItem::Page(page) => page.id,
+            ProgramItem::FunctionDef(func) => func.id,
+            ProgramItem::ExternalFunction(ext) => ext.id,
+            ProgramItem::Test(test) => test.id,
+            ProgramItem::Restriction(restriction) => restriction.id,
+            ProgramItem::DerivedTable(derived) => derived.id,
+            ProgramItem::SharedVariable(shared) => shared.id,
+            ProgramItem::Constant(constant) => constant.id,
+            ProgramItem::Hook(hook) => hook.id,
+            ProgramItem::Config(config) => config.id,
+            ProgramItem::Import(import) => import.id,
+        }
+    }
+    
+    pub fn span(&self) -> Span {
+        match self {
+            ProgramItem::TableDef(table) => table.span,
+            ProgramItem::Page(page) => page.span,
+            ProgramItem::FunctionDef(func) => func.span,
+            ProgramItem::ExternalFunction(ext) => ext.span,
+            ProgramItem::Test(test) => test.span,
+            ProgramItem::Restriction(restriction) => restriction.span,
+            ProgramItem::DerivedTable(derived) => derived.span,
+            ProgramItem::SharedVariable(shared) => shared.span,
+            ProgramItem::Constant(constant) => constant.span,
+            ProgramItem::Hook(hook) => hook.span,
+            ProgramItem::Config(config) => config.span,
+            ProgramItem::Import(import) => import.span,
+        }
+    }
+    
+    /// The same item as the program's `item`th, `lines` lines further down
+    /// (up when negative), with its node ids and spans changed to match
+    pub fn moved(&self, item: u32, lines: isize) -> ProgramItem {
+        let mut moved = self.clone();
+        moved.relocate(&Relocation { item, lines });
+        moved
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct TableDef {
-    pub name: String,
+    pub name: Atom,
     pub fields: Vec<Field>,
+    /// Text of the `///` comments written before the table
+    pub doc: Option<String>,
+    pub id: NodeId,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Field {
-    pub name: String,
+    pub name: Atom,
     pub field_type: Type,
+    /// CSV header the column is read from and written to, given with
+    /// `from "Header"` when it differs from the field name
+    pub header: Option<String>,
     pub constraints: Vec<Constraint>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum Type {
     Int,
     Float,
@@ -35,83 +157,398 @@ pub enum Type {
     Date,
     Currency,
     Bool,
-    Table(String), // Table<TypeName>
+    Table(TableType), // Table<TypeName>
+    Row(TableType),   // One row of a table, written `row(TypeName)`
     Filter,        // Filter type for table column filters
-    Ref(String),   // Reference to another table by name
+    Ref(Atom),     // Reference to another table by name
+    Array(Box<Type>), // Array of values of a type, written `string[]`
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Date => write!(f, "date"),
+            Type::Currency => write!(f, "currency"),
+            Type::Bool => write!(f, "bool"),
+            Type::Table(table) => write!(f, "table({})", table),
+            Type::Row(table) => write!(f, "row({})", table),
+            Type::Filter => write!(f, "filter"),
+            Type::Ref(table_name) => write!(f, "ref {}", table_name),
+            Type::Array(element) => write!(f, "{}[]", element),
+        }
+    }
+}
+
+/// The type of a table value: a declared table, and the changes that table
+/// operations made to its columns, in the order they were made
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct TableType {
+    pub name: Atom,
+    pub changes: Vec<ColumnChange>,
+}
+
+/// A change a table operation makes to the columns of its table
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ColumnChange {
+    /// A float column added, such as `amount_share` by `share(orders, "amount")`
+    Add(Atom),
+    /// A column renamed, by `orders rename amount to revenue`
+    Rename(Atom, Atom),
+    /// A column dropped, by `orders drop notes`
+    Drop(Atom),
+}
+
+impl TableType {
+    /// The table type after one more change to its columns
+    pub fn changed(mut self, change: ColumnChange) -> Self {
+        self.changes.push(change);
+        self
+    }
+}
+
+impl From<Atom> for TableType {
+    fn from(name: Atom) -> Self {
+        TableType { name, changes: Vec::new() }
+    }
+}
+
+impl std::fmt::Display for TableType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (index, change) in self.changes.iter().enumerate() {
+            write!(f, "{}{}", if index == 0 { " with " } else { ", " }, change)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ColumnChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColumnChange::Add(column) => write!(f, "'{}' added", column),
+            ColumnChange::Rename(from, to) => write!(f, "'{}' renamed to '{}'", from, to),
+            ColumnChange::Drop(column) => write!(f, "'{}' dropped", column),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum FilterMode {
     Single,
     Multi,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct FilterDef {
     pub column: String,
     pub mode: FilterMode,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum Constraint {
     Unique,
     NonNull,
     Validate(Expr),
-    References { table: String, field: String },
+    References { table: Atom, field: Atom },
     Key,  // Mark field as primary key
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// `restrict Table where condition`: the rows of a table users may see,
+/// applied to every load of the table
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Restriction {
+    pub table: Atom,
+    pub condition: Expr,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `table Name = expression`: a table computed from other tables, whose
+/// schema is inferred from the expression
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct DerivedTable {
+    pub name: Atom,
+    pub value: Expr,
+    pub materialize: Materialize,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// How a derived table is kept, chosen with `materialize daily` or
+/// `materialize on_build` after its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Materialize {
+    /// Computed when first used in each session
+    #[default]
+    Cached,
+    /// Kept in a CSV file, computed again once a day
+    Daily,
+    /// Kept in a CSV file computed when the app is built
+    OnBuild,
+}
+
+/// `shared let name: type = value`: a variable every page reads and writes,
+/// starting at its value in each session
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct SharedVariable {
+    pub name: Atom,
+    pub type_annotation: Option<Type>,
+    pub value: Expr,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `const NAME: type = value`: a value known when compiling, which every
+/// page and function can read but none can assign
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Constant {
+    pub name: Atom,
+    pub type_annotation: Option<Type>,
+    pub value: Expr,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `import "common.wt"`: the items of another file, whose path is relative
+/// to the importing file
+///
+/// `import analytics from "analytics.wt"` imports the file as a module: its
+/// functions and constants are named `analytics.name`.
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Import {
+    pub path: String,
+    pub module: Option<Atom>,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `on_load Table { ... }`, `on_edit Table { ... }` or `on_save Table { ... }`:
+/// statements run with the table's rows when they are loaded, edited or saved
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Hook {
+    pub event: HookEvent,
+    pub table: Atom,
+    pub body: Vec<Statement>,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `config { title = "Sales" ... }`: settings of the whole app, such as its
+/// title, theme and number formats
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Config {
+    pub settings: Vec<ConfigSetting>,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `template name(params) { statements }`: statements repeated across pages,
+/// which `use name(args)` stands for; expanded by the parser, so it is not an
+/// item of the program
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Template {
+    pub name: Atom,
+    pub params: Vec<TemplateParam>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct TemplateParam {
+    pub name: Atom,
+    /// Type of the values the parameter takes; `None` for `table`, any table
+    pub param_type: Option<Type>,
+}
+
+/// A variable a `python` block reads or sets
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct CodeBinding {
+    /// Name of the variable in the block's code
+    pub name: Atom,
+    /// The program's variable; differs from `name` once a template using
+    /// the block is expanded
+    pub variable: Atom,
+    /// `None` for `table`, any table
+    pub binding_type: Option<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct ConfigSetting {
+    pub name: Atom,
+    pub value: String,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HookEvent {
+    Load,
+    Edit,
+    Save,
+}
+
+impl HookEvent {
+    /// Keyword declaring a hook for this event
+    pub fn keyword(self) -> &'static str {
+        match self {
+            HookEvent::Load => "on_load",
+            HookEvent::Edit => "on_edit",
+            HookEvent::Save => "on_save",
+        }
+    }
+    
+    /// Variable holding the table's rows in the hook's body
+    pub fn variable(self) -> &'static str {
+        match self {
+            HookEvent::Load => "loaded",
+            HookEvent::Edit => "edited",
+            HookEvent::Save => "saved",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Page {
-    pub name: String,
+    pub name: Atom,
     pub statements: Vec<Statement>,
+    /// Text of the `///` comments written before the page
+    pub doc: Option<String>,
+    pub id: NodeId,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Statement {
-    Title(String),
-    Subtitle(String),
+/// Text shown on a page: written in the source, with the values of
+/// `{expr}` placeholders, or looked up by key in the program's locale files
+/// with `t("key")`
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum PageText {
+    Literal(String),
+    Interpolated(Vec<TextPart>),
+    Translated(String),
+}
+
+/// A piece of interpolated text: text as written, or an embedded expression
+/// and the span of its `{...}`
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum TextPart {
     Text(String),
-    Button { label: String, body: Vec<Statement> },
-    Section { title: String, body: Vec<Statement> },
+    Expr { expr: Expr, span: Span },
+}
+
+impl PageText {
+    /// The expressions embedded in the text, with the spans of their `{...}`
+    pub fn expressions(&self) -> impl Iterator<Item = (&Expr, Span)> {
+        let parts = match self {
+            PageText::Interpolated(parts) => parts.as_slice(),
+            PageText::Literal(_) | PageText::Translated(_) => &[],
+        };
+        parts.iter().filter_map(|part| match part {
+            TextPart::Expr { expr, span } => Some((expr, *span)),
+            TextPart::Text(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum Statement {
+    Title(PageText),
+    Subtitle(PageText),
+    Text(PageText),
+    Button { label: PageText, body: Vec<Statement>, id: NodeId, span: Span },
+    Section { title: PageText, body: Vec<Statement>, id: NodeId, span: Span },
+    Confirm { message: PageText, body: Vec<Statement>, id: NodeId, span: Span },
     Let { 
-        name: String, 
+        name: Atom, 
         type_annotation: Option<Type>,  // Optional type annotation
-        value: Option<Expr>  // Value is now optional (for declarations without initialization)
+        value: Option<Expr>,  // Value is now optional (for declarations without initialization)
+        id: NodeId,
+        span: Span,
     },
-    Assign { name: String, value: Expr },  // Assignment to existing variable
-    If { condition: Expr, then_branch: Vec<Statement>, else_branch: Option<Vec<Statement>> },
-    Forall { var: String, iterable: Expr, body: Vec<Statement> },
+    Assign { name: Atom, value: Expr, id: NodeId, span: Span },  // Assignment to existing variable
+    If {
+        condition: Expr,
+        then_branch: Vec<Statement>,
+        else_branch: Option<Vec<Statement>>,
+        id: NodeId,
+        then_span: Span,
+        else_span: Option<Span>,
+    },
+    Forall { var: Atom, iterable: Expr, body: Vec<Statement>, id: NodeId, span: Span },
+    Match { value: Expr, arms: Vec<MatchArm<Vec<Statement>>>, id: NodeId, span: Span },  // Runs the first arm matching the value
     Return(Expr),
     FunctionCall(FunctionCall),
+    Assert { condition: Expr, id: NodeId, span: Span },
+    Mock { target: Atom, value: Expr, id: NodeId, span: Span },  // Only valid inside `test` blocks
+    Refresh { seconds: u64, id: NodeId, span: Span },  // `refresh every 5m`, only at the top level of a page
+    Python {  // `python(inputs) -> (outputs) { code }`, passed through to the generated code
+        inputs: Vec<CodeBinding>,
+        outputs: Vec<CodeBinding>,
+        code: String,
+        id: NodeId,
+        span: Span,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Statement {
+    /// The statement's node, for those that have a source span
+    pub fn id(&self) -> Option<NodeId> {
+        match self {
+            Statement::Button { id, .. }
+            | Statement::Section { id, .. }
+            | Statement::Confirm { id, .. }
+            | Statement::Let { id, .. }
+            | Statement::Assign { id, .. }
+            | Statement::If { id, .. }
+            | Statement::Forall { id, .. }
+            | Statement::Match { id, .. }
+            | Statement::Assert { id, .. }
+            | Statement::Mock { id, .. }
+            | Statement::Refresh { id, .. }
+            | Statement::Python { id, .. } => Some(*id),
+            Statement::FunctionCall(call) => Some(call.id),
+            Statement::Title(_) | Statement::Subtitle(_) | Statement::Text(_) | Statement::Return(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct FunctionDef {
-    pub name: String,
+    pub name: Atom,
     pub params: Vec<Parameter>,
     pub return_type: Type,
     pub body: Vec<Statement>,
+    /// Text of the `///` comments written before the function
+    pub doc: Option<String>,
+    pub id: NodeId,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct ExternalFunction {
-    pub name: String,
+    pub name: Atom,
     pub params: Vec<Parameter>,
     pub return_type: Type,
     pub module: String,
+    pub id: NodeId,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Parameter {
-    pub name: String,
+    pub name: Atom,
     pub param_type: Type,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Test {
     pub name: String,
     pub body: Vec<Statement>,
+    pub id: NodeId,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -120,37 +557,201 @@ pub enum Expr {
     FloatLiteral(f64),
     StringLiteral(String),
     BoolLiteral(bool),
-    Identifier(String),
+    DateLiteral(String),  // `d"2024-01-15"`, holding the ISO date
+    CurrencyLiteral { amount: String, currency: String },  // `12.50 EUR`, with the amount's digits as written
+    Identifier(Atom),
     FunctionCall(FunctionCall),
-    BinaryOp { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
-    UnaryOp { op: UnaryOp, operand: Box<Expr> },
-    Lambda { params: Vec<String>, body: Box<Expr> },
-    FieldAccess { object: Box<Expr>, field: String },
-    Index { object: Box<Expr>, index: Box<Expr> },
-    Chain { left: Box<Expr>, right: Box<Expr> },
-    TableLiteral(Vec<(String, Expr)>),
+    // Operations and queries are nodes, whose types the analyzer records
+    BinaryOp { op: BinaryOp, left: Box<Expr>, right: Box<Expr>, id: NodeId, span: Span },
+    UnaryOp { op: UnaryOp, operand: Box<Expr>, id: NodeId, span: Span },
+    Lambda { params: Vec<Atom>, body: Box<Expr> },
+    FieldAccess { object: Box<Expr>, field: Atom, id: NodeId, span: Span },
+    Index { object: Box<Expr>, index: Box<Expr>, id: NodeId, span: Span },
+    Chain { left: Box<Expr>, right: Box<Expr>, id: NodeId, span: Span },
+    TableLiteral(Vec<(Atom, Expr)>),
     ArrayLiteral(Vec<Expr>),
     FilterLiteral(FilterDef),
     
     // Query language expressions
-    Where { table: Box<Expr>, condition: Box<Expr> },
-    SortBy { table: Box<Expr>, columns: Vec<SortColumn> },
-    ColumnSelect { table: Box<Expr>, columns: Vec<String> },
+    Where { table: Box<Expr>, condition: Box<Expr>, id: NodeId, span: Span },
+    SortBy { table: Box<Expr>, columns: Vec<SortColumn>, id: NodeId, span: Span },
+    ColumnSelect { table: Box<Expr>, columns: Vec<SelectedColumn>, id: NodeId, span: Span },
+    Between { value: Box<Expr>, low: Box<Expr>, high: Box<Expr>, id: NodeId, span: Span },  // `value between low and high`, both ends included
+    GroupBy { table: Box<Expr>, columns: Vec<Atom>, having: Box<Expr>, id: NodeId, span: Span },  // rows of the groups whose aggregates satisfy `having`
+    Rename { table: Box<Expr>, columns: Vec<(Atom, Atom)>, id: NodeId, span: Span },  // `table rename old to new, ...`
+    DropColumns { table: Box<Expr>, columns: Vec<Atom>, id: NodeId, span: Span },  // `table drop col1, col2`
+    Match { value: Box<Expr>, arms: Vec<MatchArm<Expr>>, id: NodeId, span: Span },  // The body of the first arm matching the value
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Expr {
+    /// Id and span of an expression that is a node: a call, an operation,
+    /// a field access, an index, a chain, a match or a query
+    pub fn node(&self) -> Option<(NodeId, Span)> {
+        match self {
+            Expr::FunctionCall(call) => Some((call.id, call.span)),
+            Expr::BinaryOp { id, span, .. }
+            | Expr::UnaryOp { id, span, .. }
+            | Expr::FieldAccess { id, span, .. }
+            | Expr::Index { id, span, .. }
+            | Expr::Chain { id, span, .. }
+            | Expr::Where { id, span, .. }
+            | Expr::SortBy { id, span, .. }
+            | Expr::ColumnSelect { id, span, .. }
+            | Expr::Between { id, span, .. }
+            | Expr::GroupBy { id, span, .. }
+            | Expr::Rename { id, span, .. }
+            | Expr::DropColumns { id, span, .. }
+            | Expr::Match { id, span, .. } => Some((*id, *span)),
+            _ => None,
+        }
+    }
+    
+    /// Whether an aggregate over a group's rows appears in the expression
+    /// outside any nested query
+    pub fn contains_group_aggregate(&self) -> bool {
+        match self {
+            Expr::FunctionCall(call) => {
+                call.is_group_aggregate() || call.args.iter().any(Expr::contains_group_aggregate)
+            }
+            Expr::BinaryOp { left, right, .. } => left.contains_group_aggregate() || right.contains_group_aggregate(),
+            Expr::UnaryOp { operand, .. } => operand.contains_group_aggregate(),
+            Expr::Between { value, low, high, .. } => {
+                value.contains_group_aggregate() || low.contains_group_aggregate() || high.contains_group_aggregate()
+            }
+            _ => false,
+        }
+    }
+}
+
+// Floats are hashed by their bits, so an item's hash stays a hash of the
+// source it was parsed from
+impl std::hash::Hash for Expr {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Expr::IntLiteral(value) => value.hash(state),
+            Expr::FloatLiteral(value) => value.to_bits().hash(state),
+            Expr::StringLiteral(value) | Expr::DateLiteral(value) => value.hash(state),
+            Expr::BoolLiteral(value) => value.hash(state),
+            Expr::CurrencyLiteral { amount, currency } => (amount, currency).hash(state),
+            Expr::Identifier(name) => name.hash(state),
+            Expr::FunctionCall(call) => call.hash(state),
+            Expr::BinaryOp { op, left, right, id, span } => (op, left, right, id, span).hash(state),
+            Expr::UnaryOp { op, operand, id, span } => (op, operand, id, span).hash(state),
+            Expr::Lambda { params, body } => (params, body).hash(state),
+            Expr::FieldAccess { object, field, id, span } => (object, field, id, span).hash(state),
+            Expr::Index { object, index, id, span } => (object, index, id, span).hash(state),
+            Expr::Chain { left, right, id, span } => (left, right, id, span).hash(state),
+            Expr::TableLiteral(fields) => fields.hash(state),
+            Expr::ArrayLiteral(elements) => elements.hash(state),
+            Expr::FilterLiteral(filter) => filter.hash(state),
+            Expr::Where { table, condition, id, span } => (table, condition, id, span).hash(state),
+            Expr::SortBy { table, columns, id, span } => (table, columns, id, span).hash(state),
+            Expr::ColumnSelect { table, columns, id, span } => (table, columns, id, span).hash(state),
+            Expr::Between { value, low, high, id, span } => (value, low, high, id, span).hash(state),
+            Expr::GroupBy { table, columns, having, id, span } => (table, columns, having, id, span).hash(state),
+            Expr::Rename { table, columns, id, span } => (table, columns, id, span).hash(state),
+            Expr::DropColumns { table, columns, id, span } => (table, columns, id, span).hash(state),
+            Expr::Match { value, arms, id, span } => (value, arms, id, span).hash(state),
+        }
+    }
+}
+
+/// An arm of `match`: the patterns it is taken for, separated by commas,
+/// and its body, statements or a value
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct MatchArm<T> {
+    pub patterns: Vec<MatchPattern>,
+    pub body: T,
+    pub span: Span,
+}
+
+impl<T> MatchArm<T> {
+    /// Whether the arm is taken for any value
+    pub fn is_wildcard(&self) -> bool {
+        self.patterns.contains(&MatchPattern::Wildcard)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum MatchPattern {
+    Literal(Expr),  // A literal, the value equal to it
+    Wildcard,       // `_`, any value
+}
+
+/// An item of a column selection such as `orders[id, * except notes]`
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum SelectedColumn {
+    Name(Atom),
+    Rest { except: Vec<Atom> },  // `*`: the columns not named elsewhere in the selection, less `except`
+}
+
+impl SelectedColumn {
+    /// The columns a selection picks from a table's, in order; the first
+    /// name the table does not have otherwise
+    pub fn expand(selection: &[SelectedColumn], available: &[Atom]) -> Result<Vec<Atom>, Atom> {
+        let named: Vec<Atom> = selection.iter().filter_map(|column| match column {
+            SelectedColumn::Name(name) => Some(*name),
+            SelectedColumn::Rest { .. } => None,
+        }).collect();
+        let mut columns = Vec::new();
+        for column in selection {
+            match column {
+                SelectedColumn::Name(name) => {
+                    if !available.contains(name) {
+                        return Err(*name);
+                    }
+                    columns.push(*name);
+                }
+                SelectedColumn::Rest { except } => {
+                    if let Some(missing) = except.iter().find(|name| !available.contains(name)) {
+                        return Err(*missing);
+                    }
+                    columns.extend(available.iter().filter(|name| !named.contains(name) && !except.contains(name)));
+                }
+            }
+        }
+        Ok(columns)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct SortColumn {
-    pub name: String,
+    pub key: Expr,        // A column, or an expression of the row's columns
     pub ascending: bool,  // true for asc, false for desc
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl SortColumn {
+    /// The column sorted by, when the key is a bare column
+    pub fn column(&self) -> Option<Atom> {
+        match &self.key {
+            Expr::Identifier(name) => Some(*name),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct FunctionCall {
-    pub name: String,
+    pub name: Atom,
     pub args: Vec<Expr>,
+    pub id: NodeId,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl FunctionCall {
+    /// Whether this aggregates the rows of a group: `count()`, or `sum`,
+    /// `average`, `min` or `max` of a column name
+    pub fn is_group_aggregate(&self) -> bool {
+        match self.name.as_str() {
+            "count" => self.args.is_empty(),
+            "sum" | "average" | "min" | "max" => matches!(self.args.as_slice(), [Expr::Identifier(_)]),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum BinaryOp {
     Add,
     Subtract,
@@ -165,6 +766,7 @@ pub enum BinaryOp {
     GreaterThanEqual,
     And,
     Or,
+    In,         // membership of a value in a list
     
     // Set operations on tables
     Union,      // + when used on tables
@@ -172,8 +774,318 @@ pub enum BinaryOp {
     Intersect,  // & when used on tables
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl BinaryOp {
+    /// The operator as written in source
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add | BinaryOp::Union => "+",
+            BinaryOp::Subtract | BinaryOp::Minus => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::LessThan => "<",
+            BinaryOp::LessThanEqual => "<=",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::GreaterThanEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::In => "in",
+            BinaryOp::Intersect => "&",
+        }
+    }
+    
+    /// Whether this is `==`, `!=`, `<`, `<=`, `>`, `>=` or `in`
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::LessThan | BinaryOp::LessThanEqual
+                | BinaryOp::GreaterThan | BinaryOp::GreaterThanEqual | BinaryOp::In
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum UnaryOp {
     Not,
     Negate,
+    IsNull,     // `value is null`
+    IsNotNull,  // `value is not null`
+}
+
+/// Where `ProgramItem::moved` moves an item: its position among the
+/// program's items, and the lines it moves by
+struct Relocation {
+    item: u32,
+    lines: isize,
+}
+
+/// Nodes holding node ids or spans, which change when their item moves
+trait Relocate {
+    fn relocate(&mut self, to: &Relocation);
+}
+
+impl Relocate for Span {
+    fn relocate(&mut self, to: &Relocation) {
+        *self = self.moved(to.lines);
+    }
+}
+
+impl Relocate for NodeId {
+    fn relocate(&mut self, to: &Relocation) {
+        *self = self.in_item(to.item);
+    }
+}
+
+impl<T: Relocate> Relocate for Vec<T> {
+    fn relocate(&mut self, to: &Relocation) {
+        for node in self {
+            node.relocate(to);
+        }
+    }
+}
+
+impl<T: Relocate> Relocate for Option<T> {
+    fn relocate(&mut self, to: &Relocation) {
+        if let Some(node) = self {
+            node.relocate(to);
+        }
+    }
+}
+
+impl<T: Relocate> Relocate for Box<T> {
+    fn relocate(&mut self, to: &Relocation) {
+        (**self).relocate(to);
+    }
+}
+
+impl Relocate for ProgramItem {
+    fn relocate(&mut self, to: &Relocation) {
+        match self {
+            ProgramItem::TableDef(table) => {
+                for field in &mut table.fields {
+                    field.constraints.relocate(to);
+                }
+                table.id.relocate(to);
+                table.span.relocate(to);
+            }
+            ProgramItem::Page(page) => {
+                page.statements.relocate(to);
+                page.id.relocate(to);
+                page.span.relocate(to);
+            }
+            ProgramItem::FunctionDef(func) => {
+                func.body.relocate(to);
+                func.id.relocate(to);
+                func.span.relocate(to);
+            }
+            ProgramItem::ExternalFunction(ext) => {
+                ext.id.relocate(to);
+                ext.span.relocate(to);
+            }
+            ProgramItem::Test(test) => {
+                test.body.relocate(to);
+                test.id.relocate(to);
+                test.span.relocate(to);
+            }
+            ProgramItem::Restriction(restriction) => {
+                restriction.condition.relocate(to);
+                restriction.id.relocate(to);
+                restriction.span.relocate(to);
+            }
+            ProgramItem::DerivedTable(derived) => {
+                derived.value.relocate(to);
+                derived.id.relocate(to);
+                derived.span.relocate(to);
+            }
+            ProgramItem::SharedVariable(shared) => {
+                shared.value.relocate(to);
+                shared.id.relocate(to);
+                shared.span.relocate(to);
+            }
+            ProgramItem::Constant(constant) => {
+                constant.value.relocate(to);
+                constant.id.relocate(to);
+                constant.span.relocate(to);
+            }
+            ProgramItem::Hook(hook) => {
+                hook.body.relocate(to);
+                hook.id.relocate(to);
+                hook.span.relocate(to);
+            }
+            ProgramItem::Config(config) => {
+                for setting in &mut config.settings {
+                    setting.id.relocate(to);
+                    setting.span.relocate(to);
+                }
+                config.id.relocate(to);
+                config.span.relocate(to);
+            }
+            ProgramItem::Import(import) => {
+                import.id.relocate(to);
+                import.span.relocate(to);
+            }
+        }
+    }
+}
+
+impl Relocate for Constraint {
+    fn relocate(&mut self, to: &Relocation) {
+        if let Constraint::Validate(expr) = self {
+            expr.relocate(to);
+        }
+    }
+}
+
+impl Relocate for PageText {
+    fn relocate(&mut self, to: &Relocation) {
+        if let PageText::Interpolated(parts) = self {
+            for part in parts {
+                if let TextPart::Expr { expr, span } = part {
+                    expr.relocate(to);
+                    span.relocate(to);
+                }
+            }
+        }
+    }
+}
+
+impl Relocate for Statement {
+    fn relocate(&mut self, to: &Relocation) {
+        match self {
+            Statement::Title(text) | Statement::Subtitle(text) | Statement::Text(text) => text.relocate(to),
+            Statement::Button { label: text, body, id, span }
+            | Statement::Section { title: text, body, id, span }
+            | Statement::Confirm { message: text, body, id, span } => {
+                text.relocate(to);
+                body.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Statement::Let { value, id, span, .. } => {
+                value.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Statement::Assign { value, id, span, .. } | Statement::Mock { value, id, span, .. } => {
+                value.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Statement::If { condition, then_branch, else_branch, id, then_span, else_span } => {
+                condition.relocate(to);
+                then_branch.relocate(to);
+                else_branch.relocate(to);
+                id.relocate(to);
+                then_span.relocate(to);
+                else_span.relocate(to);
+            }
+            Statement::Forall { iterable, body, id, span, .. } => {
+                iterable.relocate(to);
+                body.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Statement::Match { value, arms, id, span } => {
+                value.relocate(to);
+                arms.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Statement::Return(value) => value.relocate(to),
+            Statement::FunctionCall(call) => call.relocate(to),
+            Statement::Assert { condition, id, span } => {
+                condition.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Statement::Refresh { id, span, .. } | Statement::Python { id, span, .. } => {
+                id.relocate(to);
+                span.relocate(to);
+            }
+        }
+    }
+}
+
+impl Relocate for Expr {
+    fn relocate(&mut self, to: &Relocation) {
+        match self {
+            Expr::IntLiteral(_) | Expr::FloatLiteral(_) | Expr::StringLiteral(_) | Expr::BoolLiteral(_)
+            | Expr::DateLiteral(_) | Expr::CurrencyLiteral { .. } | Expr::Identifier(_)
+            | Expr::FilterLiteral(_) => {}
+            Expr::FunctionCall(call) => call.relocate(to),
+            Expr::Lambda { body, .. } => body.relocate(to),
+            Expr::TableLiteral(fields) => {
+                for (_, value) in fields {
+                    value.relocate(to);
+                }
+            }
+            Expr::ArrayLiteral(elements) => elements.relocate(to),
+            Expr::UnaryOp { operand: expr, id, span, .. }
+            | Expr::FieldAccess { object: expr, id, span, .. }
+            | Expr::Rename { table: expr, id, span, .. }
+            | Expr::DropColumns { table: expr, id, span, .. } => {
+                expr.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Expr::BinaryOp { left, right, id, span, .. }
+            | Expr::Index { object: left, index: right, id, span }
+            | Expr::Chain { left, right, id, span }
+            | Expr::Where { table: left, condition: right, id, span }
+            | Expr::GroupBy { table: left, having: right, id, span, .. } => {
+                left.relocate(to);
+                right.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Expr::SortBy { table, columns, id, span } => {
+                table.relocate(to);
+                for column in columns {
+                    column.key.relocate(to);
+                }
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Expr::ColumnSelect { table, id, span, .. } => {
+                table.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Expr::Between { value, low, high, id, span } => {
+                value.relocate(to);
+                low.relocate(to);
+                high.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+            Expr::Match { value, arms, id, span } => {
+                value.relocate(to);
+                arms.relocate(to);
+                id.relocate(to);
+                span.relocate(to);
+            }
+        }
+    }
+}
+
+impl<T: Relocate> Relocate for MatchArm<T> {
+    fn relocate(&mut self, to: &Relocation) {
+        for pattern in &mut self.patterns {
+            if let MatchPattern::Literal(expr) = pattern {
+                expr.relocate(to);
+            }
+        }
+        self.body.relocate(to);
+        self.span.relocate(to);
+    }
+}
+
+impl Relocate for FunctionCall {
+    fn relocate(&mut self, to: &Relocation) {
+        self.args.relocate(to);
+        self.id.relocate(to);
+        self.span.relocate(to);
+    }
 }
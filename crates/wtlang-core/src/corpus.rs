@@ -0,0 +1,209 @@
+// Test corpus runner for WTLang
+//
+// Each `.wt` file in a corpus is compiled through the lexer, parser and
+// semantic analyzer, and the diagnostics are compared against annotations
+// written in the file itself:
+//
+//   let x = y  //~ ERROR E3001      diagnostic on this line
+//   //~^ ERROR E3004                diagnostic on the line above (one `^`
+//                                   per line)
+//
+// A file passes when exactly the annotated diagnostics are reported: every
+// annotation must be matched and no unannotated diagnostic may remain.
+// `// wtlang: allow(...)` and `// expect-error ...` directives are applied
+// before the comparison.
+
+use crate::directives::Directives;
+use crate::errors::{Diagnostic, DiagnosticBag, Severity};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::semantics::SemanticAnalyzer;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A `//~` annotation expecting one diagnostic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    pub severity: Severity,
+    pub code: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}] on line {}", self.severity, self.code, self.line)
+    }
+}
+
+/// Outcome of running one corpus file
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub path: PathBuf,
+    /// Annotations with no matching diagnostic
+    pub missing: Vec<Expectation>,
+    /// Diagnostics with no matching annotation
+    pub unexpected: Vec<Diagnostic>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+impl fmt::Display for CaseResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "{}: ok", self.path.display());
+        }
+        writeln!(f, "{}: FAILED", self.path.display())?;
+        for expectation in &self.missing {
+            writeln!(f, "  missing {}", expectation)?;
+        }
+        for diagnostic in &self.unexpected {
+            writeln!(
+                f,
+                "  unexpected {}[{}] on line {}: {}",
+                diagnostic.severity, diagnostic.code, diagnostic.location.line, diagnostic.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the `//~` annotations in a source file
+pub fn parse_expectations(source: &str) -> Vec<Expectation> {
+    let mut expectations = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let Some(start) = line.find("//~") else { continue };
+        let rest = &line[start + 3..];
+        let carets = rest.chars().take_while(|&c| c == '^').count();
+        let mut words = rest[carets..].split_whitespace();
+
+        let severity = match words.next() {
+            Some("ERROR") => Severity::Error,
+            Some("WARNING") => Severity::Warning,
+            Some("INFO") => Severity::Info,
+            Some("HINT") => Severity::Hint,
+            _ => continue,
+        };
+        let Some(code) = words.next() else { continue };
+
+        expectations.push(Expectation {
+            severity,
+            code: code.to_string(),
+            line: (idx + 1).saturating_sub(carets),
+        });
+    }
+
+    expectations
+}
+
+/// Compile source text and collect every diagnostic it produces
+///
+/// Parses past lexical errors and stops after a parse that reports errors,
+/// as the compiler does.
+pub fn compile(source: &str) -> DiagnosticBag {
+    let mut lexer = Lexer::new(source);
+    let (tokens, mut diagnostics) = lexer.tokenize_recovering();
+
+    let mut parser = Parser::new(tokens);
+    let program = match parser.parse() {
+        Ok(program) => program,
+        Err(parse_diagnostics) => {
+            diagnostics.extend(parse_diagnostics);
+            return diagnostics;
+        }
+    };
+
+    let mut analyzer = SemanticAnalyzer::new();
+    let _ = analyzer.analyze(&program);
+    diagnostics.extend(parser.diagnostics());
+    diagnostics.extend(analyzer.diagnostics());
+    diagnostics
+}
+
+/// Check source text against its own annotations
+pub fn check_source(path: PathBuf, source: &str) -> CaseResult {
+    let diagnostics = Directives::parse(source).apply(&compile(source));
+    let mut unexpected: Vec<Diagnostic> = diagnostics.diagnostics().to_vec();
+    let mut missing = Vec::new();
+
+    for expectation in parse_expectations(source) {
+        let found = unexpected.iter().position(|d| {
+            d.severity == expectation.severity
+                && d.code.code() == expectation.code
+                && d.location.line == expectation.line
+        });
+        match found {
+            Some(pos) => {
+                unexpected.remove(pos);
+            }
+            None => missing.push(expectation),
+        }
+    }
+
+    CaseResult { path, missing, unexpected }
+}
+
+/// Run a single corpus file
+pub fn run_file(path: &Path) -> io::Result<CaseResult> {
+    let source = fs::read_to_string(path)?;
+    Ok(check_source(path.to_path_buf(), &source))
+}
+
+/// Run every `.wt` file in a directory tree, in path order
+pub fn run_dir(dir: &Path) -> io::Result<Vec<CaseResult>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+    files.iter().map(|path| run_file(path)).collect()
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "wt") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expectations() {
+        let source = "let x = y //~ ERROR E3001\nlet x = 1\n//~^ ERROR E3004\n//~^^^ WARNING W1001\n";
+        let expectations = parse_expectations(source);
+        assert_eq!(expectations.len(), 3);
+        assert_eq!(expectations[0], Expectation { severity: Severity::Error, code: "E3001".to_string(), line: 1 });
+        assert_eq!(expectations[1].line, 2);
+        assert_eq!(expectations[2].severity, Severity::Warning);
+        assert_eq!(expectations[2].line, 1);
+    }
+
+    #[test]
+    fn test_matching_annotations_pass() {
+        let source = "page Home {\n    x = 1 //~ ERROR E3001\n}\n";
+        let result = check_source(PathBuf::from("case.wt"), source);
+        assert!(result.passed(), "{}", result);
+    }
+
+    #[test]
+    fn test_missing_and_unexpected_are_reported() {
+        let source = "page Home {\n    x = 1\n    let _a = 1 //~ ERROR E3004\n}\n";
+        let result = check_source(PathBuf::from("case.wt"), source);
+        assert!(!result.passed());
+        assert_eq!(result.missing.len(), 1);
+        assert_eq!(result.unexpected.len(), 1);
+        assert_eq!(result.unexpected[0].location.line, 2);
+    }
+}
@@ -0,0 +1,221 @@
+// Diagnostic directives in WTLang comments
+//
+// Two comment forms control which diagnostics are reported:
+//
+//   // wtlang: allow(E3001, E3004)   suppresses those codes
+//   // expect-error E3001            asserts that E3001 is reported
+//
+// A directive on a line of its own applies to the next line with code; a
+// directive trailing code applies to its own line. An expectation that is
+// not met is reported as E6001, which makes negative tests self-checking.
+
+use crate::errors::{Diagnostic, DiagnosticBag, ErrorCode, Location};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectiveKind {
+    /// `// wtlang: allow(...)`
+    Allow,
+    /// `// expect-error ...`
+    ExpectError,
+}
+
+/// A single directive comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub kind: DirectiveKind,
+    /// Error codes named by the directive (e.g. "E3001")
+    pub codes: Vec<String>,
+    /// Location of the comment itself
+    pub location: Location,
+    /// Line the directive applies to
+    pub target_line: usize,
+}
+
+/// All directives found in a source file
+#[derive(Debug, Clone, Default)]
+pub struct Directives {
+    directives: Vec<Directive>,
+}
+
+impl Directives {
+    /// Scan source text for directive comments
+    pub fn parse(source: &str) -> Self {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut directives = Vec::new();
+
+        for (idx, line) in lines.iter().enumerate() {
+            let Some(comment_start) = find_comment(line) else { continue };
+            let comment = line[comment_start + 2..].trim();
+
+            let (kind, codes) = if let Some(rest) = comment.strip_prefix("wtlang:") {
+                let rest = rest.trim();
+                let Some(args) = rest.strip_prefix("allow(").and_then(|r| r.strip_suffix(')')) else {
+                    continue;
+                };
+                (DirectiveKind::Allow, args)
+            } else if let Some(rest) = comment.strip_prefix("expect-error") {
+                (DirectiveKind::ExpectError, rest)
+            } else {
+                continue;
+            };
+
+            let codes: Vec<String> = codes
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|c| !c.is_empty())
+                .map(|c| c.to_string())
+                .collect();
+            if codes.is_empty() {
+                continue;
+            }
+
+            // Trailing directives apply to their own line, standalone ones
+            // to the next line that has code
+            let target_line = if line[..comment_start].trim().is_empty() {
+                lines.iter()
+                    .enumerate()
+                    .skip(idx + 1)
+                    .find(|(_, l)| {
+                        let code = match find_comment(l) {
+                            Some(pos) => &l[..pos],
+                            None => l,
+                        };
+                        !code.trim().is_empty()
+                    })
+                    .map(|(i, _)| i + 1)
+                    .unwrap_or(idx + 2)
+            } else {
+                idx + 1
+            };
+
+            directives.push(Directive {
+                kind,
+                codes,
+                location: Location::new(idx + 1, comment_start + 1),
+                target_line,
+            });
+        }
+
+        Directives { directives }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Directive> {
+        self.directives.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    /// Check whether a code is suppressed on a line
+    pub fn is_allowed(&self, code: ErrorCode, line: usize) -> bool {
+        self.directives.iter().any(|d| {
+            d.kind == DirectiveKind::Allow
+                && d.target_line == line
+                && d.codes.iter().any(|c| c == code.code())
+        })
+    }
+
+    /// Apply the directives to a set of diagnostics
+    ///
+    /// Allowed diagnostics are dropped, expected ones are consumed, and each
+    /// expectation without a matching diagnostic becomes an E6001 error.
+    pub fn apply(&self, diagnostics: &DiagnosticBag) -> DiagnosticBag {
+        let mut remaining: Vec<Diagnostic> = diagnostics.diagnostics()
+            .iter()
+            .filter(|d| !self.is_allowed(d.code, d.location.line))
+            .cloned()
+            .collect();
+        let mut unmet = DiagnosticBag::new();
+
+        for directive in self.directives.iter().filter(|d| d.kind == DirectiveKind::ExpectError) {
+            for code in &directive.codes {
+                let found = remaining.iter().position(|d| {
+                    d.code.code() == code && d.location.line == directive.target_line
+                });
+                match found {
+                    Some(pos) => {
+                        remaining.remove(pos);
+                    }
+                    None => unmet.add_error(
+                        ErrorCode::E6001,
+                        format!("Expected {} on line {}, but it was not reported", code, directive.target_line),
+                        directive.location.clone(),
+                    ),
+                }
+            }
+        }
+
+        let mut result = DiagnosticBag::new();
+        for diagnostic in remaining {
+            result.add(diagnostic);
+        }
+        result.extend(unmet);
+        result
+    }
+}
+
+/// Find the start of a `//` comment on a line, ignoring string literals
+fn find_comment(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_string => i += 1,
+            b'"' => in_string = !in_string,
+            b'/' if !in_string && bytes.get(i + 1) == Some(&b'/') => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bag(diagnostics: &[(ErrorCode, usize)]) -> DiagnosticBag {
+        let mut bag = DiagnosticBag::new();
+        for (code, line) in diagnostics {
+            bag.add_error(*code, "test".to_string(), Location::new(*line, 1));
+        }
+        bag
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        let source = "// wtlang: allow(E3001, E3004)\n\n    let x = y\nlet z = w // expect-error E3001\n";
+        let directives = Directives::parse(source);
+        let parsed: Vec<_> = directives.iter().collect();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].kind, DirectiveKind::Allow);
+        assert_eq!(parsed[0].codes, vec!["E3001", "E3004"]);
+        assert_eq!(parsed[0].target_line, 3);
+        assert_eq!(parsed[1].kind, DirectiveKind::ExpectError);
+        assert_eq!(parsed[1].target_line, 4);
+    }
+
+    #[test]
+    fn test_comment_inside_string_is_ignored() {
+        let directives = Directives::parse("text \"// expect-error E3001\"\n");
+        assert!(directives.is_empty());
+    }
+
+    #[test]
+    fn test_allow_suppresses_diagnostic() {
+        let directives = Directives::parse("// wtlang: allow(E3001)\nlet x = y\n");
+        let result = directives.apply(&bag(&[(ErrorCode::E3001, 2), (ErrorCode::E3001, 3)]));
+        assert_eq!(result.diagnostics().len(), 1);
+        assert_eq!(result.diagnostics()[0].location.line, 3);
+    }
+
+    #[test]
+    fn test_expectations() {
+        let directives = Directives::parse("// expect-error E3001\nlet x = y\n// expect-error E3004\nlet a = 1\n");
+        let result = directives.apply(&bag(&[(ErrorCode::E3001, 2)]));
+        assert_eq!(result.diagnostics().len(), 1);
+        assert_eq!(result.diagnostics()[0].code, ErrorCode::E6001);
+        assert_eq!(result.diagnostics()[0].location.line, 3);
+    }
+}
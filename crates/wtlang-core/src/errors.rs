@@ -2,6 +2,7 @@
 // Each error has a unique code for easy identification and documentation
 
 use std::fmt;
+use crate::ast::NodeId;
 
 /// Error codes for WTLang compiler errors
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -11,6 +12,7 @@ pub enum ErrorCode {
     E1002, // Invalid number format
     E1003, // Invalid character
     E1004, // Unexpected end of file
+    E1005, // Invalid date literal
     
     // Syntax errors (E2xxx)
     E2001, // Missing closing brace
@@ -29,6 +31,12 @@ pub enum ErrorCode {
     E2014, // Invalid function parameter
     E2015, // Missing arrow in function return type
     E2016, // Missing colon in type annotation
+    E2017, // two statements on one line without `;`
+    E2018, // line starting with `(` or `[` after a complete statement
+    E2019, // `{...}` in page text that is not an expression
+    E2020, // syntax introduced by a later language version
+    E2021, // unknown or misplaced `#version`
+    E2022, // `use` of a template not defined before it
     
     // Semantic errors (E3xxx)
     E3001, // Undefined variable
@@ -52,6 +60,40 @@ pub enum ErrorCode {
     E3019, // Multiple key fields in table
     E3020, // Reference to undefined table
     E3021, // Reference to table without key field
+    E3022, // Mock outside of a test
+    E3023, // Mock target is not a table or function
+    E3024, // Unknown log level
+    E3025, // Translation key is not a string literal
+    E3026, // export_pdf section not on the page
+    E3027, // Unknown save_sql mode
+    E3028, // confirm outside of a button
+    E3029, // restrict on an undefined table
+    E3030, // restrict condition uses a column the table does not have
+    E3031, // derived table whose value is not a table
+    E3032, // materialized table derived from a restricted table
+    E3033, // hook on an undefined table
+    E3034, // second hook for the same event and table
+    E3035, // refresh outside the top level of a page
+    E3036, // Unknown load_csv column matching
+    E3037, // two fields stored in the same CSV column
+    E3038, // `in` with something other than a list
+    E3039, // `between` with values that have no order
+    E3040, // aggregate of a group's rows outside a grouped query
+    E3041, // display() without exactly one value
+    E3042, // numeric builtin given a value that is not a number
+    E3043, // format_number pattern that is not a number pattern
+    E3044, // sum/average null handling the runtime does not implement
+    E3045, // `sort by` a key whose values have no order
+    E3046, // Amounts in two currencies combined
+    E3047, // Unknown, repeated or invalid config setting
+    E3048, // `match` without an arm for every value
+    E3049, // Call of a plugin's builtin rejected by the plugin
+    E3050, // Output of a python block that its code never assigns
+    E3051, // const whose value is not known when compiling
+    E3052, // Assignment to a const
+    E3053, // Duplicate page definition
+    E3054, // Undefined module function
+    E3055, // Column compared with a literal of another type
     
     // Table/Data errors (E4xxx)
     E4001, // Table type mismatch with CSV
@@ -65,6 +107,23 @@ pub enum ErrorCode {
     E5001, // Cannot find external module
     E5002, // Invalid external function definition
     E5003, // External function not found in module
+    E5004, // Imported file not found
+    E5005, // Import cycle
+    E5006, // Module name already used
+    E5007, // Item not allowed in a module
+    
+    // Directive errors (E6xxx)
+    E6001, // Expected diagnostic was not reported
+    
+    // Backend errors (E7xxx)
+    E7001, // Feature not supported by the backend
+    
+    // Warnings (W1xxx)
+    W1001, // Unused variable
+    W1002, // operators mixed without parentheses
+    W1003, // sum/average skipping the missing values of a nullable column
+    W1004, // `match` arm that no value reaches
+    W1005, // name reserved in the generated Python
 }
 
 impl ErrorCode {
@@ -76,6 +135,7 @@ impl ErrorCode {
             ErrorCode::E1002 => "E1002",
             ErrorCode::E1003 => "E1003",
             ErrorCode::E1004 => "E1004",
+            ErrorCode::E1005 => "E1005",
             
             // Syntax errors
             ErrorCode::E2001 => "E2001",
@@ -94,6 +154,12 @@ impl ErrorCode {
             ErrorCode::E2014 => "E2014",
             ErrorCode::E2015 => "E2015",
             ErrorCode::E2016 => "E2016",
+            ErrorCode::E2017 => "E2017",
+            ErrorCode::E2018 => "E2018",
+            ErrorCode::E2019 => "E2019",
+            ErrorCode::E2020 => "E2020",
+            ErrorCode::E2021 => "E2021",
+            ErrorCode::E2022 => "E2022",
             
             // Semantic errors
             ErrorCode::E3001 => "E3001",
@@ -117,6 +183,40 @@ impl ErrorCode {
             ErrorCode::E3019 => "E3019",
             ErrorCode::E3020 => "E3020",
             ErrorCode::E3021 => "E3021",
+            ErrorCode::E3022 => "E3022",
+            ErrorCode::E3023 => "E3023",
+            ErrorCode::E3024 => "E3024",
+            ErrorCode::E3025 => "E3025",
+            ErrorCode::E3026 => "E3026",
+            ErrorCode::E3027 => "E3027",
+            ErrorCode::E3028 => "E3028",
+            ErrorCode::E3029 => "E3029",
+            ErrorCode::E3030 => "E3030",
+            ErrorCode::E3031 => "E3031",
+            ErrorCode::E3032 => "E3032",
+            ErrorCode::E3033 => "E3033",
+            ErrorCode::E3034 => "E3034",
+            ErrorCode::E3035 => "E3035",
+            ErrorCode::E3036 => "E3036",
+            ErrorCode::E3037 => "E3037",
+            ErrorCode::E3038 => "E3038",
+            ErrorCode::E3039 => "E3039",
+            ErrorCode::E3040 => "E3040",
+            ErrorCode::E3041 => "E3041",
+            ErrorCode::E3042 => "E3042",
+            ErrorCode::E3043 => "E3043",
+            ErrorCode::E3044 => "E3044",
+            ErrorCode::E3045 => "E3045",
+            ErrorCode::E3046 => "E3046",
+            ErrorCode::E3047 => "E3047",
+            ErrorCode::E3048 => "E3048",
+            ErrorCode::E3049 => "E3049",
+            ErrorCode::E3050 => "E3050",
+            ErrorCode::E3051 => "E3051",
+            ErrorCode::E3052 => "E3052",
+            ErrorCode::E3053 => "E3053",
+            ErrorCode::E3054 => "E3054",
+            ErrorCode::E3055 => "E3055",
             
             // Table/Data errors
             ErrorCode::E4001 => "E4001",
@@ -130,6 +230,23 @@ impl ErrorCode {
             ErrorCode::E5001 => "E5001",
             ErrorCode::E5002 => "E5002",
             ErrorCode::E5003 => "E5003",
+            ErrorCode::E5004 => "E5004",
+            ErrorCode::E5005 => "E5005",
+            ErrorCode::E5006 => "E5006",
+            ErrorCode::E5007 => "E5007",
+            
+            // Directive errors
+            ErrorCode::E6001 => "E6001",
+            
+            // Backend errors
+            ErrorCode::E7001 => "E7001",
+            
+            // Warnings
+            ErrorCode::W1001 => "W1001",
+            ErrorCode::W1002 => "W1002",
+            ErrorCode::W1003 => "W1003",
+            ErrorCode::W1004 => "W1004",
+            ErrorCode::W1005 => "W1005",
         }
     }
     
@@ -141,6 +258,7 @@ impl ErrorCode {
             ErrorCode::E1002 => "Invalid number format",
             ErrorCode::E1003 => "Invalid character",
             ErrorCode::E1004 => "Unexpected end of file",
+            ErrorCode::E1005 => "Invalid date literal",
             
             // Syntax errors
             ErrorCode::E2001 => "Missing closing brace",
@@ -159,6 +277,12 @@ impl ErrorCode {
             ErrorCode::E2014 => "Invalid function parameter",
             ErrorCode::E2015 => "Missing arrow in function return type",
             ErrorCode::E2016 => "Missing colon in type annotation",
+            ErrorCode::E2017 => "Missing statement separator",
+            ErrorCode::E2018 => "Ambiguous line break",
+            ErrorCode::E2019 => "Invalid interpolation",
+            ErrorCode::E2020 => "Feature requires a newer language version",
+            ErrorCode::E2021 => "Invalid language version",
+            ErrorCode::E2022 => "Undefined template",
             
             // Semantic errors
             ErrorCode::E3001 => "Undefined variable",
@@ -182,6 +306,40 @@ impl ErrorCode {
             ErrorCode::E3019 => "Multiple key fields in table",
             ErrorCode::E3020 => "Reference to undefined table",
             ErrorCode::E3021 => "Reference to table without key field",
+            ErrorCode::E3022 => "Mock outside of a test",
+            ErrorCode::E3023 => "Invalid mock target",
+            ErrorCode::E3024 => "Unknown log level",
+            ErrorCode::E3025 => "Translation key is not a string literal",
+            ErrorCode::E3026 => "Exported section not on the page",
+            ErrorCode::E3027 => "Unknown save mode",
+            ErrorCode::E3028 => "Confirmation outside of a button",
+            ErrorCode::E3029 => "Restriction on an undefined table",
+            ErrorCode::E3030 => "Unknown column in restriction",
+            ErrorCode::E3031 => "Derived table is not a table",
+            ErrorCode::E3032 => "Materialized table derived from a restricted table",
+            ErrorCode::E3033 => "Hook on an undefined table",
+            ErrorCode::E3034 => "Duplicate hook",
+            ErrorCode::E3035 => "Refresh outside a page",
+            ErrorCode::E3036 => "Unknown column matching",
+            ErrorCode::E3037 => "Duplicate column header",
+            ErrorCode::E3038 => "Membership test on a non-list",
+            ErrorCode::E3039 => "Range test on unordered values",
+            ErrorCode::E3040 => "Aggregate outside a grouped query",
+            ErrorCode::E3041 => "Wrong number of values to display",
+            ErrorCode::E3042 => "Number function of a non-number",
+            ErrorCode::E3043 => "Invalid number pattern",
+            ErrorCode::E3044 => "Unknown null handling",
+            ErrorCode::E3045 => "Sort key without an order",
+            ErrorCode::E3046 => "Mixed currencies",
+            ErrorCode::E3047 => "Invalid config setting",
+            ErrorCode::E3048 => "Match not covering every value",
+            ErrorCode::E3049 => "Builtin call rejected by its plugin",
+            ErrorCode::E3050 => "Python block output never assigned",
+            ErrorCode::E3051 => "Constant value not constant",
+            ErrorCode::E3052 => "Assignment to constant",
+            ErrorCode::E3053 => "Page already defined",
+            ErrorCode::E3054 => "Module has no such function",
+            ErrorCode::E3055 => "Column compared with a mistyped literal",
             
             // Table/Data errors
             ErrorCode::E4001 => "Table structure mismatch with CSV",
@@ -195,6 +353,21 @@ impl ErrorCode {
             ErrorCode::E5001 => "Cannot find external module",
             ErrorCode::E5002 => "Invalid external function definition",
             ErrorCode::E5003 => "External function not found in module",
+            ErrorCode::E5004 => "Imported file not found",
+            ErrorCode::E5005 => "Import cycle",
+            ErrorCode::E5006 => "Module name already used",
+            ErrorCode::E5007 => "Item not allowed in a module",
+            
+            // Directive errors
+            ErrorCode::E6001 => "Expected diagnostic was not reported",
+            ErrorCode::E7001 => "Feature not supported by the backend",
+            
+            // Warnings
+            ErrorCode::W1001 => "Unused variable",
+            ErrorCode::W1002 => "Ambiguous operator precedence",
+            ErrorCode::W1003 => "Missing values skipped silently",
+            ErrorCode::W1004 => "Unreachable match arm",
+            ErrorCode::W1005 => "Name reserved in Python",
         }
     }
     
@@ -202,22 +375,739 @@ impl ErrorCode {
     pub fn help(&self) -> Option<&'static str> {
         match self {
             ErrorCode::E1001 => Some("Add a closing quote (\") to terminate the string literal"),
-            ErrorCode::E1002 => Some("Check the number format - use digits only, with optional decimal point"),
+            ErrorCode::E1002 => Some("Check the number format - digits grouped by single underscores, an optional decimal point and an optional exponent such as e6"),
+            ErrorCode::E1005 => Some("Write dates as d\"YYYY-MM-DD\", such as d\"2024-01-15\""),
             ErrorCode::E2001 => Some("Add a closing brace (}) to match the opening brace"),
             ErrorCode::E2003 => Some("Add a closing parenthesis ())"),
             ErrorCode::E2005 => Some("Add a closing bracket (])"),
             ErrorCode::E2007 => Some("Provide a valid identifier (variable or function name)"),
             ErrorCode::E2016 => Some("Use colon (:) syntax for type annotations: let name: type"),
+            ErrorCode::E2017 => Some("Start the next statement on a new line, or separate the two with ';'"),
+            ErrorCode::E2018 => Some("Move the '(' or '[' to the end of the previous line; `wtc check --fix` joins the lines"),
+            ErrorCode::E2019 => Some("Write an expression between the braces, or `\\{` for a literal brace"),
+            ErrorCode::E2020 => Some("Raise the version with `#version` or `language_version` in wt.toml, or write it without the feature"),
+            ErrorCode::E2021 => Some("Write `#version \"0.2\"` on the first line, with one of the known versions"),
+            ErrorCode::E2022 => Some("Define the template above the page, function or test using it"),
             ErrorCode::E3001 => Some("Declare the variable before using it with 'let variable_name'"),
             ErrorCode::E3004 => Some("Use a different name or remove one of the definitions"),
+            ErrorCode::E3005 => Some("Rename one of the functions or remove one of the definitions"),
+            ErrorCode::E3006 => Some("Merge the fields into one table or rename one of the tables"),
             ErrorCode::E3007 => Some("Ensure the value type matches the variable's declared type"),
             ErrorCode::E3011 => Some("Initialize the variable before using it, or use conditional initialization"),
             ErrorCode::E3019 => Some("Only one field can be marked as 'key' in a table definition"),
             ErrorCode::E3020 => Some("Define the referenced table before using it in a 'ref' type"),
             ErrorCode::E3021 => Some("Add a 'key' constraint to the referenced table"),
+            ErrorCode::E3022 => Some("Move the 'mock' statement into a 'test' block"),
+            ErrorCode::E3023 => Some("Only tables, functions and external functions can be mocked"),
+            ErrorCode::E3024 => Some("Use \"info\", \"warn\" or \"error\""),
+            ErrorCode::E3025 => Some("Pass the key as a string, e.g. t(\"welcome\")"),
+            ErrorCode::E3026 => Some("Pass the title of a section of the same page as a string, e.g. export_pdf(\"Summary\")"),
+            ErrorCode::E3027 => Some("Use \"append\", \"replace\" or \"upsert\""),
+            ErrorCode::E3028 => Some("Move the 'confirm' block into the body of the button it confirms"),
+            ErrorCode::E3029 => Some("Restrict a table defined in the program, e.g. restrict Order where region == current_user().region"),
+            ErrorCode::E3030 => Some("Compare the table's own columns with values of the user, e.g. region == current_user().region"),
+            ErrorCode::E3031 => Some("Derive the table from a loaded table, e.g. table Active = load_csv(\"users.csv\", User) where active"),
+            ErrorCode::E3032 => Some("Remove the materialize option so the table is computed for each user"),
+            ErrorCode::E3033 => Some("Attach the hook to a table defined in the program, e.g. on_edit Order { check_totals(edited) }"),
+            ErrorCode::E3034 => Some("Move the statements of both hooks into one"),
+            ErrorCode::E3035 => Some("Move `refresh every ...` to the top level of the page"),
+            ErrorCode::E3036 => Some("Use \"exact\" or \"normalize\""),
+            ErrorCode::E3037 => Some("Give each field its own column with `from \"Header\"`"),
+            ErrorCode::E3038 => Some("Write the values as a list, e.g. region in [\"EU\", \"US\"], or compare with =="),
+            ErrorCode::E3039 => Some("Compare bool values with == instead"),
+            ErrorCode::E3040 => Some("Add `group by column` after the condition, or aggregate a whole table with sum(table, \"column\")"),
+            ErrorCode::E3041 => Some("Display each value with its own `display value` statement"),
+            ErrorCode::E3042 => Some("Pass an int, float or currency value, such as a numeric field of a row"),
+            ErrorCode::E3043 => Some("Use '#' and '0' digits, ',' to group thousands and '.' before the decimals, as in \"#,##0.00\""),
+            ErrorCode::E3044 => Some("Use \"skip_nulls\" to leave out missing values or \"strict\" to stop on them"),
+            ErrorCode::E3045 => Some("Sort by a number, string or date, such as a column or arithmetic on columns"),
+            ErrorCode::E3046 => Some("Write both amounts in the same currency"),
+            ErrorCode::E3047 => Some("Use one config block, each setting once, with a value the setting accepts"),
+            ErrorCode::E3048 => Some("Add an arm for the missing value, or a last `_ =>` arm for every other value"),
+            ErrorCode::E3049 => Some("Pass arguments the builtin's plugin accepts; its documentation lists them"),
+            ErrorCode::E3050 => Some("Assign the output in the block's code, or remove it from the outputs after `->`"),
+            ErrorCode::E3051 => Some("Build the value from literals, operators and the constants declared before it, or use `shared let`"),
+            ErrorCode::E3052 => Some("Constants cannot change; declare a variable with `let` to hold a changing value"),
+            ErrorCode::E3053 => Some("Rename one of the pages; each page is generated into a file named after it"),
+            ErrorCode::E3054 => Some("Check the spelling, or define the function in the imported module"),
+            ErrorCode::E3055 => Some("Compare the column with a literal of its declared type, such as d\"2024-01-01\" for a date"),
+            ErrorCode::E5004 => Some("Check the path, which is relative to the directory of the importing file"),
+            ErrorCode::E5005 => Some("Move the items the files share to another file that both import"),
+            ErrorCode::E5006 => Some("Import each module under a name of its own"),
+            ErrorCode::E5007 => Some("Move the item to a file imported without a name, which the module can import too"),
+            ErrorCode::E6001 => Some("Remove the 'expect-error' comment or fix the code so the diagnostic is reported on the next line"),
+            ErrorCode::W1001 => Some("Remove the variable or prefix its name with an underscore"),
+            ErrorCode::W1002 => Some("Add parentheses to show which operator applies first; `wtc check --fix` adds them"),
+            ErrorCode::W1003 => Some("Pass \"skip_nulls\" or \"strict\" as the third argument, or mark the field non_null"),
+            ErrorCode::W1004 => Some("Remove the arm, or move it before the arm that already matches its values"),
+            ErrorCode::W1005 => Some("Rename it, so that the generated code uses the name you wrote"),
             _ => None,
         }
     }
+    
+    /// Every error code, in numeric order
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::E1001,
+        ErrorCode::E1002,
+        ErrorCode::E1003,
+        ErrorCode::E1004,
+        ErrorCode::E1005,
+        ErrorCode::E2001,
+        ErrorCode::E2002,
+        ErrorCode::E2003,
+        ErrorCode::E2004,
+        ErrorCode::E2005,
+        ErrorCode::E2006,
+        ErrorCode::E2007,
+        ErrorCode::E2008,
+        ErrorCode::E2009,
+        ErrorCode::E2010,
+        ErrorCode::E2011,
+        ErrorCode::E2012,
+        ErrorCode::E2013,
+        ErrorCode::E2014,
+        ErrorCode::E2015,
+        ErrorCode::E2016,
+        ErrorCode::E2017,
+        ErrorCode::E2018,
+        ErrorCode::E2019,
+        ErrorCode::E2020,
+        ErrorCode::E2021,
+        ErrorCode::E2022,
+        ErrorCode::E3001,
+        ErrorCode::E3002,
+        ErrorCode::E3003,
+        ErrorCode::E3004,
+        ErrorCode::E3005,
+        ErrorCode::E3006,
+        ErrorCode::E3007,
+        ErrorCode::E3008,
+        ErrorCode::E3009,
+        ErrorCode::E3010,
+        ErrorCode::E3011,
+        ErrorCode::E3012,
+        ErrorCode::E3013,
+        ErrorCode::E3014,
+        ErrorCode::E3015,
+        ErrorCode::E3016,
+        ErrorCode::E3017,
+        ErrorCode::E3018,
+        ErrorCode::E3019,
+        ErrorCode::E3020,
+        ErrorCode::E3021,
+        ErrorCode::E3022,
+        ErrorCode::E3023,
+        ErrorCode::E3024,
+        ErrorCode::E3025,
+        ErrorCode::E3026,
+        ErrorCode::E3027,
+        ErrorCode::E3028,
+        ErrorCode::E3029,
+        ErrorCode::E3030,
+        ErrorCode::E3031,
+        ErrorCode::E3032,
+        ErrorCode::E3033,
+        ErrorCode::E3034,
+        ErrorCode::E3035,
+        ErrorCode::E3036,
+        ErrorCode::E3037,
+        ErrorCode::E3038,
+        ErrorCode::E3039,
+        ErrorCode::E3040,
+        ErrorCode::E3041,
+        ErrorCode::E3042,
+        ErrorCode::E3043,
+        ErrorCode::E3044,
+        ErrorCode::E3045,
+        ErrorCode::E3046,
+        ErrorCode::E3047,
+        ErrorCode::E3048,
+        ErrorCode::E3049,
+        ErrorCode::E3050,
+        ErrorCode::E3051,
+        ErrorCode::E3052,
+        ErrorCode::E3053,
+        ErrorCode::E3054,
+        ErrorCode::E3055,
+        ErrorCode::E4001,
+        ErrorCode::E4002,
+        ErrorCode::E4003,
+        ErrorCode::E4004,
+        ErrorCode::E4005,
+        ErrorCode::E4006,
+        ErrorCode::E5001,
+        ErrorCode::E5002,
+        ErrorCode::E5003,
+        ErrorCode::E5004,
+        ErrorCode::E5005,
+        ErrorCode::E5006,
+        ErrorCode::E5007,
+        ErrorCode::E6001,
+        ErrorCode::E7001,
+        ErrorCode::W1001,
+        ErrorCode::W1002,
+        ErrorCode::W1003,
+        ErrorCode::W1004,
+        ErrorCode::W1005,
+    ];
+    
+    /// Whether this code is reported as a warning rather than an error
+    pub fn is_warning(&self) -> bool {
+        self.code().starts_with('W')
+    }
+    
+    /// Look up an error code from its string form (e.g. "E3007")
+    pub fn from_code(code: &str) -> Option<ErrorCode> {
+        let code = code.trim();
+        ErrorCode::ALL.iter().copied().find(|c| c.code().eq_ignore_ascii_case(code))
+    }
+    
+    /// Link to this code's entry in the error code reference
+    pub fn doc_url(&self) -> String {
+        format!("{}#{}", ERROR_CODES_DOC_URL, heading_anchor(&format!("{}: {}", self.code(), self.description())))
+    }
+    
+    /// Get the long-form explanation shown by `wtc explain`
+    pub fn explanation(&self) -> Explanation {
+        match self {
+            ErrorCode::E1001 => Explanation {
+                explanation: "A string literal was opened with a double quote but the line or file ended before the closing quote.",
+                example: Some("page Home {\n    text \"Hello, World\n}"),
+                corrected: Some("page Home {\n    text \"Hello, World\"\n}"),
+            },
+            ErrorCode::E1002 => Explanation {
+                explanation: "A numeric literal contains characters or a layout the lexer does not accept, such as more than one decimal point, an underscore that is not between two digits or an exponent without digits.",
+                example: Some("let ratio = 12.34.56"),
+                corrected: Some("let ratio = 12.34"),
+            },
+            ErrorCode::E1003 => Explanation {
+                explanation: "The lexer found a character that cannot start any WTLang token.",
+                example: Some("let value = 42 @"),
+                corrected: Some("let value = 42"),
+            },
+            ErrorCode::E1004 => Explanation {
+                explanation: "The file ended in the middle of a token or construct.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E1005 => Explanation {
+                explanation: "A date literal `d\"...\"` must hold an ISO date, a four-digit year, month and day separated by dashes, and the day must exist in that month.",
+                example: Some("let due = d\"2024-02-30\""),
+                corrected: Some("let due = d\"2024-02-29\""),
+            },
+            ErrorCode::E2001 => Explanation {
+                explanation: "A block was opened with `{` but no matching `}` was found. The parser also reports this code when it finds a token that cannot start a top-level item or statement, since that usually means a block was closed too early or left open.",
+                example: Some("page Home {\n    text \"Hello\""),
+                corrected: Some("page Home {\n    text \"Hello\"\n}"),
+            },
+            ErrorCode::E2002 => Explanation {
+                explanation: "A page, section, button, function or table body must start with `{`.",
+                example: Some("page Home\n    text \"Hello\"\n}"),
+                corrected: Some("page Home {\n    text \"Hello\"\n}"),
+            },
+            ErrorCode::E2003 => Explanation {
+                explanation: "A parameter or argument list was opened with `(` but not closed. The parser also reports this code when a type was expected but something else was found.",
+                example: Some("function add(x: int, y: int -> int {\n    return x + y\n}"),
+                corrected: Some("function add(x: int, y: int) -> int {\n    return x + y\n}"),
+            },
+            ErrorCode::E2004 => Explanation {
+                explanation: "A function call or definition is missing its `(`. The parser also reports this code for a `let` that has neither a type annotation nor an initializer.",
+                example: Some("let total"),
+                corrected: Some("let total: int"),
+            },
+            ErrorCode::E2005 => Explanation {
+                explanation: "An array literal or constraint list was opened with `[` but not closed.",
+                example: Some("table Users {\n    id: int [key\n}"),
+                corrected: Some("table Users {\n    id: int [key]\n}"),
+            },
+            ErrorCode::E2006 => Explanation {
+                explanation: "A constraint list or array literal is missing its `[`.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E2007 => Explanation {
+                explanation: "A name was expected here, for example after `let`, `page`, `table` or `function`.",
+                example: Some("let : int = 42"),
+                corrected: Some("let count: int = 42"),
+            },
+            ErrorCode::E2008 => Explanation {
+                explanation: "A type was expected but not provided, for example on a function parameter, or a variable was declared without a type annotation or initializer.",
+                example: Some("function add(x, y) -> int {\n    return x + y\n}"),
+                corrected: Some("function add(x: int, y: int) -> int {\n    return x + y\n}"),
+            },
+            ErrorCode::E2009 => Explanation {
+                explanation: "An expression was expected, for example on the right-hand side of `=` or as a function argument.",
+                example: Some("let total = "),
+                corrected: Some("let total = 0"),
+            },
+            ErrorCode::E2010 => Explanation {
+                explanation: "A statement was expected inside a block.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E2011 => Explanation {
+                explanation: "A token was found that does not fit the surrounding syntax, such as a missing string literal after `title` or `text`.",
+                example: Some("page Home {\n    title Welcome\n}"),
+                corrected: Some("page Home {\n    title \"Welcome\"\n}"),
+            },
+            ErrorCode::E2012 => Explanation {
+                explanation: "Reserved for a missing statement terminator. The parser currently also reports this code when a field constraint list contains something other than `key`, `unique` or `non_null`.",
+                example: Some("table Users {\n    id: int [primary]\n}"),
+                corrected: Some("table Users {\n    id: int [key]\n}"),
+            },
+            ErrorCode::E2013 => Explanation {
+                explanation: "Parameters and arguments must be separated by commas.",
+                example: Some("function add(x: int y: int) -> int {\n    return x + y\n}"),
+                corrected: Some("function add(x: int, y: int) -> int {\n    return x + y\n}"),
+            },
+            ErrorCode::E2014 => Explanation {
+                explanation: "A function parameter must be written as `name: type`.",
+                example: Some("function greet(\"name\") -> string {\n    return name\n}"),
+                corrected: Some("function greet(name: string) -> string {\n    return name\n}"),
+            },
+            ErrorCode::E2015 => Explanation {
+                explanation: "Function definitions declare their return type after `->`.",
+                example: Some("function add(x: int, y: int) int {\n    return x + y\n}"),
+                corrected: Some("function add(x: int, y: int) -> int {\n    return x + y\n}"),
+            },
+            ErrorCode::E2016 => Explanation {
+                explanation: "Type annotations and table fields need a `:` between the name and the type.",
+                example: Some("let count int = 5"),
+                corrected: Some("let count: int = 5"),
+            },
+            ErrorCode::E2017 => Explanation {
+                explanation: "Statements end at the end of their line or at a `;`. Two statements written on one line need a `;` between them, so a statement cannot silently run into the next one.",
+                example: Some("page Home {\n    title \"Orders\" show(orders)\n}"),
+                corrected: Some("page Home {\n    title \"Orders\"; show(orders)\n}"),
+            },
+            ErrorCode::E2018 => Explanation {
+                explanation: "A `(` or `[` at the start of a line does not continue the expression on the line before, since a line break ends a call's name and the table being indexed. A statement cannot start with `(` or `[`, so the line is reported rather than being read as a new statement.",
+                example: Some("page Home {\n    show\n        (orders)\n}"),
+                corrected: Some("page Home {\n    show(orders)\n}"),
+            },
+            ErrorCode::E2019 => Explanation {
+                explanation: "Page text shows the value of each `{expr}` placeholder, so the text between the braces must be an expression such as a variable, a field or a call. A brace meant as text is written `\\{`.",
+                example: Some("page Home {\n    text \"Totals {} in EUR\"\n}"),
+                corrected: Some("page Home {\n    text \"Totals \\{} in EUR\"\n}"),
+            },
+            ErrorCode::E2020 => Explanation {
+                explanation: "The program pins an older language version, with `#version` or `language_version` in wt.toml, and uses syntax a later version introduced. Pinning keeps a program's meaning as the language grows; raising the version lets it use the newer syntax.",
+                example: Some("#version \"0.1\"\n\npage Home {\n    let label = match 1 { 1 => \"one\", _ => \"many\" }\n}"),
+                corrected: Some("#version \"0.2\"\n\npage Home {\n    let label = match 1 { 1 => \"one\", _ => \"many\" }\n}"),
+            },
+            ErrorCode::E2021 => Explanation {
+                explanation: "`#version` names the language version the program is written for. It must come before any declaration, at most once, and name a known version.",
+                example: Some("page Home {\n    title \"Home\"\n}\n#version \"0.1\""),
+                corrected: Some("#version \"0.1\"\npage Home {\n    title \"Home\"\n}"),
+            },
+            ErrorCode::E2022 => Explanation {
+                explanation: "`use name(args)` stands for the statements of the template `name`, which is expanded where it is used. A template must be defined above its uses, so a template cannot use itself.",
+                example: Some("page Home {\n    use greeting(\"Ada\")\n}\n\ntemplate greeting(name: string) {\n    text \"Hello, {name}\"\n}"),
+                corrected: Some("template greeting(name: string) {\n    text \"Hello, {name}\"\n}\n\npage Home {\n    use greeting(\"Ada\")\n}"),
+            },
+            ErrorCode::E3001 => Explanation {
+                explanation: "A name is used that is not declared in the current scope or any enclosing scope. Variables declared inside a section, button, branch or loop are not visible outside it.",
+                example: Some("page Home {\n    total = 10\n}"),
+                corrected: Some("page Home {\n    let total: int\n    total = 10\n}"),
+            },
+            ErrorCode::E3002 => Explanation {
+                explanation: "A function is called that is neither defined in the program, declared as `external`, nor a built-in.",
+                example: Some("page Home {\n    let result = calculate(5, 10)\n}"),
+                corrected: Some("function calculate(x: int, y: int) -> int {\n    return x + y\n}\n\npage Home {\n    let result = calculate(5, 10)\n}"),
+            },
+            ErrorCode::E3003 => Explanation {
+                explanation: "A table type is referenced that was never defined.",
+                example: Some("page Home {\n    let users = load_csv(\"users.csv\", User)\n}"),
+                corrected: Some("table User {\n    id: int [key],\n    name: string\n}\n\npage Home {\n    let users = load_csv(\"users.csv\", User)\n}"),
+            },
+            ErrorCode::E3004 => Explanation {
+                explanation: "A name is declared twice in the same scope. Shadowing a name from an enclosing scope is allowed; redeclaring it in the same scope is not.",
+                example: Some("page Home {\n    let count = 5\n    let count = 10\n}"),
+                corrected: Some("page Home {\n    let count: int = 5\n    count = 10\n}"),
+            },
+            ErrorCode::E3005 => Explanation {
+                explanation: "Two functions have the same name.",
+                example: Some("function total() -> int {\n    return 1\n}\n\nfunction total() -> int {\n    return 2\n}"),
+                corrected: Some("function total() -> int {\n    return 1\n}\n\nfunction grand_total() -> int {\n    return 2\n}"),
+            },
+            ErrorCode::E3006 => Explanation {
+                explanation: "Two tables have the same name.",
+                example: Some("table User {\n    id: int\n}\n\ntable User {\n    name: string\n}"),
+                corrected: Some("table User {\n    id: int,\n    name: string\n}"),
+            },
+            ErrorCode::E3007 => Explanation {
+                explanation: "The value assigned to a variable does not have the variable's declared type.",
+                example: Some("page Home {\n    let count: int = \"hello\"\n}"),
+                corrected: Some("page Home {\n    let count: int = 42\n    let message: string = \"hello\"\n}"),
+            },
+            ErrorCode::E3008 => Explanation {
+                explanation: "An argument does not have the type of the corresponding parameter.",
+                example: Some("function double(x: int) -> int {\n    return x * 2\n}\n\npage Home {\n    let result = double(\"two\")\n}"),
+                corrected: Some("page Home {\n    let result = double(2)\n}"),
+            },
+            ErrorCode::E3009 => Explanation {
+                explanation: "A function returns a value whose type differs from its declared return type.",
+                example: Some("function name() -> string {\n    return 42\n}"),
+                corrected: Some("function name() -> string {\n    return \"Ada\"\n}"),
+            },
+            ErrorCode::E3010 => Explanation {
+                explanation: "A function is called with more or fewer arguments than it declares.",
+                example: Some("function add(x: int, y: int) -> int {\n    return x + y\n}\n\npage Home {\n    let result = add(5)\n}"),
+                corrected: Some("page Home {\n    let result = add(5, 10)\n}"),
+            },
+            ErrorCode::E3011 => Explanation {
+                explanation: "A variable declared without an initializer is read before every path has assigned it a value.",
+                example: Some("page Home {\n    let result: int\n    show(result)\n}"),
+                corrected: Some("page Home {\n    let result: int\n    result = 42\n    show(result)\n}"),
+            },
+            ErrorCode::E3012 => Explanation {
+                explanation: "A field is accessed that is not part of the table definition.",
+                example: Some("table User {\n    id: int,\n    name: string\n}\n\nfunction email(u: User) -> string {\n    return u.email\n}"),
+                corrected: Some("function name(u: User) -> string {\n    return u.name\n}"),
+            },
+            ErrorCode::E3013 => Explanation {
+                explanation: "Field access with `.` only works on table rows and references.",
+                example: Some("page Home {\n    let count = 5\n    let x = count.value\n}"),
+                corrected: None,
+            },
+            ErrorCode::E3014 => Explanation {
+                explanation: "`break` can only be used inside a `forall` loop.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E3015 => Explanation {
+                explanation: "`continue` can only be used inside a `forall` loop.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E3016 => Explanation {
+                explanation: "`return` can only be used inside a function body.",
+                example: Some("page Home {\n    return 1\n}"),
+                corrected: Some("function one() -> int {\n    return 1\n}"),
+            },
+            ErrorCode::E3017 => Explanation {
+                explanation: "A function with a return type has a path that ends without returning a value.",
+                example: Some("function sign(x: int) -> int {\n    if x > 0 {\n        return 1\n    }\n}"),
+                corrected: Some("function sign(x: int) -> int {\n    if x > 0 {\n        return 1\n    }\n    return 0\n}"),
+            },
+            ErrorCode::E3018 => Explanation {
+                explanation: "Statements after a `return` in the same block never run.",
+                example: Some("function one() -> int {\n    return 1\n    let unused = 2\n}"),
+                corrected: Some("function one() -> int {\n    return 1\n}"),
+            },
+            ErrorCode::E3019 => Explanation {
+                explanation: "A table can have at most one field marked `key`.",
+                example: Some("table Order {\n    id: int [key],\n    number: int [key]\n}"),
+                corrected: Some("table Order {\n    id: int [key],\n    number: int [unique]\n}"),
+            },
+            ErrorCode::E3020 => Explanation {
+                explanation: "A `ref` field points to a table that is not defined. Referenced tables must be defined before the table that references them.",
+                example: Some("table Employee {\n    id: int [key],\n    dept: ref Department\n}"),
+                corrected: Some("table Department {\n    id: int [key]\n}\n\ntable Employee {\n    id: int [key],\n    dept: ref Department\n}"),
+            },
+            ErrorCode::E3021 => Explanation {
+                explanation: "A `ref` field can only point to a table that has a `key` field, since references are stored as key values.",
+                example: Some("table Department {\n    name: string\n}\n\ntable Employee {\n    id: int [key],\n    dept: ref Department\n}"),
+                corrected: Some("table Department {\n    id: int [key],\n    name: string\n}\n\ntable Employee {\n    id: int [key],\n    dept: ref Department\n}"),
+            },
+            ErrorCode::E3022 => Explanation {
+                explanation: "`mock` replaces a table loader or function for the duration of a test, so it is only allowed inside `test` blocks.",
+                example: Some("page Home {\n    mock Orders with sample_orders()\n}"),
+                corrected: Some("test \"orders page\" {\n    mock Orders with sample_orders()\n}"),
+            },
+            ErrorCode::E3023 => Explanation {
+                explanation: "A `mock` target must name a table, a function or an external function. Variables cannot be mocked; assign them instead.",
+                example: Some("test \"totals\" {\n    let total = 0\n    mock total with 5\n}"),
+                corrected: Some("test \"totals\" {\n    let total = 5\n}"),
+            },
+            ErrorCode::E3024 => Explanation {
+                explanation: "The second argument of `log` is the level the message is logged at in the generated app. It must be \"info\", \"warn\" or \"error\".",
+                example: Some("page Home {\n    log(\"loaded orders\", \"debug\")\n}"),
+                corrected: Some("page Home {\n    log(\"loaded orders\", \"info\")\n}"),
+            },
+            ErrorCode::E3025 => Explanation {
+                explanation: "`t` takes a single string literal, the key its text is looked up by in the program's locale files. The compiler collects the keys from the source, so they cannot be computed.",
+                example: Some("page Home {\n    let key = \"welcome\"\n    title t(key)\n}"),
+                corrected: Some("page Home {\n    title t(\"welcome\")\n}"),
+            },
+            ErrorCode::E3026 => Explanation {
+                explanation: "`export_pdf` exports the text and tables of a section of the page it is called on, named by the section's title (or translation key). The title must be a string literal naming a section of that page.",
+                example: Some("page Home {\n    section \"Summary\" {\n        text \"All orders\"\n    }\n    button \"PDF\" {\n        export_pdf(\"Totals\")\n    }\n}"),
+                corrected: Some("page Home {\n    section \"Summary\" {\n        text \"All orders\"\n    }\n    button \"PDF\" {\n        export_pdf(\"Summary\")\n    }\n}"),
+            },
+            ErrorCode::E3027 => Explanation {
+                explanation: "The fourth argument of `save_sql` is how the table is written to the database: \"append\" inserts its rows, \"replace\" deletes the existing rows first, and \"upsert\" replaces the rows whose key already exists and inserts the others.",
+                example: Some("button \"Save\" {\n    save_sql(orders, \"db\", \"orders\", \"merge\")\n}"),
+                corrected: Some("button \"Save\" {\n    save_sql(orders, \"db\", \"orders\", \"upsert\")\n}"),
+            },
+            ErrorCode::E3028 => Explanation {
+                explanation: "`confirm` asks the user to confirm a button's action before its body runs, so it must be inside the body of a `button`.",
+                example: Some("page Orders {\n    confirm \"Delete all orders?\" {\n        save_csv(empty, \"orders.csv\")\n    }\n}"),
+                corrected: Some("page Orders {\n    button \"Delete all\" {\n        confirm \"Delete all orders?\" {\n            save_csv(empty, \"orders.csv\")\n        }\n    }\n}"),
+            },
+            ErrorCode::E3029 => Explanation {
+                explanation: "`restrict` limits the rows of a table that users see whenever the table is loaded, so it must name a table defined in the program.",
+                example: Some("table Order {\n    id: int [key]\n    region: string\n}\n\nrestrict Orders where region == current_user().region"),
+                corrected: Some("table Order {\n    id: int [key]\n    region: string\n}\n\nrestrict Order where region == current_user().region"),
+            },
+            ErrorCode::E3031 => Explanation {
+                explanation: "A derived table takes its schema from its value, so the value must be a table of a known type: `load_csv` or `load_sql` with a table type, another derived table, or one of these filtered, sorted, narrowed to some columns or combined with a table of the same type.",
+                example: Some("table User {\n    name: string\n    active: bool\n}\n\ntable ActiveUsers = load_csv(\"users.csv\") where active"),
+                corrected: Some("table User {\n    name: string\n    active: bool\n}\n\ntable ActiveUsers = load_csv(\"users.csv\", User) where active"),
+            },
+            ErrorCode::E3030 => Explanation {
+                explanation: "The condition of a `restrict` rule is checked against each row of the table, so every bare name in it must be a column of the table. Values of the signed-in user come from `current_user()`.",
+                example: Some("table Order {\n    id: int [key]\n    region: string\n}\n\nrestrict Order where country == current_user().country"),
+                corrected: Some("table Order {\n    id: int [key]\n    region: string\n}\n\nrestrict Order where region == current_user().region"),
+            },
+            ErrorCode::E3032 => Explanation {
+                explanation: "A materialized table is kept in one CSV file shared by every user of the app. A table with `restrict` rules shows each user different rows, so a table derived from it cannot be materialized without showing one user's rows to the others.",
+                example: Some("table Order {\n    id: int [key]\n    region: string\n}\n\nrestrict Order where region == current_user().region\n\ntable MyOrders = load_csv(\"orders.csv\", Order) materialize daily"),
+                corrected: Some("table Order {\n    id: int [key]\n    region: string\n}\n\nrestrict Order where region == current_user().region\n\ntable MyOrders = load_csv(\"orders.csv\", Order)"),
+            },
+            ErrorCode::E3033 => Explanation {
+                explanation: "`on_load`, `on_edit` and `on_save` hooks run when the rows of a table are loaded, edited or saved through its definition, so they can only be attached to a table defined with `table Name { ... }`. Derived tables are computed from other tables: attach the hook to those instead.",
+                example: Some("on_save Order {\n    log(\"saving orders\")\n}"),
+                corrected: Some("table Order {\n    id: int [key]\n    amount: float\n}\n\non_save Order {\n    log(\"saving orders\")\n}"),
+            },
+            ErrorCode::E3034 => Explanation {
+                explanation: "A table has at most one hook for each event, so the order in which its rules run is the order of the statements in that hook.",
+                example: Some("table Order {\n    id: int [key]\n    amount: float\n}\n\non_edit Order {\n    check_totals(edited)\n}\n\non_edit Order {\n    log(\"orders edited\")\n}"),
+                corrected: Some("table Order {\n    id: int [key]\n    amount: float\n}\n\non_edit Order {\n    check_totals(edited)\n    log(\"orders edited\")\n}"),
+            },
+            ErrorCode::E3035 => Explanation {
+                explanation: "`refresh every ...` reruns the whole page on a timer, so it belongs to the page itself rather than to a button, section, function or test.",
+                example: Some("page Dashboard {\n    section \"Orders\" {\n        refresh every 5m\n        show(load_csv(\"orders.csv\"))\n    }\n}"),
+                corrected: Some("page Dashboard {\n    refresh every 5m\n    section \"Orders\" {\n        show(load_csv(\"orders.csv\"))\n    }\n}"),
+            },
+            ErrorCode::E3036 => Explanation {
+                explanation: "The third argument of `load_csv` is how the headers of the file are matched to the table's columns: \"exact\" (the default) requires the same names, and \"normalize\" also accepts headers that differ in case, surrounding spaces, or spaces and dashes written for underscores.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order, \"loose\")\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order, \"normalize\")\n}"),
+            },
+            ErrorCode::E3037 => Explanation {
+                explanation: "A field is read from and written to the CSV column named like the field, or the column given with `from \"Header\"`. Two fields of a table cannot share a column, since saving the table would write both into it.",
+                example: Some("table Sale {\n    total: currency from \"Total\"\n    Total: currency\n}"),
+                corrected: Some("table Sale {\n    total: currency from \"Total\"\n    total_net: currency from \"Total (net)\"\n}"),
+            },
+            ErrorCode::E3038 => Explanation {
+                explanation: "`value in list` is true when the value equals one of the list's items, so its right side must be a list such as `[\"EU\", \"US\"]` or a variable holding one.",
+                example: Some("page Home {\n    let region = \"EU\"\n    if region in \"EU\" {\n        text \"Europe\"\n    }\n}"),
+                corrected: Some("page Home {\n    let region = \"EU\"\n    if region in [\"EU\", \"UK\"] {\n        text \"Europe\"\n    }\n}"),
+            },
+            ErrorCode::E3039 => Explanation {
+                explanation: "`value between low and high` is true when `low <= value <= high`, so the value and both bounds must be numbers, strings or dates. Bool values have no order to test.",
+                example: Some("page Home {\n    let done = true\n    if done between false and true {\n        text \"Checked\"\n    }\n}"),
+                corrected: Some("page Home {\n    let done = true\n    if done == true {\n        text \"Checked\"\n    }\n}"),
+            },
+            ErrorCode::E3040 => Explanation {
+                explanation: "`sum(column)`, `average(column)`, `min(column)`, `max(column)` and `count()` aggregate the rows of a group, so they can only be used in the condition of a grouped query: after `having`, or in a `where` followed by `group by`. Outside one there is no group to aggregate.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders where sum(amount) > 1000)\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders group by region having sum(amount) > 1000)\n}"),
+            },
+            ErrorCode::E3041 => Explanation {
+                explanation: "`display value` and `display(value)` show one value on the page, formatted for its type: numbers, currency and dates like `format_number`, `format_currency` and `format_date`, rows as their fields and tables as a table. Several values are shown with one statement each, or combined in a string such as `text \"{a} {b}\"`.",
+                example: Some("page Home {\n    let total = 10\n    let count = 2\n    display(total, count)\n}"),
+                corrected: Some("page Home {\n    let total = 10\n    let count = 2\n    display total\n    display count\n}"),
+            },
+            ErrorCode::E3042 => Explanation {
+                explanation: "`round`, `floor`, `ceil`, `abs`, `percent`, `format_number` and `format_currency` work on a single number: an int, float or currency value. Strings, dates, bools, rows and tables have no numeric value to round or format; aggregate a table's column with `sum(table, \"column\")` first.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    let total = round(orders, 2)\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    let total = round(sum(orders, \"amount\"), 2)\n}"),
+            },
+            ErrorCode::E3043 => Explanation {
+                explanation: "`format_number(value, pattern)` formats a number like a spreadsheet pattern: `,` in the whole part groups thousands and the digits after `.` are the decimals shown. A pattern may only contain `#`, `0`, `,` and a single `.` followed by at least one digit.",
+                example: Some("page Home {\n    let ratio = 0.125\n    let shown = format_number(ratio, \"0.0%\")\n}"),
+                corrected: Some("page Home {\n    let ratio = 0.125\n    let shown = percent(ratio)\n}"),
+            },
+            ErrorCode::E3044 => Explanation {
+                explanation: "The third argument of `sum(table, \"column\", mode)` and `average` says how missing values are treated: `\"skip_nulls\"` leaves them out and `\"strict\"` stops the page with an error when the column has any.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    let total = sum(orders, \"amount\", \"ignore\")\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    let total = sum(orders, \"amount\", \"skip_nulls\")\n}"),
+            },
+            ErrorCode::E3045 => Explanation {
+                explanation: "`sort by` orders rows by keys that are columns or expressions of the row's columns, such as `amount / quantity`. Each key must have ordered values: numbers, strings or dates. A condition such as `amount > 100` is a bool, and rows, tables and filters have no order either.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders sort by amount > 100 desc)\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders sort by amount desc)\n}"),
+            },
+            ErrorCode::E3046 => Explanation {
+                explanation: "Currency literals such as `12.50 EUR` or `$5` are exact amounts of their currency. Adding, subtracting or comparing amounts of two currencies needs an exchange rate, which WTLang does not apply, so the operator is reported instead of treating both as plain numbers.",
+                example: Some("let total = 12.50 EUR + $5"),
+                corrected: Some("let total = 12.50 EUR + 5 EUR"),
+            },
+            ErrorCode::E3047 => Explanation {
+                explanation: "A program has at most one `config` block, and each of its settings is given once. The settings are `title`, `theme` (\"light\" or \"dark\"), `data_dir` and the number formats `decimal_separator`, `thousands_separator`, `date_format`, `currency_symbol` and `currency_position` (\"before\" or \"after\").",
+                example: Some("config {\n    title = \"Sales\"\n    theme = \"blue\"\n}"),
+                corrected: Some("config {\n    title = \"Sales\"\n    theme = \"dark\"\n}"),
+            },
+            ErrorCode::E3048 => Explanation {
+                explanation: "`match` runs, or takes the value of, the first arm with a pattern equal to the value. Every value must have an arm: a match on a bool needs arms for `true` and `false`, and any other match a last `_` arm for the values its literals do not name.",
+                example: Some("page Home {\n    let status = \"open\"\n    let label = match status {\n        \"open\" => \"Open\",\n        \"closed\" => \"Closed\"\n    }\n    text \"{label}\"\n}"),
+                corrected: Some("page Home {\n    let status = \"open\"\n    let label = match status {\n        \"open\" => \"Open\",\n        \"closed\" => \"Closed\",\n        _ => \"Unknown\"\n    }\n    text \"{label}\"\n}"),
+            },
+            ErrorCode::E3049 => Explanation {
+                explanation: "Builtins added by plugins check their arguments' types against their signatures, and a plugin can also reject arguments the function it calls cannot take, such as a value outside the ones it accepts. The message after the builtin's name is the plugin's.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E3050 => Explanation {
+                explanation: "A `python` block declares the variables it sets after `->`, and the rest of the page reads them as declared. The block's code must assign each of them, with `name = ...`, `for name in ...` or a tuple such as `low, high = ...`; the code is otherwise passed through unchecked.",
+                example: Some("page Home {\n    python -> (today: string) {\n        import datetime\n        now = datetime.date.today().isoformat()\n    }\n    text \"{today}\"\n}"),
+                corrected: Some("page Home {\n    python -> (today: string) {\n        import datetime\n        today = datetime.date.today().isoformat()\n    }\n    text \"{today}\"\n}"),
+            },
+            ErrorCode::E3051 => Explanation {
+                explanation: "A `const` declaration's value is computed when compiling, so it can only use literals, operators and the constants declared before it. Values read from tables or returned by functions are only known when the app runs.",
+                example: Some("const START = today()"),
+                corrected: Some("const START = \"2024-01-01\""),
+            },
+            ErrorCode::E3052 => Explanation {
+                explanation: "A constant has the value it was declared with everywhere in the program; pages and functions can read it but not assign it.",
+                example: Some("const LIMIT = 100\n\npage Home {\n    LIMIT = 200\n}"),
+                corrected: Some("const LIMIT = 100\n\npage Home {\n    let limit = LIMIT * 2\n    text \"{limit}\"\n}"),
+            },
+            ErrorCode::E3053 => Explanation {
+                explanation: "Two pages have the same name. Each page is generated into a file named after it, so one would overwrite the other.",
+                example: Some("page Home {\n    title \"Welcome\"\n}\n\npage Home {\n    title \"Orders\"\n}"),
+                corrected: Some("page Home {\n    title \"Welcome\"\n}\n\npage Orders {\n    title \"Orders\"\n}"),
+            },
+            ErrorCode::E3054 => Explanation {
+                explanation: "A call names a function of an imported module that the module does not define. Unlike calls of unknown functions elsewhere, which may be builtins, a module's functions are all known.",
+                example: Some("import analytics from \"analytics.wt\"\n\npage Home {\n    text \"{analytics.kpi()}\"\n}"),
+                corrected: Some("import analytics from \"analytics.wt\"\n\npage Home {\n    text \"{analytics.compute_kpi()}\"\n}"),
+            },
+            ErrorCode::E3055 => Explanation {
+                explanation: "A `where` condition compares a column with a literal that its declared type has no values like, such as a `date` column with a string or a `bool` column with a number. pandas would only fail on the comparison once the app runs, so it is reported when compiling. Numbers of any kind compare with int, float and currency columns, and references with the keys of the table they refer to.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders where placed > \"2024-01-01\")\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders where placed > d\"2024-01-01\")\n}"),
+            },
+            ErrorCode::E4001 => Explanation {
+                explanation: "The columns of a loaded CSV file do not match the table definition. Update the table definition or the CSV file so the columns line up.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E4002 => Explanation {
+                explanation: "Data for a table is missing a field that the definition requires.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E4003 => Explanation {
+                explanation: "Data for a table contains a field that the definition does not declare.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E4004 => Explanation {
+                explanation: "A table operation such as `where`, `sort` or `aggregate` was applied to something that is not a table, or with arguments it does not accept.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E4005 => Explanation {
+                explanation: "A filter is defined incorrectly. The column must exist and the mode must be `single` or `multi`.",
+                example: Some("let name_filter = filter(\"name\", \"several\")"),
+                corrected: Some("let name_filter = filter(\"name\", single)"),
+            },
+            ErrorCode::E4006 => Explanation {
+                explanation: "A filter names a column that the table does not have.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E5001 => Explanation {
+                explanation: "The Python module named in an `external function` declaration could not be found.",
+                example: Some("external function process(data: string) -> string from \"missing_module.py\""),
+                corrected: Some("external function process(data: string) -> string from \"helpers.py\""),
+            },
+            ErrorCode::E5002 => Explanation {
+                explanation: "An external function declaration does not follow `external function name(param: type) -> type from \"module\"`.",
+                example: Some("external function process(data: string) from \"helpers.py\""),
+                corrected: Some("external function process(data: string) -> string from \"helpers.py\""),
+            },
+            ErrorCode::E5003 => Explanation {
+                explanation: "The Python module exists but does not define the declared function.",
+                example: None,
+                corrected: None,
+            },
+            ErrorCode::E5004 => Explanation {
+                explanation: "An `import` names a file that cannot be read. The path is relative to the directory of the file with the `import`.",
+                example: Some("import \"shared/tables.wt\""),
+                corrected: Some("import \"../shared/tables.wt\""),
+            },
+            ErrorCode::E5005 => Explanation {
+                explanation: "A file imports itself, directly or through the files it imports. Move the items both files need to a third file that both import.",
+                example: Some("// orders.wt\nimport \"customers.wt\"\n\n// customers.wt\nimport \"orders.wt\""),
+                corrected: Some("// orders.wt\nimport \"common.wt\"\n\n// customers.wt\nimport \"common.wt\""),
+            },
+            ErrorCode::E5006 => Explanation {
+                explanation: "Two different files are imported as modules under the same name. The functions of a module are named after it, so the name must say which file they come from.",
+                example: Some("import stats from \"sales/stats.wt\"\nimport stats from \"stock/stats.wt\""),
+                corrected: Some("import sales from \"sales/stats.wt\"\nimport stock from \"stock/stats.wt\""),
+            },
+            ErrorCode::E5007 => Explanation {
+                explanation: "A file imported as a module defines something other than functions, external functions and constants. Tables, pages and the other items belong to the whole program rather than to a module, so they go in files imported without a name.",
+                example: Some("// analytics.wt, imported as `import analytics from \"analytics.wt\"`\ntable Sale {\n    amount: float\n}\n\nfunction total(sales: table(Sale)) -> float {\n    return sum(sales, \"amount\")\n}"),
+                corrected: Some("// analytics.wt\nimport \"tables.wt\"\n\nfunction total(sales: table(Sale)) -> float {\n    return sum(sales, \"amount\")\n}"),
+            },
+            ErrorCode::E6001 => Explanation {
+                explanation: "An `// expect-error` comment names a diagnostic that the compiler did not report on the following line. Either the code no longer has the problem or the expectation names the wrong code or line.",
+                example: Some("// expect-error E3001\nlet total = 1"),
+                corrected: Some("let total = 1"),
+            },
+            ErrorCode::E7001 => Explanation {
+                explanation: "The program uses a language feature that the backend it is compiled for cannot generate code for. The message names the feature, the backend and what to write instead.",
+                example: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders where amount * 2 > 100)\n}"),
+                corrected: Some("page Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    show(orders where amount > 50)\n}"),
+            },
+            ErrorCode::W1001 => Explanation {
+                explanation: "A variable is declared with `let` but its value is never read, which usually means a leftover or a misspelt name. Names starting with an underscore are not reported.",
+                example: Some("page Home {\n    let total = 10\n    text \"Done\"\n}"),
+                corrected: Some("page Home {\n    let total = 10\n    text \"Total: {total}\"\n}"),
+            },
+            ErrorCode::W1002 => Explanation {
+                explanation: "`&&` binds tighter than `||`, and comparisons bind tighter than `==` and `!=`, so an expression mixing them without parentheses may not group the way it reads. `a || b && c` means `a || (b && c)`, and `a < b == c` compares the result of `a < b` with `c`. Parentheses make the grouping explicit without changing what the expression means.",
+                example: Some("page Home {\n    let urgent = true\n    let late = false\n    let paid = true\n    if urgent || late && paid {\n        text \"Follow up\"\n    }\n}"),
+                corrected: Some("page Home {\n    let urgent = true\n    let late = false\n    let paid = true\n    if urgent || (late && paid) {\n        text \"Follow up\"\n    }\n}"),
+            },
+            ErrorCode::W1003 => Explanation {
+                explanation: "`sum(table, \"column\")` and `average(table, \"column\")` leave out missing values, so a total over a column with empty cells quietly covers fewer rows than the table has. The third argument chooses the behavior explicitly: `\"skip_nulls\"` leaves them out, and `\"strict\"` stops the page with an error when the column has any. Key and `non_null` fields always have a value, so aggregating them is not reported.",
+                example: Some("table Order {\n    id: int [key]\n    amount: currency\n}\n\npage Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    let total = sum(orders, \"amount\")\n    text \"Total: {total}\"\n}"),
+                corrected: Some("table Order {\n    id: int [key]\n    amount: currency\n}\n\npage Home {\n    let orders = load_csv(\"orders.csv\", Order)\n    let total = sum(orders, \"amount\", \"strict\")\n    text \"Total: {total}\"\n}"),
+            },
+            ErrorCode::W1004 => Explanation {
+                explanation: "`match` takes the first arm whose pattern equals the value, so an arm after a `_` arm, or one whose patterns all appear in earlier arms, is never taken. It is usually an arm in the wrong place or a repeated value.",
+                example: Some("page Home {\n    let open = true\n    match open {\n        _ => { text \"Any\" }\n        true => { text \"Open\" }\n    }\n}"),
+                corrected: Some("page Home {\n    let open = true\n    match open {\n        true => { text \"Open\" }\n        _ => { text \"Any\" }\n    }\n}"),
+            },
+            ErrorCode::W1005 => Explanation {
+                explanation: "A variable, parameter, function or constant is named like a Python keyword, a Python builtin the generated code calls, or a name the generated code imports, such as `class`, `len` or `st`. It would be invalid Python or hide what the generated code uses, so it is generated with a trailing underscore, `class_`. The program works the same, but the generated code and its error messages use the other name.",
+                example: Some("page Home {\n    let class = \"premium\"\n    text \"{class}\"\n}"),
+                corrected: Some("page Home {\n    let tier = \"premium\"\n    text \"{tier}\"\n}"),
+            },
+        }
+    }
+}
+
+/// Location of the error code reference linked from diagnostics
+pub const ERROR_CODES_DOC_URL: &str = "https://github.com/ercasta/wtlang/blob/main/doc/error_codes.md";
+
+/// Anchor GitHub gives a Markdown heading: lowercased, without punctuation,
+/// and with spaces turned into hyphens
+fn heading_anchor(heading: &str) -> String {
+    heading.trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+/// Long-form documentation for an error code
+///
+/// `example` shows code that triggers the error and `corrected` a fixed
+/// version of it. Codes reported outside source text (CSV mismatches,
+/// missing modules) may have neither.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    pub explanation: &'static str,
+    pub example: Option<&'static str>,
+    pub corrected: Option<&'static str>,
 }
 
 impl fmt::Display for ErrorCode {
@@ -226,6 +1116,91 @@ impl fmt::Display for ErrorCode {
     }
 }
 
+/// How diagnostics matched by a lint selector are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Warning levels configured on the command line with `-A`, `-W` and `-D`
+///
+/// A selector is `warnings` (every warning), a group such as `unused`, or a
+/// single warning code such as `W1001`. Code selectors override groups,
+/// which override `warnings`; when one selector is given several levels the
+/// strictest wins. Errors always stay errors.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    levels: Vec<(String, Level)>,
+}
+
+impl LintLevels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Set the level of a selector, rejecting unknown selectors and error codes
+    pub fn set(&mut self, selector: &str, level: Level) -> Result<(), String> {
+        let selector = selector.trim().to_lowercase();
+        if selector != "warnings" && lint_group(&selector).is_none() {
+            match ErrorCode::from_code(&selector) {
+                Some(code) if !code.is_warning() => {
+                    return Err(format!("{} is an error and cannot be allowed or denied", code));
+                }
+                Some(_) => {}
+                None => return Err(format!("unknown lint '{}'", selector)),
+            }
+        }
+        self.levels.push((selector, level));
+        Ok(())
+    }
+    
+    /// Level configured for a warning code, if any selector matches it
+    pub fn level_for(&self, code: ErrorCode) -> Option<Level> {
+        let strictest = |matches: &dyn Fn(&str) -> bool| {
+            self.levels.iter()
+                .filter(|(selector, _)| matches(selector))
+                .map(|(_, level)| *level)
+                .max()
+        };
+        strictest(&|s| s.eq_ignore_ascii_case(code.code()))
+            .or_else(|| strictest(&|s| lint_group(s).is_some_and(|codes| codes.contains(&code))))
+            .or_else(|| strictest(&|s| s == "warnings"))
+    }
+    
+    /// Adjust the severity of warnings in a set of diagnostics
+    ///
+    /// Allowed warnings are dropped and denied ones become errors.
+    pub fn apply(&self, diagnostics: &DiagnosticBag) -> DiagnosticBag {
+        let mut result = DiagnosticBag::new();
+        for diagnostic in diagnostics.diagnostics() {
+            let mut diagnostic = diagnostic.clone();
+            if diagnostic.severity == Severity::Warning {
+                match self.level_for(diagnostic.code) {
+                    Some(Level::Allow) => continue,
+                    Some(Level::Deny) => diagnostic.severity = Severity::Error,
+                    Some(Level::Warn) | None => {}
+                }
+            }
+            result.add(diagnostic);
+        }
+        result
+    }
+}
+
+/// Warning codes belonging to a named lint group
+fn lint_group(name: &str) -> Option<&'static [ErrorCode]> {
+    match name {
+        "unused" => Some(&[ErrorCode::W1001]),
+        "precedence" => Some(&[ErrorCode::W1002]),
+        "nulls" => Some(&[ErrorCode::W1003]),
+        "unreachable" => Some(&[ErrorCode::W1004]),
+        "python_names" => Some(&[ErrorCode::W1005]),
+        _ => None,
+    }
+}
+
 /// Location information for an error
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Location {
@@ -281,7 +1256,10 @@ pub struct Diagnostic {
     pub code: ErrorCode,
     pub message: String,
     pub location: Location,
+    pub end: Option<Location>,  // Just past the offending text, when known
     pub context: Option<String>,  // Source code snippet showing the error
+    pub fix: Option<Fix>,
+    pub node: Option<NodeId>,  // Node the semantic analyzer reported it at
 }
 
 impl Diagnostic {
@@ -291,7 +1269,10 @@ impl Diagnostic {
             code,
             message,
             location,
+            end: None,
             context: None,
+            fix: None,
+            node: None,
         }
     }
     
@@ -308,6 +1289,21 @@ impl Diagnostic {
         self
     }
     
+    pub fn with_end(mut self, end: Location) -> Self {
+        self.end = Some(end);
+        self
+    }
+    
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+    
+    pub fn with_node(mut self, node: NodeId) -> Self {
+        self.node = Some(node);
+        self
+    }
+    
     /// Format the diagnostic for display
     pub fn format(&self) -> String {
         let mut output = String::new();
@@ -335,10 +1331,67 @@ impl Diagnostic {
             output.push_str(&format!("  = help: {}\n", help));
         }
         
+        if let Some(ref fix) = self.fix {
+            output.push_str(&format!("  = fix: {}\n", fix.message));
+        }
+        
         output
     }
 }
 
+/// Source edits that resolve a diagnostic, applied by `wtc check --fix` and
+/// offered as a quick fix by the language server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Replacement of the source text from `start` up to (not including) `end`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: Location,
+    pub end: Location,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn insert(location: Location, text: &str) -> Self {
+        TextEdit { start: location.clone(), end: location, text: text.to_string() }
+    }
+}
+
+/// Apply the edits of several fixes to source text
+///
+/// Edits are applied from the end of the source backwards so earlier
+/// positions stay valid; an edit overlapping one already applied is skipped.
+pub fn apply_fixes<'a>(source: &str, fixes: impl IntoIterator<Item = &'a Fix>) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let mut line_starts = vec![0];
+    line_starts.extend(chars.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(i, _)| i + 1));
+    let offset = |location: &Location| {
+        let line_start = line_starts.get(location.line.saturating_sub(1)).copied().unwrap_or(chars.len());
+        (line_start + location.column.saturating_sub(1)).min(chars.len())
+    };
+    
+    let mut edits: Vec<(usize, usize, &str)> = fixes.into_iter()
+        .flat_map(|fix| &fix.edits)
+        .map(|edit| (offset(&edit.start), offset(&edit.end), edit.text.as_str()))
+        .collect();
+    edits.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    
+    let mut result = chars;
+    let mut applied_start = usize::MAX;
+    for (start, end, text) in edits {
+        if end > applied_start {
+            continue;
+        }
+        result.splice(start..end, text.chars());
+        applied_start = start;
+    }
+    result.into_iter().collect()
+}
+
 impl fmt::Display for Diagnostic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.format())
@@ -370,6 +1423,16 @@ impl DiagnosticBag {
         self.add(Diagnostic::warning(code, message, location));
     }
     
+    /// Move all diagnostics from another bag into this one
+    pub fn extend(&mut self, other: DiagnosticBag) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+    
+    /// Keep only the diagnostics for which the predicate returns true
+    pub fn retain(&mut self, f: impl FnMut(&Diagnostic) -> bool) {
+        self.diagnostics.retain(f);
+    }
+    
     pub fn has_errors(&self) -> bool {
         self.diagnostics.iter().any(|d| d.severity == Severity::Error)
     }
@@ -386,6 +1449,11 @@ impl DiagnosticBag {
         &self.diagnostics
     }
     
+    /// Diagnostics reported at a node
+    pub fn at_node(&self, node: NodeId) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(move |d| d.node == Some(node))
+    }
+    
     pub fn is_empty(&self) -> bool {
         self.diagnostics.is_empty()
     }
@@ -430,6 +1498,76 @@ mod tests {
         assert_eq!(ErrorCode::E3001.code(), "E3001");
     }
 
+    #[test]
+    fn test_from_code() {
+        assert_eq!(ErrorCode::from_code("E3007"), Some(ErrorCode::E3007));
+        assert_eq!(ErrorCode::from_code("e3007"), Some(ErrorCode::E3007));
+        assert_eq!(ErrorCode::from_code("E9999"), None);
+    }
+
+    #[test]
+    fn test_every_code_is_listed_and_explained() {
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_code(code.code()), Some(*code));
+            assert!(!code.explanation().explanation.is_empty());
+        }
+        assert_eq!(
+            ErrorCode::E3007.doc_url(),
+            format!("{}#e3007-type-mismatch-in-assignment", ERROR_CODES_DOC_URL)
+        );
+    }
+
+    #[test]
+    fn test_every_code_has_a_doc_section() {
+        let doc = include_str!("../../../doc/error_codes.md");
+        let anchors: Vec<String> = doc.lines()
+            .filter_map(|line| line.strip_prefix("### "))
+            .map(heading_anchor)
+            .collect();
+        for code in ErrorCode::ALL {
+            let url = code.doc_url();
+            let anchor = url.rsplit('#').next().unwrap();
+            assert!(anchors.iter().any(|a| a == anchor), "doc/error_codes.md has no section for {}", url);
+        }
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let mut bag = DiagnosticBag::new();
+        bag.add_warning(ErrorCode::W1001, "Variable 'x' is never used".to_string(), Location::new(1, 1));
+        bag.add_error(ErrorCode::E3001, "Undefined variable".to_string(), Location::new(2, 1));
+        
+        let mut levels = LintLevels::new();
+        levels.set("warnings", Level::Deny).unwrap();
+        assert_eq!(levels.apply(&bag).error_count(), 2);
+        
+        levels.set("unused", Level::Allow).unwrap();
+        let result = levels.apply(&bag);
+        assert_eq!(result.diagnostics().len(), 1);
+        assert_eq!(result.diagnostics()[0].code, ErrorCode::E3001);
+        
+        levels.set("W1001", Level::Warn).unwrap();
+        assert_eq!(levels.apply(&bag).warning_count(), 1);
+        
+        assert!(levels.set("E3001", Level::Allow).is_err());
+        assert!(levels.set("nonsense", Level::Deny).is_err());
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let fix = |edits: Vec<TextEdit>| Fix { message: "add parentheses".to_string(), edits };
+        let source = "if a || b && c {\n    x = y < z == w\n}";
+        let fixes = [
+            fix(vec![TextEdit::insert(Location::new(1, 9), "("), TextEdit::insert(Location::new(1, 15), ")")]),
+            fix(vec![TextEdit::insert(Location::new(2, 9), "("), TextEdit::insert(Location::new(2, 14), ")")]),
+        ];
+        assert_eq!(apply_fixes(source, &fixes), "if a || (b && c) {\n    x = (y < z) == w\n}");
+        
+        let replace = TextEdit { start: Location::new(1, 1), end: Location::new(1, 3), text: "when".to_string() };
+        let overlapping = TextEdit { start: Location::new(1, 2), end: Location::new(1, 4), text: "!".to_string() };
+        assert_eq!(apply_fixes("if a", &[fix(vec![replace, overlapping])]), "i!a");
+    }
+
     #[test]
     fn test_error_code_description() {
         assert_eq!(ErrorCode::E1001.description(), "Unterminated string literal");
@@ -0,0 +1,226 @@
+// Constant expression evaluation for WTLang
+//
+// Evaluates expressions built only from literals and operators, such as
+// `2 * (3 + 4)` or `"a" + "b"`, and the values of `const` declarations,
+// which may use the constants declared before them. Anything that depends
+// on runtime data (variables, tables, function calls) is not constant;
+// tools report only its type.
+
+use crate::ast::{BinaryOp, Expr, Type, UnaryOp};
+use crate::intern::Atom;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Result of evaluating a constant expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl Value {
+    /// The value as one of type `ty`, which for a whole number declared
+    /// `float` is a float
+    pub fn with_type(self, ty: Option<&Type>) -> Value {
+        match (self, ty) {
+            (Value::Int(n), Some(Type::Float)) => Value::Float(n as f64),
+            (value, _) => value,
+        }
+    }
+
+    pub fn type_of(&self) -> Type {
+        match self {
+            Value::Int(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::String(_) => Type::String,
+            Value::Bool(_) => Type::Bool,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The expression depends on values only known at runtime
+    NotConstant,
+    DivisionByZero,
+    Overflow,
+    InvalidOperands { op: String, left: Type, right: Type },
+    InvalidOperand { op: String, operand: Type },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::NotConstant => write!(f, "Expression is not constant"),
+            EvalError::DivisionByZero => write!(f, "Division by zero"),
+            EvalError::Overflow => write!(f, "Integer overflow"),
+            EvalError::InvalidOperands { op, left, right } => {
+                write!(f, "Cannot apply '{}' to {} and {}", op, left, right)
+            }
+            EvalError::InvalidOperand { op, operand } => {
+                write!(f, "Cannot apply '{}' to {}", op, operand)
+            }
+        }
+    }
+}
+
+/// Evaluate an expression made only of literals and operators
+pub fn eval_const(expr: &Expr) -> Result<Value, EvalError> {
+    eval_const_with(expr, &HashMap::new())
+}
+
+/// Evaluate an expression made only of literals, operators and the
+/// `const` declarations given with their values
+pub fn eval_const_with(expr: &Expr, constants: &HashMap<Atom, Value>) -> Result<Value, EvalError> {
+    let eval = |expr| eval_const_with(expr, constants);
+    match expr {
+        Expr::IntLiteral(n) => Ok(Value::Int(*n)),
+        Expr::FloatLiteral(x) => Ok(Value::Float(*x)),
+        Expr::StringLiteral(s) => Ok(Value::String(s.clone())),
+        Expr::BoolLiteral(b) => Ok(Value::Bool(*b)),
+        Expr::Identifier(name) => constants.get(name).cloned().ok_or(EvalError::NotConstant),
+        Expr::UnaryOp { op, operand, .. } => eval_unary(op, eval(operand)?),
+        Expr::BinaryOp { op, left, right, .. } => eval_binary(op, eval(left)?, eval(right)?),
+        Expr::Between { value, low, high, .. } => {
+            let value = eval(value)?;
+            let above = eval_binary(&BinaryOp::GreaterThanEqual, value.clone(), eval(low)?)?;
+            let below = eval_binary(&BinaryOp::LessThanEqual, value, eval(high)?)?;
+            eval_binary(&BinaryOp::And, above, below)
+        }
+        _ => Err(EvalError::NotConstant),
+    }
+}
+
+pub(crate) fn eval_unary(op: &UnaryOp, operand: Value) -> Result<Value, EvalError> {
+    match (op, operand) {
+        (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (UnaryOp::Negate, Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or(EvalError::Overflow),
+        (UnaryOp::Negate, Value::Float(x)) => Ok(Value::Float(-x)),
+        // Constants are never missing
+        (UnaryOp::IsNull, _) => Ok(Value::Bool(false)),
+        (UnaryOp::IsNotNull, _) => Ok(Value::Bool(true)),
+        (op, operand) => Err(EvalError::InvalidOperand {
+            op: unary_symbol(op).to_string(),
+            operand: operand.type_of(),
+        }),
+    }
+}
+
+pub(crate) fn eval_binary(op: &BinaryOp, left: Value, right: Value) -> Result<Value, EvalError> {
+    use Value::*;
+
+    let invalid = |left: &Value, right: &Value| EvalError::InvalidOperands {
+        op: op.symbol().to_string(),
+        left: left.type_of(),
+        right: right.type_of(),
+    };
+
+    // Mixed int/float arithmetic promotes to float
+    let (left, right) = match (left, right) {
+        (Int(a), Float(b)) => (Float(a as f64), Float(b)),
+        (Float(a), Int(b)) => (Float(a), Float(b as f64)),
+        pair => pair,
+    };
+
+    match (op, &left, &right) {
+        (BinaryOp::Divide | BinaryOp::Modulo, Int(_), Int(0)) => Err(EvalError::DivisionByZero),
+        (BinaryOp::Add, Int(a), Int(b)) => a.checked_add(*b).map(Int).ok_or(EvalError::Overflow),
+        (BinaryOp::Subtract, Int(a), Int(b)) => a.checked_sub(*b).map(Int).ok_or(EvalError::Overflow),
+        (BinaryOp::Multiply, Int(a), Int(b)) => a.checked_mul(*b).map(Int).ok_or(EvalError::Overflow),
+        (BinaryOp::Divide, Int(a), Int(b)) => a.checked_div(*b).map(Int).ok_or(EvalError::Overflow),
+        (BinaryOp::Modulo, Int(a), Int(b)) => a.checked_rem(*b).map(Int).ok_or(EvalError::Overflow),
+        (BinaryOp::Add, Float(a), Float(b)) => Ok(Float(a + b)),
+        (BinaryOp::Subtract, Float(a), Float(b)) => Ok(Float(a - b)),
+        (BinaryOp::Multiply, Float(a), Float(b)) => Ok(Float(a * b)),
+        (BinaryOp::Divide, Float(a), Float(b)) => Ok(Float(a / b)),
+        (BinaryOp::Modulo, Float(a), Float(b)) => Ok(Float(a % b)),
+        (BinaryOp::Add, String(a), String(b)) => Ok(String(format!("{}{}", a, b))),
+        (BinaryOp::And, Bool(a), Bool(b)) => Ok(Bool(*a && *b)),
+        (BinaryOp::Or, Bool(a), Bool(b)) => Ok(Bool(*a || *b)),
+        (BinaryOp::Equal, _, _) if left.type_of() == right.type_of() => Ok(Bool(left == right)),
+        (BinaryOp::NotEqual, _, _) if left.type_of() == right.type_of() => Ok(Bool(left != right)),
+        (BinaryOp::LessThan | BinaryOp::LessThanEqual | BinaryOp::GreaterThan | BinaryOp::GreaterThanEqual, _, _) => {
+            let ordering = match (&left, &right) {
+                (Int(a), Int(b)) => a.partial_cmp(b),
+                (Float(a), Float(b)) => a.partial_cmp(b),
+                (String(a), String(b)) => a.partial_cmp(b),
+                _ => return Err(invalid(&left, &right)),
+            };
+            let result = ordering.is_some_and(|o| match op {
+                BinaryOp::LessThan => o.is_lt(),
+                BinaryOp::LessThanEqual => o.is_le(),
+                BinaryOp::GreaterThan => o.is_gt(),
+                _ => o.is_ge(),
+            });
+            Ok(Bool(result))
+        }
+        _ => Err(invalid(&left, &right)),
+    }
+}
+
+fn unary_symbol(op: &UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Not => "!",
+        UnaryOp::Negate => "-",
+        UnaryOp::IsNull => "is null",
+        UnaryOp::IsNotNull => "is not null",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn eval(source: &str) -> Result<Value, EvalError> {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        eval_const(&parser.parse_standalone_expression().unwrap())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("2 * (3 + 4)"), Ok(Value::Int(14)));
+        assert_eq!(eval("7 % 4 - 1"), Ok(Value::Int(2)));
+        assert_eq!(eval("1 + 0.5"), Ok(Value::Float(1.5)));
+        assert_eq!(eval("-3"), Ok(Value::Int(-3)));
+    }
+
+    #[test]
+    fn test_strings_and_comparisons() {
+        assert_eq!(eval("\"a\" + \"b\""), Ok(Value::String("ab".to_string())));
+        assert_eq!(eval("1 < 2 && 3 >= 3"), Ok(Value::Bool(true)));
+        assert_eq!(eval("\"x\" == \"y\""), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("1 / 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("total + 1"), Err(EvalError::NotConstant));
+        assert!(matches!(eval("1 + \"a\""), Err(EvalError::InvalidOperands { .. })));
+    }
+
+    #[test]
+    fn test_constants() {
+        let constants = HashMap::from([(Atom::new("RATE"), Value::Float(0.25))]);
+        let mut lexer = Lexer::new("RATE * 4");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let expr = parser.parse_standalone_expression().unwrap();
+        assert_eq!(eval_const_with(&expr, &constants), Ok(Value::Float(1.0)));
+        assert_eq!(eval_const(&expr), Err(EvalError::NotConstant));
+    }
+}
@@ -0,0 +1,326 @@
+// Incremental re-analysis for WTLang
+//
+// Keeps the results of checking and lowering each top-level item, keyed by
+// a hash of the item's syntax tree as if it were the program's first item
+// and started on the first line. When a program is re-analyzed, only items
+// whose content changed are checked and lowered again; items that an edit
+// above them only moved get their cached results moved with them. Any
+// change to the global declarations (tables, function signatures,
+// externals) invalidates everything, since every body is checked against
+// them.
+
+use crate::ast::{Program, ProgramItem};
+use crate::ir::{IRBuilder, IRItem, IRModule};
+use crate::semantics::{ItemAnalysis, SemanticAnalyzer};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default)]
+struct CachedItem {
+    /// Results of checking the item, for functions, pages and tests
+    analysis: Option<ItemAnalysis>,
+    /// The item lowered to IR, once `build_ir` lowered it
+    ir: Option<IRItem>,
+    /// Position of the item among the program's items when last seen
+    item: u32,
+    /// Line the item started on when last seen
+    first_line: usize,
+}
+
+impl CachedItem {
+    /// Move the cached results to where the item is now
+    fn move_to(&mut self, item: u32, first_line: usize) {
+        let lines = first_line as isize - self.first_line as isize;
+        if (item, lines) != (self.item, 0) {
+            self.analysis = self.analysis.as_ref().map(|analysis| analysis.moved(item, lines));
+            self.ir = self.ir.as_ref().map(|ir| ir.moved(item, lines));
+        }
+        self.item = item;
+        self.first_line = first_line;
+    }
+}
+
+/// Cache of per-item analysis and IR across successive versions of a program
+#[derive(Debug, Default)]
+pub struct IncrementalAnalyzer {
+    declarations_hash: Option<u64>,
+    items: HashMap<u64, CachedItem>,
+    reanalyzed: usize,
+    reused: usize,
+    relowered: usize,
+}
+
+impl IncrementalAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Analyze a program, reusing results for items unchanged since the
+    /// previous call
+    ///
+    /// The returned analyzer is equivalent to running
+    /// `SemanticAnalyzer::analyze` on the whole program.
+    pub fn analyze(&mut self, program: &Program) -> SemanticAnalyzer {
+        let declarations_hash = declarations_hash(program);
+        if self.declarations_hash != Some(declarations_hash) {
+            self.items.clear();
+            self.declarations_hash = Some(declarations_hash);
+        }
+        self.reanalyzed = 0;
+        self.reused = 0;
+
+        let mut analyzer = SemanticAnalyzer::new();
+        analyzer.declare_items(program);
+
+        let hashes: Vec<(u64, usize)> = program.items.iter().map(item_hash).collect();
+
+        // Same order as `SemanticAnalyzer::analyze`: function bodies first,
+        // then pages and tests
+        let functions = program.items.iter().zip(&hashes)
+            .filter(|(item, _)| matches!(item, ProgramItem::FunctionDef(_)));
+        let pages_and_tests = program.items.iter().zip(&hashes)
+            .filter(|(item, _)| matches!(item, ProgramItem::Page(_) | ProgramItem::Test(_)));
+
+        for (item, &(hash, first_line)) in functions.chain(pages_and_tests) {
+            let cached = self.items.entry(hash).or_default();
+            cached.move_to(item.id().item, first_line);
+            match &cached.analysis {
+                Some(analysis) => {
+                    analyzer.reuse_item(analysis);
+                    self.reused += 1;
+                }
+                None => {
+                    cached.analysis = Some(analyzer.check_item(item));
+                    self.reanalyzed += 1;
+                }
+            }
+        }
+
+        // Drop entries for items that no longer exist
+        self.items.retain(|hash, _| hashes.iter().any(|(live, _)| live == hash));
+
+        analyzer
+    }
+
+    /// Analyze a program and lower it to IR, reusing both the analysis and
+    /// the IR of unchanged items
+    ///
+    /// Fails with the same message as `IRBuilder::build` if analysis reports
+    /// errors.
+    pub fn build_ir(&mut self, program: &Program, file: PathBuf) -> Result<IRModule, String> {
+        let analyzer = self.analyze(program);
+        if !analyzer.get_errors().is_empty() {
+            return Err(analyzer.get_errors().iter()
+                .map(|e| format!("{:?}", e))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        self.relowered = 0;
+
+        let mut ir_module = IRModule::new(
+            file.file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("program")
+                .to_string()
+        );
+        ir_module.symbols = analyzer.get_symbol_table().clone();
+
+        let mut builder = IRBuilder::with_symbols(file, analyzer.get_symbol_table().clone());
+        for item in &program.items {
+            match item {
+                // Resolved by the module loader before, as for `IRBuilder::build`
+                ProgramItem::Import(_) => continue,
+                // Computed from the constants before them, so lowered in
+                // order every time
+                ProgramItem::Constant(_) => {
+                    ir_module.items.push(builder.lower_item(item)?);
+                    continue;
+                }
+                _ => {}
+            }
+            let (hash, first_line) = item_hash(item);
+            let cached = self.items.entry(hash).or_default();
+            cached.move_to(item.id().item, first_line);
+            let ir_item = match &cached.ir {
+                Some(ir_item) => ir_item.clone(),
+                None => {
+                    let ir_item = builder.lower_item(item)?;
+                    cached.ir = Some(ir_item.clone());
+                    self.relowered += 1;
+                    ir_item
+                }
+            };
+            ir_module.items.push(ir_item);
+        }
+
+        Ok(ir_module)
+    }
+
+    /// Number of items checked during the last `analyze` call
+    pub fn reanalyzed(&self) -> usize {
+        self.reanalyzed
+    }
+
+    /// Number of items restored from the cache during the last `analyze` call
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// Number of items lowered during the last `build_ir` call, constants
+    /// left out
+    pub fn relowered(&self) -> usize {
+        self.relowered
+    }
+}
+
+/// Hash of an item's syntax tree, as if it were the program's first item
+/// and started on the first line, with the line it does start on
+///
+/// An item that only moved keeps its hash, and its cached results are moved
+/// by the difference between the two lines. Columns are kept: an item whose
+/// lines changed their indentation is not only moved.
+fn item_hash(item: &ProgramItem) -> (u64, usize) {
+    let first_line = item.span().line;
+    let mut hasher = DefaultHasher::new();
+    item.moved(0, 1 - first_line.max(1) as isize).hash(&mut hasher);
+    (hasher.finish(), first_line)
+}
+
+/// Hash of everything `declare_items` looks at
+fn declarations_hash(program: &Program) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for item in &program.items {
+        match item {
+            ProgramItem::TableDef(_) | ProgramItem::ExternalFunction(_) | ProgramItem::DerivedTable(_)
+            | ProgramItem::SharedVariable(_) | ProgramItem::Constant(_) | ProgramItem::Config(_) => {
+                item_hash(item).0.hash(&mut hasher);
+            }
+            ProgramItem::Hook(hook) => (hook.event, hook.table).hash(&mut hasher),
+            ProgramItem::FunctionDef(func) => (func.name, &func.params, &func.return_type).hash(&mut hasher),
+            // Pages are declared for their names only
+            ProgramItem::Page(page) => page.name.hash(&mut hasher),
+            ProgramItem::Test(_) | ProgramItem::Restriction(_) | ProgramItem::Import(_) => {}
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.parse().unwrap()
+    }
+
+    #[test]
+    fn test_unchanged_items_are_reused() {
+        let mut incremental = IncrementalAnalyzer::new();
+        let program = parse("page Home {\n    let a = 1\n}\npage About {\n    let b = 2\n}");
+        incremental.analyze(&program);
+        assert_eq!(incremental.reanalyzed(), 2);
+
+        let edited = parse("page Home {\n    let a = 1\n}\npage About {\n    let b = 3\n}");
+        let analyzer = incremental.analyze(&edited);
+        assert_eq!(incremental.reanalyzed(), 1);
+        assert_eq!(incremental.reused(), 1);
+
+        // The reused page still contributes its scope and types
+        assert!(analyzer.get_symbol_table().resolve_at(2, 9, "a").is_some());
+        assert!(analyzer.get_type_map().type_at(2, 9).is_some());
+    }
+
+    #[test]
+    fn test_items_moved_by_an_edit_are_reused() {
+        let mut incremental = IncrementalAnalyzer::new();
+        let program = "page Home {\n    let a = 1\n}\ntable T {\n    id: int\n}\npage About {\n    let b = 2\n    missing = 3\n}";
+        incremental.analyze(&parse(program));
+        assert_eq!(incremental.reanalyzed(), 2);
+
+        // Two more lines in Home move the table and About down
+        let edited = "page Home {\n    let a = 1\n    let c = a\n    text \"{c}\"\n}\ntable T {\n    id: int\n}\npage About {\n    let b = 2\n    missing = 3\n}";
+        let analyzer = incremental.analyze(&parse(edited));
+        assert_eq!(incremental.reanalyzed(), 1);
+        assert_eq!(incremental.reused(), 1);
+
+        // The reused page's positions moved with it
+        assert!(analyzer.get_symbol_table().resolve_at(10, 9, "b").is_some());
+        assert!(analyzer.get_symbol_table().resolve_at(8, 9, "b").is_none());
+        assert_eq!(analyzer.get_type_map().type_at(10, 9).map(|(span, _)| span.line), Some(10));
+        let diagnostics = analyzer.diagnostics();
+        let lines: Vec<usize> = diagnostics.diagnostics().iter().map(|d| d.location.line).collect();
+        let mut full = SemanticAnalyzer::new();
+        let _ = full.analyze(&parse(edited));
+        let expected: Vec<usize> = full.diagnostics().diagnostics().iter().map(|d| d.location.line).collect();
+        assert_eq!(lines, expected);
+        assert!(lines.contains(&11));
+    }
+
+    #[test]
+    fn test_inserting_an_item_keeps_the_items_below_cached() {
+        let mut incremental = IncrementalAnalyzer::new();
+        let program = "page Home {\n    let a = 1 + 2\n}\npage About {\n    let b = 3 * 4\n    missing = b\n}";
+        incremental.analyze(&parse(program));
+
+        // A new first item moves the others down and renumbers their nodes
+        let edited = format!("test \"sums\" {{\n    assert 1 + 1 == 2\n}}\n{}", program);
+        let analyzer = incremental.analyze(&parse(&edited));
+        assert_eq!(incremental.reanalyzed(), 1);
+        assert_eq!(incremental.reused(), 2);
+
+        let mut full = SemanticAnalyzer::new();
+        let _ = full.analyze(&parse(&edited));
+        let types: Vec<_> = analyzer.get_type_map().iter().collect();
+        let expected: Vec<_> = full.get_type_map().iter().collect();
+        assert_eq!(types, expected);
+        let sites = |analyzer: &SemanticAnalyzer| analyzer.diagnostics().diagnostics().iter()
+            .map(|d| (d.location.line, d.location.column))
+            .collect::<Vec<_>>();
+        assert_eq!(sites(&analyzer), sites(&full));
+    }
+
+    #[test]
+    fn test_build_ir_reuses_lowered_items() {
+        let file = PathBuf::from("app.wt");
+        let program = "table T {\n    id: int\n}\nconst LIMIT = 3\nfunction double(x: int) -> int {\n    return x * 2\n}\npage Home {\n    let a = double(LIMIT)\n}";
+        let mut incremental = IncrementalAnalyzer::new();
+        incremental.build_ir(&parse(program), file.clone()).unwrap();
+        assert_eq!(incremental.relowered(), 3);
+
+        // Only the new test is lowered; the others are moved below it
+        let edited = format!("test \"sums\" {{\n    assert 1 + 1 == 2\n}}\n{}", program);
+        let module = incremental.build_ir(&parse(&edited), file.clone()).unwrap();
+        assert_eq!(incremental.relowered(), 1);
+        let expected = IRBuilder::with_file(file).build(&parse(&edited)).unwrap();
+        assert_eq!(module.items, expected.items);
+    }
+
+    #[test]
+    fn test_declaration_change_invalidates_cache() {
+        let mut incremental = IncrementalAnalyzer::new();
+        incremental.analyze(&parse("table T {\n    id: int\n}\npage Home {\n    let a = 1\n}"));
+        incremental.analyze(&parse("table T {\n    name: string\n}\npage Home {\n    let a = 1\n}"));
+        assert_eq!(incremental.reanalyzed(), 1);
+        assert_eq!(incremental.reused(), 0);
+    }
+
+    #[test]
+    fn test_errors_match_full_analysis() {
+        let source = "page Home {\n    x = 1\n}\npage About {\n    y = 2\n}";
+        let mut full = SemanticAnalyzer::new();
+        let expected = full.analyze(&parse(source)).unwrap_err();
+
+        let mut incremental = IncrementalAnalyzer::new();
+        incremental.analyze(&parse(source));
+        let analyzer = incremental.analyze(&parse(source));
+        assert_eq!(incremental.reused(), 2);
+        assert_eq!(analyzer.get_errors().len(), expected.len());
+    }
+}
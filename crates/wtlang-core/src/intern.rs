@@ -0,0 +1,218 @@
+// String interning for WTLang identifiers
+//
+// Identifiers flow from the lexer through the AST, symbol table and IR.
+// Interning them once means every later stage clones a `u32` instead of a
+// heap string, and comparing two names is a single integer comparison.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+/// Interned identifier
+///
+/// Equality and hashing use the interned id. Ordering compares the
+/// underlying text so sorted output stays alphabetical.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Atom(u32);
+
+/// Process-wide string table backing `Atom`
+///
+/// Interned strings are leaked so `Atom::as_str` can hand out `&'static str`
+/// without holding the lock. The set of distinct identifiers in a program is
+/// small, so this is bounded in practice even for a long-running LSP.
+#[derive(Default)]
+struct Interner {
+    map: HashMap<&'static str, Atom>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, s: &str) -> Atom {
+        if let Some(&atom) = self.map.get(s) {
+            return atom;
+        }
+        let s: &'static str = Box::leak(s.to_string().into_boxed_str());
+        let atom = Atom(self.strings.len() as u32);
+        self.strings.push(s);
+        self.map.insert(s, atom);
+        atom
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| Mutex::new(Interner::default()))
+}
+
+impl Atom {
+    /// Intern a string, returning the existing atom if it was seen before
+    pub fn new(s: &str) -> Self {
+        interner().lock().unwrap().intern(s)
+    }
+
+    /// Look up an already-interned string without adding it
+    ///
+    /// Returns `None` if the string was never interned, which means no
+    /// symbol with that name can exist.
+    pub fn get(s: &str) -> Option<Self> {
+        interner().lock().unwrap().map.get(s).copied()
+    }
+
+    /// Get the interned text
+    pub fn as_str(&self) -> &'static str {
+        interner().lock().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl Deref for Atom {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Atom {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(s: &str) -> Self {
+        Atom::new(s)
+    }
+}
+
+impl From<String> for Atom {
+    fn from(s: String) -> Self {
+        Atom::new(&s)
+    }
+}
+
+impl From<&String> for Atom {
+    fn from(s: &String) -> Self {
+        Atom::new(s)
+    }
+}
+
+impl From<Atom> for String {
+    fn from(atom: Atom) -> Self {
+        atom.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Atom {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Atom {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Atom {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl PartialEq<Atom> for &str {
+    fn eq(&self, other: &Atom) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Atom> for String {
+    fn eq(&self, other: &Atom) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Atom {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self.0 == other.0 {
+            std::cmp::Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Match `String`'s Debug output so diagnostics read the same
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Atom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Atom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Atom::new(&s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_string_same_atom() {
+        let a = Atom::new("customer_id");
+        let b = Atom::from("customer_id".to_string());
+        assert_eq!(a, b);
+        assert_eq!(a.as_str(), "customer_id");
+    }
+
+    #[test]
+    fn test_different_strings_differ() {
+        assert_ne!(Atom::new("left"), Atom::new("right"));
+    }
+
+    #[test]
+    fn test_get_does_not_intern() {
+        assert!(Atom::get("never_interned_identifier_xyz").is_none());
+        let atom = Atom::new("interned_identifier_xyz");
+        assert_eq!(Atom::get("interned_identifier_xyz"), Some(atom));
+    }
+
+    #[test]
+    fn test_display_and_debug() {
+        let atom = Atom::new("Users");
+        assert_eq!(format!("{}", atom), "Users");
+        assert_eq!(format!("{:?}", atom), "\"Users\"");
+    }
+
+    #[test]
+    fn test_ordering_is_alphabetical() {
+        let z = Atom::new("zeta_order");
+        let a = Atom::new("alpha_order");
+        let mut atoms = vec![z, a];
+        atoms.sort();
+        assert_eq!(atoms, vec![a, z]);
+    }
+}
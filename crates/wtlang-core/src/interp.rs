@@ -0,0 +1,2022 @@
+// Tree-walking interpreter for WTLang
+//
+// Runs pages, tests and functions straight from the AST, without generating
+// Python. Display statements are collected as `Output` instead of being
+// rendered, buttons are treated as not clicked and filters are ignored.
+// A `DebugHook` can observe every statement that has a source span, which
+// is what the debug adapter builds on.
+
+use crate::ast::*;
+use crate::eval::{eval_binary, eval_const_with, eval_unary, EvalError, Value};
+use crate::intern::Atom;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::semantics::{number_pattern, LOG_LEVELS};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// A value produced while running a program
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeValue {
+    Scalar(Value),
+    /// Missing value, e.g. an empty CSV cell
+    Null,
+    List(Vec<RuntimeValue>),
+    Row(Vec<(Atom, RuntimeValue)>),
+    Table(TableValue),
+}
+
+impl RuntimeValue {
+    /// Text shown for the value in page output, without string quotes
+    pub fn to_display_string(&self) -> String {
+        match self {
+            RuntimeValue::Scalar(Value::String(s)) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    fn as_table(&self) -> Option<&TableValue> {
+        match self {
+            RuntimeValue::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeValue::Scalar(value) => write!(f, "{}", value),
+            RuntimeValue::Null => write!(f, "null"),
+            RuntimeValue::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            RuntimeValue::Row(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            RuntimeValue::Table(table) => write!(f, "{}", table),
+        }
+    }
+}
+
+impl From<Value> for RuntimeValue {
+    fn from(value: Value) -> Self {
+        RuntimeValue::Scalar(value)
+    }
+}
+
+/// Rows of a table, stored by column position
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableValue {
+    /// Table definition the rows follow, when known
+    pub name: Option<Atom>,
+    pub columns: Vec<Atom>,
+    pub rows: Vec<Vec<RuntimeValue>>,
+}
+
+impl TableValue {
+    pub fn new(name: Option<Atom>, columns: Vec<Atom>) -> Self {
+        TableValue { name, columns, rows: Vec::new() }
+    }
+
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|c| *c == column)
+    }
+
+    /// A row as a `Row` value
+    pub fn row(&self, index: usize) -> Option<RuntimeValue> {
+        let row = self.rows.get(index)?;
+        Some(RuntimeValue::Row(self.columns.iter().copied().zip(row.iter().cloned()).collect()))
+    }
+
+    /// The first `max_rows` rows as aligned text, noting how many were left out
+    pub fn preview(&self, max_rows: usize) -> String {
+        let shown: Vec<Vec<String>> = self.rows.iter()
+            .take(max_rows)
+            .map(|row| row.iter().map(|value| value.to_display_string()).collect())
+            .collect();
+        let widths: Vec<usize> = self.columns.iter().enumerate()
+            .map(|(i, column)| {
+                shown.iter().map(|row| row[i].chars().count()).chain([column.as_str().chars().count()]).max().unwrap_or(0)
+            })
+            .collect();
+
+        let format_line = |cells: Vec<&str>| -> String {
+            let padded: Vec<String> = cells.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).collect();
+            padded.join(" | ").trim_end().to_string()
+        };
+        let mut lines = vec![format_line(self.columns.iter().map(|c| c.as_str()).collect())];
+        for row in &shown {
+            lines.push(format_line(row.iter().map(|cell| cell.as_str()).collect()));
+        }
+        if self.rows.len() > max_rows {
+            lines.push(format!("... {} more row(s)", self.rows.len() - max_rows));
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for TableValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.name.map(|n| n.to_string()).unwrap_or_else(|| "table".to_string());
+        write!(f, "{} ({} rows)", name, self.rows.len())
+    }
+}
+
+/// What a page displayed, in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    Title(String),
+    Subtitle(String),
+    Text(String),
+    Section(String),
+    Table(TableValue),
+    /// A `log` message, with its level
+    Log(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    fn new(message: impl Into<String>) -> Self {
+        RuntimeError { message: message.into(), span: None }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} (line {})", self.message, span.line),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+type RunResult<T> = Result<T, RuntimeError>;
+
+/// A running page, test or function call
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    /// Line of the statement being run
+    pub line: usize,
+    scopes: Vec<Vec<(Atom, RuntimeValue)>>,
+}
+
+impl Frame {
+    fn new(name: String) -> Self {
+        Frame { name, line: 0, scopes: vec![Vec::new()] }
+    }
+
+    /// Variables visible in the frame, in declaration order; inner
+    /// declarations hide outer ones with the same name
+    pub fn variables(&self) -> Vec<(Atom, &RuntimeValue)> {
+        let mut seen = HashSet::new();
+        let mut variables = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            for (name, value) in scope.iter().rev() {
+                if seen.insert(*name) {
+                    variables.push((*name, value));
+                }
+            }
+        }
+        variables.reverse();
+        variables
+    }
+
+    fn lookup(&self, name: &str) -> Option<&RuntimeValue> {
+        self.scopes.iter().rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Observes execution, e.g. to stop at breakpoints
+pub trait DebugHook {
+    /// Called before each statement that has a source span
+    ///
+    /// The interpreter can be inspected, and expressions evaluated, while
+    /// the hook runs. Returning an error stops the program.
+    fn before_statement(&mut self, interpreter: &mut Interpreter, span: Span) -> RunResult<()>;
+}
+
+/// Source span of a statement, for statements that record one
+pub fn statement_span(stmt: &Statement) -> Option<Span> {
+    match stmt {
+        Statement::Let { span, .. }
+        | Statement::Assign { span, .. }
+        | Statement::Button { span, .. }
+        | Statement::Section { span, .. }
+        | Statement::Confirm { span, .. }
+        | Statement::Forall { span, .. }
+        | Statement::Match { span, .. }
+        | Statement::Assert { span, .. }
+        | Statement::Mock { span, .. }
+        | Statement::Refresh { span, .. }
+        | Statement::Python { span, .. }
+        | Statement::If { then_span: span, .. } => Some(*span),
+        Statement::FunctionCall(call) | Statement::Return(Expr::FunctionCall(call)) => Some(call.span),
+        _ => None,
+    }
+}
+
+enum Flow {
+    Normal,
+    Return(RuntimeValue),
+}
+
+pub struct Interpreter<'p> {
+    program: &'p Program,
+    tables: HashMap<Atom, &'p TableDef>,
+    functions: HashMap<Atom, &'p FunctionDef>,
+    externals: HashSet<Atom>,
+    /// Conditions of the `restrict` rules of each table
+    restrictions: HashMap<Atom, Vec<&'p Expr>>,
+    /// Values of the derived tables
+    derived: HashMap<Atom, &'p Expr>,
+    /// Values of the constants
+    constants: HashMap<Atom, Value>,
+    /// Initial values of the shared variables
+    shared: HashMap<Atom, &'p Expr>,
+    /// Values of the shared variables used so far, kept across pages until
+    /// a test starts afresh
+    shared_values: HashMap<Atom, RuntimeValue>,
+    /// `on_load`, `on_edit` and `on_save` hooks, by event and table
+    hooks: HashMap<(HookEvent, Atom), &'p Hook>,
+    frames: Vec<Frame>,
+    /// Rows of the group whose `having` condition is being evaluated
+    group: Option<TableValue>,
+    output: Vec<Output>,
+    /// Values that replace tables and functions, set by `mock`
+    mocks: HashMap<Atom, RuntimeValue>,
+    /// Directory CSV paths are relative to
+    base_dir: PathBuf,
+    hook: Option<Box<dyn DebugHook + 'p>>,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let mut tables = HashMap::new();
+        let mut functions = HashMap::new();
+        let mut externals = HashSet::new();
+        let mut restrictions: HashMap<Atom, Vec<&Expr>> = HashMap::new();
+        let mut derived = HashMap::new();
+        let mut constants = HashMap::new();
+        let mut shared = HashMap::new();
+        let mut hooks = HashMap::new();
+        for item in &program.items {
+            match item {
+                ProgramItem::TableDef(table) => { tables.insert(table.name, table); }
+                ProgramItem::FunctionDef(func) => { functions.insert(func.name, func); }
+                ProgramItem::ExternalFunction(ext) => { externals.insert(ext.name); }
+                ProgramItem::Restriction(restriction) => {
+                    restrictions.entry(restriction.table).or_default().push(&restriction.condition);
+                }
+                ProgramItem::DerivedTable(table) => { derived.insert(table.name, &table.value); }
+                ProgramItem::SharedVariable(variable) => { shared.insert(variable.name, &variable.value); }
+                // The analysis reports constants it cannot compute
+                ProgramItem::Constant(constant) => {
+                    if let Ok(value) = eval_const_with(&constant.value, &constants) {
+                        constants.insert(constant.name, value.with_type(constant.type_annotation.as_ref()));
+                    }
+                }
+                ProgramItem::Hook(hook) => { hooks.insert((hook.event, hook.table), hook); }
+                _ => {}
+            }
+        }
+
+        Interpreter {
+            program,
+            tables,
+            functions,
+            externals,
+            restrictions,
+            derived,
+            constants,
+            shared,
+            shared_values: HashMap::new(),
+            hooks,
+            frames: Vec::new(),
+            group: None,
+            output: Vec::new(),
+            mocks: HashMap::new(),
+            base_dir: PathBuf::from("."),
+            hook: None,
+        }
+    }
+
+    /// Resolve CSV paths relative to `dir`
+    pub fn with_base_dir(mut self, dir: PathBuf) -> Self {
+        self.base_dir = dir;
+        self
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn DebugHook + 'p>) {
+        self.hook = Some(hook);
+    }
+
+    pub fn output(&self) -> &[Output] {
+        &self.output
+    }
+
+    /// Frames of the running program, outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn run_page(&mut self, name: &str) -> RunResult<()> {
+        let page = self.program.items.iter()
+            .find_map(|item| match item {
+                ProgramItem::Page(page) if page.name == name => Some(page),
+                _ => None,
+            })
+            .ok_or_else(|| RuntimeError::new(format!("Page '{}' is not defined", name)))?;
+        self.run_body(format!("page {}", page.name), &page.statements)
+    }
+
+    /// Compute a derived table as CSV text, for `materialize on_build`
+    pub fn derived_table_csv(&mut self, name: &str) -> RunResult<String> {
+        let name = Atom::get(name)
+            .filter(|name| self.derived.contains_key(name))
+            .ok_or_else(|| RuntimeError::new(format!("Derived table '{}' is not defined", name)))?;
+        let value = self.lookup(&name)?;
+        Ok(table_to_csv(&table_or_rows(&value)?))
+    }
+
+    pub fn run_test(&mut self, name: &str) -> RunResult<()> {
+        let test = self.program.items.iter()
+            .find_map(|item| match item {
+                ProgramItem::Test(test) if test.name == name => Some(test),
+                _ => None,
+            })
+            .ok_or_else(|| RuntimeError::new(format!("Test '{}' is not defined", name)))?;
+        self.mocks.clear();
+        self.shared_values.clear();
+        let result = self.run_body(format!("test \"{}\"", test.name), &test.body);
+        self.mocks.clear();
+        result
+    }
+
+    fn run_body(&mut self, name: String, statements: &[Statement]) -> RunResult<()> {
+        self.frames.push(Frame::new(name));
+        let result = self.exec_statements(statements);
+        self.frames.pop();
+        result.map(|_| ())
+    }
+
+    /// Evaluate an expression in a frame of the running program
+    ///
+    /// `frame` indexes `frames()`; frames above it are hidden while the
+    /// expression runs.
+    pub fn evaluate(&mut self, source: &str, frame: usize) -> RunResult<RuntimeValue> {
+        let tokens = Lexer::new(source).tokenize()
+            .map_err(|diag| RuntimeError::new(diag.format_all()))?;
+        let expr = Parser::new(tokens).parse_standalone_expression()
+            .map_err(|diag| RuntimeError::new(diag.format_all()))?;
+
+        let hidden = self.frames.split_off((frame + 1).min(self.frames.len()));
+        let result = self.eval(&expr);
+        self.frames.extend(hidden);
+        result
+    }
+
+    // Statements
+
+    fn exec_block(&mut self, statements: &[Statement]) -> RunResult<Flow> {
+        self.frame_mut().scopes.push(Vec::new());
+        let result = self.exec_statements(statements);
+        self.frame_mut().scopes.pop();
+        result
+    }
+
+    fn exec_statements(&mut self, statements: &[Statement]) -> RunResult<Flow> {
+        for stmt in statements {
+            if let Flow::Return(value) = self.exec_statement(stmt)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_statement(&mut self, stmt: &Statement) -> RunResult<Flow> {
+        let span = statement_span(stmt);
+        if let Some(span) = span {
+            self.frame_mut().line = span.line;
+            if let Some(mut hook) = self.hook.take() {
+                let result = hook.before_statement(self, span);
+                self.hook = Some(hook);
+                result?;
+            }
+        }
+
+        self.exec_statement_kind(stmt).map_err(|mut error| {
+            if error.span.is_none() {
+                error.span = span;
+            }
+            error
+        })
+    }
+
+    fn exec_statement_kind(&mut self, stmt: &Statement) -> RunResult<Flow> {
+        // Translated text shows its key: the interpreter loads no locale files
+        match stmt {
+            Statement::Title(text) => {
+                let text = self.interpolate(text)?;
+                self.output.push(Output::Title(text));
+            }
+            Statement::Subtitle(text) => {
+                let text = self.interpolate(text)?;
+                self.output.push(Output::Subtitle(text));
+            }
+            Statement::Text(text) => {
+                let text = self.interpolate(text)?;
+                self.output.push(Output::Text(text));
+            }
+            // Buttons are never clicked (or confirmed) when running without a UI
+            Statement::Button { .. } | Statement::Confirm { .. } => {}
+            Statement::Section { title, body, .. } => {
+                let title = self.interpolate(title)?;
+                self.output.push(Output::Section(title));
+                return self.exec_block(body);
+            }
+            Statement::Let { name, value, .. } => {
+                let value = match value {
+                    Some(expr) => self.eval(expr)?,
+                    None => RuntimeValue::Null,
+                };
+                self.frame_mut().scopes.last_mut().expect("frame has a scope").push((*name, value));
+            }
+            Statement::Assign { name, value, .. } => {
+                let value = self.eval(value)?;
+                let slot = self.frame_mut().scopes.iter_mut().rev()
+                    .flat_map(|scope| scope.iter_mut().rev())
+                    .find(|(n, _)| n == name);
+                if let Some(slot) = slot {
+                    slot.1 = value;
+                } else if self.shared.contains_key(name) {
+                    self.shared_values.insert(*name, value);
+                } else {
+                    return Err(RuntimeError::new(format!("Undefined variable: '{}'", name)));
+                }
+            }
+            Statement::If { condition, then_branch, else_branch, .. } => {
+                if self.eval_condition(condition)? {
+                    return self.exec_block(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.exec_block(else_branch);
+                }
+            }
+            Statement::Forall { var, iterable, body, .. } => {
+                let items = match self.eval(iterable)? {
+                    RuntimeValue::Table(table) => (0..table.rows.len()).filter_map(|i| table.row(i)).collect(),
+                    RuntimeValue::List(items) => items,
+                    other => return Err(RuntimeError::new(format!("Cannot loop over {}", other))),
+                };
+                for item in items {
+                    self.frame_mut().scopes.push(vec![(*var, item)]);
+                    let flow = self.exec_statements(body);
+                    self.frame_mut().scopes.pop();
+                    if let Flow::Return(value) = flow? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Statement::Match { value, arms, .. } => {
+                let value = self.eval(value)?;
+                if let Some(arm) = self.matching_arm(&value, arms)? {
+                    return self.exec_block(&arm.body);
+                }
+            }
+            Statement::Return(expr) => return Ok(Flow::Return(self.eval(expr)?)),
+            Statement::FunctionCall(call) => {
+                self.call(call)?;
+            }
+            Statement::Assert { condition, .. } => {
+                if !self.eval_condition(condition)? {
+                    return Err(RuntimeError::new("Assertion failed"));
+                }
+            }
+            Statement::Mock { target, value, .. } => {
+                let value = self.eval(value)?;
+                self.mocks.insert(*target, value);
+            }
+            // Pages run once in the interpreter
+            Statement::Refresh { .. } => {}
+            Statement::Python { .. } => {
+                return Err(RuntimeError::new("python blocks only run in generated code"));
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        if self.frames.is_empty() {
+            self.frames.push(Frame::new("<expression>".to_string()));
+        }
+        self.frames.last_mut().expect("frame was pushed")
+    }
+
+    /// Page text with the values of its `{expr}` placeholders
+    fn interpolate(&mut self, text: &PageText) -> RunResult<String> {
+        let parts = match text {
+            PageText::Literal(text) | PageText::Translated(text) => return Ok(text.clone()),
+            PageText::Interpolated(parts) => parts,
+        };
+        let mut result = String::new();
+        for part in parts {
+            match part {
+                TextPart::Text(text) => result.push_str(text),
+                TextPart::Expr { expr, .. } => result.push_str(&self.eval(expr)?.to_display_string()),
+            }
+        }
+        Ok(result)
+    }
+
+    fn eval_condition(&mut self, expr: &Expr) -> RunResult<bool> {
+        match self.eval(expr)? {
+            RuntimeValue::Scalar(Value::Bool(b)) => Ok(b),
+            RuntimeValue::Null => Ok(false),
+            other => Err(RuntimeError::new(format!("Condition must be a bool, found {}", other))),
+        }
+    }
+
+    // Expressions
+
+    fn eval(&mut self, expr: &Expr) -> RunResult<RuntimeValue> {
+        match expr {
+            Expr::IntLiteral(n) => Ok(Value::Int(*n).into()),
+            Expr::FloatLiteral(x) => Ok(Value::Float(*x).into()),
+            Expr::StringLiteral(s) => Ok(Value::String(s.clone()).into()),
+            Expr::BoolLiteral(b) => Ok(Value::Bool(*b).into()),
+            // Dates are ISO text here, like the date cells of a CSV, so
+            // they compare in date order
+            Expr::DateLiteral(date) => Ok(Value::String(date.clone()).into()),
+            // Amounts are floats here, like the currency cells of a CSV
+            Expr::CurrencyLiteral { amount, .. } => amount.parse()
+                .map(|amount| Value::Float(amount).into())
+                .map_err(|_| RuntimeError::new(format!("Invalid amount '{}'", amount))),
+            Expr::Identifier(name) => self.lookup(name),
+            Expr::FunctionCall(call) => self.call(call),
+            Expr::BinaryOp { op: BinaryOp::And, left, right, .. } => {
+                Ok(Value::Bool(self.eval_condition(left)? && self.eval_condition(right)?).into())
+            }
+            Expr::BinaryOp { op: BinaryOp::Or, left, right, .. } => {
+                Ok(Value::Bool(self.eval_condition(left)? || self.eval_condition(right)?).into())
+            }
+            Expr::BinaryOp { op, left, right, .. } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(op, left, right)
+            }
+            Expr::UnaryOp { op: op @ (UnaryOp::IsNull | UnaryOp::IsNotNull), operand, .. } => {
+                let is_null = self.eval(operand)? == RuntimeValue::Null;
+                Ok(Value::Bool(is_null == (*op == UnaryOp::IsNull)).into())
+            }
+            Expr::UnaryOp { op, operand, .. } => match self.eval(operand)? {
+                RuntimeValue::Scalar(value) => eval_unary(op, value).map(Into::into).map_err(eval_error),
+                RuntimeValue::Null => Ok(RuntimeValue::Null),
+                other => Err(RuntimeError::new(format!("Cannot apply operator to {}", other))),
+            },
+            Expr::Lambda { .. } => Err(RuntimeError::new("Lambdas are not supported by the interpreter")),
+            Expr::FieldAccess { object, field, .. } => match self.eval(object)? {
+                RuntimeValue::Row(fields) => fields.into_iter()
+                    .find(|(name, _)| name == field)
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| RuntimeError::new(format!("Row has no field '{}'", field))),
+                RuntimeValue::Table(table) => {
+                    let index = table.column_index(field)
+                        .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", field)))?;
+                    Ok(RuntimeValue::List(table.rows.into_iter().map(|mut row| row.swap_remove(index)).collect()))
+                }
+                other => Err(RuntimeError::new(format!("Cannot access field '{}' on {}", field, other))),
+            },
+            Expr::Index { object, index, .. } => {
+                let object = self.eval(object)?;
+                let index = match self.eval(index)? {
+                    RuntimeValue::Scalar(Value::Int(i)) if i >= 0 => i as usize,
+                    other => return Err(RuntimeError::new(format!("Index must be a non-negative int, found {}", other))),
+                };
+                let item = match &object {
+                    RuntimeValue::List(items) => items.get(index).cloned(),
+                    RuntimeValue::Table(table) => table.row(index),
+                    other => return Err(RuntimeError::new(format!("Cannot index {}", other))),
+                };
+                item.ok_or_else(|| RuntimeError::new(format!("Index {} is out of range", index)))
+            }
+            Expr::Chain { left, right, .. } => {
+                let Expr::FunctionCall(call) = right.as_ref() else {
+                    return Err(RuntimeError::new("The right side of '->' must be a function call"));
+                };
+                // `_` marks where the left value goes; otherwise it is the first argument
+                let mut args = call.args.clone();
+                match args.iter_mut().find(|arg| matches!(arg, Expr::Identifier(name) if name == "_")) {
+                    Some(placeholder) => *placeholder = left.as_ref().clone(),
+                    None => args.insert(0, left.as_ref().clone()),
+                }
+                self.call(&FunctionCall { name: call.name, args, id: call.id, span: call.span })
+            }
+            Expr::TableLiteral(fields) => {
+                let mut row = Vec::new();
+                for (name, value) in fields {
+                    row.push((*name, self.eval(value)?));
+                }
+                Ok(RuntimeValue::Row(row))
+            }
+            Expr::ArrayLiteral(items) => {
+                let items: RunResult<Vec<_>> = items.iter().map(|item| self.eval(item)).collect();
+                Ok(RuntimeValue::List(items?))
+            }
+            Expr::Match { value, arms, .. } => {
+                let value = self.eval(value)?;
+                match self.matching_arm(&value, arms)? {
+                    Some(arm) => self.eval(&arm.body),
+                    None => Err(RuntimeError::new(format!("No arm of the match is taken for {}", value))),
+                }
+            }
+            // Filters only affect widgets, which the interpreter does not show
+            Expr::FilterLiteral(_) => Ok(RuntimeValue::Null),
+            Expr::Between { value, low, high, .. } => {
+                let value = self.eval(value)?;
+                let above = binary(&BinaryOp::GreaterThanEqual, value.clone(), self.eval(low)?)?;
+                let below = binary(&BinaryOp::LessThanEqual, value, self.eval(high)?)?;
+                binary(&BinaryOp::And, above, below)
+            }
+            Expr::Where { table, condition, .. } => {
+                let table = self.eval_table(table)?;
+                Ok(RuntimeValue::Table(self.filter_rows(table, condition)?))
+            }
+            Expr::GroupBy { table, columns, having, .. } => {
+                let table = self.eval_table(table)?;
+                Ok(RuntimeValue::Table(self.filter_groups(table, columns, having)?))
+            }
+            Expr::SortBy { table, columns, .. } => {
+                let table = self.eval_table(table)?;
+                Ok(RuntimeValue::Table(self.sort_rows(table, columns)?))
+            }
+            Expr::ColumnSelect { table, columns, .. } => {
+                let table = self.eval_table(table)?;
+                let columns = SelectedColumn::expand(columns, &table.columns)
+                    .map_err(|column| RuntimeError::new(format!("Table has no column '{}'", column)))?;
+                let indices: Vec<usize> = columns.iter().filter_map(|column| table.column_index(column)).collect();
+                let mut selected = TableValue::new(None, columns);
+                selected.rows = table.rows.iter()
+                    .map(|row| indices.iter().map(|&i| row[i].clone()).collect())
+                    .collect();
+                Ok(RuntimeValue::Table(selected))
+            }
+            Expr::Rename { table, columns, .. } => {
+                let mut table = self.eval_table(table)?;
+                for (from, to) in columns {
+                    let index = table.column_index(from)
+                        .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", from)))?;
+                    table.columns[index] = *to;
+                }
+                table.name = None;
+                Ok(RuntimeValue::Table(table))
+            }
+            Expr::DropColumns { table, columns, .. } => {
+                let mut table = self.eval_table(table)?;
+                for column in columns {
+                    let index = table.column_index(column)
+                        .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", column)))?;
+                    table.columns.remove(index);
+                    for row in &mut table.rows {
+                        row.remove(index);
+                    }
+                }
+                table.name = None;
+                Ok(RuntimeValue::Table(table))
+            }
+        }
+    }
+
+    /// The first arm with a pattern equal to the value, or `_`
+    fn matching_arm<'a, T>(&mut self, value: &RuntimeValue, arms: &'a [MatchArm<T>]) -> RunResult<Option<&'a MatchArm<T>>> {
+        for arm in arms {
+            for pattern in &arm.patterns {
+                let matched = match pattern {
+                    MatchPattern::Wildcard => true,
+                    MatchPattern::Literal(literal) => {
+                        let literal = self.eval(literal)?;
+                        binary(&BinaryOp::Equal, value.clone(), literal)? == RuntimeValue::Scalar(Value::Bool(true))
+                    }
+                };
+                if matched {
+                    return Ok(Some(arm));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The rows of a table for which a condition on their fields holds
+    fn filter_rows(&mut self, mut table: TableValue, condition: &Expr) -> RunResult<TableValue> {
+        let mut kept = Vec::new();
+        for index in 0..table.rows.len() {
+            let Some(RuntimeValue::Row(fields)) = table.row(index) else { continue };
+            self.frame_mut().scopes.push(fields);
+            let keep = self.eval_condition(condition);
+            self.frame_mut().scopes.pop();
+            if keep? {
+                kept.push(index);
+            }
+        }
+        let rows = std::mem::take(&mut table.rows);
+        table.rows = rows.into_iter().enumerate()
+            .filter(|(index, _)| kept.contains(index))
+            .map(|(_, row)| row)
+            .collect();
+        Ok(table)
+    }
+
+    /// The rows of a table ordered by keys computed from their fields
+    fn sort_rows(&mut self, mut table: TableValue, columns: &[SortColumn]) -> RunResult<TableValue> {
+        for column in columns.iter().filter_map(SortColumn::column) {
+            if table.column_index(&column).is_none() {
+                return Err(RuntimeError::new(format!("Table has no column '{}'", column)));
+            }
+        }
+        let mut keys = Vec::new();
+        for index in 0..table.rows.len() {
+            let Some(RuntimeValue::Row(fields)) = table.row(index) else { continue };
+            self.frame_mut().scopes.push(fields);
+            let row_keys: RunResult<Vec<RuntimeValue>> = columns.iter().map(|column| self.eval(&column.key)).collect();
+            self.frame_mut().scopes.pop();
+            keys.push(row_keys?);
+        }
+        let mut rows: Vec<(Vec<RuntimeValue>, Vec<RuntimeValue>)> = keys.into_iter()
+            .zip(std::mem::take(&mut table.rows))
+            .collect();
+        rows.sort_by(|(a, _), (b, _)| {
+            columns.iter().enumerate()
+                .map(|(i, column)| {
+                    let ordering = compare(&a[i], &b[i]);
+                    if column.ascending { ordering } else { ordering.reverse() }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        table.rows = rows.into_iter().map(|(_, row)| row).collect();
+        Ok(table)
+    }
+
+    /// Keep the rows of the groups, by the values of `columns`, that satisfy
+    /// `having`, in their original order
+    fn filter_groups(&mut self, mut table: TableValue, columns: &[Atom], having: &Expr) -> RunResult<TableValue> {
+        let mut indices = Vec::new();
+        for column in columns {
+            indices.push(table.column_index(column)
+                .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", column)))?);
+        }
+        let mut groups: Vec<(Vec<&RuntimeValue>, Vec<usize>)> = Vec::new();
+        for (index, row) in table.rows.iter().enumerate() {
+            let key: Vec<&RuntimeValue> = indices.iter().map(|&i| &row[i]).collect();
+            match groups.iter_mut().find(|(group_key, _)| *group_key == key) {
+                Some((_, rows)) => rows.push(index),
+                None => groups.push((key, vec![index])),
+            }
+        }
+        let groups: Vec<Vec<usize>> = groups.into_iter().map(|(_, rows)| rows).collect();
+        
+        let mut kept = Vec::new();
+        for rows in groups {
+            let mut group = TableValue::new(table.name, table.columns.clone());
+            group.rows = rows.iter().map(|&i| table.rows[i].clone()).collect();
+            self.group = Some(group);
+            let keep = self.eval_condition(having);
+            self.group = None;
+            if keep? {
+                kept.extend(rows);
+            }
+        }
+        let rows = std::mem::take(&mut table.rows);
+        table.rows = rows.into_iter().enumerate()
+            .filter(|(index, _)| kept.contains(index))
+            .map(|(_, row)| row)
+            .collect();
+        Ok(table)
+    }
+
+    fn eval_table(&mut self, expr: &Expr) -> RunResult<TableValue> {
+        match self.eval(expr)? {
+            RuntimeValue::Table(table) => Ok(table),
+            other => Err(RuntimeError::new(format!("Expected a table, found {}", other))),
+        }
+    }
+
+    fn lookup(&mut self, name: &Atom) -> RunResult<RuntimeValue> {
+        if let Some(value) = self.frames.last().and_then(|frame| frame.lookup(name)) {
+            return Ok(value.clone());
+        }
+        if let Some(value) = self.shared_value(name)? {
+            return Ok(value);
+        }
+        if let Some(value) = self.constants.get(name) {
+            return Ok(RuntimeValue::Scalar(value.clone()));
+        }
+        // A derived table is computed from its value where it is used,
+        // unless a test mocks it
+        if let Some(value) = self.derived.get(name).copied() {
+            if let Some(mock) = self.mocks.get(name) {
+                return table_or_rows(mock).map(RuntimeValue::Table);
+            }
+            self.frames.push(Frame::new(format!("table {}", name)));
+            let result = self.eval(value);
+            self.frames.pop();
+            return result;
+        }
+        // A table name stands for an empty table with its columns, which is
+        // how `load_csv(path, Table)` learns the schema
+        if let Some(table) = self.tables.get(name) {
+            let columns = table.fields.iter().map(|field| field.name).collect();
+            return Ok(RuntimeValue::Table(TableValue::new(Some(*name), columns)));
+        }
+        Err(RuntimeError::new(format!("Undefined variable: '{}'", name)))
+    }
+
+    /// Current value of a shared variable, starting at its initial value
+    fn shared_value(&mut self, name: &Atom) -> RunResult<Option<RuntimeValue>> {
+        if let Some(value) = self.shared_values.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        let Some(initial) = self.shared.get(name).copied() else {
+            return Ok(None);
+        };
+        self.frames.push(Frame::new(format!("shared {}", name)));
+        let value = self.eval(initial);
+        self.frames.pop();
+        let value = value?;
+        self.shared_values.insert(*name, value.clone());
+        Ok(Some(value))
+    }
+
+    fn call(&mut self, call: &FunctionCall) -> RunResult<RuntimeValue> {
+        if let (true, Some(group)) = (call.is_group_aggregate(), &self.group) {
+            return group_aggregate(call, group);
+        }
+        let mut args = Vec::new();
+        for arg in &call.args {
+            args.push(self.eval(arg)?);
+        }
+
+        let result = if let Some(mock) = self.mocks.get(&call.name) {
+            Ok(mock.clone())
+        } else if let Some(func) = self.functions.get(&call.name).copied() {
+            self.call_function(func, args)
+        } else if self.externals.contains(&call.name) {
+            Err(RuntimeError::new(format!(
+                "External function '{}' cannot run in the interpreter; mock it in tests", call.name
+            )))
+        } else {
+            self.call_builtin(call.name.as_str(), args)
+        };
+
+        result.map_err(|mut error| {
+            if error.span.is_none() {
+                error.span = Some(call.span);
+            }
+            error
+        })
+    }
+
+    fn call_function(&mut self, func: &FunctionDef, args: Vec<RuntimeValue>) -> RunResult<RuntimeValue> {
+        if args.len() != func.params.len() {
+            return Err(RuntimeError::new(format!(
+                "Function '{}' takes {} argument(s), {} given", func.name, func.params.len(), args.len()
+            )));
+        }
+
+        let mut frame = Frame::new(func.name.to_string());
+        frame.scopes[0] = func.params.iter().map(|p| p.name).zip(args).collect();
+        self.frames.push(frame);
+        let flow = self.exec_statements(&func.body);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal => Ok(RuntimeValue::Null),
+        }
+    }
+
+    /// Run the hook of a table for an event, with the table's rows in the
+    /// event's variable, returning what the hook returns
+    fn run_hook(&mut self, event: HookEvent, table: &TableValue) -> RunResult<RuntimeValue> {
+        let Some(hook) = table.name.and_then(|name| self.hooks.get(&(event, name)).copied()) else {
+            return Ok(RuntimeValue::Null);
+        };
+
+        let mut frame = Frame::new(format!("{} {}", event.keyword(), hook.table));
+        frame.scopes[0].push((Atom::new(event.variable()), RuntimeValue::Table(table.clone())));
+        self.frames.push(frame);
+        let flow = self.exec_statements(&hook.body);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal => Ok(RuntimeValue::Null),
+        }
+    }
+
+    /// Whether the `on_save` hook of a table lets it be saved: it cancels the
+    /// save by returning false
+    fn save_allowed(&mut self, table: &TableValue) -> RunResult<bool> {
+        let result = self.run_hook(HookEvent::Save, table)?;
+        Ok(!matches!(result, RuntimeValue::Scalar(Value::Bool(false))))
+    }
+
+    fn call_builtin(&mut self, name: &str, args: Vec<RuntimeValue>) -> RunResult<RuntimeValue> {
+        let arg = |index: usize| -> RunResult<&RuntimeValue> {
+            args.get(index).ok_or_else(|| RuntimeError::new(format!("{} is missing argument {}", name, index + 1)))
+        };
+        let table_arg = |index: usize| -> RunResult<&TableValue> {
+            let value = arg(index)?;
+            value.as_table().ok_or_else(|| RuntimeError::new(format!("{} expects a table, found {}", name, value)))
+        };
+        let number_arg = |index: usize| -> RunResult<f64> {
+            match arg(index)? {
+                RuntimeValue::Scalar(Value::Int(n)) => Ok(*n as f64),
+                RuntimeValue::Scalar(Value::Float(f)) => Ok(*f),
+                other => Err(RuntimeError::new(format!("{} expects a number, found {}", name, other))),
+            }
+        };
+        let string_arg = |index: usize| -> RunResult<String> {
+            match arg(index)? {
+                RuntimeValue::Scalar(Value::String(s)) => Ok(s.clone()),
+                other => Err(RuntimeError::new(format!("{} expects a string, found {}", name, other))),
+            }
+        };
+
+        match name {
+            "load_csv" => {
+                let schema = args.get(1).and_then(|value| value.as_table()).and_then(|t| t.name);
+                if let Some(mock) = schema.and_then(|table| self.mocks.get(&table)) {
+                    return Ok(mock.clone());
+                }
+                let path = self.base_dir.join(string_arg(0)?);
+                let text = fs::read_to_string(&path)
+                    .map_err(|e| RuntimeError::new(format!("Failed to read {}: {}", path.display(), e)))?;
+                let schema = schema.and_then(|table| self.tables.get(&table).copied());
+                let match_headers = args.len() > 2 && string_arg(2)? == "normalize";
+                let mut table = parse_csv_table(&text, schema, match_headers);
+                // Only the rows the user may see, by the table's restrictions
+                let conditions = schema.and_then(|table| self.restrictions.get(&table.name)).cloned().unwrap_or_default();
+                for condition in conditions {
+                    table = self.filter_rows(table, condition)?;
+                }
+                self.run_hook(HookEvent::Load, &table)?;
+                Ok(RuntimeValue::Table(table))
+            }
+            // Databases are not reached from the interpreter; a mocked
+            // table is loaded, and saves are logged
+            "load_sql" => {
+                let schema = args.get(2).and_then(|value| value.as_table()).and_then(|t| t.name);
+                match schema.and_then(|table| self.mocks.get(&table)) {
+                    Some(mock) => Ok(mock.clone()),
+                    None => Err(RuntimeError::new(format!(
+                        "load_sql cannot read {} in the interpreter; mock the table instead", string_arg(1)?
+                    ))),
+                }
+            }
+            "save_sql" => {
+                if !self.save_allowed(table_arg(0)?)? {
+                    return Ok(RuntimeValue::Null);
+                }
+                let mode = if args.len() > 3 { string_arg(3)? } else { "append".to_string() };
+                let message = format!("save_sql to {} ({}, not written)", string_arg(2)?, mode);
+                self.output.push(Output::Log("info".to_string(), message));
+                Ok(RuntimeValue::Null)
+            }
+            "save_csv" => {
+                if !self.save_allowed(table_arg(0)?)? {
+                    return Ok(RuntimeValue::Null);
+                }
+                let path = self.base_dir.join(string_arg(1)?);
+                fs::write(&path, table_to_csv(table_arg(0)?))
+                    .map_err(|e| RuntimeError::new(format!("Failed to write {}: {}", path.display(), e)))?;
+                Ok(RuntimeValue::Null)
+            }
+            // Only generated apps record and discard edits
+            "audit" | "revert" => Ok(RuntimeValue::Null),
+            // No one signs in to the interpreter, so restricted tables only
+            // load once a test mocks the user
+            "current_user" => Err(RuntimeError::new("current_user has no signed-in user in the interpreter; mock it in tests")),
+            // Tables are not edited in the interpreter, so `on_edit` hooks
+            // only run in generated apps
+            "show" | "show_editable" => {
+                let table = table_arg(0)?.clone();
+                self.output.push(Output::Table(table.clone()));
+                Ok(RuntimeValue::Table(table))
+            }
+            "display" => {
+                let output = match arg(0)? {
+                    RuntimeValue::Table(table) => Output::Table(table.clone()),
+                    value => Output::Text(value.to_display_string()),
+                };
+                self.output.push(output);
+                Ok(RuntimeValue::Null)
+            }
+            "log" => {
+                let message = arg(0)?.to_display_string();
+                let level = if args.len() > 1 { string_arg(1)? } else { "info".to_string() };
+                if !LOG_LEVELS.contains(&level.as_str()) {
+                    return Err(RuntimeError::new(format!("Unknown log level '{}'", level)));
+                }
+                self.output.push(Output::Log(level, message));
+                Ok(RuntimeValue::Null)
+            }
+            // Without locale files a key shows as itself
+            "t" => Ok(Value::String(string_arg(0)?).into()),
+            // The US defaults; wt.toml formats only apply to generated apps
+            "format_number" | "format_currency" => {
+                let value = match arg(0)? {
+                    RuntimeValue::Scalar(Value::Int(n)) => *n as f64,
+                    RuntimeValue::Scalar(Value::Float(f)) => *f,
+                    other => return Err(RuntimeError::new(format!("{} expects a number, found {}", name, other))),
+                };
+                let (grouped, decimals) = match args.get(1) {
+                    None => (true, 2),
+                    Some(RuntimeValue::Scalar(Value::Int(n))) if *n >= 0 => (true, *n as usize),
+                    Some(RuntimeValue::Scalar(Value::String(pattern))) if name == "format_number" => number_pattern(pattern)
+                        .ok_or_else(|| RuntimeError::new(format!("Invalid number pattern \"{}\"", pattern)))?,
+                    Some(other) => return Err(RuntimeError::new(format!("{} expects a number of decimals, found {}", name, other))),
+                };
+                let number = format_number(value.abs(), decimals, grouped);
+                let sign = if value < 0.0 { "-" } else { "" };
+                let symbol = if name == "format_currency" { "$" } else { "" };
+                Ok(Value::String(format!("{}{}{}", sign, symbol, number)).into())
+            }
+            "format_date" => Ok(Value::String(string_arg(0)?).into()),
+            "percent" => {
+                let value = number_arg(0)?;
+                let decimals = match args.get(1) {
+                    None => 1,
+                    Some(RuntimeValue::Scalar(Value::Int(n))) if *n >= 0 => *n as usize,
+                    Some(other) => return Err(RuntimeError::new(format!("percent expects a number of decimals, found {}", other))),
+                };
+                let sign = if value < 0.0 { "-" } else { "" };
+                Ok(Value::String(format!("{}{}%", sign, format_number((value * 100.0).abs(), decimals, true))).into())
+            }
+            // Halves round to even, like the generated apps' Python
+            "round" => match (arg(0)?, args.get(1)) {
+                (RuntimeValue::Null, _) => Ok(RuntimeValue::Null),
+                (_, None) => Ok(Value::Int(number_arg(0)?.round_ties_even() as i64).into()),
+                (_, Some(RuntimeValue::Scalar(Value::Int(n)))) => {
+                    let scale = 10f64.powi(*n as i32);
+                    Ok(Value::Float((number_arg(0)? * scale).round_ties_even() / scale).into())
+                }
+                (_, Some(other)) => Err(RuntimeError::new(format!("round expects a number of decimals, found {}", other))),
+            },
+            "floor" | "ceil" => match arg(0)? {
+                RuntimeValue::Null => Ok(RuntimeValue::Null),
+                _ => {
+                    let value = number_arg(0)?;
+                    Ok(Value::Int(if name == "floor" { value.floor() } else { value.ceil() } as i64).into())
+                }
+            },
+            "abs" => match arg(0)? {
+                RuntimeValue::Scalar(Value::Int(n)) => Ok(Value::Int(n.abs()).into()),
+                RuntimeValue::Null => Ok(RuntimeValue::Null),
+                _ => Ok(Value::Float(number_arg(0)?.abs()).into()),
+            },
+            // Nothing leaves the interpreter; the call is logged instead
+            "send_email" => {
+                let message = format!("send_email to {}: {} (not sent)", arg(0)?.to_display_string(), arg(1)?.to_display_string());
+                self.output.push(Output::Log("info".to_string(), message));
+                Ok(Value::Bool(false).into())
+            }
+            "export_pdf" => {
+                let message = format!("export_pdf of section {} (not exported)", string_arg(0)?);
+                self.output.push(Output::Log("info".to_string(), message));
+                Ok(Value::Bool(false).into())
+            }
+            "count" => Ok(Value::Int(table_arg(0)?.rows.len() as i64).into()),
+            "first" | "last" | "single" => {
+                let table = table_arg(0)?;
+                let rows = table.rows.len();
+                if rows == 0 || (name == "single" && rows > 1) {
+                    return Err(RuntimeError::new(format!("{} expects {} row, found {}", name, if name == "single" { "exactly one" } else { "at least one" }, rows)));
+                }
+                Ok(table.row(if name == "last" { rows - 1 } else { 0 }).unwrap_or(RuntimeValue::Null))
+            }
+            "sum" | "average" | "min" | "max" => {
+                let table = table_arg(0)?;
+                let column = string_arg(1)?;
+                let index = table.column_index(&column)
+                    .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", column)))?;
+                // "strict" stops on missing values, which are otherwise skipped
+                if matches!(name, "sum" | "average") && args.len() > 2 && string_arg(2)? == "strict" {
+                    let missing = table.rows.iter().filter(|row| row[index] == RuntimeValue::Null).count();
+                    if missing > 0 {
+                        return Err(RuntimeError::new(format!("{} of '{}' found {} missing value(s)", name, column, missing)));
+                    }
+                }
+                aggregate(name, table.rows.iter().map(|row| &row[index]))
+            }
+            "share" => {
+                let mut table = table_arg(0)?.clone();
+                let column = string_arg(1)?;
+                let index = table.column_index(&column)
+                    .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", column)))?;
+                let group = match args.get(2) {
+                    Some(_) => Some(string_arg(2)?),
+                    None => None,
+                };
+                let group_index = group.map(|group| table.column_index(&group)
+                    .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", group))))
+                    .transpose()?;
+                let number = |value: &RuntimeValue| match value {
+                    RuntimeValue::Scalar(Value::Int(n)) => Some(*n as f64),
+                    RuntimeValue::Scalar(Value::Float(f)) => Some(*f),
+                    _ => None,
+                };
+                let key = |row: &[RuntimeValue]| group_index.map(|i| row[i].to_string()).unwrap_or_default();
+                let mut totals: HashMap<String, f64> = HashMap::new();
+                for row in &table.rows {
+                    *totals.entry(key(row)).or_default() += number(&row[index]).unwrap_or(0.0);
+                }
+                for row in &mut table.rows {
+                    let total = totals[&key(row)];
+                    let share = number(&row[index]).filter(|_| total != 0.0).map(|value| Value::Float(value / total).into());
+                    row.push(share.unwrap_or(RuntimeValue::Null));
+                }
+                table.columns.push(Atom::new(&format!("{}_share", column)));
+                Ok(RuntimeValue::Table(table))
+            }
+            "table_from" => match arg(0)? {
+                RuntimeValue::List(rows) => table_from_rows(rows),
+                other => Err(RuntimeError::new(format!("table_from expects a list of rows, found {}", other))),
+            },
+            "assert_row_count" => {
+                let table = table_arg(0)?;
+                match arg(1)? {
+                    RuntimeValue::Scalar(Value::Int(n)) if table.rows.len() as i64 == *n => Ok(RuntimeValue::Null),
+                    expected => Err(RuntimeError::new(format!(
+                        "expected {} row(s), found {}:\n{}", expected, table.rows.len(), table.preview(5)
+                    ))),
+                }
+            }
+            "assert_contains" => {
+                let table = table_arg(0)?;
+                let RuntimeValue::Row(wanted) = arg(1)? else {
+                    return Err(RuntimeError::new("assert_contains expects a row such as { id: 3 }"));
+                };
+                let found = (0..table.rows.len()).filter_map(|i| table.row(i)).any(|row| {
+                    let RuntimeValue::Row(fields) = row else { return false };
+                    wanted.iter().all(|w| fields.contains(w))
+                });
+                if found {
+                    Ok(RuntimeValue::Null)
+                } else {
+                    Err(RuntimeError::new(format!("no row matches {}:\n{}", arg(1)?, table.preview(5))))
+                }
+            }
+            "assert_equal_tables" => {
+                let ignore_order = matches!(args.get(2), Some(RuntimeValue::Scalar(Value::Bool(true))));
+                let actual = table_or_rows(arg(0)?)?;
+                let expected = table_or_rows(arg(1)?)?;
+                match table_differences(&actual, &expected, ignore_order) {
+                    differences if differences.is_empty() => Ok(RuntimeValue::Null),
+                    differences => Err(RuntimeError::new(format!("tables differ:\n{}", differences.join("\n")))),
+                }
+            }
+            _ => Err(RuntimeError::new(format!("Unknown function: '{}'", name))),
+        }
+    }
+}
+
+/// A non-negative number with `,` between thousands
+fn format_number(value: f64, decimals: usize, group_thousands: bool) -> String {
+    let text = format!("{:.*}", decimals, value);
+    let (whole, fraction) = text.split_once('.').map_or((text.as_str(), None), |(w, f)| (w, Some(f)));
+    let mut grouped = String::new();
+    for (index, digit) in whole.chars().enumerate() {
+        if group_thousands && index > 0 && (whole.len() - index) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    match fraction {
+        Some(fraction) => format!("{}.{}", grouped, fraction),
+        None => grouped,
+    }
+}
+
+fn eval_error(error: EvalError) -> RuntimeError {
+    RuntimeError::new(error.to_string())
+}
+
+fn binary(op: &BinaryOp, left: RuntimeValue, right: RuntimeValue) -> RunResult<RuntimeValue> {
+    match (left, right) {
+        (value, RuntimeValue::List(items)) if *op == BinaryOp::In => Ok(Value::Bool(items.contains(&value)).into()),
+        (RuntimeValue::Scalar(l), RuntimeValue::Scalar(r)) => eval_binary(op, l, r).map(Into::into).map_err(eval_error),
+        (RuntimeValue::Table(l), RuntimeValue::Table(r)) => {
+            let rows = match op {
+                BinaryOp::Add | BinaryOp::Union => {
+                    let mut rows = l.rows.clone();
+                    for row in r.rows {
+                        if !rows.contains(&row) {
+                            rows.push(row);
+                        }
+                    }
+                    rows
+                }
+                BinaryOp::Subtract | BinaryOp::Minus => l.rows.iter().filter(|row| !r.rows.contains(row)).cloned().collect(),
+                BinaryOp::Intersect => l.rows.iter().filter(|row| r.rows.contains(row)).cloned().collect(),
+                _ => return Err(RuntimeError::new("Tables only support +, - and &")),
+            };
+            Ok(RuntimeValue::Table(TableValue { rows, ..l }))
+        }
+        // Missing values only compare equal to each other and make
+        // arithmetic results missing
+        (l @ RuntimeValue::Null, r) | (l, r @ RuntimeValue::Null) => match op {
+            BinaryOp::Equal => Ok(Value::Bool(l == r).into()),
+            BinaryOp::NotEqual => Ok(Value::Bool(l != r).into()),
+            _ => Ok(RuntimeValue::Null),
+        },
+        (l, r) => match op {
+            BinaryOp::Equal => Ok(Value::Bool(l == r).into()),
+            BinaryOp::NotEqual => Ok(Value::Bool(l != r).into()),
+            _ => Err(RuntimeError::new(format!("Cannot apply operator to {} and {}", l, r))),
+        },
+    }
+}
+
+/// Order values for sorting; missing values sort last
+fn compare(a: &RuntimeValue, b: &RuntimeValue) -> Ordering {
+    use Value::*;
+    match (a, b) {
+        (RuntimeValue::Null, RuntimeValue::Null) => Ordering::Equal,
+        (RuntimeValue::Null, _) => Ordering::Greater,
+        (_, RuntimeValue::Null) => Ordering::Less,
+        (RuntimeValue::Scalar(a), RuntimeValue::Scalar(b)) => match (a, b) {
+            (Int(a), Int(b)) => a.cmp(b),
+            (Int(a), Float(b)) => (*a as f64).total_cmp(b),
+            (Float(a), Int(b)) => a.total_cmp(&(*b as f64)),
+            (Float(a), Float(b)) => a.total_cmp(b),
+            (String(a), String(b)) => a.cmp(b),
+            (Bool(a), Bool(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        },
+        _ => Ordering::Equal,
+    }
+}
+
+fn aggregate<'v>(name: &str, values: impl Iterator<Item = &'v RuntimeValue>) -> RunResult<RuntimeValue> {
+    let values: Vec<&RuntimeValue> = values.filter(|v| **v != RuntimeValue::Null).collect();
+    if name == "min" || name == "max" {
+        let best = values.into_iter().reduce(|a, b| {
+            let ordering = compare(a, b);
+            if (name == "min") == ordering.is_le() { a } else { b }
+        });
+        return Ok(best.cloned().unwrap_or(RuntimeValue::Null));
+    }
+
+    let mut total = RuntimeValue::Scalar(Value::Int(0));
+    for value in &values {
+        total = binary(&BinaryOp::Add, total, (*value).clone())?;
+    }
+    if name == "average" {
+        if values.is_empty() {
+            return Ok(RuntimeValue::Null);
+        }
+        return binary(&BinaryOp::Divide, total_as_float(total), Value::Float(values.len() as f64).into());
+    }
+    Ok(total)
+}
+
+/// `count()`, or `sum`, `average`, `min` or `max` of a column, over the
+/// rows of a group
+fn group_aggregate(call: &FunctionCall, group: &TableValue) -> RunResult<RuntimeValue> {
+    let [Expr::Identifier(column)] = call.args.as_slice() else {
+        return Ok(Value::Int(group.rows.len() as i64).into());
+    };
+    let index = group.column_index(column)
+        .ok_or_else(|| RuntimeError::new(format!("Table has no column '{}'", column)))?;
+    aggregate(call.name.as_str(), group.rows.iter().map(|row| &row[index]))
+}
+
+fn total_as_float(total: RuntimeValue) -> RuntimeValue {
+    match total {
+        RuntimeValue::Scalar(Value::Int(n)) => Value::Float(n as f64).into(),
+        other => other,
+    }
+}
+
+fn table_from_rows(rows: &[RuntimeValue]) -> RunResult<RuntimeValue> {
+    let mut table = TableValue::new(None, Vec::new());
+    for row in rows {
+        let RuntimeValue::Row(fields) = row else {
+            return Err(RuntimeError::new(format!("Expected a row, found {}", row)));
+        };
+        for (name, _) in fields {
+            if !table.columns.contains(name) {
+                table.columns.push(*name);
+            }
+        }
+    }
+    for row in rows {
+        let RuntimeValue::Row(fields) = row else { continue };
+        table.rows.push(table.columns.iter()
+            .map(|column| fields.iter().find(|(n, _)| n == column).map(|(_, v)| v.clone()).unwrap_or(RuntimeValue::Null))
+            .collect());
+    }
+    Ok(RuntimeValue::Table(table))
+}
+
+fn table_or_rows(value: &RuntimeValue) -> RunResult<TableValue> {
+    match value {
+        RuntimeValue::Table(table) => Ok(table.clone()),
+        RuntimeValue::List(rows) => match table_from_rows(rows)? {
+            RuntimeValue::Table(table) => Ok(table),
+            _ => unreachable!("table_from_rows returns a table"),
+        },
+        other => Err(RuntimeError::new(format!("Expected a table, found {}", other))),
+    }
+}
+
+/// Added, removed and changed rows between two tables, described the same
+/// way as by the generated pytest helpers
+fn table_differences(actual: &TableValue, expected: &TableValue, ignore_order: bool) -> Vec<String> {
+    let mut differences = Vec::new();
+    if actual.columns != expected.columns {
+        let names = |t: &TableValue| t.columns.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ");
+        differences.push(format!("columns differ: [{}] vs expected [{}]", names(actual), names(expected)));
+        return differences;
+    }
+
+    let rows = |t: &TableValue| (0..t.rows.len()).filter_map(|i| t.row(i)).collect::<Vec<_>>();
+    let (actual_rows, expected_rows) = (rows(actual), rows(expected));
+    if ignore_order {
+        let mut remaining = expected_rows.clone();
+        for row in actual_rows {
+            match remaining.iter().position(|r| *r == row) {
+                Some(index) => { remaining.remove(index); }
+                None => differences.push(format!("+ added row {}", row)),
+            }
+        }
+        differences.extend(remaining.iter().map(|row| format!("- removed row {}", row)));
+    } else {
+        for (index, (got, want)) in actual_rows.iter().zip(&expected_rows).enumerate() {
+            if got != want {
+                differences.push(format!("~ changed row {}: {} -> {}", index, want, got));
+            }
+        }
+        differences.extend(actual_rows.iter().skip(expected_rows.len()).map(|row| format!("+ added row {}", row)));
+        differences.extend(expected_rows.iter().skip(actual_rows.len()).map(|row| format!("- removed row {}", row)));
+    }
+    differences
+}
+
+/// Parse CSV text into a table, converting cells to the table's field
+/// types when the table definition is known
+fn parse_csv_table(text: &str, schema: Option<&TableDef>, match_headers: bool) -> TableValue {
+    let mut records = parse_csv(text).into_iter();
+    let mut header: Vec<Atom> = records.next().unwrap_or_default().iter().map(|h| Atom::new(h.trim())).collect();
+    // Columns declared with `from "Header"` are read from that header
+    if let Some(schema) = schema {
+        for column in header.iter_mut() {
+            if let Some(field) = schema.fields.iter().find(|f| f.header.as_deref().map(str::trim) == Some(column.as_str())) {
+                *column = field.name;
+            }
+        }
+    }
+    // Headers named like a column once case and spacing are ignored take
+    // the column's name
+    if let (true, Some(schema)) = (match_headers, schema) {
+        let mut matched = HashSet::new();
+        for column in header.iter_mut() {
+            let field = schema.fields.iter().find(|f| column_key(f.name.as_str()) == column_key(column.as_str()));
+            if let Some(field) = field.filter(|f| matched.insert(f.name)) {
+                *column = field.name;
+            }
+        }
+    }
+    let types: Vec<Option<&Type>> = header.iter()
+        .map(|column| schema.and_then(|t| t.fields.iter().find(|f| f.name == *column)).map(|f| &f.field_type))
+        .collect();
+
+    let mut table = TableValue::new(schema.map(|t| t.name), header);
+    for record in records {
+        table.rows.push(types.iter().enumerate()
+            .map(|(i, ty)| parse_cell(record.get(i).map(String::as_str).unwrap_or(""), *ty))
+            .collect());
+    }
+    table
+}
+
+/// Column name with case, surrounding spaces and runs of spaces and dashes
+/// ignored, as `load_csv(path, Table, "normalize")` matches headers
+fn column_key(name: &str) -> String {
+    name.replace('-', " ").split_whitespace().collect::<Vec<_>>().join("_").to_lowercase()
+}
+
+fn parse_cell(cell: &str, ty: Option<&Type>) -> RuntimeValue {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return RuntimeValue::Null;
+    }
+    let int = || cell.parse::<i64>().ok().map(Value::Int);
+    let float = || cell.parse::<f64>().ok().map(Value::Float);
+    let bool = || match cell.to_ascii_lowercase().as_str() {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => None,
+    };
+    let value = match ty {
+        Some(Type::Int) => int(),
+        Some(Type::Float | Type::Currency) => float(),
+        Some(Type::Bool) => bool(),
+        Some(_) => None,
+        None => int().or_else(float).or_else(bool),
+    };
+    value.unwrap_or_else(|| Value::String(cell.to_string())).into()
+}
+
+/// Split CSV text into records, honoring double-quoted cells
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut cell)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut cell));
+                records.push(std::mem::take(&mut record));
+            }
+            c => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !record.is_empty() {
+        record.push(cell);
+        records.push(record);
+    }
+    records
+}
+
+fn table_to_csv(table: &TableValue) -> String {
+    let cell = |text: String| {
+        if text.contains([',', '"', '\n']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text
+        }
+    };
+    let mut csv = table.columns.iter().map(|c| cell(c.to_string())).collect::<Vec<_>>().join(",");
+    csv.push('\n');
+    for row in &table.rows {
+        let cells: Vec<String> = row.iter()
+            .map(|value| match value {
+                RuntimeValue::Null => String::new(),
+                value => cell(value.to_display_string()),
+            })
+            .collect();
+        csv.push_str(&cells.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Program {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    const PROGRAM: &str = r#"
+table Order {
+    id: int [key],
+    amount: float
+}
+
+function clamp(value: int, low: int, high: int) -> int {
+    if value < low {
+        return low
+    }
+    if value > high {
+        return high
+    }
+    return value
+}
+
+page Home {
+    title "Orders"
+    let total = 0
+    forall n in [1, 2, 3] {
+        total = total + n
+    }
+    text "Total: {total}, clamped: {clamp(total, 0, 5)}"
+    let orders = table_from([{ id: 2, amount: 5.0 }, { id: 1, amount: 20.0 }, { id: 3, amount: 7.5 }])
+    let big = orders where amount > 6.0 sort by id
+    show(big)
+    text "Largest: {max(orders, \"amount\")}"
+    log("shown {count(big)} orders", "warn")
+}
+
+test "big orders" {
+    let orders = table_from([{ id: 1, amount: 20.0 }, { id: 2, amount: 5.0 }])
+    assert_row_count(orders where amount > 10.0, 1)
+    assert_contains(orders, { id: 2 })
+    assert clamp(9, 0, 5) == 5
+}
+
+test "failing" {
+    assert count(table_from([])) == 1
+}
+"#;
+
+    #[test]
+    fn test_run_page() {
+        let program = parse(PROGRAM);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+
+        let output = interpreter.output();
+        assert_eq!(output[0], Output::Title("Orders".to_string()));
+        assert_eq!(output[1], Output::Text("Total: 6, clamped: 5".to_string()));
+        let Output::Table(big) = &output[2] else { panic!("expected a table") };
+        assert_eq!(big.preview(10), "id | amount\n1  | 20.0\n3  | 7.5");
+        assert_eq!(output[3], Output::Text("Largest: 20.0".to_string()));
+        assert_eq!(output[4], Output::Log("warn".to_string(), "shown {count(big)} orders".to_string()));
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let program = parse(PROGRAM);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_test("big orders").unwrap();
+
+        let error = interpreter.run_test("failing").unwrap_err();
+        assert_eq!(error.message, "Assertion failed");
+        assert_eq!(error.span.map(|s| s.line), Some(39));
+    }
+
+    #[test]
+    fn test_membership() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, region: "EU" }, { id: 2, region: "US" }, { id: 3, region: "APAC" }])
+    let regions = ["EU", "APAC"]
+    show(orders where region in regions)
+    if 2 in [1, 3] {
+        text "found"
+    } else {
+        text "missing"
+    }
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let output = interpreter.output();
+        let Output::Table(shown) = &output[0] else { panic!("expected a table") };
+        assert_eq!(shown.preview(10), "id | region\n1  | EU\n3  | APAC");
+        assert_eq!(output[1], Output::Text("missing".to_string()));
+    }
+
+    #[test]
+    fn test_between_and_null_checks() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, amount: 5 }, { id: 2, amount: 50 }, { id: 3, amount: 100 }, { id: 4 }])
+    show(orders where amount between 10 and 100)
+    show(orders where amount is null)
+    if 7 between 1 and 5 || 7 is not null {
+        text "checked"
+    }
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let output = interpreter.output();
+        let Output::Table(shown) = &output[0] else { panic!("expected a table") };
+        assert_eq!(shown.preview(10), "id | amount\n2  | 50\n3  | 100");
+        let Output::Table(shown) = &output[1] else { panic!("expected a table") };
+        assert_eq!(shown.preview(10), "id | amount\n4  | null");
+        assert_eq!(output[2], Output::Text("checked".to_string()));
+    }
+
+    #[test]
+    fn test_group_by() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([
+        { id: 1, region: "EU", amount: 600 },
+        { id: 2, region: "US", amount: 300 },
+        { id: 3, region: "EU", amount: 500 },
+        { id: 4, region: "US", amount: 100 },
+    ])
+    show(orders group by region having sum(amount) > 1000)
+    show(orders where count() == 2 && max(amount) < 400 group by region)
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let output = interpreter.output();
+        let Output::Table(shown) = &output[0] else { panic!("expected a table") };
+        assert_eq!(shown.preview(10), "id | region | amount\n1  | EU     | 600\n3  | EU     | 500");
+        let Output::Table(shown) = &output[1] else { panic!("expected a table") };
+        assert_eq!(shown.preview(10), "id | region | amount\n2  | US     | 300\n4  | US     | 100");
+    }
+
+    #[test]
+    fn test_null_handling() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, amount: 5 }, { id: 2 }])
+    text "{sum(orders, \"amount\")} {average(orders, \"amount\", \"skip_nulls\")}"
+    let strict = sum(orders, "amount", "strict")
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        let error = interpreter.run_page("Home").unwrap_err();
+        assert_eq!(interpreter.output()[0], Output::Text("5 5.0".to_string()));
+        assert_eq!(error.message, "sum of 'amount' found 1 missing value(s)");
+    }
+
+    #[test]
+    fn test_share() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ region: "EU", amount: 30 }, { region: "US", amount: 50 }, { region: "EU", amount: 10 }])
+    show(share(orders, "amount"))
+    show(share(orders, "amount" by "region"))
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let Output::Table(shares) = &interpreter.output()[0] else { panic!("Expected a table") };
+        assert_eq!(shares.preview(10), "region | amount | amount_share\nEU     | 30     | 0.3333333333333333\nUS     | 50     | 0.5555555555555556\nEU     | 10     | 0.1111111111111111");
+        let Output::Table(shares) = &interpreter.output()[1] else { panic!("Expected a table") };
+        assert_eq!(shares.preview(10), "region | amount | amount_share\nEU     | 30     | 0.75\nUS     | 50     | 1.0\nEU     | 10     | 0.25");
+    }
+
+    #[test]
+    fn test_sort_by_expression() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, amount: 30, quantity: 3 }, { id: 2, amount: 40, quantity: 2 }, { id: 3, amount: 10, quantity: 1 }])
+    show(orders sort by amount / quantity desc, id)
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let Output::Table(sorted) = &interpreter.output()[0] else { panic!("Expected a table") };
+        assert_eq!(sorted.preview(10), "id | amount | quantity\n2  | 40     | 2\n1  | 30     | 3\n3  | 10     | 1");
+    }
+
+    #[test]
+    fn test_date_literals() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, placed: "2023-12-31" }, { id: 2, placed: "2024-01-15" }])
+    show(orders where placed >= d"2024-01-01")
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let Output::Table(recent) = &interpreter.output()[0] else { panic!("Expected a table") };
+        assert_eq!(recent.preview(10), "id | placed\n2  | 2024-01-15");
+    }
+
+    #[test]
+    fn test_column_patterns() {
+        let program = parse(r#"
+page Home {
+    let users = table_from([{ id: 1, name: "Ann", password: "x", team: "A" }])
+    show(users[name, * except password])
+    show(users[*, id])
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let Output::Table(selected) = &interpreter.output()[0] else { panic!("Expected a table") };
+        assert_eq!(selected.preview(10), "name | id | team\nAnn  | 1  | A");
+        let Output::Table(selected) = &interpreter.output()[1] else { panic!("Expected a table") };
+        assert_eq!(selected.preview(10), "name | password | team | id\nAnn  | x        | A    | 1");
+    }
+
+    #[test]
+    fn test_rename_and_drop() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, amount: 30, notes: "x" }, { id: 2, amount: 40, notes: "y" }])
+    let revenue = orders rename amount to revenue drop notes
+    show(revenue)
+    text "{first(revenue).revenue}"
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        let Output::Table(revenue) = &interpreter.output()[0] else { panic!("Expected a table") };
+        assert_eq!(revenue.preview(10), "id | revenue\n1  | 30\n2  | 40");
+        assert_eq!(interpreter.output()[1], Output::Text("30".to_string()));
+    }
+
+    #[test]
+    fn test_row_accessors() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, amount: 5 }, { id: 2, amount: 50 }])
+    text "{first(orders).amount} {last(orders).amount}"
+    let order = single(orders where id == 2)
+    text "{order.id}"
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        assert_eq!(interpreter.output()[0], Output::Text("5 50".to_string()));
+        assert_eq!(interpreter.output()[1], Output::Text("2".to_string()));
+        
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1 }, { id: 2 }])
+    let order = single(orders)
+}
+"#);
+        let error = Interpreter::new(&program).run_page("Home").unwrap_err();
+        assert_eq!(error.message, "single expects exactly one row, found 2");
+    }
+
+    #[test]
+    fn test_number_functions() {
+        let program = parse(r##"
+page Home {
+    let total = 1234.567
+    let shown = format_number(total, "#,##0.0")
+    let plain = format_number(total, "0")
+    text "{round(total)} {round(total, 2)} {floor(-2.5)} {ceil(2.1)} {abs(-3)}"
+    text "{shown} {plain} {format_number(total, 0)} {percent(0.125)}"
+}
+"##);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        assert_eq!(interpreter.output()[0], Output::Text("1235 1234.57 -3 3 3".to_string()));
+        assert_eq!(interpreter.output()[1], Output::Text("1,234.6 1235 1,235 12.5%".to_string()));
+    }
+
+    #[test]
+    fn test_display() {
+        let program = parse(r#"
+page Home {
+    let orders = table_from([{ id: 1, region: "EU" }])
+    display count(orders)
+    display first(orders)
+    display(orders)
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Home").unwrap();
+        assert_eq!(interpreter.output()[0], Output::Text("1".to_string()));
+        assert_eq!(interpreter.output()[1], Output::Text("{id: 1, region: \"EU\"}".to_string()));
+        assert!(matches!(interpreter.output()[2], Output::Table(_)));
+    }
+
+    #[test]
+    fn test_load_csv_uses_the_table_schema() {
+        let table = parse_csv_table("id,amount\n1,2\n2,\n", None, false);
+        assert_eq!(table.rows[1][1], RuntimeValue::Null);
+
+        let program = parse("table T {\n    code: string,\n    n: float\n}");
+        let ProgramItem::TableDef(def) = &program.items[0] else { panic!() };
+        let table = parse_csv_table("code,n\n007,\"1\"\n", Some(def), false);
+        assert_eq!(table.rows[0], vec![
+            RuntimeValue::Scalar(Value::String("007".to_string())),
+            RuntimeValue::Scalar(Value::Float(1.0)),
+        ]);
+        
+        let program = parse("table T {\n    order_date: string,\n    n: float\n}");
+        let ProgramItem::TableDef(def) = &program.items[0] else { panic!() };
+        let table = parse_csv_table(" Order  Date ,N,n\n2024-01-01,1,2\n", Some(def), true);
+        assert_eq!(table.columns, vec![Atom::new("order_date"), Atom::new("n"), Atom::new("n")]);
+        let table = parse_csv_table("Order Date,n\n2024-01-01,1\n", Some(def), false);
+        assert_eq!(table.columns[0], "Order Date");
+        
+        let program = parse("table T {\n    revenue: currency from \"Total Revenue (€)\"\n}");
+        let ProgramItem::TableDef(def) = &program.items[0] else { panic!() };
+        let table = parse_csv_table("Total Revenue (€)\n12.5\n", Some(def), false);
+        assert_eq!(table.columns, vec![Atom::new("revenue")]);
+    }
+
+    #[test]
+    fn test_restrictions_filter_loaded_tables() {
+        let dir = std::env::temp_dir().join(format!("wtlang_restrict_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("orders.csv"), "id,region\n1,EU\n2,US\n3,EU\n").unwrap();
+        let program = parse(r#"
+table Order {
+    id: int [key]
+    region: string
+}
+
+restrict Order where region == current_user().region
+
+test "signed in" {
+    mock current_user with { region: "EU" }
+    assert_row_count(load_csv("orders.csv", Order), 2)
+}
+
+test "signed out" {
+    assert_row_count(load_csv("orders.csv", Order), 0)
+}
+"#);
+        let mut interpreter = Interpreter::new(&program).with_base_dir(dir.clone());
+        interpreter.run_test("signed in").unwrap();
+        let error = interpreter.run_test("signed out").unwrap_err();
+        assert!(error.message.starts_with("current_user has no signed-in user"), "{}", error.message);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_derived_tables() {
+        let dir = std::env::temp_dir().join(format!("wtlang_derived_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("users.csv"), "name,active\nAda,true\nBob,false\nCy,true\n").unwrap();
+        let program = parse(r#"
+table User {
+    name: string
+    active: bool
+}
+
+table ActiveUsers = load_csv("users.csv", User) where active == true
+table Names = ActiveUsers[name]
+
+test "derived" {
+    assert_row_count(ActiveUsers, 2)
+    assert_row_count(Names, 2)
+}
+
+test "mocked" {
+    mock ActiveUsers with [{ name: "Ada", active: true }]
+    assert_row_count(Names, 1)
+}
+"#);
+        let mut interpreter = Interpreter::new(&program).with_base_dir(dir.clone());
+        interpreter.run_test("derived").unwrap();
+        interpreter.run_test("mocked").unwrap();
+        assert_eq!(interpreter.derived_table_csv("Names").unwrap(), "name\nAda\nCy\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_shared_variables() {
+        let program = parse(r#"
+shared let region: string = "All"
+
+page Filter {
+    region = "EU"
+}
+
+page Report {
+    text "Region: {region}"
+}
+
+test "starts afresh" {
+    assert region == "All"
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Report").unwrap();
+        interpreter.run_page("Filter").unwrap();
+        interpreter.run_page("Report").unwrap();
+        assert_eq!(interpreter.output(), [
+            Output::Text("Region: All".to_string()),
+            Output::Text("Region: EU".to_string()),
+        ]);
+        interpreter.run_test("starts afresh").unwrap();
+    }
+
+    #[test]
+    fn test_constants() {
+        let program = parse(r#"
+const RATE: float = 1
+const DOUBLE = RATE * 2
+
+function taxed(amount: float) -> float {
+    return amount * DOUBLE
+}
+
+page Report {
+    text "Taxed: {taxed(3.0)}"
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Report").unwrap();
+        assert_eq!(interpreter.output(), [Output::Text("Taxed: 6.0".to_string())]);
+    }
+
+    #[test]
+    fn test_match() {
+        let program = parse(r#"
+function size(count: int) -> string {
+    return match count { 0 => "none", 1, 2 => "few", _ => "many" }
+}
+
+page Sizes {
+    forall count in [0, 2, 7] {
+        match count {
+            0 => { text "empty" }
+            _ => {
+                let label = size(count)
+                text "{label}"
+            }
+        }
+    }
+}
+"#);
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.run_page("Sizes").unwrap();
+        assert_eq!(interpreter.output(), [
+            Output::Text("empty".to_string()),
+            Output::Text("few".to_string()),
+            Output::Text("many".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let dir = std::env::temp_dir().join(format!("wtlang_hooks_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("orders.csv"), "id,amount\n1,5.0\n2,-3.0\n").unwrap();
+        let program = parse(r#"
+table Order {
+    id: int [key]
+    amount: float
+}
+
+on_load Order {
+    log("loaded orders")
+}
+
+on_save Order {
+    if count(saved where amount < 0) > 0 {
+        log("negative amounts", "error")
+        return false
+    }
+}
+
+page Home {
+    let orders = load_csv("orders.csv", Order)
+    save_csv(orders where amount > 0, "positive.csv")
+    save_csv(orders, "all.csv")
+}
+"#);
+        let mut interpreter = Interpreter::new(&program).with_base_dir(dir.clone());
+        interpreter.run_page("Home").unwrap();
+        assert_eq!(interpreter.output(), [
+            Output::Log("info".to_string(), "loaded orders".to_string()),
+            Output::Log("error".to_string(), "negative amounts".to_string()),
+        ]);
+        assert!(dir.join("positive.csv").exists());
+        assert!(!dir.join("all.csv").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<(usize, String)>>>);
+
+    impl DebugHook for Recorder {
+        fn before_statement(&mut self, interpreter: &mut Interpreter, span: Span) -> RunResult<()> {
+            let depth = interpreter.frames().len() - 1;
+            let total = interpreter.evaluate("total", depth).map(|v| v.to_string()).unwrap_or_default();
+            self.0.borrow_mut().push((span.line, total));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_debug_hook_sees_statements_and_variables() {
+        let program = parse(PROGRAM);
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.set_hook(Box::new(Recorder(seen.clone())));
+        interpreter.run_page("Home").unwrap();
+
+        let seen = seen.borrow();
+        // `let total` runs before `total` exists; the loop body then sees it grow
+        assert_eq!(seen[0], (19, String::new()));
+        let loop_body: Vec<&str> = seen.iter().filter(|(line, _)| *line == 21).map(|(_, t)| t.as_str()).collect();
+        assert_eq!(loop_body, ["0", "1", "3"]);
+    }
+}
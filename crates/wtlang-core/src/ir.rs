@@ -11,10 +11,14 @@ pub mod types;
 pub mod nodes;
 pub mod module;
 pub mod builder;
+pub mod diff;
+pub mod features;
 
 // Re-export commonly used types
 pub use types::*;
 pub use nodes::*;
 pub use module::*;
 pub use builder::*;
+pub use diff::*;
+pub use features::*;
 
@@ -3,19 +3,26 @@
 // Converts AST representation to IR representation with type information
 
 use crate::ast;
+use crate::intern::Atom;
 use crate::ir::types::*;
 use crate::ir::nodes::*;
 use crate::ir::module::IRModule;
 use crate::symbols::SymbolTable;
 use crate::semantics::SemanticAnalyzer;
+use crate::plugins::PluginRegistry;
 use std::path::PathBuf;
+use crate::eval::{eval_const_with, Value};
 
 pub struct IRBuilder {
     current_file: PathBuf,
     symbol_table: SymbolTable,
     key_counter: usize,
     // Track local variable types during lowering
-    local_vars: std::collections::HashMap<String, Type>,
+    local_vars: std::collections::HashMap<Atom, Type>,
+    /// Values of the constants lowered so far
+    constants: std::collections::HashMap<Atom, Value>,
+    /// Builtins added by plugins, checked by the analysis `build` runs
+    plugins: PluginRegistry,
 }
 
 impl IRBuilder {
@@ -25,6 +32,8 @@ impl IRBuilder {
             symbol_table: SymbolTable::new(),
             key_counter: 0,
             local_vars: std::collections::HashMap::new(),
+            constants: std::collections::HashMap::new(),
+            plugins: PluginRegistry::new(),
         }
     }
     
@@ -34,13 +43,36 @@ impl IRBuilder {
             symbol_table: SymbolTable::new(),
             key_counter: 0,
             local_vars: std::collections::HashMap::new(),
+            constants: std::collections::HashMap::new(),
+            plugins: PluginRegistry::new(),
         }
     }
     
+    /// Create a builder that lowers against an already analyzed symbol table
+    ///
+    /// Used when items are lowered one at a time with `lower_item` instead of
+    /// through `build`.
+    pub fn with_symbols(file: PathBuf, symbol_table: SymbolTable) -> Self {
+        IRBuilder {
+            current_file: file,
+            symbol_table,
+            key_counter: 0,
+            local_vars: std::collections::HashMap::new(),
+            constants: std::collections::HashMap::new(),
+            plugins: PluginRegistry::new(),
+        }
+    }
+    
+    /// Check calls of the builtins added by plugins when building
+    pub fn with_plugins(mut self, plugins: PluginRegistry) -> Self {
+        self.plugins = plugins;
+        self
+    }
+    
     /// Build IR from AST program
     pub fn build(&mut self, program: &ast::Program) -> Result<IRModule, String> {
         // First, run semantic analysis to populate symbol table
-        let mut analyzer = SemanticAnalyzer::new();
+        let mut analyzer = SemanticAnalyzer::new().with_plugins(self.plugins.clone());
         analyzer.analyze(program)
             .map_err(|errors| {
                 errors.iter()
@@ -61,61 +93,74 @@ impl IRBuilder {
         
         ir_module.symbols = self.symbol_table.clone();
         
-        // Convert each program item
+        // Convert each program item; imports are resolved by the module
+        // loader before
         for item in &program.items {
-            match item {
-                ast::ProgramItem::TableDef(table_def) => {
-                    ir_module.items.push(self.lower_table_def(table_def)?);
-                }
-                ast::ProgramItem::Page(page) => {
-                    ir_module.items.push(self.lower_page(page)?);
-                }
-                ast::ProgramItem::FunctionDef(func) => {
-                    ir_module.items.push(self.lower_function_def(func)?);
-                }
-                ast::ProgramItem::ExternalFunction(ext_func) => {
-                    ir_module.items.push(self.lower_external_function(ext_func)?);
-                }
-                ast::ProgramItem::Test(test) => {
-                    ir_module.items.push(self.lower_test(test)?);
-                }
+            if !matches!(item, ast::ProgramItem::Import(_)) {
+                ir_module.items.push(self.lower_item(item)?);
             }
         }
         
         Ok(ir_module)
     }
     
+    /// Lower a single top-level item
+    pub fn lower_item(&mut self, item: &ast::ProgramItem) -> Result<IRItem, String> {
+        match item {
+            ast::ProgramItem::TableDef(table_def) => self.lower_table_def(table_def),
+            ast::ProgramItem::Page(page) => self.lower_page(page),
+            ast::ProgramItem::FunctionDef(func) => self.lower_function_def(func),
+            ast::ProgramItem::ExternalFunction(ext_func) => self.lower_external_function(ext_func),
+            ast::ProgramItem::Test(test) => self.lower_test(test),
+            ast::ProgramItem::Restriction(restriction) => self.lower_restriction(restriction),
+            ast::ProgramItem::DerivedTable(derived) => self.lower_derived_table(derived),
+            ast::ProgramItem::SharedVariable(shared) => self.lower_shared_variable(shared),
+            ast::ProgramItem::Constant(constant) => self.lower_constant(constant),
+            ast::ProgramItem::Hook(hook) => self.lower_hook(hook),
+            ast::ProgramItem::Config(config) => Ok(IRItem::Config {
+                settings: config.settings.iter().map(|s| (s.name, s.value.clone())).collect(),
+                source_loc: SourceRange::node(config.id, config.span),
+            }),
+            ast::ProgramItem::Import(import) => Err(format!("Import of '{}' was not resolved", import.path)),
+        }
+    }
+    
     fn lower_table_def(&mut self, table_def: &ast::TableDef) -> Result<IRItem, String> {
-        let mut schema = TableSchema::new(table_def.name.clone());
+        let mut schema = TableSchema::new(table_def.name);
         
         for field in &table_def.fields {
             schema.fields.push(Field {
-                name: field.name.clone(),
+                name: field.name,
                 ty: FieldType::from(&field.field_type),
+                header: field.header.clone(),
+                references: field.constraints.iter().find_map(|constraint| match constraint {
+                    ast::Constraint::References { table, field } => Some((*table, *field)),
+                    _ => None,
+                }),
             });
             
             for constraint in &field.constraints {
                 match constraint {
                     ast::Constraint::Unique => {
-                        schema.constraints.push(Constraint::Unique(field.name.clone()));
+                        schema.constraints.push(Constraint::Unique(field.name));
                     }
                     ast::Constraint::NonNull => {
-                        schema.constraints.push(Constraint::NonNull(field.name.clone()));
+                        schema.constraints.push(Constraint::NonNull(field.name));
                     }
                     ast::Constraint::Key => {
-                        schema.constraints.push(Constraint::PrimaryKey(field.name.clone()));
+                        schema.constraints.push(Constraint::PrimaryKey(field.name));
                     }
                     _ => {
-                        // Validate and References are not yet fully supported
+                        // References are kept on the field; Validate is not yet supported
                     }
                 }
             }
         }
         
         Ok(IRItem::TableDef {
-            name: table_def.name.clone(),
+            name: table_def.name,
             schema,
-            source_loc: SourceRange::default(),
+            source_loc: SourceRange::node(table_def.id, table_def.span),
         })
     }
     
@@ -126,9 +171,9 @@ impl IRBuilder {
         let body = self.lower_statements(&page.statements)?;
         
         Ok(IRItem::PageDef {
-            name: page.name.clone(),
+            name: page.name,
             body,
-            source_loc: SourceRange::default(),
+            source_loc: SourceRange::node(page.id, page.span),
         })
     }
     
@@ -138,39 +183,39 @@ impl IRBuilder {
         
         let params: Vec<Param> = func.params.iter()
             .map(|p| Param {
-                name: p.name.clone(),
+                name: p.name,
                 ty: Type::from(&p.param_type),
             })
             .collect();
         
         // Add parameters to local vars
         for param in &params {
-            self.local_vars.insert(param.name.clone(), param.ty.clone());
+            self.local_vars.insert(param.name, param.ty.clone());
         }
         
         let body = self.lower_statements(&func.body)?;
         
         Ok(IRItem::FunctionDef {
-            name: func.name.clone(),
+            name: func.name,
             params,
             return_type: Type::from(&func.return_type),
             body,
             is_external: false,
             external_info: None,
-            source_loc: SourceRange::default(),
+            source_loc: SourceRange::node(func.id, func.span),
         })
     }
     
     fn lower_external_function(&mut self, ext_func: &ast::ExternalFunction) -> Result<IRItem, String> {
         let params = ext_func.params.iter()
             .map(|p| Param {
-                name: p.name.clone(),
+                name: p.name,
                 ty: Type::from(&p.param_type),
             })
             .collect();
         
         Ok(IRItem::FunctionDef {
-            name: ext_func.name.clone(),
+            name: ext_func.name,
             params,
             return_type: Type::from(&ext_func.return_type),
             body: Vec::new(),
@@ -179,7 +224,7 @@ impl IRBuilder {
                 language: "python".to_string(),
                 module: ext_func.module.clone(),
             }),
-            source_loc: SourceRange::default(),
+            source_loc: SourceRange::node(ext_func.id, ext_func.span),
         })
     }
     
@@ -192,7 +237,82 @@ impl IRBuilder {
         Ok(IRItem::TestDef {
             name: test.name.clone(),
             body,
-            source_loc: SourceRange::default(),
+            source_loc: SourceRange::node(test.id, test.span),
+        })
+    }
+    
+    fn lower_restriction(&mut self, restriction: &ast::Restriction) -> Result<IRItem, String> {
+        // Bare names in the condition are columns of the restricted table
+        self.local_vars.clear();
+        
+        Ok(IRItem::Restriction {
+            table: restriction.table,
+            condition: self.lower_expr(&restriction.condition)?,
+            source_loc: SourceRange::node(restriction.id, restriction.span),
+        })
+    }
+    
+    fn lower_derived_table(&mut self, derived: &ast::DerivedTable) -> Result<IRItem, String> {
+        self.local_vars.clear();
+        
+        Ok(IRItem::DerivedTable {
+            name: derived.name,
+            value: self.lower_expr(&derived.value)?,
+            materialize: Materialize::from(derived.materialize),
+            source_loc: SourceRange::node(derived.id, derived.span),
+        })
+    }
+    
+    fn lower_shared_variable(&mut self, shared: &ast::SharedVariable) -> Result<IRItem, String> {
+        self.local_vars.clear();
+        
+        let value = self.lower_expr(&shared.value)?;
+        let ty = match &shared.type_annotation {
+            Some(ty) => Type::from(ty),
+            None => value.get_type().clone(),
+        };
+        Ok(IRItem::SharedVariable {
+            name: shared.name,
+            ty,
+            value,
+            source_loc: SourceRange::node(shared.id, shared.span),
+        })
+    }
+    
+    /// A constant, folded into its value
+    fn lower_constant(&mut self, constant: &ast::Constant) -> Result<IRItem, String> {
+        let value = eval_const_with(&constant.value, &self.constants)
+            .map_err(|e| format!("Value of constant '{}' cannot be computed: {}", constant.name, e))?
+            .with_type(constant.type_annotation.as_ref());
+        self.constants.insert(constant.name, value.clone());
+        let (ty, value) = match value {
+            Value::Int(n) => (Type::Int, Literal::Int(n)),
+            Value::Float(x) => (Type::Float, Literal::Float(x)),
+            Value::String(s) => (Type::String, Literal::String(s)),
+            Value::Bool(b) => (Type::Bool, Literal::Bool(b)),
+        };
+        Ok(IRItem::Constant {
+            name: constant.name,
+            ty,
+            value,
+            source_loc: SourceRange::node(constant.id, constant.span),
+        })
+    }
+    
+    fn lower_hook(&mut self, hook: &ast::Hook) -> Result<IRItem, String> {
+        // The hook's variable holds the rows of its table
+        self.local_vars.clear();
+        let param = Atom::new(hook.event.variable());
+        self.local_vars.insert(param, Type::from(&ast::Type::Table(hook.table.into())));
+        
+        let body = self.lower_statements(&hook.body)?;
+        
+        Ok(IRItem::Hook {
+            event: HookEvent::from(hook.event),
+            table: hook.table,
+            param,
+            body,
+            source_loc: SourceRange::node(hook.id, hook.span),
         })
     }
     
@@ -202,11 +322,28 @@ impl IRBuilder {
             .collect()
     }
     
+    fn lower_text(&mut self, text: &ast::PageText) -> Result<DisplayText, String> {
+        match text {
+            ast::PageText::Literal(text) => Ok(DisplayText::Literal(text.clone())),
+            ast::PageText::Interpolated(parts) => {
+                let mut segments = Vec::new();
+                for part in parts {
+                    segments.push(match part {
+                        ast::TextPart::Text(text) => TextSegment::Text(text.clone()),
+                        ast::TextPart::Expr { expr, .. } => TextSegment::Expr(self.lower_expr(expr)?),
+                    });
+                }
+                Ok(DisplayText::Interpolated(segments))
+            }
+            ast::PageText::Translated(key) => Ok(DisplayText::Translated { key: key.clone() }),
+        }
+    }
+    
     fn lower_statement(&mut self, stmt: &ast::Statement) -> Result<IRNode, String> {
         match stmt {
             ast::Statement::Title(text) => {
                 Ok(IRNode::ShowText {
-                    text: text.clone(),
+                    text: self.lower_text(text)?,
                     style: TextStyle::Title,
                     source_loc: SourceRange::default(),
                 })
@@ -214,7 +351,7 @@ impl IRBuilder {
             
             ast::Statement::Subtitle(text) => {
                 Ok(IRNode::ShowText {
-                    text: text.clone(),
+                    text: self.lower_text(text)?,
                     style: TextStyle::Subtitle,
                     source_loc: SourceRange::default(),
                 })
@@ -222,63 +359,71 @@ impl IRBuilder {
             
             ast::Statement::Text(text) => {
                 Ok(IRNode::ShowText {
-                    text: text.clone(),
+                    text: self.lower_text(text)?,
                     style: TextStyle::Normal,
                     source_loc: SourceRange::default(),
                 })
             }
             
-            ast::Statement::Button { label, body } => {
+            ast::Statement::Button { label, body, id, span } => {
                 Ok(IRNode::Button {
-                    label: label.clone(),
+                    label: self.lower_text(label)?,
                     body: self.lower_statements(body)?,
-                    source_loc: SourceRange::default(),
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
             
-            ast::Statement::Section { title, body } => {
+            ast::Statement::Section { title, body, id, span } => {
                 Ok(IRNode::Section {
-                    title: title.clone(),
+                    title: self.lower_text(title)?,
                     body: self.lower_statements(body)?,
-                    source_loc: SourceRange::default(),
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
             
-            ast::Statement::Let { name, type_annotation, value } => {
-                let ty = if let Some(type_ann) = type_annotation {
-                    Type::from(type_ann)
-                } else if let Some(val_expr) = value {
-                    self.infer_expr_type(val_expr)?
-                } else {
-                    return Err(format!("Variable '{}' requires either type annotation or initial value", name));
-                };
-                
+            ast::Statement::Confirm { message, body, id, span } => {
+                Ok(IRNode::Confirm {
+                    message: self.lower_text(message)?,
+                    body: self.lower_statements(body)?,
+                    source_loc: SourceRange::node(*id, *span),
+                })
+            }
+            
+            ast::Statement::Let { name, type_annotation, value, id, span } => {
                 let ir_value = if let Some(val_expr) = value {
                     Some(Box::new(self.lower_expr(val_expr)?))
                 } else {
                     None
                 };
                 
+                let ty = if let Some(type_ann) = type_annotation {
+                    Type::from(type_ann)
+                } else if let Some(ir_value) = &ir_value {
+                    ir_value.get_type().clone()
+                } else {
+                    return Err(format!("Variable '{}' requires either type annotation or initial value", name));
+                };
+                
                 // Register the variable in local environment
-                self.local_vars.insert(name.clone(), ty.clone());
+                self.local_vars.insert(*name, ty.clone());
                 
                 Ok(IRNode::Binding {
-                    name: name.clone(),
+                    name: *name,
                     ty,
                     value: ir_value,
-                    source_loc: SourceRange::default(),
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
             
-            ast::Statement::Assign { name, value } => {
+            ast::Statement::Assign { name, value, id, span } => {
                 Ok(IRNode::Assignment {
-                    target: name.clone(),
+                    target: *name,
                     value: Box::new(self.lower_expr(value)?),
-                    source_loc: SourceRange::default(),
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
             
-            ast::Statement::If { condition, then_branch, else_branch } => {
+            ast::Statement::If { condition, then_branch, else_branch, id, then_span, .. } => {
                 Ok(IRNode::Conditional {
                     condition: Box::new(self.lower_expr(condition)?),
                     then_branch: self.lower_statements(then_branch)?,
@@ -287,16 +432,43 @@ impl IRBuilder {
                     } else {
                         None
                     },
-                    source_loc: SourceRange::default(),
+                    source_loc: SourceRange::node(*id, *then_span),
+                })
+            }
+            
+            ast::Statement::Match { value, arms, id, span } => {
+                let arms: Result<Vec<_>, String> = arms.iter()
+                    .map(|arm| Ok(MatchArm {
+                        patterns: self.lower_match_patterns(&arm.patterns)?,
+                        body: self.lower_statements(&arm.body)?,
+                    }))
+                    .collect();
+                Ok(IRNode::Match {
+                    value: Box::new(self.lower_expr(value)?),
+                    arms: arms?,
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
             
-            ast::Statement::Forall { var, iterable, body } => {
+            ast::Statement::Forall { var, iterable, body, id, span } => {
+                let iterable = self.lower_expr(iterable)?;
+                // The variable holds an element of an array, or a row of a table
+                let var_type = match iterable.get_type() {
+                    Type::Array(element) => element.as_ref().clone(),
+                    Type::Table(schema) => Type::Row(schema.clone()),
+                    _ => Type::Error,
+                };
+                let enclosing = self.local_vars.insert(*var, var_type);
+                let body = self.lower_statements(body);
+                match enclosing {
+                    Some(ty) => self.local_vars.insert(*var, ty),
+                    None => self.local_vars.remove(var),
+                };
                 Ok(IRNode::Loop {
-                    variable: var.clone(),
-                    iterable: Box::new(self.lower_expr(iterable)?),
-                    body: self.lower_statements(body)?,
-                    source_loc: SourceRange::default(),
+                    variable: *var,
+                    iterable: Box::new(iterable),
+                    body: body?,
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
             
@@ -311,7 +483,59 @@ impl IRBuilder {
                 let expr = self.lower_function_call(call)?;
                 Ok(IRNode::ExprStmt {
                     expr: Box::new(expr),
-                    source_loc: SourceRange::default(),
+                    source_loc: SourceRange::node(call.id, call.span),
+                })
+            }
+            
+            ast::Statement::Assert { condition, id, span } => {
+                Ok(IRNode::Assert {
+                    condition: Box::new(self.lower_expr(condition)?),
+                    source_loc: SourceRange::node(*id, *span),
+                })
+            }
+            
+            ast::Statement::Mock { target, value, id, span } => {
+                Ok(IRNode::Mock {
+                    target: *target,
+                    value: Box::new(self.lower_expr(value)?),
+                    source_loc: SourceRange::node(*id, *span),
+                })
+            }
+            
+            ast::Statement::Refresh { seconds, id, span } => {
+                Ok(IRNode::Refresh {
+                    seconds: *seconds,
+                    source_loc: SourceRange::node(*id, *span),
+                })
+            }
+            
+            ast::Statement::Python { inputs, outputs, code, id, span } => {
+                // An input taking any table has the type of the table passed
+                let inputs = inputs.iter()
+                    .map(|input| CodeVariable {
+                        name: input.name,
+                        variable: input.variable,
+                        ty: match &input.binding_type {
+                            Some(ty) => self.ast_type_to_ir_type(ty),
+                            None => self.local_vars.get(&input.variable).cloned().unwrap_or(Type::Error),
+                        },
+                    })
+                    .collect();
+                let outputs: Vec<CodeVariable> = outputs.iter()
+                    .map(|output| CodeVariable {
+                        name: output.name,
+                        variable: output.variable,
+                        ty: output.binding_type.as_ref().map_or(Type::Error, |ty| self.ast_type_to_ir_type(ty)),
+                    })
+                    .collect();
+                for output in &outputs {
+                    self.local_vars.insert(output.variable, output.ty.clone());
+                }
+                Ok(IRNode::Python {
+                    inputs,
+                    outputs,
+                    code: code.clone(),
+                    source_loc: SourceRange::node(*id, *span),
                 })
             }
         }
@@ -347,17 +571,32 @@ impl IRBuilder {
                 })
             }
             
+            ast::Expr::DateLiteral(val) => {
+                Ok(IRExpr::Literal {
+                    value: Literal::Date(val.clone()),
+                    ty: Type::Date,
+                })
+            }
+            
+            ast::Expr::CurrencyLiteral { amount, currency } => {
+                Ok(IRExpr::Literal {
+                    value: Literal::Currency { amount: amount.clone(), currency: currency.clone() },
+                    ty: Type::Currency,
+                })
+            }
+            
             ast::Expr::Identifier(name) => {
                 // Special handling for _ placeholder in chaining
                 if name == "_" {
                     Ok(IRExpr::Variable {
-                        name: "_".to_string(),
-                        ty: Type::Error, // Type will be determined by context
+                        name: Atom::new("_"),
+                        // The left side's, within a chain
+                        ty: self.local_vars.get(name).cloned().unwrap_or(Type::Error),
                     })
                 } else {
                     let ty = self.lookup_variable_type(name)?;
                     Ok(IRExpr::Variable {
-                        name: name.clone(),
+                        name: *name,
                         ty,
                     })
                 }
@@ -367,7 +606,7 @@ impl IRBuilder {
                 self.lower_function_call(call)
             }
             
-            ast::Expr::BinaryOp { op, left, right } => {
+            ast::Expr::BinaryOp { op, left, right, .. } => {
                 let left_ir = self.lower_expr(left)?;
                 let right_ir = self.lower_expr(right)?;
                 
@@ -411,9 +650,12 @@ impl IRBuilder {
                 }
             }
             
-            ast::Expr::UnaryOp { op, operand } => {
+            ast::Expr::UnaryOp { op, operand, .. } => {
                 let operand_ir = self.lower_expr(operand)?;
-                let ty = operand_ir.get_type().clone();
+                let ty = match op {
+                    ast::UnaryOp::IsNull | ast::UnaryOp::IsNotNull => Type::Bool,
+                    _ => operand_ir.get_type().clone(),
+                };
                 
                 Ok(IRExpr::UnaryOp {
                     op: UnOp::from(op),
@@ -422,7 +664,16 @@ impl IRBuilder {
                 })
             }
             
-            ast::Expr::FieldAccess { object, field } => {
+            ast::Expr::Between { value, low, high, .. } => {
+                Ok(IRExpr::Between {
+                    value: Box::new(self.lower_expr(value)?),
+                    low: Box::new(self.lower_expr(low)?),
+                    high: Box::new(self.lower_expr(high)?),
+                    ty: Type::Bool,
+                })
+            }
+            
+            ast::Expr::FieldAccess { object, field, .. } => {
                 let object_ir = self.lower_expr(object)?;
                 
                 // Check if this is a reference navigation
@@ -430,7 +681,7 @@ impl IRBuilder {
                     // This is a reference field - create RefNavigation node
                     Ok(IRExpr::RefNavigation {
                         object: Box::new(object_ir),
-                        field: field.clone(),
+                        field: *field,
                         target_table: ref_info.target_table,
                         ty: Type::Table(ref_info.target_schema),
                     })
@@ -439,13 +690,13 @@ impl IRBuilder {
                     let ty = self.infer_field_access_type(object_ir.get_type(), field)?;
                     Ok(IRExpr::FieldAccess {
                         object: Box::new(object_ir),
-                        field: field.clone(),
+                        field: *field,
                         ty,
                     })
                 }
             }
             
-            ast::Expr::Index { object, index } => {
+            ast::Expr::Index { object, index, .. } => {
                 let object_ir = self.lower_expr(object)?;
                 let index_ir = self.lower_expr(index)?;
                 let ty = self.infer_index_type(object_ir.get_type())?;
@@ -457,9 +708,16 @@ impl IRBuilder {
                 })
             }
             
-            ast::Expr::Chain { left, right } => {
+            ast::Expr::Chain { left, right, .. } => {
                 let left_ir = self.lower_expr(left)?;
-                let right_ir = self.lower_expr(right)?;
+                let placeholder = Atom::new("_");
+                let enclosing = self.local_vars.insert(placeholder, left_ir.get_type().clone());
+                let right_ir = self.lower_expr(right);
+                match enclosing {
+                    Some(ty) => self.local_vars.insert(placeholder, ty),
+                    None => self.local_vars.remove(&placeholder),
+                };
+                let right_ir = right_ir?;
                 let ty = right_ir.get_type().clone();
                 
                 Ok(IRExpr::Chain {
@@ -472,7 +730,7 @@ impl IRBuilder {
             ast::Expr::TableLiteral(fields) => {
                 let ir_fields: Result<Vec<_>, String> = fields.iter()
                     .map(|(name, expr)| {
-                        self.lower_expr(expr).map(|ir_expr| (name.clone(), ir_expr))
+                        self.lower_expr(expr).map(|ir_expr| (*name, ir_expr))
                     })
                     .collect();
                 
@@ -486,10 +744,32 @@ impl IRBuilder {
                 let ir_elements: Result<Vec<_>, String> = elements.iter()
                     .map(|e| self.lower_expr(e))
                     .collect();
+                let ir_elements = ir_elements?;
+                let ty = match array_element_type(ir_elements.iter().map(|e| e.get_type())) {
+                    Type::Error => Type::Error,
+                    element => Type::Array(Box::new(element)),
+                };
                 
                 Ok(IRExpr::ArrayConstructor {
-                    elements: ir_elements?,
-                    ty: Type::Error, // Would need element type inference
+                    elements: ir_elements,
+                    ty,
+                })
+            }
+            
+            ast::Expr::Match { value, arms, .. } => {
+                let arms: Result<Vec<_>, String> = arms.iter()
+                    .map(|arm| Ok(MatchArm {
+                        patterns: self.lower_match_patterns(&arm.patterns)?,
+                        body: self.lower_expr(&arm.body)?,
+                    }))
+                    .collect();
+                let arms = arms?;
+                // The arms share a type, checked by the semantic analysis
+                let ty = arms.first().map_or(Type::Error, |arm| arm.body.get_type().clone());
+                Ok(IRExpr::Match {
+                    value: Box::new(self.lower_expr(value)?),
+                    arms,
+                    ty,
                 })
             }
             
@@ -515,7 +795,7 @@ impl IRBuilder {
                 })
             }
             
-            ast::Expr::Where { table, condition } => {
+            ast::Expr::Where { table, condition, .. } => {
                 let table_ir = self.lower_expr(table)?;
                 let condition_ir = self.lower_expr(condition)?;
                 let ty = table_ir.get_type().clone();
@@ -527,16 +807,17 @@ impl IRBuilder {
                 })
             }
             
-            ast::Expr::SortBy { table, columns } => {
+            ast::Expr::SortBy { table, columns, .. } => {
                 let table_ir = self.lower_expr(table)?;
                 let ty = table_ir.get_type().clone();
                 
-                let sort_specs: Vec<SortSpec> = columns.iter()
-                    .map(|col| SortSpec {
-                        column: col.name.clone(),
+                let mut sort_specs = Vec::new();
+                for col in columns {
+                    sort_specs.push(SortSpec {
+                        key: self.lower_expr(&col.key)?,
                         ascending: col.ascending,
-                    })
-                    .collect();
+                    });
+                }
                 
                 Ok(IRExpr::SortBy {
                     table: Box::new(table_ir),
@@ -545,11 +826,84 @@ impl IRBuilder {
                 })
             }
             
-            ast::Expr::ColumnSelect { table, columns } => {
+            ast::Expr::GroupBy { table, columns, having, .. } => {
+                let table_ir = self.lower_expr(table)?;
+                let having_ir = self.lower_expr(having)?;
+                let ty = table_ir.get_type().clone();
+                
+                Ok(IRExpr::GroupBy {
+                    table: Box::new(table_ir),
+                    columns: columns.clone(),
+                    having: Box::new(having_ir),
+                    ty,
+                })
+            }
+            
+            // `*` stands for the table's other columns, so they must be known
+            ast::Expr::ColumnSelect { table, columns, .. } => {
                 let table_ir = self.lower_expr(table)?;
                 let ty = table_ir.get_type().clone();
+                let available = ty.as_table().and_then(|schema| self.symbol_table.table_columns(&schema.table_type()));
+                let columns = match available {
+                    Some(available) => ast::SelectedColumn::expand(columns, &available)
+                        .map_err(|column| format!("Cannot select column '{}', which the table does not have", column))?,
+                    None => columns.iter()
+                        .map(|column| match column {
+                            ast::SelectedColumn::Name(name) => Ok(*name),
+                            ast::SelectedColumn::Rest { .. } => Err("Cannot select '*' from a table whose columns are unknown".to_string()),
+                        })
+                        .collect::<Result<_, _>>()?,
+                };
                 
                 Ok(IRExpr::ColumnSelect {
+                    table: Box::new(table_ir),
+                    columns,
+                    ty,
+                })
+            }
+            
+            // The result's schema records the changed columns, so it is not
+            // validated as the original table
+            ast::Expr::Rename { table, columns, .. } => {
+                let table_ir = self.lower_expr(table)?;
+                let mut ty = table_ir.get_type().clone();
+                if let Type::Table(schema) = &mut ty {
+                    for &(from, to) in columns {
+                        if !self.has_column(schema, from) {
+                            return Err(format!("Cannot rename column '{}', which table {} does not have", from, schema.table_type()));
+                        }
+                        for field in schema.fields.iter_mut().filter(|field| field.name == from) {
+                            field.name = to;
+                        }
+                        for constraint in schema.constraints.iter_mut().filter(|constraint| constraint.field() == from) {
+                            *constraint.field_mut() = to;
+                        }
+                        schema.changes.push(ast::ColumnChange::Rename(from, to));
+                    }
+                }
+                
+                Ok(IRExpr::Rename {
+                    table: Box::new(table_ir),
+                    columns: columns.clone(),
+                    ty,
+                })
+            }
+            
+            ast::Expr::DropColumns { table, columns, .. } => {
+                let table_ir = self.lower_expr(table)?;
+                let mut ty = table_ir.get_type().clone();
+                if let Type::Table(schema) = &mut ty {
+                    for &column in columns {
+                        if !self.has_column(schema, column) {
+                            return Err(format!("Cannot drop column '{}', which table {} does not have", column, schema.table_type()));
+                        }
+                        schema.fields.retain(|field| field.name != column);
+                        schema.constraints.retain(|constraint| constraint.field() != column);
+                        schema.changes.push(ast::ColumnChange::Drop(column));
+                    }
+                }
+                
+                Ok(IRExpr::DropColumns {
                     table: Box::new(table_ir),
                     columns: columns.clone(),
                     ty,
@@ -558,11 +912,25 @@ impl IRBuilder {
         }
     }
     
+    fn lower_match_patterns(&mut self, patterns: &[ast::MatchPattern]) -> Result<Vec<MatchPattern>, String> {
+        patterns.iter()
+            .map(|pattern| match pattern {
+                ast::MatchPattern::Literal(literal) => Ok(MatchPattern::Literal(self.lower_expr(literal)?)),
+                ast::MatchPattern::Wildcard => Ok(MatchPattern::Wildcard),
+            })
+            .collect()
+    }
+    
     fn lower_function_call(&mut self, call: &ast::FunctionCall) -> Result<IRExpr, String> {
-        let args: Result<Vec<_>, String> = call.args.iter()
-            .map(|arg| self.lower_expr(arg))
-            .collect();
-        let args = args?;
+        let mut args = Vec::new();
+        for arg in &call.args {
+            // The parameters of a lambda passed with a table are its rows
+            let row = match (arg, args.first().map(IRExpr::get_type)) {
+                (ast::Expr::Lambda { .. }, Some(Type::Table(schema))) => Some(Type::Row(schema.clone())),
+                _ => None,
+            };
+            args.push(self.lower_argument(arg, row)?);
+        }
         
         // Special handling for built-in functions
         let ty = match call.name.as_str() {
@@ -581,15 +949,58 @@ impl IRBuilder {
                     // Parse filters from arguments - simplified for now
                 }
                 
+                // show_editable returns the edited table
+                let ty = if editable { table_expr.get_type().clone() } else { Type::Unit };
+                
                 self.key_counter += 1;
                 return Ok(IRExpr::FunctionCall {
-                    function: if editable { "show_editable" } else { "show" }.to_string(),
+                    function: Atom::new(if editable { "show_editable" } else { "show" }),
                     args,
-                    ty: Type::Unit,
+                    ty,
                 });
             }
-            "load_csv" => Type::Error, // Would need table type from argument
-            "save_csv" => Type::Unit,
+            // load_csv(path, Table) is typed by its table argument
+            "load_csv" => args.get(1)
+                .map(|arg| arg.get_type())
+                .filter(|ty| ty.is_table())
+                .cloned()
+                .unwrap_or(Type::Error),
+            // load_sql(connection, name, Table) likewise by its third
+            "load_sql" => args.get(2)
+                .map(|arg| arg.get_type())
+                .filter(|ty| ty.is_table())
+                .cloned()
+                .unwrap_or(Type::Error),
+            "save_csv" | "save_sql" | "log" | "revert" | "display" => Type::Unit,
+            // share(table, "column") has the table's fields and the float
+            // column_share
+            "share" => match (args.first().map(|arg| arg.get_type()), args.get(1)) {
+                (Some(Type::Table(schema)), Some(IRExpr::Literal { value: Literal::String(column), .. })) => {
+                    let mut schema = schema.clone();
+                    let share = Atom::new(&format!("{}_share", column));
+                    schema.fields.push(Field { name: share, ty: FieldType::Float, header: None, references: None });
+                    schema.changes.push(ast::ColumnChange::Add(share));
+                    Type::Table(schema)
+                }
+                _ => Type::Error,
+            },
+            // first(table), last(table) and single(table) are rows of it
+            "first" | "last" | "single" => match args.first().map(|arg| arg.get_type()) {
+                Some(Type::Table(schema)) => Type::Row(schema.clone()),
+                _ => Type::Error,
+            },
+            "t" | "format_number" | "format_currency" | "format_date" | "percent" => Type::String,
+            // round(x), floor and ceil are whole numbers, round(x, n) keeps
+            // n decimals and abs the type of its number
+            "round" if args.len() > 1 => Type::Float,
+            "round" | "floor" | "ceil" => Type::Int,
+            "abs" => args.first().map(|arg| arg.get_type().clone()).unwrap_or(Type::Error),
+            // Whether the email was sent or the PDF offered, unless an
+            // external function of the same name replaces the builtin
+            "send_email" | "export_pdf" => match self.symbol_table.lookup(&call.name) {
+                Some(symbol) => self.ast_type_to_ir_type(&symbol.symbol_type),
+                None => Type::Bool,
+            },
             "where" | "sort" | "aggregate" => {
                 if !args.is_empty() {
                     args[0].get_type().clone()
@@ -604,26 +1015,37 @@ impl IRBuilder {
         };
         
         Ok(IRExpr::FunctionCall {
-            function: call.name.clone(),
+            function: call.name,
             args,
             ty,
         })
     }
     
-    fn infer_expr_type(&self, expr: &ast::Expr) -> Result<Type, String> {
-        match expr {
-            ast::Expr::IntLiteral(_) => Ok(Type::Int),
-            ast::Expr::FloatLiteral(_) => Ok(Type::Float),
-            ast::Expr::StringLiteral(_) => Ok(Type::String),
-            ast::Expr::BoolLiteral(_) => Ok(Type::Bool),
-            ast::Expr::Identifier(name) => self.lookup_variable_type(name),
-            _ => Ok(Type::Error), // Simplified - would need full type inference
+    /// Lower a call argument, typing a lambda's parameters as `param_type`
+    fn lower_argument(&mut self, arg: &ast::Expr, param_type: Option<Type>) -> Result<IRExpr, String> {
+        let (ast::Expr::Lambda { params, .. }, Some(param_type)) = (arg, param_type) else {
+            return self.lower_expr(arg);
+        };
+        let enclosing: Vec<(Atom, Option<Type>)> = params.iter()
+            .map(|param| (*param, self.local_vars.insert(*param, param_type.clone())))
+            .collect();
+        let lowered = self.lower_expr(arg);
+        for (param, ty) in enclosing.into_iter().rev() {
+            match ty {
+                Some(ty) => self.local_vars.insert(param, ty),
+                None => self.local_vars.remove(&param),
+            };
+        }
+        let mut lowered = lowered?;
+        if let IRExpr::Lambda { ty: Type::Function { params, .. }, .. } = &mut lowered {
+            params.fill(param_type);
         }
+        Ok(lowered)
     }
     
     fn lookup_variable_type(&self, name: &str) -> Result<Type, String> {
         // Check local variables first
-        if let Some(ty) = self.local_vars.get(name) {
+        if let Some(ty) = Atom::get(name).and_then(|atom| self.local_vars.get(&atom)) {
             return Ok(ty.clone());
         }
         
@@ -665,7 +1087,7 @@ impl IRBuilder {
             ast::BinaryOp::GreaterThan | ast::BinaryOp::GreaterThanEqual => {
                 Ok(Type::Bool)
             }
-            ast::BinaryOp::And | ast::BinaryOp::Or => {
+            ast::BinaryOp::And | ast::BinaryOp::Or | ast::BinaryOp::In => {
                 Ok(Type::Bool)
             }
             ast::BinaryOp::Union | ast::BinaryOp::Minus | ast::BinaryOp::Intersect => {
@@ -675,36 +1097,56 @@ impl IRBuilder {
         }
     }
     
+    /// Whether a table has a column, assumed when its fields are unknown
+    fn has_column(&self, schema: &TableSchema, column: Atom) -> bool {
+        let table = schema.table_type();
+        schema.has_field(&column)
+            || !self.symbol_table.has_fields(&table)
+            || self.symbol_table.get_field_type(&table, &column).is_some()
+    }
+    
     fn infer_field_access_type(&self, object_ty: &Type, field: &str) -> Result<Type, String> {
         if let Some(schema) = object_ty.as_table() {
             if let Some(field_type) = schema.get_field_type(field) {
-                Ok(match field_type {
-                    FieldType::Int => Type::Int,
-                    FieldType::Float => Type::Float,
-                    FieldType::String => Type::String,
-                    FieldType::Bool => Type::Bool,
-                    FieldType::Date => Type::Date,
-                    FieldType::Currency => Type::Currency,
-                    FieldType::Ref { table_name } => {
-                        // Look up the referenced table schema
-                        if let Some(target_symbol) = self.symbol_table.lookup(table_name) {
-                            self.ast_type_to_ir_type(&target_symbol.symbol_type)
-                        } else {
-                            Type::Error
-                        }
-                    }
-                })
+                Ok(self.field_type_to_ir_type(field_type))
             } else {
                 Err(format!("Field '{}' not found in table", field))
             }
+        } else if let Type::Row(schema) = object_ty {
+            // The schemas of tables held in variables are only known by
+            // name, so their rows' fields are typed later
+            Ok(schema.get_field_type(field)
+                .map(|field_type| self.field_type_to_ir_type(field_type))
+                .unwrap_or(Type::Error))
         } else {
             Ok(Type::Error)
         }
     }
     
-    fn infer_index_type(&self, _object_ty: &Type) -> Result<Type, String> {
-        // Simplified - would need to handle array types properly
-        Ok(Type::Error)
+    fn field_type_to_ir_type(&self, field_type: &FieldType) -> Type {
+        match field_type {
+            FieldType::Int => Type::Int,
+            FieldType::Float => Type::Float,
+            FieldType::String => Type::String,
+            FieldType::Bool => Type::Bool,
+            FieldType::Date => Type::Date,
+            FieldType::Currency => Type::Currency,
+            FieldType::Ref { table_name } => {
+                // Look up the referenced table schema
+                if let Some(target_symbol) = self.symbol_table.lookup(table_name) {
+                    self.ast_type_to_ir_type(&target_symbol.symbol_type)
+                } else {
+                    Type::Error
+                }
+            }
+        }
+    }
+    
+    fn infer_index_type(&self, object_ty: &Type) -> Result<Type, String> {
+        match object_ty {
+            Type::Array(element) => Ok(element.as_ref().clone()),
+            _ => Ok(Type::Error),
+        }
     }
     
     fn check_ref_field(&self, object_ty: &Type, field: &str) -> Option<RefInfo> {
@@ -716,7 +1158,7 @@ impl IRBuilder {
                     if let Some(target_symbol) = self.symbol_table.lookup(table_name) {
                         if let Type::Table(target_schema) = self.ast_type_to_ir_type(&target_symbol.symbol_type) {
                             return Some(RefInfo {
-                                target_table: table_name.clone(),
+                                target_table: *table_name,
                                 target_schema,
                             });
                         }
@@ -729,7 +1171,7 @@ impl IRBuilder {
 }
 
 struct RefInfo {
-    target_table: String,
+    target_table: Atom,
     target_schema: TableSchema,
 }
 
@@ -738,3 +1180,15 @@ impl Default for IRBuilder {
         Self::new()
     }
 }
+
+/// The type shared by an array's elements: ints mixed with floats or
+/// currency widen to them, and an empty array or mixed types give no type
+fn array_element_type<'a>(mut types: impl Iterator<Item = &'a Type>) -> Type {
+    let Some(first) = types.next() else { return Type::Error };
+    types.fold(first.clone(), |element, ty| match (&element, ty) {
+        _ if element == *ty => element,
+        (Type::Int, Type::Float | Type::Currency) => ty.clone(),
+        (Type::Float | Type::Currency, Type::Int) => element,
+        _ => Type::Error,
+    })
+}
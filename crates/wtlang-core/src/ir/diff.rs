@@ -0,0 +1,156 @@
+// Differences between two versions of a table schema
+
+use std::fmt;
+use crate::intern::Atom;
+use crate::ir::types::{Constraint, Field, FieldType, TableSchema};
+
+/// How a table's schema changed from one version to the next
+///
+/// Constraint changes are only listed for fields both versions have; the
+/// constraints of an added field are in the new schema.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    pub added: Vec<Field>,
+    pub removed: Vec<Field>,
+    pub retyped: Vec<RetypedField>,
+    pub added_constraints: Vec<Constraint>,
+    pub removed_constraints: Vec<Constraint>,
+}
+
+/// A field kept under the same name with a different type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetypedField {
+    pub name: Atom,
+    pub old: FieldType,
+    pub new: FieldType,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.retyped.is_empty()
+            && self.added_constraints.is_empty()
+            && self.removed_constraints.is_empty()
+    }
+}
+
+impl TableSchema {
+    /// Compare this schema with a newer version of it
+    pub fn diff(&self, other: &TableSchema) -> SchemaDiff {
+        let mut diff = SchemaDiff::default();
+        for field in &self.fields {
+            match other.get_field(&field.name) {
+                None => diff.removed.push(field.clone()),
+                Some(new) if new.ty != field.ty => diff.retyped.push(RetypedField {
+                    name: field.name,
+                    old: field.ty.clone(),
+                    new: new.ty.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.added = other.fields.iter()
+            .filter(|field| !self.has_field(&field.name))
+            .cloned()
+            .collect();
+
+        let kept = |constraint: &Constraint| {
+            let field = constraint.field();
+            self.has_field(&field) && other.has_field(&field)
+        };
+        diff.removed_constraints = self.constraints.iter()
+            .filter(|c| kept(c) && !other.constraints.contains(c))
+            .cloned()
+            .collect();
+        diff.added_constraints = other.constraints.iter()
+            .filter(|c| kept(c) && !self.constraints.contains(c))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+/// The constraint as written after a field type, e.g. `non_null`
+fn constraint_keyword(constraint: &Constraint) -> &'static str {
+    match constraint {
+        Constraint::Unique(_) => "unique",
+        Constraint::NonNull(_) => "non_null",
+        Constraint::PrimaryKey(_) => "key",
+    }
+}
+
+/// One line per change: `+` for added, `-` for removed and `~` for retyped
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for field in &self.added {
+            writeln!(f, "+ {}: {}", field.name, field.ty)?;
+        }
+        for field in &self.removed {
+            writeln!(f, "- {}: {}", field.name, field.ty)?;
+        }
+        for field in &self.retyped {
+            writeln!(f, "~ {}: {} -> {}", field.name, field.old, field.new)?;
+        }
+        for constraint in &self.added_constraints {
+            writeln!(f, "+ {} {}", constraint.field(), constraint_keyword(constraint))?;
+        }
+        for constraint in &self.removed_constraints {
+            writeln!(f, "- {} {}", constraint.field(), constraint_keyword(constraint))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(fields: &[(&str, FieldType)], constraints: Vec<Constraint>) -> TableSchema {
+        let mut schema = TableSchema::new(Atom::from("Users"));
+        schema.fields = fields.iter()
+            .map(|(name, ty)| Field { name: Atom::from(*name), ty: ty.clone(), header: None, references: None })
+            .collect();
+        schema.constraints = constraints;
+        schema
+    }
+
+    #[test]
+    fn test_identical_schemas_have_no_diff() {
+        let users = schema(&[("id", FieldType::Int)], vec![Constraint::PrimaryKey(Atom::from("id"))]);
+        assert!(users.diff(&users.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_added_removed_and_retyped_fields() {
+        let old = schema(&[("id", FieldType::Int), ("name", FieldType::String), ("age", FieldType::Int)], vec![]);
+        let new = schema(&[("id", FieldType::Int), ("age", FieldType::Float), ("email", FieldType::String)], vec![]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "email");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name, "name");
+        assert_eq!(diff.retyped, vec![RetypedField {
+            name: Atom::from("age"),
+            old: FieldType::Int,
+            new: FieldType::Float,
+        }]);
+        assert_eq!(diff.to_string(), "+ email: string\n- name: string\n~ age: int -> float\n");
+    }
+
+    #[test]
+    fn test_constraint_changes_on_kept_fields() {
+        let old = schema(
+            &[("id", FieldType::Int), ("email", FieldType::String), ("phone", FieldType::String)],
+            vec![Constraint::Unique(Atom::from("email")), Constraint::NonNull(Atom::from("phone"))],
+        );
+        let new = schema(
+            &[("id", FieldType::Int), ("email", FieldType::String), ("nickname", FieldType::String)],
+            vec![Constraint::NonNull(Atom::from("email")), Constraint::Unique(Atom::from("nickname"))],
+        );
+        let diff = old.diff(&new);
+        assert_eq!(diff.added_constraints, vec![Constraint::NonNull(Atom::from("email"))]);
+        assert_eq!(diff.removed_constraints, vec![Constraint::Unique(Atom::from("email"))]);
+        assert_eq!(diff.to_string(), "+ nickname: string\n- phone: string\n+ email non_null\n- email unique\n");
+    }
+}
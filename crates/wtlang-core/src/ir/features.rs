@@ -0,0 +1,219 @@
+// Runtime features of a program
+//
+// What a generated app needs besides the backend's own runtime: the
+// libraries of the builtins it calls, such as a database driver for
+// `load_sql`, and those the code of its `python` blocks imports. Backends
+// turn them into dependencies, e.g. the Streamlit backend's requirements.txt.
+
+use std::collections::BTreeSet;
+use crate::ir::module::IRModule;
+use crate::ir::nodes::*;
+
+/// Python modules of plotting libraries
+const PLOTTING_MODULES: &[&str] = &["matplotlib", "plotly", "altair", "seaborn", "bokeh"];
+
+/// Python modules of HTTP clients
+const HTTP_MODULES: &[&str] = &["requests", "httpx"];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RuntimeFeature {
+    /// `export_excel`
+    Excel,
+    /// `load_sql` and `save_sql`
+    Sql,
+    /// `export_pdf`
+    PdfExport,
+    /// `refresh every`
+    Refresh,
+    /// A plotting library imported by a `python` block, by module name
+    Plotting(String),
+    /// An HTTP client imported by a `python` block, by module name
+    Requests(String),
+}
+
+impl IRModule {
+    /// The runtime features used anywhere in the program, tests included
+    pub fn runtime_features(&self) -> BTreeSet<RuntimeFeature> {
+        let mut features = BTreeSet::new();
+        for item in &self.items {
+            match item {
+                IRItem::FunctionDef { body, .. }
+                | IRItem::PageDef { body, .. }
+                | IRItem::TestDef { body, .. }
+                | IRItem::Hook { body, .. } => nodes_features(body, &mut features),
+                IRItem::Restriction { condition: value, .. }
+                | IRItem::DerivedTable { value, .. }
+                | IRItem::SharedVariable { value, .. } => expr_features(value, &mut features),
+                IRItem::TableDef { .. } | IRItem::Constant { .. } | IRItem::Config { .. } => {}
+            }
+        }
+        features
+    }
+}
+
+fn nodes_features(nodes: &[IRNode], features: &mut BTreeSet<RuntimeFeature>) {
+    for node in nodes {
+        node_features(node, features);
+    }
+}
+
+fn node_features(node: &IRNode, features: &mut BTreeSet<RuntimeFeature>) {
+    match node {
+        IRNode::ShowTable { table, .. } => expr_features(table, features),
+        IRNode::ShowText { text, .. } => text_features(text, features),
+        IRNode::Button { label: text, body, .. }
+        | IRNode::Section { title: text, body, .. }
+        | IRNode::Confirm { message: text, body, .. } => {
+            text_features(text, features);
+            nodes_features(body, features);
+        }
+        IRNode::Conditional { condition, then_branch, else_branch, .. } => {
+            expr_features(condition, features);
+            nodes_features(then_branch, features);
+            if let Some(else_branch) = else_branch {
+                nodes_features(else_branch, features);
+            }
+        }
+        IRNode::Match { value, arms, .. } => {
+            expr_features(value, features);
+            for arm in arms {
+                nodes_features(&arm.body, features);
+            }
+        }
+        IRNode::Loop { iterable, body, .. } => {
+            expr_features(iterable, features);
+            nodes_features(body, features);
+        }
+        IRNode::Binding { value, .. } | IRNode::Return { value, .. } => {
+            if let Some(value) = value {
+                expr_features(value, features);
+            }
+        }
+        IRNode::Assignment { value, .. }
+        | IRNode::ExprStmt { expr: value, .. }
+        | IRNode::Assert { condition: value, .. }
+        | IRNode::Mock { value, .. } => expr_features(value, features),
+        IRNode::Refresh { .. } => {
+            features.insert(RuntimeFeature::Refresh);
+        }
+        IRNode::Python { code, .. } => {
+            for module in imported_modules(code) {
+                if PLOTTING_MODULES.contains(&module) {
+                    features.insert(RuntimeFeature::Plotting(module.to_string()));
+                } else if HTTP_MODULES.contains(&module) {
+                    features.insert(RuntimeFeature::Requests(module.to_string()));
+                }
+            }
+        }
+    }
+}
+
+fn text_features(text: &DisplayText, features: &mut BTreeSet<RuntimeFeature>) {
+    if let DisplayText::Interpolated(segments) = text {
+        for segment in segments {
+            if let TextSegment::Expr(expr) = segment {
+                expr_features(expr, features);
+            }
+        }
+    }
+}
+
+fn expr_features(expr: &IRExpr, features: &mut BTreeSet<RuntimeFeature>) {
+    match expr {
+        IRExpr::Literal { .. } | IRExpr::Variable { .. } => {}
+        IRExpr::FunctionCall { function, args, .. } => {
+            let feature = match function.as_str() {
+                "export_excel" => Some(RuntimeFeature::Excel),
+                "load_sql" | "save_sql" => Some(RuntimeFeature::Sql),
+                "export_pdf" => Some(RuntimeFeature::PdfExport),
+                _ => None,
+            };
+            features.extend(feature);
+            for arg in args {
+                expr_features(arg, features);
+            }
+        }
+        IRExpr::BinaryOp { left, right, .. }
+        | IRExpr::Index { object: left, index: right, .. }
+        | IRExpr::Chain { left, right, .. }
+        | IRExpr::Where { table: left, condition: right, .. }
+        | IRExpr::GroupBy { table: left, having: right, .. }
+        | IRExpr::Union { left, right, .. }
+        | IRExpr::Minus { left, right, .. }
+        | IRExpr::Intersect { left, right, .. } => {
+            expr_features(left, features);
+            expr_features(right, features);
+        }
+        IRExpr::UnaryOp { operand: value, .. }
+        | IRExpr::FieldAccess { object: value, .. }
+        | IRExpr::Lambda { body: value, .. }
+        | IRExpr::ColumnSelect { table: value, .. }
+        | IRExpr::Rename { table: value, .. }
+        | IRExpr::DropColumns { table: value, .. }
+        | IRExpr::RefNavigation { object: value, .. } => expr_features(value, features),
+        IRExpr::Between { value, low, high, .. } => {
+            expr_features(value, features);
+            expr_features(low, features);
+            expr_features(high, features);
+        }
+        IRExpr::TableConstructor { fields, .. } => {
+            for (_, value) in fields {
+                expr_features(value, features);
+            }
+        }
+        IRExpr::ArrayConstructor { elements, .. } => {
+            for element in elements {
+                expr_features(element, features);
+            }
+        }
+        IRExpr::SortBy { table, columns, .. } => {
+            expr_features(table, features);
+            for column in columns {
+                expr_features(&column.key, features);
+            }
+        }
+        IRExpr::Match { value, arms, .. } => {
+            expr_features(value, features);
+            for arm in arms {
+                expr_features(&arm.body, features);
+            }
+        }
+    }
+}
+
+/// Top-level modules imported by Python code, e.g. `matplotlib` for
+/// `import matplotlib.pyplot as plt` or `from matplotlib import pyplot`
+fn imported_modules(code: &str) -> Vec<&str> {
+    fn top_level(module: &str) -> &str {
+        module.trim().split(['.', ' ']).next().unwrap_or("")
+    }
+    let mut modules = Vec::new();
+    for line in code.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("import ") {
+            modules.extend(rest.split(',').map(top_level));
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            modules.push(top_level(rest));
+        }
+    }
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IRBuilder, Lexer, Parser};
+
+    #[test]
+    fn test_runtime_features() {
+        let source = "page Report {\n    let orders = load_sql(\"sqlite:///shop.db\", \"orders\")\n    refresh every 1m\n    python(orders: table) {\n        import matplotlib.pyplot as plt, os\n        from requests import get\n        import yaml\n    }\n    button \"Export\" {\n        export_excel(orders, \"orders.xlsx\")\n    }\n}\n";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let module = IRBuilder::new().build(&program).unwrap();
+        assert_eq!(module.runtime_features(), BTreeSet::from([
+            RuntimeFeature::Excel,
+            RuntimeFeature::Sql,
+            RuntimeFeature::Refresh,
+            RuntimeFeature::Plotting("matplotlib".to_string()),
+            RuntimeFeature::Requests("requests".to_string()),
+        ]));
+    }
+}
@@ -2,9 +2,10 @@
 //
 // Top-level structure representing a complete WTLang program in IR form
 
+use crate::intern::Atom;
 use crate::ir::nodes::*;
 use crate::symbols::SymbolTable;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 /// Complete IR representation of a WTLang program
 #[derive(Debug, Clone)]
@@ -14,7 +15,7 @@ pub struct IRModule {
     #[allow(dead_code)]
     pub symbols: SymbolTable,
     /// Type environment: maps variable names to their types
-    pub type_env: HashMap<String, crate::ir::types::Type>,
+    pub type_env: BTreeMap<Atom, crate::ir::types::Type>,
 }
 
 impl IRModule {
@@ -23,7 +24,7 @@ impl IRModule {
             name,
             items: Vec::new(),
             symbols: SymbolTable::new(),
-            type_env: HashMap::new(),
+            type_env: BTreeMap::new(),
         }
     }
     
@@ -31,7 +32,7 @@ impl IRModule {
     pub fn find_table(&self, name: &str) -> Option<&crate::ir::types::TableSchema> {
         for item in &self.items {
             if let IRItem::TableDef { name: table_name, schema, .. } = item {
-                if table_name == name {
+                if *table_name == name {
                     return Some(schema);
                 }
             }
@@ -43,7 +44,7 @@ impl IRModule {
     pub fn find_function(&self, name: &str) -> Option<(&Vec<Param>, &crate::ir::types::Type)> {
         for item in &self.items {
             if let IRItem::FunctionDef { name: fn_name, params, return_type, .. } = item {
-                if fn_name == name {
+                if *fn_name == name {
                     return Some((params, return_type));
                 }
             }
@@ -55,7 +56,7 @@ impl IRModule {
     pub fn find_page(&self, name: &str) -> Option<&Vec<IRNode>> {
         for item in &self.items {
             if let IRItem::PageDef { name: page_name, body, .. } = item {
-                if page_name == name {
+                if *page_name == name {
                     return Some(body);
                 }
             }
@@ -3,6 +3,8 @@
 // Platform-independent representation of program structure
 
 use serde::{Deserialize, Serialize};
+use crate::ast::{NodeId, Span};
+use crate::intern::Atom;
 use crate::ir::types::*;
 use std::path::PathBuf;
 
@@ -12,6 +14,9 @@ pub struct SourceRange {
     pub file: PathBuf,
     pub start: Position,
     pub end: Position,
+    /// AST node the IR was lowered from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<NodeId>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -26,12 +31,45 @@ impl Position {
     }
 }
 
+impl SourceRange {
+    /// Range of the AST node `id`, which covers `span`
+    pub fn node(id: NodeId, span: Span) -> Self {
+        SourceRange { node: Some(id), ..SourceRange::from(span) }
+    }
+    
+    /// The same range in the program's `item`th item, `lines` lines further
+    /// down; ranges without a position, on line 0, keep it
+    pub fn moved(&self, item: u32, lines: isize) -> Self {
+        let move_position = |position: Position| match position.line {
+            0 => position,
+            line => Position::new(line.saturating_add_signed(lines), position.column),
+        };
+        SourceRange {
+            file: self.file.clone(),
+            start: move_position(self.start),
+            end: move_position(self.end),
+            node: self.node.map(|node| node.in_item(item)),
+        }
+    }
+}
+
+impl From<Span> for SourceRange {
+    fn from(span: Span) -> Self {
+        SourceRange {
+            start: Position::new(span.line, span.column),
+            end: Position::new(span.end_line, span.end_column),
+            ..SourceRange::default()
+        }
+    }
+}
+
 impl Default for SourceRange {
     fn default() -> Self {
         SourceRange {
             file: PathBuf::from("<unknown>"),
             start: Position::new(0, 0),
             end: Position::new(0, 0),
+            node: None,
         }
     }
 }
@@ -48,13 +86,13 @@ pub struct TargetLocation {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum IRItem {
     TableDef {
-        name: String,
+        name: Atom,
         schema: TableSchema,
         source_loc: SourceRange,
     },
     
     FunctionDef {
-        name: String,
+        name: Atom,
         params: Vec<Param>,
         return_type: Type,
         body: Vec<IRNode>,
@@ -64,7 +102,7 @@ pub enum IRItem {
     },
     
     PageDef {
-        name: String,
+        name: Atom,
         body: Vec<IRNode>,
         source_loc: SourceRange,
     },
@@ -74,11 +112,58 @@ pub enum IRItem {
         body: Vec<IRNode>,
         source_loc: SourceRange,
     },
+    
+    /// Rows of a table users may see, applied wherever the table is loaded
+    Restriction {
+        table: Atom,
+        condition: IRExpr,
+        source_loc: SourceRange,
+    },
+    
+    /// Table computed from other tables, typed by the table it is derived from
+    DerivedTable {
+        name: Atom,
+        value: IRExpr,
+        materialize: Materialize,
+        source_loc: SourceRange,
+    },
+    
+    /// Variable kept in the session, read and written by every page
+    SharedVariable {
+        name: Atom,
+        ty: Type,
+        value: IRExpr,
+        source_loc: SourceRange,
+    },
+    
+    /// Value known when compiling, computed from the constants before it
+    Constant {
+        name: Atom,
+        ty: Type,
+        value: Literal,
+        source_loc: SourceRange,
+    },
+    
+    /// Statements run with a table's rows, bound to `param`, when they are
+    /// loaded, edited or saved
+    Hook {
+        event: HookEvent,
+        table: Atom,
+        param: Atom,
+        body: Vec<IRNode>,
+        source_loc: SourceRange,
+    },
+    
+    /// Settings of the whole app from its `config` block, by name
+    Config {
+        settings: Vec<(Atom, String)>,
+        source_loc: SourceRange,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Param {
-    pub name: String,
+    pub name: Atom,
     pub ty: Type,
 }
 
@@ -102,19 +187,26 @@ pub enum IRNode {
     },
     
     ShowText {
-        text: String,
+        text: DisplayText,
         style: TextStyle,
         source_loc: SourceRange,
     },
     
     Button {
-        label: String,
+        label: DisplayText,
         body: Vec<IRNode>,
         source_loc: SourceRange,
     },
     
     Section {
-        title: String,
+        title: DisplayText,
+        body: Vec<IRNode>,
+        source_loc: SourceRange,
+    },
+    
+    /// Body run only once the user confirms the message
+    Confirm {
+        message: DisplayText,
         body: Vec<IRNode>,
         source_loc: SourceRange,
     },
@@ -127,8 +219,15 @@ pub enum IRNode {
         source_loc: SourceRange,
     },
     
+    /// Runs the first arm matching the value
+    Match {
+        value: Box<IRExpr>,
+        arms: Vec<MatchArm<Vec<IRNode>>>,
+        source_loc: SourceRange,
+    },
+    
     Loop {
-        variable: String,
+        variable: Atom,
         iterable: Box<IRExpr>,
         body: Vec<IRNode>,
         source_loc: SourceRange,
@@ -136,14 +235,14 @@ pub enum IRNode {
     
     // Variables
     Binding {
-        name: String,
+        name: Atom,
         ty: Type,
         value: Option<Box<IRExpr>>,
         source_loc: SourceRange,
     },
     
     Assignment {
-        target: String,
+        target: Atom,
         value: Box<IRExpr>,
         source_loc: SourceRange,
     },
@@ -159,6 +258,147 @@ pub enum IRNode {
         value: Option<Box<IRExpr>>,
         source_loc: SourceRange,
     },
+    
+    // Testing
+    Assert {
+        condition: Box<IRExpr>,
+        source_loc: SourceRange,
+    },
+    
+    /// Replace a table's loader or a function while a test runs
+    Mock {
+        target: Atom,
+        value: Box<IRExpr>,
+        source_loc: SourceRange,
+    },
+    
+    /// Rerun the page every `seconds`
+    Refresh {
+        seconds: u64,
+        source_loc: SourceRange,
+    },
+    
+    /// Python code passed through as written
+    Python {
+        /// Variables the code reads
+        inputs: Vec<CodeVariable>,
+        /// Variables the code sets
+        outputs: Vec<CodeVariable>,
+        code: String,
+        source_loc: SourceRange,
+    },
+}
+
+impl IRItem {
+    /// The same item lowered from the program's `item`th item, `lines`
+    /// lines further down, for an item that an edit above it moved without
+    /// changing it
+    pub fn moved(&self, item: u32, lines: isize) -> IRItem {
+        let mut moved = self.clone();
+        let (source_loc, body) = match &mut moved {
+            IRItem::FunctionDef { source_loc, body, .. }
+            | IRItem::PageDef { source_loc, body, .. }
+            | IRItem::TestDef { source_loc, body, .. }
+            | IRItem::Hook { source_loc, body, .. } => (source_loc, Some(body)),
+            IRItem::TableDef { source_loc, .. }
+            | IRItem::Restriction { source_loc, .. }
+            | IRItem::DerivedTable { source_loc, .. }
+            | IRItem::SharedVariable { source_loc, .. }
+            | IRItem::Constant { source_loc, .. }
+            | IRItem::Config { source_loc, .. } => (source_loc, None),
+        };
+        *source_loc = source_loc.moved(item, lines);
+        for node in body.into_iter().flatten() {
+            node.relocate(item, lines);
+        }
+        moved
+    }
+}
+
+impl IRNode {
+    /// Move the statement and those nested in it, as `IRItem::moved` does
+    fn relocate(&mut self, item: u32, lines: isize) {
+        let bodies: Vec<&mut Vec<IRNode>> = match self {
+            IRNode::Button { body, .. }
+            | IRNode::Section { body, .. }
+            | IRNode::Confirm { body, .. }
+            | IRNode::Loop { body, .. } => vec![body],
+            IRNode::Conditional { then_branch, else_branch, .. } => {
+                std::iter::once(then_branch).chain(else_branch.as_mut()).collect()
+            }
+            IRNode::Match { arms, .. } => arms.iter_mut().map(|arm| &mut arm.body).collect(),
+            IRNode::ShowTable { .. } | IRNode::ShowText { .. } | IRNode::Binding { .. }
+            | IRNode::Assignment { .. } | IRNode::ExprStmt { .. } | IRNode::Return { .. }
+            | IRNode::Assert { .. } | IRNode::Mock { .. } | IRNode::Refresh { .. }
+            | IRNode::Python { .. } => Vec::new(),
+        };
+        for node in bodies.into_iter().flatten() {
+            node.relocate(item, lines);
+        }
+        let source_loc = match self {
+            IRNode::ShowTable { source_loc, .. } |
+            IRNode::ShowText { source_loc, .. } |
+            IRNode::Button { source_loc, .. } |
+            IRNode::Section { source_loc, .. } |
+            IRNode::Confirm { source_loc, .. } |
+            IRNode::Conditional { source_loc, .. } |
+            IRNode::Match { source_loc, .. } |
+            IRNode::Loop { source_loc, .. } |
+            IRNode::Binding { source_loc, .. } |
+            IRNode::Assignment { source_loc, .. } |
+            IRNode::ExprStmt { source_loc, .. } |
+            IRNode::Return { source_loc, .. } |
+            IRNode::Assert { source_loc, .. } |
+            IRNode::Mock { source_loc, .. } |
+            IRNode::Refresh { source_loc, .. } |
+            IRNode::Python { source_loc, .. } => source_loc,
+        };
+        *source_loc = source_loc.moved(item, lines);
+    }
+    
+    /// Where the statement was written
+    pub fn source_loc(&self) -> &SourceRange {
+        match self {
+            IRNode::ShowTable { source_loc, .. } |
+            IRNode::ShowText { source_loc, .. } |
+            IRNode::Button { source_loc, .. } |
+            IRNode::Section { source_loc, .. } |
+            IRNode::Confirm { source_loc, .. } |
+            IRNode::Conditional { source_loc, .. } |
+            IRNode::Match { source_loc, .. } |
+            IRNode::Loop { source_loc, .. } |
+            IRNode::Binding { source_loc, .. } |
+            IRNode::Assignment { source_loc, .. } |
+            IRNode::ExprStmt { source_loc, .. } |
+            IRNode::Return { source_loc, .. } |
+            IRNode::Assert { source_loc, .. } |
+            IRNode::Mock { source_loc, .. } |
+            IRNode::Refresh { source_loc, .. } |
+            IRNode::Python { source_loc, .. } => source_loc,
+        }
+    }
+}
+
+/// A variable of the program read or set by a `python` block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeVariable {
+    /// Name of the variable in the block's code
+    pub name: Atom,
+    pub variable: Atom,
+    pub ty: Type,
+}
+
+/// An arm of a `match`, taken when the value equals one of its patterns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchArm<T> {
+    pub patterns: Vec<MatchPattern>,
+    pub body: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MatchPattern {
+    Literal(IRExpr),
+    Wildcard,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -167,12 +407,29 @@ pub struct FilterSpec {
     pub mode: FilterMode,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A key of `sort by`: a column, or an expression of the row's columns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SortSpec {
-    pub column: String,
+    pub key: IRExpr,
     pub ascending: bool,
 }
 
+/// Text shown on a page, either as written, with the values of its
+/// embedded expressions, or translated at runtime
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DisplayText {
+    Literal(String),
+    Interpolated(Vec<TextSegment>),
+    Translated { key: String },
+}
+
+/// A piece of interpolated text: text as written, or a typed expression
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TextSegment {
+    Text(String),
+    Expr(IRExpr),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TextStyle {
     Title,
@@ -189,7 +446,7 @@ pub enum IRExpr {
     },
     
     Variable {
-        name: String,
+        name: Atom,
         ty: Type,
     },
     
@@ -206,15 +463,22 @@ pub enum IRExpr {
         ty: Type,
     },
     
+    Between {
+        value: Box<IRExpr>,
+        low: Box<IRExpr>,
+        high: Box<IRExpr>,
+        ty: Type,
+    },
+    
     FunctionCall {
-        function: String,
+        function: Atom,
         args: Vec<IRExpr>,
         ty: Type,
     },
     
     FieldAccess {
         object: Box<IRExpr>,
-        field: String,
+        field: Atom,
         ty: Type,
     },
     
@@ -231,7 +495,7 @@ pub enum IRExpr {
     },
     
     TableConstructor {
-        fields: Vec<(String, IRExpr)>,
+        fields: Vec<(Atom, IRExpr)>,
         ty: Type,
     },
     
@@ -241,7 +505,7 @@ pub enum IRExpr {
     },
     
     Lambda {
-        params: Vec<String>,
+        params: Vec<Atom>,
         body: Box<IRExpr>,
         ty: Type,
     },
@@ -261,7 +525,26 @@ pub enum IRExpr {
     
     ColumnSelect {
         table: Box<IRExpr>,
-        columns: Vec<String>,
+        columns: Vec<Atom>,
+        ty: Type,
+    },
+    
+    Rename {
+        table: Box<IRExpr>,
+        columns: Vec<(Atom, Atom)>,
+        ty: Type,
+    },
+    
+    DropColumns {
+        table: Box<IRExpr>,
+        columns: Vec<Atom>,
+        ty: Type,
+    },
+    
+    GroupBy {
+        table: Box<IRExpr>,
+        columns: Vec<Atom>,
+        having: Box<IRExpr>,
         ty: Type,
     },
     
@@ -287,8 +570,15 @@ pub enum IRExpr {
     // Reference navigation (automatic join/lookup)
     RefNavigation {
         object: Box<IRExpr>,
-        field: String,
-        target_table: String,
+        field: Atom,
+        target_table: Atom,
+        ty: Type,
+    },
+    
+    /// The body of the first arm matching the value
+    Match {
+        value: Box<IRExpr>,
+        arms: Vec<MatchArm<IRExpr>>,
         ty: Type,
     },
 }
@@ -300,6 +590,7 @@ impl IRExpr {
             IRExpr::Variable { ty, .. } |
             IRExpr::BinaryOp { ty, .. } |
             IRExpr::UnaryOp { ty, .. } |
+            IRExpr::Between { ty, .. } |
             IRExpr::FunctionCall { ty, .. } |
             IRExpr::FieldAccess { ty, .. } |
             IRExpr::Index { ty, .. } |
@@ -310,10 +601,14 @@ impl IRExpr {
             IRExpr::Where { ty, .. } |
             IRExpr::SortBy { ty, .. } |
             IRExpr::ColumnSelect { ty, .. } |
+            IRExpr::Rename { ty, .. } |
+            IRExpr::DropColumns { ty, .. } |
+            IRExpr::GroupBy { ty, .. } |
             IRExpr::Union { ty, .. } |
             IRExpr::Minus { ty, .. } |
             IRExpr::Intersect { ty, .. } |
-            IRExpr::RefNavigation { ty, .. } => ty,
+            IRExpr::RefNavigation { ty, .. } |
+            IRExpr::Match { ty, .. } => ty,
         }
     }
 }
@@ -324,6 +619,8 @@ pub enum Literal {
     Float(f64),
     String(String),
     Bool(bool),
+    Date(String),  // ISO date
+    Currency { amount: String, currency: String },  // Exact amount, as written
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -341,6 +638,7 @@ pub enum BinOp {
     Ge,
     And,
     Or,
+    In,
     
     // Set operations (used separately from Union/Minus/Intersect IRExpr variants)
     // These are for when we need to represent set ops as binary operations
@@ -353,6 +651,8 @@ pub enum BinOp {
 pub enum UnOp {
     Neg,
     Not,
+    IsNull,
+    IsNotNull,
 }
 
 // Conversion from AST to IR for operators
@@ -372,6 +672,7 @@ impl From<&crate::ast::BinaryOp> for BinOp {
             crate::ast::BinaryOp::GreaterThanEqual => BinOp::Ge,
             crate::ast::BinaryOp::And => BinOp::And,
             crate::ast::BinaryOp::Or => BinOp::Or,
+            crate::ast::BinaryOp::In => BinOp::In,
             crate::ast::BinaryOp::Union => BinOp::Union,
             crate::ast::BinaryOp::Minus => BinOp::SetMinus,
             crate::ast::BinaryOp::Intersect => BinOp::Intersect,
@@ -384,6 +685,8 @@ impl From<&crate::ast::UnaryOp> for UnOp {
         match op {
             crate::ast::UnaryOp::Not => UnOp::Not,
             crate::ast::UnaryOp::Negate => UnOp::Neg,
+            crate::ast::UnaryOp::IsNull => UnOp::IsNull,
+            crate::ast::UnaryOp::IsNotNull => UnOp::IsNotNull,
         }
     }
 }
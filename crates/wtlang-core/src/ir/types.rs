@@ -3,6 +3,8 @@
 // Fully resolved and type-checked type information for the IR
 
 use serde::{Deserialize, Serialize};
+use crate::ast::{ColumnChange, TableType};
+use crate::intern::Atom;
 use std::fmt;
 
 /// Fully resolved types in the IR
@@ -19,9 +21,15 @@ pub enum Type {
     /// Table with fully resolved schema
     Table(TableSchema),
     
+    /// One row of a table, from `first`, `last` or `single`
+    Row(TableSchema),
+    
+    /// Array of values of one type
+    Array(Box<Type>),
+    
     /// Filter specification
     Filter {
-        table_name: String,
+        table_name: Atom,
         mode: FilterMode,
     },
     
@@ -69,6 +77,8 @@ impl fmt::Display for Type {
             Type::Date => write!(f, "date"),
             Type::Currency => write!(f, "currency"),
             Type::Table(schema) => write!(f, "table<{}>", schema.name),
+            Type::Row(schema) => write!(f, "row<{}>", schema.name),
+            Type::Array(element) => write!(f, "{}[]", element),
             Type::Filter { table_name, mode } => write!(f, "filter<{}, {:?}>", table_name, mode),
             Type::Function { params, return_type } => {
                 write!(f, "(")?;
@@ -86,20 +96,35 @@ impl fmt::Display for Type {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TableSchema {
-    pub name: String,
+    pub name: Atom,
     pub fields: Vec<Field>,
     pub constraints: Vec<Constraint>,
+    /// Changes table operations made to the columns of the declared table
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<ColumnChange>,
 }
 
 impl TableSchema {
-    pub fn new(name: String) -> Self {
+    pub fn new(name: Atom) -> Self {
         TableSchema {
             name,
             fields: Vec::new(),
             constraints: Vec::new(),
+            changes: Vec::new(),
         }
     }
     
+    /// The declared table this is the schema of, unless table operations
+    /// changed its columns, so it no longer validates as that table
+    pub fn declared(&self) -> Option<Atom> {
+        self.changes.is_empty().then_some(self.name)
+    }
+    
+    /// The type of the table's values in the AST
+    pub fn table_type(&self) -> TableType {
+        TableType { name: self.name, changes: self.changes.clone() }
+    }
+    
     pub fn has_field(&self, field_name: &str) -> bool {
         self.fields.iter().any(|f| f.name == field_name)
     }
@@ -131,8 +156,12 @@ impl TableSchema {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Field {
-    pub name: String,
+    pub name: Atom,
     pub ty: FieldType,
+    /// CSV header the column is stored under, when it differs from the name
+    pub header: Option<String>,
+    /// Table and field its values refer to, from `references Table.field`
+    pub references: Option<(Atom, Atom)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -144,7 +173,7 @@ pub enum FieldType {
     Date,
     Currency,
     Ref {
-        table_name: String,
+        table_name: Atom,
     },
 }
 
@@ -164,9 +193,24 @@ impl fmt::Display for FieldType {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Constraint {
-    Unique(String),        // Field name
-    NonNull(String),       // Field name
-    PrimaryKey(String),    // Field name
+    Unique(Atom),        // Field name
+    NonNull(Atom),       // Field name
+    PrimaryKey(Atom),    // Field name
+}
+
+impl Constraint {
+    /// The field the constraint applies to
+    pub fn field(&self) -> Atom {
+        match self {
+            Constraint::Unique(field) | Constraint::NonNull(field) | Constraint::PrimaryKey(field) => *field,
+        }
+    }
+    
+    pub fn field_mut(&mut self) -> &mut Atom {
+        match self {
+            Constraint::Unique(field) | Constraint::NonNull(field) | Constraint::PrimaryKey(field) => field,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -175,6 +219,23 @@ pub enum FilterMode {
     Multi,
 }
 
+/// How a derived table is kept: computed per session, or in a CSV file
+/// refreshed daily or written when the app is built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Materialize {
+    Cached,
+    Daily,
+    OnBuild,
+}
+
+/// When a table's hook runs: after loading, on edits, or before saving
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum HookEvent {
+    Load,
+    Edit,
+    Save,
+}
+
 /// Convert AST type to IR type (without table resolution yet)
 impl From<&crate::ast::Type> for Type {
     fn from(ast_type: &crate::ast::Type) -> Self {
@@ -186,15 +247,23 @@ impl From<&crate::ast::Type> for Type {
             crate::ast::Type::Date => Type::Date,
             crate::ast::Type::Currency => Type::Currency,
             crate::ast::Type::Filter => Type::Error, // Will be resolved during semantic analysis
-            crate::ast::Type::Table(name) => {
+            crate::ast::Type::Table(table) => {
                 // Placeholder - will be resolved with actual schema during semantic analysis
-                Type::Table(TableSchema::new(name.clone()))
+                Type::Table(TableSchema::from(table))
             }
+            crate::ast::Type::Row(table) => Type::Row(TableSchema::from(table)),
+            crate::ast::Type::Array(element) => Type::Array(Box::new(Type::from(element.as_ref()))),
             crate::ast::Type::Ref(_) => Type::Error, // Will be resolved during semantic analysis
         }
     }
 }
 
+impl From<&TableType> for TableSchema {
+    fn from(table: &TableType) -> Self {
+        TableSchema { changes: table.changes.clone(), ..TableSchema::new(table.name) }
+    }
+}
+
 impl From<&crate::ast::FilterMode> for FilterMode {
     fn from(mode: &crate::ast::FilterMode) -> Self {
         match mode {
@@ -204,6 +273,26 @@ impl From<&crate::ast::FilterMode> for FilterMode {
     }
 }
 
+impl From<crate::ast::Materialize> for Materialize {
+    fn from(materialize: crate::ast::Materialize) -> Self {
+        match materialize {
+            crate::ast::Materialize::Cached => Materialize::Cached,
+            crate::ast::Materialize::Daily => Materialize::Daily,
+            crate::ast::Materialize::OnBuild => Materialize::OnBuild,
+        }
+    }
+}
+
+impl From<crate::ast::HookEvent> for HookEvent {
+    fn from(event: crate::ast::HookEvent) -> Self {
+        match event {
+            crate::ast::HookEvent::Load => HookEvent::Load,
+            crate::ast::HookEvent::Edit => HookEvent::Edit,
+            crate::ast::HookEvent::Save => HookEvent::Save,
+        }
+    }
+}
+
 impl From<&crate::ast::Type> for FieldType {
     fn from(ast_type: &crate::ast::Type) -> Self {
         match ast_type {
@@ -214,7 +303,7 @@ impl From<&crate::ast::Type> for FieldType {
             crate::ast::Type::Date => FieldType::Date,
             crate::ast::Type::Currency => FieldType::Currency,
             crate::ast::Type::Ref(table_name) => FieldType::Ref {
-                table_name: table_name.clone(),
+                table_name: *table_name,
             },
             _ => panic!("Cannot convert {:?} to FieldType", ast_type),
         }
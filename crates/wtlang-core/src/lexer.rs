@@ -1,5 +1,6 @@
 // Token types for the WTLang lexer
 use crate::errors::{ErrorCode, DiagnosticBag, Location};
+use crate::intern::Atom;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
@@ -10,6 +11,7 @@ pub enum TokenType {
     Subtitle,
     Button,
     Section,
+    Confirm,
     Text,
     Let,
     Function,
@@ -18,6 +20,11 @@ pub enum TokenType {
     Import,
     Test,
     Mock,
+    Restrict,
+    Shared,
+    OnLoad,
+    OnEdit,
+    OnSave,
     Assert,
     If,
     Else,
@@ -47,10 +54,13 @@ pub enum TokenType {
     IntLiteral(i64),
     FloatLiteral(f64),
     StringLiteral(String),
+    InterpolatedString(Vec<StringPart>),  // A string with `{expr}` placeholders
+    DateLiteral(String),  // `d"2024-01-15"`, holding the ISO date
+    CurrencyLiteral { amount: String, currency: String },  // `12.50 EUR` or `$1,234.56`, with the amount's digits as written
     BoolLiteral(bool),
     
     // Identifiers
-    Identifier(String),
+    Identifier(Atom),
     
     // Operators
     Plus,
@@ -87,6 +97,66 @@ pub enum TokenType {
     // Special
     Eof,
     Newline,
+    /// A `///` comment line, without its slashes and the space after them
+    DocComment(String),
+    /// A `#version` line, with the text after `#version`
+    Version(String),
+    /// The body of a `python { ... }` block, passed through as written
+    /// apart from the indentation its lines share
+    RawCode(String),
+    /// Text the lexer reported an error for, kept so that parsing can
+    /// continue past it
+    Invalid(String),
+}
+
+impl TokenType {
+    /// Spelling of a keyword that only has its meaning in some positions and
+    /// otherwise names a variable, column or table
+    ///
+    /// Words such as `text`, `date` and `key` are common CSV headers, so they
+    /// stay usable as column names.
+    pub fn contextual_keyword(&self) -> Option<&'static str> {
+        match self {
+            TokenType::Title => Some("title"),
+            TokenType::Subtitle => Some("subtitle"),
+            TokenType::Button => Some("button"),
+            TokenType::Section => Some("section"),
+            TokenType::Confirm => Some("confirm"),
+            TokenType::Text => Some("text"),
+            TokenType::Filter => Some("filter"),
+            TokenType::Single => Some("single"),
+            TokenType::Multi => Some("multi"),
+            TokenType::By => Some("by"),
+            TokenType::Asc => Some("asc"),
+            TokenType::Desc => Some("desc"),
+            TokenType::Key => Some("key"),
+            TokenType::Ref => Some("ref"),
+            TokenType::Date => Some("date"),
+            TokenType::Currency => Some("currency"),
+            TokenType::Number => Some("number"),
+            _ => None,
+        }
+    }
+}
+
+/// A piece of an interpolated string: text as written, or the source of an
+/// expression embedded with `{expr}` and the position of its `{`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    Code { source: String, line: usize, column: usize },
+}
+
+impl StringPart {
+    /// The string as written, with its placeholders
+    pub fn join(parts: &[StringPart]) -> String {
+        parts.iter()
+            .map(|part| match part {
+                StringPart::Text(text) => text.clone(),
+                StringPart::Code { source, .. } => format!("{{{}}}", source),
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -94,19 +164,79 @@ pub struct Token {
     pub token_type: TokenType,
     pub line: usize,
     pub column: usize,
+    // Position just past the token's last character
+    pub end_line: usize,
+    pub end_column: usize,
+    // Byte offsets of the token in the source, `end` being exclusive
+    pub start: usize,
+    pub end: usize,
 }
 
 impl Token {
     pub fn new(token_type: TokenType, line: usize, column: usize) -> Self {
-        Token { token_type, line, column }
+        Token { token_type, line, column, end_line: line, end_column: column, start: 0, end: 0 }
+    }
+
+    /// The token as written in the source it was read from
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
+
+/// Source text between tokens that tools such as a formatter keep
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub line: usize,
+    pub column: usize,
+    // Byte offsets in the source, `end` being exclusive
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriviaKind {
+    /// A `//` comment, with its slashes; trailing when it follows a token
+    /// on the same line rather than leading the next one
+    Comment { text: String, trailing: bool },
+    /// Empty lines between tokens or comments
+    BlankLines(usize),
+}
+
+impl Trivia {
+    /// The trivia in a byte range of the source, such as between two
+    /// tokens; `trivia` must be in source order, as the lexer collects it
+    pub fn between(trivia: &[Trivia], start: usize, end: usize) -> &[Trivia] {
+        let from = trivia.partition_point(|t| t.start < start);
+        let to = trivia.partition_point(|t| t.start < end);
+        &trivia[from..to.max(from)]
     }
 }
 
+/// A position in the input
+#[derive(Debug, Clone, Copy, Default)]
+struct Mark {
+    position: usize,
+    byte: usize,
+    line: usize,
+    column: usize,
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
     line: usize,
     column: usize,
+    // Byte offset of `position`
+    byte: usize,
+    // Where the current token starts
+    token_start: Mark,
+    // Comments and blank lines read so far, and whether a line ended since
+    // the last token
+    trivia: Vec<Trivia>,
+    after_newline: bool,
+    // Where the `{` opening the body of a `python` block is
+    raw_code_at: Option<usize>,
     diagnostics: DiagnosticBag,
     source: String,  // Keep source for context in error messages
 }
@@ -118,12 +248,31 @@ impl Lexer {
             position: 0,
             line: 1,
             column: 1,
+            byte: 0,
+            token_start: Mark::default(),
+            trivia: Vec::new(),
+            after_newline: true,
+            raw_code_at: None,
             diagnostics: DiagnosticBag::new(),
             source: input.to_string(),
         }
     }
 
     pub fn tokenize(&mut self) -> Result<Vec<Token>, DiagnosticBag> {
+        let (tokens, diagnostics) = self.tokenize_recovering();
+        if diagnostics.has_errors() {
+            Err(diagnostics)
+        } else {
+            Ok(tokens)
+        }
+    }
+    
+    /// Tokenize the whole input even if it has errors
+    ///
+    /// Text with an error becomes an `Invalid` token, which the parser
+    /// skips, so that later stages still report their errors in the same
+    /// pass.
+    pub fn tokenize_recovering(&mut self) -> (Vec<Token>, DiagnosticBag) {
         let mut tokens = Vec::new();
         
         while !self.is_at_end() {
@@ -133,21 +282,44 @@ impl Lexer {
             }
             
             match self.next_token() {
-                Ok(token) => tokens.push(token),
+                Ok(mut token) => {
+                    token.end_line = self.line;
+                    token.end_column = self.column;
+                    token.start = self.token_start.byte;
+                    token.end = self.byte;
+                    tokens.push(token);
+                    self.after_newline = false;
+                }
                 Err(_) => {
-                    // Error already added to diagnostics, continue to find more errors
-                    self.advance(); // Skip the problematic character
+                    // Error already added to diagnostics; skip at least the
+                    // problematic character and continue to find more errors
+                    let start = self.token_start;
+                    if self.position == start.position {
+                        self.advance();
+                    }
+                    let text = self.input[start.position..self.position].iter().collect();
+                    let mut token = Token::new(TokenType::Invalid(text), start.line, start.column);
+                    token.end_line = self.line;
+                    token.end_column = self.column;
+                    token.start = start.byte;
+                    token.end = self.byte;
+                    tokens.push(token);
+                    self.after_newline = false;
                 }
             }
         }
         
-        tokens.push(Token::new(TokenType::Eof, self.line, self.column));
+        let mut eof = Token::new(TokenType::Eof, self.line, self.column);
+        eof.start = self.byte;
+        eof.end = self.byte;
+        tokens.push(eof);
         
-        if self.diagnostics.has_errors() {
-            Err(self.diagnostics.clone())
-        } else {
-            Ok(tokens)
-        }
+        (tokens, self.diagnostics.clone())
+    }
+    
+    /// Comments and blank lines of the input tokenized so far, in source order
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
     }
     
     fn add_error(&mut self, code: ErrorCode, message: String, line: usize, column: usize) {
@@ -158,9 +330,26 @@ impl Lexer {
     fn next_token(&mut self) -> Result<Token, ()> {
         let start_line = self.line;
         let start_column = self.column;
+        self.token_start = self.mark();
         
         let ch = self.current_char();
         
+        // Code of another language, which is not tokenized
+        if self.raw_code_at == Some(self.position) {
+            return self.read_raw_code(start_line, start_column);
+        }
+        
+        // Doc comments, for the declaration that follows; `////` is a
+        // plain comment
+        if self.at_doc_comment() {
+            return Ok(self.read_doc_comment(start_line, start_column));
+        }
+        
+        // The language version the program is written for
+        if ch == '#' && self.input[self.position + 1..].starts_with(&['v', 'e', 'r', 's', 'i', 'o', 'n']) {
+            return Ok(self.read_version(start_line, start_column));
+        }
+        
         // Single-line comments
         if ch == '/' && self.peek() == Some('/') {
             self.skip_comment();
@@ -173,11 +362,23 @@ impl Lexer {
             return self.read_string();
         }
         
-        // Numbers
+        // Date literals
+        if ch == 'd' && self.peek() == Some('"') {
+            return self.read_date();
+        }
+        
+        // Numbers, and amounts followed by a currency code
         if ch.is_ascii_digit() {
             return self.read_number();
         }
         
+        // Amounts written after a currency symbol
+        if let Some(currency) = symbol_currency(ch) {
+            if self.peek().is_some_and(|next| next.is_ascii_digit()) {
+                return Ok(self.read_symbol_amount(currency));
+            }
+        }
+        
         // Identifiers and keywords
         if ch.is_alphabetic() || ch == '_' {
             return self.read_identifier();
@@ -306,9 +507,25 @@ impl Lexer {
         
         self.advance(); // Skip opening quote
         let mut value = String::new();
+        let mut parts = Vec::new();
         
         while !self.is_at_end() && self.current_char() != '"' {
-            if self.current_char() == '\\' {
+            if self.current_char() == '{' {
+                // A `{` without a closing `}` in the string is text, and
+                // `\{` always is
+                if let Some((code, length)) = self.embedded_code() {
+                    if !value.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut value)));
+                    }
+                    parts.push(StringPart::Code { source: code, line: self.line, column: self.column });
+                    for _ in 0..length {
+                        self.advance();
+                    }
+                    continue;
+                }
+                value.push('{');
+                self.advance();
+            } else if self.current_char() == '\\' {
                 self.advance();
                 if !self.is_at_end() {
                     let escaped = match self.current_char() {
@@ -339,7 +556,122 @@ impl Lexer {
         }
         
         self.advance(); // Skip closing quote
-        Ok(Token::new(TokenType::StringLiteral(value), start_line, start_column))
+        if parts.is_empty() {
+            return Ok(Token::new(TokenType::StringLiteral(value), start_line, start_column));
+        }
+        if !value.is_empty() {
+            parts.push(StringPart::Text(value));
+        }
+        Ok(Token::new(TokenType::InterpolatedString(parts), start_line, start_column))
+    }
+
+    /// The currency code after a number, such as the `EUR` of `12.50 EUR`:
+    /// three capital letters on the same line, consumed when present
+    fn currency_code(&mut self) -> Option<String> {
+        let mut offset = self.position;
+        while matches!(self.input.get(offset), Some(' ' | '\t')) {
+            offset += 1;
+        }
+        if offset == self.position {
+            return None;
+        }
+        let code: String = self.input.get(offset..offset + 3)?.iter().collect();
+        let ends = !self.input.get(offset + 3).is_some_and(|&c| c.is_alphanumeric() || c == '_');
+        if !ends || !code.chars().all(|c| c.is_ascii_uppercase()) {
+            return None;
+        }
+        while self.position < offset + 3 {
+            self.advance();
+        }
+        Some(code)
+    }
+
+    /// An amount after a currency symbol, whose thousands may be separated
+    /// by commas, such as `$1,234.56`
+    fn read_symbol_amount(&mut self, currency: &str) -> Token {
+        let start_line = self.line;
+        let start_column = self.column;
+        
+        self.advance(); // Skip the symbol
+        let mut amount = String::new();
+        let digit = |lexer: &Self, offset: usize| lexer.input.get(lexer.position + offset).is_some_and(|c| c.is_ascii_digit());
+        loop {
+            while digit(self, 0) {
+                amount.push(self.current_char());
+                self.advance();
+            }
+            // A comma separates thousands only when three digits follow
+            let thousands = self.current_char() == ',' && (1..=3).all(|offset| digit(self, offset)) && !digit(self, 4);
+            if !thousands {
+                break;
+            }
+            self.advance();
+        }
+        if self.current_char() == '.' && digit(self, 1) {
+            amount.push('.');
+            self.advance();
+            while digit(self, 0) {
+                amount.push(self.current_char());
+                self.advance();
+            }
+        }
+        Token::new(TokenType::CurrencyLiteral { amount, currency: currency.to_string() }, start_line, start_column)
+    }
+
+    fn read_date(&mut self) -> Result<Token, ()> {
+        let start_line = self.line;
+        let start_column = self.column;
+        
+        self.advance(); // Skip `d`
+        self.advance(); // Skip opening quote
+        let mut value = String::new();
+        while !self.is_at_end() && self.current_char() != '"' && self.current_char() != '\n' {
+            value.push(self.current_char());
+            self.advance();
+        }
+        
+        if self.is_at_end() || self.current_char() != '"' {
+            self.add_error(
+                ErrorCode::E1001,
+                "Unterminated date literal".to_string(),
+                start_line,
+                start_column
+            );
+            return Err(());
+        }
+        self.advance(); // Skip closing quote
+        
+        if !is_iso_date(&value) {
+            self.add_error(
+                ErrorCode::E1005,
+                format!("Invalid date literal d\"{}\"", value),
+                start_line,
+                start_column
+            );
+            return Err(());
+        }
+        Ok(Token::new(TokenType::DateLiteral(value), start_line, start_column))
+    }
+
+    /// The source of the expression in the `{...}` at the current position,
+    /// with its string's escapes undone, and the characters it spans
+    fn embedded_code(&self) -> Option<(String, usize)> {
+        let mut code = String::new();
+        let mut chars = self.input[self.position + 1..].iter();
+        let mut length = 1;
+        while let Some(&c) = chars.next() {
+            length += 1;
+            match c {
+                '}' => return Some((code, length)),
+                '"' | '\n' => return None,
+                '\\' => {
+                    code.push(*chars.next()?);
+                    length += 1;
+                }
+                _ => code.push(c),
+            }
+        }
+        None
     }
 
     fn read_number(&mut self) -> Result<Token, ()> {
@@ -349,15 +681,37 @@ impl Lexer {
         let mut value = String::new();
         let mut is_float = false;
         
-        while !self.is_at_end() && (self.current_char().is_ascii_digit() || self.current_char() == '.') {
-            if self.current_char() == '.' {
-                if is_float {
-                    break; // Second dot, stop here
-                }
-                is_float = true;
+        self.read_digits(&mut value)?;
+        if self.current_char() == '.' {
+            is_float = true;
+            value.push('.');
+            self.advance();
+            if self.current_char() == '_' {
+                return self.invalid_number("'_' must be between digits");
             }
-            value.push(self.current_char());
+            self.read_digits(&mut value)?;
+            if self.current_char() == '.' && self.peek().is_some_and(|next| next.is_ascii_digit()) {
+                return self.invalid_number("more than one decimal point");
+            }
+        }
+        
+        // Exponent, as in 1.5e6 or 2E-3
+        if matches!(self.current_char(), 'e' | 'E') {
+            is_float = true;
+            value.push('e');
             self.advance();
+            if matches!(self.current_char(), '+' | '-') {
+                value.push(self.current_char());
+                self.advance();
+            }
+            if !self.current_char().is_ascii_digit() {
+                return self.invalid_number("the exponent needs at least one digit");
+            }
+            self.read_digits(&mut value)?;
+        }
+        
+        if let Some(currency) = self.currency_code() {
+            return Ok(Token::new(TokenType::CurrencyLiteral { amount: value, currency }, start_line, start_column));
         }
         
         if is_float {
@@ -379,7 +733,7 @@ impl Lexer {
                 Err(_) => {
                     self.add_error(
                         ErrorCode::E1002,
-                        format!("Invalid integer '{}'", value),
+                        format!("Integer '{}' is too large", value),
                         start_line,
                         start_column
                     );
@@ -389,6 +743,29 @@ impl Lexer {
         }
     }
 
+    /// Read digits, which may be grouped with single underscores as in 1_000
+    fn read_digits(&mut self, value: &mut String) -> Result<(), ()> {
+        while self.current_char().is_ascii_digit() || self.current_char() == '_' {
+            if self.current_char() == '_' && !self.peek().is_some_and(|next| next.is_ascii_digit()) {
+                self.invalid_number("'_' must be between digits")?;
+            }
+            if self.current_char() != '_' {
+                value.push(self.current_char());
+            }
+            self.advance();
+        }
+        Ok(())
+    }
+    
+    /// Report a malformed number at the current character and skip the rest of it
+    fn invalid_number<T>(&mut self, message: &str) -> Result<T, ()> {
+        self.add_error(ErrorCode::E1002, format!("Invalid number: {}", message), self.line, self.column);
+        while self.current_char().is_alphanumeric() || matches!(self.current_char(), '_' | '.') {
+            self.advance();
+        }
+        Err(())
+    }
+    
     fn read_identifier(&mut self) -> Result<Token, ()> {
         let start_line = self.line;
         let start_column = self.column;
@@ -407,6 +784,7 @@ impl Lexer {
             "subtitle" => TokenType::Subtitle,
             "button" => TokenType::Button,
             "section" => TokenType::Section,
+            "confirm" => TokenType::Confirm,
             "text" => TokenType::Text,
             "let" => TokenType::Let,
             "function" => TokenType::Function,
@@ -415,6 +793,11 @@ impl Lexer {
             "import" => TokenType::Import,
             "test" => TokenType::Test,
             "mock" => TokenType::Mock,
+            "restrict" => TokenType::Restrict,
+            "shared" => TokenType::Shared,
+            "on_load" => TokenType::OnLoad,
+            "on_edit" => TokenType::OnEdit,
+            "on_save" => TokenType::OnSave,
             "assert" => TokenType::Assert,
             "if" => TokenType::If,
             "else" => TokenType::Else,
@@ -439,29 +822,188 @@ impl Lexer {
             "number" => TokenType::Number,
             "true" => TokenType::BoolLiteral(true),
             "false" => TokenType::BoolLiteral(false),
-            _ => TokenType::Identifier(value),
+            _ => TokenType::Identifier(Atom::new(&value)),
         };
         
+        // `python` starting a statement may start a block of Python code
+        if value == "python" && self.after_newline {
+            self.raw_code_at = self.find_raw_code();
+        }
+        
         Ok(Token::new(token_type, start_line, start_column))
     }
+    
+    /// Where the body of a `python` block starts, if the header after
+    /// `python` is an optional `(inputs)` and `-> (outputs)`, then `{`
+    fn find_raw_code(&self) -> Option<usize> {
+        let skip_spaces = |mut i: usize| {
+            while matches!(self.input.get(i), Some(' ' | '\t')) {
+                i += 1;
+            }
+            i
+        };
+        // The `)` closing the `(` at `i`; bindings have no braces
+        let closing_paren = |mut i: usize| {
+            let mut depth = 0;
+            while let Some(&ch) = self.input.get(i) {
+                match ch {
+                    '(' => depth += 1,
+                    ')' if depth == 1 => return Some(i),
+                    ')' => depth -= 1,
+                    '{' | '}' => return None,
+                    _ => {}
+                }
+                i += 1;
+            }
+            None
+        };
+        
+        let mut i = skip_spaces(self.position);
+        if self.input.get(i) == Some(&'(') {
+            i = skip_spaces(closing_paren(i)? + 1);
+        }
+        if self.input[i..].starts_with(&['-', '>']) {
+            i = skip_spaces(i + 2);
+            if self.input.get(i) != Some(&'(') {
+                return None;
+            }
+            i = skip_spaces(closing_paren(i)? + 1);
+        }
+        (self.input.get(i) == Some(&'{')).then_some(i)
+    }
+    
+    /// The body of a `python` block, up to the `}` matching its `{`; braces
+    /// in Python strings and comments do not count
+    fn read_raw_code(&mut self, line: usize, column: usize) -> Result<Token, ()> {
+        self.raw_code_at = None;
+        self.advance();
+        let start = self.position;
+        let mut depth = 0;
+        let mut quote: Option<&[char]> = None;
+        loop {
+            if self.is_at_end() {
+                self.add_error(ErrorCode::E1004, "Unterminated python block, expected '}'".to_string(), line, column);
+                return Err(());
+            }
+            let rest = &self.input[self.position..];
+            if let Some(closing) = quote {
+                if rest.starts_with(closing) {
+                    (0..closing.len()).for_each(|_| self.advance());
+                    quote = None;
+                } else {
+                    if rest[0] == '\\' {
+                        self.advance();
+                    }
+                    self.advance();
+                }
+                continue;
+            }
+            if let Some(opening) = PYTHON_QUOTES.iter().find(|opening| rest.starts_with(opening)) {
+                (0..opening.len()).for_each(|_| self.advance());
+                quote = Some(opening);
+                continue;
+            }
+            match rest[0] {
+                '#' => {
+                    while !self.is_at_end() && self.current_char() != '\n' {
+                        self.advance();
+                    }
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        let code: String = self.input[start..self.position].iter().collect();
+        self.advance();
+        Ok(Token::new(TokenType::RawCode(dedent(&code)), line, column))
+    }
 
     fn skip_whitespace(&mut self) {
+        // Blank lines start the input, or follow the line break ending a
+        // line with content
+        let mut blank_start = (self.position == 0).then(|| self.mark());
+        let mut blank_lines = 0;
         while !self.is_at_end() {
             let ch = self.current_char();
             if ch == ' ' || ch == '\t' || ch == '\r' || ch == '\n' {
                 self.advance();
+                if ch == '\n' {
+                    self.after_newline = true;
+                    match blank_start {
+                        Some(_) => blank_lines += 1,
+                        None => blank_start = Some(self.mark()),
+                    }
+                }
             } else {
                 break;
             }
         }
+        
+        if let (Some(start), true) = (blank_start, blank_lines > 0 && !self.is_at_end()) {
+            self.trivia.push(Trivia {
+                kind: TriviaKind::BlankLines(blank_lines),
+                line: start.line,
+                column: start.column,
+                start: start.byte,
+                end: self.byte,
+            });
+        }
     }
 
     fn skip_comment(&mut self) {
+        let start = self.mark();
+        while !self.is_at_end() && self.current_char() != '\n' {
+            self.advance();
+        }
+        let text = self.input[start.position..self.position].iter().collect::<String>();
+        self.trivia.push(Trivia {
+            kind: TriviaKind::Comment { text: text.trim_end().to_string(), trailing: !self.after_newline },
+            line: start.line,
+            column: start.column,
+            start: start.byte,
+            end: self.byte,
+        });
+    }
+    
+    fn at_doc_comment(&self) -> bool {
+        let slashes = self.input[self.position..].iter().take_while(|&&ch| ch == '/').count();
+        slashes == 3
+    }
+
+    fn read_doc_comment(&mut self, line: usize, column: usize) -> Token {
+        for _ in 0..3 {
+            self.advance();
+        }
+        let start = self.position;
+        while !self.is_at_end() && self.current_char() != '\n' {
+            self.advance();
+        }
+        let text: String = self.input[start..self.position].iter().collect();
+        let text = text.strip_prefix(' ').unwrap_or(&text).trim_end().to_string();
+        Token::new(TokenType::DocComment(text), line, column)
+    }
+    
+    fn read_version(&mut self, line: usize, column: usize) -> Token {
+        for _ in 0.."#version".len() {
+            self.advance();
+        }
+        let start = self.position;
         while !self.is_at_end() && self.current_char() != '\n' {
             self.advance();
         }
+        let text: String = self.input[start..self.position].iter().collect();
+        Token::new(TokenType::Version(text.trim().to_string()), line, column)
+    }
+    
+    fn mark(&self) -> Mark {
+        Mark { position: self.position, byte: self.byte, line: self.line, column: self.column }
     }
 
+
     fn current_char(&self) -> char {
         if self.is_at_end() {
             '\0'
@@ -486,6 +1028,7 @@ impl Lexer {
             } else {
                 self.column += 1;
             }
+            self.byte += self.input[self.position].len_utf8();
             self.position += 1;
         }
     }
@@ -495,6 +1038,55 @@ impl Lexer {
     }
 }
 
+/// Quotes of Python strings, longest first
+const PYTHON_QUOTES: [&[char]; 4] = [&['"', '"', '"'], &['\'', '\'', '\''], &['"'], &['\'']];
+
+/// Code without the blank lines around it and the indentation its lines
+/// share, so that it can be indented where it is generated
+fn dedent(code: &str) -> String {
+    let lines: Vec<&str> = code.lines().map(str::trim_end).collect();
+    let first = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let last = lines.iter().rposition(|line| !line.is_empty()).map_or(first, |last| last + 1);
+    let lines = &lines[first..last];
+    let indent = lines.iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines.iter()
+        .map(|line| line.get(indent..).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The currency code of a currency symbol that can start an amount
+fn symbol_currency(symbol: char) -> Option<&'static str> {
+    match symbol {
+        '$' => Some("USD"),
+        '\u{20ac}' => Some("EUR"),
+        '\u{a3}' => Some("GBP"),
+        '\u{a5}' => Some("JPY"),
+        _ => None,
+    }
+}
+
+/// Whether text is a date that exists, written as `YYYY-MM-DD`
+fn is_iso_date(text: &str) -> bool {
+    let [year, month, day] = text.split('-').collect::<Vec<_>>()[..] else { return false };
+    if [(year, 4), (month, 2), (day, 2)].iter().any(|(part, len)| part.len() != *len || !part.bytes().all(|b| b.is_ascii_digit())) {
+        return false;
+    }
+    let (Ok(year), Ok(month), Ok(day)) = (year.parse::<u32>(), month.parse::<u32>(), day.parse::<u32>()) else { return false };
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -507,7 +1099,7 @@ mod tests {
         assert_eq!(tokens[0].token_type, TokenType::Page);
         assert_eq!(tokens[1].token_type, TokenType::Table);
         assert_eq!(tokens[2].token_type, TokenType::From);
-        assert_eq!(tokens[3].token_type, TokenType::Identifier("display".to_string()));
+        assert_eq!(tokens[3].token_type, TokenType::Identifier(Atom::new("display")));
         assert_eq!(tokens[4].token_type, TokenType::Button);
         assert_eq!(tokens[5].token_type, TokenType::Section);
     }
@@ -528,10 +1120,10 @@ mod tests {
         let mut lexer = Lexer::new("myVar my_var MyClass _private");
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens[0].token_type, TokenType::Identifier("myVar".to_string()));
-        assert_eq!(tokens[1].token_type, TokenType::Identifier("my_var".to_string()));
-        assert_eq!(tokens[2].token_type, TokenType::Identifier("MyClass".to_string()));
-        assert_eq!(tokens[3].token_type, TokenType::Identifier("_private".to_string()));
+        assert_eq!(tokens[0].token_type, TokenType::Identifier(Atom::new("myVar")));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier(Atom::new("my_var")));
+        assert_eq!(tokens[2].token_type, TokenType::Identifier(Atom::new("MyClass")));
+        assert_eq!(tokens[3].token_type, TokenType::Identifier(Atom::new("_private")));
     }
 
     #[test]
@@ -544,6 +1136,36 @@ mod tests {
         assert_eq!(tokens[2].token_type, TokenType::IntLiteral(1000));
     }
 
+    #[test]
+    fn test_number_separators_and_exponents() {
+        let tokens = Lexer::new("1_000_000 1.5e6 2E-3 1_0.2_5 4e+2").tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token_type, TokenType::IntLiteral(1_000_000));
+        assert_eq!(tokens[1].token_type, TokenType::FloatLiteral(1.5e6));
+        assert_eq!(tokens[2].token_type, TokenType::FloatLiteral(2e-3));
+        assert_eq!(tokens[3].token_type, TokenType::FloatLiteral(10.25));
+        assert_eq!(tokens[4].token_type, TokenType::FloatLiteral(400.0));
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        for (source, message, column) in [
+            ("1__0", "'_' must be between digits", 2),
+            ("100_", "'_' must be between digits", 4),
+            ("1_.5", "'_' must be between digits", 2),
+            ("1.5e", "the exponent needs at least one digit", 5),
+            ("2e-x", "the exponent needs at least one digit", 4),
+            ("12.34.56", "more than one decimal point", 6),
+            ("99999999999999999999", "Integer '99999999999999999999' is too large", 1),
+        ] {
+            let diagnostics = Lexer::new(source).tokenize().unwrap_err();
+            let error = &diagnostics.diagnostics()[0];
+            assert_eq!(error.code, ErrorCode::E1002, "{}", source);
+            assert!(error.message.contains(message), "{}: {}", source, error.message);
+            assert_eq!(error.location.column, column, "{}", source);
+        }
+    }
+
     #[test]
     fn test_float_literals() {
         let mut lexer = Lexer::new("3.14 0.5 10.0");
@@ -569,6 +1191,24 @@ mod tests {
         assert_eq!(tokens[2].token_type, TokenType::StringLiteral("".to_string()));
     }
 
+    #[test]
+    fn test_interpolated_strings() {
+        let mut lexer = Lexer::new(r#""Total: ${total} of {count(orders)}" "{x}" "a { b" "\{x}""#);
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token_type, TokenType::InterpolatedString(vec![
+            StringPart::Text("Total: $".to_string()),
+            StringPart::Code { source: "total".to_string(), line: 1, column: 10 },
+            StringPart::Text(" of ".to_string()),
+            StringPart::Code { source: "count(orders)".to_string(), line: 1, column: 21 },
+        ]));
+        assert_eq!(tokens[1].token_type, TokenType::InterpolatedString(vec![
+            StringPart::Code { source: "x".to_string(), line: 1, column: 39 },
+        ]));
+        assert_eq!(tokens[2].token_type, TokenType::StringLiteral("a { b".to_string()));
+        assert_eq!(tokens[3].token_type, TokenType::StringLiteral("{x}".to_string()));
+    }
+
     #[test]
     fn test_boolean_literals() {
         let mut lexer = Lexer::new("true false");
@@ -636,6 +1276,63 @@ mod tests {
         assert_eq!(tokens[1].column, 1);
     }
 
+    #[test]
+    fn test_trivia() {
+        let source = "// Header\n\npage Home { // opens\n    title \"x\"\n\n\n    // body\n}\n";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let trivia = lexer.trivia();
+        
+        let kinds: Vec<&TriviaKind> = trivia.iter().map(|t| &t.kind).collect();
+        assert_eq!(kinds, vec![
+            &TriviaKind::Comment { text: "// Header".to_string(), trailing: false },
+            &TriviaKind::BlankLines(1),
+            &TriviaKind::Comment { text: "// opens".to_string(), trailing: true },
+            &TriviaKind::BlankLines(2),
+            &TriviaKind::Comment { text: "// body".to_string(), trailing: false },
+        ]);
+        assert_eq!((trivia[3].line, trivia[3].column), (5, 1));
+        assert_eq!(&source[trivia[2].start..trivia[2].end], "// opens");
+        
+        // Between `{` and `title`, and before the closing `}`
+        let between = Trivia::between(trivia, tokens[2].end, tokens[3].start);
+        assert_eq!(between, &trivia[2..3]);
+        let before_close = Trivia::between(trivia, tokens[4].end, tokens[5].start);
+        assert_eq!(before_close, &trivia[3..5]);
+        assert!(Trivia::between(trivia, tokens[0].end, tokens[1].start).is_empty());
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let source = "/// Orders\n///   placed online\n//// not a doc\ntable Order {}";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::DocComment("Orders".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::DocComment("  placed online".to_string()));
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 1));
+        assert_eq!(tokens[2].token_type, TokenType::Table);
+        assert_eq!(lexer.trivia().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_tokens() {
+        let source = "x # y & z\n1.5.6 w";
+        let (tokens, diagnostics) = Lexer::new(source).tokenize_recovering();
+        
+        assert_eq!(diagnostics.error_count(), 3);
+        let types: Vec<TokenType> = tokens.into_iter().map(|t| t.token_type).collect();
+        assert_eq!(types, vec![
+            TokenType::Identifier(Atom::new("x")),
+            TokenType::Invalid("#".to_string()),
+            TokenType::Identifier(Atom::new("y")),
+            TokenType::Invalid("&".to_string()),
+            TokenType::Identifier(Atom::new("z")),
+            TokenType::Invalid("1.5.6".to_string()),
+            TokenType::Identifier(Atom::new("w")),
+            TokenType::Eof,
+        ]);
+    }
+
     #[test]
     fn test_unterminated_string() {
         let mut lexer = Lexer::new(r#""unterminated"#);
@@ -647,13 +1344,47 @@ mod tests {
         assert!(diag.format_all().contains("Unterminated string"));
     }
 
+    #[test]
+    fn test_date_literals() {
+        let mut lexer = Lexer::new(r#"d"2024-01-15" d"2024-02-29" d "x""#);
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token_type, TokenType::DateLiteral("2024-01-15".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::DateLiteral("2024-02-29".to_string()));
+        assert_eq!(tokens[2].token_type, TokenType::Identifier(Atom::new("d")));
+        assert_eq!(tokens[3].token_type, TokenType::StringLiteral("x".to_string()));
+        
+        for invalid in [r#"d"2023-02-29""#, r#"d"2024-13-01""#, r#"d"2024-1-15""#, r#"d"15/01/2024""#] {
+            let diag = Lexer::new(invalid).tokenize().unwrap_err();
+            assert_eq!(diag.diagnostics()[0].code, ErrorCode::E1005, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_currency_literals() {
+        let currency = |amount: &str, currency: &str| TokenType::CurrencyLiteral { amount: amount.to_string(), currency: currency.to_string() };
+        let mut lexer = Lexer::new("12.50 EUR $1,234.56 \u{20ac}5 7 USDX 3\nGBP f($1,23)");
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token_type, currency("12.50", "EUR"));
+        assert_eq!(tokens[1].token_type, currency("1234.56", "USD"));
+        assert_eq!(tokens[2].token_type, currency("5", "EUR"));
+        assert_eq!(tokens[3].token_type, TokenType::IntLiteral(7));
+        assert_eq!(tokens[4].token_type, TokenType::Identifier(Atom::new("USDX")));
+        assert_eq!(tokens[5].token_type, TokenType::IntLiteral(3));
+        assert_eq!(tokens[6].token_type, TokenType::Identifier(Atom::new("GBP")));
+        assert_eq!(tokens[9].token_type, currency("1", "USD"));
+        assert_eq!(tokens[10].token_type, TokenType::Comma);
+        assert_eq!(tokens[11].token_type, TokenType::IntLiteral(23));
+    }
+
     #[test]
     fn test_complex_expression() {
         let mut lexer = Lexer::new("let x: number = 42 + 3.14");
         let tokens = lexer.tokenize().unwrap();
         
         assert_eq!(tokens[0].token_type, TokenType::Let);
-        assert_eq!(tokens[1].token_type, TokenType::Identifier("x".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier(Atom::new("x")));
         assert_eq!(tokens[2].token_type, TokenType::Colon);
         assert_eq!(tokens[3].token_type, TokenType::Number);
         assert_eq!(tokens[4].token_type, TokenType::Assign);
@@ -671,9 +1402,9 @@ mod tests {
         let tokens = lexer.tokenize().unwrap();
         
         assert_eq!(tokens[0].token_type, TokenType::Function);
-        assert_eq!(tokens[1].token_type, TokenType::Identifier("add".to_string()));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier(Atom::new("add")));
         assert_eq!(tokens[2].token_type, TokenType::LeftParen);
-        assert_eq!(tokens[3].token_type, TokenType::Identifier("x".to_string()));
+        assert_eq!(tokens[3].token_type, TokenType::Identifier(Atom::new("x")));
         assert_eq!(tokens[4].token_type, TokenType::Colon);
         assert_eq!(tokens[5].token_type, TokenType::Number);
     }
@@ -710,6 +1441,20 @@ mod tests {
         assert_eq!(tokens[4].token_type, TokenType::Return);
     }
 
+    #[test]
+    fn test_token_offsets() {
+        let source = "let größe = \"x\"\n  // note\n  total";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text(source)).collect();
+        assert_eq!(texts, vec!["let", "größe", "=", "\"x\"", "total", ""]);
+        assert_eq!((tokens[1].start, tokens[1].end), (4, 11));
+        assert_eq!((tokens[1].column, tokens[1].end_column), (5, 10));
+        assert_eq!((tokens[4].line, tokens[4].column, tokens[4].end_line, tokens[4].end_column), (3, 3, 3, 8));
+        assert_eq!(tokens[5].start, source.len());
+    }
+
     #[test]
     fn test_empty_input() {
         let mut lexer = Lexer::new("");
@@ -1,19 +1,43 @@
 // WTLang Core Library
 // Shared components for compiler, LSP, and other tools
 
+pub mod intern;
 pub mod lexer;
 pub mod ast;
 pub mod parser;
 pub mod symbols;
 pub mod semantics;
+pub mod type_map;
+pub mod incremental;
 pub mod errors;
+pub mod line_index;
+pub mod logging;
+pub mod directives;
+pub mod corpus;
+pub mod eval;
+pub mod interp;
 pub mod ir;
+pub mod version;
+pub mod plugins;
+pub mod template;
+pub mod modules;
 
 // Re-export commonly used types
-pub use lexer::{Lexer, Token, TokenType};
+pub use intern::Atom;
+pub use lexer::{Lexer, Token, TokenType, Trivia, TriviaKind};
 pub use ast::*;
 pub use parser::Parser;
 pub use symbols::{Symbol, SymbolTable, SymbolKind, SymbolError, ScopeKind};
-pub use semantics::{SemanticAnalyzer, SemanticError};
-pub use errors::{ErrorCode, Diagnostic, DiagnosticBag, Location, Severity};
+pub use semantics::{SemanticAnalyzer, SemanticError, ItemAnalysis};
+pub use type_map::TypeMap;
+pub use incremental::IncrementalAnalyzer;
+pub use errors::{ErrorCode, Explanation, Diagnostic, DiagnosticBag, Fix, TextEdit, apply_fixes, Level, LintLevels, Location, Severity};
+pub use directives::Directives;
+pub use line_index::{LineIndex, PositionEncoding};
+pub use logging::{LogFilter, LogFormat, Logger};
+pub use eval::{eval_const, eval_const_with, EvalError, Value};
+pub use interp::{DebugHook, Frame, Interpreter, Output, RuntimeError, RuntimeValue, TableValue};
 pub use ir::{IRModule, IRBuilder};
+pub use version::{Feature, LanguageVersion};
+pub use plugins::{BuiltinPlugin, ManifestBuiltin, PluginRegistry};
+pub use modules::{LoadedProgram, ModuleLoader, SourceFile};
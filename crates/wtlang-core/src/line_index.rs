@@ -0,0 +1,135 @@
+// Line and column conversion between WTLang and editor positions
+//
+// The lexer counts columns in characters, starting at 1. Editors count
+// offsets from 0 in code units of some encoding: LSP clients default to
+// UTF-16 and may offer UTF-8, so a non-ASCII identifier or string before a
+// diagnostic shifts it unless the column is converted.
+
+/// The unit an editor counts column offsets in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// Bytes of UTF-8
+    Utf8,
+    /// UTF-16 code units, the LSP default
+    #[default]
+    Utf16,
+    /// Characters, the same as WTLang columns
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Code units of `ch` in this encoding
+    pub fn len(self, ch: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => ch.len_utf8(),
+            PositionEncoding::Utf16 => ch.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// The lines of a source, for converting columns to and from offsets
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        LineIndex { lines: source.lines().collect() }
+    }
+
+    /// The text of a 1-based line, without its line ending
+    pub fn line(&self, line: usize) -> Option<&'a str> {
+        self.lines.get(line.checked_sub(1)?).copied()
+    }
+
+    /// The 0-based offset in `encoding` of a 1-based character column
+    ///
+    /// Columns past the end of the line, such as an error at the end of the
+    /// file, count one unit per missing character.
+    pub fn offset(&self, line: usize, column: usize, encoding: PositionEncoding) -> usize {
+        let column = column.saturating_sub(1);
+        let text = self.line(line).unwrap_or("");
+        let mut chars = 0;
+        let mut offset = 0;
+        for ch in text.chars().take(column) {
+            chars += 1;
+            offset += encoding.len(ch);
+        }
+        offset + (column - chars)
+    }
+
+    /// The 1-based character column of a 0-based offset in `encoding`
+    ///
+    /// An offset inside a character, such as between the two halves of a
+    /// UTF-16 surrogate pair, gives that character's column.
+    pub fn column(&self, line: usize, offset: usize, encoding: PositionEncoding) -> usize {
+        let text = self.line(line).unwrap_or("");
+        let mut units = 0;
+        let mut column = 1;
+        for ch in text.chars() {
+            let len = encoding.len(ch);
+            if units + len > offset {
+                return column;
+            }
+            units += len;
+            column += 1;
+        }
+        column + (offset - units)
+    }
+
+    /// Convert an offset from one encoding to another on the same line
+    pub fn convert(&self, line: usize, offset: usize, from: PositionEncoding, to: PositionEncoding) -> usize {
+        self.offset(line, self.column(line, offset, from), to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "let größe = 1\r\nlet x = \"😀\" + y\n";
+
+    #[test]
+    fn test_ascii_columns_are_offsets() {
+        let index = LineIndex::new("let x = 1");
+        for encoding in [PositionEncoding::Utf8, PositionEncoding::Utf16, PositionEncoding::Utf32] {
+            assert_eq!(index.offset(1, 5, encoding), 4);
+            assert_eq!(index.column(1, 4, encoding), 5);
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_identifier() {
+        let index = LineIndex::new(SOURCE);
+        assert_eq!(index.line(1), Some("let größe = 1"));
+        // `=` is the 11th character, after the two-byte `ö` and `ß`
+        assert_eq!(index.offset(1, 11, PositionEncoding::Utf8), 12);
+        assert_eq!(index.offset(1, 11, PositionEncoding::Utf16), 10);
+        assert_eq!(index.column(1, 12, PositionEncoding::Utf8), 11);
+        assert_eq!(index.column(1, 10, PositionEncoding::Utf16), 11);
+    }
+
+    #[test]
+    fn test_surrogate_pairs() {
+        let index = LineIndex::new(SOURCE);
+        // `+` is the 13th character, after the emoji in the string
+        assert_eq!(index.offset(2, 13, PositionEncoding::Utf8), 15);
+        assert_eq!(index.offset(2, 13, PositionEncoding::Utf16), 13);
+        assert_eq!(index.offset(2, 13, PositionEncoding::Utf32), 12);
+        assert_eq!(index.column(2, 13, PositionEncoding::Utf16), 13);
+        // Both halves of the pair map to the emoji
+        assert_eq!(index.column(2, 9, PositionEncoding::Utf16), 10);
+        assert_eq!(index.column(2, 10, PositionEncoding::Utf16), 10);
+        assert_eq!(index.convert(2, 13, PositionEncoding::Utf16, PositionEncoding::Utf8), 15);
+    }
+
+    #[test]
+    fn test_past_end_of_line() {
+        let index = LineIndex::new(SOURCE);
+        assert_eq!(index.offset(1, 16, PositionEncoding::Utf8), 17);
+        assert_eq!(index.column(1, 17, PositionEncoding::Utf8), 16);
+        assert_eq!(index.offset(3, 1, PositionEncoding::Utf16), 0);
+    }
+}
@@ -0,0 +1,319 @@
+// Log output of the WTLang tools
+//
+// The compiler and the language server log through `tracing`. This module
+// is the subscriber both install: it keeps the events `WTLANG_LOG` enables
+// and writes them to stderr as text or, with `WTLANG_LOG_FORMAT=json`, as
+// one JSON object per line. Stderr keeps logs apart from the compiler's
+// output and from the language server's protocol on stdout.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing::level_filters::LevelFilter;
+
+/// Environment variable with the log filter, e.g. `debug` or `wtlang_lsp=trace,warn`
+pub const LOG_ENV: &str = "WTLANG_LOG";
+/// Environment variable selecting `text` or `json` output
+pub const LOG_FORMAT_ENV: &str = "WTLANG_LOG_FORMAT";
+
+/// How log events are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `WARN wtc: message key=value`
+    #[default]
+    Text,
+    /// `{"timestamp":…,"level":"WARN","target":"wtc","message":…,"key":…}`
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Which events are logged: a default level and levels for targets
+///
+/// Written like `warn,wtlang_lsp=debug`: a bare level sets the default and
+/// `target=level` applies to the target and its submodules, the longest
+/// matching target winning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn new(default: LevelFilter) -> Self {
+        LogFilter { default, targets: Vec::new() }
+    }
+
+    /// Parse a filter, starting from `default` for what it does not set
+    pub fn parse(spec: &str, default: LevelFilter) -> Result<Self, String> {
+        let mut filter = LogFilter::new(default);
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = parse_level(level)?;
+                    filter.targets.push((target.trim().to_string(), level));
+                }
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        // Longest targets first, so the first match is the most specific
+        filter.targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(filter)
+    }
+
+    /// The level enabled for a target such as `wtlang_lsp::server`
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.targets.iter()
+            .find(|(prefix, _)| {
+                target == prefix || target.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    pub fn enabled(&self, target: &str, level: &Level) -> bool {
+        self.level(target) >= *level
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|(_, level)| *level).fold(self.default, std::cmp::max)
+    }
+}
+
+fn parse_level(name: &str) -> Result<LevelFilter, String> {
+    name.trim().parse::<LevelFilter>()
+        .map_err(|_| format!("invalid log level '{}'; expected off, error, warn, info, debug or trace", name.trim()))
+}
+
+/// Subscriber writing the enabled events to stderr
+pub struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+    next_span: AtomicU64,
+}
+
+impl Logger {
+    pub fn new(filter: LogFilter, format: LogFormat) -> Self {
+        Logger { filter, format, next_span: AtomicU64::new(1) }
+    }
+
+    /// A logger configured by `WTLANG_LOG` and `WTLANG_LOG_FORMAT`, logging
+    /// at `default` what the filter does not mention
+    pub fn from_env(default: LevelFilter) -> Result<Self, String> {
+        let filter = match std::env::var(LOG_ENV) {
+            Ok(spec) => LogFilter::parse(&spec, default).map_err(|e| format!("{}: {}", LOG_ENV, e))?,
+            Err(_) => LogFilter::new(default),
+        };
+        let format = match std::env::var(LOG_FORMAT_ENV) {
+            Ok(name) => LogFormat::parse(&name)
+                .ok_or_else(|| format!("{}: unknown format '{}'; expected text or json", LOG_FORMAT_ENV, name))?,
+            Err(_) => LogFormat::Text,
+        };
+        Ok(Logger::new(filter, format))
+    }
+
+    /// Use `format` instead of the one from the environment
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Install as the process' subscriber; later calls have no effect
+    pub fn install(self) {
+        let _ = tracing::subscriber::set_global_default(self);
+    }
+
+    /// The line written for an event
+    pub fn format_event(&self, event: &Event<'_>) -> String {
+        let metadata = event.metadata();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        match self.format {
+            LogFormat::Text => {
+                let mut line = format!("{:>5} {}: {}", metadata.level(), metadata.target(), fields.message);
+                for (name, value) in &fields.values {
+                    match value {
+                        Value::String(text) => line.push_str(&format!(" {}={}", name, text)),
+                        value => line.push_str(&format!(" {}={}", name, value)),
+                    }
+                }
+                line
+            }
+            LogFormat::Json => {
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |t| t.as_secs_f64());
+                let mut object = Map::new();
+                object.insert("timestamp".to_string(), Value::from(timestamp));
+                object.insert("level".to_string(), Value::from(metadata.level().as_str()));
+                object.insert("target".to_string(), Value::from(metadata.target()));
+                object.insert("message".to_string(), Value::from(fields.message));
+                for (name, value) in fields.values {
+                    object.insert(name, value);
+                }
+                Value::Object(object).to_string()
+            }
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.enabled(metadata.target(), metadata.level())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.filter.max_level())
+    }
+
+    // Spans are not logged, only given distinct ids
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let line = self.format_event(event);
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// The message and other fields of an event
+#[derive(Default)]
+struct Fields {
+    message: String,
+    values: Vec<(String, Value)>,
+}
+
+impl Fields {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = match value {
+                Value::String(text) => text,
+                value => value.to_string(),
+            };
+        } else {
+            self.values.push((field.name().to_string(), value));
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.insert(field, Value::from(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_filter_targets() {
+        let filter = LogFilter::parse("info, wtlang_lsp=debug, wtlang_lsp::server=off", LevelFilter::WARN).unwrap();
+        assert_eq!(filter.level("wtc"), LevelFilter::INFO);
+        assert_eq!(filter.level("wtlang_lsp"), LevelFilter::DEBUG);
+        assert_eq!(filter.level("wtlang_lsp::document"), LevelFilter::DEBUG);
+        assert_eq!(filter.level("wtlang_lsp::server"), LevelFilter::OFF);
+        assert_eq!(filter.level("wtlang_lspx"), LevelFilter::INFO);
+        assert!(filter.enabled("wtlang_lsp", &Level::DEBUG));
+        assert!(!filter.enabled("wtc", &Level::DEBUG));
+        assert_eq!(filter.max_level(), LevelFilter::DEBUG);
+
+        assert_eq!(LogFilter::parse("", LevelFilter::WARN).unwrap(), LogFilter::new(LevelFilter::WARN));
+        assert!(LogFilter::parse("loud", LevelFilter::WARN).unwrap_err().contains("'loud'"));
+    }
+
+    /// Collects the formatted lines instead of writing them
+    struct Capture {
+        logger: Logger,
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            self.logger.enabled(metadata)
+        }
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+            self.logger.new_span(attributes)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            self.lines.lock().unwrap().push(self.logger.format_event(event));
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn capture(format: LogFormat, log: impl FnOnce()) -> Vec<String> {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let capture = Capture {
+            logger: Logger::new(LogFilter::new(LevelFilter::INFO), format),
+            lines: lines.clone(),
+        };
+        tracing::subscriber::with_default(capture, log);
+        let lines = lines.lock().unwrap().clone();
+        lines
+    }
+
+    #[test]
+    fn test_text_format() {
+        let lines = capture(LogFormat::Text, || {
+            tracing::info!(target: "wtc", stage = "lex", tokens = 11u64, "stage finished");
+            tracing::debug!(target: "wtc", "not logged");
+        });
+        assert_eq!(lines, vec![" INFO wtc: stage finished stage=lex tokens=11"]);
+    }
+
+    #[test]
+    fn test_json_format() {
+        let lines = capture(LogFormat::Json, || {
+            tracing::warn!(target: "wtc", path = "locales", missing = 2u64, "no translation");
+        });
+        let event: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(event["level"], "WARN");
+        assert_eq!(event["target"], "wtc");
+        assert_eq!(event["message"], "no translation");
+        assert_eq!(event["path"], "locales");
+        assert_eq!(event["missing"], 2);
+        assert!(event["timestamp"].is_f64());
+    }
+}
@@ -0,0 +1,406 @@
+// Programs split across files
+//
+// `import "common.wt"` brings the items of another file into a program. The
+// loader reads each imported file, relative to the file importing it, and
+// merges its items before those of the importing file, so that the tables
+// and functions a file uses are declared first. A file imported by several
+// others is read once, and a file importing itself, directly or not, is an
+// error. The items of each file are numbered after those read before it, so
+// the nodes of the merged program keep distinct ids and the diagnostics
+// reported on them can name the file they come from.
+//
+// `import analytics from "analytics.wt"` imports a file as a module instead.
+// Its functions and constants are named `analytics.name`, both where they
+// are defined and where they are used, so they cannot clash with those of
+// other files, and its name is that of the module the backend generates
+// them into. A module only defines functions, external functions and
+// constants; the tables it uses come from files imported without a name.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use crate::ast::*;
+use crate::directives::Directives;
+use crate::errors::{Diagnostic, DiagnosticBag, ErrorCode, Location};
+use crate::intern::Atom;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::version::LanguageVersion;
+
+/// A file of a program, its text and the numbers of its items
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+    pub items: Range<u32>,
+}
+
+/// A program read from a file and the files it imports
+#[derive(Debug, Clone)]
+pub struct LoadedProgram {
+    /// Items of every file, those of imported files first
+    pub program: Program,
+    /// The files, in the order their items are merged; the file the
+    /// program was loaded from is last
+    pub files: Vec<SourceFile>,
+    /// Diagnostics found while reading the files: warnings, and the
+    /// lexical errors the parser reads past
+    pub diagnostics: DiagnosticBag,
+}
+
+impl LoadedProgram {
+    /// The file a node of the program comes from
+    pub fn file_of(&self, node: NodeId) -> Option<&Path> {
+        self.files.iter()
+            .find(|file| file.items.contains(&node.item))
+            .map(|file| file.path.as_path())
+    }
+
+    /// The diagnostics, each reported on a node with the file of the node
+    pub fn locate(&self, diagnostics: &DiagnosticBag) -> DiagnosticBag {
+        let mut located = DiagnosticBag::new();
+        for diagnostic in diagnostics.diagnostics() {
+            let mut diagnostic = diagnostic.clone();
+            if diagnostic.location.file.is_none() {
+                diagnostic.location.file = diagnostic.node
+                    .and_then(|node| self.file_of(node))
+                    .map(|path| path.display().to_string());
+            }
+            located.add(diagnostic);
+        }
+        located
+    }
+
+    /// The located diagnostics, with the `// wtlang: allow(...)` and
+    /// `// expect-error` comments of each file applied to those reported
+    /// in it
+    pub fn apply_directives(&self, diagnostics: &DiagnosticBag) -> DiagnosticBag {
+        let mut applied = DiagnosticBag::new();
+        for (index, file) in self.files.iter().enumerate() {
+            // Diagnostics of no file are the loaded file's, which is last
+            let name = file.path.display().to_string();
+            let last = index + 1 == self.files.len();
+            let mut own = DiagnosticBag::new();
+            for diagnostic in diagnostics.diagnostics() {
+                if diagnostic.location.file.as_ref().map_or(last, |other| *other == name) {
+                    own.add(diagnostic.clone());
+                }
+            }
+            for mut diagnostic in Directives::parse(&file.source).apply(&own).diagnostics().iter().cloned() {
+                diagnostic.location.file.get_or_insert_with(|| name.clone());
+                applied.add(diagnostic);
+            }
+        }
+        applied
+    }
+}
+
+/// Reads a program and the files it imports
+pub struct ModuleLoader {
+    version: LanguageVersion,
+    items: Vec<ProgramItem>,
+    files: Vec<SourceFile>,
+    next_item: u32,
+    // Files read so far, by canonical path and the name of the module they
+    // are read as, and those being read, each importing the next
+    loaded: HashSet<(PathBuf, Option<Atom>)>,
+    reading: Vec<(PathBuf, String)>,
+    // File imported as each module, by canonical path and as named
+    modules: HashMap<Atom, (PathBuf, String)>,
+    diagnostics: DiagnosticBag,
+    // Whether a file could not be read or parsed
+    failed: bool,
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        ModuleLoader {
+            version: LanguageVersion::CURRENT,
+            items: Vec::new(),
+            files: Vec::new(),
+            next_item: 0,
+            loaded: HashSet::new(),
+            reading: Vec::new(),
+            modules: HashMap::new(),
+            diagnostics: DiagnosticBag::new(),
+            failed: false,
+        }
+    }
+
+    /// Read the files as the given language version, as set by the
+    /// project; a `#version` line in a file takes precedence
+    pub fn with_language_version(mut self, version: LanguageVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Read the program in `source`, the text of the file at `path`, and
+    /// the files it imports
+    ///
+    /// Fails when a file cannot be read or parsed, with the diagnostics of
+    /// all files, each naming its file.
+    pub fn load(mut self, path: &Path, source: &str) -> Result<LoadedProgram, DiagnosticBag> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.read(path, canonical, source, None);
+        if self.failed {
+            return Err(self.diagnostics);
+        }
+        Ok(LoadedProgram {
+            program: Program { items: self.items },
+            files: self.files,
+            diagnostics: self.diagnostics,
+        })
+    }
+
+    fn read(&mut self, path: &Path, canonical: PathBuf, source: &str, module: Option<Atom>) {
+        let name = path.display().to_string();
+        self.loaded.insert((canonical.clone(), module));
+
+        let (tokens, lexed) = Lexer::new(source).tokenize_recovering();
+        self.add_all(lexed, &name);
+        let parser = |tokens| Parser::new(tokens)
+            .with_language_version(self.version)
+            .with_first_item(self.next_item);
+        // A module is parsed a first time for the names of its functions and
+        // constants, which are then qualified wherever they appear
+        let mut parser = match module {
+            Some(module) => {
+                let members = parser(tokens.clone()).parse().map(|program| members(&program)).unwrap_or_default();
+                parser(tokens).with_namespace(module, members)
+            }
+            None => parser(tokens),
+        };
+        let program = match parser.parse() {
+            Ok(program) => program,
+            Err(diagnostics) => {
+                self.failed = true;
+                return self.add_all(diagnostics, &name);
+            }
+        };
+        self.add_all(parser.diagnostics(), &name);
+        if let Some(module) = module {
+            self.check_module(module, &program, &name);
+        }
+        let first = self.next_item;
+        self.next_item += program.items.len() as u32;
+
+        // The imported files, each before the items of the file importing it
+        self.reading.push((canonical, name.clone()));
+        let directory = path.parent().unwrap_or(Path::new(""));
+        for item in &program.items {
+            if let ProgramItem::Import(import) = item {
+                self.import(directory, import, &name);
+            }
+        }
+        self.reading.pop();
+
+        self.items.extend(program.items.into_iter().filter(|item| !matches!(item, ProgramItem::Import(_))));
+        self.files.push(SourceFile { path: path.to_path_buf(), source: source.to_string(), items: first..self.next_item });
+    }
+
+    fn import(&mut self, directory: &Path, import: &Import, importer: &str) {
+        let path = directory.join(&import.path);
+        let location = Location::with_file(import.span.line, import.span.column, importer.to_string());
+        let found = path.canonicalize()
+            .and_then(|canonical| Ok((fs::read_to_string(&path)?, canonical)));
+        let (source, canonical) = match found {
+            Ok(found) => found,
+            Err(e) => {
+                let message = format!("Cannot read imported file '{}': {}", path.display(), e);
+                self.failed = true;
+                self.diagnostics.add(Diagnostic::error(ErrorCode::E5004, message, location).with_node(import.id));
+                return;
+            }
+        };
+
+        if let Some(module) = import.module {
+            let name = path.display().to_string();
+            let (file, first) = self.modules.entry(module).or_insert_with(|| (canonical.clone(), name));
+            if *file != canonical {
+                let message = format!("Module name '{}' is already used for {}", module, first);
+                self.failed = true;
+                self.diagnostics.add(Diagnostic::error(ErrorCode::E5006, message, location).with_node(import.id));
+                return;
+            }
+        }
+        if let Some(start) = self.reading.iter().position(|(reading, _)| *reading == canonical) {
+            let mut cycle: Vec<&str> = self.reading[start..].iter().map(|(_, name)| name.as_str()).collect();
+            let name = path.display().to_string();
+            cycle.push(&name);
+            let message = format!("Import cycle: {}", cycle.join(" imports "));
+            self.failed = true;
+            self.diagnostics.add(Diagnostic::error(ErrorCode::E5005, message, location).with_node(import.id));
+            return;
+        }
+        if !self.loaded.contains(&(canonical.clone(), import.module)) {
+            self.read(&path, canonical, &source, import.module);
+        }
+    }
+
+    /// Report the items of a module other than functions, external
+    /// functions, constants and imports
+    fn check_module(&mut self, module: Atom, program: &Program, file: &str) {
+        for item in &program.items {
+            let (kind, span) = match item {
+                ProgramItem::FunctionDef(_) | ProgramItem::ExternalFunction(_)
+                    | ProgramItem::Constant(_) | ProgramItem::Import(_) => continue,
+                ProgramItem::TableDef(table) => ("a table", table.span),
+                ProgramItem::Page(page) => ("a page", page.span),
+                ProgramItem::Test(test) => ("a test", test.span),
+                ProgramItem::Restriction(restriction) => ("a restriction", restriction.span),
+                ProgramItem::DerivedTable(derived) => ("a derived table", derived.span),
+                ProgramItem::SharedVariable(shared) => ("a shared variable", shared.span),
+                ProgramItem::Hook(hook) => ("a hook", hook.span),
+                ProgramItem::Config(config) => ("a config block", config.span),
+            };
+            let message = format!("Module '{}' cannot define {}, only functions, external functions and constants", module, kind);
+            let location = Location::with_file(span.line, span.column, file.to_string());
+            self.failed = true;
+            self.diagnostics.add(Diagnostic::error(ErrorCode::E5007, message, location).with_node(item.id()));
+        }
+    }
+
+    fn add_all(&mut self, diagnostics: DiagnosticBag, file: &str) {
+        for mut diagnostic in diagnostics.diagnostics().iter().cloned() {
+            diagnostic.location.file.get_or_insert_with(|| file.to_string());
+            self.diagnostics.add(diagnostic);
+        }
+    }
+}
+
+/// Names of the functions and constants of a module
+fn members(program: &Program) -> HashSet<Atom> {
+    program.items.iter()
+        .filter_map(|item| match item {
+            ProgramItem::FunctionDef(func) => Some(func.name),
+            ProgramItem::ExternalFunction(ext) => Some(ext.name),
+            ProgramItem::Constant(constant) => Some(constant.name),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Default for ModuleLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SemanticAnalyzer;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtlang_modules_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        dir
+    }
+
+    fn load(dir: &Path, files: &[(&str, &str)]) -> Result<LoadedProgram, DiagnosticBag> {
+        for (name, source) in files {
+            fs::write(dir.join(name), source).unwrap();
+        }
+        let (entry, source) = files[0];
+        ModuleLoader::new().load(&dir.join(entry), source)
+    }
+
+    #[test]
+    fn test_load_imports() {
+        let dir = temp_dir("imports");
+        let loaded = load(&dir, &[
+            ("app.wt", "import \"shared/tables.wt\"\nimport \"shared/totals.wt\"\npage Home {\n    text \"{total()}\"\n}\n"),
+            ("shared/tables.wt", "table Order {\n    id: int [key]\n}\n"),
+            ("shared/totals.wt", "import \"tables.wt\"\nfunction total() -> int {\n    let unused = 2\n    return 1\n}\n"),
+        ]).unwrap();
+
+        // Imported items first, and each file once
+        let names: Vec<_> = loaded.program.items.iter().map(|item| match item {
+            ProgramItem::TableDef(table) => table.name.to_string(),
+            ProgramItem::FunctionDef(func) => func.name.to_string(),
+            ProgramItem::Page(page) => page.name.to_string(),
+            _ => panic!("unexpected item {:?}", item),
+        }).collect();
+        assert_eq!(names, ["Order", "total", "Home"]);
+        let ids: HashSet<_> = loaded.program.items.iter().map(ProgramItem::id).collect();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(loaded.file_of(loaded.program.items[1].id()), Some(dir.join("shared/totals.wt").as_path()));
+        assert_eq!(loaded.file_of(loaded.program.items[2].id()), Some(dir.join("app.wt").as_path()));
+
+        // Diagnostics name the file of the node they are reported on
+        let mut analyzer = SemanticAnalyzer::new();
+        analyzer.analyze(&loaded.program).unwrap();
+        let warnings = loaded.apply_directives(&loaded.locate(&analyzer.diagnostics()));
+        assert_eq!(warnings.diagnostics().len(), 1);
+        assert_eq!(warnings.diagnostics()[0].location, Location::with_file(3, 5, dir.join("shared/totals.wt").display().to_string()));
+    }
+
+    #[test]
+    fn test_load_modules() {
+        let dir = temp_dir("modules");
+        let loaded = load(&dir, &[
+            ("app.wt", "import kpi from \"shared/kpi.wt\"\nimport stats from \"shared/stats.wt\"\npage Home {\n    text \"{kpi.score(stats.mean(2, 4))}\"\n}\n"),
+            ("shared/kpi.wt", "import stats from \"stats.wt\"\nconst WEIGHT = 2\nfunction score(value: float) -> float {\n    return stats.mean(value, WEIGHT)\n}\n"),
+            ("shared/stats.wt", "function mean(a: float, b: float) -> float {\n    return (a + b) / 2\n}\n"),
+        ]).unwrap();
+        
+        // Each module once, its names qualified with the name it is imported as
+        let names: Vec<_> = loaded.program.items.iter().map(|item| match item {
+            ProgramItem::FunctionDef(func) => func.name.to_string(),
+            ProgramItem::Constant(constant) => constant.name.to_string(),
+            ProgramItem::Page(page) => page.name.to_string(),
+            _ => panic!("unexpected item {:?}", item),
+        }).collect();
+        assert_eq!(names, ["stats.mean", "kpi.WEIGHT", "kpi.score", "Home"]);
+        let mut analyzer = SemanticAnalyzer::new();
+        analyzer.analyze(&loaded.program).unwrap();
+        
+        // Calls of functions a module does not define
+        let dir = temp_dir("module_errors");
+        let loaded = load(&dir, &[
+            ("app.wt", "import stats from \"stats.wt\"\npage Home {\n    let m = stats.median(1, 2)\n    text \"{m}\"\n}\n"),
+            ("stats.wt", "function mean(a: float, b: float) -> float {\n    return (a + b) / 2\n}\n"),
+        ]).unwrap();
+        let errors = SemanticAnalyzer::new().analyze(&loaded.program).unwrap_err();
+        assert_eq!(errors[0].code(), ErrorCode::E3054);
+        assert_eq!(errors[0].to_string(), "Module 'stats' has no function 'median'");
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = temp_dir("errors");
+        let errors = load(&dir, &[
+            ("app.wt", "import \"orders.wt\"\nimport \"missing.wt\"\nimport \"broken.wt\"\n"),
+            ("orders.wt", "import \"customers.wt\"\ntable Order {\n    id: int\n}\n"),
+            ("customers.wt", "import \"orders.wt\"\ntable Customer {\n    id: int\n}\n"),
+            ("broken.wt", "table Note {\n    body string\n}\n"),
+        ]).unwrap_err();
+        let errors = errors.diagnostics();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+
+        // Import errors name the importing file, syntax errors their own
+        let file = |name: &str| dir.join(name).display().to_string();
+        assert_eq!(errors[0].code, ErrorCode::E5005);
+        assert_eq!(errors[0].location.file, Some(file("customers.wt")));
+        assert_eq!(errors[0].message, format!("Import cycle: {} imports {} imports {}", file("orders.wt"), file("customers.wt"), file("orders.wt")));
+        assert_eq!(errors[1].code, ErrorCode::E5004);
+        assert_eq!(errors[1].location, Location::with_file(2, 1, file("app.wt")));
+        assert_eq!(errors[2].location.file, Some(file("broken.wt")));
+        
+        // Modules only define functions and constants, and are named apart
+        let errors = load(&dir, &[
+            ("modules.wt", "import stats from \"scale.wt\"\nimport stats from \"stats.wt\"\nimport notes from \"notes.wt\"\n"),
+            ("scale.wt", "const SCALE = 2\n"),
+            ("stats.wt", "const SCALE = 3\n"),
+            ("notes.wt", "const LIMIT = 3\ntable Note {\n    body: string\n}\n"),
+        ]).unwrap_err();
+        let errors = errors.diagnostics();
+        let codes: Vec<_> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, [ErrorCode::E5006, ErrorCode::E5007], "{:?}", errors);
+        assert_eq!(errors[0].message, format!("Module name 'stats' is already used for {}", file("scale.wt")));
+        assert_eq!(errors[1].message, "Module 'notes' cannot define a table, only functions, external functions and constants");
+        assert_eq!(errors[1].location, Location::with_file(2, 1, file("notes.wt")));
+    }
+}
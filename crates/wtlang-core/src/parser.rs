@@ -1,27 +1,132 @@
 // Parser for WTLang
 use crate::ast::*;
-use crate::lexer::{Token, TokenType};
-use crate::errors::{ErrorCode, DiagnosticBag, Location};
+use crate::lexer::{Lexer, StringPart, Token, TokenType};
+use crate::errors::{ErrorCode, Diagnostic, DiagnosticBag, Fix, Location, TextEdit};
+use crate::intern::Atom;
+use crate::template;
+use crate::version::{Feature, LanguageVersion};
+use std::collections::{HashMap, HashSet};
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     diagnostics: DiagnosticBag,
+    // Token ranges of the expressions written in parentheses, from `(` to `)`
+    parenthesized: HashSet<(usize, usize)>,
+    // Top-level item being parsed and the number of its next node, and the
+    // number of the first item
+    item: u32,
+    next_index: u32,
+    first_item: u32,
+    // Text of the `///` comments before a token, by the token's index
+    docs: HashMap<usize, String>,
+    // Language version the program is read as, and its `#version` lines
+    // with the index of the token after them
+    version: LanguageVersion,
+    version_lines: Vec<(usize, Token)>,
+    // Templates defined so far, and the number of `use`s expanded
+    templates: HashMap<Atom, Template>,
+    expansions: usize,
+    // Names of the modules imported so far, and when the program is read as
+    // a module, its name and the names of its functions and constants
+    modules: HashSet<Atom>,
+    namespace: Option<(Atom, HashSet<Atom>)>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        // The lexer already reported its invalid tokens; parse as if they
+        // were not there. Doc comments are kept apart, for the declaration
+        // starting at the token after them.
+        let mut kept = Vec::with_capacity(tokens.len());
+        let mut docs = HashMap::new();
+        let mut doc_lines = Vec::new();
+        let mut version_lines = Vec::new();
+        for token in tokens {
+            match token.token_type {
+                TokenType::Invalid(_) => {}
+                TokenType::DocComment(line) => doc_lines.push(line),
+                TokenType::Version(_) => version_lines.push((kept.len(), token)),
+                _ => {
+                    if !doc_lines.is_empty() {
+                        docs.insert(kept.len(), doc_lines.join("\n"));
+                        doc_lines.clear();
+                    }
+                    kept.push(token);
+                }
+            }
+        }
         Parser { 
-            tokens, 
+            tokens: kept, 
             current: 0,
             diagnostics: DiagnosticBag::new(),
+            parenthesized: HashSet::new(),
+            item: 0,
+            next_index: 0,
+            first_item: 0,
+            docs,
+            version: LanguageVersion::CURRENT,
+            version_lines,
+            templates: HashMap::new(),
+            expansions: 0,
+            modules: HashSet::new(),
+            namespace: None,
         }
     }
+    
+    /// Read the program as the given language version, as set by the
+    /// project; a `#version` line in the program takes precedence
+    pub fn with_language_version(mut self, version: LanguageVersion) -> Self {
+        self.version = version;
+        self
+    }
+    
+    /// Number the program's items from `first` on, so that their nodes
+    /// keep distinct ids when merged after `first` items of other files
+    pub fn with_first_item(mut self, first: u32) -> Self {
+        self.first_item = first;
+        self
+    }
+    
+    /// Read the program as the module imported as `name`: its functions
+    /// and constants, named in `members`, are defined and used as
+    /// `name.member`
+    pub fn with_namespace(mut self, name: Atom, members: HashSet<Atom>) -> Self {
+        self.namespace = Some((name, members));
+        self
+    }
+    
+    /// The language version the program is read as
+    pub fn language_version(&self) -> LanguageVersion {
+        self.version
+    }
+    
+    /// Warnings found while parsing, such as ambiguous operator precedence
+    ///
+    /// `parse` only returns the diagnostics when there are errors.
+    pub fn diagnostics(&self) -> DiagnosticBag {
+        self.diagnostics.clone()
+    }
 
     pub fn parse(&mut self) -> Result<Program, DiagnosticBag> {
+        self.read_version_lines();
         let mut items = Vec::new();
         
+        let mut item = self.first_item;
         while !self.is_at_end() {
+            // Templates are expanded where they are used, not items
+            if self.at_template() {
+                if self.parse_template().is_err() {
+                    self.synchronize();
+                }
+                continue;
+            }
+            
+            // Number each item's nodes apart, so that they keep their ids
+            // when another item changes
+            self.item = item;
+            self.next_index = 0;
+            item += 1;
             match self.parse_program_item() {
                 Ok(item) => items.push(item),
                 Err(_) => {
@@ -39,45 +144,393 @@ impl Parser {
         }
     }
     
+    /// Parse a single expression spanning all of the tokens
+    ///
+    /// Used by tools that check one expression outside of a program, such
+    /// as `wtc eval`.
+    pub fn parse_standalone_expression(&mut self) -> Result<Expr, DiagnosticBag> {
+        let result = self.parse_expression();
+        if result.is_ok() && !self.is_at_end() {
+            self.add_error(
+                ErrorCode::E2001,
+                format!("Unexpected token after expression: {:?}", self.peek().token_type)
+            );
+        }
+        
+        match result {
+            Ok(expr) if !self.diagnostics.has_errors() => Ok(expr),
+            _ => Err(self.diagnostics.clone()),
+        }
+    }
+    
     fn synchronize(&mut self) {
         // Skip tokens until we find a likely start of a new item
         while !self.is_at_end() {
-            if matches!(
-                self.peek().token_type,
-                TokenType::Page | TokenType::Table | TokenType::Function | TokenType::External | TokenType::Test
-            ) {
+            if self.at_item_start() {
                 return;
             }
             self.advance();
         }
     }
     
+    fn at_item_start(&self) -> bool {
+        matches!(
+            self.peek().token_type,
+            TokenType::Page | TokenType::Table | TokenType::Function | TokenType::External | TokenType::Test
+                | TokenType::Restrict | TokenType::Shared | TokenType::Import
+                | TokenType::OnLoad | TokenType::OnEdit | TokenType::OnSave
+        ) || self.at_config() || self.at_template() || self.at_const()
+    }
+    
+    /// `config` is only a keyword before the `{` of a config block
+    fn at_config(&self) -> bool {
+        self.check_identifier_value("config")
+            && matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::LeftBrace))
+    }
+    
+    /// `const` is only a keyword before the name of a constant
+    fn at_const(&self) -> bool {
+        self.check_identifier_value("const")
+            && matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::Identifier(_)))
+    }
+    
+    /// `template` is only a keyword before the name and `(` of a template
+    fn at_template(&self) -> bool {
+        self.check_identifier_value("template") && self.at_named_call(1)
+    }
+    
+    /// `use` is only a keyword at the start of a statement, before the name
+    /// and `(` of a template
+    fn at_use(&self) -> bool {
+        self.check_identifier_value("use") && self.at_named_call(1)
+    }
+    
+    /// `python` is only a keyword before the header and body of a block of
+    /// Python code, which the lexer reads as one token
+    fn at_python(&self) -> bool {
+        self.check_identifier_value("python")
+            && self.tokens[self.current + 1..].iter()
+                .map(|token| &token.token_type)
+                .take_while(|token_type| !matches!(token_type, TokenType::LeftBrace | TokenType::RightBrace | TokenType::Eof))
+                .any(|token_type| matches!(token_type, TokenType::RawCode(_)))
+    }
+    
+    /// Whether the tokens from `offset` on are a name and `(`
+    fn at_named_call(&self, offset: usize) -> bool {
+        matches!(self.tokens.get(self.current + offset).map(|t| &t.token_type), Some(TokenType::Identifier(_)))
+            && matches!(self.tokens.get(self.current + offset + 1).map(|t| &t.token_type), Some(TokenType::LeftParen))
+    }
+    
+    /// Skip the rest of a statement that failed to parse from `start`: up to
+    /// the next statement of the same block, or the `}` closing the block
+    ///
+    /// Brackets opened since `start` are tracked so that recovery never stops
+    /// inside a nested block or call, or leaves the enclosing block early.
+    fn synchronize_statement(&mut self, start: usize) {
+        if self.current == start {
+            self.advance();
+        }
+        
+        let mut depth = 0usize;
+        for token in &self.tokens[start..self.current] {
+            match token.token_type {
+                TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => depth += 1,
+                TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }
+        
+        while !self.is_at_end() && !self.at_item_start() {
+            let token = self.peek();
+            match token.token_type {
+                TokenType::RightBrace | TokenType::Semicolon if depth == 0 => return,
+                TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket => {
+                    depth = depth.saturating_sub(1)
+                }
+                TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => depth += 1,
+                TokenType::Title | TokenType::Subtitle | TokenType::Text | TokenType::Button
+                    | TokenType::Section | TokenType::Confirm | TokenType::Let | TokenType::If
+                    | TokenType::Forall | TokenType::Return | TokenType::Assert | TokenType::Mock
+                    if depth == 0 => return,
+                // An identifier at the start of a line begins a call or an assignment
+                TokenType::Identifier(_) if depth == 0 && token.line > self.tokens[self.current - 1].line => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+    
+    /// Parse a `{ ... }` block of statements, reporting every statement that
+    /// fails to parse rather than only the first
+    ///
+    /// Fails when the block is never closed, leaving recovery to the next item.
+    fn parse_block(&mut self) -> Result<Vec<Statement>, ()> {
+        self.expect(TokenType::LeftBrace)?;
+        
+        let mut body = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            if self.is_at_end() || self.at_item_start() {
+                // Reports the missing `}`
+                self.expect(TokenType::RightBrace)?;
+            }
+            if self.check(&TokenType::Semicolon) {
+                self.advance();
+                continue;
+            }
+            
+            let start = self.current;
+            let parsed = if self.at_use() {
+                self.parse_use()
+            } else {
+                self.parse_statement().map(|statement| vec![statement])
+            };
+            match parsed {
+                Ok(statements) => {
+                    body.extend(statements);
+                    self.check_statement_end();
+                }
+                // A nested block that was never closed has already been reported
+                Err(()) if self.is_at_end() || self.at_item_start() => return Err(()),
+                Err(()) => self.synchronize_statement(start),
+            }
+        }
+        
+        self.expect(TokenType::RightBrace)?;
+        Ok(body)
+    }
+    
+    /// Statements end at a line break, a `;` or the end of their block;
+    /// report another statement starting on the same line
+    fn check_statement_end(&mut self) {
+        if self.check(&TokenType::Semicolon) || self.check(&TokenType::RightBrace)
+            || self.is_at_end() || !self.continues_line() {
+            return;
+        }
+        let previous = &self.tokens[self.current - 1];
+        let fix = Fix {
+            message: "Separate the statements with ';'".to_string(),
+            edits: vec![TextEdit::insert(Location::new(previous.end_line, previous.end_column), ";")],
+        };
+        let token = self.peek();
+        let location = Location::new(token.line, token.column);
+        self.diagnostics.add(
+            Diagnostic::error(ErrorCode::E2017, "Expected a new line or ';' before the next statement".to_string(), location)
+                .with_fix(fix)
+        );
+    }
+    
+    /// Whether the current token is on the line where the previous one ends
+    ///
+    /// A call's `(` and an index's `[` must be, so a line starting with one
+    /// never continues the line before.
+    fn continues_line(&self) -> bool {
+        self.current == 0 || self.peek().line <= self.tokens[self.current - 1].end_line
+    }
+    
+    /// Report a line starting with `(` or `[` that reads as the continuation
+    /// of the complete statement before it; true if reported
+    fn report_line_break(&mut self) -> bool {
+        let symbol = match self.peek().token_type {
+            TokenType::LeftParen => "(",
+            TokenType::LeftBracket => "[",
+            _ => return false,
+        };
+        if self.continues_line() {
+            return false;
+        }
+        let previous = &self.tokens[self.current - 1];
+        let token = self.peek();
+        let location = Location::new(token.line, token.column);
+        let fix = Fix {
+            message: "Join the line with the previous one".to_string(),
+            edits: vec![TextEdit {
+                start: Location::new(previous.end_line, previous.end_column),
+                end: location.clone(),
+                text: String::new(),
+            }],
+        };
+        let message = format!("'{}' at the start of a line does not continue the previous line", symbol);
+        self.diagnostics.add(Diagnostic::error(ErrorCode::E2018, message, location).with_fix(fix));
+        true
+    }
+    
+    /// Take the language version from a `#version` line before the first
+    /// declaration
+    fn read_version_lines(&mut self) {
+        for (i, (index, token)) in std::mem::take(&mut self.version_lines).into_iter().enumerate() {
+            let TokenType::Version(text) = &token.token_type else { continue };
+            let location = Location::new(token.line, token.column);
+            let message = if index > 0 || i > 0 {
+                "#version must come once, before any declaration".to_string()
+            } else if let Some(version) = LanguageVersion::parse(text) {
+                self.version = version;
+                continue;
+            } else {
+                format!("Unknown language version '{}'; the known versions are {}", text, LanguageVersion::known())
+            };
+            self.diagnostics.add(Diagnostic::error(ErrorCode::E2021, message, location)
+                .with_end(Location::new(token.end_line, token.end_column)));
+        }
+    }
+    
+    /// Report a use of syntax the program's language version does not have;
+    /// parsing goes on as if it had
+    fn require(&mut self, feature: Feature) {
+        if self.version < feature.since() {
+            self.add_error(
+                ErrorCode::E2020,
+                format!(
+                    "{} requires language version {}, but the program is written for {}",
+                    feature.description(), feature.since(), self.version
+                )
+            );
+        }
+    }
+    
     fn add_error(&mut self, code: ErrorCode, message: String) {
         let token = self.peek();
         let location = Location::new(token.line, token.column);
-        self.diagnostics.add_error(code, message, location);
+        let end = Location::new(token.end_line, token.end_column);
+        self.diagnostics.add(Diagnostic::error(code, message, location).with_end(end));
+    }
+    
+    /// Report an error over a construct already parsed
+    fn add_error_in(&mut self, span: Span, code: ErrorCode, message: String) {
+        let location = Location::new(span.line, span.column);
+        let end = Location::new(span.end_line, span.end_column);
+        self.diagnostics.add(Diagnostic::error(code, message, location).with_end(end));
     }
 
     fn parse_program_item(&mut self) -> Result<ProgramItem, ()> {
         match &self.peek().token_type {
-            TokenType::Table => Ok(ProgramItem::TableDef(self.parse_table_def()?)),
+            TokenType::Table => self.parse_table_item(),
             TokenType::Page => Ok(ProgramItem::Page(self.parse_page()?)),
             TokenType::Function => Ok(ProgramItem::FunctionDef(self.parse_function_def()?)),
             TokenType::External => Ok(ProgramItem::ExternalFunction(self.parse_external_function()?)),
             TokenType::Test => Ok(ProgramItem::Test(self.parse_test()?)),
+            TokenType::Restrict => {
+                self.require(Feature::Restriction);
+                Ok(ProgramItem::Restriction(self.parse_restriction()?))
+            }
+            TokenType::Shared => {
+                self.require(Feature::SharedVariable);
+                Ok(ProgramItem::SharedVariable(self.parse_shared_variable()?))
+            }
+            TokenType::OnLoad | TokenType::OnEdit | TokenType::OnSave => {
+                self.require(Feature::Hook);
+                let event = match self.peek().token_type {
+                    TokenType::OnLoad => HookEvent::Load,
+                    TokenType::OnEdit => HookEvent::Edit,
+                    _ => HookEvent::Save,
+                };
+                Ok(ProgramItem::Hook(self.parse_hook(event)?))
+            }
+            _ if self.at_config() => {
+                self.require(Feature::Config);
+                Ok(ProgramItem::Config(self.parse_config()?))
+            }
+            _ if self.at_const() => {
+                self.require(Feature::Constant);
+                Ok(ProgramItem::Constant(self.parse_constant()?))
+            }
+            TokenType::Import => {
+                self.require(Feature::Import);
+                let start = self.advance().clone();
+                let module = if self.check_identifier() {
+                    let name = self.expect_identifier()?;
+                    self.expect(TokenType::From)?;
+                    self.modules.insert(name);
+                    Some(name)
+                } else {
+                    None
+                };
+                let path = self.expect_string()?;
+                Ok(ProgramItem::Import(Import { path, module, id: self.next_id(), span: self.span_from(&start) }))
+            }
             _ => {
                 self.add_error(
                     ErrorCode::E2001,
-                    format!("Expected table, page, function, external, or test, got {:?}", self.peek().token_type)
+                    format!("Expected table, page, function, external, test, restrict, shared, const, config, template, import, or a hook, got {:?}", self.peek().token_type)
                 );
                 Err(())
             }
         }
     }
 
-    fn parse_table_def(&mut self) -> Result<TableDef, ()> {
+    /// `table Name { fields }`, or `table Name = expression` for a derived table
+    fn parse_table_item(&mut self) -> Result<ProgramItem, ()> {
+        let doc = self.doc_comment();
+        let start = self.peek().clone();
         self.expect(TokenType::Table)?;
         let name = self.expect_identifier()?;
+        if self.check(&TokenType::Assign) {
+            self.require(Feature::DerivedTable);
+            self.advance();
+            let value = self.parse_expression()?;
+            let materialize = self.parse_materialize()?;
+            return Ok(ProgramItem::DerivedTable(DerivedTable { name, value, materialize, id: self.next_id(), span: self.span_from(&start) }));
+        }
+        Ok(ProgramItem::TableDef(self.parse_table_def(name, doc, &start)?))
+    }
+
+    /// Text of the doc comments before the current token
+    fn doc_comment(&self) -> Option<String> {
+        self.docs.get(&self.current).cloned()
+    }
+
+    /// Optional `materialize daily` or `materialize on_build` of a derived table
+    fn parse_materialize(&mut self) -> Result<Materialize, ()> {
+        if !self.check_identifier_value("materialize") {
+            return Ok(Materialize::Cached);
+        }
+        self.advance();
+        let materialize = if self.check_identifier_value("daily") {
+            Materialize::Daily
+        } else if self.check_identifier_value("on_build") {
+            Materialize::OnBuild
+        } else {
+            self.add_error(
+                ErrorCode::E2011,
+                format!("Expected 'daily' or 'on_build' after materialize, got {:?}", self.peek().token_type)
+            );
+            return Err(());
+        };
+        self.advance();
+        Ok(materialize)
+    }
+
+    /// A duration such as `30s`, `5m` or `1h`, in seconds
+    fn parse_duration(&mut self) -> Result<u64, ()> {
+        let amount = match self.peek().token_type {
+            TokenType::IntLiteral(amount) if amount > 0 => amount as u64,
+            _ => {
+                self.add_error(
+                    ErrorCode::E2011,
+                    format!("Expected a duration such as 30s, 5m or 1h, got {:?}", self.peek().token_type)
+                );
+                return Err(());
+            }
+        };
+        self.advance();
+        let unit = match &self.peek().token_type {
+            TokenType::Identifier(unit) if unit == "s" => 1,
+            TokenType::Identifier(unit) if unit == "m" => 60,
+            TokenType::Identifier(unit) if unit == "h" => 3600,
+            other => {
+                self.add_error(
+                    ErrorCode::E2011,
+                    format!("Expected a duration unit s, m or h, got {:?}", other)
+                );
+                return Err(());
+            }
+        };
+        self.advance();
+        Ok(amount * unit)
+    }
+
+    fn parse_table_def(&mut self, name: Atom, doc: Option<String>, start: &Token) -> Result<TableDef, ()> {
         self.expect(TokenType::LeftBrace)?;
         
         let mut fields = Vec::new();
@@ -86,13 +539,24 @@ impl Parser {
         }
         
         self.expect(TokenType::RightBrace)?;
-        Ok(TableDef { name, fields })
+        Ok(TableDef { name, fields, doc, id: self.next_id(), span: self.span_from(start) })
     }
 
     fn parse_field(&mut self) -> Result<Field, ()> {
         let name = self.expect_identifier()?;
         self.expect(TokenType::Colon)?;
         let field_type = self.parse_type()?;
+        if let Type::Array(_) = field_type {
+            self.add_error(ErrorCode::E2003, format!("Field '{}' cannot hold an array; use a table of rows instead", name));
+            return Err(());
+        }
+        
+        let mut header = None;
+        if self.check(&TokenType::From) {
+            self.require(Feature::FieldHeader);
+            self.advance();
+            header = Some(self.expect_string()?);
+        }
         
         let mut constraints = Vec::new();
         if self.check(&TokenType::LeftBracket) {
@@ -106,10 +570,23 @@ impl Parser {
             self.advance();
         }
         
-        Ok(Field { name, field_type, constraints })
+        Ok(Field { name, field_type, header, constraints })
     }
 
     fn parse_type(&mut self) -> Result<Type, ()> {
+        let mut ty = self.parse_base_type()?;
+        // `string[]`; a `[` not closed right away starts field constraints
+        while self.check(&TokenType::LeftBracket)
+            && matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::RightBracket))
+        {
+            self.advance();
+            self.advance();
+            ty = Type::Array(Box::new(ty));
+        }
+        Ok(ty)
+    }
+
+    fn parse_base_type(&mut self) -> Result<Type, ()> {
         let token = self.advance().clone();
         match &token.token_type {
             TokenType::Int => Ok(Type::Int),
@@ -126,6 +603,13 @@ impl Parser {
                 let table_name = self.expect_identifier()?;
                 Ok(Type::Ref(table_name))
             }
+            // row(TableName); row is not a keyword, so it stays usable as a name
+            TokenType::Identifier(name) if name == "row" => {
+                self.expect(TokenType::LeftParen)?;
+                let table_name = self.expect_identifier()?;
+                self.expect(TokenType::RightParen)?;
+                Ok(Type::Row(table_name.into()))
+            }
             _ => {
                 self.add_error(
                     ErrorCode::E2003,
@@ -147,11 +631,18 @@ impl Parser {
                     Constraint::Key
                 }
                 TokenType::Identifier(ident) => {
-                    let ident_str = ident.clone();
+                    let ident_str = *ident;
                     self.advance();
                     match ident_str.as_str() {
                         "unique" => Constraint::Unique,
                         "non_null" => Constraint::NonNull,
+                        "references" => {
+                            self.require(Feature::References);
+                            let table = self.expect_identifier()?;
+                            self.expect(TokenType::Dot)?;
+                            let field = self.expect_identifier()?;
+                            Constraint::References { table, field }
+                        }
                         _ => {
                             self.add_error(
                                 ErrorCode::E2012,
@@ -172,67 +663,67 @@ impl Parser {
             
             constraints.push(constraint);
             
-            if !self.check(&TokenType::Comma) {
+            if !self.list_separator(&TokenType::RightBracket) {
                 break;
             }
-            self.advance();
         }
         
         Ok(constraints)
     }
 
     fn parse_page(&mut self) -> Result<Page, ()> {
+        let doc = self.doc_comment();
+        let start = self.peek().clone();
         self.expect(TokenType::Page)?;
         let name = self.expect_identifier()?;
-        self.expect(TokenType::LeftBrace)?;
-        
-        let mut statements = Vec::new();
-        while !self.check(&TokenType::RightBrace) {
-            statements.push(self.parse_statement()?);
-        }
-        
-        self.expect(TokenType::RightBrace)?;
-        Ok(Page { name, statements })
+        let statements = self.parse_block()?;
+        Ok(Page { name, statements, doc, id: self.next_id(), span: self.span_from(&start) })
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ()> {
+        let start = self.peek().clone();
         match &self.peek().token_type {
+            // `text = ...` assigns a variable named by a contextual keyword
+            token_type if token_type.contextual_keyword().is_some()
+                && matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::Assign)) => {
+                let name = self.expect_identifier()?;
+                self.advance();
+                let value = self.parse_expression()?;
+                Ok(Statement::Assign { name, value, id: self.next_id(), span: self.span_from(&start) })
+            },
             TokenType::Title => {
                 self.advance();
-                let text = self.expect_string()?;
+                let text = self.parse_page_text()?;
                 Ok(Statement::Title(text))
             },
             TokenType::Subtitle => {
                 self.advance();
-                let text = self.expect_string()?;
+                let text = self.parse_page_text()?;
                 Ok(Statement::Subtitle(text))
             },
             TokenType::Text => {
                 self.advance();
-                let text = self.expect_string()?;
+                let text = self.parse_page_text()?;
                 Ok(Statement::Text(text))
             },
             TokenType::Button => {
                 self.advance();
-                let label = self.expect_string()?;
-                self.expect(TokenType::LeftBrace)?;
-                let mut body = Vec::new();
-                while !self.check(&TokenType::RightBrace) {
-                    body.push(self.parse_statement()?);
-                }
-                self.expect(TokenType::RightBrace)?;
-                Ok(Statement::Button { label, body })
+                let label = self.parse_page_text()?;
+                let body = self.parse_block()?;
+                Ok(Statement::Button { label, body, id: self.next_id(), span: self.span_from(&start) })
             },
             TokenType::Section => {
                 self.advance();
-                let title = self.expect_string()?;
-                self.expect(TokenType::LeftBrace)?;
-                let mut body = Vec::new();
-                while !self.check(&TokenType::RightBrace) {
-                    body.push(self.parse_statement()?);
-                }
-                self.expect(TokenType::RightBrace)?;
-                Ok(Statement::Section { title, body })
+                let title = self.parse_page_text()?;
+                let body = self.parse_block()?;
+                Ok(Statement::Section { title, body, id: self.next_id(), span: self.span_from(&start) })
+            },
+            TokenType::Confirm => {
+                self.require(Feature::Confirm);
+                self.advance();
+                let message = self.parse_page_text()?;
+                let body = self.parse_block()?;
+                Ok(Statement::Confirm { message, body, id: self.next_id(), span: self.span_from(&start) })
             },
             TokenType::Let => {
                 self.advance();
@@ -263,38 +754,89 @@ impl Parser {
                     return Err(());
                 }
                 
-                Ok(Statement::Let { name, type_annotation, value })
+                Ok(Statement::Let { name, type_annotation, value, id: self.next_id(), span: self.span_from(&start) })
             },
             TokenType::If => {
                 self.advance();
                 let condition = self.parse_expression()?;
-                self.expect(TokenType::LeftBrace)?;
-                let mut then_branch = Vec::new();
-                while !self.check(&TokenType::RightBrace) {
-                    then_branch.push(self.parse_statement()?);
-                }
-                self.expect(TokenType::RightBrace)?;
+                let then_branch = self.parse_block()?;
+                let then_span = self.span_from(&start);
                 
+                let mut else_span = None;
                 let else_branch = if self.check(&TokenType::Else) {
-                    self.advance();
-                    self.expect(TokenType::LeftBrace)?;
-                    let mut else_stmts = Vec::new();
-                    while !self.check(&TokenType::RightBrace) {
-                        else_stmts.push(self.parse_statement()?);
-                    }
-                    self.expect(TokenType::RightBrace)?;
+                    let else_start = self.advance().clone();
+                    let else_stmts = self.parse_block()?;
+                    else_span = Some(self.span_from(&else_start));
                     Some(else_stmts)
                 } else {
                     None
                 };
                 
-                Ok(Statement::If { condition, then_branch, else_branch })
+                Ok(Statement::If { condition, then_branch, else_branch, id: self.next_id(), then_span, else_span })
+            },
+            TokenType::Forall => {
+                self.advance();
+                let var = self.expect_identifier()?;
+                self.expect(TokenType::In)?;
+                let iterable = self.parse_expression()?;
+                let body = self.parse_block()?;
+                Ok(Statement::Forall { var, iterable, body, id: self.next_id(), span: self.span_from(&start) })
             },
             TokenType::Return => {
                 self.advance();
                 let value = self.parse_expression()?;
                 Ok(Statement::Return(value))
             },
+            TokenType::Assert => {
+                self.advance();
+                let condition = self.parse_expression()?;
+                Ok(Statement::Assert { condition, id: self.next_id(), span: self.span_from(&start) })
+            },
+            TokenType::Mock => {
+                // mock <table or function> with <expr>
+                self.advance();
+                let target = self.expect_name()?;
+                if !self.check_identifier_value("with") {
+                    self.add_error(ErrorCode::E2001, "Expected 'with' after mock target".to_string());
+                    return Err(());
+                }
+                self.advance();
+                let value = self.parse_expression()?;
+                Ok(Statement::Mock { target, value, id: self.next_id(), span: self.span_from(&start) })
+            },
+            // `refresh every 5m`; refresh is not a keyword, so it stays
+            // usable as a name
+            TokenType::Identifier(name) if name == "refresh"
+                && matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::Identifier(next)) if next == "every") => {
+                self.require(Feature::Refresh);
+                self.advance();
+                self.advance();
+                let seconds = self.parse_duration()?;
+                Ok(Statement::Refresh { seconds, id: self.next_id(), span: self.span_from(&start) })
+            },
+            TokenType::Identifier(_) if self.at_python() => self.parse_python(),
+            // `match value { ... }`; match is not a keyword, so it stays
+            // usable as a name
+            TokenType::Identifier(_) if self.at_match() => {
+                let (value, arms) = self.parse_match(|parser| parser.parse_block())?;
+                Ok(Statement::Match { value, arms, id: self.next_id(), span: self.span_from(&start) })
+            },
+            // `display value` is display(value); display is not a keyword,
+            // so it stays usable as a name
+            TokenType::Identifier(name) if name == "display"
+                && self.tokens.get(self.current + 1).is_some_and(|next| next.line == start.end_line && matches!(
+                    next.token_type,
+                    TokenType::Identifier(_) | TokenType::IntLiteral(_) | TokenType::FloatLiteral(_)
+                        | TokenType::StringLiteral(_) | TokenType::InterpolatedString(_)
+                        | TokenType::BoolLiteral(_) | TokenType::DateLiteral(_)
+                        | TokenType::CurrencyLiteral { .. } | TokenType::Minus
+                        | TokenType::Not | TokenType::LeftBracket
+                )) => {
+                let name = *name;
+                self.advance();
+                let value = self.parse_expression()?;
+                Ok(Statement::FunctionCall(FunctionCall { name, args: vec![value], id: self.next_id(), span: self.span_from(&start) }))
+            },
             TokenType::Identifier(_) => {
                 // Could be assignment or function call
                 let name_or_expr = self.parse_expression()?;
@@ -305,13 +847,15 @@ impl Parser {
                     if self.check(&TokenType::Assign) {
                         self.advance(); // consume =
                         let value = self.parse_expression()?;
-                        return Ok(Statement::Assign { name: name.clone(), value });
+                        return Ok(Statement::Assign { name: *name, value, id: self.next_id(), span: self.span_from(&start) });
                     }
                 }
                 
                 // Otherwise it should be a function call
                 if let Expr::FunctionCall(call) = name_or_expr {
                     Ok(Statement::FunctionCall(call))
+                } else if self.report_line_break() {
+                    Err(())
                 } else {
                     self.add_error(
                         ErrorCode::E2001,
@@ -321,6 +865,9 @@ impl Parser {
                 }
             },
             _ => {
+                if self.report_line_break() {
+                    return Err(());
+                }
                 self.add_error(
                     ErrorCode::E2001,
                     format!("Unexpected token in statement: {:?}", self.peek().token_type)
@@ -331,28 +878,173 @@ impl Parser {
     }
 
     fn parse_function_def(&mut self) -> Result<FunctionDef, ()> {
+        let doc = self.doc_comment();
+        let start = self.peek().clone();
         self.expect(TokenType::Function)?;
         let name = self.expect_identifier()?;
+        let name = self.member_name(name);
         self.expect(TokenType::LeftParen)?;
         let params = self.parse_parameters()?;
         self.expect(TokenType::RightParen)?;
         self.expect(TokenType::Arrow)?;
         let return_type = self.parse_type()?;
-        self.expect(TokenType::LeftBrace)?;
+        let body = self.parse_block()?;
+        Ok(FunctionDef { name, params, return_type, body, doc, id: self.next_id(), span: self.span_from(&start) })
+    }
+
+    /// `template name(param: type, ...) { statements }`, kept to expand the
+    /// `use`s after it
+    fn parse_template(&mut self) -> Result<(), ()> {
+        self.require(Feature::Template);
+        let start = self.advance().clone();
+        let name = self.expect_identifier()?;
+        self.expect(TokenType::LeftParen)?;
+        let mut params = Vec::new();
+        while !self.check(&TokenType::RightParen) {
+            let name = self.expect_identifier()?;
+            self.expect(TokenType::Colon)?;
+            // `table` takes any table
+            let param_type = if self.check(&TokenType::Table) {
+                self.advance();
+                None
+            } else {
+                Some(self.parse_type()?)
+            };
+            params.push(TemplateParam { name, param_type });
+            if !self.list_separator(&TokenType::RightParen) {
+                break;
+            }
+        }
+        self.expect(TokenType::RightParen)?;
+        let body = self.parse_block()?;
+        let span = self.span_from(&start);
+        if self.templates.contains_key(&name) {
+            self.add_error_in(span, ErrorCode::E3004, format!("Template '{}' is already defined", name));
+        }
+        self.templates.insert(name, Template { name, params, body, span });
+        Ok(())
+    }
+    
+    /// `python(inputs) -> (outputs) { code }`
+    fn parse_python(&mut self) -> Result<Statement, ()> {
+        self.require(Feature::Python);
+        let start = self.advance().clone();
+        let inputs = if self.check(&TokenType::LeftParen) {
+            self.parse_code_bindings()?
+        } else {
+            Vec::new()
+        };
+        let outputs = if self.check(&TokenType::Arrow) {
+            self.advance();
+            self.parse_code_bindings()?
+        } else {
+            Vec::new()
+        };
+        if let Some(output) = outputs.iter().find(|output| output.binding_type.is_none()) {
+            self.add_error(
+                ErrorCode::E2003,
+                format!("Output '{}' needs the table it holds rows of, such as table(Order)", output.name)
+            );
+            return Err(());
+        }
+        let TokenType::RawCode(code) = &self.peek().token_type else {
+            self.add_error(ErrorCode::E2001, "Expected '{' starting the Python code".to_string());
+            return Err(());
+        };
+        let code = code.clone();
+        self.advance();
+        Ok(Statement::Python { inputs, outputs, code, id: self.next_id(), span: self.span_from(&start) })
+    }
+    
+    /// `(name: type, ...)` of a `python` block, where a type can also be
+    /// `table`, any table, or `table(Name)`
+    fn parse_code_bindings(&mut self) -> Result<Vec<CodeBinding>, ()> {
+        self.expect(TokenType::LeftParen)?;
+        let mut bindings: Vec<CodeBinding> = Vec::new();
+        while !self.check(&TokenType::RightParen) {
+            let name = self.expect_identifier()?;
+            self.expect(TokenType::Colon)?;
+            let binding_type = if self.check(&TokenType::Table) {
+                self.advance();
+                if self.check(&TokenType::LeftParen) {
+                    self.advance();
+                    let table = self.expect_identifier()?;
+                    self.expect(TokenType::RightParen)?;
+                    Some(Type::Table(table.into()))
+                } else {
+                    None
+                }
+            } else {
+                Some(self.parse_type()?)
+            };
+            if bindings.iter().any(|binding| binding.name == name) {
+                self.add_error(ErrorCode::E3004, format!("'{}' is declared twice", name));
+            }
+            bindings.push(CodeBinding { name, variable: name, binding_type });
+            if !self.list_separator(&TokenType::RightParen) {
+                break;
+            }
+        }
+        self.expect(TokenType::RightParen)?;
+        Ok(bindings)
+    }
+    
+    /// `use name(args)`: the statements of the template, with each
+    /// parameter replaced by its argument
+    fn parse_use(&mut self) -> Result<Vec<Statement>, ()> {
+        self.require(Feature::Template);
+        let start = self.advance().clone();
+        let name = self.expect_identifier()?;
+        self.expect(TokenType::LeftParen)?;
+        let args = self.parse_arguments(name)?;
+        self.expect(TokenType::RightParen)?;
+        let span = self.span_from(&start);
         
-        let mut body = Vec::new();
-        while !self.check(&TokenType::RightBrace) {
-            body.push(self.parse_statement()?);
+        let Some(template) = self.templates.get(&name).cloned() else {
+            self.add_error_in(span, ErrorCode::E2022, format!("Template '{}' is not defined before this use", name));
+            return Ok(Vec::new());
+        };
+        if args.len() != template.params.len() {
+            self.add_error_in(span, ErrorCode::E3010, format!(
+                "Template '{}' takes {} argument(s), found {}", name, template.params.len(), args.len()
+            ));
+            return Ok(Vec::new());
+        }
+        // Arguments written as literals have a type to check
+        for (param, arg) in template.params.iter().zip(&args) {
+            let found = match arg {
+                Expr::IntLiteral(_) => Type::Int,
+                Expr::FloatLiteral(_) => Type::Float,
+                Expr::StringLiteral(_) => Type::String,
+                Expr::BoolLiteral(_) => Type::Bool,
+                Expr::DateLiteral(_) => Type::Date,
+                Expr::CurrencyLiteral { .. } => Type::Currency,
+                _ => continue,
+            };
+            let accepted = match &param.param_type {
+                Some(expected) => *expected == found
+                    || (found == Type::Int && matches!(expected, Type::Float | Type::Currency)),
+                None => false,
+            };
+            if !accepted {
+                let expected = param.param_type.as_ref().map_or_else(|| "a table".to_string(), Type::to_string);
+                self.add_error_in(span, ErrorCode::E3008, format!(
+                    "Argument '{}' of template '{}' must be {}, found {}", param.name, name, expected, found
+                ));
+            }
         }
         
-        self.expect(TokenType::RightBrace)?;
-        Ok(FunctionDef { name, params, return_type, body })
+        self.expansions += 1;
+        let suffix = format!("{}_{}", name, self.expansions);
+        Ok(template::expand(&template, args, &suffix, &mut || self.next_id()))
     }
-
+    
     fn parse_external_function(&mut self) -> Result<ExternalFunction, ()> {
+        let start = self.peek().clone();
         self.expect(TokenType::External)?;
         self.expect(TokenType::Function)?;
         let name = self.expect_identifier()?;
+        let name = self.member_name(name);
         self.expect(TokenType::LeftParen)?;
         let params = self.parse_parameters()?;
         self.expect(TokenType::RightParen)?;
@@ -361,7 +1053,7 @@ impl Parser {
         self.expect(TokenType::From)?;
         let module = self.expect_string()?;
         
-        Ok(ExternalFunction { name, params, return_type, module })
+        Ok(ExternalFunction { name, params, return_type, module, id: self.next_id(), span: self.span_from(&start) })
     }
 
     fn parse_parameters(&mut self) -> Result<Vec<Parameter>, ()> {
@@ -377,27 +1069,87 @@ impl Parser {
             let param_type = self.parse_type()?;
             params.push(Parameter { name, param_type });
             
-            if !self.check(&TokenType::Comma) {
+            if !self.list_separator(&TokenType::RightParen) {
                 break;
             }
-            self.advance();
         }
         
         Ok(params)
     }
 
     fn parse_test(&mut self) -> Result<Test, ()> {
+        let start = self.peek().clone();
         self.expect(TokenType::Test)?;
         let name = self.expect_string()?;
+        let body = self.parse_block()?;
+        Ok(Test { name, body, id: self.next_id(), span: self.span_from(&start) })
+    }
+
+    fn parse_restriction(&mut self) -> Result<Restriction, ()> {
+        let start = self.peek().clone();
+        self.expect(TokenType::Restrict)?;
+        let table = self.expect_identifier()?;
+        self.expect(TokenType::Where)?;
+        let condition = self.parse_or()?;
+        Ok(Restriction { table, condition, id: self.next_id(), span: self.span_from(&start) })
+    }
+
+    fn parse_shared_variable(&mut self) -> Result<SharedVariable, ()> {
+        let start = self.peek().clone();
+        self.expect(TokenType::Shared)?;
+        self.expect(TokenType::Let)?;
+        let name = self.expect_identifier()?;
+        let type_annotation = if self.check(&TokenType::Colon) {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect(TokenType::Assign)?;
+        let value = self.parse_expression()?;
+        Ok(SharedVariable { name, type_annotation, value, id: self.next_id(), span: self.span_from(&start) })
+    }
+
+    fn parse_constant(&mut self) -> Result<Constant, ()> {
+        let start = self.advance().clone();
+        let name = self.expect_identifier()?;
+        let name = self.member_name(name);
+        let type_annotation = if self.check(&TokenType::Colon) {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect(TokenType::Assign)?;
+        let value = self.parse_expression()?;
+        Ok(Constant { name, type_annotation, value, id: self.next_id(), span: self.span_from(&start) })
+    }
+
+    /// `on_load Table { ... }` and the other hooks, after its keyword
+    fn parse_hook(&mut self, event: HookEvent) -> Result<Hook, ()> {
+        let start = self.advance().clone();
+        let table = self.expect_identifier()?;
+        let body = self.parse_block()?;
+        Ok(Hook { event, table, body, id: self.next_id(), span: self.span_from(&start) })
+    }
+
+    /// `config { name = "value" ... }`, settings optionally separated by commas
+    fn parse_config(&mut self) -> Result<Config, ()> {
+        let start = self.advance().clone();
         self.expect(TokenType::LeftBrace)?;
-        
-        let mut body = Vec::new();
+        let mut settings = Vec::new();
         while !self.check(&TokenType::RightBrace) {
-            body.push(self.parse_statement()?);
+            let setting_start = self.peek().clone();
+            let name = self.expect_identifier()?;
+            self.expect(TokenType::Assign)?;
+            let value = self.expect_string()?;
+            settings.push(ConfigSetting { name, value, id: self.next_id(), span: self.span_from(&setting_start) });
+            if self.check(&TokenType::Comma) {
+                self.advance();
+            }
         }
-        
         self.expect(TokenType::RightBrace)?;
-        Ok(Test { name, body })
+        Ok(Config { settings, id: self.next_id(), span: self.span_from(&start) })
     }
 
     fn parse_expression(&mut self) -> Result<Expr, ()> {
@@ -405,6 +1157,7 @@ impl Parser {
     }
 
     fn parse_chain(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_where_sort()?;
         
         while self.check(&TokenType::Arrow) {
@@ -413,6 +1166,8 @@ impl Parser {
             left = Expr::Chain {
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
@@ -420,6 +1175,7 @@ impl Parser {
     }
     
     fn parse_where_sort(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut expr = self.parse_or()?;
         
         loop {
@@ -430,15 +1186,18 @@ impl Parser {
                 expr = Expr::Where {
                     table: Box::new(expr),
                     condition: Box::new(condition),
+                    id: self.next_id(),
+                    span: self.span_since(start),
                 };
             } else if self.check_identifier_value("sort") {
-                // Parse: table sort by col1 [asc|desc], col2 [asc|desc], ...
+                // Parse: table sort by key1 [asc|desc], key2 [asc|desc], ...
+                // where each key is a column or an expression of columns
                 self.advance();
                 self.expect(TokenType::By)?;
                 
                 let mut columns = Vec::new();
                 loop {
-                    let col_name = self.expect_identifier()?;
+                    let key = self.parse_or()?;
                     let ascending = if self.check(&TokenType::Asc) {
                         self.advance();
                         true
@@ -448,18 +1207,93 @@ impl Parser {
                     } else {
                         true  // Default to ascending
                     };
-                    columns.push(SortColumn { name: col_name, ascending });
+                    columns.push(SortColumn { key, ascending });
                     
+                    // The list has no closing token, so a trailing comma
+                    // ends it when no key follows
                     if !self.check(&TokenType::Comma) {
                         break;
                     }
                     self.advance();
+                    if !self.check_identifier() && !self.check(&TokenType::LeftParen) {
+                        break;
+                    }
                 }
                 
                 expr = Expr::SortBy {
                     table: Box::new(expr),
                     columns,
+                    id: self.next_id(),
+                    span: self.span_since(start),
                 };
+            } else if self.check_identifier_value("group") {
+                // Parse: table group by col1, col2 having condition, or
+                // table where condition group by col1, col2 when the
+                // condition aggregates the groups' rows
+                self.require(Feature::GroupBy);
+                self.advance();
+                self.expect(TokenType::By)?;
+                
+                let mut columns = vec![self.expect_identifier()?];
+                while self.check(&TokenType::Comma) {
+                    self.advance();
+                    if !self.check_identifier() {
+                        break;
+                    }
+                    columns.push(self.expect_identifier()?);
+                }
+                
+                let (table, having) = match expr {
+                    Expr::Where { table, condition, .. }
+                        if condition.contains_group_aggregate() && !self.check_identifier_value("having") => (table, condition),
+                    table => {
+                        if !self.check_identifier_value("having") {
+                            self.add_error(
+                                ErrorCode::E2011,
+                                format!("Expected 'having' after the columns of 'group by', got {:?}", self.peek().token_type)
+                            );
+                            return Err(());
+                        }
+                        self.advance();
+                        (Box::new(table), Box::new(self.parse_or()?))
+                    }
+                };
+                expr = Expr::GroupBy { table, columns, having, id: self.next_id(), span: self.span_since(start) };
+            } else if self.check_identifier_value("rename") {
+                // Parse: table rename old1 to new1, old2 to new2, ...
+                self.advance();
+                
+                let mut columns = Vec::new();
+                loop {
+                    let from = self.expect_identifier()?;
+                    if !self.check_identifier_value("to") {
+                        self.add_error(
+                            ErrorCode::E2011,
+                            format!("Expected 'to' after the column to rename, got {:?}", self.peek().token_type)
+                        );
+                        return Err(());
+                    }
+                    self.advance();
+                    columns.push((from, self.expect_identifier()?));
+                    
+                    if !self.check_further_column() {
+                        break;
+                    }
+                    self.advance();
+                }
+                
+                expr = Expr::Rename { table: Box::new(expr), columns, id: self.next_id(), span: self.span_since(start) };
+            } else if self.check_identifier_value("drop") {
+                // Parse: table drop col1, col2, ...
+                self.advance();
+                
+                let mut columns = vec![self.expect_identifier()?];
+                while self.check_further_column() {
+                    self.advance();
+                    columns.push(self.expect_identifier()?);
+                }
+                
+                expr = Expr::DropColumns { table: Box::new(expr), columns, id: self.next_id(), span: self.span_since(start) };
             } else {
                 break;
             }
@@ -469,15 +1303,21 @@ impl Parser {
     }
 
     fn parse_or(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_and()?;
         
         while self.check(&TokenType::Or) {
+            let operator = self.current;
             self.advance();
             let right = self.parse_and()?;
+            self.check_precedence(&BinaryOp::Or, &left, start, operator - 1);
+            self.check_precedence(&BinaryOp::Or, &right, operator + 1, self.current - 1);
             left = Expr::BinaryOp {
                 op: BinaryOp::Or,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
@@ -485,15 +1325,21 @@ impl Parser {
     }
 
     fn parse_and(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_equality()?;
         
         while self.check(&TokenType::And) {
+            let operator = self.current;
             self.advance();
             let right = self.parse_equality()?;
+            self.check_precedence(&BinaryOp::And, &left, start, operator - 1);
+            self.check_precedence(&BinaryOp::And, &right, operator + 1, self.current - 1);
             left = Expr::BinaryOp {
                 op: BinaryOp::And,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
@@ -501,6 +1347,7 @@ impl Parser {
     }
 
     fn parse_equality(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_comparison()?;
         
         while self.check(&TokenType::Equals) || self.check(&TokenType::NotEquals) {
@@ -509,12 +1356,17 @@ impl Parser {
             } else {
                 BinaryOp::NotEqual
             };
+            let operator = self.current;
             self.advance();
             let right = self.parse_comparison()?;
+            self.check_precedence(&op, &left, start, operator - 1);
+            self.check_precedence(&op, &right, operator + 1, self.current - 1);
             left = Expr::BinaryOp {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
@@ -522,32 +1374,122 @@ impl Parser {
     }
 
     fn parse_comparison(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_addition()?;
         
-        while matches!(self.peek().token_type, 
-            TokenType::LessThan | TokenType::LessThanEquals | 
-            TokenType::GreaterThan | TokenType::GreaterThanEquals) {
+        loop {
+            // `between` and `is` are not keywords, so they stay usable as names
+            if self.check_identifier_value("between") {
+                self.require(Feature::Between);
+                self.advance();
+                let low = self.parse_addition()?;
+                if !self.check_identifier_value("and") {
+                    self.add_error(
+                        ErrorCode::E2011,
+                        format!("Expected 'and' between the bounds of 'between', got {:?}", self.peek().token_type)
+                    );
+                    return Err(());
+                }
+                self.advance();
+                let high = self.parse_addition()?;
+                left = Expr::Between {
+                    value: Box::new(left),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                    id: self.next_id(),
+                    span: self.span_since(start),
+                };
+                continue;
+            }
+            if self.check_identifier_value("is") {
+                self.require(Feature::NullCheck);
+                self.advance();
+                let negated = self.check_identifier_value("not");
+                if negated {
+                    self.advance();
+                }
+                if !self.check_identifier_value("null") {
+                    self.add_error(
+                        ErrorCode::E2011,
+                        format!("Expected 'null' after 'is', got {:?}", self.peek().token_type)
+                    );
+                    return Err(());
+                }
+                self.advance();
+                left = Expr::UnaryOp {
+                    op: if negated { UnaryOp::IsNotNull } else { UnaryOp::IsNull },
+                    operand: Box::new(left),
+                    id: self.next_id(),
+                    span: self.span_since(start),
+                };
+                continue;
+            }
+            if !matches!(self.peek().token_type, 
+                TokenType::LessThan | TokenType::LessThanEquals | 
+                TokenType::GreaterThan | TokenType::GreaterThanEquals | TokenType::In) {
+                break;
+            }
             
             let op = match self.peek().token_type {
                 TokenType::LessThan => BinaryOp::LessThan,
                 TokenType::LessThanEquals => BinaryOp::LessThanEqual,
                 TokenType::GreaterThan => BinaryOp::GreaterThan,
                 TokenType::GreaterThanEquals => BinaryOp::GreaterThanEqual,
+                TokenType::In => {
+                    self.require(Feature::Membership);
+                    BinaryOp::In
+                }
                 _ => unreachable!(),
             };
+            let operator = self.current;
             self.advance();
             let right = self.parse_addition()?;
+            self.check_precedence(&op, &left, start, operator - 1);
+            self.check_precedence(&op, &right, operator + 1, self.current - 1);
             left = Expr::BinaryOp {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
         Ok(left)
     }
 
+    /// Warn when an operand of `op`, spanning tokens `first..=last`, is an
+    /// unparenthesized operation whose grouping is easy to misread, such as
+    /// `b && c` in `a || b && c` or `a < b` in `a < b == c`
+    fn check_precedence(&mut self, op: &BinaryOp, operand: &Expr, first: usize, last: usize) {
+        let Expr::BinaryOp { op: inner, .. } = operand else { return };
+        let ambiguous = match op {
+            BinaryOp::Or => *inner == BinaryOp::And,
+            _ => op.is_comparison() && inner.is_comparison(),
+        };
+        if !ambiguous || self.parenthesized.contains(&(first, last)) {
+            return;
+        }
+        
+        let (first, last) = (&self.tokens[first], &self.tokens[last]);
+        let location = Location::new(first.line, first.column);
+        let fix = Fix {
+            message: "Add parentheses around the operand".to_string(),
+            edits: vec![
+                TextEdit::insert(location.clone(), "("),
+                TextEdit::insert(Location::new(last.end_line, last.end_column), ")"),
+            ],
+        };
+        let message = if op.is_comparison() {
+            format!("The result of `{}` is compared again with `{}`; add parentheses to make the grouping explicit", inner.symbol(), op.symbol())
+        } else {
+            format!("`{}` is applied before `{}`; add parentheses to make the grouping explicit", inner.symbol(), op.symbol())
+        };
+        self.diagnostics.add(Diagnostic::warning(ErrorCode::W1002, message, location).with_fix(fix));
+    }
+
     fn parse_addition(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_multiplication()?;
         
         while self.check(&TokenType::Plus) || self.check(&TokenType::Minus) {
@@ -562,6 +1504,8 @@ impl Parser {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
@@ -569,6 +1513,7 @@ impl Parser {
     }
 
     fn parse_multiplication(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut left = self.parse_unary()?;
         
         while self.check(&TokenType::Star) || self.check(&TokenType::Slash) || self.check(&TokenType::Percent) {
@@ -584,6 +1529,8 @@ impl Parser {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: self.next_id(),
+                span: self.span_since(start),
             };
         }
         
@@ -592,6 +1539,7 @@ impl Parser {
 
     fn parse_unary(&mut self) -> Result<Expr, ()> {
         if self.check(&TokenType::Not) || self.check(&TokenType::Minus) {
+            let start = self.current;
             let op = if self.check(&TokenType::Not) {
                 UnaryOp::Not
             } else {
@@ -602,6 +1550,8 @@ impl Parser {
             return Ok(Expr::UnaryOp {
                 op,
                 operand: Box::new(operand),
+                id: self.next_id(),
+                span: self.span_since(start),
             });
         }
         
@@ -609,6 +1559,7 @@ impl Parser {
     }
 
     fn parse_postfix(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
         let mut expr = self.parse_primary()?;
         
         loop {
@@ -618,35 +1569,30 @@ impl Parser {
                 expr = Expr::FieldAccess {
                     object: Box::new(expr),
                     field,
+                    id: self.next_id(),
+                    span: self.span_since(start),
                 };
-            } else if self.check(&TokenType::LeftBracket) {
+            } else if self.check(&TokenType::LeftBracket) && self.continues_line() {
                 self.advance();
                 
                 // Check if it's column selection [col1, col2] or index [expr]
-                // Column selection starts with identifier and may have commas
-                if self.check_identifier() {
+                // Column selection starts with identifier or `*` and may have commas
+                if self.check_identifier() || self.check(&TokenType::Star) {
                     // Try parsing as column selection
-                    let first_col = self.expect_identifier()?;
+                    let first_col = self.parse_selected_column()?;
                     
                     if self.check(&TokenType::Comma) {
                         // Multiple columns: definitely column selection
                         let mut columns = vec![first_col];
-                        while self.check(&TokenType::Comma) {
-                            self.advance();
-                            columns.push(self.expect_identifier()?);
+                        while self.list_separator(&TokenType::RightBracket) {
+                            columns.push(self.parse_selected_column()?);
                         }
                         self.expect(TokenType::RightBracket)?;
-                        expr = Expr::ColumnSelect {
-                            table: Box::new(expr),
-                            columns,
-                        };
+                        expr = self.column_select(expr, columns, start)?;
                     } else if self.check(&TokenType::RightBracket) {
                         // Single column: table[col]
                         self.advance();
-                        expr = Expr::ColumnSelect {
-                            table: Box::new(expr),
-                            columns: vec![first_col],
-                        };
+                        expr = self.column_select(expr, vec![first_col], start)?;
                     } else {
                         // Something else after identifier - not column selection
                         // This is actually an error, but we'll just fail
@@ -663,6 +1609,8 @@ impl Parser {
                     expr = Expr::Index {
                         object: Box::new(expr),
                         index: Box::new(index),
+                        id: self.next_id(),
+                        span: self.span_since(start),
                     };
                 }
             } else {
@@ -689,32 +1637,91 @@ impl Parser {
                 self.advance();
                 Ok(Expr::StringLiteral(s.clone()))
             },
+            // Only page text interpolates; other strings keep their braces
+            TokenType::InterpolatedString(parts) => {
+                let s = StringPart::join(parts);
+                self.advance();
+                Ok(Expr::StringLiteral(s))
+            },
             TokenType::BoolLiteral(b) => {
                 self.advance();
                 Ok(Expr::BoolLiteral(*b))
             },
-            TokenType::Identifier(name) => {
-                let name = name.clone();
+            TokenType::DateLiteral(date) => {
+                let date = date.clone();
+                self.require(Feature::DateLiteral);
+                self.advance();
+                Ok(Expr::DateLiteral(date))
+            },
+            TokenType::CurrencyLiteral { amount, currency } => {
+                let (amount, currency) = (amount.clone(), currency.clone());
+                self.require(Feature::CurrencyLiteral);
                 self.advance();
+                Ok(Expr::CurrencyLiteral { amount, currency })
+            },
+            TokenType::Filter if matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::LeftParen)) => {
+                // Parse filter literal: filter(column, single/multi)
+                self.advance();
+                self.expect(TokenType::LeftParen)?;
+                let column = self.expect_string()?;
+                self.expect(TokenType::Comma)?;
+                
+                let mode_token = self.advance().clone();
+                let mode = match &mode_token.token_type {
+                    TokenType::Single => FilterMode::Single,
+                    TokenType::Multi => FilterMode::Multi,
+                    _ => {
+                        self.add_error(
+                            ErrorCode::E2011,
+                            format!("Expected 'single' or 'multi', got {:?}", mode_token.token_type)
+                        );
+                        return Err(());
+                    }
+                };
+                
+                if self.check(&TokenType::Comma) {
+                    self.advance();
+                }
+                self.expect(TokenType::RightParen)?;
+                Ok(Expr::FilterLiteral(FilterDef { column, mode }))
+            },
+            // `where(table, row => condition)`, the call form of `table where condition`
+            TokenType::Where if matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::LeftParen)) => {
+                self.advance();
+                self.advance();
+                let name = Atom::new("where");
+                let args = self.parse_arguments(name)?;
+                self.expect(TokenType::RightParen)?;
+                Ok(Expr::FunctionCall(FunctionCall { name, args, id: self.next_id(), span: self.span_from(&token) }))
+            },
+            _ if self.at_lambda() => self.parse_lambda(),
+            _ if self.at_match() => {
+                let (value, arms) = self.parse_match(|parser| parser.parse_expression())?;
+                Ok(Expr::Match { value: Box::new(value), arms, id: self.next_id(), span: self.span_from(&token) })
+            },
+            _ if self.check_identifier() => {
+                let name = self.expect_name()?;
                 
                 // Check for function call
-                if self.check(&TokenType::LeftParen) {
+                if self.check(&TokenType::LeftParen) && self.continues_line() {
                     self.advance();
-                    let args = self.parse_arguments()?;
+                    let args = self.parse_arguments(name)?;
                     self.expect(TokenType::RightParen)?;
-                    Ok(Expr::FunctionCall(FunctionCall { name, args }))
+                    Ok(Expr::FunctionCall(FunctionCall { name, args, id: self.next_id(), span: self.span_from(&token) }))
                 } else {
                     Ok(Expr::Identifier(name))
                 }
             },
             TokenType::Underscore => {
                 self.advance();
-                Ok(Expr::Identifier("_".to_string()))
+                Ok(Expr::Identifier(Atom::new("_")))
             },
             TokenType::LeftParen => {
+                let open = self.current;
                 self.advance();
                 let expr = self.parse_expression()?;
                 self.expect(TokenType::RightParen)?;
+                self.parenthesized.insert((open, self.current - 1));
                 Ok(expr)
             },
             TokenType::LeftBracket => {
@@ -732,36 +1739,31 @@ impl Parser {
                 elements.push(self.parse_expression()?);
                 
                 // Parse remaining elements
-                while self.check(&TokenType::Comma) {
-                    self.advance(); // consume comma
+                while self.list_separator(&TokenType::RightBracket) {
                     elements.push(self.parse_expression()?);
                 }
                 
                 self.expect(TokenType::RightBracket)?;
                 Ok(Expr::ArrayLiteral(elements))
             },
-            TokenType::Filter => {
-                // Parse filter literal: filter(column, single/multi)
+            TokenType::LeftBrace => {
+                // Parse row literal: { field: expr, ... }
                 self.advance();
-                self.expect(TokenType::LeftParen)?;
-                let column = self.expect_string()?;
-                self.expect(TokenType::Comma)?;
+                let mut fields = Vec::new();
                 
-                let mode_token = self.advance().clone();
-                let mode = match &mode_token.token_type {
-                    TokenType::Single => FilterMode::Single,
-                    TokenType::Multi => FilterMode::Multi,
-                    _ => {
-                        self.add_error(
-                            ErrorCode::E2011,
-                            format!("Expected 'single' or 'multi', got {:?}", mode_token.token_type)
-                        );
-                        return Err(());
+                while !self.check(&TokenType::RightBrace) {
+                    let name = self.expect_identifier()?;
+                    self.expect(TokenType::Colon)?;
+                    fields.push((name, self.parse_expression()?));
+                    
+                    if !self.check(&TokenType::Comma) {
+                        break;
                     }
-                };
+                    self.advance(); // consume comma
+                }
                 
-                self.expect(TokenType::RightParen)?;
-                Ok(Expr::FilterLiteral(FilterDef { column, mode }))
+                self.expect(TokenType::RightBrace)?;
+                Ok(Expr::TableLiteral(fields))
             },
             _ => {
                 self.add_error(
@@ -772,24 +1774,196 @@ impl Parser {
             }
         }
     }
-
-    fn parse_arguments(&mut self) -> Result<Vec<Expr>, ()> {
-        let mut args = Vec::new();
+
+    fn parse_arguments(&mut self, function: Atom) -> Result<Vec<Expr>, ()> {
+        let mut args = Vec::new();
+        
+        if self.check(&TokenType::RightParen) {
+            return Ok(args);
+        }
+        
+        loop {
+            args.push(self.parse_expression()?);
+            
+            // share(table, "column" by "group") passes the group as the
+            // next argument
+            if function == "share" && self.check(&TokenType::By) {
+                self.advance();
+                args.push(self.parse_expression()?);
+            }
+            
+            if !self.list_separator(&TokenType::RightParen) {
+                break;
+            }
+        }
+        
+        Ok(args)
+    }
+    
+    /// Parse a column of a selection: a name, or `*` and the columns it
+    /// leaves out, which run to the end of the selection
+    fn parse_selected_column(&mut self) -> Result<SelectedColumn, ()> {
+        if !self.check(&TokenType::Star) {
+            return Ok(SelectedColumn::Name(self.expect_identifier()?));
+        }
+        self.require(Feature::ColumnPattern);
+        self.advance();
+        
+        let mut except = Vec::new();
+        if self.check_identifier_value("except") {
+            self.advance();
+            except.push(self.expect_identifier()?);
+            while self.check(&TokenType::Comma) && !matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::RightBracket)) {
+                self.advance();
+                except.push(self.expect_identifier()?);
+            }
+        }
+        Ok(SelectedColumn::Rest { except })
+    }
+    
+    /// A column selection, which can have only one `*`, of the table
+    /// starting at token `start`
+    fn column_select(&mut self, table: Expr, columns: Vec<SelectedColumn>, start: usize) -> Result<Expr, ()> {
+        if columns.iter().filter(|column| matches!(column, SelectedColumn::Rest { .. })).count() > 1 {
+            self.add_error(ErrorCode::E2011, "A column selection can have only one '*'".to_string());
+            return Err(());
+        }
+        Ok(Expr::ColumnSelect { table: Box::new(table), columns, id: self.next_id(), span: self.span_since(start) })
+    }
+    
+    /// Whether a lambda starts here: `row =>` or `(a, b) =>`
+    fn at_lambda(&self) -> bool {
+        let token = |offset: usize| self.tokens.get(self.current + offset).map(|t| &t.token_type);
+        let is_name = |token: &TokenType| matches!(token, TokenType::Identifier(_)) || token.contextual_keyword().is_some();
+        match token(0) {
+            Some(first) if is_name(first) => matches!(token(1), Some(TokenType::FatArrow)),
+            Some(TokenType::LeftParen) => {
+                let mut offset = 1;
+                loop {
+                    match token(offset) {
+                        Some(TokenType::RightParen) => return matches!(token(offset + 1), Some(TokenType::FatArrow)),
+                        Some(TokenType::Comma) => offset += 1,
+                        Some(next) if is_name(next) => offset += 1,
+                        _ => return false,
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+    
+    /// Parse `row => body` or `(a, b) => body`
+    fn parse_lambda(&mut self) -> Result<Expr, ()> {
+        self.require(Feature::Lambda);
+        let mut params = Vec::new();
+        if self.check(&TokenType::LeftParen) {
+            self.advance();
+            if !self.check(&TokenType::RightParen) {
+                params.push(self.expect_identifier()?);
+                while self.list_separator(&TokenType::RightParen) {
+                    params.push(self.expect_identifier()?);
+                }
+            }
+            self.expect(TokenType::RightParen)?;
+        } else {
+            params.push(self.expect_identifier()?);
+        }
+        self.expect(TokenType::FatArrow)?;
+        let body = self.parse_expression()?;
+        Ok(Expr::Lambda { params, body: Box::new(body) })
+    }
+    
+    /// Whether `match` starts a match here rather than naming something:
+    /// the value matched follows on the same line
+    fn at_match(&self) -> bool {
+        if !self.check_identifier_value("match") {
+            return false;
+        }
+        let line = self.peek().end_line;
+        self.tokens.get(self.current + 1).is_some_and(|next| next.line == line && (matches!(
+            next.token_type,
+            TokenType::Identifier(_) | TokenType::IntLiteral(_) | TokenType::FloatLiteral(_)
+                | TokenType::StringLiteral(_) | TokenType::InterpolatedString(_)
+                | TokenType::BoolLiteral(_) | TokenType::DateLiteral(_)
+                | TokenType::CurrencyLiteral { .. } | TokenType::Not
+        ) || next.token_type.contextual_keyword().is_some()))
+    }
+    
+    /// Parse `match value { patterns => body ... }`, the arms' bodies with
+    /// `parse_body`; arms may be separated by commas
+    fn parse_match<T>(&mut self, mut parse_body: impl FnMut(&mut Self) -> Result<T, ()>) -> Result<(Expr, Vec<MatchArm<T>>), ()> {
+        self.require(Feature::Match);
+        self.advance();
+        let value = self.parse_expression()?;
+        self.expect(TokenType::LeftBrace)?;
         
-        if self.check(&TokenType::RightParen) {
-            return Ok(args);
+        let mut arms = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            if self.is_at_end() || self.at_item_start() {
+                // Reports the missing `}`
+                self.expect(TokenType::RightBrace)?;
+            }
+            let start = self.peek().clone();
+            let mut patterns = vec![self.parse_match_pattern()?];
+            while self.check(&TokenType::Comma) {
+                self.advance();
+                patterns.push(self.parse_match_pattern()?);
+            }
+            self.expect(TokenType::FatArrow)?;
+            let body = parse_body(self)?;
+            arms.push(MatchArm { patterns, body, span: self.span_from(&start) });
+            if self.check(&TokenType::Comma) {
+                self.advance();
+            }
         }
         
-        loop {
-            args.push(self.parse_expression()?);
-            
-            if !self.check(&TokenType::Comma) {
-                break;
-            }
+        self.expect(TokenType::RightBrace)?;
+        Ok((value, arms))
+    }
+    
+    /// A literal, possibly negative, or `_`
+    fn parse_match_pattern(&mut self) -> Result<MatchPattern, ()> {
+        let negative = self.check(&TokenType::Minus);
+        if negative {
             self.advance();
         }
-        
-        Ok(args)
+        let literal = match &self.peek().token_type {
+            TokenType::Underscore if !negative => {
+                self.advance();
+                return Ok(MatchPattern::Wildcard);
+            }
+            TokenType::Identifier(name) if name.as_str() == "_" && !negative => {
+                self.advance();
+                return Ok(MatchPattern::Wildcard);
+            }
+            TokenType::IntLiteral(_) | TokenType::FloatLiteral(_) | TokenType::CurrencyLiteral { .. } => self.parse_primary()?,
+            TokenType::StringLiteral(_) | TokenType::InterpolatedString(_) | TokenType::BoolLiteral(_)
+                | TokenType::DateLiteral(_) if !negative => self.parse_primary()?,
+            _ => {
+                self.add_error(
+                    ErrorCode::E2001,
+                    format!("Expected a literal or '_' as a match pattern, got {:?}", self.peek().token_type)
+                );
+                return Err(());
+            }
+        };
+        Ok(MatchPattern::Literal(match literal {
+            Expr::IntLiteral(n) if negative => Expr::IntLiteral(-n),
+            Expr::FloatLiteral(f) if negative => Expr::FloatLiteral(-f),
+            Expr::CurrencyLiteral { amount, currency } if negative => Expr::CurrencyLiteral { amount: format!("-{}", amount), currency },
+            literal => literal,
+        }))
+    }
+    
+    /// Consume the comma between two items of a list closed by `close`
+    ///
+    /// False at the end of the list, which may follow a trailing comma.
+    fn list_separator(&mut self, close: &TokenType) -> bool {
+        if !self.check(&TokenType::Comma) {
+            return false;
+        }
+        self.advance();
+        !self.check(close)
     }
 
     // Helper methods
@@ -807,6 +1981,25 @@ impl Parser {
     fn is_at_end(&self) -> bool {
         matches!(self.peek().token_type, TokenType::Eof)
     }
+    
+    /// Id of the next node of the current item
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId::new(self.item, self.next_index);
+        self.next_index += 1;
+        id
+    }
+    
+    /// Span from the given start token to the most recently consumed token
+    fn span_from(&self, start: &Token) -> Span {
+        let end = &self.tokens[self.current.saturating_sub(1)];
+        Span::new(start.line, start.column, end.line, end.column)
+    }
+    
+    /// Span from the token at index `start` to the most recently consumed
+    /// token
+    fn span_since(&self, start: usize) -> Span {
+        self.span_from(&self.tokens[start])
+    }
 
     fn check(&self, token_type: &TokenType) -> bool {
         if self.is_at_end() {
@@ -815,11 +2008,22 @@ impl Parser {
         std::mem::discriminant(&self.peek().token_type) == std::mem::discriminant(token_type)
     }
     
+    /// Whether the next token is a name: an identifier or a contextual keyword
     fn check_identifier(&self) -> bool {
         if self.is_at_end() {
             return false;
         }
         matches!(self.peek().token_type, TokenType::Identifier(_))
+            || self.peek().token_type.contextual_keyword().is_some()
+    }
+    
+    /// Whether a comma and another column name follow, rather than a
+    /// further argument such as `filter("region", single)`
+    fn check_further_column(&self) -> bool {
+        let token = |offset: usize| self.tokens.get(self.current + offset).map(|t| &t.token_type);
+        self.check(&TokenType::Comma)
+            && token(1).is_some_and(|next| matches!(next, TokenType::Identifier(_)) || next.contextual_keyword().is_some())
+            && !matches!(token(2), Some(TokenType::LeftParen))
     }
     
     fn check_identifier_value(&self, value: &str) -> bool {
@@ -842,10 +2046,14 @@ impl Parser {
         }
     }
 
-    fn expect_identifier(&mut self) -> Result<String, ()> {
+    fn expect_identifier(&mut self) -> Result<Atom, ()> {
+        if let Some(word) = self.peek().token_type.contextual_keyword() {
+            self.advance();
+            return Ok(Atom::new(word));
+        }
         match &self.peek().token_type {
             TokenType::Identifier(name) => {
-                let name = name.clone();
+                let name = *name;
                 self.advance();
                 Ok(name)
             },
@@ -859,6 +2067,86 @@ impl Parser {
         }
     }
 
+    /// A string literal, or `t("key")` for text looked up in the locale files
+    fn parse_page_text(&mut self) -> Result<PageText, ()> {
+        if matches!(&self.peek().token_type, TokenType::Identifier(name) if name.as_str() == "t") {
+            self.advance();
+            self.expect(TokenType::LeftParen)?;
+            let key = self.expect_string()?;
+            self.expect(TokenType::RightParen)?;
+            return Ok(PageText::Translated(key));
+        }
+        let TokenType::InterpolatedString(parts) = &self.peek().token_type else {
+            return self.expect_string().map(PageText::Literal);
+        };
+        let parts = parts.clone();
+        let mut text = Vec::new();
+        for part in parts {
+            match part {
+                StringPart::Text(s) => text.push(TextPart::Text(s)),
+                StringPart::Code { source, line, column } => {
+                    // The expression's positions and node ids continue the
+                    // program's, the code starting just after the `{`
+                    let expr = Lexer::new(&source).tokenize().ok().and_then(|tokens| {
+                        let tokens = tokens.into_iter().map(|token| placed(token, line, column + 1)).collect();
+                        let mut parser = self.nested(tokens);
+                        parser.item = self.item;
+                        parser.next_index = self.next_index;
+                        let expr = parser.parse_standalone_expression().ok();
+                        self.next_index = parser.next_index;
+                        expr
+                    });
+                    match expr {
+                        Some(expr) => {
+                            let span = Span::new(line, column, line, column + source.chars().count() + 2);
+                            text.push(TextPart::Expr { expr, span });
+                        }
+                        None => self.diagnostics.add_error(
+                            ErrorCode::E2019,
+                            format!("Invalid expression '{{{}}}' in interpolated text", source),
+                            Location::new(line, column),
+                        ),
+                    }
+                }
+            }
+        }
+        self.advance();
+        Ok(PageText::Interpolated(text))
+    }
+    
+    /// A parser of code nested in the program's, such as the expressions
+    /// of interpolated text, that knows its modules
+    fn nested(&self, tokens: Vec<Token>) -> Parser {
+        let mut parser = Parser::new(tokens);
+        parser.modules = self.modules.clone();
+        parser.namespace = self.namespace.clone();
+        parser
+    }
+    
+    /// A name used in an expression: `module.member` for a member of an
+    /// imported module, and the names of the module being read's own
+    /// functions and constants qualified with its name
+    fn expect_name(&mut self) -> Result<Atom, ()> {
+        let name = self.expect_identifier()?;
+        let member = self.modules.contains(&name)
+            && self.check(&TokenType::Dot)
+            && matches!(self.tokens.get(self.current + 1).map(|t| &t.token_type), Some(TokenType::Identifier(_)));
+        if member {
+            self.advance();
+            let member = self.expect_identifier()?;
+            return Ok(Atom::new(&format!("{}.{}", name, member)));
+        }
+        Ok(self.member_name(name))
+    }
+    
+    /// The name of a function or constant of the module being read
+    fn member_name(&self, name: Atom) -> Atom {
+        match &self.namespace {
+            Some((module, members)) if members.contains(&name) => Atom::new(&format!("{}.{}", module, name)),
+            _ => name,
+        }
+    }
+    
     fn expect_string(&mut self) -> Result<String, ()> {
         match &self.peek().token_type {
             TokenType::StringLiteral(s) => {
@@ -866,6 +2154,11 @@ impl Parser {
                 self.advance();
                 Ok(s)
             },
+            TokenType::InterpolatedString(parts) => {
+                let s = StringPart::join(parts);
+                self.advance();
+                Ok(s)
+            },
             _ => {
                 self.add_error(
                     ErrorCode::E2011,
@@ -877,6 +2170,20 @@ impl Parser {
     }
 }
 
+/// A token of code lexed on its own, moved to where the code starts in the
+/// program: 1-based `line` and `column`
+fn placed(mut token: Token, line: usize, column: usize) -> Token {
+    if token.line == 1 {
+        token.column += column - 1;
+    }
+    if token.end_line == 1 {
+        token.end_column += column - 1;
+    }
+    token.line += line - 1;
+    token.end_line += line - 1;
+    token
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -944,7 +2251,7 @@ mod tests {
             ProgramItem::Page(page) => {
                 assert_eq!(page.statements.len(), 1);
                 match &page.statements[0] {
-                    Statement::Let { name, type_annotation, value } => {
+                    Statement::Let { name, type_annotation, value, .. } => {
                         assert_eq!(name, "x");
                         assert!(type_annotation.is_some());
                         assert!(value.is_some());
@@ -968,7 +2275,7 @@ mod tests {
         match &program.items[0] {
             ProgramItem::Page(page) => {
                 match &page.statements[0] {
-                    Statement::Let { name, type_annotation, value } => {
+                    Statement::Let { name, type_annotation, value, .. } => {
                         assert_eq!(name, "result");
                         assert!(type_annotation.is_some());
                         assert!(value.is_none());
@@ -994,7 +2301,7 @@ mod tests {
             ProgramItem::Page(page) => {
                 assert_eq!(page.statements.len(), 2);
                 match &page.statements[1] {
-                    Statement::Assign { name, value } => {
+                    Statement::Assign { name, value, .. } => {
                         assert_eq!(name, "x");
                         assert!(matches!(value, Expr::IntLiteral(_)));
                     },
@@ -1065,7 +2372,7 @@ mod tests {
         match &program.items[0] {
             ProgramItem::Page(page) => {
                 match &page.statements[0] {
-                    Statement::If { condition: _, then_branch, else_branch } => {
+                    Statement::If { condition: _, then_branch, else_branch, .. } => {
                         assert_eq!(then_branch.len(), 1);
                         assert!(else_branch.is_some());
                         assert_eq!(else_branch.as_ref().unwrap().len(), 1);
@@ -1112,7 +2419,7 @@ mod tests {
         match &program.items[0] {
             ProgramItem::Page(page) => {
                 match &page.statements[0] {
-                    Statement::FunctionCall(FunctionCall { name, args }) => {
+                    Statement::FunctionCall(FunctionCall { name, args, .. }) => {
                         assert_eq!(name, "display");
                         assert_eq!(args.len(), 1);
                     },
@@ -1123,6 +2430,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_interpolated_text() {
+        let source = "page Home {\n    text \"Total: {sum(orders, \\\"amount\\\")} of {order.id}\"\n    title \"Plain \\{braces}\"\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        let Statement::Text(PageText::Interpolated(parts)) = &page.statements[0] else { panic!("expected interpolated text") };
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], TextPart::Text("Total: ".to_string()));
+        assert!(matches!(&parts[1], TextPart::Expr { expr: Expr::FunctionCall(call), span } if call.name == "sum" && span.column == 18));
+        assert!(matches!(&parts[3], TextPart::Expr { expr: Expr::FieldAccess { field, .. }, .. } if field == "id"));
+        assert_eq!(page.statements[1], Statement::Title(PageText::Literal("Plain {braces}".to_string())));
+        
+        let mut lexer = Lexer::new("page Home {\n    text \"Total {1 +} here\"\n}");
+        let diagnostics = Parser::new(lexer.tokenize().unwrap()).parse().unwrap_err();
+        let codes: Vec<(ErrorCode, usize, usize)> = diagnostics.diagnostics().iter()
+            .map(|d| (d.code, d.location.line, d.location.column))
+            .collect();
+        assert_eq!(codes, vec![(ErrorCode::E2019, 2, 17)]);
+    }
+
+    #[test]
+    fn test_parse_display_statement() {
+        let source = r#"
+            page Test {
+                display user.id
+                let display = 1
+            }
+        "#;
+        let program = parse_source(source).unwrap();
+        
+        match &program.items[0] {
+            ProgramItem::Page(page) => {
+                match &page.statements[0] {
+                    Statement::FunctionCall(FunctionCall { name, args, .. }) => {
+                        assert_eq!(name, "display");
+                        assert!(matches!(args.as_slice(), [Expr::FieldAccess { .. }]));
+                    },
+                    _ => panic!("Expected function call"),
+                }
+                assert!(matches!(page.statements[1], Statement::Let { .. }));
+            },
+            _ => panic!("Expected Page item"),
+        }
+    }
+
     #[test]
     fn test_parse_binary_expression() {
         let source = r#"
@@ -1159,7 +2511,7 @@ mod tests {
                 match &page.statements[0] {
                     Statement::FunctionCall(FunctionCall { args, .. }) => {
                         match &args[0] {
-                            Expr::FieldAccess { object: _, field } => {
+                            Expr::FieldAccess { object: _, field, .. } => {
                                 assert_eq!(field, "name");
                             },
                             _ => panic!("Expected field access"),
@@ -1238,4 +2590,565 @@ mod tests {
         let program = parse_source(source).unwrap();
         assert_eq!(program.items.len(), 3); // table, function, page
     }
+
+    #[test]
+    fn test_parse_standalone_expression() {
+        let mut lexer = Lexer::new("1 + 2 * x");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let expr = parser.parse_standalone_expression().unwrap();
+        assert!(matches!(expr, Expr::BinaryOp { op: BinaryOp::Add, .. }));
+        
+        let mut lexer = Lexer::new("1 + 2 )");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert!(parser.parse_standalone_expression().is_err());
+    }
+
+    #[test]
+    fn test_parse_assert_and_mock() {
+        let source = "test \"totals\" {\n    mock Orders with sample()\n    assert total() == 3\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Test(test) = &program.items[0] else { panic!("expected a test") };
+        assert!(matches!(&test.body[0], Statement::Mock { target, .. } if target == "Orders"));
+        assert!(matches!(&test.body[1], Statement::Assert { condition: Expr::BinaryOp { .. }, .. }));
+        
+        assert!(parse_source("test \"t\" {\n    mock Orders sample()\n}").is_err());
+    }
+
+    #[test]
+    fn test_node_ids() {
+        let source = "page A {\n    let x = 1\n    show(x)\n}\npage B {\n    let y = 2\n}";
+        let program = parse_source(source).unwrap();
+        assert_eq!(program.items[0].id(), NodeId::new(0, 2));
+        let ProgramItem::Page(a) = &program.items[0] else { panic!("expected a page") };
+        let ids: Vec<_> = a.statements.iter().filter_map(Statement::id).collect();
+        assert_eq!(ids, vec![NodeId::new(0, 0), NodeId::new(0, 1)]);
+        
+        // Nodes of one item keep their ids when another item changes
+        let edited = parse_source(&source.replace("show(x)", "show(x); text \"more\"; show(x)")).unwrap();
+        assert_eq!(edited.items[1], program.items[1]);
+        assert_eq!(edited.items[1].id(), NodeId::new(1, 1));
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let source = "/// Orders placed online\n/// by customers\ntable Order {\n    /// Not kept\n    id: int\n}\n\n/// Sum of two numbers\nfunction add(a: int, b: int) -> int {\n    return a + b\n}\n\npage Home {\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::TableDef(table) = &program.items[0] else { panic!("expected a table") };
+        assert_eq!(table.doc.as_deref(), Some("Orders placed online\nby customers"));
+        assert_eq!(table.span.line, 3);
+        let ProgramItem::FunctionDef(func) = &program.items[1] else { panic!("expected a function") };
+        assert_eq!(func.doc.as_deref(), Some("Sum of two numbers"));
+        let ProgramItem::Page(page) = &program.items[2] else { panic!("expected a page") };
+        assert_eq!(page.doc, None);
+    }
+
+    #[test]
+    fn test_parse_refresh() {
+        let source = "page Dashboard {\n    refresh every 5m\n    refresh = 2\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        assert_eq!(page.statements[0], Statement::Refresh { seconds: 300, id: NodeId::new(0, 0), span: Span::new(2, 5, 2, 20) });
+        assert!(matches!(&page.statements[1], Statement::Assign { name, .. } if name == "refresh"));
+        
+        assert!(parse_source("page P {\n    refresh every 5 minutes\n}").is_err());
+        assert!(parse_source("page P {\n    refresh every 0s\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let source = "config {\n    title = \"Sales\", theme = \"dark\"\n    data_dir = \"data\"\n}\npage Home {\n    let config = 1\n    text \"{config}\"\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Config(config) = &program.items[0] else { panic!("expected a config block") };
+        let settings: Vec<(&str, &str)> = config.settings.iter().map(|s| (s.name.as_str(), s.value.as_str())).collect();
+        assert_eq!(settings, vec![("title", "Sales"), ("theme", "dark"), ("data_dir", "data")]);
+        assert_eq!(config.settings[1].span, Span::new(2, 22, 2, 30));
+        assert!(matches!(&program.items[1], ProgramItem::Page(_)));
+        
+        assert!(parse_source("config {\n    title: \"Sales\"\n}").is_err());
+        assert!(parse_source("config {\n    title = 3\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_recovers_inside_blocks() {
+        let source = "page Home {\n    let x = * 2\n    button \"Go\" {\n        show(orders, , 1)\n        text \"done\"\n    }\n    let y: = 2\n    title \"Home\"\n}\npage Other {\n    text 3\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        let lines: Vec<usize> = diagnostics.diagnostics().iter().map(|d| d.location.line).collect();
+        assert_eq!(lines, vec![2, 4, 7, 11]);
+        
+        // A block that is never closed is reported once, and the next item still parses
+        let source = "page Home {\n    if x {\n        text \"a\"\npage Other {\n    text 3\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        let lines: Vec<usize> = diagnostics.diagnostics().iter().map(|d| d.location.line).collect();
+        assert_eq!(lines, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_precedence_warnings() {
+        let warnings = |source: &str| {
+            let mut lexer = Lexer::new(source);
+            let mut parser = Parser::new(lexer.tokenize().unwrap());
+            parser.parse_standalone_expression().unwrap();
+            parser.diagnostics()
+        };
+        
+        let source = "a || b && c";
+        let diagnostics = warnings(source);
+        assert_eq!(diagnostics.warning_count(), 1);
+        let diagnostic = &diagnostics.diagnostics()[0];
+        assert_eq!(diagnostic.code, ErrorCode::W1002);
+        assert_eq!(diagnostic.location.column, 6);
+        assert_eq!(crate::errors::apply_fixes(source, diagnostic.fix.as_ref()), "a || (b && c)");
+        
+        let source = "x < y == z >= w";
+        let diagnostics = warnings(source);
+        let fixes = diagnostics.diagnostics().iter().filter_map(|d| d.fix.as_ref());
+        assert_eq!(crate::errors::apply_fixes(source, fixes), "(x < y) == (z >= w)");
+        
+        assert!(warnings("a || (b && c)").is_empty());
+        assert_eq!(warnings("(a && b) || c && (d || e)").warning_count(), 1);
+        assert!(warnings("a + b * c == d && e").is_empty());
+    }
+
+    #[test]
+    fn test_parse_contextual_keywords() {
+        let source = "table Note {\n    key: int [key]\n    text: text\n    date: date\n}\npage Home {\n    let text = \"x\"\n    text = \"y\"\n    text \"Latest: {text}\"\n    show(notes where text != \"\" sort by date desc)\n    show(notes[key, text])\n    show(notes, filter(\"text\", single))\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::TableDef(table) = &program.items[0] else { panic!("expected a table") };
+        let names: Vec<&str> = table.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["key", "text", "date"]);
+        assert_eq!(table.fields[0].constraints, vec![Constraint::Key]);
+        assert_eq!(table.fields[2].field_type, Type::Date);
+        
+        let ProgramItem::Page(page) = &program.items[1] else { panic!("expected a page") };
+        assert!(matches!(&page.statements[0], Statement::Let { name, .. } if name == "text"));
+        assert!(matches!(&page.statements[1], Statement::Assign { name, .. } if name == "text"));
+        assert!(matches!(&page.statements[2], Statement::Text(_)));
+        let Statement::FunctionCall(call) = &page.statements[3] else { panic!("expected a call") };
+        let Expr::SortBy { table, columns, .. } = &call.args[0] else { panic!("expected a sort") };
+        assert_eq!(columns[0].column(), Some(Atom::new("date")));
+        assert!(!columns[0].ascending);
+        assert!(matches!(&**table, Expr::Where { condition, .. }
+            if matches!(&**condition, Expr::BinaryOp { left, .. } if **left == Expr::Identifier(Atom::new("text")))));
+        let Statement::FunctionCall(call) = &page.statements[5] else { panic!("expected a call") };
+        assert!(matches!(&call.args[1], Expr::FilterLiteral(_)));
+    }
+
+    #[test]
+    fn test_parse_field_header() {
+        let source = "table Sale {\n    id: int [key]\n    revenue: currency from \"Total Revenue (€)\" [non_null]\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::TableDef(table) = &program.items[0] else { panic!("expected a table") };
+        assert_eq!(table.fields[0].header, None);
+        assert_eq!(table.fields[1].header.as_deref(), Some("Total Revenue (€)"));
+        assert_eq!(table.fields[1].field_type, Type::Currency);
+        assert_eq!(table.fields[1].constraints, vec![Constraint::NonNull]);
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let source = "table Order {\n    customer_id: int [references Customer.id, non_null]\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::TableDef(table) = &program.items[0] else { panic!("expected a table") };
+        assert_eq!(table.fields[0].constraints, vec![
+            Constraint::References { table: Atom::new("Customer"), field: Atom::new("id") },
+            Constraint::NonNull,
+        ]);
+    }
+
+    #[test]
+    fn test_parse_trailing_commas() {
+        let source = "table Order {\n    id: int [key, non_null,],\n    total: float,\n}\nfunction pick(a: int, b: int,) -> int {\n    return max(a, b,)\n}\npage Home {\n    let orders = load_csv(\"orders.csv\", Order,)\n    let ids = [1, 2, 3,]\n    show(orders[id, total,], filter(\"total\", multi,))\n    show(orders sort by total desc, id,)\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::TableDef(table) = &program.items[0] else { panic!("expected a table") };
+        assert_eq!(table.fields[0].constraints, vec![Constraint::Key, Constraint::NonNull]);
+        let ProgramItem::FunctionDef(function) = &program.items[1] else { panic!("expected a function") };
+        assert_eq!(function.params.len(), 2);
+        assert!(matches!(&function.body[0], Statement::Return(Expr::FunctionCall(call)) if call.args.len() == 2));
+        
+        let ProgramItem::Page(page) = &program.items[2] else { panic!("expected a page") };
+        assert!(matches!(&page.statements[0], Statement::Let { value: Some(Expr::FunctionCall(call)), .. } if call.args.len() == 2));
+        assert!(matches!(&page.statements[1], Statement::Let { value: Some(Expr::ArrayLiteral(items)), .. } if items.len() == 3));
+        let Statement::FunctionCall(call) = &page.statements[2] else { panic!("expected a call") };
+        assert!(matches!(&call.args[0], Expr::ColumnSelect { columns, .. } if columns.len() == 2));
+        assert!(matches!(&call.args[1], Expr::FilterLiteral(FilterDef { mode: FilterMode::Multi, .. })));
+        let Statement::FunctionCall(call) = &page.statements[3] else { panic!("expected a call") };
+        assert!(matches!(&call.args[0], Expr::SortBy { columns, .. } if columns.len() == 2));
+    }
+
+    #[test]
+    fn test_parse_sort_by_expression() {
+        let source = "page Home {\n    show(orders sort by amount / quantity desc, (amount - discount), id,)\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        let Statement::FunctionCall(call) = &page.statements[0] else { panic!("expected a call") };
+        let Expr::SortBy { columns, .. } = &call.args[0] else { panic!("expected a sort") };
+        assert_eq!(columns.len(), 3);
+        assert!(matches!(&columns[0].key, Expr::BinaryOp { op: BinaryOp::Divide, .. }));
+        assert!(!columns[0].ascending);
+        assert!(matches!(&columns[1].key, Expr::BinaryOp { op: BinaryOp::Subtract, .. }));
+        assert_eq!(columns[2].column(), Some(Atom::new("id")));
+    }
+
+    #[test]
+    fn test_parse_column_patterns() {
+        let mut lexer = Lexer::new("users[name, * except password, salt,]");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::ColumnSelect { columns, .. } = parser.parse_standalone_expression().unwrap() else { panic!("expected a selection") };
+        assert_eq!(columns, vec![
+            SelectedColumn::Name(Atom::new("name")),
+            SelectedColumn::Rest { except: vec![Atom::new("password"), Atom::new("salt")] },
+        ]);
+        
+        let mut lexer = Lexer::new("users[*, id]");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::ColumnSelect { columns, .. } = parser.parse_standalone_expression().unwrap() else { panic!("expected a selection") };
+        assert_eq!(columns, vec![SelectedColumn::Rest { except: vec![] }, SelectedColumn::Name(Atom::new("id"))]);
+        
+        let mut lexer = Lexer::new("page Home {\n    show(users[*, name, *])\n}");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        assert_eq!(diagnostics.diagnostics()[0].code, ErrorCode::E2011);
+    }
+
+    #[test]
+    fn test_parse_rename_and_drop() {
+        let source = "page Home {\n    show(orders rename amount to revenue, note to comment drop internal_notes, id, filter(\"region\", single))\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        let Statement::FunctionCall(call) = &page.statements[0] else { panic!("expected a call") };
+        assert_eq!(call.args.len(), 2);
+        let Expr::DropColumns { table, columns, .. } = &call.args[0] else { panic!("expected a drop") };
+        assert_eq!(columns, &vec![Atom::new("internal_notes"), Atom::new("id")]);
+        let Expr::Rename { columns, .. } = table.as_ref() else { panic!("expected a rename") };
+        assert_eq!(columns, &vec![(Atom::new("amount"), Atom::new("revenue")), (Atom::new("note"), Atom::new("comment"))]);
+        
+        let mut lexer = Lexer::new("page Home {\n    show(orders rename amount revenue)\n}");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        assert_eq!(diagnostics.diagnostics()[0].code, ErrorCode::E2011);
+    }
+
+    #[test]
+    fn test_parse_statement_separators() {
+        let source = "page Home {\n    ; title \"Orders\"; show(orders);\n    let x = orders\n    [id]\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        let codes: Vec<(ErrorCode, usize)> = diagnostics.diagnostics().iter().map(|d| (d.code, d.location.line)).collect();
+        assert_eq!(codes, vec![(ErrorCode::E2018, 4)]);
+        
+        let source = "page Home {\n    title \"Orders\" show(orders)\n    show\n        (orders)\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        let codes: Vec<(ErrorCode, usize)> = diagnostics.diagnostics().iter().map(|d| (d.code, d.location.line)).collect();
+        assert_eq!(codes, vec![(ErrorCode::E2017, 2), (ErrorCode::E2018, 4)]);
+        let fixes: Vec<&Fix> = diagnostics.diagnostics().iter().filter_map(|d| d.fix.as_ref()).collect();
+        assert_eq!(
+            crate::errors::apply_fixes(source, fixes),
+            "page Home {\n    title \"Orders\"; show(orders)\n    show(orders)\n}"
+        );
+    }
+
+    #[test]
+    fn test_parse_membership() {
+        let mut lexer = Lexer::new("status in [\"open\", \"paid\"] && total > 2");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::BinaryOp { op: BinaryOp::And, left, .. } = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected &&")
+        };
+        let Expr::BinaryOp { op: BinaryOp::In, left, right, .. } = *left else { panic!("expected in") };
+        assert_eq!(*left, Expr::Identifier(Atom::new("status")));
+        assert!(matches!(*right, Expr::ArrayLiteral(ref items) if items.len() == 2));
+    }
+
+    #[test]
+    fn test_parse_between_and_null_checks() {
+        let mut lexer = Lexer::new("amount between 10 and 100 && note is not null");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::BinaryOp { op: BinaryOp::And, left, right, .. } = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected &&")
+        };
+        let Expr::Between { value, low, high, .. } = *left else { panic!("expected between") };
+        assert_eq!(*value, Expr::Identifier(Atom::new("amount")));
+        assert_eq!(*low, Expr::IntLiteral(10));
+        assert_eq!(*high, Expr::IntLiteral(100));
+        assert!(matches!(*right, Expr::UnaryOp { op: UnaryOp::IsNotNull, .. }));
+        
+        let mut lexer = Lexer::new("note is null");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert!(matches!(parser.parse_standalone_expression().unwrap(), Expr::UnaryOp { op: UnaryOp::IsNull, .. }));
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        let mut lexer = Lexer::new("orders group by region, status having sum(amount) > 1000");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::GroupBy { table, columns, having, .. } = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected group by")
+        };
+        assert_eq!(*table, Expr::Identifier(Atom::new("orders")));
+        assert_eq!(columns, vec![Atom::new("region"), Atom::new("status")]);
+        assert!(having.contains_group_aggregate());
+        
+        // A where condition aggregating the groups becomes their condition
+        let mut lexer = Lexer::new("orders where count() > 2 group by region");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::GroupBy { table, having, .. } = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected group by")
+        };
+        assert_eq!(*table, Expr::Identifier(Atom::new("orders")));
+        assert!(matches!(*having, Expr::BinaryOp { op: BinaryOp::GreaterThan, .. }));
+        
+        let mut lexer = Lexer::new("orders where status == \"open\" group by region having count() > 2");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::GroupBy { table, .. } = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected group by")
+        };
+        assert!(matches!(*table, Expr::Where { .. }));
+    }
+
+    #[test]
+    fn test_parse_row_literal() {
+        let mut lexer = Lexer::new("{ id: 3, name: \"Bob\" }");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::TableLiteral(fields) = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected a row literal")
+        };
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].0, "id");
+        assert_eq!(fields[1].1, Expr::StringLiteral("Bob".to_string()));
+        
+        let mut lexer = Lexer::new("{}");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert_eq!(parser.parse_standalone_expression().unwrap(), Expr::TableLiteral(Vec::new()));
+    }
+
+    #[test]
+    fn test_parse_match() {
+        let mut lexer = Lexer::new("match status { \"new\", \"open\" => 1, -2 => 2, _ => 3 }");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::Match { value, arms, .. } = parser.parse_standalone_expression().unwrap() else {
+            panic!("expected a match")
+        };
+        assert_eq!(*value, Expr::Identifier(Atom::new("status")));
+        assert_eq!(arms.len(), 3);
+        assert_eq!(arms[0].patterns.len(), 2);
+        assert_eq!(arms[1].patterns, vec![MatchPattern::Literal(Expr::IntLiteral(-2))]);
+        assert!(arms[2].is_wildcard());
+        assert_eq!(arms[2].body, Expr::IntLiteral(3));
+        
+        let program = parse_source("page Test {\n    match done {\n        true => { text \"x\" }\n        false => {}\n    }\n}").unwrap();
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        let Statement::Match { arms, .. } = &page.statements[0] else { panic!("expected a match") };
+        assert_eq!(arms[0].body.len(), 1);
+        assert!(arms[1].body.is_empty());
+        
+        // Still a name elsewhere
+        let mut lexer = Lexer::new("match + 1");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert!(matches!(parser.parse_standalone_expression().unwrap(), Expr::BinaryOp { .. }));
+    }
+
+    #[test]
+    fn test_parse_lambda() {
+        let mut lexer = Lexer::new("where(orders, row => row.amount > 100)");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::FunctionCall(call) = parser.parse_standalone_expression().unwrap() else { panic!("expected a call") };
+        assert_eq!(call.name, "where");
+        let Expr::Lambda { params, body } = &call.args[1] else { panic!("expected a lambda") };
+        assert_eq!(params, &vec![Atom::new("row")]);
+        assert!(matches!(body.as_ref(), Expr::BinaryOp { op: BinaryOp::GreaterThan, .. }));
+        
+        let mut lexer = Lexer::new("(a, b) => a + b");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let Expr::Lambda { params, .. } = parser.parse_standalone_expression().unwrap() else { panic!("expected a lambda") };
+        assert_eq!(params, vec![Atom::new("a"), Atom::new("b")]);
+        
+        // Still a parenthesized expression without the arrow
+        let mut lexer = Lexer::new("(a) + b");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert!(matches!(parser.parse_standalone_expression().unwrap(), Expr::BinaryOp { op: BinaryOp::Add, .. }));
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let source = "template total_of(t: table, col: string) {\n    let total = sum(t, col)\n    text \"{total}\"\n    show(where(t, total => total.amount > 1))\n}\npage Home {\n    let total = 0\n    use total_of(orders, \"amount\")\n    use total_of(refunds, \"amount\")\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let program = parser.parse().unwrap();
+        assert_eq!(program.items.len(), 1);
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        assert_eq!(page.statements.len(), 7);
+        
+        // The template's variable is renamed apart for each use, and its
+        // parameters replaced by the arguments
+        let Statement::Let { name, value: Some(Expr::FunctionCall(call)), id, .. } = &page.statements[4] else { panic!("expected a let") };
+        assert_eq!(name.as_str(), "total__total_of_2");
+        assert_eq!(call.args, vec![Expr::Identifier(Atom::new("refunds")), Expr::StringLiteral("amount".to_string())]);
+        let Statement::Let { name, id: first_id, .. } = &page.statements[1] else { panic!("expected a let") };
+        assert_eq!(name.as_str(), "total__total_of_1");
+        assert_ne!(id, first_id);
+        
+        // A lambda's parameter shadows the template's variable
+        let Statement::FunctionCall(show) = &page.statements[3] else { panic!("expected show") };
+        let Expr::FunctionCall(filter) = &show.args[0] else { panic!("expected where") };
+        let Expr::Lambda { params, body } = &filter.args[1] else { panic!("expected a lambda") };
+        assert_eq!(params, &vec![Atom::new("total")]);
+        assert!(matches!(body.as_ref(), Expr::BinaryOp { left, .. }
+            if matches!(left.as_ref(), Expr::FieldAccess { object, .. } if **object == Expr::Identifier(Atom::new("total")))));
+    }
+
+    #[test]
+    fn test_parse_array_types() {
+        let source = "function names(grid: int[][]) -> string[] {\n    return [\"a\"]\n}\ntable Tag {\n    label: string [key]\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let program = parser.parse().unwrap();
+        let ProgramItem::FunctionDef(function) = &program.items[0] else { panic!("expected a function") };
+        assert_eq!(function.params[0].param_type, Type::Array(Box::new(Type::Array(Box::new(Type::Int)))));
+        assert_eq!(function.return_type, Type::Array(Box::new(Type::String)));
+        assert_eq!(function.return_type.to_string(), "string[]");
+        let ProgramItem::TableDef(table) = &program.items[1] else { panic!("expected a table") };
+        assert_eq!(table.fields[0].field_type, Type::String);
+        assert_eq!(table.fields[0].constraints, vec![Constraint::Key]);
+        
+        // Tables hold arrays as rows, not fields
+        let mut lexer = Lexer::new("table Tag {\n    labels: string[]\n}");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_constant() {
+        let source = "const TAX_RATE: float = 0.22\nconst LABEL = \"VAT\"\npage Home {\n    let const = TAX_RATE\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let program = parser.parse().unwrap();
+        let ProgramItem::Constant(constant) = &program.items[0] else { panic!("expected a constant") };
+        assert_eq!(constant.name, "TAX_RATE");
+        assert_eq!(constant.type_annotation, Some(Type::Float));
+        assert_eq!(constant.value, Expr::FloatLiteral(0.22));
+        assert!(matches!(&program.items[1], ProgramItem::Constant(c) if c.type_annotation.is_none()));
+        
+        // `const` stays usable as a name
+        let ProgramItem::Page(page) = &program.items[2] else { panic!("expected a page") };
+        assert!(matches!(&page.statements[0], Statement::Let { name, .. } if name == "const"));
+    }
+
+    #[test]
+    fn test_parse_import() {
+        let source = "import \"common.wt\"\npage Home {\n    show(orders)\n}";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).with_first_item(3).parse().unwrap();
+        let ProgramItem::Import(import) = &program.items[0] else { panic!("expected an import") };
+        assert_eq!(import.path, "common.wt");
+        assert_eq!(import.id, NodeId::new(3, 0));
+        assert_eq!(program.items[1].id().item, 4);
+        
+        assert!(parse_source("import common").is_err());
+    }
+
+    #[test]
+    fn test_parse_module_names() {
+        // Members of imported modules are single qualified names; other
+        // field accesses are not
+        let source = "import analytics from \"analytics.wt\"\npage Home {\n    let kpi = analytics.compute_kpi(analytics.RATE)\n    text \"{analytics.RATE} {order.total}\"\n}";
+        let program = parse_source(source).unwrap();
+        let ProgramItem::Import(import) = &program.items[0] else { panic!("expected an import") };
+        assert_eq!(import.module, Some(Atom::new("analytics")));
+        let ProgramItem::Page(page) = &program.items[1] else { panic!("expected a page") };
+        let Statement::Let { value: Some(Expr::FunctionCall(call)), .. } = &page.statements[0] else { panic!("expected a call") };
+        assert_eq!(call.name, "analytics.compute_kpi");
+        assert!(matches!(&call.args[0], Expr::Identifier(name) if name == "analytics.RATE"));
+        let Statement::Text(PageText::Interpolated(parts)) = &page.statements[1] else { panic!("expected text") };
+        assert!(matches!(&parts[0], TextPart::Expr { expr: Expr::Identifier(name), .. } if name == "analytics.RATE"));
+        assert!(matches!(&parts[2], TextPart::Expr { expr: Expr::FieldAccess { .. }, .. }));
+        
+        // A module's own functions and constants are named after it
+        let source = "const RATE = 2\nfunction double(n: int) -> int {\n    return n * RATE\n}\nfunction quadruple(n: int) -> int {\n    return double(double(n))\n}";
+        let members = HashSet::from([Atom::new("RATE"), Atom::new("double"), Atom::new("quadruple")]);
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).with_namespace(Atom::new("math"), members).parse().unwrap();
+        let ProgramItem::Constant(constant) = &program.items[0] else { panic!("expected a constant") };
+        assert_eq!(constant.name, "math.RATE");
+        let ProgramItem::FunctionDef(func) = &program.items[2] else { panic!("expected a function") };
+        assert_eq!(func.name, "math.quadruple");
+        let Statement::Return(Expr::FunctionCall(call)) = &func.body[0] else { panic!("expected a call") };
+        assert_eq!(call.name, "math.double");
+    }
+
+    #[test]
+    fn test_parse_python_block() {
+        let source = "page Home {\n    let python = 1\n    python(t: table, n: int) -> (top: table(Sale)) {\n        top = t.head(n)  # {\n        if n:\n            st.write('}')\n    }\n    python {\n    }\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let program = parser.parse().unwrap();
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        assert!(matches!(&page.statements[0], Statement::Let { name, .. } if name == "python"));
+        let Statement::Python { inputs, outputs, code, .. } = &page.statements[1] else { panic!("expected a python block") };
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[0].binding_type, None);
+        assert_eq!(inputs[1].binding_type, Some(Type::Int));
+        assert_eq!(outputs[0].binding_type, Some(Type::Table(Atom::new("Sale").into())));
+        assert_eq!(code, "top = t.head(n)  # {\nif n:\n    st.write('}')");
+        assert!(matches!(&page.statements[2], Statement::Python { code, .. } if code.is_empty()));
+        
+        // Outputs are declared with the table they hold
+        let mut lexer = Lexer::new("page Home {\n    python -> (top: table) {\n        top = None\n    }\n}");
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_language_version() {
+        let source = "#version \"0.1\"\npage Home {\n    let x = match 1 { _ => 2 }\n    show(orders where total between 1 and 5)\n}";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer.tokenize().unwrap());
+        let diagnostics = parser.parse().unwrap_err();
+        let codes: Vec<(ErrorCode, usize)> = diagnostics.diagnostics().iter().map(|d| (d.code, d.location.line)).collect();
+        assert_eq!(codes, vec![(ErrorCode::E2020, 3), (ErrorCode::E2020, 4)]);
+        assert_eq!(
+            diagnostics.diagnostics()[0].message,
+            "`match` requires language version 0.2, but the program is written for 0.1"
+        );
+        
+        // The same program as the current version, pinned or not
+        let program = parse_source(&source.replace("0.1", "0.2")).unwrap();
+        assert_eq!(program.items.len(), 1);
+        let mut lexer = Lexer::new("page Home {\n    let x = match 1 { _ => 2 }\n}");
+        let mut parser = Parser::new(lexer.tokenize().unwrap()).with_language_version(LanguageVersion::V0_1);
+        assert!(parser.parse().is_err());
+        
+        for source in ["#version \"9.9\"\npage Home {}", "page Home {}\n#version \"0.1\""] {
+            let mut lexer = Lexer::new(source);
+            let mut parser = Parser::new(lexer.tokenize().unwrap());
+            let diagnostics = parser.parse().unwrap_err();
+            assert_eq!(diagnostics.diagnostics()[0].code, ErrorCode::E2021, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_block_spans() {
+        let source = "page Test {\n    forall row in rows {\n        text \"x\"\n    }\n}";
+        let program = parse_source(source).unwrap();
+        
+        match &program.items[0] {
+            ProgramItem::Page(page) => {
+                assert_eq!(page.span, Span::new(1, 1, 5, 1));
+                match &page.statements[0] {
+                    Statement::Forall { var, span, .. } => {
+                        assert_eq!(*var, "row");
+                        assert_eq!(*span, Span::new(2, 5, 4, 5));
+                    },
+                    _ => panic!("Expected Forall statement"),
+                }
+            },
+            _ => panic!("Expected Page item"),
+        }
+    }
 }
@@ -0,0 +1,280 @@
+// Builtin functions added by plugins
+//
+// Lets organizations expose their own Python utilities as builtins that are
+// checked like the language's: calls are checked against the builtin's
+// signature, the plugin may reject arguments its utility cannot take, and
+// each backend generates the call from the plugin's code for it. Most
+// builtins are declared in a `[builtins.<name>]` section of the project's
+// `wt.toml`, as a `ManifestBuiltin`; a Rust plugin implements
+// `BuiltinPlugin` when it needs validation beyond the argument types.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use crate::ast::{Expr, Parameter, ProgramItem, Type};
+use crate::{Atom, Lexer, Parser};
+
+/// A builtin function added to the language
+pub trait BuiltinPlugin: Send + Sync {
+    fn name(&self) -> Atom;
+
+    /// Parameters, in the order calls pass them
+    fn params(&self) -> &[Parameter];
+
+    fn return_type(&self) -> &Type;
+
+    /// Why a call with these arguments is invalid beyond their types, e.g.
+    /// a literal the utility does not accept
+    fn validate(&self, _args: &[Expr]) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Lines the generated code needs on a backend to call the builtin,
+    /// such as `from acme.finance import pct_change`
+    fn imports(&self, _backend: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Code calling the builtin on a backend, given the code of its
+    /// arguments; `None` when the plugin does not support the backend
+    fn generate(&self, backend: &str, args: &[String]) -> Option<String>;
+}
+
+/// The builtins added to the language for a program
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Arc<dyn BuiltinPlugin>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        PluginRegistry::default()
+    }
+
+    /// Add a builtin; each name can be added once
+    pub fn register(&mut self, plugin: impl BuiltinPlugin + 'static) -> Result<(), String> {
+        if self.get(plugin.name().as_str()).is_some() {
+            return Err(format!("builtin '{}' is registered twice", plugin.name()));
+        }
+        self.plugins.push(Arc::new(plugin));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn BuiltinPlugin>> {
+        self.plugins.iter().find(|plugin| plugin.name() == name).cloned()
+    }
+
+    /// The builtins, in the order they were registered
+    pub fn iter(&self) -> impl Iterator<Item = &dyn BuiltinPlugin> {
+        self.plugins.iter().map(|plugin| plugin.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+}
+
+impl fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.plugins.iter().map(|plugin| plugin.name())).finish()
+    }
+}
+
+/// A builtin declared in a project's `wt.toml`:
+///
+/// ```toml
+/// [builtins.pct_change]
+/// signature = "(old: float, new: float) -> float"
+/// import = "from acme.finance import pct_change"
+/// streamlit = "pct_change({old}, {new})"
+/// ```
+///
+/// Every setting besides `signature` and `import` is the code of a call on
+/// the backend it is named after, with `{param}` for each argument.
+#[derive(Debug, Clone)]
+pub struct ManifestBuiltin {
+    name: Atom,
+    params: Vec<Parameter>,
+    return_type: Type,
+    imports: Vec<String>,
+    templates: BTreeMap<String, String>,
+}
+
+impl ManifestBuiltin {
+    pub fn new(name: &str, settings: &BTreeMap<String, String>) -> Result<Self, String> {
+        let signature = settings.get("signature")
+            .ok_or_else(|| format!("builtin '{}' has no signature", name))?;
+        let (params, return_type) = parse_signature(name, signature)?;
+        let imports = settings.get("import")
+            .map(|lines| lines.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        let templates: BTreeMap<String, String> = settings.iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "signature" | "import"))
+            .map(|(backend, template)| (backend.clone(), template.clone()))
+            .collect();
+        if templates.is_empty() {
+            return Err(format!("builtin '{}' has no code for any backend, such as `streamlit = \"...\"`", name));
+        }
+        Ok(ManifestBuiltin { name: Atom::from(name), params, return_type, imports, templates })
+    }
+
+    /// Backends the builtin has code for
+    pub fn backends(&self) -> impl Iterator<Item = &str> {
+        self.templates.keys().map(String::as_str)
+    }
+}
+
+impl BuiltinPlugin for ManifestBuiltin {
+    fn name(&self) -> Atom {
+        self.name
+    }
+
+    fn params(&self) -> &[Parameter] {
+        &self.params
+    }
+
+    fn return_type(&self) -> &Type {
+        &self.return_type
+    }
+
+    fn imports(&self, backend: &str) -> Vec<String> {
+        if self.templates.contains_key(backend) {
+            self.imports.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn generate(&self, backend: &str, args: &[String]) -> Option<String> {
+        let template = self.templates.get(backend)?;
+        let mut code = String::new();
+        let mut rest = template.as_str();
+        while let Some(open) = rest.find('{') {
+            code.push_str(&rest[..open]);
+            rest = &rest[open..];
+            let argument = rest.find('}').and_then(|close| {
+                let name = &rest[1..close];
+                let index = self.params.iter().position(|param| param.name == name)?;
+                Some((close, args.get(index)?))
+            });
+            match argument {
+                Some((close, arg)) => {
+                    code.push_str(arg);
+                    rest = &rest[close + 1..];
+                }
+                // Braces of the backend's own code, such as a dict
+                None => {
+                    code.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        code.push_str(rest);
+        Some(code)
+    }
+}
+
+/// Parameters and return type of a signature like `(x: int) -> float`,
+/// read as the signature of an external function
+fn parse_signature(name: &str, signature: &str) -> Result<(Vec<Parameter>, Type), String> {
+    let invalid = |reason: String| format!("invalid signature of builtin '{}': {}", name, reason);
+    let source = format!("external function {}{} from \"plugin\"", name, signature);
+    let first_message = |diagnostics: crate::DiagnosticBag| {
+        diagnostics.diagnostics().first().map(|d| d.message.clone()).unwrap_or_default()
+    };
+    let tokens = Lexer::new(&source).tokenize().map_err(|d| invalid(first_message(d)))?;
+    let program = Parser::new(tokens).parse().map_err(|d| invalid(first_message(d)))?;
+    match program.items.as_slice() {
+        [ProgramItem::ExternalFunction(function)] => Ok((function.params.clone(), function.return_type.clone())),
+        _ => Err(invalid(format!("expected `(param: type, ...) -> type`, found `{}`", signature))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_manifest_builtin() {
+        let builtin = ManifestBuiltin::new("pct_change", &settings(&[
+            ("signature", "(old: float, new: float) -> float"),
+            ("import", "from acme.finance import pct_change"),
+            ("streamlit", "pct_change({old}, {new}, **{\"round\": 2})"),
+        ])).unwrap();
+        assert_eq!(builtin.params().len(), 2);
+        assert_eq!(builtin.params()[1].param_type, Type::Float);
+        assert_eq!(builtin.return_type(), &Type::Float);
+        assert_eq!(builtin.imports("streamlit"), vec!["from acme.finance import pct_change"]);
+        assert_eq!(
+            builtin.generate("streamlit", &["a".to_string(), "b + 1".to_string()]).as_deref(),
+            Some("pct_change(a, b + 1, **{\"round\": 2})")
+        );
+        assert_eq!(builtin.generate("dash", &[]), None);
+
+        let mut registry = PluginRegistry::new();
+        registry.register(builtin.clone()).unwrap();
+        assert!(registry.get("pct_change").is_some());
+        assert!(registry.register(builtin).is_err());
+    }
+
+    #[test]
+    fn test_manifest_builtin_errors() {
+        let error = ManifestBuiltin::new("f", &settings(&[("streamlit", "f()")])).unwrap_err();
+        assert_eq!(error, "builtin 'f' has no signature");
+        let error = ManifestBuiltin::new("f", &settings(&[("signature", "(x: int)"), ("streamlit", "f({x})")])).unwrap_err();
+        assert!(error.starts_with("invalid signature of builtin 'f': "), "{}", error);
+        assert!(ManifestBuiltin::new("f", &settings(&[("signature", "() -> int")])).is_err());
+    }
+
+    /// A plugin taking a currency code it knows
+    struct FxRate {
+        params: Vec<Parameter>,
+    }
+
+    impl BuiltinPlugin for FxRate {
+        fn name(&self) -> Atom {
+            Atom::from("fx_rate")
+        }
+
+        fn params(&self) -> &[Parameter] {
+            &self.params
+        }
+
+        fn return_type(&self) -> &Type {
+            &Type::Float
+        }
+
+        fn validate(&self, args: &[Expr]) -> Vec<String> {
+            match args.first() {
+                Some(Expr::StringLiteral(code)) if code.len() != 3 => vec![format!("unknown currency \"{}\"", code)],
+                _ => Vec::new(),
+            }
+        }
+
+        fn generate(&self, _backend: &str, args: &[String]) -> Option<String> {
+            Some(format!("fx.rate({})", args.join(", ")))
+        }
+    }
+
+    #[test]
+    fn test_checked_calls() {
+        let mut plugins = PluginRegistry::new();
+        plugins.register(FxRate {
+            params: vec![Parameter { name: Atom::from("currency"), param_type: Type::String }],
+        }).unwrap();
+        let source = "page Home {\n    let a = fx_rate(\"EUR\")\n    let b = fx_rate(\"euro\")\n    let c = fx_rate(1)\n    let d = fx_rate()\n    text \"{a} {b} {c} {d}\"\n}\n";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let mut analyzer = crate::SemanticAnalyzer::new().with_plugins(plugins);
+        let errors = analyzer.analyze(&program).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|error| format!("{}: {}", error.code().code(), error)).collect();
+        assert_eq!(messages, vec![
+            "E3049: Invalid call of fx_rate(): unknown currency \"euro\"",
+            "E3008: Argument 'currency' of fx_rate() must be string, found int",
+            "E3010: fx_rate() takes 1 argument(s), found 0",
+        ]);
+    }
+}
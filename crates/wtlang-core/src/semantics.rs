@@ -2,44 +2,313 @@
 // Type checking, symbol table building, and validation
 
 use crate::ast::*;
+use crate::errors::{Diagnostic, DiagnosticBag, ErrorCode, Location, Severity};
+use crate::intern::Atom;
+use crate::plugins::{BuiltinPlugin, PluginRegistry};
 use crate::symbols::*;
+use crate::type_map::TypeMap;
+use crate::eval::{eval_const_with, EvalError, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Levels accepted by the `log` builtin
+pub const LOG_LEVELS: &[&str] = &["info", "warn", "error"];
+
+/// Settings of a `config` block
+pub const CONFIG_SETTINGS: &[&str] = &[
+    "title", "theme", "data_dir",
+    "decimal_separator", "thousands_separator", "date_format", "currency_symbol", "currency_position",
+];
+
+/// Modes `save_sql` writes a table to a database in
+pub const SAVE_MODES: &[&str] = &["append", "replace", "upsert"];
+
+/// How `load_csv` matches the headers of a file to a table's columns
+pub const COLUMN_MATCHING: &[&str] = &["exact", "normalize"];
+
+/// How `sum` and `average` treat missing values: skipped, or an error
+pub const NULL_HANDLING: &[&str] = &["skip_nulls", "strict"];
+
+/// Python's keywords
+pub const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+    "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+    "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+    "return", "try", "while", "with", "yield",
+];
+
+/// Names besides Python's keywords that a program's variables, functions
+/// and constants cannot have in the generated Python: the builtins the
+/// generated code calls, and the names it imports. Such names, and
+/// keywords, are generated with a trailing `_`.
+pub const PYTHON_RESERVED_NAMES: &[&str] = &[
+    "abs", "all", "any", "bool", "dict", "enumerate", "filter", "float", "format", "getattr",
+    "hasattr", "id", "input", "int", "isinstance", "len", "list", "map", "max", "min",
+    "next", "object", "open", "print", "range", "repr", "round", "set", "sorted", "str",
+    "sum", "super", "tuple", "type", "zip",
+    "st", "pd", "pa", "Decimal", "date", "datetime", "DataFrame", "Series", "pytest",
+    "monkeypatch", "helpers", "wt_runtime", "show_filtered",
+];
+
+/// Whether a name is generated with a trailing `_` in Python
+pub fn is_python_reserved(name: &str) -> bool {
+    PYTHON_KEYWORDS.contains(&name) || PYTHON_RESERVED_NAMES.contains(&name)
+}
+
+/// Builtins whose first argument is a number
+const NUMERIC_FUNCTIONS: &[&str] = &["round", "floor", "ceil", "abs", "percent", "format_number", "format_currency"];
+
+/// Whether a `format_number` pattern such as `"#,##0.00"` groups thousands,
+/// and how many decimals it shows; `None` if it is not a pattern
+pub fn number_pattern(pattern: &str) -> Option<(bool, usize)> {
+    let (whole, fraction) = pattern.split_once('.').unwrap_or((pattern, ""));
+    let valid_whole = !whole.is_empty()
+        && whole.chars().all(|c| matches!(c, '#' | '0' | ','))
+        && !whole.starts_with(',')
+        && !whole.ends_with(',');
+    let valid_fraction = fraction.chars().all(|c| matches!(c, '#' | '0'))
+        && fraction.is_empty() != pattern.contains('.');
+    (valid_whole && valid_fraction).then(|| (whole.contains(','), fraction.len()))
+}
 
 pub struct SemanticAnalyzer {
     symbols: SymbolTable,
     errors: Vec<SemanticError>,
+    /// Node and source span of each entry in `errors`
+    error_sites: Vec<(NodeId, Span)>,
+    warnings: Vec<SemanticError>,
+    warning_sites: Vec<(NodeId, Span)>,
+    /// Span of the innermost construct being checked, used to locate errors
+    current_span: Span,
+    /// Node of the innermost construct being checked
+    current_node: NodeId,
+    types: TypeMap,
+    /// Variables declared in the item being checked, with their `let`
+    declared_variables: HashMap<(ScopeId, Atom), (NodeId, Span)>,
+    /// Variables read in the item being checked
+    used_variables: HashSet<(ScopeId, Atom)>,
+    /// Section titles of the page being checked, for `export_pdf`
+    page_sections: Vec<String>,
+    /// Builtins added by plugins
+    plugins: PluginRegistry,
+}
+
+/// Results of checking a single top-level item
+///
+/// Captured by `check_item` so an unchanged item can be restored with
+/// `reuse_item` instead of being checked again.
+#[derive(Debug, Clone, Default)]
+pub struct ItemAnalysis {
+    errors: Vec<SemanticError>,
+    error_sites: Vec<(NodeId, Span)>,
+    warnings: Vec<SemanticError>,
+    warning_sites: Vec<(NodeId, Span)>,
+    types: Vec<(NodeId, Span, Type)>,
+    scopes: Vec<Scope>,
+}
+
+impl ItemAnalysis {
+    pub fn errors(&self) -> &[SemanticError] {
+        &self.errors
+    }
+    
+    pub fn warnings(&self) -> &[SemanticError] {
+        &self.warnings
+    }
+    
+    /// The same results for the program's `item`th item, `lines` lines
+    /// further down, for an item that an edit above it moved without
+    /// changing it
+    pub fn moved(&self, item: u32, lines: isize) -> ItemAnalysis {
+        let move_sites = |sites: &[(NodeId, Span)]| sites.iter().map(|(node, span)| (node.in_item(item), span.moved(lines))).collect();
+        ItemAnalysis {
+            errors: self.errors.clone(),
+            error_sites: move_sites(&self.error_sites),
+            warnings: self.warnings.clone(),
+            warning_sites: move_sites(&self.warning_sites),
+            types: self.types.iter().map(|(node, span, ty)| (node.in_item(item), span.moved(lines), ty.clone())).collect(),
+            scopes: self.scopes.iter().map(|scope| scope.moved(item, lines)).collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum SemanticError {
     UndefinedVariable {
-        name: String,
+        name: Atom,
     },
     Redefinition {
-        name: String,
+        name: Atom,
     },
     TypeMismatch {
         expected: String,
         found: String,
     },
     UninitializedVariable {
-        name: String,
+        name: Atom,
     },
     MissingTypeOrInitializer {
-        name: String,
+        name: Atom,
     },
     MultipleKeyFields {
-        table_name: String,
-        key_fields: Vec<String>,
+        table_name: Atom,
+        key_fields: Vec<Atom>,
     },
     UndefinedReferenceTarget {
-        field_name: String,
-        table_name: String,
-        target_table: String,
+        field_name: Atom,
+        table_name: Atom,
+        target_table: Atom,
     },
     ReferenceToTableWithoutKey {
-        field_name: String,
-        table_name: String,
-        target_table: String,
+        field_name: Atom,
+        table_name: Atom,
+        target_table: Atom,
+    },
+    UnusedVariable {
+        name: Atom,
+    },
+    PythonReservedName {
+        name: Atom,
+    },
+    MockOutsideTest {
+        target: Atom,
+    },
+    InvalidMockTarget {
+        target: Atom,
+    },
+    InvalidLogLevel {
+        level: String,
+    },
+    TranslationKeyNotLiteral,
+    UnknownExportSection {
+        section: Option<String>,
+    },
+    InvalidSaveMode {
+        mode: String,
+    },
+    ConfirmOutsideButton,
+    UndefinedRestrictedTable {
+        table: Atom,
+    },
+    UnknownRestrictionColumn {
+        table: Atom,
+        column: Atom,
+    },
+    DerivedTableNotTable {
+        name: Atom,
+    },
+    MaterializedRestrictedTable {
+        name: Atom,
+        table: Atom,
+    },
+    UndefinedHookTable {
+        event: HookEvent,
+        table: Atom,
+    },
+    DuplicateHook {
+        event: HookEvent,
+        table: Atom,
+    },
+    RefreshOutsidePage,
+    InvalidColumnMatching {
+        mode: String,
+    },
+    DuplicateColumnHeader {
+        table_name: Atom,
+        header: String,
+        fields: (Atom, Atom),
+    },
+    MembershipInNonList {
+        found: Type,
+    },
+    UnorderedRange {
+        found: Type,
+    },
+    UngroupedAggregate {
+        function: Atom,
+    },
+    UndefinedField {
+        table: TableType,
+        field: Atom,
+    },
+    DisplayArguments {
+        count: usize,
+    },
+    NonNumericArgument {
+        function: Atom,
+        found: Type,
+    },
+    InvalidNumberPattern {
+        pattern: String,
+    },
+    InvalidNullHandling {
+        mode: String,
+    },
+    UnorderedSortKey {
+        column: Option<Atom>,
+        found: Type,
+    },
+    MixedCurrencies {
+        left: String,
+        right: String,
+    },
+    InvalidConfig {
+        message: String,
+    },
+    NullableAggregate {
+        function: Atom,
+        table: Atom,
+        column: String,
+    },
+    NonExhaustiveMatch {
+        /// The bool without an arm; otherwise the arms lack a `_`
+        missing: Option<bool>,
+    },
+    UnreachableMatchArm,
+    ArgumentCount {
+        function: Atom,
+        expected: usize,
+        found: usize,
+    },
+    ArgumentType {
+        function: Atom,
+        param: Atom,
+        expected: Type,
+        found: Type,
+    },
+    PluginRejected {
+        function: Atom,
+        message: String,
+    },
+    PythonOutputUnassigned {
+        name: Atom,
+    },
+    NotConstant {
+        name: Atom,
+        reason: EvalError,
+    },
+    AssignToConstant {
+        name: Atom,
+    },
+    DuplicateTable {
+        name: Atom,
+        first: Span,
+    },
+    DuplicateFunction {
+        name: Atom,
+        first: Span,
+    },
+    DuplicatePage {
+        name: Atom,
+        first: Span,
+    },
+    UndefinedModuleFunction {
+        module: String,
+        name: String,
+    },
+    ColumnLiteralMismatch {
+        column: Atom,
+        column_type: Type,
+        literal: String,
     },
 }
 
@@ -63,7 +332,7 @@ impl std::fmt::Display for SemanticError {
             }
             SemanticError::MultipleKeyFields { table_name, key_fields } => {
                 write!(f, "Table '{}' has multiple key fields: {}. Only one key is allowed.",
-                    table_name, key_fields.join(", "))
+                    table_name, key_fields.iter().map(|k| k.as_str()).collect::<Vec<_>>().join(", "))
             }
             SemanticError::UndefinedReferenceTarget { field_name, table_name, target_table } => {
                 write!(f, "Field '{}' in table '{}' references undefined table '{}'",
@@ -73,23 +342,344 @@ impl std::fmt::Display for SemanticError {
                 write!(f, "Field '{}' in table '{}' cannot reference table '{}' because it has no key field",
                     field_name, table_name, target_table)
             }
+            SemanticError::UnusedVariable { name } => {
+                write!(f, "Variable '{}' is never used", name)
+            }
+            SemanticError::MockOutsideTest { target } => {
+                write!(f, "Cannot mock '{}' outside of a test", target)
+            }
+            SemanticError::InvalidMockTarget { target } => {
+                write!(f, "'{}' is not a table or function and cannot be mocked", target)
+            }
+            SemanticError::InvalidLogLevel { level } => {
+                write!(f, "Unknown log level '{}'; expected \"info\", \"warn\" or \"error\"", level)
+            }
+            SemanticError::TranslationKeyNotLiteral => {
+                write!(f, "t() takes a single string literal key")
+            }
+            SemanticError::UnknownExportSection { section: Some(section) } => {
+                write!(f, "The page has no section \"{}\" to export", section)
+            }
+            SemanticError::UnknownExportSection { section: None } => {
+                write!(f, "export_pdf() takes the title of a section of the page as a string literal")
+            }
+            SemanticError::InvalidSaveMode { mode } => {
+                write!(f, "Unknown save mode '{}'; expected \"append\", \"replace\" or \"upsert\"", mode)
+            }
+            SemanticError::ConfirmOutsideButton => {
+                write!(f, "'confirm' can only be used inside a button")
+            }
+            SemanticError::UndefinedRestrictedTable { table } => {
+                write!(f, "Cannot restrict '{}': no table of that name is defined", table)
+            }
+            SemanticError::UnknownRestrictionColumn { table, column } => {
+                write!(f, "Restriction of '{}' uses '{}', which is not a column of the table", table, column)
+            }
+            SemanticError::DerivedTableNotTable { name } => {
+                write!(f, "Cannot infer the schema of derived table '{}': its value is not a table of a known type", name)
+            }
+            SemanticError::MaterializedRestrictedTable { name, table } => {
+                write!(f, "Cannot materialize '{}': it is derived from '{}', whose rows depend on the signed-in user", name, table)
+            }
+            SemanticError::UndefinedHookTable { event, table } => {
+                write!(f, "Cannot attach {} to '{}': no table of that name is defined", event.keyword(), table)
+            }
+            SemanticError::DuplicateHook { event, table } => {
+                write!(f, "Table '{}' already has an {} hook", table, event.keyword())
+            }
+            SemanticError::RefreshOutsidePage => {
+                write!(f, "'refresh every' can only be used at the top level of a page")
+            }
+            SemanticError::InvalidColumnMatching { mode } => {
+                write!(f, "Unknown column matching '{}'; expected \"exact\" or \"normalize\"", mode)
+            }
+            SemanticError::DuplicateColumnHeader { table_name, header, fields: (first, second) } => {
+                write!(f, "Fields '{}' and '{}' of table '{}' are both stored in the column '{}'",
+                    first, second, table_name, header)
+            }
+            SemanticError::MembershipInNonList { found } => {
+                write!(f, "'in' tests membership in a list, found {}", found)
+            }
+            SemanticError::UnorderedRange { found } => {
+                write!(f, "'between' needs ordered values, found {}", found)
+            }
+            SemanticError::UngroupedAggregate { function } => {
+                write!(f, "'{}' aggregates the rows of a group, but this query is not grouped", function)
+            }
+            SemanticError::UndefinedField { table, field } => {
+                write!(f, "Table '{}' has no field '{}'", table.name, field)?;
+                let changes: Vec<String> = table.changes.iter().map(ToString::to_string).collect();
+                if !changes.is_empty() {
+                    write!(f, " (with {})", changes.join(", "))?;
+                }
+                Ok(())
+            }
+            SemanticError::DisplayArguments { count } => {
+                write!(f, "display() takes one value to show, found {}", count)
+            }
+            SemanticError::NonNumericArgument { function, found } => {
+                write!(f, "{}() expects a number, found {}", function, found)
+            }
+            SemanticError::InvalidNumberPattern { pattern } => {
+                write!(f, "Invalid number pattern \"{}\"; expected a pattern such as \"#,##0.00\"", pattern)
+            }
+            SemanticError::InvalidNullHandling { mode } => {
+                write!(f, "Unknown null handling '{}'; expected \"skip_nulls\" or \"strict\"", mode)
+            }
+            SemanticError::UnorderedSortKey { column: Some(column), found } => {
+                write!(f, "Cannot sort by column '{}' of type {}, which has no order", column, found)
+            }
+            SemanticError::UnorderedSortKey { column: None, found } => {
+                write!(f, "'sort by' needs ordered values, found {}", found)
+            }
+            SemanticError::MixedCurrencies { left, right } => {
+                write!(f, "Cannot combine an amount in {} with an amount in {}", left, right)
+            }
+            SemanticError::InvalidConfig { message } => write!(f, "{}", message),
+            SemanticError::NullableAggregate { function, table, column } => {
+                write!(f, "{}() skips the missing values of '{}.{}', which may be empty", function, table, column)
+            }
+            SemanticError::NonExhaustiveMatch { missing: Some(value) } => {
+                write!(f, "match has no arm for {}", value)
+            }
+            SemanticError::NonExhaustiveMatch { missing: None } => {
+                write!(f, "match has no '_' arm for the values its patterns do not name")
+            }
+            SemanticError::UnreachableMatchArm => {
+                write!(f, "This match arm is never taken: earlier arms match all of its values")
+            }
+            SemanticError::PythonReservedName { name } => {
+                write!(f, "'{}' is a reserved name in Python and is generated as '{}_'", name, name)
+            }
+            SemanticError::ArgumentCount { function, expected, found } => {
+                write!(f, "{}() takes {} argument(s), found {}", function, expected, found)
+            }
+            SemanticError::ArgumentType { function, param, expected, found } => {
+                write!(f, "Argument '{}' of {}() must be {}, found {}", param, function, expected, found)
+            }
+            SemanticError::PluginRejected { function, message } => {
+                write!(f, "Invalid call of {}(): {}", function, message)
+            }
+            SemanticError::PythonOutputUnassigned { name } => {
+                write!(f, "Output '{}' of the python block is never assigned by its code", name)
+            }
+            SemanticError::NotConstant { name, reason: EvalError::NotConstant } => {
+                write!(f, "Value of constant '{}' is not known when compiling", name)
+            }
+            SemanticError::NotConstant { name, reason } => {
+                write!(f, "Value of constant '{}' cannot be computed: {}", name, reason)
+            }
+            SemanticError::AssignToConstant { name } => {
+                write!(f, "Cannot assign to constant '{}'", name)
+            }
+            SemanticError::DuplicateTable { name, first } => {
+                write!(f, "Table '{}' is already defined at {}:{}", name, first.line, first.column)
+            }
+            SemanticError::DuplicateFunction { name, first } => {
+                write!(f, "Function '{}' is already defined at {}:{}", name, first.line, first.column)
+            }
+            SemanticError::DuplicatePage { name, first } => {
+                write!(f, "Page '{}' is already defined at {}:{}", name, first.line, first.column)
+            }
+            SemanticError::UndefinedModuleFunction { module, name } => {
+                write!(f, "Module '{}' has no function '{}'", module, name)
+            }
+            SemanticError::ColumnLiteralMismatch { column, column_type, literal } => {
+                write!(f, "Cannot compare column '{}' of type {} with {}", column, column_type, literal)
+            }
         }
     }
 }
 
 impl std::error::Error for SemanticError {}
 
+impl SemanticError {
+    /// Error code reported for this error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SemanticError::UndefinedVariable { .. } => ErrorCode::E3001,
+            SemanticError::Redefinition { .. } => ErrorCode::E3004,
+            SemanticError::TypeMismatch { .. } => ErrorCode::E3007,
+            SemanticError::UninitializedVariable { .. } => ErrorCode::E3011,
+            SemanticError::MissingTypeOrInitializer { .. } => ErrorCode::E2008,
+            SemanticError::MultipleKeyFields { .. } => ErrorCode::E3019,
+            SemanticError::UndefinedReferenceTarget { .. } => ErrorCode::E3020,
+            SemanticError::ReferenceToTableWithoutKey { .. } => ErrorCode::E3021,
+            SemanticError::UnusedVariable { .. } => ErrorCode::W1001,
+            SemanticError::MockOutsideTest { .. } => ErrorCode::E3022,
+            SemanticError::InvalidMockTarget { .. } => ErrorCode::E3023,
+            SemanticError::InvalidLogLevel { .. } => ErrorCode::E3024,
+            SemanticError::TranslationKeyNotLiteral => ErrorCode::E3025,
+            SemanticError::UnknownExportSection { .. } => ErrorCode::E3026,
+            SemanticError::InvalidSaveMode { .. } => ErrorCode::E3027,
+            SemanticError::ConfirmOutsideButton => ErrorCode::E3028,
+            SemanticError::UndefinedRestrictedTable { .. } => ErrorCode::E3029,
+            SemanticError::UnknownRestrictionColumn { .. } => ErrorCode::E3030,
+            SemanticError::DerivedTableNotTable { .. } => ErrorCode::E3031,
+            SemanticError::MaterializedRestrictedTable { .. } => ErrorCode::E3032,
+            SemanticError::UndefinedHookTable { .. } => ErrorCode::E3033,
+            SemanticError::DuplicateHook { .. } => ErrorCode::E3034,
+            SemanticError::RefreshOutsidePage => ErrorCode::E3035,
+            SemanticError::InvalidColumnMatching { .. } => ErrorCode::E3036,
+            SemanticError::DuplicateColumnHeader { .. } => ErrorCode::E3037,
+            SemanticError::MembershipInNonList { .. } => ErrorCode::E3038,
+            SemanticError::UnorderedRange { .. } => ErrorCode::E3039,
+            SemanticError::UngroupedAggregate { .. } => ErrorCode::E3040,
+            SemanticError::UndefinedField { .. } => ErrorCode::E3012,
+            SemanticError::DisplayArguments { .. } => ErrorCode::E3041,
+            SemanticError::NonNumericArgument { .. } => ErrorCode::E3042,
+            SemanticError::InvalidNumberPattern { .. } => ErrorCode::E3043,
+            SemanticError::InvalidNullHandling { .. } => ErrorCode::E3044,
+            SemanticError::UnorderedSortKey { .. } => ErrorCode::E3045,
+            SemanticError::MixedCurrencies { .. } => ErrorCode::E3046,
+            SemanticError::InvalidConfig { .. } => ErrorCode::E3047,
+            SemanticError::NullableAggregate { .. } => ErrorCode::W1003,
+            SemanticError::NonExhaustiveMatch { .. } => ErrorCode::E3048,
+            SemanticError::UnreachableMatchArm => ErrorCode::W1004,
+            SemanticError::PythonReservedName { .. } => ErrorCode::W1005,
+            SemanticError::ArgumentCount { .. } => ErrorCode::E3010,
+            SemanticError::ArgumentType { .. } => ErrorCode::E3008,
+            SemanticError::PluginRejected { .. } => ErrorCode::E3049,
+            SemanticError::PythonOutputUnassigned { .. } => ErrorCode::E3050,
+            SemanticError::NotConstant { .. } => ErrorCode::E3051,
+            SemanticError::AssignToConstant { .. } => ErrorCode::E3052,
+            SemanticError::DuplicateTable { .. } => ErrorCode::E3006,
+            SemanticError::DuplicateFunction { .. } => ErrorCode::E3005,
+            SemanticError::DuplicatePage { .. } => ErrorCode::E3053,
+            SemanticError::UndefinedModuleFunction { .. } => ErrorCode::E3054,
+            SemanticError::ColumnLiteralMismatch { .. } => ErrorCode::E3055,
+        }
+    }
+    
+    /// Severity this error is reported with
+    ///
+    /// Warnings do not make `analyze` fail.
+    pub fn severity(&self) -> Severity {
+        match self {
+            SemanticError::UnusedVariable { .. }
+            | SemanticError::NullableAggregate { .. }
+            | SemanticError::UnreachableMatchArm
+            | SemanticError::PythonReservedName { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
 impl SemanticAnalyzer {
     pub fn new() -> Self {
         SemanticAnalyzer {
             symbols: SymbolTable::new(),
             errors: Vec::new(),
+            error_sites: Vec::new(),
+            warnings: Vec::new(),
+            warning_sites: Vec::new(),
+            current_span: Span::default(),
+            current_node: NodeId::default(),
+            types: TypeMap::new(),
+            declared_variables: HashMap::new(),
+            used_variables: HashSet::new(),
+            page_sections: Vec::new(),
+            plugins: PluginRegistry::new(),
         }
     }
     
+    /// Check calls of the builtins added by plugins against their signatures
+    pub fn with_plugins(mut self, plugins: PluginRegistry) -> Self {
+        self.plugins = plugins;
+        self
+    }
+    
     pub fn analyze(&mut self, program: &Program) -> Result<(), Vec<SemanticError>> {
         // First pass: Collect global declarations (tables, function signatures)
+        self.declare_items(program);
+        
+        // Second pass: Check function bodies
         for item in &program.items {
+            if let ProgramItem::FunctionDef(_) = item {
+                self.check_item(item);
+            }
+        }
+        
+        // Third pass: Check pages and tests
+        for item in &program.items {
+            if let ProgramItem::Page(_) | ProgramItem::Test(_) = item {
+                self.check_item(item);
+            }
+        }
+        
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+    
+    /// Check a single expression against the global declarations
+    ///
+    /// Call after `analyze` (or `declare_items`) so that tables and functions
+    /// are in scope. Returns the inferred type, or the errors the expression
+    /// introduced.
+    pub fn analyze_expression(&mut self, expr: &Expr) -> Result<Type, Vec<SemanticError>> {
+        let first_error = self.errors.len();
+        self.check_expression(expr);
+        let ty = self.infer_expr_type(expr);
+        
+        if self.errors.len() == first_error {
+            Ok(ty)
+        } else {
+            Err(self.errors[first_error..].to_vec())
+        }
+    }
+    
+    /// Collect global declarations (tables, function signatures, externals)
+    ///
+    /// Must run before any `check_item` call.
+    pub fn declare_items(&mut self, program: &Program) {
+        // Builtins of plugins, which the program's own items may shadow
+        for plugin in self.plugins.iter() {
+            let _ = self.symbols.define(
+                plugin.name(),
+                Symbol {
+                    name: plugin.name(),
+                    symbol_type: plugin.return_type().clone(),
+                    kind: SymbolKind::ExternalFunction,
+                    is_initialized: true,
+                    is_mutable: false,
+                },
+            );
+        }
+        
+        // Tables, functions and pages are named once in the program; a
+        // second definition is reported with the location of the first
+        let mut definitions: HashMap<Atom, Span> = HashMap::new();
+        let mut pages: HashMap<Atom, Span> = HashMap::new();
+        for item in &program.items {
+            let (name, span) = match item {
+                ProgramItem::TableDef(table) => (table.name, table.span),
+                ProgramItem::FunctionDef(func) => (func.name, func.span),
+                ProgramItem::ExternalFunction(ext) => (ext.name, ext.span),
+                ProgramItem::Page(page) => {
+                    if let Some(&first) = pages.get(&page.name) {
+                        self.current_span = page.span;
+                        self.current_node = page.id;
+                        self.report(SemanticError::DuplicatePage { name: page.name, first });
+                    } else {
+                        pages.insert(page.name, page.span);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(&first) = definitions.get(&name) {
+                self.current_span = span;
+                self.current_node = item.id();
+                self.report(match item {
+                    ProgramItem::TableDef(_) => SemanticError::DuplicateTable { name, first },
+                    _ => SemanticError::DuplicateFunction { name, first },
+                });
+                continue;
+            }
+            definitions.insert(name, span);
             match item {
                 ProgramItem::TableDef(table) => {
                     self.define_table(table);
@@ -104,65 +694,211 @@ impl SemanticAnalyzer {
             }
         }
         
-        // Second pass: Check function bodies
+        // Restrictions name tables declared anywhere in the program
         for item in &program.items {
-            if let ProgramItem::FunctionDef(func) = item {
-                self.check_function_body(func);
+            if let ProgramItem::Restriction(restriction) = item {
+                let table = program.items.iter().find_map(|item| match item {
+                    ProgramItem::TableDef(table) if table.name == restriction.table => Some(table),
+                    _ => None,
+                });
+                self.check_restriction(restriction, table);
             }
         }
         
-        // Third pass: Check pages and tests
+        // Hooks are attached to tables declared anywhere in the program, at
+        // most one per event and table
+        let mut hooks = HashSet::new();
         for item in &program.items {
-            match item {
-                ProgramItem::Page(page) => {
-                    self.check_page(page);
+            if let ProgramItem::Hook(hook) = item {
+                self.current_span = hook.span;
+                self.current_node = hook.id;
+                let defined = program.items.iter()
+                    .any(|item| matches!(item, ProgramItem::TableDef(table) if table.name == hook.table));
+                if !defined {
+                    self.report(SemanticError::UndefinedHookTable { event: hook.event, table: hook.table });
+                } else if !hooks.insert((hook.event, hook.table)) {
+                    self.report(SemanticError::DuplicateHook { event: hook.event, table: hook.table });
                 }
-                ProgramItem::Test(test) => {
-                    self.check_test(test);
+            }
+        }
+        
+        // Constants, in source order, may use the constants before them
+        let mut constants = HashMap::new();
+        for item in &program.items {
+            if let ProgramItem::Constant(constant) = item {
+                self.define_constant(constant, &mut constants);
+            }
+        }
+        
+        // Derived tables, in source order, may use the tables and functions
+        // above and the derived tables before them
+        let restricted: Vec<Atom> = program.items.iter()
+            .filter_map(|item| match item {
+                ProgramItem::Restriction(restriction) => Some(restriction.table),
+                _ => None,
+            })
+            .collect();
+        for item in &program.items {
+            if let ProgramItem::DerivedTable(derived) = item {
+                self.define_derived_table(derived, &restricted);
+            }
+        }
+        
+        for item in &program.items {
+            if let ProgramItem::SharedVariable(shared) = item {
+                self.define_shared_variable(shared);
+            }
+        }
+        
+        let mut configs = program.items.iter().filter_map(|item| match item {
+            ProgramItem::Config(config) => Some(config),
+            _ => None,
+        });
+        if let Some(config) = configs.next() {
+            self.check_config(config);
+        }
+        for config in configs {
+            self.current_span = config.span;
+            self.current_node = config.id;
+            self.report(SemanticError::InvalidConfig { message: "A program has only one config block".to_string() });
+        }
+    }
+    
+    /// Each setting of the config block is known, given once and valid
+    fn check_config(&mut self, config: &Config) {
+        let mut seen = HashSet::new();
+        for setting in &config.settings {
+            self.current_span = setting.span;
+            self.current_node = setting.id;
+            let value = setting.value.as_str();
+            let problem = if !CONFIG_SETTINGS.contains(&setting.name.as_str()) {
+                Some(format!("Unknown config setting '{}'; expected one of {}", setting.name, CONFIG_SETTINGS.join(", ")))
+            } else if !seen.insert(setting.name) {
+                Some(format!("Config setting '{}' is given more than once", setting.name))
+            } else {
+                match setting.name.as_str() {
+                    "theme" if !matches!(value, "light" | "dark") => Some("theme must be \"light\" or \"dark\"".to_string()),
+                    "currency_position" if !matches!(value, "before" | "after") => {
+                        Some("currency_position must be \"before\" or \"after\"".to_string())
+                    }
+                    "decimal_separator" if value.chars().count() != 1 => {
+                        Some("decimal_separator must be a single character".to_string())
+                    }
+                    "thousands_separator" if value.chars().count() > 1 => {
+                        Some("thousands_separator must be a single character, or empty for none".to_string())
+                    }
+                    "date_format" if !value.contains('%') => {
+                        Some("date_format must be a strftime format such as \"%d/%m/%Y\"".to_string())
+                    }
+                    _ => None,
                 }
-                _ => {}
+            };
+            if let Some(message) = problem {
+                self.report(SemanticError::InvalidConfig { message });
             }
         }
         
-        if self.errors.is_empty() {
-            Ok(())
-        } else {
-            Err(self.errors.clone())
+        let decimal = config.settings.iter().find(|s| s.name == "decimal_separator");
+        let thousands = config.settings.iter().find(|s| s.name == "thousands_separator");
+        if decimal.map_or(".", |s| s.value.as_str()) == thousands.map_or(",", |s| s.value.as_str()) {
+            self.current_span = decimal.or(thousands).map_or(config.span, |s| s.span);
+            self.current_node = decimal.or(thousands).map_or(config.id, |s| s.id);
+            self.report(SemanticError::InvalidConfig {
+                message: "decimal_separator and thousands_separator must differ".to_string(),
+            });
+        }
+    }
+    
+    /// Check the body of a single top-level item against the declarations
+    ///
+    /// Tables and external functions have no body, so they produce an empty
+    /// analysis.
+    pub fn check_item(&mut self, item: &ProgramItem) -> ItemAnalysis {
+        let first_error = self.errors.len();
+        let first_warning = self.warnings.len();
+        let first_type = self.types.len();
+        let first_scope = self.symbols.scopes().count();
+        
+        match item {
+            ProgramItem::FunctionDef(func) => self.check_function_body(func),
+            ProgramItem::Page(page) => self.check_page(page),
+            ProgramItem::Test(test) => self.check_test(test),
+            ProgramItem::Hook(hook) => self.check_hook(hook),
+            ProgramItem::TableDef(_) | ProgramItem::ExternalFunction(_) | ProgramItem::Restriction(_)
+            | ProgramItem::DerivedTable(_) | ProgramItem::SharedVariable(_) | ProgramItem::Constant(_)
+            | ProgramItem::Config(_) | ProgramItem::Import(_) => {}
+        }
+        self.report_unused_variables();
+        
+        let scopes = self.symbols.scopes().nth(first_scope)
+            .map(|(id, _)| self.symbols.scopes_since(id))
+            .unwrap_or_default();
+        ItemAnalysis {
+            errors: self.errors[first_error..].to_vec(),
+            error_sites: self.error_sites[first_error..].to_vec(),
+            warnings: self.warnings[first_warning..].to_vec(),
+            warning_sites: self.warning_sites[first_warning..].to_vec(),
+            types: self.types.iter().skip(first_type).map(|(n, s, t)| (n, *s, t.clone())).collect(),
+            scopes,
+        }
+    }
+    
+    /// Restore the results of an earlier `check_item` for an unchanged item
+    pub fn reuse_item(&mut self, analysis: &ItemAnalysis) {
+        self.errors.extend(analysis.errors.iter().cloned());
+        self.error_sites.extend(analysis.error_sites.iter().copied());
+        self.warnings.extend(analysis.warnings.iter().cloned());
+        self.warning_sites.extend(analysis.warning_sites.iter().copied());
+        for (node, span, ty) in &analysis.types {
+            self.types.insert(*node, *span, ty.clone());
         }
+        self.symbols.graft_scopes(&analysis.scopes);
     }
     
     fn define_table(&mut self, table: &TableDef) {
+        self.current_span = table.span;
+        self.current_node = table.id;
+        
         // First, define the table in the symbol table
-        if let Err(_e) = self.symbols.define(
-            table.name.clone(),
+        if let Err(_e) = self.symbols.define_at(
+            table.name,
             Symbol {
-                name: table.name.clone(),
-                symbol_type: Type::Table(table.name.clone()),
+                name: table.name,
+                symbol_type: Type::Table(table.name.into()),
                 kind: SymbolKind::Table,
                 is_initialized: true,
                 is_mutable: false,
             },
+            self.current_node,
         ) {
-            self.errors.push(SemanticError::Redefinition {
-                name: table.name.clone(),
+            self.report(SemanticError::Redefinition {
+                name: table.name,
             });
             return;
         }
         
+        self.symbols.register_fields(
+            table.name,
+            table.fields.iter().map(|field| (field.name, field.field_type.clone())).collect(),
+        );
+        
         // Find key fields
         let mut key_fields = Vec::new();
         for field in &table.fields {
             for constraint in &field.constraints {
                 if matches!(constraint, Constraint::Key) {
-                    key_fields.push(field.name.clone());
+                    key_fields.push(field.name);
+                }
+                if matches!(constraint, Constraint::Key | Constraint::NonNull) {
+                    self.symbols.register_non_null(table.name, field.name);
                 }
             }
         }
         
         // Validate: at most one key field per table
         if key_fields.len() > 1 {
-            self.errors.push(SemanticError::MultipleKeyFields {
-                table_name: table.name.clone(),
+            self.report(SemanticError::MultipleKeyFields {
+                table_name: table.name,
                 key_fields,
             });
             return;
@@ -170,7 +906,21 @@ impl SemanticAnalyzer {
         
         // Register key in symbol table
         if let Some(key) = key_fields.first() {
-            self.symbols.register_key(table.name.clone(), key.clone());
+            self.symbols.register_key(table.name, *key);
+        }
+        
+        // Each CSV column holds one field, whether named by the field itself
+        // or by `from "Header"`
+        let mut headers: HashMap<&str, Atom> = HashMap::new();
+        for field in &table.fields {
+            let header = field.header.as_deref().unwrap_or(field.name.as_str());
+            if let Some(first) = headers.insert(header, field.name) {
+                self.report(SemanticError::DuplicateColumnHeader {
+                    table_name: table.name,
+                    header: header.to_string(),
+                    fields: (first, field.name),
+                });
+            }
         }
         
         // Find and validate reference fields
@@ -178,109 +928,334 @@ impl SemanticAnalyzer {
             if let Type::Ref(target_table) = &field.field_type {
                 // Check target table exists
                 if !self.symbols.has_table(target_table) {
-                    self.errors.push(SemanticError::UndefinedReferenceTarget {
-                        field_name: field.name.clone(),
-                        table_name: table.name.clone(),
-                        target_table: target_table.clone(),
+                    self.report(SemanticError::UndefinedReferenceTarget {
+                        field_name: field.name,
+                        table_name: table.name,
+                        target_table: *target_table,
                     });
                     continue;
                 }
                 
                 // Check target table has a key
                 if self.symbols.get_key_field(target_table).is_none() {
-                    self.errors.push(SemanticError::ReferenceToTableWithoutKey {
-                        field_name: field.name.clone(),
-                        table_name: table.name.clone(),
-                        target_table: target_table.clone(),
+                    self.report(SemanticError::ReferenceToTableWithoutKey {
+                        field_name: field.name,
+                        table_name: table.name,
+                        target_table: *target_table,
                     });
                     continue;
                 }
                 
                 // Register the reference
                 self.symbols.register_ref(
-                    table.name.clone(),
-                    field.name.clone(),
-                    target_table.clone(),
+                    table.name,
+                    field.name,
+                    *target_table,
                 );
             }
+            
+            // `references Table.field` names a table and one of its fields
+            for constraint in &field.constraints {
+                let Constraint::References { table: target_table, field: target_field } = constraint else { continue };
+                if !self.symbols.has_table(target_table) {
+                    self.report(SemanticError::UndefinedReferenceTarget {
+                        field_name: field.name,
+                        table_name: table.name,
+                        target_table: *target_table,
+                    });
+                } else {
+                    let target_table = TableType::from(*target_table);
+                    if self.symbols.has_fields(&target_table) && self.symbols.get_field_type(&target_table, target_field).is_none() {
+                        self.report(SemanticError::UndefinedField { table: target_table, field: *target_field });
+                    }
+                }
+            }
         }
     }
     
     fn define_function_signature(&mut self, func: &FunctionDef) {
-        if let Err(_e) = self.symbols.define(
-            func.name.clone(),
+        self.current_span = func.span;
+        self.current_node = func.id;
+        self.check_python_name(func.name);
+        if let Err(_e) = self.symbols.define_at(
+            func.name,
             Symbol {
-                name: func.name.clone(),
+                name: func.name,
                 symbol_type: func.return_type.clone(),
                 kind: SymbolKind::Function,
                 is_initialized: true,
                 is_mutable: false,
             },
+            self.current_node,
         ) {
-            self.errors.push(SemanticError::Redefinition {
-                name: func.name.clone(),
+            self.report(SemanticError::Redefinition {
+                name: func.name,
             });
         }
     }
     
     fn define_external_function(&mut self, ext: &ExternalFunction) {
-        if let Err(_e) = self.symbols.define(
-            ext.name.clone(),
+        self.current_span = ext.span;
+        self.current_node = ext.id;
+        if let Err(_e) = self.symbols.define_at(
+            ext.name,
             Symbol {
-                name: ext.name.clone(),
+                name: ext.name,
                 symbol_type: ext.return_type.clone(),
                 kind: SymbolKind::ExternalFunction,
                 is_initialized: true,
                 is_mutable: false,
             },
+            self.current_node,
         ) {
-            self.errors.push(SemanticError::Redefinition {
-                name: ext.name.clone(),
+            self.report(SemanticError::Redefinition {
+                name: ext.name,
             });
         }
     }
     
-    fn check_function_body(&mut self, func: &FunctionDef) {
-        self.symbols.push_scope(ScopeKind::FunctionBody);
+    /// Check the value of a derived table and declare it with the type of
+    /// the table it is derived from
+    ///
+    /// A materialized table is shared by all users, so it cannot be derived
+    /// from one of the `restricted` tables.
+    fn define_derived_table(&mut self, derived: &DerivedTable, restricted: &[Atom]) {
+        self.current_span = derived.span;
+        self.current_node = derived.id;
+        self.check_expression(&derived.value);
+        let Some(table) = self.table_type(&derived.value) else {
+            self.report(SemanticError::DerivedTableNotTable { name: derived.name });
+            return;
+        };
+        if derived.materialize != Materialize::Cached && restricted.contains(&table.name) {
+            self.report(SemanticError::MaterializedRestrictedTable { name: derived.name, table: table.name });
+        }
         
-        // Add parameters to function scope
-        for param in &func.params {
-            if let Err(_e) = self.symbols.define(
-                param.name.clone(),
-                Symbol {
-                    name: param.name.clone(),
-                    symbol_type: param.param_type.clone(),
-                    kind: SymbolKind::Parameter,
-                    is_initialized: true,
-                    is_mutable: false,
-                },
-            ) {
-                self.errors.push(SemanticError::Redefinition {
-                    name: param.name.clone(),
+        if let Err(_e) = self.symbols.define_at(
+            derived.name,
+            Symbol {
+                name: derived.name,
+                symbol_type: Type::Table(table),
+                kind: SymbolKind::DerivedTable,
+                is_initialized: true,
+                is_mutable: false,
+            },
+            self.current_node,
+        ) {
+            self.report(SemanticError::Redefinition {
+                name: derived.name,
+            });
+        }
+    }
+    
+    /// Check the initial value of a shared variable and declare it for
+    /// every page
+    fn define_shared_variable(&mut self, shared: &SharedVariable) {
+        self.current_span = shared.span;
+        self.current_node = shared.id;
+        self.check_python_name(shared.name);
+        self.check_expression(&shared.value);
+        let value_type = self.infer_expr_type(&shared.value);
+        if let Some(expected_type) = &shared.type_annotation {
+            if !self.types_compatible(expected_type, &value_type) {
+                self.report(SemanticError::TypeMismatch {
+                    expected: expected_type.to_string(),
+                    found: value_type.to_string(),
                 });
             }
         }
         
-        // Check function body
-        for stmt in &func.body {
-            self.check_statement(stmt);
+        if let Err(_e) = self.symbols.define_at(
+            shared.name,
+            Symbol {
+                name: shared.name,
+                symbol_type: shared.type_annotation.clone().unwrap_or(value_type),
+                kind: SymbolKind::SharedVariable,
+                is_initialized: true,
+                is_mutable: true,
+            },
+            self.current_node,
+        ) {
+            self.report(SemanticError::Redefinition {
+                name: shared.name,
+            });
         }
-        
-        self.symbols.pop_scope();
     }
     
-    fn check_page(&mut self, page: &Page) {
-        self.symbols.push_scope(ScopeKind::Page);
-        
-        for stmt in &page.statements {
-            self.check_statement(stmt);
+    /// Compute a constant's value and declare it for every page and function
+    fn define_constant(&mut self, constant: &Constant, constants: &mut HashMap<Atom, Value>) {
+        self.current_span = constant.span;
+        self.current_node = constant.id;
+        self.check_python_name(constant.name);
+        let value_type = match eval_const_with(&constant.value, constants) {
+            Ok(value) => {
+                let value_type = value.type_of();
+                constants.insert(constant.name, value);
+                value_type
+            }
+            Err(reason) => {
+                self.check_expression(&constant.value);
+                self.report(SemanticError::NotConstant { name: constant.name, reason });
+                self.infer_expr_type(&constant.value)
+            }
+        };
+        if let Some(expected_type) = &constant.type_annotation {
+            if !self.types_compatible(expected_type, &value_type) {
+                self.report(SemanticError::TypeMismatch {
+                    expected: expected_type.to_string(),
+                    found: value_type.to_string(),
+                });
+            }
+        }
+        
+        if let Err(_e) = self.symbols.define_at(
+            constant.name,
+            Symbol {
+                name: constant.name,
+                symbol_type: constant.type_annotation.clone().unwrap_or(value_type),
+                kind: SymbolKind::Constant,
+                is_initialized: true,
+                is_mutable: false,
+            },
+            self.current_node,
+        ) {
+            self.report(SemanticError::Redefinition {
+                name: constant.name,
+            });
+        }
+    }
+    
+    /// Table type of a value: a typed load, a function returning a table, or
+    /// a derived table or variable holding one, possibly queried or combined
+    /// with a table of the same type
+    fn table_type(&self, expr: &Expr) -> Option<TableType> {
+        match expr {
+            Expr::FunctionCall(call) => {
+                let table_arg = match call.name.as_str() {
+                    "load_csv" => call.args.get(1),
+                    "load_sql" => call.args.get(2),
+                    // share(table, "column") adds the float column_share
+                    "share" if self.symbols.lookup(&call.name).is_none() => {
+                        let table = self.table_type(call.args.first()?)?;
+                        return match call.args.get(1) {
+                            Some(Expr::StringLiteral(column)) => Some(table.changed(ColumnChange::Add(Atom::new(&format!("{}_share", column))))),
+                            _ => Some(table),
+                        };
+                    }
+                    // Queries keep their table's rows
+                    "where" | "sort" | "sort_desc" if self.symbols.lookup(&call.name).is_none() => {
+                        return self.table_type(call.args.first()?);
+                    }
+                    _ => {
+                        let symbol = self.symbols.lookup(&call.name)?;
+                        return match (&symbol.kind, &symbol.symbol_type) {
+                            (SymbolKind::Function | SymbolKind::ExternalFunction, Type::Table(table)) => Some(table.clone()),
+                            _ => None,
+                        };
+                    }
+                };
+                match table_arg {
+                    Some(Expr::Identifier(name)) if self.symbols.has_table(name) => Some((*name).into()),
+                    _ => None,
+                }
+            }
+            // A table's name is its type, not a value
+            Expr::Identifier(name) => {
+                let symbol = self.symbols.lookup(name)?;
+                match (&symbol.kind, &symbol.symbol_type) {
+                    (SymbolKind::Table, _) => None,
+                    (_, Type::Table(table)) => Some(table.clone()),
+                    _ => None,
+                }
+            }
+            Expr::Where { table, .. } | Expr::SortBy { table, .. } | Expr::ColumnSelect { table, .. }
+            | Expr::GroupBy { table, .. } => self.table_type(table),
+            Expr::Rename { table, columns, .. } => {
+                let table = self.table_type(table)?;
+                Some(columns.iter().fold(table, |table, &(from, to)| table.changed(ColumnChange::Rename(from, to))))
+            }
+            Expr::DropColumns { table, columns, .. } => {
+                let table = self.table_type(table)?;
+                Some(columns.iter().fold(table, |table, &column| table.changed(ColumnChange::Drop(column))))
+            }
+            Expr::BinaryOp { op: BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Union | BinaryOp::Minus | BinaryOp::Intersect, left, right, .. } => {
+                let table = self.table_type(left)?;
+                (self.table_type(right)? == table).then_some(table)
+            }
+            _ => None,
+        }
+    }
+    
+    /// Check that a restriction names a table and only uses its columns
+    fn check_restriction(&mut self, restriction: &Restriction, table: Option<&TableDef>) {
+        self.current_span = restriction.span;
+        self.current_node = restriction.id;
+        let Some(table) = table else {
+            self.report(SemanticError::UndefinedRestrictedTable { table: restriction.table });
+            return;
+        };
+        
+        let mut names = Vec::new();
+        collect_row_names(&restriction.condition, &mut names);
+        for name in names {
+            if !table.fields.iter().any(|field| field.name == name) {
+                self.report(SemanticError::UnknownRestrictionColumn { table: table.name, column: name });
+            }
+        }
+    }
+    
+    fn check_function_body(&mut self, func: &FunctionDef) {
+        self.current_span = func.span;
+        self.current_node = func.id;
+        self.page_sections.clear();
+        self.symbols.push_scope_with_span(ScopeKind::FunctionBody, func.span);
+        
+        // Add parameters to function scope
+        for param in &func.params {
+            self.check_python_name(param.name);
+            if let Err(_e) = self.symbols.define(
+                param.name,
+                Symbol {
+                    name: param.name,
+                    symbol_type: param.param_type.clone(),
+                    kind: SymbolKind::Parameter,
+                    is_initialized: true,
+                    is_mutable: false,
+                },
+            ) {
+                self.report(SemanticError::Redefinition {
+                    name: param.name,
+                });
+            }
+        }
+        
+        // Check function body
+        for stmt in &func.body {
+            self.check_statement(stmt);
+        }
+        
+        self.symbols.pop_scope();
+    }
+    
+    fn check_page(&mut self, page: &Page) {
+        self.current_span = page.span;
+        self.current_node = page.id;
+        self.page_sections.clear();
+        collect_section_titles(&page.statements, &mut self.page_sections);
+        self.symbols.push_scope_with_span(ScopeKind::Page, page.span);
+        
+        for stmt in &page.statements {
+            self.check_statement(stmt);
         }
         
         self.symbols.pop_scope();
     }
     
     fn check_test(&mut self, test: &Test) {
-        self.symbols.push_scope(ScopeKind::TestBody);
+        self.current_span = test.span;
+        self.current_node = test.id;
+        self.page_sections.clear();
+        self.symbols.push_scope_with_span(ScopeKind::TestBody, test.span);
         
         for stmt in &test.body {
             self.check_statement(stmt);
@@ -289,11 +1264,75 @@ impl SemanticAnalyzer {
         self.symbols.pop_scope();
     }
     
+    /// Check a hook's body, with the table's rows in the event's variable
+    fn check_hook(&mut self, hook: &Hook) {
+        self.current_span = hook.span;
+        self.current_node = hook.id;
+        self.page_sections.clear();
+        self.symbols.push_scope_with_span(ScopeKind::HookBody, hook.span);
+        
+        let name = Atom::new(hook.event.variable());
+        let _ = self.symbols.define(
+            name,
+            Symbol {
+                name,
+                symbol_type: Type::Table(hook.table.into()),
+                kind: SymbolKind::Parameter,
+                is_initialized: true,
+                is_mutable: false,
+            },
+        );
+        for stmt in &hook.body {
+            self.check_statement(stmt);
+        }
+        
+        self.symbols.pop_scope();
+    }
+    
     fn check_statement(&mut self, stmt: &Statement) {
+        let enclosing_span = self.current_span;
+        let enclosing_node = self.current_node;
+        if let Some(id) = stmt.id() {
+            self.current_node = id;
+        }
         match stmt {
-            Statement::Let { name, type_annotation, value } => {
+            Statement::Let { span, .. }
+            | Statement::Assign { span, .. }
+            | Statement::Button { span, .. }
+            | Statement::Section { span, .. }
+            | Statement::Confirm { span, .. }
+            | Statement::Forall { span, .. }
+            | Statement::Match { span, .. }
+            | Statement::Assert { span, .. }
+            | Statement::Mock { span, .. }
+            | Statement::Refresh { span, .. }
+            | Statement::Python { span, .. }
+            | Statement::If { then_span: span, .. } => self.current_span = *span,
+            _ => {}
+        }
+        
+        self.note_statement_uses(stmt);
+        for expr in statement_expressions(stmt) {
+            self.record_expr_types(expr);
+        }
+        self.check_statement_kind(stmt);
+        self.current_span = enclosing_span;
+        self.current_node = enclosing_node;
+    }
+    
+    fn check_statement_kind(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Let { name, type_annotation, value, id, span } => {
+                if let Some(val) = value {
+                    self.check_builtin_calls(val);
+                    self.check_memberships(val);
+                }
+                
                 // Determine the type
-                let symbol_type = if let Some(ref val) = value {
+                let symbol_type = if let Some(Type::Array(element)) = type_annotation {
+                    // An array takes its declared type, which `[]` has no other way to give
+                    Type::Array(element.clone())
+                } else if let Some(ref val) = value {
                     // Infer type from expression
                     self.infer_expr_type(val)
                 } else if let Some(ref ty) = type_annotation {
@@ -301,49 +1340,68 @@ impl SemanticAnalyzer {
                     ty.clone()
                 } else {
                     // This should be caught by parser, but double-check
-                    self.errors.push(SemanticError::MissingTypeOrInitializer {
-                        name: name.clone(),
+                    self.report(SemanticError::MissingTypeOrInitializer {
+                        name: *name,
                     });
                     Type::Int  // Dummy type to continue analysis
                 };
+                self.types.insert(*id, *span, symbol_type.clone());
+                
+                self.check_python_name(*name);
+                
+                // Pages assign shared variables rather than hiding them, and
+                // constants are the same everywhere
+                let shadows_shared = self.symbols.lookup(name)
+                    .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::SharedVariable | SymbolKind::Constant));
                 
                 // Define the variable
-                if let Err(_e) = self.symbols.define(
-                    name.clone(),
+                if shadows_shared {
+                    self.report(SemanticError::Redefinition {
+                        name: *name,
+                    });
+                } else if let Err(_e) = self.symbols.define_at(
+                    *name,
                     Symbol {
-                        name: name.clone(),
+                        name: *name,
                         symbol_type: symbol_type.clone(),
                         kind: SymbolKind::Variable,
                         is_initialized: value.is_some(),
                         is_mutable: false,
                     },
+                    *id,
                 ) {
-                    self.errors.push(SemanticError::Redefinition {
-                        name: name.clone(),
+                    self.report(SemanticError::Redefinition {
+                        name: *name,
                     });
+                } else {
+                    self.declared_variables.insert((self.symbols.current_scope_id(), *name), (*id, *span));
                 }
                 
                 // If both type annotation and value are present, check compatibility
                 if let (Some(ref expected_type), Some(ref val)) = (type_annotation, value) {
                     let inferred_type = self.infer_expr_type(val);
-                    if !self.types_compatible(expected_type, &inferred_type) {
-                        self.errors.push(SemanticError::TypeMismatch {
-                            expected: format!("{:?}", expected_type),
-                            found: format!("{:?}", inferred_type),
+                    let empty_array = matches!((expected_type, val), (Type::Array(_), Expr::ArrayLiteral(items)) if items.is_empty());
+                    if !empty_array && !self.types_compatible(expected_type, &inferred_type) {
+                        self.report(SemanticError::TypeMismatch {
+                            expected: expected_type.to_string(),
+                            found: inferred_type.to_string(),
                         });
                     }
                 }
             }
             
-            Statement::Assign { name, value } => {
+            Statement::Assign { name, value, id, span } => {
                 // Check if variable exists
-                if let Some(symbol) = self.symbols.lookup(name) {
+                if self.symbols.lookup(name).is_some_and(|symbol| symbol.kind == SymbolKind::Constant) {
+                    self.report(SemanticError::AssignToConstant { name: *name });
+                } else if let Some(symbol) = self.symbols.lookup(name) {
                     // Check type compatibility if we have type information
                     let value_type = self.infer_expr_type(value);
+                    self.types.insert(*id, *span, value_type.clone());
                     if !self.types_compatible(&symbol.symbol_type, &value_type) {
-                        self.errors.push(SemanticError::TypeMismatch {
-                            expected: format!("{:?}", symbol.symbol_type),
-                            found: format!("{:?}", value_type),
+                        self.report(SemanticError::TypeMismatch {
+                            expected: symbol.symbol_type.to_string(),
+                            found: value_type.to_string(),
                         });
                     }
                     
@@ -352,8 +1410,8 @@ impl SemanticAnalyzer {
                         // Ignore error, variable might be in parent scope
                     }
                 } else {
-                    self.errors.push(SemanticError::UndefinedVariable {
-                        name: name.clone(),
+                    self.report(SemanticError::UndefinedVariable {
+                        name: *name,
                     });
                 }
                 
@@ -361,33 +1419,51 @@ impl SemanticAnalyzer {
                 self.check_expression(value);
             }
             
-            Statement::Section { body, .. } => {
-                self.symbols.push_scope(ScopeKind::Section);
+            Statement::Title(text) | Statement::Subtitle(text) | Statement::Text(text) => {
+                self.check_text(text);
+            }
+            
+            Statement::Section { title, body, span, .. } => {
+                self.check_text(title);
+                self.symbols.push_scope_with_span(ScopeKind::Section, *span);
+                for s in body {
+                    self.check_statement(s);
+                }
+                self.symbols.pop_scope();
+            }
+            
+            Statement::Button { label, body, span, .. } => {
+                self.check_text(label);
+                self.symbols.push_scope_with_span(ScopeKind::Button, *span);
                 for s in body {
                     self.check_statement(s);
                 }
                 self.symbols.pop_scope();
             }
             
-            Statement::Button { body, .. } => {
-                self.symbols.push_scope(ScopeKind::Button);
+            Statement::Confirm { message, body, span, .. } => {
+                if !self.symbols.within(ScopeKind::Button) {
+                    self.report(SemanticError::ConfirmOutsideButton);
+                }
+                self.check_text(message);
+                self.symbols.push_scope_with_span(ScopeKind::Confirm, *span);
                 for s in body {
                     self.check_statement(s);
                 }
                 self.symbols.pop_scope();
             }
             
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::If { condition, then_branch, else_branch, then_span, else_span, .. } => {
                 self.check_expression(condition);
                 
-                self.symbols.push_scope(ScopeKind::IfBranch);
+                self.symbols.push_scope_with_span(ScopeKind::IfBranch, *then_span);
                 for s in then_branch {
                     self.check_statement(s);
                 }
                 self.symbols.pop_scope();
                 
                 if let Some(else_stmts) = else_branch {
-                    self.symbols.push_scope(ScopeKind::IfBranch);
+                    self.symbols.push_scope_with_span(ScopeKind::IfBranch, else_span.unwrap_or_default());
                     for s in else_stmts {
                         self.check_statement(s);
                     }
@@ -395,28 +1471,39 @@ impl SemanticAnalyzer {
                 }
             }
             
-            Statement::Forall { var, iterable, body } => {
+            Statement::Forall { var, iterable, body, span, .. } => {
                 self.check_expression(iterable);
                 
                 // Infer element type before entering new scope
                 let iter_type = self.infer_expr_type(iterable);
                 let elem_type = self.get_element_type(&iter_type);
+                // Only tables and arrays have elements to loop over
+                if let Some(found) = self.known_type(iterable) {
+                    if matches!(found, Type::Int | Type::Float | Type::String | Type::Date | Type::Currency | Type::Bool | Type::Row(_)) {
+                        self.report(SemanticError::TypeMismatch {
+                            expected: "table or array".to_string(),
+                            found: found.to_string(),
+                        });
+                    }
+                }
                 
-                self.symbols.push_scope(ScopeKind::ForallLoop);
+                self.symbols.push_scope_with_span(ScopeKind::ForallLoop, *span);
                 
                 // Define loop variable (type is element type of iterable)
-                if let Err(_e) = self.symbols.define(
-                    var.clone(),
+                self.check_python_name(*var);
+                if let Err(_e) = self.symbols.define_at(
+                    *var,
                     Symbol {
-                        name: var.clone(),
+                        name: *var,
                         symbol_type: elem_type,
                         kind: SymbolKind::LoopVariable,
                         is_initialized: true,
                         is_mutable: false,
                     },
+                    self.current_node,
                 ) {
-                    self.errors.push(SemanticError::Redefinition {
-                        name: var.clone(),
+                    self.report(SemanticError::Redefinition {
+                        name: *var,
                     });
                 }
                 
@@ -427,6 +1514,18 @@ impl SemanticAnalyzer {
                 self.symbols.pop_scope();
             }
             
+            Statement::Match { value, arms, .. } => {
+                self.check_expression(value);
+                self.check_match(value, arms);
+                for arm in arms {
+                    self.symbols.push_scope_with_span(ScopeKind::MatchArm, arm.span);
+                    for s in &arm.body {
+                        self.check_statement(s);
+                    }
+                    self.symbols.pop_scope();
+                }
+            }
+            
             Statement::Return(expr) => {
                 self.check_expression(expr);
             }
@@ -435,22 +1534,107 @@ impl SemanticAnalyzer {
                 self.check_function_call(call);
             }
             
-            _ => {}
+            Statement::Assert { condition, .. } => {
+                self.check_expression(condition);
+            }
+            
+            Statement::Mock { target, value, .. } => {
+                if !self.symbols.within(ScopeKind::TestBody) {
+                    self.report(SemanticError::MockOutsideTest { target: *target });
+                }
+                // Tests sign a user in by mocking current_user
+                let mockable = match self.symbols.lookup(target) {
+                    Some(s) => matches!(s.kind, SymbolKind::Table | SymbolKind::DerivedTable | SymbolKind::Function | SymbolKind::ExternalFunction),
+                    None => *target == "current_user",
+                };
+                if !mockable {
+                    self.report(SemanticError::InvalidMockTarget { target: *target });
+                }
+                self.check_expression(value);
+            }
+            
+            Statement::Refresh { .. } => {
+                if self.symbols.current_scope().kind() != ScopeKind::Page {
+                    self.report(SemanticError::RefreshOutsidePage);
+                }
+            }
+            
+            Statement::Python { inputs, outputs, code, id, span } => self.check_python(inputs, outputs, code, *id, *span),
         }
     }
     
+    /// Check a `python` block's bindings: its inputs are variables of their
+    /// declared types, and its code assigns the outputs it declares, which
+    /// are then variables of the page
+    fn check_python(&mut self, inputs: &[CodeBinding], outputs: &[CodeBinding], code: &str, id: NodeId, span: Span) {
+        for input in inputs {
+            let Some(found) = self.symbols.lookup(&input.variable).map(|symbol| symbol.symbol_type.clone()) else {
+                self.report(SemanticError::UndefinedVariable { name: input.variable });
+                continue;
+            };
+            let (fits, expected) = match &input.binding_type {
+                // Values whose type is not inferred, such as tables loaded
+                // without a table type, are taken as ints
+                None => (matches!(found, Type::Table(_) | Type::Int), "table".to_string()),
+                Some(Type::Table(_)) if found == Type::Int => continue,
+                Some(expected) => (
+                    self.types_compatible(expected, &found)
+                        || (found == Type::Int && matches!(expected, Type::Float | Type::Currency)),
+                    expected.to_string(),
+                ),
+            };
+            if !fits {
+                self.report(SemanticError::TypeMismatch { expected, found: found.to_string() });
+            }
+        }
+        for output in outputs {
+            if !python_assigns(code, output.name.as_str()) {
+                self.report(SemanticError::PythonOutputUnassigned { name: output.name });
+            }
+            // Outputs are declared with their types by the parser
+            let Some(symbol_type) = output.binding_type.clone() else { continue };
+            let defined = self.symbols.define_at(
+                output.variable,
+                Symbol {
+                    name: output.variable,
+                    symbol_type,
+                    kind: SymbolKind::Variable,
+                    is_initialized: true,
+                    is_mutable: false,
+                },
+                id,
+            );
+            if defined.is_err() {
+                self.report(SemanticError::Redefinition { name: output.variable });
+            } else {
+                self.declared_variables.insert((self.symbols.current_scope_id(), output.variable), (id, span));
+            }
+        }
+    }
+    
+    /// Check the expressions embedded in page text with `{expr}`
+    fn check_text(&mut self, text: &PageText) {
+        let enclosing_span = self.current_span;
+        for (expr, span) in text.expressions() {
+            self.current_span = span;
+            self.check_expression(expr);
+            self.check_memberships(expr);
+        }
+        self.current_span = enclosing_span;
+    }
+    
     fn check_expression(&mut self, expr: &Expr) {
         match expr {
             Expr::Identifier(name) => {
                 if let Some(symbol) = self.symbols.lookup(name) {
                     if !symbol.is_initialized {
-                        self.errors.push(SemanticError::UninitializedVariable {
-                            name: name.clone(),
+                        self.report(SemanticError::UninitializedVariable {
+                            name: *name,
                         });
                     }
                 } else {
-                    self.errors.push(SemanticError::UndefinedVariable {
-                        name: name.clone(),
+                    self.report(SemanticError::UndefinedVariable {
+                        name: *name,
                     });
                 }
             }
@@ -459,31 +1643,114 @@ impl SemanticAnalyzer {
                 self.check_function_call(call);
             }
             
-            Expr::BinaryOp { left, right, .. } => {
+            Expr::BinaryOp { op, left, right, .. } => {
                 self.check_expression(left);
                 self.check_expression(right);
+                if *op == BinaryOp::In {
+                    self.check_membership(left, right);
+                }
             }
             
             Expr::UnaryOp { operand, .. } => {
                 self.check_expression(operand);
             }
             
-            Expr::Lambda { body, .. } => {
-                self.check_expression(body);
+            Expr::Between { value, low, high, .. } => {
+                self.check_expression(value);
+                self.check_expression(low);
+                self.check_expression(high);
+                self.check_range(value, low, high);
+            }
+            
+            // The other names of a condition are columns, checked when the
+            // program is lowered
+            Expr::Where { table, condition, .. } => {
+                self.check_memberships(condition);
+                self.check_builtin_calls(condition);
+                let row = self.table_type(table);
+                self.check_condition_types(row.as_ref(), condition);
+            }
+            
+            // Aggregates are allowed in the condition of a grouped query
+            Expr::GroupBy { having, .. } => {
+                self.check_memberships(having);
+            }
+            
+            Expr::SortBy { .. } => {
+                self.check_memberships(expr);
+            }
+            
+            Expr::ColumnSelect { table, columns, .. } => {
+                self.check_expression(table);
+                let row = self.table_type(table);
+                for column in columns {
+                    match column {
+                        SelectedColumn::Name(name) => self.check_table_column(row.as_ref(), *name),
+                        SelectedColumn::Rest { except } => {
+                            for name in except {
+                                self.check_table_column(row.as_ref(), *name);
+                            }
+                        }
+                    }
+                }
+            }
+            
+            // Each renamed or dropped column must be one the table still has
+            Expr::Rename { table, columns, .. } => {
+                self.check_expression(table);
+                let mut row = self.table_type(table);
+                for &(from, to) in columns {
+                    self.check_table_column(row.as_ref(), from);
+                    row = row.map(|row| row.changed(ColumnChange::Rename(from, to)));
+                }
+            }
+            
+            Expr::DropColumns { table, columns, .. } => {
+                self.check_expression(table);
+                let mut row = self.table_type(table);
+                for &column in columns {
+                    self.check_table_column(row.as_ref(), column);
+                    row = row.map(|row| row.changed(ColumnChange::Drop(column)));
+                }
+            }
+            
+            Expr::Lambda { params, body } => {
+                self.check_lambda(params, body, None);
             }
             
-            Expr::FieldAccess { object, .. } => {
+            Expr::FieldAccess { object, field, .. } => {
                 self.check_expression(object);
+                if let Type::Row(table) = self.infer_expr_type(object) {
+                    if self.symbols.has_fields(&table) && self.symbols.get_field_type(&table, field).is_none() {
+                        self.report(SemanticError::UndefinedField { table, field: *field });
+                    }
+                }
             }
             
-            Expr::Index { object, index } => {
+            Expr::Index { object, index, .. } => {
                 self.check_expression(object);
                 self.check_expression(index);
             }
             
-            Expr::Chain { left, right } => {
+            // `table -> where(_, row => ...)` passes the table as `_`, as
+            // if the call were the body of a lambda taking it
+            Expr::Chain { left, right, .. } => {
                 self.check_expression(left);
+                let placeholder = Atom::new("_");
+                let symbol_type = self.infer_expr_type(left);
+                self.symbols.push_scope_with_span(ScopeKind::Lambda, self.current_span);
+                let _ = self.symbols.define(
+                    placeholder,
+                    Symbol {
+                        name: placeholder,
+                        symbol_type,
+                        kind: SymbolKind::Parameter,
+                        is_initialized: true,
+                        is_mutable: false,
+                    },
+                );
                 self.check_expression(right);
+                self.symbols.pop_scope();
             }
             
             Expr::ArrayLiteral(items) => {
@@ -492,20 +1759,583 @@ impl SemanticAnalyzer {
                 }
             }
             
+            Expr::TableLiteral(fields) => {
+                for (_, value) in fields {
+                    self.check_expression(value);
+                }
+            }
+            
+            Expr::Match { value, arms, .. } => {
+                self.check_expression(value);
+                self.check_match(value, arms);
+                self.check_match_values(arms);
+                for arm in arms {
+                    self.check_expression(&arm.body);
+                }
+            }
+            
             _ => {}
         }
     }
     
+    /// Check the arms of `match`: the literals among the value and the
+    /// patterns must have one type, every value must have an arm, and every
+    /// arm must match a value the arms before it do not
+    fn check_match<T>(&mut self, value: &Expr, arms: &[MatchArm<T>]) {
+        let mut expected = literal_type(value);
+        let mut seen: Vec<&Expr> = Vec::new();
+        let mut after_wildcard = false;
+        for arm in arms {
+            let exhausted = after_wildcard || covers_bools(&seen);
+            let mut matches_new = arm.is_wildcard();
+            for pattern in &arm.patterns {
+                let MatchPattern::Literal(literal) = pattern else { continue };
+                if let Some(found) = literal_type(literal) {
+                    match &expected {
+                        None => expected = Some(found),
+                        Some(ty) if !comparable_types(ty, &found) => {
+                            self.report(SemanticError::TypeMismatch { expected: ty.to_string(), found: found.to_string() });
+                        }
+                        Some(_) => {}
+                    }
+                }
+                if !seen.contains(&literal) {
+                    seen.push(literal);
+                    matches_new = true;
+                }
+            }
+            if exhausted || !matches_new {
+                self.report_at(SemanticError::UnreachableMatchArm, self.current_node, arm.span);
+            }
+            after_wildcard |= arm.is_wildcard();
+        }
+        
+        if !after_wildcard && !covers_bools(&seen) {
+            let missing = [true, false].into_iter()
+                .find(|value| seen.iter().any(|literal| **literal == Expr::BoolLiteral(!value)));
+            self.report(SemanticError::NonExhaustiveMatch { missing });
+        }
+    }
+    
+    /// The literals among the values of a match's arms must have one type
+    fn check_match_values(&mut self, arms: &[MatchArm<Expr>]) {
+        let mut expected: Option<Type> = None;
+        for arm in arms {
+            let Some(found) = literal_type(&arm.body) else { continue };
+            match &expected {
+                None => expected = Some(found),
+                Some(ty) if !comparable_types(ty, &found) => {
+                    self.report_at(
+                        SemanticError::TypeMismatch { expected: ty.to_string(), found: found.to_string() },
+                        self.current_node,
+                        arm.span,
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    
+    /// Check `value in list`: the list must be one, and the literals among
+    /// the value and the list's items must have one type
+    fn check_membership(&mut self, value: &Expr, list: &Expr) {
+        let Expr::ArrayLiteral(items) = list else {
+            if let Some(found) = literal_type(list) {
+                self.report(SemanticError::MembershipInNonList { found });
+            }
+            return;
+        };
+        let mut expected = literal_type(value);
+        for found in items.iter().filter_map(literal_type) {
+            match &expected {
+                None => expected = Some(found),
+                Some(ty) if !comparable_types(ty, &found) => {
+                    self.report(SemanticError::TypeMismatch { expected: ty.to_string(), found: found.to_string() });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    
+    /// Check `value between low and high`: the literals among the three
+    /// must have one type, which must be ordered
+    fn check_range(&mut self, value: &Expr, low: &Expr, high: &Expr) {
+        let mut expected: Option<Type> = None;
+        for found in [value, low, high].into_iter().filter_map(literal_type) {
+            if found == Type::Bool {
+                self.report(SemanticError::UnorderedRange { found });
+                return;
+            }
+            match &expected {
+                None => expected = Some(found),
+                Some(ty) if !comparable_types(ty, &found) => {
+                    self.report(SemanticError::TypeMismatch { expected: ty.to_string(), found: found.to_string() });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    
+    /// Report a column that a table with known fields does not have
+    fn check_table_column(&mut self, table: Option<&TableType>, column: Atom) {
+        if let Some(table) = table.filter(|table| self.symbols.has_fields(table)) {
+            if self.symbols.get_field_type(table, &column).is_none() {
+                self.report(SemanticError::UndefinedField { table: table.clone(), field: column });
+            }
+        }
+    }
+    
+    /// Check every `in`, `between`, `sort by` key and pair of currency
+    /// amounts of an expression, including those of conditions and call
+    /// arguments
+    fn check_memberships(&mut self, expr: &Expr) {
+        match expr {
+            Expr::BinaryOp { op, left, right, .. } => {
+                if *op == BinaryOp::In {
+                    self.check_membership(left, right);
+                }
+                if let (Expr::CurrencyLiteral { currency: left, .. }, Expr::CurrencyLiteral { currency: right, .. }) = (&**left, &**right) {
+                    if left != right {
+                        self.report(SemanticError::MixedCurrencies { left: left.clone(), right: right.clone() });
+                    }
+                }
+                self.check_memberships(left);
+                match (op, right.as_ref()) {
+                    // The list's elements were checked against the value
+                    (BinaryOp::In, Expr::ArrayLiteral(items)) => {
+                        for item in items {
+                            self.check_memberships(item);
+                        }
+                    }
+                    _ => self.check_memberships(right),
+                }
+            }
+            Expr::Between { value, low, high, .. } => {
+                self.check_range(value, low, high);
+                self.check_memberships(value);
+                self.check_memberships(low);
+                self.check_memberships(high);
+            }
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::ColumnSelect { table: inner, .. }
+            | Expr::Rename { table: inner, .. }
+            | Expr::DropColumns { table: inner, .. } => self.check_memberships(inner),
+            Expr::Where { table, condition, .. } => {
+                self.check_memberships(table);
+                self.check_memberships(condition);
+                let row = self.table_type(table);
+                self.check_condition_types(row.as_ref(), condition);
+            }
+            Expr::GroupBy { table, having, .. } => {
+                self.check_memberships(table);
+                self.check_memberships(having);
+            }
+            Expr::SortBy { table, columns, .. } => {
+                self.check_memberships(table);
+                let row = self.table_type(table);
+                for column in columns {
+                    self.check_memberships(&column.key);
+                    if let Some(found @ (Type::Bool | Type::Table(_) | Type::Row(_) | Type::Filter)) = self.sort_key_type(row.as_ref(), &column.key) {
+                        let column = match column.key {
+                            Expr::Identifier(name) => Some(name),
+                            _ => None,
+                        };
+                        self.report(SemanticError::UnorderedSortKey { column, found });
+                    }
+                }
+            }
+            Expr::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.check_memberships(arg);
+                }
+            }
+            Expr::Match { value, arms, .. } => {
+                self.check_memberships(value);
+                for arm in arms {
+                    self.check_memberships(&arm.body);
+                }
+            }
+            Expr::ArrayLiteral(items) => {
+                self.check_array_elements(items);
+                for item in items {
+                    self.check_memberships(item);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// The elements of an array share a type, set by the first whose type
+    /// is known; ints mixed with floats or amounts widen to them
+    fn check_array_elements(&mut self, items: &[Expr]) {
+        let mut element: Option<Type> = None;
+        for item in items {
+            let Some(found) = self.known_type(item) else { continue };
+            match element {
+                None => element = Some(found),
+                Some(Type::Int) if matches!(found, Type::Float | Type::Currency) => element = Some(found),
+                Some(Type::Float | Type::Currency) if found == Type::Int => {}
+                Some(ref expected) if *expected != found => {
+                    self.report(SemanticError::TypeMismatch { expected: expected.to_string(), found: found.to_string() });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    
+    /// Check the comparisons of a `where` condition on the rows of `row`
+    /// between a column and a literal its declared type has no values like,
+    /// which pandas would only fail on once the app runs
+    fn check_condition_types(&mut self, row: Option<&TableType>, condition: &Expr) {
+        let compared: Vec<(&Expr, &Expr)> = match condition {
+            Expr::BinaryOp { op: BinaryOp::And | BinaryOp::Or, left, right, .. } => {
+                self.check_condition_types(row, left);
+                return self.check_condition_types(row, right);
+            }
+            Expr::UnaryOp { op: UnaryOp::Not, operand, .. } => return self.check_condition_types(row, operand),
+            Expr::BinaryOp {
+                op: BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::LessThan | BinaryOp::LessThanEqual
+                    | BinaryOp::GreaterThan | BinaryOp::GreaterThanEqual,
+                left,
+                right, ..
+            } => vec![(left, right), (right, left)],
+            // Bounds of two types are already reported by `check_range`
+            Expr::Between { low, high, .. } if literal_type(low).zip(literal_type(high)).is_some_and(|(low, high)| !comparable_types(&low, &high)) => {
+                return;
+            }
+            Expr::Between { value, low, high, .. } => vec![(value, low), (value, high)],
+            _ => return,
+        };
+        let Some(row) = row else { return };
+        for (column, value) in compared {
+            let Expr::Identifier(column) = column else { continue };
+            let (Some(column_type), Some(found)) = (self.symbols.get_field_type(row, column), literal_type(value)) else {
+                continue;
+            };
+            // References hold the keys of another table
+            if comparable_types(column_type, &found) || matches!(column_type, Type::Ref(_)) {
+                continue;
+            }
+            let literal = match value {
+                Expr::IntLiteral(n) => format!("the int {}", n),
+                Expr::FloatLiteral(n) => format!("the float {}", n),
+                Expr::StringLiteral(s) => format!("the string \"{}\"", s),
+                Expr::BoolLiteral(b) => format!("the bool {}", b),
+                Expr::DateLiteral(date) => format!("the date d\"{}\"", date),
+                Expr::CurrencyLiteral { amount, currency } => format!("the amount {} {}", amount, currency),
+                _ => continue,
+            };
+            let error = SemanticError::ColumnLiteralMismatch { column: *column, column_type: column_type.clone(), literal };
+            return self.report(error);
+        }
+    }
+    
+    /// The type of a `sort by` key, an expression of the columns of `row`,
+    /// when it is known
+    fn sort_key_type(&mut self, row: Option<&TableType>, key: &Expr) -> Option<Type> {
+        match key {
+            Expr::Identifier(name) => {
+                let row = row.filter(|row| self.symbols.has_fields(row))?;
+                if let Some(ty) = self.symbols.get_field_type(row, name) {
+                    return Some(ty.clone());
+                }
+                if self.symbols.lookup(name).is_none() {
+                    self.report(SemanticError::UndefinedField { table: row.clone(), field: *name });
+                }
+                None
+            }
+            Expr::BinaryOp { op, left, right, .. } => match op {
+                BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => {
+                    let left = self.sort_key_type(row, left);
+                    let right = self.sort_key_type(row, right);
+                    match (left, right) {
+                        (Some(left), Some(right)) if comparable_types(&left, &right) => Some(left),
+                        _ => None,
+                    }
+                }
+                BinaryOp::Union | BinaryOp::Minus | BinaryOp::Intersect => None,
+                _ => Some(Type::Bool),
+            },
+            Expr::UnaryOp { op: UnaryOp::Negate, operand, .. } => self.sort_key_type(row, operand),
+            Expr::UnaryOp { .. } | Expr::Between { .. } => Some(Type::Bool),
+            _ => literal_type(key),
+        }
+    }
+    
     fn check_function_call(&mut self, call: &FunctionCall) {
+        let enclosing_span = self.current_span;
+        let enclosing_node = self.current_node;
+        self.current_span = call.span;
+        self.current_node = call.id;
+        
         // Check if function exists
         if self.symbols.lookup(&call.name).is_none() {
             // It might be a builtin function, so don't error for now
             // In a more complete implementation, we'd have a list of builtins
         }
         
-        // Check arguments
+        // Check arguments; the parameters of a lambda passed with a table
+        // are its rows
+        let row = call.args.first().and_then(|arg| self.table_type(arg));
+        for arg in &call.args {
+            match arg {
+                Expr::Lambda { params, body } => self.check_lambda(params, body, row.as_ref()),
+                _ => self.check_expression(arg),
+            }
+        }
+        self.check_builtin_arguments(call);
+        
+        self.current_span = enclosing_span;
+        self.current_node = enclosing_node;
+    }
+    
+    /// Check a lambda's body with its parameters in scope, typed as rows of
+    /// `row` when it is known
+    fn check_lambda(&mut self, params: &[Atom], body: &Expr, row: Option<&TableType>) {
+        self.symbols.push_scope_with_span(ScopeKind::Lambda, self.current_span);
+        for param in params {
+            self.check_python_name(*param);
+            let _ = self.symbols.define(
+                *param,
+                Symbol {
+                    name: *param,
+                    symbol_type: row.cloned().map(Type::Row).unwrap_or(Type::Int),
+                    kind: SymbolKind::Parameter,
+                    is_initialized: true,
+                    is_mutable: false,
+                },
+            );
+        }
+        self.check_expression(body);
+        self.check_row_comparisons(body);
+        self.symbols.pop_scope();
+    }
+    
+    /// Report comparisons in a lambda's condition between a field of a row
+    /// and a literal the field's declared type has no values like
+    fn check_row_comparisons(&mut self, condition: &Expr) {
+        match condition {
+            Expr::BinaryOp { op: BinaryOp::And | BinaryOp::Or, left, right, .. } => {
+                self.check_row_comparisons(left);
+                self.check_row_comparisons(right);
+            }
+            Expr::UnaryOp { op: UnaryOp::Not, operand, .. } => self.check_row_comparisons(operand),
+            Expr::BinaryOp {
+                op: BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::LessThan | BinaryOp::LessThanEqual
+                    | BinaryOp::GreaterThan | BinaryOp::GreaterThanEqual,
+                left,
+                right, ..
+            } => {
+                for (field, value) in [(left, right), (right, left)] {
+                    let (Some(expected), Some(found)) = (self.row_field_type(field), literal_type(value)) else { continue };
+                    if !comparable_types(&expected, &found) {
+                        self.report(SemanticError::TypeMismatch { expected: expected.to_string(), found: found.to_string() });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// Declared type of `row.field` when `row` is a row of a known table;
+    /// references hold keys of any type
+    fn row_field_type(&mut self, expr: &Expr) -> Option<Type> {
+        let Expr::FieldAccess { object, field, .. } = expr else { return None };
+        let Type::Row(table) = self.infer_expr_type(object) else { return None };
+        match self.symbols.get_field_type(&table, field)? {
+            Type::Ref(_) => None,
+            ty => Some(ty.clone()),
+        }
+    }
+    
+    /// Check the builtin calls in a `let` value, whose expressions are
+    /// otherwise only typed
+    fn check_builtin_calls(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FunctionCall(call) => {
+                let row = call.args.first().and_then(|arg| self.table_type(arg));
+                self.check_builtin_call(call, row.as_ref());
+            }
+            // `table -> where(_, row => ...)` passes the table as `_`
+            Expr::Chain { left, right, .. } => {
+                self.check_builtin_calls(left);
+                match right.as_ref() {
+                    Expr::FunctionCall(call) => {
+                        let row = self.table_type(left);
+                        self.check_builtin_call(call, row.as_ref());
+                    }
+                    right => self.check_builtin_calls(right),
+                }
+            }
+            Expr::BinaryOp { left, right, .. } => {
+                self.check_builtin_calls(left);
+                self.check_builtin_calls(right);
+            }
+            Expr::UnaryOp { operand: inner, .. } | Expr::FieldAccess { object: inner, .. } => {
+                self.check_builtin_calls(inner);
+            }
+            Expr::Between { value, low, high, .. } => {
+                self.check_builtin_calls(value);
+                self.check_builtin_calls(low);
+                self.check_builtin_calls(high);
+            }
+            Expr::Where { table, condition, .. } => {
+                self.check_builtin_calls(table);
+                self.check_builtin_calls(condition);
+            }
+            Expr::GroupBy { table, .. } | Expr::Rename { table, .. } | Expr::DropColumns { table, .. } => {
+                self.check_builtin_calls(table);
+            }
+            // Values are only typed, so their matches are checked here
+            Expr::Match { value, arms, .. } => {
+                self.check_match(value, arms);
+                self.check_match_values(arms);
+                self.check_builtin_calls(value);
+                for arm in arms {
+                    self.check_builtin_calls(&arm.body);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// Check a call in a `let` value; the parameters of a lambda passed
+    /// with a table are its rows, of type `row`
+    fn check_builtin_call(&mut self, call: &FunctionCall, row: Option<&TableType>) {
+        self.check_builtin_arguments(call);
         for arg in &call.args {
-            self.check_expression(arg);
+            match arg {
+                Expr::Lambda { params, body } => self.check_lambda(params, body, row),
+                _ => self.check_builtin_calls(arg),
+            }
+        }
+    }
+    
+    /// Builtins whose arguments must be particular literals, and calls of
+    /// functions a module does not define
+    fn check_builtin_arguments(&mut self, call: &FunctionCall) {
+        // Modules have no builtins, so their functions are all known
+        if let Some((module, name)) = split_qualified(&call.name) {
+            if self.symbols.lookup_qualified(module, name).is_none() {
+                self.report(SemanticError::UndefinedModuleFunction { module: module.to_string(), name: name.to_string() });
+            }
+            return;
+        }
+        
+        if let Some(plugin) = self.plugins.get(&call.name) {
+            self.check_plugin_call(plugin.as_ref(), call);
+            return;
+        }
+        
+        // sum(column) and the other aggregates of a group's rows only have
+        // a group in the condition of a grouped query
+        if call.is_group_aggregate() && self.symbols.lookup(&call.name).is_none() {
+            self.report(SemanticError::UngroupedAggregate { function: call.name });
+        }
+        
+        // log(message, level) takes one of the levels generated apps log at
+        if call.name == "log" {
+            if let Some(Expr::StringLiteral(level)) = call.args.get(1) {
+                if !LOG_LEVELS.contains(&level.as_str()) {
+                    self.report(SemanticError::InvalidLogLevel { level: level.clone() });
+                }
+            }
+        }
+        
+        // display(value) shows a single value
+        if call.name == "display" && self.symbols.lookup(&call.name).is_none() && call.args.len() != 1 {
+            self.report(SemanticError::DisplayArguments { count: call.args.len() });
+        }
+        
+        // round, floor, ceil, abs, percent and the number formats take a
+        // number, and format_number a valid pattern
+        if NUMERIC_FUNCTIONS.contains(&call.name.as_str()) && self.symbols.lookup(&call.name).is_none() {
+            if let Some(value) = call.args.first() {
+                let found = self.infer_expr_type(value);
+                if !matches!(found, Type::Int | Type::Float | Type::Currency) {
+                    self.report(SemanticError::NonNumericArgument { function: call.name, found });
+                }
+            }
+            if let (true, Some(Expr::StringLiteral(pattern))) = (call.name == "format_number", call.args.get(1)) {
+                if number_pattern(pattern).is_none() {
+                    self.report(SemanticError::InvalidNumberPattern { pattern: pattern.clone() });
+                }
+            }
+        }
+        
+        // sum(table, "column", mode) and average treat missing values in one
+        // of the ways the runtime implements; without a mode, skipping them
+        // in a column that may be empty is worth a warning
+        if matches!(call.name.as_str(), "sum" | "average") && self.symbols.lookup(&call.name).is_none() {
+            match (call.args.first(), call.args.get(1), call.args.get(2)) {
+                (_, _, Some(Expr::StringLiteral(mode))) if !NULL_HANDLING.contains(&mode.as_str()) => {
+                    self.report(SemanticError::InvalidNullHandling { mode: mode.clone() });
+                }
+                (Some(table), Some(Expr::StringLiteral(column)), None) => {
+                    if let Some(table) = self.table_type(table) {
+                        if self.symbols.get_field_type(&table, column).is_some() && !self.symbols.is_non_null(&table, column) {
+                            self.report(SemanticError::NullableAggregate { function: call.name, table: table.name, column: column.clone() });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        
+        // share(table, "column" by "group") divides a numeric column of the
+        // table by its total within each group
+        if call.name == "share" && self.symbols.lookup(&call.name).is_none() {
+            if let Some(table) = call.args.first().and_then(|table| self.table_type(table)) {
+                for (index, arg) in call.args.iter().enumerate().skip(1) {
+                    let Expr::StringLiteral(column) = arg else { continue };
+                    match self.symbols.get_field_type(&table, column) {
+                        None if self.symbols.has_fields(&table) => {
+                            self.report(SemanticError::UndefinedField { table: table.clone(), field: Atom::new(column) });
+                        }
+                        Some(found) if index == 1 && !matches!(found, Type::Int | Type::Float | Type::Currency) => {
+                            self.report(SemanticError::NonNumericArgument { function: call.name, found: found.clone() });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        
+        // t("key") keys are collected from the source into locale files
+        if call.name == "t" && self.symbols.lookup(&call.name).is_none()
+            && !matches!(call.args.as_slice(), [Expr::StringLiteral(_)]) {
+            self.report(SemanticError::TranslationKeyNotLiteral);
+        }
+        
+        // save_sql(table, connection, name, mode) writes in one of the
+        // modes the runtime implements
+        if call.name == "save_sql" {
+            if let Some(Expr::StringLiteral(mode)) = call.args.get(3) {
+                if !SAVE_MODES.contains(&mode.as_str()) {
+                    self.report(SemanticError::InvalidSaveMode { mode: mode.clone() });
+                }
+            }
+        }
+        
+        // load_csv(path, Table, matching) matches headers in one of the ways
+        // the runtime implements
+        if call.name == "load_csv" {
+            if let Some(Expr::StringLiteral(mode)) = call.args.get(2) {
+                if !COLUMN_MATCHING.contains(&mode.as_str()) {
+                    self.report(SemanticError::InvalidColumnMatching { mode: mode.clone() });
+                }
+            }
+        }
+        
+        // export_pdf("title") exports a section of the same page
+        if call.name == "export_pdf" && self.symbols.lookup(&call.name).is_none() {
+            match call.args.as_slice() {
+                [Expr::StringLiteral(section)] if self.page_sections.contains(section) => {}
+                [Expr::StringLiteral(section)] => {
+                    self.report(SemanticError::UnknownExportSection { section: Some(section.clone()) });
+                }
+                _ => self.report(SemanticError::UnknownExportSection { section: None }),
+            }
         }
     }
     
@@ -515,39 +2345,392 @@ impl SemanticAnalyzer {
             Expr::FloatLiteral(_) => Type::Float,
             Expr::StringLiteral(_) => Type::String,
             Expr::BoolLiteral(_) => Type::Bool,
+            Expr::DateLiteral(_) => Type::Date,
+            Expr::CurrencyLiteral { .. } => Type::Currency,
             Expr::Identifier(name) => {
                 self.symbols.lookup(name)
                     .map(|s| s.symbol_type.clone())
                     .unwrap_or(Type::Int)  // Default type if not found
             }
+            // first(table), last(table) and single(table) are rows of it
+            Expr::FunctionCall(call) if matches!(call.name.as_str(), "first" | "last" | "single")
+                && self.symbols.lookup(&call.name).is_none() => {
+                call.args.first()
+                    .and_then(|table| self.table_type(table))
+                    .map(Type::Row)
+                    .unwrap_or(Type::Int)
+            }
+            // round(x), floor and ceil are whole numbers, round(x, n) keeps
+            // n decimals and abs the type of its number
+            Expr::FunctionCall(call) if NUMERIC_FUNCTIONS.contains(&call.name.as_str())
+                && self.symbols.lookup(&call.name).is_none() => match call.name.as_str() {
+                "round" if call.args.len() > 1 => Type::Float,
+                "round" | "floor" | "ceil" => Type::Int,
+                "abs" => call.args.first().map(|value| self.infer_expr_type(value)).unwrap_or(Type::Int),
+                _ => Type::String,
+            },
             Expr::FunctionCall(call) => {
+                if let Some(table) = self.table_type(expr) {
+                    return Type::Table(table);
+                }
                 self.symbols.lookup(&call.name)
                     .map(|s| s.symbol_type.clone())
                     .unwrap_or(Type::Int)  // Default type if not found
             }
-            _ => Type::Int,  // Simplified for now
+            // Ints mixed with floats or amounts widen to them
+            Expr::ArrayLiteral(items) => {
+                let mut element: Option<Type> = None;
+                for item in items {
+                    let found = self.infer_expr_type(item);
+                    element = match element {
+                        Some(Type::Int) if matches!(found, Type::Float | Type::Currency) => Some(found),
+                        None => Some(found),
+                        element => element,
+                    };
+                }
+                Type::Array(Box::new(element.unwrap_or(Type::Int)))
+            }
+            Expr::Index { object, .. } => match self.infer_expr_type(object) {
+                Type::Array(element) => *element,
+                _ => self.table_type(expr).map(Type::Table).unwrap_or(Type::Int),
+            },
+            // The arms' values share a type, checked with the match
+            Expr::Match { arms, .. } => arms.first()
+                .map(|arm| self.infer_expr_type(&arm.body))
+                .unwrap_or(Type::Int),
+            // Arithmetic widens ints mixed with floats or amounts to them,
+            // and comparisons and conditions are bools
+            Expr::BinaryOp { op, left, right, .. } => match op {
+                BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => {
+                    if let Some(table) = self.table_type(expr) {
+                        return Type::Table(table);
+                    }
+                    match (self.infer_expr_type(left), self.infer_expr_type(right)) {
+                        (Type::Currency, _) | (_, Type::Currency) => Type::Currency,
+                        (Type::Float, _) | (_, Type::Float) => Type::Float,
+                        (left, _) => left,
+                    }
+                }
+                BinaryOp::Union | BinaryOp::Minus | BinaryOp::Intersect => {
+                    self.table_type(expr).map(Type::Table).unwrap_or(Type::Int)
+                }
+                _ => Type::Bool,
+            },
+            Expr::UnaryOp { op: UnaryOp::Negate, operand, .. } => self.infer_expr_type(operand),
+            Expr::UnaryOp { .. } | Expr::Between { .. } => Type::Bool,
+            // A reference field navigates to the referenced row
+            Expr::FieldAccess { object, field, .. } => match self.infer_expr_type(object) {
+                Type::Row(table) => match self.symbols.get_field_type(&table, field) {
+                    Some(Type::Ref(target)) => Type::Row((*target).into()),
+                    Some(ty) => ty.clone(),
+                    None => Type::Int,
+                },
+                _ => Type::Int,
+            },
+            _ => self.table_type(expr).map(Type::Table).unwrap_or(Type::Int),  // Simplified for now
+        }
+    }
+    
+    /// Record the type of each node of an expression, innermost first
+    ///
+    /// The conditions and sort keys of queries and the bodies of lambdas
+    /// name the columns of rows rather than variables, and a call chained
+    /// with `->` takes the value before it, so those are left out.
+    fn record_expr_types(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.record_expr_types(arg);
+                }
+            }
+            Expr::BinaryOp { left, right, .. } | Expr::Index { object: left, index: right, .. } => {
+                self.record_expr_types(left);
+                self.record_expr_types(right);
+            }
+            Expr::Between { value, low, high, .. } => {
+                self.record_expr_types(value);
+                self.record_expr_types(low);
+                self.record_expr_types(high);
+            }
+            Expr::Chain { left, .. } => return self.record_expr_types(left),
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::FieldAccess { object: inner, .. }
+            | Expr::Where { table: inner, .. }
+            | Expr::SortBy { table: inner, .. }
+            | Expr::ColumnSelect { table: inner, .. }
+            | Expr::GroupBy { table: inner, .. }
+            | Expr::Rename { table: inner, .. }
+            | Expr::DropColumns { table: inner, .. } => self.record_expr_types(inner),
+            Expr::TableLiteral(fields) => {
+                for (_, value) in fields {
+                    self.record_expr_types(value);
+                }
+            }
+            Expr::ArrayLiteral(items) => {
+                for item in items {
+                    self.record_expr_types(item);
+                }
+            }
+            Expr::Match { value, arms, .. } => {
+                self.record_expr_types(value);
+                for arm in arms {
+                    self.record_expr_types(&arm.body);
+                }
+            }
+            _ => {}
+        }
+        if let Some((id, span)) = expr.node() {
+            let ty = self.infer_expr_type(expr);
+            self.types.insert(id, span, ty);
         }
     }
     
     fn get_element_type(&self, ty: &Type) -> Type {
         match ty {
-            Type::Table(name) => Type::Table(name.clone()),
+            Type::Table(table) => Type::Row(table.clone()),
+            Type::Array(element) => element.as_ref().clone(),
             _ => Type::Int,  // Simplified
         }
     }
     
+    /// Check a call of a plugin's builtin: the number of arguments, the
+    /// types of those whose type is known, then the plugin's own validation
+    fn check_plugin_call(&mut self, plugin: &dyn BuiltinPlugin, call: &FunctionCall) {
+        let params = plugin.params();
+        if call.args.len() != params.len() {
+            self.report(SemanticError::ArgumentCount { function: call.name, expected: params.len(), found: call.args.len() });
+            return;
+        }
+        for (param, arg) in params.iter().zip(&call.args) {
+            let Some(found) = self.known_type(arg) else { continue };
+            // Whole numbers are accepted where a float or an amount is
+            let widened = found == Type::Int && matches!(param.param_type, Type::Float | Type::Currency);
+            if found != param.param_type && !widened {
+                self.report(SemanticError::ArgumentType {
+                    function: call.name,
+                    param: param.name,
+                    expected: param.param_type.clone(),
+                    found,
+                });
+            }
+        }
+        for message in plugin.validate(&call.args) {
+            self.report(SemanticError::PluginRejected { function: call.name, message });
+        }
+    }
+    
+    /// Type of a value whose type is certain: literals, and variables and
+    /// calls of functions that are declared
+    fn known_type(&mut self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::IntLiteral(_) | Expr::FloatLiteral(_) | Expr::StringLiteral(_) | Expr::BoolLiteral(_)
+            | Expr::DateLiteral(_) | Expr::CurrencyLiteral { .. } => Some(self.infer_expr_type(expr)),
+            Expr::Identifier(name) => self.symbols.lookup(name).map(|symbol| symbol.symbol_type.clone()),
+            Expr::FunctionCall(call) if self.symbols.lookup(&call.name).is_some() => Some(self.infer_expr_type(expr)),
+            _ => None,
+        }
+    }
+    
     fn types_compatible(&self, t1: &Type, t2: &Type) -> bool {
-        // Simplified type compatibility check
-        t1 == t2
+        match (t1, t2) {
+            // An array of ints is also one of floats or amounts
+            (Type::Array(e1), Type::Array(e2)) => {
+                self.types_compatible(e1, e2) || (**e2 == Type::Int && matches!(**e1, Type::Float | Type::Currency))
+            }
+            // Simplified type compatibility check
+            _ => t1 == t2,
+        }
     }
     
     pub fn get_errors(&self) -> &[SemanticError] {
         &self.errors
     }
     
+    pub fn get_warnings(&self) -> &[SemanticError] {
+        &self.warnings
+    }
+    
+    /// Errors and warnings found so far as located diagnostics
+    pub fn diagnostics(&self) -> DiagnosticBag {
+        let mut bag = DiagnosticBag::new();
+        let reported = self.errors.iter().zip(&self.error_sites)
+            .chain(self.warnings.iter().zip(&self.warning_sites));
+        for (error, (node, span)) in reported {
+            bag.add(Diagnostic::new(
+                error.severity(),
+                error.code(),
+                error.to_string(),
+                Location::new(span.line, span.column),
+            ).with_node(*node));
+        }
+        bag
+    }
+    
+    fn report(&mut self, error: SemanticError) {
+        self.report_at(error, self.current_node, self.current_span);
+    }
+    
+    fn report_at(&mut self, error: SemanticError, node: NodeId, span: Span) {
+        if error.severity() == Severity::Warning {
+            self.warnings.push(error);
+            self.warning_sites.push((node, span));
+        } else {
+            self.errors.push(error);
+            self.error_sites.push((node, span));
+        }
+    }
+    
+    /// Warn about a name the generated Python renames; of a module's
+    /// functions and constants, only their names in the module count
+    fn check_python_name(&mut self, name: Atom) {
+        let name = split_qualified(&name).map_or(name, |(_, member)| Atom::new(member));
+        if is_python_reserved(&name) {
+            self.report(SemanticError::PythonReservedName { name });
+        }
+    }
+    
+    /// Record a read of a variable so it is not reported as unused
+    fn note_use(&mut self, name: &str) {
+        if let (Some(scope), Some(atom)) = (self.symbols.defining_scope(name), Atom::get(name)) {
+            self.used_variables.insert((scope, atom));
+        }
+    }
+    
+    /// Record the variable reads in the expressions a statement evaluates
+    /// directly; nested bodies are handled when they are checked
+    fn note_statement_uses(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Title(text) | Statement::Subtitle(text) | Statement::Text(text) => {
+                for (expr, _) in text.expressions() {
+                    self.note_expr_uses(expr);
+                }
+            }
+            Statement::Button { label: text, .. }
+            | Statement::Section { title: text, .. }
+            | Statement::Confirm { message: text, .. } => {
+                for (expr, _) in text.expressions() {
+                    self.note_expr_uses(expr);
+                }
+            }
+            Statement::Let { value: Some(value), .. } | Statement::Assign { value, .. } => {
+                self.note_expr_uses(value);
+            }
+            Statement::If { condition: expr, .. }
+            | Statement::Forall { iterable: expr, .. }
+            | Statement::Match { value: expr, .. }
+            | Statement::Return(expr)
+            | Statement::Assert { condition: expr, .. }
+            | Statement::Mock { value: expr, .. } => self.note_expr_uses(expr),
+            Statement::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.note_expr_uses(arg);
+                }
+            }
+            Statement::Python { inputs, .. } => {
+                for input in inputs {
+                    self.note_use(input.variable.as_str());
+                }
+            }
+            Statement::Let { value: None, .. } | Statement::Refresh { .. } => {}
+        }
+    }
+    
+    fn note_expr_uses(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(name) => self.note_use(name),
+            Expr::StringLiteral(text) => self.note_interpolated_uses(text),
+            Expr::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.note_expr_uses(arg);
+                }
+            }
+            Expr::BinaryOp { left, right, .. } | Expr::Chain { left, right, .. } => {
+                self.note_expr_uses(left);
+                self.note_expr_uses(right);
+            }
+            Expr::Index { object: left, index: right, .. } => {
+                self.note_expr_uses(left);
+                self.note_expr_uses(right);
+            }
+            Expr::Between { value, low, high, .. } => {
+                self.note_expr_uses(value);
+                self.note_expr_uses(low);
+                self.note_expr_uses(high);
+            }
+            Expr::UnaryOp { operand: inner, .. }
+            | Expr::Lambda { body: inner, .. }
+            | Expr::FieldAccess { object: inner, .. }
+            | Expr::SortBy { table: inner, .. }
+            | Expr::ColumnSelect { table: inner, .. }
+            | Expr::Rename { table: inner, .. }
+            | Expr::DropColumns { table: inner, .. } => self.note_expr_uses(inner),
+            Expr::Where { table, condition, .. } | Expr::GroupBy { table, having: condition, .. } => {
+                self.note_expr_uses(table);
+                self.note_expr_uses(condition);
+            }
+            Expr::TableLiteral(fields) => {
+                for (_, value) in fields {
+                    self.note_expr_uses(value);
+                }
+            }
+            Expr::ArrayLiteral(items) => {
+                for item in items {
+                    self.note_expr_uses(item);
+                }
+            }
+            Expr::Match { value, arms, .. } => {
+                self.note_expr_uses(value);
+                for arm in arms {
+                    self.note_expr_uses(&arm.body);
+                }
+            }
+            Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BoolLiteral(_)
+            | Expr::DateLiteral(_)
+            | Expr::CurrencyLiteral { .. }
+            | Expr::FilterLiteral(_) => {}
+        }
+    }
+    
+    /// Record reads inside `{name}` interpolations of a string
+    fn note_interpolated_uses(&mut self, text: &str) {
+        for segment in text.split('{').skip(1) {
+            let Some(end) = segment.find('}') else { continue };
+            let expr = segment[..end].trim();
+            let name = expr.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("");
+            if !name.is_empty() {
+                self.note_use(name);
+            }
+        }
+    }
+    
+    /// Warn about variables declared in the item just checked but never read
+    ///
+    /// Names starting with an underscore are exempt.
+    fn report_unused_variables(&mut self) {
+        let mut unused: Vec<(Atom, (NodeId, Span))> = self.declared_variables
+            .drain()
+            .filter(|(key, _)| !self.used_variables.contains(key))
+            .filter(|((_, name), _)| !name.starts_with('_'))
+            .map(|((_, name), site)| (name, site))
+            .collect();
+        self.used_variables.clear();
+        
+        unused.sort_by_key(|(_, (_, span))| (span.line, span.column));
+        for (name, (node, span)) in unused {
+            self.report_at(SemanticError::UnusedVariable { name }, node, span);
+        }
+    }
+    
     pub fn get_symbol_table(&self) -> &SymbolTable {
         &self.symbols
     }
+    
+    /// Types resolved for bindings and assignments, keyed by statement span
+    pub fn get_type_map(&self) -> &TypeMap {
+        &self.types
+    }
 }
 
 impl Default for SemanticAnalyzer {
@@ -555,3 +2738,135 @@ impl Default for SemanticAnalyzer {
         Self::new()
     }
 }
+
+/// Titles (or translation keys) of the sections among `statements`, nested
+/// ones included; interpolated titles are only known once the page runs
+fn collect_section_titles(statements: &[Statement], titles: &mut Vec<String>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Section { title, body, .. } => {
+                if let PageText::Literal(title) | PageText::Translated(title) = title {
+                    titles.push(title.clone());
+                }
+                collect_section_titles(body, titles);
+            }
+            Statement::Button { body, .. } | Statement::Confirm { body, .. } | Statement::Forall { body, .. } => {
+                collect_section_titles(body, titles);
+            }
+            Statement::If { then_branch, else_branch, .. } => {
+                collect_section_titles(then_branch, titles);
+                collect_section_titles(else_branch.as_deref().unwrap_or_default(), titles);
+            }
+            Statement::Match { arms, .. } => {
+                for arm in arms {
+                    collect_section_titles(&arm.body, titles);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Bare names in a condition checked against each row of a table, each once
+///
+/// Field names and called functions are not row names; the objects fields
+/// are read from and call arguments may contain some.
+fn collect_row_names(expr: &Expr, names: &mut Vec<Atom>) {
+    match expr {
+        Expr::Identifier(name) if !names.contains(name) => names.push(*name),
+        Expr::FunctionCall(call) => {
+            for arg in &call.args {
+                collect_row_names(arg, names);
+            }
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            collect_row_names(left, names);
+            collect_row_names(right, names);
+        }
+        Expr::UnaryOp { operand: inner, .. } | Expr::FieldAccess { object: inner, .. } => {
+            collect_row_names(inner, names);
+        }
+        _ => {}
+    }
+}
+
+/// Type of a literal; other expressions are not typed here
+fn literal_type(expr: &Expr) -> Option<Type> {
+    match expr {
+        Expr::IntLiteral(_) => Some(Type::Int),
+        Expr::FloatLiteral(_) => Some(Type::Float),
+        Expr::StringLiteral(_) => Some(Type::String),
+        Expr::BoolLiteral(_) => Some(Type::Bool),
+        Expr::DateLiteral(_) => Some(Type::Date),
+        Expr::CurrencyLiteral { .. } => Some(Type::Currency),
+        _ => None,
+    }
+}
+
+/// The expressions a statement evaluates itself, leaving out those of the
+/// statements nested in it
+fn statement_expressions(stmt: &Statement) -> Vec<&Expr> {
+    match stmt {
+        Statement::Title(text) | Statement::Subtitle(text) | Statement::Text(text)
+        | Statement::Button { label: text, .. }
+        | Statement::Section { title: text, .. }
+        | Statement::Confirm { message: text, .. } => text.expressions().map(|(expr, _)| expr).collect(),
+        Statement::Let { value: Some(value), .. } | Statement::Assign { value, .. } => vec![value],
+        Statement::If { condition: expr, .. }
+        | Statement::Forall { iterable: expr, .. }
+        | Statement::Match { value: expr, .. }
+        | Statement::Return(expr)
+        | Statement::Assert { condition: expr, .. }
+        | Statement::Mock { value: expr, .. } => vec![expr],
+        Statement::FunctionCall(call) => call.args.iter().collect(),
+        Statement::Let { value: None, .. } | Statement::Refresh { .. } | Statement::Python { .. } => Vec::new(),
+    }
+}
+
+/// Whether match patterns include both `true` and `false`
+fn covers_bools(literals: &[&Expr]) -> bool {
+    [true, false].into_iter().all(|value| literals.contains(&&Expr::BoolLiteral(value)))
+}
+
+/// Whether values of two types can be equal, ints and floats included
+fn comparable_types(a: &Type, b: &Type) -> bool {
+    let numeric = |ty: &Type| matches!(ty, Type::Int | Type::Float | Type::Currency);
+    a == b || (numeric(a) && numeric(b))
+}
+
+/// Whether Python code binds a name on some line: `name = ...`, `name += ...`,
+/// `name: type = ...`, a tuple such as `low, high = ...`, or `for name in ...`
+fn python_assigns(code: &str, name: &str) -> bool {
+    let is_name = |target: &str| target.trim().trim_matches(|c| c == '(' || c == ')').trim() == name;
+    code.lines().any(|line| {
+        let line = line.trim_start();
+        if let Some((targets, _)) = line.strip_prefix("for ").and_then(|rest| rest.split_once(" in ")) {
+            return targets.split(',').any(is_name);
+        }
+        assignment_targets(line).is_some_and(|targets| {
+            targets.split(',').any(|target| is_name(target.split(':').next().unwrap_or(target)))
+        })
+    })
+}
+
+/// The text before the `=` of a line assigning variables, without the
+/// operator of an augmented assignment such as `+=`
+fn assignment_targets(line: &str) -> Option<&str> {
+    let bytes = line.as_bytes();
+    let mut depth = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            // Strings and comments before any `=`: not an assignment
+            b'#' | b'"' | b'\'' => return None,
+            b'=' if depth == 0 => {
+                let comparison = bytes.get(i + 1) == Some(&b'=')
+                    || matches!(i.checked_sub(1).map(|before| bytes[before]), Some(b'=' | b'!' | b'<' | b'>'));
+                return (!comparison).then(|| line[..i].trim_end_matches(|c| "+-*/%&|^@".contains(c)));
+            }
+            _ => {}
+        }
+    }
+    None
+}
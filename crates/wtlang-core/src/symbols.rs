@@ -1,20 +1,42 @@
 // Symbol table implementation for WTLang
 
-use crate::ast::Type;
-use std::collections::HashMap;
-use std::sync::Arc;
+use crate::ast::{ColumnChange, NodeId, Span, TableType, Type};
+use crate::intern::Atom;
+use std::collections::{HashMap, HashSet};
+
+/// Index of a scope in the symbol table's scope arena
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(usize);
+
+impl ScopeId {
+    /// The global scope is always the first scope in the arena
+    pub const GLOBAL: ScopeId = ScopeId(0);
+    
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
 
 /// Symbol table for a single scope
 #[derive(Debug, Clone)]
 pub struct Scope {
     /// Parent scope (None for global scope)
-    parent: Option<Arc<Scope>>,
+    parent: Option<ScopeId>,
+    
+    /// Nested scopes, in the order they were entered
+    children: Vec<ScopeId>,
     
     /// Symbols defined in this scope
-    symbols: HashMap<String, Symbol>,
+    symbols: HashMap<Atom, Symbol>,
+    
+    /// Node declaring each symbol, for those declared by one
+    definitions: HashMap<Atom, NodeId>,
     
     /// Scope kind for error messages
     kind: ScopeKind,
+    
+    /// Source span covered by this scope (None for global scope)
+    span: Option<Span>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -23,17 +45,21 @@ pub enum ScopeKind {
     Page,
     Section,
     Button,
+    Confirm,
     IfBranch,
+    MatchArm,
     ForallLoop,
+    Lambda,
     FunctionBody,
     TestBody,
+    HookBody,
 }
 
 /// Information about a symbol
 #[derive(Debug, Clone)]
 pub struct Symbol {
     /// Symbol name
-    pub name: String,
+    pub name: Atom,
     
     /// Symbol type
     pub symbol_type: Type,
@@ -54,6 +80,12 @@ pub enum SymbolKind {
     Parameter,
     LoopVariable,
     Table,
+    /// `table Name = expression`, typed by the table it is derived from
+    DerivedTable,
+    /// `shared let`, kept in the session and visible from every page
+    SharedVariable,
+    /// `const`, a value known when compiling and visible everywhere
+    Constant,
     Function,
     ExternalFunction,
 }
@@ -61,18 +93,18 @@ pub enum SymbolKind {
 #[derive(Debug, Clone)]
 pub enum SymbolError {
     Redefinition {
-        name: String,
+        name: Atom,
     },
     UndefinedVariable {
-        name: String,
+        name: Atom,
     },
     TypeMismatch {
-        name: String,
+        name: Atom,
         expected: Type,
         found: Type,
     },
     UninitializedVariable {
-        name: String,
+        name: Atom,
     },
 }
 
@@ -99,16 +131,19 @@ impl std::error::Error for SymbolError {}
 
 impl Scope {
     /// Create a new scope with optional parent
-    pub fn new(parent: Option<Arc<Scope>>, kind: ScopeKind) -> Self {
+    pub fn new(parent: Option<ScopeId>, kind: ScopeKind) -> Self {
         Scope {
             parent,
+            children: Vec::new(),
             symbols: HashMap::new(),
+            definitions: HashMap::new(),
             kind,
+            span: None,
         }
     }
     
     /// Define a new symbol in this scope
-    pub fn define(&mut self, name: String, symbol: Symbol) -> Result<(), SymbolError> {
+    pub fn define(&mut self, name: Atom, symbol: Symbol) -> Result<(), SymbolError> {
         if self.symbols.contains_key(&name) {
             return Err(SymbolError::Redefinition { name });
         }
@@ -116,15 +151,19 @@ impl Scope {
         Ok(())
     }
     
-    /// Look up a symbol in this scope or parent scopes
-    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
-        self.symbols.get(name)
-            .or_else(|| self.parent.as_ref()?.lookup(name))
-    }
-    
     /// Look up a symbol only in this scope (not parent)
     pub fn lookup_local(&self, name: &str) -> Option<&Symbol> {
-        self.symbols.get(name)
+        self.symbols.get(&Atom::get(name)?)
+    }
+    
+    /// Get parent scope
+    pub fn parent(&self) -> Option<ScopeId> {
+        self.parent
+    }
+    
+    /// Get nested scopes
+    pub fn children(&self) -> &[ScopeId] {
+        &self.children
     }
     
     /// Get scope kind
@@ -132,129 +171,389 @@ impl Scope {
         self.kind
     }
     
+    /// Get the source span covered by this scope
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+    
+    /// The same scope moved to the program's `item`th item, `lines` lines
+    /// further down, for an item that moved
+    pub fn moved(&self, item: u32, lines: isize) -> Scope {
+        Scope {
+            definitions: self.definitions.iter().map(|(name, node)| (*name, node.in_item(item))).collect(),
+            span: self.span.map(|span| span.moved(lines)),
+            ..self.clone()
+        }
+    }
+    
     /// Get all symbols in this scope
-    pub fn symbols(&self) -> &HashMap<String, Symbol> {
+    pub fn symbols(&self) -> &HashMap<Atom, Symbol> {
         &self.symbols
     }
 }
 
 /// Global symbol table managing all scopes
+///
+/// Scopes live in an arena and refer to their parent by index. Popping a
+/// scope only moves the cursor back to its parent, so after analysis every
+/// scope (including sibling pages, loops and function bodies) is still
+/// available to tooling.
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
-    /// Global scope containing tables, functions, etc.
-    global: Arc<Scope>,
+    /// All scopes ever entered; index 0 is the global scope
+    scopes: Vec<Scope>,
     
-    /// Current scope stack during analysis
-    current_scopes: Vec<Arc<Scope>>,
+    /// Scopes entered but not yet exited during analysis
+    active: Vec<ScopeId>,
     
     /// Map of table name to key field name
-    table_keys: HashMap<String, String>,
+    table_keys: HashMap<Atom, Atom>,
     
     /// Map of table name to fields that reference other tables
     /// Each entry is (field_name, target_table)
-    table_refs: HashMap<String, Vec<(String, String)>>,
+    table_refs: HashMap<Atom, Vec<(Atom, Atom)>>,
+    
+    /// Map of table name to its fields and their types
+    table_fields: HashMap<Atom, Vec<(Atom, Type)>>,
+    
+    /// Fields that always have a value, as (table_name, field_name)
+    non_null_fields: HashSet<(Atom, Atom)>,
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
-            global: Arc::new(Scope::new(None, ScopeKind::Global)),
-            current_scopes: vec![],
+            scopes: vec![Scope::new(None, ScopeKind::Global)],
+            active: vec![],
             table_keys: HashMap::new(),
             table_refs: HashMap::new(),
+            table_fields: HashMap::new(),
+            non_null_fields: HashSet::new(),
         }
     }
     
-    /// Enter a new scope
-    pub fn push_scope(&mut self, kind: ScopeKind) {
-        let parent = self.current_scope();
-        let new_scope = Arc::new(Scope::new(Some(parent.clone()), kind));
-        self.current_scopes.push(new_scope);
+    /// Enter a new scope nested in the current one
+    pub fn push_scope(&mut self, kind: ScopeKind) -> ScopeId {
+        let parent = self.current_scope_id();
+        let id = ScopeId(self.scopes.len());
+        self.scopes.push(Scope::new(Some(parent), kind));
+        self.scopes[parent.0].children.push(id);
+        self.active.push(id);
+        id
+    }
+    
+    /// Enter a new scope that covers the given source span
+    ///
+    /// Spans let tooling map a cursor position back to the scope that was
+    /// active there (see `scope_at`).
+    pub fn push_scope_with_span(&mut self, kind: ScopeKind, span: Span) -> ScopeId {
+        let id = self.push_scope(kind);
+        self.scopes[id.0].span = Some(span);
+        id
     }
     
     /// Exit current scope
-    pub fn pop_scope(&mut self) -> Option<Arc<Scope>> {
-        self.current_scopes.pop()
+    ///
+    /// The scope stays in the arena; only the cursor moves.
+    pub fn pop_scope(&mut self) -> Option<ScopeId> {
+        self.active.pop()
+    }
+    
+    /// Get the id of the current scope (or global if no scopes pushed)
+    pub fn current_scope_id(&self) -> ScopeId {
+        self.active.last().copied().unwrap_or(ScopeId::GLOBAL)
     }
     
     /// Get current scope (or global if no scopes pushed)
-    pub fn current_scope(&self) -> Arc<Scope> {
-        self.current_scopes.last()
-            .cloned()
-            .unwrap_or_else(|| self.global.clone())
+    pub fn current_scope(&self) -> &Scope {
+        &self.scopes[self.current_scope_id().0]
     }
     
     /// Get global scope
-    pub fn global_scope(&self) -> Arc<Scope> {
-        self.global.clone()
+    pub fn global_scope(&self) -> &Scope {
+        &self.scopes[ScopeId::GLOBAL.0]
+    }
+    
+    /// Get a scope by id
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0]
+    }
+    
+    /// Iterate over every scope with its id, in creation order
+    pub fn scopes(&self) -> impl Iterator<Item = (ScopeId, &Scope)> {
+        self.scopes.iter().enumerate().map(|(i, s)| (ScopeId(i), s))
+    }
+    
+    /// Copy out every scope created since `first`
+    ///
+    /// Scopes entered while checking one top-level item occupy a contiguous
+    /// run of the arena, so this captures that item's scope tree. Ids in the
+    /// copy are relative to `first`; links to scopes outside the run are
+    /// dropped so the copy can be grafted into another table.
+    pub fn scopes_since(&self, first: ScopeId) -> Vec<Scope> {
+        self.scopes[first.0..]
+            .iter()
+            .map(|scope| {
+                let mut scope = scope.clone();
+                scope.parent = scope.parent
+                    .filter(|p| p.0 >= first.0)
+                    .map(|p| ScopeId(p.0 - first.0));
+                for child in &mut scope.children {
+                    child.0 -= first.0;
+                }
+                scope
+            })
+            .collect()
+    }
+    
+    /// Append scopes captured by `scopes_since`, nesting their roots in the
+    /// current scope
+    pub fn graft_scopes(&mut self, scopes: &[Scope]) {
+        let offset = self.scopes.len();
+        let parent = self.current_scope_id();
+        for scope in scopes {
+            let id = ScopeId(self.scopes.len());
+            let mut scope = scope.clone();
+            match scope.parent {
+                Some(p) => scope.parent = Some(ScopeId(p.0 + offset)),
+                None => {
+                    scope.parent = Some(parent);
+                    self.scopes[parent.0].children.push(id);
+                }
+            }
+            for child in &mut scope.children {
+                child.0 += offset;
+            }
+            self.scopes.push(scope);
+        }
     }
     
     /// Define symbol in current scope
-    pub fn define(&mut self, name: String, symbol: Symbol) -> Result<(), SymbolError> {
-        if self.current_scopes.is_empty() {
-            // Define in global scope
-            Arc::make_mut(&mut self.global).define(name, symbol)
-        } else {
-            // Define in current scope
-            let idx = self.current_scopes.len() - 1;
-            Arc::make_mut(&mut self.current_scopes[idx]).define(name, symbol)
+    pub fn define(&mut self, name: Atom, symbol: Symbol) -> Result<(), SymbolError> {
+        let id = self.current_scope_id();
+        self.scopes[id.0].define(name, symbol)
+    }
+    
+    /// Define symbol in current scope, declared by `node`
+    pub fn define_at(&mut self, name: Atom, symbol: Symbol, node: NodeId) -> Result<(), SymbolError> {
+        let id = self.current_scope_id();
+        self.scopes[id.0].define(name, symbol)?;
+        self.scopes[id.0].definitions.insert(name, node);
+        Ok(())
+    }
+    
+    /// Find the node declaring a name as seen from the given scope
+    pub fn definition_from(&self, scope: ScopeId, name: &str) -> Option<NodeId> {
+        let name = Atom::get(name)?;
+        let mut current = Some(scope);
+        while let Some(id) = current {
+            let scope = &self.scopes[id.0];
+            if scope.symbols.contains_key(&name) {
+                return scope.definitions.get(&name).copied();
+            }
+            current = scope.parent;
         }
+        None
+    }
+    
+    /// Find the symbol a node declares, with the scope defining it
+    pub fn defined_by(&self, node: NodeId) -> Option<(ScopeId, &Symbol)> {
+        self.scopes().find_map(|(id, scope)| {
+            let (name, _) = scope.definitions.iter().find(|(_, n)| **n == node)?;
+            Some((id, scope.symbols.get(name)?))
+        })
     }
     
     /// Look up symbol from current scope
     pub fn lookup(&self, name: &str) -> Option<Symbol> {
-        self.current_scope().lookup(name).cloned()
+        self.lookup_from(self.current_scope_id(), name).cloned()
     }
     
-    /// Mark a variable as initialized
-    pub fn mark_initialized(&mut self, name: &str) -> Result<(), SymbolError> {
-        // Try to find and update the symbol in current or parent scopes
-        for i in (0..self.current_scopes.len()).rev() {
-            let scope = &self.current_scopes[i];
-            if scope.lookup_local(name).is_some() {
-                let scope_mut = Arc::make_mut(&mut self.current_scopes[i]);
-                if let Some(symbol) = scope_mut.symbols.get_mut(name) {
-                    symbol.is_initialized = true;
-                    return Ok(());
+    /// Look up a symbol starting at the given scope and walking up to global
+    pub fn lookup_from(&self, scope: ScopeId, name: &str) -> Option<&Symbol> {
+        let name = Atom::get(name)?;
+        let mut current = Some(scope);
+        while let Some(id) = current {
+            let scope = &self.scopes[id.0];
+            if let Some(symbol) = scope.symbols.get(&name) {
+                return Some(symbol);
+            }
+            current = scope.parent;
+        }
+        None
+    }
+    
+    /// Look up `name` in the module imported as `module`, whose functions
+    /// and constants are global symbols named `module.name`
+    pub fn lookup_qualified(&self, module: &str, name: &str) -> Option<&Symbol> {
+        self.lookup_from(ScopeId::GLOBAL, &format!("{}.{}", module, name))
+    }
+    
+    /// Find the scope that defines a name, searching from the current scope
+    /// outwards
+    pub fn defining_scope(&self, name: &str) -> Option<ScopeId> {
+        let name = Atom::get(name)?;
+        let mut current = Some(self.current_scope_id());
+        while let Some(id) = current {
+            let scope = &self.scopes[id.0];
+            if scope.symbols.contains_key(&name) {
+                return Some(id);
+            }
+            current = scope.parent;
+        }
+        None
+    }
+    
+    /// Whether the current scope is, or is nested inside, a scope of `kind`
+    pub fn within(&self, kind: ScopeKind) -> bool {
+        let mut current = Some(self.current_scope_id());
+        while let Some(id) = current {
+            let scope = &self.scopes[id.0];
+            if scope.kind == kind {
+                return true;
+            }
+            current = scope.parent;
+        }
+        false
+    }
+    
+    /// Find the innermost scope containing a 1-based line/column position
+    ///
+    /// Falls back to the global scope when the position is outside every
+    /// spanned scope.
+    pub fn scope_at(&self, line: usize, column: usize) -> ScopeId {
+        let mut current = ScopeId::GLOBAL;
+        'descend: loop {
+            for &child in &self.scopes[current.0].children {
+                if self.scopes[child.0].span.is_some_and(|s| s.contains(line, column)) {
+                    current = child;
+                    continue 'descend;
                 }
             }
+            return current;
         }
+    }
+    
+    /// Resolve a name as seen from a 1-based line/column position
+    pub fn resolve_at(&self, line: usize, column: usize, name: &str) -> Option<&Symbol> {
+        self.lookup_from(self.scope_at(line, column), name)
+    }
+    
+    /// All symbols visible at a 1-based line/column position
+    ///
+    /// Inner definitions shadow outer ones, so each name appears once.
+    pub fn symbols_at(&self, line: usize, column: usize) -> Vec<&Symbol> {
+        let mut seen = std::collections::HashSet::new();
+        let mut visible = Vec::new();
+        let mut current = Some(self.scope_at(line, column));
+        while let Some(id) = current {
+            let scope = &self.scopes[id.0];
+            for (name, symbol) in &scope.symbols {
+                if seen.insert(*name) {
+                    visible.push(symbol);
+                }
+            }
+            current = scope.parent;
+        }
+        visible
+    }
+    
+    /// Mark a variable as initialized
+    pub fn mark_initialized(&mut self, name: &str) -> Result<(), SymbolError> {
+        let Some(atom) = Atom::get(name) else {
+            return Err(SymbolError::UndefinedVariable { name: Atom::new(name) });
+        };
         
-        // Check global scope
-        if self.global.lookup_local(name).is_some() {
-            let global_mut = Arc::make_mut(&mut self.global);
-            if let Some(symbol) = global_mut.symbols.get_mut(name) {
+        // Find the nearest enclosing scope that defines the symbol
+        let mut current = Some(self.current_scope_id());
+        while let Some(id) = current {
+            let scope = &mut self.scopes[id.0];
+            if let Some(symbol) = scope.symbols.get_mut(&atom) {
                 symbol.is_initialized = true;
                 return Ok(());
             }
+            current = scope.parent;
         }
         
-        Err(SymbolError::UndefinedVariable { name: name.to_string() })
+        Err(SymbolError::UndefinedVariable { name: atom })
     }
     
     /// Register a key field for a table
-    pub fn register_key(&mut self, table_name: String, key_field: String) {
+    pub fn register_key(&mut self, table_name: Atom, key_field: Atom) {
         self.table_keys.insert(table_name, key_field);
     }
     
     /// Register a reference field for a table
-    pub fn register_ref(&mut self, table_name: String, field_name: String, target_table: String) {
+    pub fn register_ref(&mut self, table_name: Atom, field_name: Atom, target_table: Atom) {
         self.table_refs
             .entry(table_name)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push((field_name, target_table));
     }
     
+    /// Register the fields of a table
+    pub fn register_fields(&mut self, table_name: Atom, fields: Vec<(Atom, Type)>) {
+        self.table_fields.insert(table_name, fields);
+    }
+    
+    /// Get the type of a table's field; `None` if the table or field is unknown
+    pub fn get_field_type(&self, table: &TableType, field_name: &str) -> Option<&Type> {
+        match column_origin(table, field_name) {
+            Origin::Declared(field_name) => self.table_fields.get(&table.name)?
+                .iter()
+                .find(|(f, _)| *f == field_name)
+                .map(|(_, ty)| ty),
+            Origin::Added => Some(&Type::Float),
+            Origin::Removed => None,
+        }
+    }
+    
+    /// The columns of a table in order; `None` if its fields are unknown
+    pub fn table_columns(&self, table: &TableType) -> Option<Vec<Atom>> {
+        let mut columns: Vec<Atom> = self.table_fields.get(&table.name)?.iter().map(|(f, _)| *f).collect();
+        for change in &table.changes {
+            match *change {
+                ColumnChange::Add(column) => columns.push(column),
+                ColumnChange::Rename(from, to) => {
+                    for column in columns.iter_mut().filter(|column| **column == from) {
+                        *column = to;
+                    }
+                }
+                ColumnChange::Drop(column) => columns.retain(|c| *c != column),
+            }
+        }
+        Some(columns)
+    }
+    
+    /// Check whether the fields of a table are known
+    pub fn has_fields(&self, table: &TableType) -> bool {
+        self.table_fields.contains_key(&table.name)
+    }
+    
+    /// Register a field that is a key or `non_null`, so it always has a value
+    pub fn register_non_null(&mut self, table_name: Atom, field_name: Atom) {
+        self.non_null_fields.insert((table_name, field_name));
+    }
+    
+    /// Check whether a field of a table always has a value
+    pub fn is_non_null(&self, table: &TableType, field_name: &str) -> bool {
+        match column_origin(table, field_name) {
+            Origin::Declared(field_name) => Atom::get(field_name)
+                .is_some_and(|field| self.non_null_fields.contains(&(table.name, field))),
+            Origin::Added | Origin::Removed => false,
+        }
+    }
+    
     /// Get the key field for a table
-    pub fn get_key_field(&self, table_name: &str) -> Option<&String> {
-        self.table_keys.get(table_name)
+    pub fn get_key_field(&self, table_name: &str) -> Option<&Atom> {
+        self.table_keys.get(&Atom::get(table_name)?)
     }
     
     /// Get the target table for a reference field
-    pub fn get_ref_target(&self, table_name: &str, field_name: &str) -> Option<&String> {
-        self.table_refs.get(table_name)?
+    pub fn get_ref_target(&self, table_name: &str, field_name: &str) -> Option<&Atom> {
+        self.table_refs.get(&Atom::get(table_name)?)?
             .iter()
-            .find(|(f, _)| f == field_name)
+            .find(|(f, _)| *f == field_name)
             .map(|(_, t)| t)
     }
     
@@ -272,6 +571,36 @@ impl Default for SymbolTable {
     }
 }
 
+/// The module and name of a function or constant of a module, such as
+/// `analytics` and `compute_kpi` for `analytics.compute_kpi`
+pub fn split_qualified(name: &str) -> Option<(&str, &str)> {
+    name.split_once('.')
+}
+
+/// Where a column of a table type comes from
+enum Origin<'a> {
+    /// The declared table's column of this name
+    Declared(&'a str),
+    /// A column a change added
+    Added,
+    /// None: a change renamed or dropped the column
+    Removed,
+}
+
+/// Follow a column back through the changes of a table type, latest first
+fn column_origin<'a>(table: &TableType, column: &'a str) -> Origin<'a> {
+    let mut column = column;
+    for change in table.changes.iter().rev() {
+        match *change {
+            ColumnChange::Add(added) if added == column => return Origin::Added,
+            ColumnChange::Rename(from, _) | ColumnChange::Drop(from) if from == column => return Origin::Removed,
+            ColumnChange::Rename(from, to) if to == column => column = from.as_str(),
+            _ => {}
+        }
+    }
+    Origin::Declared(column)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -281,14 +610,14 @@ mod tests {
         let mut table = SymbolTable::new();
         
         let symbol = Symbol {
-            name: "global_var".to_string(),
+            name: Atom::new("global_var"),
             symbol_type: Type::Float,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
         
-        table.define("global_var".to_string(), symbol).unwrap();
+        table.define(Atom::new("global_var"), symbol).unwrap();
         let found = table.lookup("global_var").unwrap();
         
         assert_eq!(found.name, "global_var");
@@ -302,24 +631,24 @@ mod tests {
         
         // Define in global
         let global_sym = Symbol {
-            name: "outer".to_string(),
+            name: Atom::new("outer"),
             symbol_type: Type::String,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("outer".to_string(), global_sym).unwrap();
+        table.define(Atom::new("outer"), global_sym).unwrap();
         
         // Enter page scope
         table.push_scope(ScopeKind::Page);
         let page_sym = Symbol {
-            name: "inner".to_string(),
+            name: Atom::new("inner"),
             symbol_type: Type::Int,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("inner".to_string(), page_sym).unwrap();
+        table.define(Atom::new("inner"), page_sym).unwrap();
         
         // Inner scope can see both
         assert!(table.lookup("outer").is_some());
@@ -339,24 +668,24 @@ mod tests {
         
         // Define in global
         let outer_sym = Symbol {
-            name: "x".to_string(),
+            name: Atom::new("x"),
             symbol_type: Type::Int,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("x".to_string(), outer_sym).unwrap();
+        table.define(Atom::new("x"), outer_sym).unwrap();
         
         // Enter function scope
         table.push_scope(ScopeKind::FunctionBody);
         let inner_sym = Symbol {
-            name: "x".to_string(),
+            name: Atom::new("x"),
             symbol_type: Type::String,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("x".to_string(), inner_sym).unwrap();
+        table.define(Atom::new("x"), inner_sym).unwrap();
         
         // Inner shadows outer
         let found = table.lookup("x").unwrap();
@@ -375,22 +704,22 @@ mod tests {
         let mut table = SymbolTable::new();
         
         let sym1 = Symbol {
-            name: "var".to_string(),
+            name: Atom::new("var"),
             symbol_type: Type::Float,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("var".to_string(), sym1).unwrap();
+        table.define(Atom::new("var"), sym1).unwrap();
         
         let sym2 = Symbol {
-            name: "var".to_string(),
+            name: Atom::new("var"),
             symbol_type: Type::String,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        let result = table.define("var".to_string(), sym2);
+        let result = table.define(Atom::new("var"), sym2);
         
         assert!(result.is_err());
         match result.unwrap_err() {
@@ -410,13 +739,13 @@ mod tests {
         let mut table = SymbolTable::new();
         
         let symbol = Symbol {
-            name: "var".to_string(),
+            name: Atom::new("var"),
             symbol_type: Type::Int,
             kind: SymbolKind::Variable,
             is_initialized: false,
             is_mutable: true,
         };
-        table.define("var".to_string(), symbol).unwrap();
+        table.define(Atom::new("var"), symbol).unwrap();
         
         // Initially not initialized
         assert!(!table.lookup("var").unwrap().is_initialized);
@@ -434,13 +763,13 @@ mod tests {
         
         table.push_scope(ScopeKind::Page);
         let symbol = Symbol {
-            name: "var".to_string(),
+            name: Atom::new("var"),
             symbol_type: Type::Float,
             kind: SymbolKind::Variable,
             is_initialized: false,
             is_mutable: true,
         };
-        table.define("var".to_string(), symbol).unwrap();
+        table.define(Atom::new("var"), symbol).unwrap();
         
         table.push_scope(ScopeKind::IfBranch);
         table.mark_initialized("var").unwrap();
@@ -457,13 +786,13 @@ mod tests {
         table.push_scope(ScopeKind::FunctionBody);
         
         let param = Symbol {
-            name: "x".to_string(),
+            name: Atom::new("x"),
             symbol_type: Type::Int,
             kind: SymbolKind::Parameter,
             is_initialized: true,  // Parameters are initialized by definition
             is_mutable: true,
         };
-        table.define("x".to_string(), param).unwrap();
+        table.define(Atom::new("x"), param).unwrap();
         
         let found = table.lookup("x").unwrap();
         assert_eq!(found.kind, SymbolKind::Parameter);
@@ -477,13 +806,13 @@ mod tests {
         table.push_scope(ScopeKind::ForallLoop);
         
         let loop_var = Symbol {
-            name: "item".to_string(),
+            name: Atom::new("item"),
             symbol_type: Type::String,
             kind: SymbolKind::LoopVariable,
             is_initialized: true,
             is_mutable: false,  // Loop variables typically shouldn't be reassigned
         };
-        table.define("item".to_string(), loop_var).unwrap();
+        table.define(Atom::new("item"), loop_var).unwrap();
         
         let found = table.lookup("item").unwrap();
         assert_eq!(found.kind, SymbolKind::LoopVariable);
@@ -495,35 +824,35 @@ mod tests {
         
         // Global
         let global_sym = Symbol {
-            name: "global".to_string(),
+            name: Atom::new("global"),
             symbol_type: Type::Float,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("global".to_string(), global_sym).unwrap();
+        table.define(Atom::new("global"), global_sym).unwrap();
         
         // Page scope
         table.push_scope(ScopeKind::Page);
         let page_sym = Symbol {
-            name: "page_var".to_string(),
+            name: Atom::new("page_var"),
             symbol_type: Type::String,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("page_var".to_string(), page_sym).unwrap();
+        table.define(Atom::new("page_var"), page_sym).unwrap();
         
         // Section scope
         table.push_scope(ScopeKind::Section);
         let section_sym = Symbol {
-            name: "section_var".to_string(),
+            name: Atom::new("section_var"),
             symbol_type: Type::Bool,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("section_var".to_string(), section_sym).unwrap();
+        table.define(Atom::new("section_var"), section_sym).unwrap();
         
         // All three should be visible
         assert!(table.lookup("global").is_some());
@@ -548,23 +877,23 @@ mod tests {
         
         // Table definition
         let table_sym = Symbol {
-            name: "User".to_string(),
-            symbol_type: Type::Table("User".to_string()),
+            name: Atom::new("User"),
+            symbol_type: Type::Table(Atom::new("User").into()),
             kind: SymbolKind::Table,
             is_initialized: true,
             is_mutable: false,
         };
-        table.define("User".to_string(), table_sym).unwrap();
+        table.define(Atom::new("User"), table_sym).unwrap();
         
         // Function definition (functions don't have a specific Type variant in this AST)
         let func_sym = Symbol {
-            name: "add".to_string(),
+            name: Atom::new("add"),
             symbol_type: Type::String,  // Placeholder since there's no Type::Function
             kind: SymbolKind::Function,
             is_initialized: true,
             is_mutable: false,
         };
-        table.define("add".to_string(), func_sym).unwrap();
+        table.define(Atom::new("add"), func_sym).unwrap();
         
         assert_eq!(table.lookup("User").unwrap().kind, SymbolKind::Table);
         assert_eq!(table.lookup("add").unwrap().kind, SymbolKind::Function);
@@ -577,13 +906,13 @@ mod tests {
         // Page 1
         table.push_scope(ScopeKind::Page);
         let sym1 = Symbol {
-            name: "page1_var".to_string(),
+            name: Atom::new("page1_var"),
             symbol_type: Type::Int,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("page1_var".to_string(), sym1).unwrap();
+        table.define(Atom::new("page1_var"), sym1).unwrap();
         table.pop_scope();
         
         // Page 2
@@ -592,13 +921,71 @@ mod tests {
         assert!(table.lookup("page1_var").is_none());
         
         let sym2 = Symbol {
-            name: "page2_var".to_string(),
+            name: Atom::new("page2_var"),
             symbol_type: Type::String,
             kind: SymbolKind::Variable,
             is_initialized: true,
             is_mutable: true,
         };
-        table.define("page2_var".to_string(), sym2).unwrap();
+        table.define(Atom::new("page2_var"), sym2).unwrap();
         assert!(table.lookup("page2_var").is_some());
     }
+
+    #[test]
+    fn test_popped_scopes_are_kept() {
+        let mut table = SymbolTable::new();
+        
+        let page1 = table.push_scope(ScopeKind::Page);
+        table.define(Atom::new("first"), Symbol {
+            name: Atom::new("first"),
+            symbol_type: Type::Int,
+            kind: SymbolKind::Variable,
+            is_initialized: true,
+            is_mutable: false,
+        }).unwrap();
+        table.pop_scope();
+        
+        let page2 = table.push_scope(ScopeKind::Page);
+        table.pop_scope();
+        
+        // Both sibling scopes survive and hang off the global scope
+        assert_eq!(table.global_scope().children(), &[page1, page2]);
+        assert_eq!(table.scope(page1).parent(), Some(ScopeId::GLOBAL));
+        assert!(table.scope(page1).lookup_local("first").is_some());
+        assert!(table.lookup_from(page2, "first").is_none());
+        assert_eq!(table.scopes().count(), 3);
+    }
+
+    #[test]
+    fn test_scope_at_position() {
+        let mut table = SymbolTable::new();
+        table.define(Atom::new("Users"), Symbol {
+            name: Atom::new("Users"),
+            symbol_type: Type::Table(Atom::new("Users").into()),
+            kind: SymbolKind::Table,
+            is_initialized: true,
+            is_mutable: false,
+        }).unwrap();
+        
+        let page = table.push_scope_with_span(ScopeKind::Page, Span::new(1, 1, 10, 1));
+        let loop_scope = table.push_scope_with_span(ScopeKind::ForallLoop, Span::new(3, 5, 5, 5));
+        table.define(Atom::new("row"), Symbol {
+            name: Atom::new("row"),
+            symbol_type: Type::Table(Atom::new("Users").into()),
+            kind: SymbolKind::LoopVariable,
+            is_initialized: true,
+            is_mutable: false,
+        }).unwrap();
+        table.pop_scope();
+        table.pop_scope();
+        
+        assert_eq!(table.scope_at(4, 10), loop_scope);
+        assert_eq!(table.scope_at(7, 1), page);
+        assert_eq!(table.scope_at(20, 1), ScopeId::GLOBAL);
+        
+        assert!(table.resolve_at(4, 10, "row").is_some());
+        assert!(table.resolve_at(7, 1, "row").is_none());
+        assert!(table.resolve_at(7, 1, "Users").is_some());
+        assert_eq!(table.symbols_at(4, 10).len(), 2);
+    }
 }
@@ -0,0 +1,270 @@
+// Expansion of templates
+//
+// `template kpi_row(t: table, col: string) { ... }` declares statements that
+// pages repeat, and `use kpi_row(sales, "amount")` stands for them with each
+// parameter replaced by its argument. The parser expands every `use` as it
+// reads it, so later stages only see ordinary statements. Expansion is
+// hygienic: the variables a template declares are renamed apart for each
+// use, so two uses of a template never clash, and the template's variables
+// neither capture nor shadow those around the `use`.
+
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::intern::Atom;
+
+/// The statements `use template(args)` stands for
+///
+/// The variables the template declares get `suffix` appended to their
+/// names, and every node a new id from `next_id`.
+pub fn expand(template: &Template, args: Vec<Expr>, suffix: &str, next_id: &mut dyn FnMut() -> NodeId) -> Vec<Statement> {
+    let mut declared = Vec::new();
+    let mut code_inputs = Vec::new();
+    visit(&template.body, &mut |statement| match statement {
+        Statement::Let { name, .. } | Statement::Forall { var: name, .. } => declared.push(*name),
+        Statement::Python { inputs, outputs, .. } => {
+            code_inputs.extend(inputs.iter().map(|input| input.variable));
+            declared.extend(outputs.iter().map(|output| output.variable));
+        }
+        _ => {}
+    });
+    let mut args: HashMap<Atom, Expr> = template.params.iter().map(|param| param.name).zip(args).collect();
+    
+    // `python` blocks read variables, so other arguments they read are
+    // bound to one first
+    let mut body = Vec::new();
+    for param in &template.params {
+        let Some(arg) = args.get_mut(&param.name) else { continue };
+        if code_inputs.contains(&param.name) && !matches!(arg, Expr::Identifier(_)) {
+            let name = Atom::from(format!("{}__{}", param.name, suffix));
+            let value = std::mem::replace(arg, Expr::Identifier(name));
+            body.push(Statement::Let { name, type_annotation: None, value: Some(value), id: next_id(), span: template.span });
+        }
+    }
+    
+    let mut expansion = Expansion {
+        args,
+        renames: declared.into_iter()
+            .map(|name| (name, Atom::from(format!("{}__{}", name, suffix))))
+            .collect(),
+        next_id,
+    };
+    let mut statements = template.body.clone();
+    expansion.statements(&mut statements);
+    body.extend(statements);
+    body
+}
+
+/// Call `f` with every statement, nested ones included
+fn visit(statements: &[Statement], f: &mut dyn FnMut(&Statement)) {
+    for statement in statements {
+        f(statement);
+        match statement {
+            Statement::Forall { body, .. }
+            | Statement::Button { body, .. }
+            | Statement::Section { body, .. }
+            | Statement::Confirm { body, .. } => visit(body, f),
+            Statement::If { then_branch, else_branch, .. } => {
+                visit(then_branch, f);
+                if let Some(else_branch) = else_branch {
+                    visit(else_branch, f);
+                }
+            }
+            Statement::Match { arms, .. } => {
+                for arm in arms {
+                    visit(&arm.body, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Expansion<'a> {
+    /// Argument of each parameter
+    args: HashMap<Atom, Expr>,
+    /// New name of each variable the template declares
+    renames: HashMap<Atom, Atom>,
+    next_id: &'a mut dyn FnMut() -> NodeId,
+}
+
+impl Expansion<'_> {
+    fn statements(&mut self, statements: &mut [Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Title(text) | Statement::Subtitle(text) | Statement::Text(text) => self.page_text(text),
+            Statement::Button { label: text, body, id, .. }
+            | Statement::Section { title: text, body, id, .. }
+            | Statement::Confirm { message: text, body, id, .. } => {
+                self.page_text(text);
+                self.statements(body);
+                *id = (self.next_id)();
+            }
+            Statement::Let { name, value, id, .. } => {
+                self.rename(name);
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                *id = (self.next_id)();
+            }
+            Statement::Assign { name, value, id, .. } => {
+                // Assigning a parameter assigns the variable passed for it
+                match self.args.get(name) {
+                    Some(Expr::Identifier(variable)) => *name = *variable,
+                    _ => self.rename(name),
+                }
+                self.expr(value);
+                *id = (self.next_id)();
+            }
+            Statement::If { condition, then_branch, else_branch, id, .. } => {
+                self.expr(condition);
+                self.statements(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statements(else_branch);
+                }
+                *id = (self.next_id)();
+            }
+            Statement::Forall { var, iterable, body, id, .. } => {
+                self.rename(var);
+                self.expr(iterable);
+                self.statements(body);
+                *id = (self.next_id)();
+            }
+            Statement::Match { value, arms, id, .. } => {
+                self.expr(value);
+                for arm in arms {
+                    self.patterns(&mut arm.patterns);
+                    self.statements(&mut arm.body);
+                }
+                *id = (self.next_id)();
+            }
+            Statement::Return(value) => self.expr(value),
+            Statement::FunctionCall(call) => self.call(call),
+            Statement::Assert { condition: value, id, .. } | Statement::Mock { value, id, .. } => {
+                self.expr(value);
+                *id = (self.next_id)();
+            }
+            Statement::Refresh { id, .. } => *id = (self.next_id)(),
+            // The code keeps its names; the bindings say which variables they are
+            Statement::Python { inputs, outputs, id, .. } => {
+                for binding in inputs.iter_mut().chain(outputs) {
+                    match self.args.get(&binding.variable) {
+                        Some(Expr::Identifier(variable)) => binding.variable = *variable,
+                        _ => self.rename(&mut binding.variable),
+                    }
+                }
+                *id = (self.next_id)();
+            }
+        }
+    }
+
+    fn rename(&self, name: &mut Atom) {
+        if let Some(renamed) = self.renames.get(name) {
+            *name = *renamed;
+        }
+    }
+
+    fn page_text(&mut self, text: &mut PageText) {
+        if let PageText::Interpolated(parts) = text {
+            for part in parts {
+                if let TextPart::Expr { expr, .. } = part {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+
+    fn patterns(&mut self, patterns: &mut [MatchPattern]) {
+        for pattern in patterns {
+            if let MatchPattern::Literal(value) = pattern {
+                self.expr(value);
+            }
+        }
+    }
+
+    fn call(&mut self, call: &mut FunctionCall) {
+        for arg in &mut call.args {
+            self.expr(arg);
+        }
+        call.id = (self.next_id)();
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Identifier(name) => {
+                if let Some(renamed) = self.renames.get(name) {
+                    *name = *renamed;
+                } else if let Some(arg) = self.args.get(name) {
+                    *expr = arg.clone();
+                }
+            }
+            Expr::FunctionCall(call) => self.call(call),
+            Expr::Lambda { params, body } => {
+                // The lambda's parameters shadow the template's names
+                let shadowed: Vec<_> = params.iter()
+                    .map(|param| (*param, self.renames.remove(param), self.args.remove(param)))
+                    .collect();
+                self.expr(body);
+                for (param, renamed, arg) in shadowed {
+                    if let Some(renamed) = renamed {
+                        self.renames.insert(param, renamed);
+                    }
+                    if let Some(arg) = arg {
+                        self.args.insert(param, arg);
+                    }
+                }
+            }
+            Expr::BinaryOp { left, right, .. } | Expr::Chain { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Index { object: left, index: right, .. } | Expr::Where { table: left, condition: right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::UnaryOp { operand: value, .. }
+            | Expr::FieldAccess { object: value, .. }
+            | Expr::ColumnSelect { table: value, .. }
+            | Expr::Rename { table: value, .. }
+            | Expr::DropColumns { table: value, .. } => self.expr(value),
+            Expr::SortBy { table, columns, .. } => {
+                self.expr(table);
+                for column in columns {
+                    self.expr(&mut column.key);
+                }
+            }
+            Expr::GroupBy { table, having, .. } => {
+                self.expr(table);
+                self.expr(having);
+            }
+            Expr::Between { value, low, high, .. } => {
+                self.expr(value);
+                self.expr(low);
+                self.expr(high);
+            }
+            Expr::Match { value, arms, .. } => {
+                self.expr(value);
+                for arm in arms {
+                    self.patterns(&mut arm.patterns);
+                    self.expr(&mut arm.body);
+                }
+            }
+            Expr::TableLiteral(fields) => {
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+            }
+            Expr::ArrayLiteral(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::IntLiteral(_) | Expr::FloatLiteral(_) | Expr::StringLiteral(_) | Expr::BoolLiteral(_)
+            | Expr::DateLiteral(_) | Expr::CurrencyLiteral { .. } | Expr::FilterLiteral(_) => {}
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Per-expression type information for WTLang
+//
+// The semantic analyzer records the type it resolved for each binding and
+// assignment, and for each expression node, keyed by node id along with the
+// node's source span. Tools such as the LSP query this after analysis for
+// hover, inlay hints and code actions.
+
+use crate::ast::{NodeId, Span, Type};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Mapping from nodes to resolved types
+#[derive(Debug, Clone, Default)]
+pub struct TypeMap {
+    entries: HashMap<NodeId, (Span, Type)>,
+    /// Nodes in the order their types were first recorded
+    order: Vec<NodeId>,
+}
+
+impl TypeMap {
+    pub fn new() -> Self {
+        TypeMap::default()
+    }
+    
+    /// Record the resolved type of a node, replacing any earlier entry
+    pub fn insert(&mut self, node: NodeId, span: Span, ty: Type) {
+        if self.entries.insert(node, (span, ty)).is_none() {
+            self.order.push(node);
+        }
+    }
+    
+    /// Get the type recorded for a node
+    pub fn get(&self, node: NodeId) -> Option<&Type> {
+        self.entries.get(&node).map(|(_, ty)| ty)
+    }
+    
+    /// Find the innermost span containing a 1-based line/column position:
+    /// the one starting last, and of those the one ending first
+    pub fn type_at(&self, line: usize, column: usize) -> Option<(&Span, &Type)> {
+        self.entries
+            .values()
+            .filter(|(span, _)| span.contains(line, column))
+            .max_by_key(|(span, _)| (span.line, span.column, Reverse((span.end_line, span.end_column))))
+            .map(|(span, ty)| (span, ty))
+    }
+    
+    /// Every recorded node, in the order it was first recorded
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Span, &Type)> {
+        self.order.iter().map(|node| {
+            let (span, ty) = &self.entries[node];
+            (*node, span, ty)
+        })
+    }
+    
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ProgramItem;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantics::SemanticAnalyzer;
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut map = TypeMap::new();
+        let node = NodeId::new(0, 3);
+        map.insert(node, Span::new(1, 1, 1, 10), Type::Int);
+        map.insert(node, Span::new(1, 1, 1, 10), Type::Float);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(node), Some(&Type::Float));
+        assert_eq!(map.get(NodeId::new(1, 3)), None);
+    }
+
+    #[test]
+    fn test_analyzer_records_binding_types() {
+        let source = "page Home {\n    let count = 42\n    let ratio: float = 1.5\n    count = 7\n}";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+        let mut analyzer = SemanticAnalyzer::new();
+        let _ = analyzer.analyze(&program);
+        
+        let types = analyzer.get_type_map();
+        assert_eq!(types.len(), 3);
+        assert_eq!(types.type_at(2, 9).map(|(_, ty)| ty), Some(&Type::Int));
+        assert_eq!(types.type_at(3, 9).map(|(_, ty)| ty), Some(&Type::Float));
+        assert_eq!(types.type_at(4, 5).map(|(_, ty)| ty), Some(&Type::Int));
+        assert!(types.type_at(1, 1).is_none());
+    }
+
+    #[test]
+    fn test_nested_expressions_have_their_own_types() {
+        let source = "page Home {\n    let count = 3\n    let big = count * 2.5 > 1.0\n    let flag = !(count - 1 > 0)\n}";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let mut analyzer = SemanticAnalyzer::new();
+        let _ = analyzer.analyze(&program);
+        
+        let types = analyzer.get_type_map();
+        let at = |line, column| types.type_at(line, column).map(|(_, ty)| ty.clone());
+        assert_eq!(at(3, 5), Some(Type::Bool));     // the binding
+        assert_eq!(at(3, 15), Some(Type::Float));   // `count * 2.5`
+        assert_eq!(at(3, 23), Some(Type::Float));   // `2.5`, inside the product
+        assert_eq!(at(3, 27), Some(Type::Bool));    // `> 1.0`
+        assert_eq!(at(4, 16), Some(Type::Bool));    // `!(...)`
+        assert_eq!(at(4, 18), Some(Type::Int));     // `count - 1`
+        assert_eq!(at(4, 28), Some(Type::Bool));    // `> 0`
+    }
+
+    #[test]
+    fn test_analysis_keyed_by_node() {
+        let source = "page Home {\n    let count = 42\n    missing = 1\n}";
+        let mut parser = Parser::new(Lexer::new(source).tokenize().unwrap());
+        let program = parser.parse().unwrap();
+        let mut analyzer = SemanticAnalyzer::new();
+        let _ = analyzer.analyze(&program);
+        
+        let ProgramItem::Page(page) = &program.items[0] else { panic!("expected a page") };
+        let (let_id, assign_id) = (page.statements[0].id().unwrap(), page.statements[1].id().unwrap());
+        assert_eq!(analyzer.get_type_map().get(let_id), Some(&Type::Int));
+        
+        let symbols = analyzer.get_symbol_table();
+        let (_, symbol) = symbols.defined_by(let_id).unwrap();
+        assert_eq!(symbol.name, "count");
+        assert_eq!(symbols.definition_from(symbols.scope_at(3, 5), "count"), Some(let_id));
+        
+        let diagnostics = analyzer.diagnostics();
+        assert_eq!(diagnostics.at_node(assign_id).count(), 1);
+        assert_eq!(diagnostics.at_node(let_id).count(), 1);  // `count` is never read
+    }
+}
@@ -0,0 +1,151 @@
+// Language versions and the syntax each of them introduced
+//
+// A program can pin the version of the language it is written for, with a
+// `#version "0.1"` line at its top or `language_version` in its `wt.toml`.
+// Syntax introduced by a later version is then reported as E2020 instead of
+// being read with a meaning the program was not written for. Programs that
+// pin no version are read as the current one.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LanguageVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl LanguageVersion {
+    /// The language as first released
+    pub const V0_1: LanguageVersion = LanguageVersion { major: 0, minor: 1 };
+    /// Queries over columns and rows, new declarations and `match`
+    pub const V0_2: LanguageVersion = LanguageVersion { major: 0, minor: 2 };
+
+    pub const CURRENT: LanguageVersion = LanguageVersion::V0_2;
+
+    /// Every version, oldest first
+    pub const ALL: &'static [LanguageVersion] = &[LanguageVersion::V0_1, LanguageVersion::V0_2];
+
+    /// A known version written as `major.minor`, with or without quotes
+    pub fn parse(text: &str) -> Option<LanguageVersion> {
+        let text = text.trim();
+        let text = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text);
+        LanguageVersion::ALL.iter().copied().find(|version| version.to_string() == text)
+    }
+
+    /// The known versions, e.g. `0.1, 0.2`
+    pub fn known() -> String {
+        LanguageVersion::ALL.iter().map(|version| version.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+impl Default for LanguageVersion {
+    fn default() -> Self {
+        LanguageVersion::CURRENT
+    }
+}
+
+impl fmt::Display for LanguageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Syntax that not every language version has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    Match,
+    Membership,
+    Between,
+    NullCheck,
+    GroupBy,
+    ColumnPattern,
+    DateLiteral,
+    CurrencyLiteral,
+    FieldHeader,
+    References,
+    Confirm,
+    Refresh,
+    Restriction,
+    DerivedTable,
+    SharedVariable,
+    Constant,
+    Hook,
+    Config,
+    Lambda,
+    Template,
+    Python,
+    Import,
+}
+
+impl Feature {
+    /// The version that introduced the feature
+    pub fn since(self) -> LanguageVersion {
+        match self {
+            Feature::Match
+            | Feature::Membership
+            | Feature::Between
+            | Feature::NullCheck
+            | Feature::GroupBy
+            | Feature::ColumnPattern
+            | Feature::DateLiteral
+            | Feature::CurrencyLiteral
+            | Feature::FieldHeader
+            | Feature::References
+            | Feature::Confirm
+            | Feature::Refresh
+            | Feature::Restriction
+            | Feature::DerivedTable
+            | Feature::SharedVariable
+            | Feature::Constant
+            | Feature::Hook
+            | Feature::Config
+            | Feature::Lambda
+            | Feature::Template
+            | Feature::Python
+            | Feature::Import => LanguageVersion::V0_2,
+        }
+    }
+
+    /// The feature as named in diagnostics, e.g. `` `match` ``
+    pub fn description(self) -> &'static str {
+        match self {
+            Feature::Match => "`match`",
+            Feature::Membership => "`in` membership tests",
+            Feature::Between => "`between`",
+            Feature::NullCheck => "`is null` and `is not null`",
+            Feature::GroupBy => "`group by`",
+            Feature::ColumnPattern => "`*` column patterns",
+            Feature::DateLiteral => "Date literals",
+            Feature::CurrencyLiteral => "Currency literals",
+            Feature::FieldHeader => "`from \"Header\"` column headers",
+            Feature::References => "`references` constraints",
+            Feature::Confirm => "`confirm` blocks",
+            Feature::Refresh => "`refresh every`",
+            Feature::Restriction => "`restrict` rules",
+            Feature::DerivedTable => "Derived tables",
+            Feature::SharedVariable => "`shared` variables",
+            Feature::Constant => "`const` declarations",
+            Feature::Hook => "Table hooks",
+            Feature::Config => "`config` blocks",
+            Feature::Lambda => "Lambdas",
+            Feature::Template => "Templates",
+            Feature::Python => "`python` blocks",
+            Feature::Import => "`import`",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(LanguageVersion::parse("0.1"), Some(LanguageVersion::V0_1));
+        assert_eq!(LanguageVersion::parse(" \"0.2\" "), Some(LanguageVersion::V0_2));
+        assert_eq!(LanguageVersion::parse("0.9"), None);
+        assert_eq!(LanguageVersion::parse("latest"), None);
+        assert!(LanguageVersion::V0_1 < LanguageVersion::CURRENT);
+        assert_eq!(LanguageVersion::known(), "0.1, 0.2");
+    }
+}
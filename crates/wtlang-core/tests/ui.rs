@@ -0,0 +1,17 @@
+// Runs the annotated `.wt` corpus in tests/ui through the compiler front end
+
+use std::path::Path;
+use wtlang_core::corpus;
+
+#[test]
+fn ui_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("ui");
+    let results = corpus::run_dir(&dir).expect("failed to read the ui corpus");
+    assert!(!results.is_empty(), "no .wt files found in {}", dir.display());
+
+    let failures: Vec<String> = results.iter()
+        .filter(|result| !result.passed())
+        .map(|result| result.to_string())
+        .collect();
+    assert!(failures.is_empty(), "{} corpus file(s) failed:\n{}", failures.len(), failures.join("\n"));
+}
@@ -0,0 +1,336 @@
+// Interpreter side of the debug adapter
+//
+// The program runs on its own thread. The hook below decides where to stop;
+// while stopped it serves the requests that need the paused interpreter
+// (stack, variables, evaluate) and waits for a resume command.
+
+use crate::protocol::Connection;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use wtlang_core::interp::statement_span;
+use wtlang_core::{DebugHook, Interpreter, Output, Program, ProgramItem, RuntimeError, RuntimeValue, Span, Statement};
+
+/// Requests forwarded to the interpreter thread
+pub enum Command {
+    StackTrace(Value),
+    Scopes(Value),
+    Variables(Value),
+    Evaluate(Value),
+    Resume(Value, Step),
+    Disconnect(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Continue,
+    /// Stop at the next statement in the same or an outer frame
+    Over,
+    In,
+    Out,
+}
+
+/// What the launched program runs
+pub enum Entry {
+    Page(String),
+    Test(String),
+}
+
+/// State shared with the request loop
+#[derive(Default)]
+pub struct Shared {
+    /// Breakpoint lines by source path
+    pub breakpoints: Mutex<HashMap<PathBuf, HashSet<usize>>>,
+    pub pause_requested: AtomicBool,
+    /// Set when the client disconnects; the program stops at the next statement
+    pub disconnected: AtomicBool,
+}
+
+pub struct Session {
+    pub connection: Arc<Connection>,
+    pub shared: Arc<Shared>,
+    pub commands: Receiver<Command>,
+    pub path: PathBuf,
+    pub stop_on_entry: bool,
+    /// Rows shown when previewing a table
+    pub preview_rows: usize,
+}
+
+/// Run the program to completion, reporting output and the exit status
+pub fn run(session: Session, program: Program, entry: Entry) {
+    let connection = session.connection.clone();
+    let base_dir = session.path.parent().map(PathBuf::from).unwrap_or_default();
+    let mut interpreter = Interpreter::new(&program).with_base_dir(base_dir);
+    let printed = Arc::new(AtomicUsize::new(0));
+    interpreter.set_hook(Box::new(Hook {
+        session: &session,
+        step: None,
+        entry: true,
+        handles: Vec::new(),
+        printed: printed.clone(),
+    }));
+
+    let result = match &entry {
+        Entry::Page(name) => interpreter.run_page(name),
+        Entry::Test(name) => interpreter.run_test(name),
+    };
+
+    print_output(&connection, &interpreter, printed.load(Ordering::SeqCst));
+    drop(interpreter);
+
+    // Answer requests that arrived after the last stop; once the receiver
+    // is dropped the request loop answers them itself
+    for command in session.commands.try_iter() {
+        match command {
+            Command::Disconnect(request) => connection.respond(&request, json!({})),
+            Command::StackTrace(request) | Command::Scopes(request) | Command::Variables(request)
+            | Command::Evaluate(request) | Command::Resume(request, _) => {
+                connection.respond_error(&request, NOT_RUNNING)
+            }
+        }
+    }
+    drop(session);
+
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(error) if error.message == DISCONNECTED => 0,
+        Err(error) => {
+            connection.event("output", json!({ "category": "stderr", "output": format!("error: {}\n", error) }));
+            1
+        }
+    };
+    connection.event("exited", json!({ "exitCode": exit_code }));
+    connection.event("terminated", json!({}));
+}
+
+const DISCONNECTED: &str = "Stopped by the debugger";
+pub const NOT_RUNNING: &str = "The program is not running";
+
+/// Send page output produced since the first `from` items
+fn print_output(connection: &Connection, interpreter: &Interpreter, from: usize) -> usize {
+    let output = interpreter.output();
+    for item in output.iter().skip(from) {
+        let (category, text) = match item {
+            Output::Title(text) => ("stdout", format!("# {}", text)),
+            Output::Subtitle(text) => ("stdout", format!("## {}", text)),
+            Output::Text(text) => ("stdout", text.clone()),
+            Output::Section(title) => ("stdout", format!("[{}]", title)),
+            Output::Table(table) => ("stdout", table.preview(20)),
+            Output::Log(level, message) => ("console", format!("[{}] {}", level, message)),
+        };
+        connection.event("output", json!({ "category": category, "output": format!("{}\n", text) }));
+    }
+    output.len()
+}
+
+/// Lines that hold a statement, where breakpoints can stop
+pub fn statement_lines(program: &Program) -> HashSet<usize> {
+    fn collect(statements: &[Statement], lines: &mut HashSet<usize>) {
+        for stmt in statements {
+            if let Some(span) = statement_span(stmt) {
+                lines.insert(span.line);
+            }
+            match stmt {
+                Statement::Button { body, .. }
+                | Statement::Section { body, .. }
+                | Statement::Confirm { body, .. }
+                | Statement::Forall { body, .. } => collect(body, lines),
+                Statement::If { then_branch, else_branch, .. } => {
+                    collect(then_branch, lines);
+                    if let Some(else_branch) = else_branch {
+                        collect(else_branch, lines);
+                    }
+                }
+                Statement::Match { arms, .. } => {
+                    for arm in arms {
+                        collect(&arm.body, lines);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut lines = HashSet::new();
+    for item in &program.items {
+        match item {
+            ProgramItem::Page(page) => collect(&page.statements, &mut lines),
+            ProgramItem::FunctionDef(func) => collect(&func.body, &mut lines),
+            ProgramItem::Test(test) => collect(&test.body, &mut lines),
+            ProgramItem::Hook(hook) => collect(&hook.body, &mut lines),
+            _ => {}
+        }
+    }
+    lines
+}
+
+/// A value with children, referenced from a `variables` response
+enum Handle {
+    /// Variables of the frame at this index of `Interpreter::frames`
+    Frame(usize),
+    Value(RuntimeValue),
+}
+
+struct Hook<'a> {
+    session: &'a Session,
+    /// Set while stepping, with the frame depth the step started from
+    step: Option<(Step, usize)>,
+    entry: bool,
+    /// Valid until the program resumes; reference `n` is `handles[n - 1]`
+    handles: Vec<Handle>,
+    /// Output items already sent to the client
+    printed: Arc<AtomicUsize>,
+}
+
+impl DebugHook for Hook<'_> {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, span: Span) -> Result<(), RuntimeError> {
+        if self.session.shared.disconnected.load(Ordering::SeqCst) {
+            return Err(RuntimeError { message: DISCONNECTED.to_string(), span: None });
+        }
+        let printed = self.printed.load(Ordering::SeqCst);
+        self.printed.store(print_output(&self.session.connection, interpreter, printed), Ordering::SeqCst);
+
+        let depth = interpreter.frames().len();
+        let breakpoint = self.session.shared.breakpoints.lock().unwrap_or_else(|e| e.into_inner())
+            .get(&self.session.path)
+            .is_some_and(|lines| lines.contains(&span.line));
+        let stepped = match self.step {
+            Some((Step::In, _)) => true,
+            Some((Step::Over, from)) => depth <= from,
+            Some((Step::Out, from)) => depth < from,
+            _ => false,
+        };
+
+        let reason = if std::mem::take(&mut self.entry) && self.session.stop_on_entry {
+            "entry"
+        } else if self.session.shared.pause_requested.swap(false, Ordering::SeqCst) {
+            "pause"
+        } else if breakpoint {
+            "breakpoint"
+        } else if stepped {
+            "step"
+        } else {
+            return Ok(());
+        };
+        self.pause(interpreter, reason)
+    }
+}
+
+impl Hook<'_> {
+    fn pause(&mut self, interpreter: &mut Interpreter, reason: &str) -> Result<(), RuntimeError> {
+        let connection = self.session.connection.clone();
+        connection.event("stopped", json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true }));
+        self.handles.clear();
+
+        loop {
+            let Ok(command) = self.session.commands.recv() else {
+                return Err(RuntimeError { message: DISCONNECTED.to_string(), span: None });
+            };
+            match command {
+                Command::StackTrace(request) => {
+                    let frames: Vec<Value> = interpreter.frames().iter().enumerate().rev()
+                        .map(|(id, frame)| json!({
+                            "id": id,
+                            "name": frame.name,
+                            "line": frame.line,
+                            "column": 1,
+                            "source": { "path": self.session.path },
+                        }))
+                        .collect();
+                    let total = frames.len();
+                    connection.respond(&request, json!({ "stackFrames": frames, "totalFrames": total }));
+                }
+                Command::Scopes(request) => {
+                    let frame = request["arguments"]["frameId"].as_u64().unwrap_or(0) as usize;
+                    let reference = self.add_handle(Handle::Frame(frame));
+                    connection.respond(&request, json!({ "scopes": [
+                        { "name": "Locals", "variablesReference": reference, "expensive": false }
+                    ]}));
+                }
+                Command::Variables(request) => {
+                    let reference = request["arguments"]["variablesReference"].as_u64().unwrap_or(0) as usize;
+                    match self.variables(interpreter, reference) {
+                        Some(variables) => connection.respond(&request, json!({ "variables": variables })),
+                        None => connection.respond_error(&request, "Unknown variables reference"),
+                    }
+                }
+                Command::Evaluate(request) => {
+                    let arguments = &request["arguments"];
+                    let expression = arguments["expression"].as_str().unwrap_or_default();
+                    let top = interpreter.frames().len().saturating_sub(1);
+                    let frame = arguments["frameId"].as_u64().map_or(top, |id| id as usize);
+                    match interpreter.evaluate(expression, frame) {
+                        Ok(value) => {
+                            let result = match &value {
+                                RuntimeValue::Table(table) => table.preview(self.session.preview_rows),
+                                value => value.to_string(),
+                            };
+                            let reference = self.reference_for(&value);
+                            connection.respond(&request, json!({ "result": result, "variablesReference": reference }));
+                        }
+                        Err(error) => connection.respond_error(&request, error.message),
+                    }
+                }
+                Command::Resume(request, step) => {
+                    self.step = (step != Step::Continue).then_some((step, interpreter.frames().len()));
+                    connection.respond(&request, json!({ "allThreadsContinued": true }));
+                    return Ok(());
+                }
+                Command::Disconnect(request) => {
+                    connection.respond(&request, json!({}));
+                    return Err(RuntimeError { message: DISCONNECTED.to_string(), span: None });
+                }
+            }
+        }
+    }
+
+    fn add_handle(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+
+    /// Reference to expand a value, or 0 for values without children
+    fn reference_for(&mut self, value: &RuntimeValue) -> usize {
+        match value {
+            RuntimeValue::Table(_) | RuntimeValue::Row(_) | RuntimeValue::List(_) => self.add_handle(Handle::Value(value.clone())),
+            _ => 0,
+        }
+    }
+
+    fn variable(&mut self, name: String, value: &RuntimeValue) -> Value {
+        json!({
+            "name": name,
+            "value": value.to_string(),
+            "variablesReference": self.reference_for(value),
+        })
+    }
+
+    fn variables(&mut self, interpreter: &Interpreter, reference: usize) -> Option<Vec<Value>> {
+        let children: Vec<(String, RuntimeValue)> = match self.handles.get(reference.checked_sub(1)?)? {
+            Handle::Frame(index) => interpreter.frames().get(*index)?.variables().into_iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            Handle::Value(RuntimeValue::Row(fields)) => fields.iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            Handle::Value(RuntimeValue::List(items)) => items.iter().enumerate()
+                .map(|(i, value)| (format!("[{}]", i), value.clone()))
+                .collect(),
+            Handle::Value(RuntimeValue::Table(table)) => {
+                let mut rows: Vec<(String, RuntimeValue)> = (0..table.rows.len().min(self.session.preview_rows))
+                    .filter_map(|i| Some((format!("[{}]", i), table.row(i)?)))
+                    .collect();
+                if table.rows.len() > self.session.preview_rows {
+                    let more = format!("{} more row(s)", table.rows.len() - self.session.preview_rows);
+                    rows.push(("...".to_string(), RuntimeValue::Scalar(wtlang_core::Value::String(more))));
+                }
+                rows
+            }
+            Handle::Value(_) => Vec::new(),
+        };
+        Some(children.into_iter().map(|(name, value)| self.variable(name, &value)).collect())
+    }
+}
@@ -0,0 +1,386 @@
+// WTLang debug adapter
+//
+// Speaks the Debug Adapter Protocol over stdio and runs programs on the
+// interpreter in wtlang-core, so pages and tests can be stepped through
+// without generating Python.
+
+mod debugger;
+mod protocol;
+
+use debugger::{Command, Entry, Session, Shared, Step};
+use protocol::{read_message, Connection};
+use serde_json::{json, Value};
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use wtlang_core::{Lexer, Parser, Program, ProgramItem};
+
+/// Rows shown when previewing a table, unless the launch configuration
+/// sets `previewRows`
+const DEFAULT_PREVIEW_ROWS: usize = 10;
+
+struct Launch {
+    path: PathBuf,
+    program: Program,
+    entry: Entry,
+    stop_on_entry: bool,
+    preview_rows: usize,
+}
+
+struct Server {
+    connection: Arc<Connection>,
+    shared: Arc<Shared>,
+    launch: Option<Launch>,
+    commands: Option<Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    fn new(connection: Connection) -> Self {
+        Server {
+            connection: Arc::new(connection),
+            shared: Arc::new(Shared::default()),
+            launch: None,
+            commands: None,
+            thread: None,
+        }
+    }
+
+    fn run(mut self, mut reader: impl BufRead) -> io::Result<()> {
+        while let Some(request) = read_message(&mut reader)? {
+            if request["type"] != "request" {
+                continue;
+            }
+            if !self.handle(request) {
+                break;
+            }
+        }
+        // Closing the channel stops a paused program
+        self.commands = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        Ok(())
+    }
+
+    /// Handle one request; returns false once the client disconnected
+    fn handle(&mut self, request: Value) -> bool {
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.connection.respond(&request, json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                }));
+                self.connection.event("initialized", json!({}));
+            }
+            "launch" => match load(arguments) {
+                Ok(launch) => {
+                    self.launch = Some(launch);
+                    self.connection.respond(&request, json!({}));
+                }
+                Err(message) => self.connection.respond_error(&request, message),
+            },
+            "setBreakpoints" => {
+                let path = PathBuf::from(arguments["source"]["path"].as_str().unwrap_or_default());
+                let lines: Vec<usize> = arguments["breakpoints"].as_array().into_iter().flatten()
+                    .filter_map(|bp| bp["line"].as_u64().map(|line| line as usize))
+                    .collect();
+                let valid = parse_file(&path).map(|program| debugger::statement_lines(&program));
+                let verified: Vec<(usize, bool)> = lines.iter()
+                    .map(|line| (*line, valid.as_ref().is_ok_and(|valid| valid.contains(line))))
+                    .collect();
+
+                self.shared.breakpoints.lock().unwrap_or_else(|e| e.into_inner()).insert(
+                    path,
+                    verified.iter().filter(|(_, ok)| *ok).map(|(line, _)| *line).collect(),
+                );
+                let breakpoints: Vec<Value> = verified.iter()
+                    .map(|(line, ok)| json!({ "verified": ok, "line": line }))
+                    .collect();
+                self.connection.respond(&request, json!({ "breakpoints": breakpoints }));
+            }
+            "configurationDone" => {
+                self.connection.respond(&request, json!({}));
+                self.start();
+            }
+            "threads" => self.connection.respond(&request, json!({ "threads": [{ "id": 1, "name": "main" }] })),
+            "pause" => {
+                self.shared.pause_requested.store(true, Ordering::SeqCst);
+                self.connection.respond(&request, json!({}));
+            }
+            "stackTrace" => self.forward(Command::StackTrace(request)),
+            "scopes" => self.forward(Command::Scopes(request)),
+            "variables" => self.forward(Command::Variables(request)),
+            "evaluate" => self.forward(Command::Evaluate(request)),
+            "continue" => self.forward(Command::Resume(request, Step::Continue)),
+            "next" => self.forward(Command::Resume(request, Step::Over)),
+            "stepIn" => self.forward(Command::Resume(request, Step::In)),
+            "stepOut" => self.forward(Command::Resume(request, Step::Out)),
+            "disconnect" | "terminate" => {
+                self.shared.disconnected.store(true, Ordering::SeqCst);
+                let running = self.commands.as_ref()
+                    .is_some_and(|commands| commands.send(Command::Disconnect(request.clone())).is_ok());
+                if !running {
+                    self.connection.respond(&request, json!({}));
+                }
+                return request["command"] != "disconnect";
+            }
+            command => self.connection.respond_error(&request, format!("Unsupported request: {}", command)),
+        }
+        true
+    }
+
+    fn start(&mut self) {
+        let Some(launch) = self.launch.take() else { return };
+        let (sender, receiver) = mpsc::channel();
+        let session = Session {
+            connection: self.connection.clone(),
+            shared: self.shared.clone(),
+            commands: receiver,
+            path: launch.path,
+            stop_on_entry: launch.stop_on_entry,
+            preview_rows: launch.preview_rows,
+        };
+        self.commands = Some(sender);
+        self.thread = Some(std::thread::spawn(move || debugger::run(session, launch.program, launch.entry)));
+    }
+
+    /// Pass a request to the interpreter thread, which answers it the next
+    /// time the program is stopped
+    fn forward(&mut self, command: Command) {
+        let result = match &self.commands {
+            Some(commands) => commands.send(command).map_err(|mpsc::SendError(command)| command),
+            None => Err(command),
+        };
+        if let Err(command) = result {
+            self.not_running(command);
+        }
+    }
+
+    fn not_running(&self, command: Command) {
+        let request = match &command {
+            Command::StackTrace(r) | Command::Scopes(r) | Command::Variables(r)
+            | Command::Evaluate(r) | Command::Resume(r, _) | Command::Disconnect(r) => r,
+        };
+        self.connection.respond_error(request, debugger::NOT_RUNNING);
+    }
+}
+
+fn parse_file(path: &PathBuf) -> Result<Program, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let tokens = Lexer::new(&source).tokenize().map_err(|diag| diag.format_all())?;
+    Parser::new(tokens).parse().map_err(|diag| diag.format_all())
+}
+
+/// Read the launch configuration and parse the program
+fn load(arguments: &Value) -> Result<Launch, String> {
+    let path = PathBuf::from(arguments["program"].as_str().ok_or("Missing 'program' in launch configuration")?);
+    let program = parse_file(&path)?;
+
+    let entry = if let Some(test) = arguments["test"].as_str() {
+        Entry::Test(test.to_string())
+    } else if let Some(page) = arguments["page"].as_str() {
+        Entry::Page(page.to_string())
+    } else {
+        let first_page = program.items.iter().find_map(|item| match item {
+            ProgramItem::Page(page) => Some(page.name.to_string()),
+            _ => None,
+        });
+        Entry::Page(first_page.ok_or_else(|| format!("{} has no pages; set 'page' or 'test'", path.display()))?)
+    };
+
+    Ok(Launch {
+        path,
+        program,
+        entry,
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        preview_rows: arguments["previewRows"].as_u64().map_or(DEFAULT_PREVIEW_ROWS, |n| n as usize),
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    let server = Server::new(Connection::new(Box::new(io::stdout())));
+    server.run(io::stdin().lock())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor, Read, Write};
+    use std::sync::mpsc::Receiver;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Input that blocks until the test sends the next request
+    struct Pipe {
+        chunks: Receiver<Vec<u8>>,
+        pending: Cursor<Vec<u8>>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            loop {
+                let n = self.pending.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+                match self.chunks.recv() {
+                    Ok(chunk) => self.pending = Cursor::new(chunk),
+                    Err(_) => return Ok(0),
+                }
+            }
+        }
+    }
+
+    /// Drives the adapter one request at a time, like an editor would
+    struct Client {
+        input: Option<Sender<Vec<u8>>>,
+        output: Buffer,
+        server: Option<JoinHandle<()>>,
+        seq: usize,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (input, chunks) = mpsc::channel();
+            let output = Buffer::default();
+            let connection = Connection::new(Box::new(output.clone()));
+            let pipe = BufReader::new(Pipe { chunks, pending: Cursor::new(Vec::new()) });
+            let server = std::thread::spawn(move || Server::new(connection).run(pipe).unwrap());
+            Client { input: Some(input), output, server: Some(server), seq: 0 }
+        }
+
+        fn messages(&self) -> Vec<Value> {
+            let mut reader = Cursor::new(self.output.0.lock().unwrap().clone());
+            std::iter::from_fn(|| read_message(&mut reader).unwrap()).collect()
+        }
+
+        fn wait_for(&self, matches: impl Fn(&Value) -> bool) -> Value {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            loop {
+                if let Some(message) = self.messages().into_iter().find(&matches) {
+                    return message;
+                }
+                assert!(Instant::now() < deadline, "timed out waiting for the adapter");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        /// Send a request and wait for its response
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let body = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }).to_string();
+            let message = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+            self.input.as_ref().unwrap().send(message.into_bytes()).unwrap();
+            let seq = self.seq;
+            self.wait_for(|m| m["type"] == "response" && m["request_seq"] == seq)
+        }
+
+        fn event(&self, event: &str) -> Value {
+            self.wait_for(|m| m["event"] == event)
+        }
+
+        fn finish(mut self) -> Vec<Value> {
+            self.input = None;
+            self.server.take().unwrap().join().unwrap();
+            self.messages()
+        }
+    }
+
+    const PROGRAM: &str = "\
+page Home {
+    let orders = table_from([{ id: 1 }, { id: 2 }, { id: 3 }])
+    let total = count(orders)
+    let doubled = total * 2
+    text \"{doubled} rows\"
+}
+";
+
+    #[test]
+    fn test_breakpoint_variables_and_evaluate() {
+        let path = std::env::temp_dir().join(format!("wtlang_dap_{}.wt", std::process::id()));
+        std::fs::write(&path, PROGRAM).unwrap();
+        let path = path.display().to_string();
+
+        let mut client = Client::start();
+        client.request("initialize", json!({}));
+        client.request("launch", json!({ "program": path, "previewRows": 2 }));
+        let breakpoints = client.request("setBreakpoints", json!({
+            "source": { "path": path }, "breakpoints": [{ "line": 4 }, { "line": 5 }]
+        }));
+        assert_eq!(breakpoints["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(breakpoints["body"]["breakpoints"][1]["verified"], false);
+        client.request("configurationDone", json!({}));
+
+        assert_eq!(client.event("stopped")["body"]["reason"], "breakpoint");
+        let frames = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(frames["body"]["stackFrames"][0]["name"], "page Home");
+        assert_eq!(frames["body"]["stackFrames"][0]["line"], 4);
+
+        let scopes = client.request("scopes", json!({ "frameId": 0 }));
+        let locals = scopes["body"]["scopes"][0]["variablesReference"].clone();
+        let variables = client.request("variables", json!({ "variablesReference": locals }));
+        let variables = &variables["body"]["variables"];
+        assert_eq!(variables[0]["name"], "orders");
+        assert_eq!(variables[1]["value"], "3");
+
+        // The table preview stops after `previewRows` rows
+        let rows = client.request("variables", json!({ "variablesReference": variables[0]["variablesReference"] }));
+        let rows = rows["body"]["variables"].as_array().unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["value"], "{id: 1}");
+        assert_eq!(rows[2]["value"], "\"1 more row(s)\"");
+
+        let result = client.request("evaluate", json!({ "expression": "total * 2", "frameId": 0 }));
+        assert_eq!(result["body"]["result"], "6");
+
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("exited")["body"]["exitCode"], 0);
+        client.request("disconnect", json!({}));
+        let messages = client.finish();
+        std::fs::remove_file(&path).unwrap();
+
+        let output = messages.iter().find(|m| m["event"] == "output").unwrap();
+        assert_eq!(output["body"]["output"], "6 rows\n");
+    }
+
+    #[test]
+    fn test_disconnect_stops_a_paused_program() {
+        let path = std::env::temp_dir().join(format!("wtlang_dap_entry_{}.wt", std::process::id()));
+        std::fs::write(&path, PROGRAM).unwrap();
+
+        let mut client = Client::start();
+        client.request("launch", json!({ "program": path.display().to_string(), "stopOnEntry": true }));
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.event("stopped")["body"]["reason"], "entry");
+        client.request("next", json!({ "threadId": 1 }));
+        let frames = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(frames["body"]["stackFrames"][0]["line"], 3);
+
+        assert_eq!(client.request("disconnect", json!({}))["success"], true);
+        let messages = client.finish();
+        std::fs::remove_file(&path).unwrap();
+        // The page never got to display anything
+        assert!(!messages.iter().any(|m| m["event"] == "output"));
+    }
+}
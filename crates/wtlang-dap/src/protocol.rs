@@ -0,0 +1,100 @@
+// Debug Adapter Protocol message framing
+//
+// Messages are JSON bodies preceded by a `Content-Length` header, the same
+// framing the language server uses.
+
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+/// Read the next message, or `None` at end of input
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Sends responses and events to the client; shared between the request
+/// loop and the interpreter thread
+pub struct Connection {
+    writer: Mutex<Box<dyn Write + Send>>,
+    seq: AtomicI64,
+}
+
+impl Connection {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Connection { writer: Mutex::new(writer), seq: AtomicI64::new(1) }
+    }
+
+    pub fn respond(&self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    pub fn respond_error(&self, request: &Value, message: impl Into<String>) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message.into(),
+        }));
+    }
+
+    pub fn event(&self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&self, mut message: Value) {
+        message["seq"] = json!(self.seq.fetch_add(1, Ordering::SeqCst));
+        let body = message.to_string();
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        // The client going away is handled by the request loop reaching
+        // end of input, so write errors are ignored here
+        let _ = write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_messages() {
+        let input = "Content-Length: 13\r\n\r\n{\"seq\": 1}   Content-Length: 2\r\nContent-Type: json\r\n\r\n{}";
+        let mut reader = Cursor::new(input);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({"seq": 1})));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+}
@@ -0,0 +1,325 @@
+// Index of the .wt files of a workspace
+//
+// Every file is analyzed once for its top-level symbols and diagnostics.
+// The results are kept under `.wtlang/cache/` in the workspace, one JSON
+// file per source file with the hash of the source it was made from, so
+// that reopening the workspace only analyzes the files that changed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use wtlang_core::{Directives, Lexer, Parser, ProgramItem, SemanticAnalyzer, Severity, Span};
+
+/// Directory of the cache, relative to the workspace root
+pub const CACHE_DIR: &str = ".wtlang/cache";
+
+/// Changed whenever the cached data changes shape or meaning
+const CACHE_FORMAT: u32 = 1;
+
+/// Directories never searched for .wt files
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// What the index knows about one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Hash of the source the entry was made from
+    pub hash: String,
+    pub symbols: Vec<IndexedSymbol>,
+    pub diagnostics: Vec<IndexedDiagnostic>,
+}
+
+/// A top-level item of a file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedSymbol {
+    pub name: String,
+    /// `table`, `page`, `function`, …, as declared
+    pub kind: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+/// A diagnostic of a file, positioned like the compiler's
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedDiagnostic {
+    pub severity: String,
+    pub code: String,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub end: Option<(usize, usize)>,
+}
+
+impl IndexedDiagnostic {
+    pub fn severity(&self) -> Severity {
+        match self.severity.as_str() {
+            "error" => Severity::Error,
+            "warning" => Severity::Warning,
+            "info" => Severity::Info,
+            _ => Severity::Hint,
+        }
+    }
+}
+
+/// One cache file
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    format: u32,
+    server: String,
+    path: PathBuf,
+    entry: FileEntry,
+}
+
+/// The analyzed files of a workspace, by path
+#[derive(Debug)]
+pub struct WorkspaceIndex {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, FileEntry>,
+}
+
+impl WorkspaceIndex {
+    pub fn new(root: PathBuf) -> Self {
+        WorkspaceIndex { root, files: BTreeMap::new() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn files(&self) -> &BTreeMap<PathBuf, FileEntry> {
+        &self.files
+    }
+
+    /// Index every .wt file of the workspace up to `max_file_size` bytes,
+    /// returning the sources read and how many of them had to be analyzed
+    pub fn index_workspace(&mut self, max_file_size: usize) -> (Vec<(PathBuf, String)>, usize) {
+        let mut sources = Vec::new();
+        let mut analyzed = 0;
+        for path in workspace_files(&self.root) {
+            let Ok(source) = fs::read_to_string(&path) else { continue };
+            if source.len() > max_file_size {
+                continue;
+            }
+            if self.update(&path, &source) {
+                analyzed += 1;
+            }
+            sources.push((path, source));
+        }
+        (sources, analyzed)
+    }
+
+    /// Bring a file's entry up to date with its source, from the cache when
+    /// it was made from the same source; returns whether it was analyzed
+    pub fn update(&mut self, path: &Path, source: &str) -> bool {
+        let hash = content_hash(source);
+        if self.files.get(path).is_some_and(|entry| entry.hash == hash) {
+            return false;
+        }
+        if let Some(entry) = self.read_cache(path).filter(|entry| entry.hash == hash) {
+            self.files.insert(path.to_path_buf(), entry);
+            return false;
+        }
+
+        let entry = analyze(source, hash);
+        if let Err(e) = self.write_cache(path, &entry) {
+            tracing::warn!(path = %path.display(), error = %e, "could not cache the analysis");
+        }
+        self.files.insert(path.to_path_buf(), entry);
+        true
+    }
+
+    /// Forget a file, e.g. once it is deleted
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+        let _ = fs::remove_file(self.cache_path(path));
+    }
+
+    /// Delete the cache and forget every file
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.files.clear();
+        match fs::remove_dir_all(self.root.join(CACHE_DIR)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Cache file of a source file, named after the hash of its path
+    fn cache_path(&self, path: &Path) -> PathBuf {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.root.join(CACHE_DIR).join(format!("{}.json", content_hash(&relative.to_string_lossy())))
+    }
+
+    fn read_cache(&self, path: &Path) -> Option<FileEntry> {
+        let text = fs::read_to_string(self.cache_path(path)).ok()?;
+        let cache: CacheFile = serde_json::from_str(&text).ok()?;
+        let current = cache.format == CACHE_FORMAT && cache.server == env!("CARGO_PKG_VERSION") && cache.path == path;
+        current.then_some(cache.entry)
+    }
+
+    fn write_cache(&self, path: &Path, entry: &FileEntry) -> io::Result<()> {
+        let cache_path = self.cache_path(path);
+        if let Some(dir) = cache_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let cache = CacheFile {
+            format: CACHE_FORMAT,
+            server: env!("CARGO_PKG_VERSION").to_string(),
+            path: path.to_path_buf(),
+            entry: entry.clone(),
+        };
+        fs::write(cache_path, serde_json::to_string(&cache)?)
+    }
+}
+
+/// Analyze a file's source as the language server does for open documents
+pub fn analyze(source: &str, hash: String) -> FileEntry {
+    let (tokens, mut diagnostics) = Lexer::new(source).tokenize_recovering();
+    let mut parser = Parser::new(tokens);
+    let mut symbols = Vec::new();
+    match parser.parse() {
+        Ok(program) => {
+            let mut analyzer = SemanticAnalyzer::new();
+            let _ = analyzer.analyze(&program);
+            diagnostics.extend(parser.diagnostics());
+            diagnostics.extend(analyzer.diagnostics());
+            for item in &program.items {
+                if let Some((kind, name, span)) = item_symbol(item) {
+                    symbols.push(IndexedSymbol {
+                        name,
+                        kind: kind.to_string(),
+                        line: span.line,
+                        column: span.column,
+                        end_line: span.end_line,
+                        end_column: span.end_column,
+                    });
+                }
+            }
+        }
+        Err(e) => diagnostics.extend(e),
+    }
+
+    let diagnostics = Directives::parse(source).apply(&diagnostics).diagnostics().iter()
+        .map(|diag| IndexedDiagnostic {
+            severity: diag.severity.to_string(),
+            code: diag.code.code().to_string(),
+            message: diag.message.clone(),
+            line: diag.location.line,
+            column: diag.location.column,
+            end: diag.end.as_ref().map(|end| (end.line, end.column)),
+        })
+        .collect();
+    FileEntry { hash, symbols, diagnostics }
+}
+
+/// The kind, name and span of a named top-level item
+fn item_symbol(item: &ProgramItem) -> Option<(&'static str, String, Span)> {
+    match item {
+        ProgramItem::TableDef(table) => Some(("table", table.name.to_string(), table.span)),
+        ProgramItem::DerivedTable(derived) => Some(("derived table", derived.name.to_string(), derived.span)),
+        ProgramItem::Page(page) => Some(("page", page.name.to_string(), page.span)),
+        ProgramItem::FunctionDef(func) => Some(("function", func.name.to_string(), func.span)),
+        ProgramItem::ExternalFunction(func) => Some(("external function", func.name.to_string(), func.span)),
+        ProgramItem::SharedVariable(shared) => Some(("shared", shared.name.to_string(), shared.span)),
+        ProgramItem::Constant(constant) => Some(("const", constant.name.to_string(), constant.span)),
+        ProgramItem::Test(test) => Some(("test", test.name.clone(), test.span)),
+        _ => None,
+    }
+}
+
+/// The .wt files under a directory, skipping hidden and build directories
+pub fn workspace_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if path.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                    dirs.push(path);
+                }
+            } else if path.extension().is_some_and(|ext| ext == "wt") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// FNV-1a hash of a text, the one `wtc` uses for its lockfile
+pub fn content_hash(content: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtlang_lsp_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry_json(index: &WorkspaceIndex, path: &Path) -> serde_json::Value {
+        serde_json::to_value(&index.files()[path]).unwrap()
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let root = temp_dir("cache");
+        let app = root.join("app.wt");
+        let shared = root.join("lib").join("shared.wt");
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(&app, "page Home {\n    x = 1\n}\n").unwrap();
+        fs::write(&shared, "table Order {\n    id: int [key]\n}\n").unwrap();
+        fs::write(root.join("target").join("skipped.wt"), "page Skipped {}\n").unwrap();
+
+        // The first index analyzes every file and saves the results
+        let mut first = WorkspaceIndex::new(root.clone());
+        let (sources, analyzed) = first.index_workspace(usize::MAX);
+        assert_eq!(sources.into_iter().map(|(path, _)| path).collect::<Vec<_>>(), [app.clone(), shared.clone()]);
+        assert_eq!(analyzed, 2);
+        assert_eq!(first.files()[&app].diagnostics[0].code, "E3001");
+        assert_eq!(first.files()[&shared].symbols[0].name, "Order");
+        assert_eq!(fs::read_dir(root.join(CACHE_DIR)).unwrap().count(), 2);
+
+        // The next one reloads them
+        let mut second = WorkspaceIndex::new(root.clone());
+        assert_eq!(second.index_workspace(usize::MAX).1, 0);
+        assert_eq!(entry_json(&second, &app), entry_json(&first, &app));
+        assert_eq!(entry_json(&second, &shared), entry_json(&first, &shared));
+
+        // A changed source invalidates its entry, and only its entry
+        fs::write(&app, "page Home {\n    let x = 1\n    text \"{x}\"\n}\n").unwrap();
+        let mut third = WorkspaceIndex::new(root.clone());
+        assert_eq!(third.index_workspace(usize::MAX).1, 1);
+        assert!(third.files()[&app].diagnostics.is_empty());
+        assert_eq!(WorkspaceIndex::new(root.clone()).index_workspace(usize::MAX).1, 0);
+
+        // So does a cache of another format
+        let cache = third.cache_path(&shared);
+        let text = fs::read_to_string(&cache).unwrap();
+        fs::write(&cache, text.replace(&format!("\"format\":{}", CACHE_FORMAT), "\"format\":0")).unwrap();
+        assert_eq!(WorkspaceIndex::new(root.clone()).index_workspace(usize::MAX).1, 1);
+
+        third.remove(&shared);
+        assert!(!third.cache_path(&shared).exists());
+        third.clear().unwrap();
+        assert!(!root.join(CACHE_DIR).exists());
+        assert!(third.files().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
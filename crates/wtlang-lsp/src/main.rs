@@ -1,9 +1,23 @@
+mod index;
+mod settings;
+
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
-use wtlang_core::{Lexer, Parser, SemanticAnalyzer, Type, SymbolKind, Severity};
+use wtlang_core::{Lexer, Parser, SemanticAnalyzer, IncrementalAnalyzer, Directives, DiagnosticBag, Type, SymbolKind, Severity, Atom, NodeId, Span, Statement, ProgramItem, LineIndex, PositionEncoding, Logger};
 use std::collections::HashMap;
+use std::path::Path;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use futures::FutureExt;
+use std::time::Instant;
 use tokio::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use settings::Settings;
+use index::{FileEntry, WorkspaceIndex};
+
+/// Command deleting the workspace index's cache and indexing again
+const CLEAR_CACHE_COMMAND: &str = "wtlang.clearCache";
 
 #[derive(Debug)]
 struct DocumentState {
@@ -11,11 +25,18 @@ struct DocumentState {
     version: i32,
     // Cache parsed AST and symbol table for performance
     program: Option<wtlang_core::ast::Program>,
+    // Per-item analysis results reused across edits
+    analysis: IncrementalAnalyzer,
 }
 
 pub struct WTLangServer {
     client: Client,
     documents: Mutex<HashMap<Url, DocumentState>>,
+    // Unit of the client's column offsets, agreed in `initialize`
+    encoding: Mutex<PositionEncoding>,
+    settings: Mutex<Settings>,
+    // Symbols and diagnostics of the workspace's files, open or not
+    index: Mutex<Option<WorkspaceIndex>>,
 }
 
 impl WTLangServer {
@@ -23,25 +44,134 @@ impl WTLangServer {
         WTLangServer {
             client,
             documents: Mutex::new(HashMap::new()),
+            encoding: Mutex::new(PositionEncoding::Utf16),
+            settings: Mutex::new(Settings::default()),
+            index: Mutex::new(None),
+        }
+    }
+
+    /// Run a handler, answering with an internal error instead of taking
+    /// the server down if it panics
+    ///
+    /// The panic is logged with `context`, the document and position of the
+    /// request, so that it can be reported and reproduced.
+    async fn guard<T>(&self, method: &str, context: String, handler: impl Future<Output = Result<T>>) -> Result<T> {
+        match catch_panic(handler).await {
+            Ok(result) => result,
+            Err(message) => {
+                tracing::error!(method, %context, panic = %message, "request handler panicked");
+                self.client
+                    .log_message(MessageType::ERROR, format!("internal error in {} at {}: {}", method, context, message))
+                    .await;
+                let mut error = tower_lsp::jsonrpc::Error::internal_error();
+                error.message = format!("internal error in {}: {}", method, message).into();
+                Err(error)
+            }
+        }
+    }
+
+    /// LSP position of a 1-based line and character column
+    fn to_position(index: &LineIndex, encoding: PositionEncoding, line: usize, column: usize) -> Position {
+        Position {
+            line: line.saturating_sub(1) as u32,
+            character: index.offset(line, column, encoding) as u32,
+        }
+    }
+
+    /// 1-based line and character column of an LSP position
+    fn from_position(index: &LineIndex, encoding: PositionEncoding, position: Position) -> (usize, usize) {
+        let line = position.line as usize + 1;
+        (line, index.column(line, position.character as usize, encoding))
+    }
+
+    /// LSP diagnostic from a 1-based start and optional end
+    fn to_diagnostic(
+        index: &LineIndex,
+        encoding: PositionEncoding,
+        severity: Severity,
+        code: Option<wtlang_core::ErrorCode>,
+        message: &str,
+        (line, column): (usize, usize),
+        end: Option<(usize, usize)>,
+    ) -> Diagnostic {
+        let severity = match severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Info => DiagnosticSeverity::INFORMATION,
+            Severity::Hint => DiagnosticSeverity::HINT,
+        };
+
+        let range = Range {
+            start: Self::to_position(index, encoding, line, column),
+            // Highlight the offending token, or one character
+            end: match end {
+                Some(end) if end > (line, column) => Self::to_position(index, encoding, end.0, end.1),
+                _ => Self::to_position(index, encoding, line, column + 1),
+            },
+        };
+
+        Diagnostic {
+            range,
+            severity: Some(severity),
+            code: code.map(|code| NumberOrString::String(format!("{:?}", code))),
+            // Same explanation `wtc explain` prints
+            code_description: code
+                .and_then(|code| Url::parse(&code.doc_url()).ok())
+                .map(|href| CodeDescription { href }),
+            source: Some("wtlang".to_string()),
+            message: message.to_string(),
+            related_information: None,
+            tags: None,
+            data: None,
         }
     }
 
     async fn parse_and_analyze(&self, uri: &Url) -> Option<(wtlang_core::ast::Program, SemanticAnalyzer)> {
-        let docs = self.documents.lock().await;
-        let doc = docs.get(uri)?;
+        let max_file_size = self.settings.lock().await.analysis.max_file_size;
+        let mut docs = self.documents.lock().await;
+        let doc = docs.get_mut(uri)?;
+        if doc.source.len() > max_file_size {
+            return None;
+        }
         
         let mut lexer = Lexer::new(&doc.source);
-        let tokens = lexer.tokenize().ok()?;
+        let (tokens, _) = lexer.tokenize_recovering();
         
         let mut parser = Parser::new(tokens);
         let program = parser.parse().ok()?;
         
-        let mut analyzer = SemanticAnalyzer::new();
-        let _ = analyzer.analyze(&program);
+        let analyzer = doc.analysis.analyze(&program);
         
         Some((program, analyzer))
     }
 
+    /// Diagnostics of every stage for a document's source, only checking
+    /// the syntax of documents over the size limit
+    async fn document_diagnostics(&self, uri: &Url, source: &str) -> Option<DiagnosticBag> {
+        let analyze = source.len() <= self.settings.lock().await.analysis.max_file_size;
+        
+        // Lexical analysis, parsing past invalid tokens
+        let mut lexer = Lexer::new(source);
+        let (tokens, mut diag_bag) = lexer.tokenize_recovering();
+        
+        // Parsing
+        let mut parser = Parser::new(tokens);
+        match parser.parse() {
+            Ok(program) => {
+                // Semantic analysis, reusing results for unchanged items
+                diag_bag.extend(parser.diagnostics());
+                if analyze {
+                    diag_bag.extend(self.documents.lock().await.get_mut(uri)?.analysis.analyze(&program).diagnostics());
+                }
+            }
+            Err(e) => diag_bag.extend(e),
+        }
+
+        // Drop diagnostics silenced by `// wtlang: allow(...)` and check
+        // `// expect-error ...` comments
+        Some(Directives::parse(source).apply(&diag_bag))
+    }
+
     async fn publish_diagnostics(&self, uri: Url) {
         let docs = self.documents.lock().await;
         let doc = match docs.get(&uri) {
@@ -54,101 +184,230 @@ impl WTLangServer {
         let version = doc.version;
         drop(docs);
 
-        // Lexical analysis
-        let mut lexer = Lexer::new(&source);
-        let mut diag_bag = wtlang_core::DiagnosticBag::new();
-        
-        match lexer.tokenize() {
-            Ok(tokens) => {
-                // Parsing
-                let mut parser = Parser::new(tokens);
-                match parser.parse() {
-                    Ok(program) => {
-                        // Semantic analysis
-                        let mut analyzer = SemanticAnalyzer::new();
-                        if let Err(sem_errors) = analyzer.analyze(&program) {
-                            for err in sem_errors {
-                                // Convert semantic errors to diagnostics
-                                let diagnostic = Diagnostic {
-                                    range: Range {
-                                        start: Position { line: 0, character: 0 },
-                                        end: Position { line: 0, character: 1 },
-                                    },
-                                    severity: Some(DiagnosticSeverity::ERROR),
-                                    code: None,
-                                    code_description: None,
-                                    source: Some("wtlang".to_string()),
-                                    message: err.to_string(),
-                                    related_information: None,
-                                    tags: None,
-                                    data: None,
-                                };
-                                diagnostics.push(diagnostic);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        diag_bag = e;
-                    }
-                }
+        // A panic in the analysis is shown in place of its diagnostics
+        let start = Instant::now();
+        let diag_bag = match catch_panic(self.document_diagnostics(&uri, &source)).await {
+            Ok(Some(diag_bag)) => diag_bag,
+            Ok(None) => return,
+            Err(message) => {
+                tracing::error!(%uri, version, panic = %message, "analysis panicked");
+                let diagnostics = vec![internal_error_diagnostic(&message)];
+                self.client.publish_diagnostics(uri, diagnostics, Some(version)).await;
+                return;
             }
-            Err(e) => {
-                diag_bag = e;
+        };
+        tracing::debug!(
+            %uri,
+            version,
+            diagnostics = diag_bag.diagnostics().len(),
+            duration_ms = (start.elapsed().as_secs_f64() * 1e6).round() / 1000.0,
+            "analyzed document"
+        );
+
+        let settings = self.settings.lock().await.clone();
+        let (shown, overflow) = limit_diagnostics(diag_bag.diagnostics().iter().collect(), |diag| diag.severity, settings.diagnostics.max_count);
+        
+        // Convert DiagnosticBag to LSP diagnostics
+        let encoding = *self.encoding.lock().await;
+        let index = LineIndex::new(&source);
+        for diag in shown {
+            let start = (diag.location.line, diag.location.column);
+            let end = diag.end.as_ref().map(|end| (end.line, end.column));
+            diagnostics.push(Self::to_diagnostic(&index, encoding, diag.severity, Some(diag.code), &diag.message, start, end));
+        }
+        
+        // Say what was left out, at the top of the document
+        let mut notes = Vec::new();
+        if source.len() > settings.analysis.max_file_size {
+            notes.push(format!(
+                "This file is larger than {} bytes, so only its syntax is checked; raise wtlang.analysis.maxFileSize to analyze it.",
+                settings.analysis.max_file_size
+            ));
+        }
+        notes.extend(overflow);
+        diagnostics.extend(notes_diagnostic(&notes));
+
+        self.client.publish_diagnostics(uri, diagnostics, Some(version)).await;
+    }
+
+    /// Index the workspace's files, from the cache where they are
+    /// unchanged, and publish the diagnostics of those not open
+    async fn index_workspace(&self) {
+        let start = Instant::now();
+        let max_file_size = self.settings.lock().await.analysis.max_file_size;
+        let mut guard = self.index.lock().await;
+        let Some(index) = guard.as_mut() else { return };
+        let (sources, analyzed) = index.index_workspace(max_file_size);
+        let files: Vec<_> = sources.into_iter()
+            .filter_map(|(path, source)| {
+                let entry = index.files().get(&path)?.clone();
+                Some((path, source, entry))
+            })
+            .collect();
+        drop(guard);
+        tracing::info!(
+            files = files.len(),
+            analyzed,
+            duration_ms = (start.elapsed().as_secs_f64() * 1e6).round() / 1000.0,
+            "indexed workspace"
+        );
+
+        for (path, source, entry) in files {
+            self.publish_indexed(&path, &source, &entry).await;
+        }
+    }
+
+    /// Bring a file of the workspace up to date in the index from disk
+    async fn reindex(&self, path: &Path) {
+        let max_file_size = self.settings.lock().await.analysis.max_file_size;
+        let mut guard = self.index.lock().await;
+        let Some(index) = guard.as_mut() else { return };
+        if !path.starts_with(index.root()) {
+            return;
+        }
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) if source.len() <= max_file_size => source,
+            _ => {
+                index.remove(path);
+                return;
             }
+        };
+        index.update(path, &source);
+        let entry = index.files().get(path).cloned();
+        drop(guard);
+
+        if let Some(entry) = entry {
+            self.publish_indexed(path, &source, &entry).await;
         }
+    }
 
-        // Convert DiagnosticBag to LSP diagnostics
-        for diag in diag_bag.diagnostics() {
-            let severity = match diag.severity {
-                Severity::Error => DiagnosticSeverity::ERROR,
-                Severity::Warning => DiagnosticSeverity::WARNING,
-                Severity::Info => DiagnosticSeverity::INFORMATION,
-                Severity::Hint => DiagnosticSeverity::HINT,
-            };
+    /// Publish the indexed diagnostics of a file, unless it is open and
+    /// so has diagnostics of its own
+    async fn publish_indexed(&self, path: &Path, source: &str, entry: &FileEntry) {
+        let Ok(uri) = Url::from_file_path(path) else { return };
+        if self.documents.lock().await.contains_key(&uri) {
+            return;
+        }
 
-            let loc = &diag.location;
-            let range = Range {
-                start: Position {
-                    line: (loc.line.saturating_sub(1)) as u32,
-                    character: (loc.column.saturating_sub(1)) as u32,
-                },
-                end: Position {
-                    line: (loc.line.saturating_sub(1)) as u32,
-                    character: loc.column as u32,
-                },
-            };
+        let encoding = *self.encoding.lock().await;
+        let max_count = self.settings.lock().await.diagnostics.max_count;
+        let index = LineIndex::new(source);
+        let (shown, overflow) = limit_diagnostics(entry.diagnostics.iter().collect(), |diag| diag.severity(), max_count);
+        let mut diagnostics: Vec<_> = shown.into_iter()
+            .map(|diag| {
+                let code = wtlang_core::ErrorCode::from_code(&diag.code);
+                Self::to_diagnostic(&index, encoding, diag.severity(), code, &diag.message, (diag.line, diag.column), diag.end)
+            })
+            .collect();
+        diagnostics.extend(notes_diagnostic(overflow.as_slice()));
+        self.client.publish_diagnostics(uri, diagnostics, None).await;
+    }
 
-            let lsp_diagnostic = Diagnostic {
-                range,
-                severity: Some(severity),
-                code: Some(NumberOrString::String(format!("{:?}", diag.code))),
-                code_description: None,
-                source: Some("wtlang".to_string()),
-                message: diag.message.clone(),
-                related_information: None,
-                tags: None,
-                data: None,
-            };
-            diagnostics.push(lsp_diagnostic);
+    async fn workspace_symbols(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+        let query = params.query.to_lowercase();
+        let encoding = *self.encoding.lock().await;
+        let guard = self.index.lock().await;
+        let Some(index) = guard.as_ref() else { return Ok(None) };
+
+        let mut symbols = Vec::new();
+        for (path, entry) in index.files() {
+            let mut matches = entry.symbols.iter()
+                .filter(|symbol| symbol.name.to_lowercase().contains(&query))
+                .peekable();
+            if matches.peek().is_none() {
+                continue;
+            }
+            let Ok(uri) = Url::from_file_path(path) else { continue };
+            let Ok(source) = std::fs::read_to_string(path) else { continue };
+            let lines = LineIndex::new(&source);
+            for symbol in matches {
+                let kind = match symbol.kind.as_str() {
+                    "table" | "derived table" => tower_lsp::lsp_types::SymbolKind::STRUCT,
+                    "page" => tower_lsp::lsp_types::SymbolKind::MODULE,
+                    "shared" => tower_lsp::lsp_types::SymbolKind::VARIABLE,
+                    "const" => tower_lsp::lsp_types::SymbolKind::CONSTANT,
+                    "test" => tower_lsp::lsp_types::SymbolKind::METHOD,
+                    _ => tower_lsp::lsp_types::SymbolKind::FUNCTION,
+                };
+                #[allow(deprecated)]
+                symbols.push(SymbolInformation {
+                    name: symbol.name.clone(),
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location {
+                        uri: uri.clone(),
+                        range: Range {
+                            start: Self::to_position(&lines, encoding, symbol.line, symbol.column),
+                            end: Self::to_position(&lines, encoding, symbol.end_line, symbol.end_column),
+                        },
+                    },
+                    container_name: Some(symbol.kind.clone()),
+                });
+            }
         }
 
-        self.client.publish_diagnostics(uri, diagnostics, Some(version)).await;
+        Ok(Some(symbols))
+    }
+
+    async fn run_command(&self, params: ExecuteCommandParams) -> Result<Option<serde_json::Value>> {
+        match params.command.as_str() {
+            CLEAR_CACHE_COMMAND => {
+                if let Some(index) = self.index.lock().await.as_mut() {
+                    if let Err(e) = index.clear() {
+                        tracing::warn!(error = %e, "could not delete the cache");
+                        self.client
+                            .show_message(MessageType::WARNING, format!("Could not delete the WTLang cache: {}", e))
+                            .await;
+                    }
+                }
+                self.index_workspace().await;
+                Ok(None)
+            }
+            command => Err(tower_lsp::jsonrpc::Error::invalid_params(format!("unknown command '{}'", command))),
+        }
     }
     
     fn get_builtin_functions() -> Vec<(&'static str, &'static str, &'static str)> {
         vec![
-            ("load_csv", "load_csv(table_type, filename: string) -> table", "Load a CSV file into a table with validation"),
+            ("load_csv", "load_csv(filename: string, table_type, columns?: string) -> table", "Load a CSV file into a table with validation"),
             ("save_csv", "save_csv(table, filename: string)", "Save a table to a CSV file"),
+            ("load_sql", "load_sql(connection: string, name: string, table_type?) -> table", "Load a database table through a Streamlit SQL connection"),
+            ("save_sql", "save_sql(table, connection: string, name: string, mode?: \"append\"|\"replace\"|\"upsert\")", "Check a table's constraints and write it to a database table in one transaction"),
             ("show", "show(table, filters?: filter[]) -> table", "Display a table with optional filters"),
             ("show_editable", "show_editable(table, filters?: filter[]) -> table", "Display an editable table with optional filters"),
+            ("display", "display(value)", "Display a value formatted for its type; also written `display value`"),
+            ("audit", "audit(path: string) | audit(connection: string, name: string)", "Option of show_editable recording who changed what, and when, in a CSV file or database table"),
+            ("revert", "revert(table)", "Discard the edits made to a table in its editors, restoring it as loaded"),
+            ("current_user", "current_user()", "Claims of the signed-in user, such as current_user().email"),
             ("aggregate", "aggregate(table, group_by: string, agg_func: string, column: string) -> table", "Group and aggregate table data"),
-            ("sum", "sum(table, column: string) -> number", "Calculate sum of a column"),
-            ("average", "average(table, column: string) -> number", "Calculate average of a column"),
+            ("sum", "sum(table, column: string, nulls?: \"skip_nulls\"|\"strict\") -> number", "Calculate sum of a column, skipping or stopping on missing values"),
+            ("average", "average(table, column: string, nulls?: \"skip_nulls\"|\"strict\") -> number", "Calculate average of a column, skipping or stopping on missing values"),
             ("count", "count(table) -> int", "Count rows in a table"),
+            ("share", "share(table, column: string [by group: string]) -> table", "Add column_share: each row's share of the column's total, or of its group's"),
+            ("first", "first(table) -> row", "First row of a table, whose fields are read with .field"),
+            ("last", "last(table) -> row", "Last row of a table, whose fields are read with .field"),
+            ("single", "single(table) -> row", "The only row of a table; an error unless it has exactly one"),
             ("min", "min(table, column: string) -> number", "Find minimum value in a column"),
             ("max", "max(table, column: string) -> number", "Find maximum value in a column"),
             ("filter", "filter(column: string, mode: single|multi) -> filter", "Create a filter for table columns"),
             ("table_from", "table_from(data: array) -> table", "Create a table from array of objects"),
+            ("t", "t(key: string) -> string", "Text of a key in the app's current language, from locales/<language>.toml"),
+            ("format_number", "format_number(value: number, decimals?: int | pattern: string) -> string", "A number with the separators set in wt.toml, or a pattern such as \"#,##0.0\""),
+            ("format_currency", "format_currency(value: number, decimals?: int) -> string", "An amount with the currency symbol set in wt.toml"),
+            ("format_date", "format_date(value: date) -> string", "A date in the date format set in wt.toml"),
+            ("percent", "percent(value: number, decimals?: int) -> string", "A fraction as a percentage, 0.125 as 12.5%"),
+            ("round", "round(value: number, decimals?: int) -> int | float", "A number rounded to a whole number or to a number of decimals"),
+            ("floor", "floor(value: number) -> int", "The largest whole number not above a number"),
+            ("ceil", "ceil(value: number) -> int", "The smallest whole number not below a number"),
+            ("abs", "abs(value: number) -> number", "A number without its sign"),
+            ("send_email", "send_email(to: string, subject: string, body: string, attachment?: table) -> bool", "Send an email through the SMTP server in the app's secrets, with a table attached as CSV"),
+            ("export_pdf", "export_pdf(section: string) -> bool", "Offer the text and tables of a page section as a PDF download"),
+            ("log", "log(message: string, level?: \"info\"|\"warn\"|\"error\")", "Write a message to the app's log"),
+            ("assert_equal_tables", "assert_equal_tables(actual: table, expected: table, ignore_order?: bool)", "Assert that two tables have the same rows, listing added, removed and changed rows"),
+            ("assert_row_count", "assert_row_count(table, count: int)", "Assert that a table has exactly `count` rows"),
+            ("assert_contains", "assert_contains(table, row: {field: value, ...})", "Assert that some row of a table has the given values"),
+            ("generated", "generated(rows: int) -> table", "Generate test rows that satisfy a table's constraints, for use in `mock Table with generated(n)`"),
         ]
     }
     
@@ -159,22 +418,37 @@ impl WTLangServer {
             ("function", "Define a function"),
             ("external", "Declare an external function"),
             ("test", "Define a test case"),
+            ("restrict", "Limit the rows of a table users may see"),
+            ("shared", "Declare a variable shared by every page"),
+            ("const", "Declare a constant every page and function can read"),
+            ("config", "Set the app's title, theme, data directory and formats"),
+            ("import", "Use the tables, functions and pages of another file (import \"common.wt\"), or its functions as a module (import analytics from \"analytics.wt\")"),
+            ("on_load", "Run statements when a table is loaded"),
+            ("on_edit", "Run statements when a table is edited"),
+            ("on_save", "Run statements before a table is saved"),
+            ("template", "Define statements repeated across pages (template name(param: type) { ... })"),
+            ("use", "Expand a template defined above (use name(args))"),
+            ("python", "Run Python code as written (python(inputs) -> (outputs) { ... })"),
             ("let", "Declare a variable"),
             ("if", "Conditional statement"),
             ("else", "Else branch"),
+            ("match", "Branch on a value (match value { 1, 2 => ..., _ => ... })"),
             ("forall", "Loop over collection"),
             ("return", "Return from function"),
             ("button", "Create a button"),
             ("section", "Create a section"),
+            ("confirm", "Ask for confirmation before running a button's action"),
             ("title", "Set page title"),
             ("subtitle", "Set page subtitle"),
             ("text", "Display text"),
             ("from", "Import/reference"),
-            ("in", "Used in forall loops"),
+            ("in", "Loop variable of forall, or membership test (value in list)"),
             ("where", "Filter table rows (infix syntax: table where condition)"),
             ("by", "Used in sort expressions (sort by column)"),
             ("asc", "Sort in ascending order"),
             ("desc", "Sort in descending order"),
+            ("rename", "Rename table columns (table rename old to new)"),
+            ("drop", "Remove table columns (table drop column)"),
             ("key", "Marks a table field as primary key"),
             ("ref", "Reference type (ref TableName)"),
             ("int", "Integer type"),
@@ -191,90 +465,47 @@ impl WTLangServer {
             ("multi", "Multi-select filter mode"),
         ]
     }
-}
-
-#[tower_lsp::async_trait]
-impl LanguageServer for WTLangServer {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        Ok(InitializeResult {
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                hover_provider: Some(HoverProviderCapability::Simple(true)),
-                completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(vec![".".to_string(), ">".to_string()]),
-                    ..Default::default()
-                }),
-                definition_provider: Some(OneOf::Left(true)),
-                // We use push diagnostics (publish_diagnostics), not pull diagnostics
-                ..Default::default()
-            },
-            server_info: Some(ServerInfo {
-                name: "WTLang Language Server".to_string(),
-                version: Some("0.1.0".to_string()),
-            }),
-        })
-    }
-
-    async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "WTLang Language Server initialized")
-            .await;
-    }
-
-    async fn shutdown(&self) -> Result<()> {
-        Ok(())
-    }
-
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let uri = params.text_document.uri;
-        let text = params.text_document.text;
-        let version = params.text_document.version;
-
-        let mut docs = self.documents.lock().await;
-        docs.insert(uri.clone(), DocumentState {
-            source: text,
-            version,
-            program: None,
-        });
-        drop(docs);
-
-        self.publish_diagnostics(uri).await;
-    }
-
-    async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let uri = params.text_document.uri;
-        let version = params.text_document.version;
-
-        if let Some(change) = params.content_changes.first() {
-            let mut docs = self.documents.lock().await;
-            if let Some(doc) = docs.get_mut(&uri) {
-                doc.source = change.text.clone();
-                doc.version = version;
-                doc.program = None; // Invalidate cache
+    
+    /// Collect `let` bindings without a type annotation, which get inlay hints
+    fn collect_untyped_lets(statements: &[Statement], out: &mut Vec<(Atom, NodeId, Span)>) {
+        for stmt in statements {
+            match stmt {
+                Statement::Let { name, type_annotation: None, value: Some(_), id, span } => {
+                    out.push((*name, *id, *span));
+                }
+                Statement::Button { body, .. }
+                | Statement::Section { body, .. }
+                | Statement::Confirm { body, .. }
+                | Statement::Forall { body, .. } => {
+                    Self::collect_untyped_lets(body, out);
+                }
+                Statement::If { then_branch, else_branch, .. } => {
+                    Self::collect_untyped_lets(then_branch, out);
+                    if let Some(else_branch) = else_branch {
+                        Self::collect_untyped_lets(else_branch, out);
+                    }
+                }
+                Statement::Match { arms, .. } => {
+                    for arm in arms {
+                        Self::collect_untyped_lets(&arm.body, out);
+                    }
+                }
+                _ => {}
             }
-            drop(docs);
-
-            self.publish_diagnostics(uri).await;
         }
     }
 
-    async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        let mut docs = self.documents.lock().await;
-        docs.remove(&params.text_document.uri);
-    }
-
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+    async fn hover_response(&self, params: HoverParams) -> Result<Option<Hover>> {
         let uri = params.text_document_position_params.text_document.uri;
         let position = params.text_document_position_params.position;
         
         // Get document and parse
-        let (_program, analyzer) = match self.parse_and_analyze(&uri).await {
+        let (program, analyzer) = match self.parse_and_analyze(&uri).await {
             Some(result) => result,
             None => return Ok(None),
         };
         
+        let encoding = *self.encoding.lock().await;
         let docs = self.documents.lock().await;
         let doc = match docs.get(&uri) {
             Some(d) => d,
@@ -282,26 +513,23 @@ impl LanguageServer for WTLangServer {
         };
         
         // Get word at position
-        let lines: Vec<&str> = doc.source.lines().collect();
-        let line_idx = position.line as usize;
-        let char_idx = position.character as usize;
-        
-        if line_idx >= lines.len() {
-            return Ok(None);
-        }
+        let index = LineIndex::new(&doc.source);
+        let (line_no, column) = Self::from_position(&index, encoding, position);
+        let line = match index.line(line_no) {
+            Some(line) => line,
+            None => return Ok(None),
+        };
         
-        let line = lines[line_idx];
+        // Byte offset of the cursor in the line
+        let char_idx = index.offset(line_no, column, PositionEncoding::Utf8);
         if char_idx >= line.len() {
             return Ok(None);
         }
         
         // Find word boundaries
-        let start = line[..char_idx]
-            .rfind(|c: char| !c.is_alphanumeric() && c != '_')
-            .map(|i| i + 1)
-            .unwrap_or(0);
+        let start = word_start(&line[..char_idx]);
         let end = line[char_idx..]
-            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .find(|c: char| !is_word_char(c))
             .map(|i| i + char_idx)
             .unwrap_or(line.len());
         
@@ -310,42 +538,54 @@ impl LanguageServer for WTLangServer {
         }
         
         let word = &line[start..end];
+        let range = Range {
+            start: Self::to_position(&index, encoding, line_no, index.column(line_no, start, PositionEncoding::Utf8)),
+            end: Self::to_position(&index, encoding, line_no, index.column(line_no, end, PositionEncoding::Utf8)),
+        };
         
-        // Look up symbol in symbol table
+        // Look up symbol in the scope enclosing the cursor
         let symbol_table = analyzer.get_symbol_table();
-        if let Some(symbol) = symbol_table.lookup(word) {
-            let type_str = match &symbol.symbol_type {
-                Type::Int => "int",
-                Type::Float => "float",
-                Type::String => "string",
-                Type::Date => "date",
-                Type::Currency => "currency",
-                Type::Bool => "bool",
-                Type::Table(name) => &format!("table({})", name),
-                Type::Filter => "filter",
-                Type::Ref(table_name) => &format!("ref {}", table_name),
-            };
-            
+        if let Some(symbol) = symbol_table.resolve_at(line_no, column, word) {
             let kind_str = match symbol.kind {
                 SymbolKind::Variable => "variable",
                 SymbolKind::Parameter => "parameter",
                 SymbolKind::LoopVariable => "loop variable",
                 SymbolKind::Table => "table",
+                SymbolKind::DerivedTable => "derived table",
+                SymbolKind::SharedVariable => "shared variable",
+                SymbolKind::Constant => "constant",
                 SymbolKind::Function => "function",
                 SymbolKind::ExternalFunction => "external function",
             };
             
-            let hover_text = format!("**{}** `{}`\n\n*Type:* `{}`", kind_str, word, type_str);
+            let mut hover_text = format!("**{}** `{}`\n\n*Type:* `{}`", kind_str, word, symbol.symbol_type);
+            let documented = matches!(symbol.kind, SymbolKind::Table | SymbolKind::Function);
+            if let Some(doc) = item_doc(&program, word).filter(|_| documented) {
+                hover_text.push_str(&format!("\n\n{}", doc));
+            }
             
             return Ok(Some(Hover {
                 contents: HoverContents::Markup(MarkupContent {
                     kind: MarkupKind::Markdown,
                     value: hover_text,
                 }),
-                range: Some(Range {
-                    start: Position { line: position.line, character: start as u32 },
-                    end: Position { line: position.line, character: end as u32 },
+                range: Some(range),
+            }));
+        }
+        
+        // Pages are not symbols, but may be documented
+        let is_page = program.items.iter().any(|item| matches!(item, ProgramItem::Page(page) if page.name == word));
+        if is_page {
+            let mut hover_text = format!("**page** `{}`", word);
+            if let Some(doc) = item_doc(&program, word) {
+                hover_text.push_str(&format!("\n\n{}", doc));
+            }
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: hover_text,
                 }),
+                range: Some(range),
             }));
         }
         
@@ -358,10 +598,7 @@ impl LanguageServer for WTLangServer {
                         kind: MarkupKind::Markdown,
                         value: hover_text,
                     }),
-                    range: Some(Range {
-                        start: Position { line: position.line, character: start as u32 },
-                        end: Position { line: position.line, character: end as u32 },
-                    }),
+                    range: Some(range),
                 }));
             }
         }
@@ -375,19 +612,36 @@ impl LanguageServer for WTLangServer {
                         kind: MarkupKind::Markdown,
                         value: hover_text,
                     }),
-                    range: Some(Range {
-                        start: Position { line: position.line, character: start as u32 },
-                        end: Position { line: position.line, character: end as u32 },
-                    }),
+                    range: Some(range),
                 }));
             }
         }
         
+        // Fall back to the type of the innermost expression, binding or
+        // assignment under the cursor
+        if let Some((_, ty)) = analyzer.get_type_map().type_at(line_no, column) {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("*Type:* `{}`", ty),
+                }),
+                range: None,
+            }));
+        }
+        
         Ok(None)
     }
 
-    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+    async fn completion_response(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let encoding = *self.encoding.lock().await;
+        let source = match self.documents.lock().await.get(&uri) {
+            Some(doc) => doc.source.clone(),
+            None => String::new(),
+        };
+        let index = LineIndex::new(&source);
+        let (cursor_line, cursor_column) = Self::from_position(&index, encoding, position);
         
         let mut items = Vec::new();
         
@@ -421,9 +675,8 @@ impl LanguageServer for WTLangServer {
         // Add user-defined symbols from the document
         if let Some((program, analyzer)) = self.parse_and_analyze(&uri).await {
             let symbol_table = analyzer.get_symbol_table();
-            let global_scope = symbol_table.global_scope();
             
-            for (name, symbol) in global_scope.symbols() {
+            for symbol in symbol_table.symbols_at(cursor_line, cursor_column) {
                 let (kind, detail) = match symbol.kind {
                     SymbolKind::Table => {
                         if let Type::Table(table_name) = &symbol.symbol_type {
@@ -432,20 +685,25 @@ impl LanguageServer for WTLangServer {
                             (CompletionItemKind::CLASS, "table".to_string())
                         }
                     }
+                    SymbolKind::DerivedTable => {
+                        (CompletionItemKind::CLASS, format!("derived {}", symbol.symbol_type))
+                    }
                     SymbolKind::Function => {
                         (CompletionItemKind::FUNCTION, format!("function -> {:?}", symbol.symbol_type))
                     }
                     SymbolKind::ExternalFunction => {
                         (CompletionItemKind::FUNCTION, format!("external function -> {:?}", symbol.symbol_type))
                     }
-                    SymbolKind::Variable => {
+                    SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::LoopVariable | SymbolKind::SharedVariable => {
                         (CompletionItemKind::VARIABLE, format!("{:?}", symbol.symbol_type))
                     }
-                    _ => (CompletionItemKind::VARIABLE, format!("{:?}", symbol.symbol_type)),
+                    SymbolKind::Constant => {
+                        (CompletionItemKind::CONSTANT, format!("{:?}", symbol.symbol_type))
+                    }
                 };
                 
                 items.push(CompletionItem {
-                    label: name.clone(),
+                    label: symbol.name.to_string(),
                     kind: Some(kind),
                     detail: Some(detail.clone()),
                     ..Default::default()
@@ -456,33 +714,29 @@ impl LanguageServer for WTLangServer {
             // This is a simplified approach - a full implementation would parse context
             let docs = self.documents.lock().await;
             if let Some(doc) = docs.get(&uri) {
-                let position = params.text_document_position.position;
                 let lines: Vec<&str> = doc.source.lines().collect();
                 if let Some(line) = lines.get(position.line as usize) {
-                    let before_cursor = &line[..position.character.min(line.len() as u32) as usize];
+                    let cursor = index.offset(cursor_line, cursor_column, PositionEncoding::Utf8);
+                    let before_cursor = &line[..cursor.min(line.len())];
                     
                     // Check if we're after a dot (field access)
                     if let Some(dot_pos) = before_cursor.rfind('.') {
                         let before_dot = before_cursor[..dot_pos].trim_end();
                         
                         // Try to find the last identifier before the dot
-                        let ident_start = before_dot
-                            .rfind(|c: char| !c.is_alphanumeric() && c != '_')
-                            .map(|i| i + 1)
-                            .unwrap_or(0);
-                        let identifier = &before_dot[ident_start..];
+                        let identifier = &before_dot[word_start(before_dot)..];
                         
                         // Look up the identifier in symbol table
-                        if let Some(symbol) = symbol_table.lookup(identifier) {
-                            if let Type::Table(table_name) = &symbol.symbol_type {
+                        if let Some(symbol) = symbol_table.resolve_at(cursor_line, cursor_column, identifier) {
+                            if let Type::Table(table) | Type::Row(table) = &symbol.symbol_type {
                                 // Find the table definition
                                 for item in &program.items {
                                     if let wtlang_core::ast::ProgramItem::TableDef(table_def) = item {
-                                        if &table_def.name == table_name {
+                                        if table.changes.is_empty() && table_def.name == table.name {
                                             // Add field completions
                                             for field in &table_def.fields {
                                                 items.push(CompletionItem {
-                                                    label: field.name.clone(),
+                                                    label: field.name.to_string(),
                                                     kind: Some(CompletionItemKind::FIELD),
                                                     detail: Some(format!("{:?}", field.field_type)),
                                                     ..Default::default()
@@ -502,6 +756,313 @@ impl LanguageServer for WTLangServer {
         Ok(Some(CompletionResponse::Array(items)))
     }
 
+    async fn inlay_hints(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri;
+        
+        let (program, analyzer) = match self.parse_and_analyze(&uri).await {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        
+        let encoding = *self.encoding.lock().await;
+        let docs = self.documents.lock().await;
+        let doc = match docs.get(&uri) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        let index = LineIndex::new(&doc.source);
+        
+        let mut lets = Vec::new();
+        for item in &program.items {
+            match item {
+                ProgramItem::Page(page) => Self::collect_untyped_lets(&page.statements, &mut lets),
+                ProgramItem::FunctionDef(func) => Self::collect_untyped_lets(&func.body, &mut lets),
+                ProgramItem::Test(test) => Self::collect_untyped_lets(&test.body, &mut lets),
+                ProgramItem::Hook(hook) => Self::collect_untyped_lets(&hook.body, &mut lets),
+                _ => {}
+            }
+        }
+        
+        let type_map = analyzer.get_type_map();
+        let mut hints = Vec::new();
+        for (name, id, span) in lets {
+            let Some(ty) = type_map.get(id) else { continue };
+            let Some(line) = index.line(span.line) else { continue };
+            
+            // Place the hint right after the variable name following `let`
+            let search_from = index.offset(span.line, span.column, PositionEncoding::Utf8).min(line.len());
+            let Some(offset) = line[search_from..].find(name.as_str()) else { continue };
+            let column = index.column(span.line, search_from + offset + name.len(), PositionEncoding::Utf8);
+            let position = Self::to_position(&index, encoding, span.line, column);
+            if position.line < params.range.start.line || position.line > params.range.end.line {
+                continue;
+            }
+            
+            hints.push(InlayHint {
+                position,
+                label: InlayHintLabel::String(format!(": {}", ty)),
+                kind: Some(InlayHintKind::TYPE),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: None,
+                data: None,
+            });
+        }
+        
+        Ok(Some(hints))
+    }
+
+    async fn code_actions(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let source = match self.documents.lock().await.get(&uri) {
+            Some(doc) => doc.source.clone(),
+            None => return Ok(None),
+        };
+        let diag_bag = match self.document_diagnostics(&uri, &source).await {
+            Some(diag_bag) => diag_bag,
+            None => return Ok(None),
+        };
+        
+        let encoding = *self.encoding.lock().await;
+        let index = LineIndex::new(&source);
+        let position = |line: usize, column: usize| Self::to_position(&index, encoding, line, column);
+        
+        // Quick fixes of the diagnostics on the requested lines
+        let mut actions = Vec::new();
+        for diag in diag_bag.diagnostics() {
+            let Some(fix) = &diag.fix else { continue };
+            let line = position(diag.location.line, diag.location.column).line;
+            if line < params.range.start.line || line > params.range.end.line {
+                continue;
+            }
+            
+            let edits = fix.edits.iter()
+                .map(|edit| TextEdit {
+                    range: Range {
+                        start: position(edit.start.line, edit.start.column),
+                        end: position(edit.end.line, edit.end.column),
+                    },
+                    new_text: edit.text.clone(),
+                })
+                .collect();
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: fix.message.clone(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), edits)])),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            }));
+        }
+        
+        Ok(Some(actions))
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for WTLangServer {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // Count columns in UTF-8 bytes if the client can, else in the
+        // UTF-16 code units every client supports
+        let offered = params.capabilities.general
+            .and_then(|general| general.position_encodings)
+            .unwrap_or_default();
+        let (encoding, kind) = if offered.contains(&PositionEncodingKind::UTF8) {
+            (PositionEncoding::Utf8, PositionEncodingKind::UTF8)
+        } else {
+            (PositionEncoding::Utf16, PositionEncodingKind::UTF16)
+        };
+        *self.encoding.lock().await = encoding;
+        if let Some(options) = &params.initialization_options {
+            *self.settings.lock().await = Settings::from_json(options);
+        }
+        #[allow(deprecated)]
+        let root = params.workspace_folders.as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| &folder.uri)
+            .or(params.root_uri.as_ref())
+            .and_then(|uri| uri.to_file_path().ok());
+        if let Some(root) = root {
+            *self.index.lock().await = Some(WorkspaceIndex::new(root));
+        }
+        tracing::info!(
+            client = params.client_info.as_ref().map_or("unknown", |info| info.name.as_str()),
+            ?encoding,
+            "initializing"
+        );
+
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                position_encoding: Some(kind),
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::FULL,
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(vec![".".to_string(), ">".to_string()]),
+                    ..Default::default()
+                }),
+                definition_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![CLEAR_CACHE_COMMAND.to_string()],
+                    ..Default::default()
+                }),
+                // We use push diagnostics (publish_diagnostics), not pull diagnostics
+                ..Default::default()
+            },
+            server_info: Some(ServerInfo {
+                name: "WTLang Language Server".to_string(),
+                version: Some("0.1.0".to_string()),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        tracing::info!("initialized");
+        self.client
+            .log_message(MessageType::INFO, "WTLang Language Server initialized")
+            .await;
+        let _ = self.guard("initialized", "workspace".to_string(), async {
+            self.index_workspace().await;
+            Ok(())
+        }).await;
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        tracing::info!("shutting down");
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let text = params.text_document.text;
+        let version = params.text_document.version;
+        tracing::debug!(%uri, version, "opened document");
+
+        let mut docs = self.documents.lock().await;
+        docs.insert(uri.clone(), DocumentState {
+            source: text,
+            version,
+            program: None,
+            analysis: IncrementalAnalyzer::new(),
+        });
+        drop(docs);
+
+        let context = request_context(&uri, None);
+        let _ = self.guard("textDocument/didOpen", context, async {
+            self.publish_diagnostics(uri).await;
+            Ok(())
+        }).await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+
+        if let Some(change) = params.content_changes.first() {
+            let mut docs = self.documents.lock().await;
+            if let Some(doc) = docs.get_mut(&uri) {
+                doc.source = change.text.clone();
+                doc.version = version;
+                doc.program = None; // Invalidate cache
+            }
+            drop(docs);
+
+            let context = request_context(&uri, None);
+            let _ = self.guard("textDocument/didChange", context, async {
+                self.publish_diagnostics(uri).await;
+                Ok(())
+            }).await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let settings = Settings::from_json(&params.settings);
+        tracing::info!(?settings, "settings changed");
+        *self.settings.lock().await = settings;
+        
+        // The limits may hide or show diagnostics of open documents
+        let uris: Vec<Url> = self.documents.lock().await.keys().cloned().collect();
+        for uri in uris {
+            let context = request_context(&uri, None);
+            let _ = self.guard("workspace/didChangeConfiguration", context, async {
+                self.publish_diagnostics(uri).await;
+                Ok(())
+            }).await;
+        }
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        tracing::debug!(uri = %params.text_document.uri, "closed document");
+        let mut docs = self.documents.lock().await;
+        docs.remove(&params.text_document.uri);
+        drop(docs);
+
+        // Back to the diagnostics of the file as saved
+        if let Ok(path) = params.text_document.uri.to_file_path() {
+            let context = request_context(&params.text_document.uri, None);
+            let _ = self.guard("textDocument/didClose", context, async {
+                self.reindex(&path).await;
+                Ok(())
+            }).await;
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else { continue };
+            if change.typ == FileChangeType::DELETED {
+                if let Some(index) = self.index.lock().await.as_mut() {
+                    index.remove(&path);
+                }
+                if !self.documents.lock().await.contains_key(&change.uri) {
+                    self.client.publish_diagnostics(change.uri, Vec::new(), None).await;
+                }
+            } else {
+                let context = request_context(&change.uri, None);
+                let _ = self.guard("workspace/didChangeWatchedFiles", context, async {
+                    self.reindex(&path).await;
+                    Ok(())
+                }).await;
+            }
+        }
+    }
+
+    async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+        let context = format!("query '{}'", params.query);
+        self.guard("workspace/symbol", context, self.workspace_symbols(params)).await
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<serde_json::Value>> {
+        let context = params.command.clone();
+        self.guard("workspace/executeCommand", context, self.run_command(params)).await
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let context = request_context(&params.text_document_position_params.text_document.uri, Some(params.text_document_position_params.position));
+        self.guard("textDocument/hover", context, self.hover_response(params)).await
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let context = request_context(&params.text_document_position.text_document.uri, Some(params.text_document_position.position));
+        self.guard("textDocument/completion", context, self.completion_response(params)).await
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let context = request_context(&params.text_document.uri, Some(params.range.start));
+        self.guard("textDocument/inlayHint", context, self.inlay_hints(params)).await
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let context = request_context(&params.text_document.uri, Some(params.range.start));
+        self.guard("textDocument/codeAction", context, self.code_actions(params)).await
+    }
     async fn goto_definition(
         &self,
         _params: GotoDefinitionParams,
@@ -511,10 +1072,99 @@ impl LanguageServer for WTLangServer {
     }
 }
 
+/// Order in which diagnostics are kept when there are too many
+fn severity_rank(severity: Severity) -> u8 {
+    match severity {
+        Severity::Error => 0,
+        Severity::Warning => 1,
+        Severity::Info => 2,
+        Severity::Hint => 3,
+    }
+}
+
+/// At most `max_count` of the diagnostics, errors first so that the limit
+/// drops warnings before errors, and a note saying how many were left out
+fn limit_diagnostics<T>(mut diagnostics: Vec<T>, severity: impl Fn(&T) -> Severity, max_count: usize) -> (Vec<T>, Option<String>) {
+    let total = diagnostics.len();
+    diagnostics.sort_by_key(|diag| severity_rank(severity(diag)));
+    diagnostics.truncate(max_count);
+    let note = (diagnostics.len() < total).then(|| format!(
+        "Showing {} of {} diagnostics; raise wtlang.diagnostics.maxCount to see them all.",
+        diagnostics.len(), total
+    ));
+    (diagnostics, note)
+}
+
+/// Diagnostic at the top of the document saying what was left out, if
+/// anything was
+fn notes_diagnostic(notes: &[String]) -> Option<Diagnostic> {
+    (!notes.is_empty()).then(|| Diagnostic {
+        range: Range::default(),
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        source: Some("wtlang".to_string()),
+        message: notes.join(" "),
+        ..Default::default()
+    })
+}
+
+/// Await a future, returning the message of its panic if it panics
+async fn catch_panic<T>(future: impl Future<Output = T>) -> std::result::Result<T, String> {
+    AssertUnwindSafe(future).catch_unwind().await.map_err(|panic| {
+        panic.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string())
+    })
+}
+
+/// Diagnostic published instead of those of a document whose analysis
+/// panicked, so that the user knows why there are none
+fn internal_error_diagnostic(message: &str) -> Diagnostic {
+    Diagnostic {
+        range: Range::default(),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("wtlang".to_string()),
+        message: format!("Internal error while analyzing this file: {}. Other features keep working; please report it.", message),
+        ..Default::default()
+    }
+}
+
+/// Document and position of a request, for logging
+fn request_context(uri: &Url, position: Option<Position>) -> String {
+    match position {
+        Some(position) => format!("{}:{}:{}", uri, position.line + 1, position.character + 1),
+        None => uri.to_string(),
+    }
+}
+
+/// The `///` documentation of the table, function or page named `name`
+fn item_doc<'a>(program: &'a wtlang_core::ast::Program, name: &str) -> Option<&'a str> {
+    program.items.iter().find_map(|item| match item {
+        ProgramItem::TableDef(table) if table.name == name => table.doc.as_deref(),
+        ProgramItem::FunctionDef(func) if func.name == name => func.doc.as_deref(),
+        ProgramItem::Page(page) if page.name == name => page.doc.as_deref(),
+        _ => None,
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte offset where the word ending `text` starts
+fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .find(|&(_, c)| !is_word_char(c))
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
 #[tokio::main]
 async fn main() {
-    // Set up basic error handling
-    env_logger::init();
+    // Log to stderr, as stdout carries the protocol
+    match Logger::from_env(LevelFilter::WARN) {
+        Ok(logger) => logger.install(),
+        Err(e) => eprintln!("{}", e),
+    }
     
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
@@ -522,3 +1172,51 @@ async fn main() {
     let (service, socket) = LspService::new(|client| WTLangServer::new(client));
     Server::new(stdin, stdout, socket).serve(service).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_panicking_analysis_becomes_a_diagnostic() {
+        // An analysis with a bug indexing past the end of its items
+        let analysis = async {
+            let bags: Vec<DiagnosticBag> = Vec::new();
+            Some(bags[0].clone())
+        };
+        let message = catch_panic(analysis).await.unwrap_err();
+        assert!(message.starts_with("index out of bounds"), "{}", message);
+        let diagnostic = internal_error_diagnostic(&message);
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostic.range, Range::default());
+        assert!(diagnostic.message.starts_with("Internal error while analyzing this file: index out of bounds"), "{}", diagnostic.message);
+
+        assert_eq!(catch_panic(async { panic!("line {}", 3) }).await, Err::<(), _>("line 3".to_string()));
+        assert_eq!(catch_panic(async { 1 }).await, Ok(1));
+    }
+
+    #[test]
+    fn test_diagnostics_are_capped() {
+        let source = "page Home {\n    let a: int = \"x\"\n    first = 1\n    second = 2\n    third = 3\n}\n";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let mut analyzer = SemanticAnalyzer::new();
+        let _ = analyzer.analyze(&program);
+        let bag = analyzer.diagnostics();
+        assert!(bag.diagnostics().len() > 3, "{:?}", bag);
+
+        let (shown, overflow) = limit_diagnostics(bag.diagnostics().iter().collect(), |diag| diag.severity, 3);
+        assert_eq!(shown.len(), 3);
+        assert!(shown.iter().all(|diag| diag.severity == Severity::Error), "{:?}", shown);
+        let expected = format!("Showing 3 of {} diagnostics; raise wtlang.diagnostics.maxCount to see them all.", bag.diagnostics().len());
+        assert_eq!(overflow.as_deref(), Some(expected.as_str()));
+        let note = notes_diagnostic(overflow.as_slice()).unwrap();
+        assert_eq!(note.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(note.message, expected);
+
+        // Errors are kept before warnings, and nothing is said when all fit
+        let severities = vec![Severity::Hint, Severity::Warning, Severity::Error];
+        assert_eq!(limit_diagnostics(severities.clone(), |severity| *severity, 2), (vec![Severity::Error, Severity::Warning], Some("Showing 2 of 3 diagnostics; raise wtlang.diagnostics.maxCount to see them all.".to_string())));
+        assert_eq!(limit_diagnostics(severities, |severity| *severity, 3).1, None);
+        assert!(notes_diagnostic(&[]).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+// Client settings of the language server
+//
+// Sent as the initialization options and, when the user changes them, in
+// `workspace/didChangeConfiguration` under the `wtlang` section. They
+// mirror the `wtlang.*` settings of the VS Code extension.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub diagnostics: DiagnosticsSettings,
+    pub analysis: AnalysisSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticsSettings {
+    /// Most diagnostics published for one document
+    pub max_count: usize,
+}
+
+impl Default for DiagnosticsSettings {
+    fn default() -> Self {
+        DiagnosticsSettings { max_count: 200 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AnalysisSettings {
+    /// Size in bytes above which a document is only checked for syntax
+    /// errors, without semantic analysis, hover, completion or inlay hints
+    pub max_file_size: usize,
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        AnalysisSettings { max_file_size: 1_000_000 }
+    }
+}
+
+impl Settings {
+    /// Settings from the client's JSON, either the `wtlang` section itself
+    /// or an object holding it; what is missing or invalid keeps its default
+    pub fn from_json(value: &Value) -> Self {
+        let section = value.get("wtlang").unwrap_or(value);
+        Settings::deserialize(section).unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "invalid settings, using the defaults");
+            Settings::default()
+        })
+    }
+}
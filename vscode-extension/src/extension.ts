@@ -48,6 +48,12 @@ export function activate(context: ExtensionContext) {
         documentSelector: [{ scheme: 'file', language: 'wtlang' }],
         synchronize: {
             fileEvents: workspace.createFileSystemWatcher('**/*.wt'),
+            // Send changes of the wtlang.* settings to the server
+            configurationSection: 'wtlang',
+        },
+        initializationOptions: {
+            diagnostics: { maxCount: config.get<number>('diagnostics.maxCount') },
+            analysis: { maxFileSize: config.get<number>('analysis.maxFileSize') },
         },
     };
 